@@ -0,0 +1,725 @@
+use crate::changelog;
+use crate::config::Config;
+use crate::controller::{self, PlaybackController, RewindTarget, VariedTrack};
+use crate::gen::{
+    next_arp_chords, next_brightness, next_comping, next_counter_melody, next_echo, next_harmony, next_lofi, next_modulation, next_pulse_width, next_waveform, parse_song_id_to_app_state, MusicControl, MusicProgress, MAX_CROSSFADE_SECS, SAMPLE_RATE, SONG_ID_VERSION, WAVEFORM_TRACKS,
+};
+use crate::session;
+use crate::share;
+use crate::tui::{format_duration, AppState, UserAction};
+use crate::workers;
+use crossbeam_channel::{Receiver as CrossbeamReceiver, RecvTimeoutError};
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// POLL_INTERVAL: How long to wait for a command before checking for progress updates again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// HELP_LINES: Commands accepted by the line interface, mirroring the TUI hotkeys.
+const HELP_LINES: &[&str] = &[
+    "Commands (type one and press Enter):",
+    "  p: Play or pause",
+    "  r: Rewind to the start of the section; twice to restart the song",
+    "  f: Next song with the same settings",
+    "  g: Generate a random song",
+    "  l SONG-ID: Load a song by its ID or share code",
+    "  + or -: Volume up or down",
+    "  m: Mute or unmute",
+    "  L: Loop the current song on or off",
+    "  d: Drums on or off, from the next song",
+    "  x: Change the crossfade between songs (off, 1 to 5 seconds)",
+    "  v: Change the harmony voice (off, choruses, whole song), from the next song",
+    "  c: Change the counter-melody (auto, on, off), from the next song",
+    "  a: Change the chip arpeggio chords (auto, on, off), from the next song",
+    "  o: Change the chord comping (auto rhythm, sustain), from the next song",
+    "  u: Change the square pulse width (auto, 12.5%, 25%, 50%, sweeping), from the next song",
+    "  k: Change the key change in the last section (auto, on, off), from the next song",
+    "  t: Change the brightness (auto, dark, warm, bright, open), from the next song",
+    "  e: Change the melody echo (auto, off), from the next song",
+    "  i: Change the lo-fi bit crusher (auto, off, light, heavy), from the next song",
+    "  n: NES mode on or off (two pulses, triangle bass, noise drums), from the next song",
+    "  w TRACK: Change the lead, chords or bass waveform (auto, square, sine, triangle, saw, noise), from the next song",
+    "  M: Replay the current song with a new melody",
+    "  V: Replay the current song with a new bass line",
+    "  Y: Say the current song's share code, a shorter form of its ID",
+    "  s: Say what is playing",
+    "  ?: List commands",
+    "  q: Quit",
+];
+
+/* Command - A parsed line of input.
+ *
+ * Most commands map directly onto the `UserAction` the TUI would produce for the
+ * same hotkey; the status query has no TUI equivalent since the TUI is always on screen.
+ */
+enum Command {
+    Action(UserAction), // A hotkey action, dispatched like in the TUI
+    Status,             // Describe what is playing
+    Waveform,           // Step the waveform of the track named in the argument
+}
+
+/* parse_command - Parses one line of input into a command.
+ *
+ * inputs:
+ *     - line (&str): The line typed by the user.
+ *
+ * outputs:
+ *     - Option<(Command, &str)>: The command and its (trimmed) argument, or None if the line is not a command.
+ */
+fn parse_command(line: &str) -> Option<(Command, &str)> {
+    let line = line.trim();
+    let mut chars = line.chars();
+    let letter = chars.next()?;
+    let argument = chars.as_str().trim();
+    // Uppercase like the TUI hotkeys, since the lowercase letters are taken
+    let uppercase_action = match letter {
+        'L' => Some(UserAction::ToggleLoop),
+        'M' => Some(UserAction::VarySong(VariedTrack::Melody)),
+        'V' => Some(UserAction::VarySong(VariedTrack::Bass)),
+        'Y' => Some(UserAction::ShareSong),
+        _ => None,
+    };
+    if let Some(action) = uppercase_action {
+        return Some((Command::Action(action), argument));
+    }
+    let command = match letter.to_ascii_lowercase() {
+        'p' => Command::Action(UserAction::TogglePlayback),
+        'r' => Command::Action(UserAction::RewindSong),
+        'f' => Command::Action(UserAction::FastForwardSong),
+        'g' => Command::Action(UserAction::GenerateRandomMusic),
+        'l' => Command::Action(UserAction::AttemptLoadSong),
+        '+' | '=' => Command::Action(UserAction::VolumeUp),
+        '-' => Command::Action(UserAction::VolumeDown),
+        'm' => Command::Action(UserAction::ToggleMute),
+        'x' => Command::Action(UserAction::CycleCrossfade),
+        'k' => Command::Action(UserAction::CycleModulation),
+        'v' => Command::Action(UserAction::CycleHarmony),
+        'c' => Command::Action(UserAction::CycleCounterMelody),
+        'a' => Command::Action(UserAction::CycleArpChords),
+        'o' => Command::Action(UserAction::CycleComping),
+        'u' => Command::Action(UserAction::CyclePulseWidth),
+        'w' => Command::Waveform,
+        't' => Command::Action(UserAction::CycleBrightness),
+        'e' => Command::Action(UserAction::CycleEcho),
+        'i' => Command::Action(UserAction::CycleLoFi),
+        'n' => Command::Action(UserAction::ToggleNesMode),
+        'd' => Command::Action(UserAction::ToggleDrums),
+        's' => Command::Status,
+        '?' | 'h' => Command::Action(UserAction::ToggleHelp),
+        'q' => Command::Action(UserAction::Quit),
+        _ => return None,
+    };
+    Some((command, argument))
+}
+
+/* describe_length - Puts a song's true length into words.
+ *
+ * Songs end on a whole bar, so they run a few seconds off the length option they were asked for.
+ *
+ * inputs:
+ *     - total_samples (u64): Length of the song in samples.
+ *
+ * outputs:
+ *     - String: e.g. "5 minutes", "1 minute 58 seconds" or "45 seconds".
+ */
+fn describe_length(total_samples: u64) -> String {
+    let seconds = (total_samples as f32 / SAMPLE_RATE as f32).round() as u64;
+    let count = |n: u64, unit: &str| if n == 1 { format!("1 {}", unit) } else { format!("{} {}s", n, unit) };
+    match (seconds / 60, seconds % 60) {
+        (0, seconds) => count(seconds, "second"),
+        (minutes, 0) => count(minutes, "minute"),
+        (minutes, seconds) => format!("{} {}", count(minutes, "minute"), count(seconds, "second")),
+    }
+}
+
+/* LineInterface - Plain, line-based front end for screen readers.
+ *
+ * Prints each state change as a sentence and reads single-letter commands, driving the
+ * same `PlaybackController` as the TUI. Nothing is redrawn in place, so every line stays
+ * readable in the terminal's scrollback.
+ *
+ * fields:
+ *     - controller (PlaybackController): Owns the music service.
+ *     - app_state (AppState): Parameters of the current (or next) song.
+ *     - is_playing (bool): True while a song is playing (not paused).
+ *     - has_song (bool): True once a song has been started.
+ *     - current_samples (u64): Last reported playback position.
+ *     - total_samples (u64): Length of the current song in samples.
+ *     - endless (bool): True if the current song plays on until it is skipped or stopped.
+ *     - loudness_db (Option<f32>): Measured loudness of the current song, once it is fully rendered.
+ */
+struct LineInterface {
+    controller: PlaybackController,
+    app_state: AppState,
+    is_playing: bool,
+    has_song: bool,
+    current_samples: u64,
+    total_samples: u64,
+    endless: bool,
+    loudness_db: Option<f32>,
+}
+
+impl LineInterface {
+    /* new - Creates a line interface with nothing playing.
+     *
+     * inputs:
+     *     - app_state (AppState): The starting parameters, with config defaults applied.
+     *
+     * outputs:
+     *     - Self: The new interface.
+     */
+    fn new(app_state: AppState) -> Self {
+        let mut controller = PlaybackController::new(app_state.volume);
+        controller.set_rewind_to_section(
+            !std::env::args().any(|arg| arg == controller::REWIND_TO_START_FLAG),
+        );
+        Self {
+            controller,
+            app_state,
+            is_playing: false,
+            has_song: false,
+            current_samples: 0,
+            total_samples: 0,
+            endless: false,
+            loudness_db: None,
+        }
+    }
+
+    /* position - Describes the playback position, e.g. "1:32 of 5:00".
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - String: The elapsed and total time; only the elapsed time for an endless song.
+     */
+    fn position(&self) -> String {
+        if self.endless {
+            return format!(
+                "{} of an endless song",
+                format_duration(self.current_samples as f32 / SAMPLE_RATE as f32)
+            );
+        }
+        format!(
+            "{} of {}",
+            format_duration(self.current_samples as f32 / SAMPLE_RATE as f32),
+            format_duration(self.total_samples as f32 / SAMPLE_RATE as f32)
+        )
+    }
+
+    /* handle_progress - Records a progress update and announces new songs.
+     *
+     * inputs:
+     *     - &mut self
+     *     - progress (MusicProgress): The update from the music service.
+     *     - out (&mut impl Write): Where to print announcements.
+     *
+     * outputs:
+     *     - io::Result<()>: Ok, or the error from writing.
+     */
+    fn handle_progress(&mut self, progress: MusicProgress, out: &mut impl Write) -> io::Result<()> {
+        if progress.generating {
+            return Ok(()); // Says nothing about the song; the one being generated is announced next
+        }
+        if let Some(error) = progress.error {
+            // The service could not play and has stopped
+            self.is_playing = false;
+            self.total_samples = 0;
+            return writeln!(out, "Cannot play: {}", error);
+        }
+        self.current_samples = progress.current_samples;
+        self.total_samples = progress.total_samples;
+        self.endless = progress.endless;
+        self.loudness_db = progress.loudness_db;
+        if let Some(app_state) = progress.app_state {
+            self.app_state = app_state;
+            self.is_playing = true;
+            let bpm = if self.app_state.bpm.is_empty() {
+                format!("{} (random)", progress.bpm)
+            } else {
+                self.app_state.bpm.clone()
+            };
+            writeln!(
+                out,
+                "Now playing: {} {} {}, {} BPM, {}, seed {}",
+                self.app_state.scale,
+                self.app_state.quality.to_lowercase(),
+                self.app_state.style,
+                bpm,
+                if progress.endless { "endless".to_string() } else { describe_length(progress.total_samples) },
+                progress.actual_seed
+            )?;
+            writeln!(
+                out,
+                "Song ID: {}",
+                controller::song_id(&self.app_state, progress.actual_seed)
+            )?;
+        }
+        Ok(())
+    }
+
+    /* handle_command - Carries out one command.
+     *
+     * inputs:
+     *     - &mut self
+     *     - command (Command): The parsed command.
+     *     - argument (&str): Text after the command letter (the song ID for `l`, the track for `w`).
+     *     - out (&mut impl Write): Where to print the response.
+     *
+     * outputs:
+     *     - io::Result<bool>: Ok(false) when the user asked to quit, otherwise Ok(true).
+     */
+    fn handle_command(&mut self, command: Command, argument: &str, out: &mut impl Write) -> io::Result<bool> {
+        let action = match command {
+            Command::Status => {
+                if self.has_song {
+                    let state = if self.is_playing { "Playing" } else { "Paused" };
+                    let loudness = match self.loudness_db {
+                        Some(loudness) => format!(", loudness {:.0} dB", loudness),
+                        None => String::new(),
+                    };
+                    writeln!(
+                        out,
+                        "{}: {} {} {}, at {}{}",
+                        state,
+                        self.app_state.scale,
+                        self.app_state.quality.to_lowercase(),
+                        self.app_state.style,
+                        self.position(),
+                        loudness
+                    )?;
+                } else {
+                    writeln!(out, "Nothing is playing. Type g to generate a song.")?;
+                }
+                return Ok(true);
+            }
+            Command::Waveform => match WAVEFORM_TRACKS.iter().position(|track| track.eq_ignore_ascii_case(argument)) {
+                Some(track) => UserAction::CycleWaveform(track),
+                None => {
+                    writeln!(out, "Type w lead, w chords or w bass to change a track's waveform.")?;
+                    return Ok(true);
+                }
+            },
+            Command::Action(action) => action,
+        };
+
+        match action {
+            UserAction::Quit => return Ok(false),
+            UserAction::ToggleHelp => {
+                for line in HELP_LINES {
+                    writeln!(out, "{}", line)?;
+                }
+            }
+            UserAction::TogglePlayback if !self.has_song => {
+                writeln!(out, "Nothing is playing. Type g to generate a song.")?;
+            }
+            UserAction::TogglePlayback => {
+                if self.is_playing {
+                    self.controller.send(MusicControl::Pause);
+                    self.is_playing = false;
+                    writeln!(out, "Paused at {}", self.position())?;
+                } else {
+                    self.controller.send(MusicControl::Resume);
+                    self.is_playing = true;
+                    writeln!(out, "Playing from {}", self.position())?;
+                }
+            }
+            UserAction::RewindSong if self.has_song => {
+                match self.controller.rewind(Instant::now()) {
+                    Some(RewindTarget::Section) => {
+                        writeln!(out, "Rewound to the start of the section. Press r again to restart the song.")?;
+                    }
+                    _ => {
+                        self.current_samples = 0;
+                        writeln!(out, "Rewound to the start.")?;
+                    }
+                }
+                self.is_playing = true;
+            }
+            UserAction::RewindSong => {
+                writeln!(out, "Nothing is playing. Type g to generate a song.")?;
+            }
+            UserAction::FastForwardSong => {
+                writeln!(out, "Skipping to the next song...")?;
+                self.controller.skip(&self.app_state);
+                self.has_song = true;
+            }
+            UserAction::GenerateRandomMusic => {
+                writeln!(out, "Generating a new song...")?;
+                self.controller
+                    .start_song(controller::random_song_state(&self.app_state));
+                self.has_song = true;
+            }
+            UserAction::ShareSong => match self.controller.current_seed() {
+                Some(seed) if self.has_song => {
+                    match share::share_code(&controller::song_id(&self.app_state, seed)) {
+                        Ok(code) => writeln!(out, "Share code: {}", code)?,
+                        Err(error_message) => writeln!(out, "Could not make a share code. {}", error_message)?,
+                    }
+                }
+                _ => writeln!(out, "Nothing is playing. Type g to generate a song.")?,
+            },
+            UserAction::VarySong(track) => match self.controller.current_seed() {
+                Some(seed) => {
+                    writeln!(
+                        out,
+                        "{}",
+                        match track {
+                            VariedTrack::Melody => "Same song, new melody...",
+                            VariedTrack::Bass => "Same song, new bass line...",
+                        }
+                    )?;
+                    self.controller
+                        .start_song(controller::varied_song_state(&self.app_state, seed, track));
+                }
+                None => writeln!(out, "Nothing is playing. Type g to generate a song.")?,
+            },
+            UserAction::AttemptLoadSong => match parse_song_id_to_app_state(argument) {
+                Ok(mut loaded_app_state) => {
+                    loaded_app_state.carry_playback_settings(&self.app_state);
+                    writeln!(out, "Loading {}...", argument)?;
+                    if loaded_app_state.id_version < SONG_ID_VERSION {
+                        writeln!(
+                            out,
+                            "This is a version {} song ID; it may sound different from when it was shared.",
+                            loaded_app_state.id_version
+                        )?;
+                    }
+                    self.controller.start_song(loaded_app_state);
+                    self.controller.send(MusicControl::Resume);
+                    self.has_song = true;
+                }
+                Err(error_message) => {
+                    writeln!(out, "Could not load song. {}", error_message)?;
+                }
+            },
+            UserAction::VolumeUp | UserAction::VolumeDown => {
+                let volume = self
+                    .controller
+                    .step_volume(matches!(action, UserAction::VolumeUp));
+                let muted_note = if self.controller.is_muted() { " (muted)" } else { "" };
+                writeln!(out, "Volume {}%{}", (volume * 100.0).round() as u32, muted_note)?;
+            }
+            UserAction::ToggleLoop => {
+                self.app_state.is_looping = !self.app_state.is_looping;
+                self.controller
+                    .send(MusicControl::SetLoop(self.app_state.is_looping));
+                if self.app_state.is_looping {
+                    writeln!(out, "Loop on: the current song will repeat.")?;
+                } else {
+                    writeln!(out, "Loop off.")?;
+                }
+            }
+            UserAction::ToggleDrums => {
+                self.app_state.drums = !self.app_state.drums;
+                if self.app_state.drums {
+                    writeln!(out, "Drums on from the next song.")?;
+                } else {
+                    writeln!(out, "Drums off from the next song.")?;
+                }
+            }
+            UserAction::CycleHarmony => {
+                self.app_state.harmony = next_harmony(&self.app_state.harmony).to_string();
+                match self.app_state.harmony.as_str() {
+                    "Chorus" => writeln!(out, "Harmony in the choruses from the next song.")?,
+                    "Song" => writeln!(out, "Harmony through the whole song from the next song.")?,
+                    _ => writeln!(out, "Harmony off from the next song.")?,
+                }
+            }
+            UserAction::CycleCounterMelody => {
+                self.app_state.counter_melody = next_counter_melody(&self.app_state.counter_melody).to_string();
+                writeln!(out, "Counter-melody {} from the next song.", self.app_state.counter_melody.to_lowercase())?;
+            }
+            UserAction::CycleArpChords => {
+                self.app_state.arp_chords = next_arp_chords(&self.app_state.arp_chords).to_string();
+                writeln!(out, "Arp chords {} from the next song.", self.app_state.arp_chords.to_lowercase())?;
+            }
+            UserAction::CycleComping => {
+                self.app_state.comping = next_comping(&self.app_state.comping).to_string();
+                writeln!(out, "Comping {} from the next song.", self.app_state.comping.to_lowercase())?;
+            }
+            UserAction::CyclePulseWidth => {
+                self.app_state.pulse_width = next_pulse_width(&self.app_state.pulse_width).to_string();
+                writeln!(out, "Pulse width {} from the next song.", self.app_state.pulse_width.to_lowercase())?;
+            }
+            UserAction::CycleBrightness => {
+                self.app_state.brightness = next_brightness(&self.app_state.brightness).to_string();
+                writeln!(out, "Brightness {} from the next song.", self.app_state.brightness.to_lowercase())?;
+            }
+            UserAction::CycleEcho => {
+                self.app_state.echo = next_echo(&self.app_state.echo).to_string();
+                writeln!(out, "Echo {} from the next song.", self.app_state.echo.to_lowercase())?;
+            }
+            UserAction::CycleLoFi => {
+                self.app_state.lofi = next_lofi(&self.app_state.lofi).to_string();
+                writeln!(out, "Lo-fi {} from the next song.", self.app_state.lofi.to_lowercase())?;
+            }
+            UserAction::ToggleNesMode => {
+                self.app_state.nes_mode = !self.app_state.nes_mode;
+                if self.app_state.nes_mode {
+                    writeln!(out, "NES mode on from the next song.")?;
+                } else {
+                    writeln!(out, "NES mode off from the next song.")?;
+                }
+            }
+            UserAction::CycleWaveform(track) => {
+                let setting = &mut self.app_state.waveforms[track];
+                *setting = next_waveform(setting).to_string();
+                writeln!(out, "{} waveform {} from the next song.", WAVEFORM_TRACKS[track], setting.to_lowercase())?;
+            }
+            UserAction::CycleModulation => {
+                self.app_state.modulation = next_modulation(&self.app_state.modulation).to_string();
+                writeln!(out, "Key change {} from the next song.", self.app_state.modulation.to_lowercase())?;
+            }
+            UserAction::CycleCrossfade => {
+                self.app_state.crossfade_secs =
+                    (self.app_state.crossfade_secs + 1) % (MAX_CROSSFADE_SECS + 1);
+                self.controller
+                    .send(MusicControl::SetCrossfade(self.app_state.crossfade_secs));
+                match self.app_state.crossfade_secs {
+                    0 => writeln!(out, "Crossfade off.")?,
+                    1 => writeln!(out, "Crossfade 1 second.")?,
+                    seconds => writeln!(out, "Crossfade {} seconds.", seconds)?,
+                }
+            }
+            UserAction::ToggleMute => {
+                if self.controller.toggle_mute() {
+                    writeln!(out, "Muted.")?;
+                } else {
+                    writeln!(
+                        out,
+                        "Unmuted, volume {}%.",
+                        (self.controller.volume() * 100.0).round() as u32
+                    )?;
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /* run - Processes commands and progress updates until the user quits or input ends.
+     *
+     * inputs:
+     *     - &mut self
+     *     - commands (CrossbeamReceiver<String>): Lines of user input.
+     *     - out (&mut impl Write): Where to print output.
+     *
+     * outputs:
+     *     - io::Result<()>: Ok on a normal exit, or the error from writing.
+     */
+    fn run(&mut self, commands: CrossbeamReceiver<String>, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            while let Some(progress) = self.controller.try_recv_progress() {
+                self.handle_progress(progress, out)?;
+            }
+            out.flush()?;
+
+            match commands.recv_timeout(POLL_INTERVAL) {
+                Ok(line) if line.trim().is_empty() => {}
+                Ok(line) => match parse_command(&line) {
+                    Some((command, argument)) => {
+                        if !self.handle_command(command, argument, out)? {
+                            break;
+                        }
+                    }
+                    None => writeln!(out, "Unknown command '{}'. Type ? for help.", line.trim())?,
+                },
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break, // End of input
+            }
+        }
+        if !self.controller.shutdown(controller::QUIT_SHUTDOWN_DEADLINE) {
+            writeln!(out, "Warning: The music service did not stop in time and was left to exit with the app.")?;
+        }
+        workers::global().shutdown(workers::QUIT_SHUTDOWN_DEADLINE);
+        writeln!(out, "Goodbye.")?;
+        out.flush()
+    }
+}
+
+/* run - Entry point of the `--a11y` plain output mode.
+ *
+ * Uses plain stdout lines instead of the full-screen TUI: no alternate screen, raw mode or
+ * cursor movement. Stdin is read on a separate thread so progress can be announced while
+ * waiting for a command.
+ *
+ * inputs:
+ *     - config (&Config): Startup defaults from the config file.
+ *
+ * outputs:
+ *     - Result<(), Box<dyn Error>>: Ok on exit, or an error if output could not be written.
+ */
+pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    writeln!(out, "8BitBeats {} (accessible mode).", env!("CARGO_PKG_VERSION"))?;
+
+    // Same once-per-upgrade changelog as the TUI, printed instead of shown in a popup
+    let current_version = env!("CARGO_PKG_VERSION");
+    let last_seen_version = session::load_last_seen_version();
+    if changelog::should_show_whats_new(last_seen_version.as_deref(), current_version) {
+        writeln!(out, "What's new:")?;
+        let last_seen = last_seen_version.as_deref().unwrap_or_default();
+        for line in changelog::whats_new_lines(last_seen, current_version) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    if last_seen_version.as_deref() != Some(current_version) {
+        let _ = session::save_last_seen_version(current_version);
+    }
+    writeln!(out, "Type g and press Enter to generate a song, or ? for all commands.")?;
+
+    let (command_sender, command_receiver) = crossbeam_channel::unbounded::<String>();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if command_sender.send(line).is_err() {
+                break;
+            }
+        }
+        // Dropping the sender tells the interface that input has ended
+    });
+
+    let mut app_state = AppState::default();
+    config.apply(&mut app_state);
+    let mut interface = LineInterface::new(app_state);
+    if config.autoplay {
+        writeln!(out, "Generating a song...")?;
+        let app_state = controller::fresh_song_state(&interface.app_state);
+        interface.controller.start_song(app_state);
+        interface.has_song = true;
+    }
+    interface.run(command_receiver, &mut out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{AudioOutput, MIN_SONG_SECS};
+    use std::sync::{Arc, Mutex};
+
+    // WAIT_LIMIT: How long a test waits for the interface to say something.
+    const WAIT_LIMIT: Duration = Duration::from_secs(60);
+
+    // Output the test can read while the interface is still writing to it
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedOutput {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).lines().map(str::to_string).collect()
+        }
+
+        fn wait_for(&self, start: &str) -> String {
+            let started = Instant::now();
+            loop {
+                if let Some(line) = self.lines().into_iter().find(|line| line.starts_with(start)) {
+                    return line;
+                }
+                assert!(started.elapsed() < WAIT_LIMIT, "never said {:?}: {:?}", start, self.lines());
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_interface(output: AudioOutput) -> LineInterface {
+        let app_state = AppState {
+            volume: 0.5,
+            ..AppState::default()
+        };
+        LineInterface {
+            controller: PlaybackController::with_output(app_state.volume, output),
+            ..LineInterface::new(app_state)
+        }
+    }
+
+    // Runs the interface on the whole script, as if it was piped to stdin
+    fn run_script(script: &[&str]) -> Vec<String> {
+        let (command_sender, commands) = crossbeam_channel::unbounded();
+        for line in script {
+            command_sender.send(line.to_string()).unwrap();
+        }
+        drop(command_sender);
+        let mut out = SharedOutput::default();
+        test_interface(AudioOutput::Fake(None)).run(commands, &mut out).unwrap();
+        out.lines()
+    }
+
+    #[test]
+    fn scripted_commands_without_a_song_get_plain_answers() {
+        let lines = run_script(&["s", "p", "", "zzz", "+", "m", "m", "d", "w", "w bass", "l not-an-id", "q", "s"]);
+        let expected = [
+            "Nothing is playing. Type g to generate a song.",
+            "Nothing is playing. Type g to generate a song.",
+            "Unknown command 'zzz'. Type ? for help.",
+            "Volume 55%",
+            "Muted.",
+            "Unmuted, volume 55%.",
+            "Drums off from the next song.",
+        ];
+        assert_eq!(&lines[..expected.len()], &expected);
+        let rest = &lines[expected.len()..];
+        assert_eq!(rest[0], "Type w lead, w chords or w bass to change a track's waveform.");
+        let bass_waveform = next_waveform(&AppState::default().waveforms[2]).to_lowercase();
+        assert_eq!(rest[1], format!("Bass waveform {} from the next song.", bass_waveform));
+        assert!(rest[2].starts_with("Could not load song."), "{}", rest[2]);
+        // Nothing after the quit is read
+        assert_eq!(&rest[3..], &["Goodbye."]);
+    }
+
+    #[test]
+    fn the_help_lists_every_command() {
+        let lines = run_script(&["?"]);
+        assert_eq!(&lines[..HELP_LINES.len()], HELP_LINES);
+        assert_eq!(lines.last().map(String::as_str), Some("Goodbye."));
+    }
+
+    #[test]
+    fn a_loaded_song_is_announced_paused_and_described() {
+        let (sink_sender, _sinks) = crossbeam_channel::unbounded();
+        let mut interface = test_interface(AudioOutput::Fake(Some(sink_sender)));
+        let song = AppState {
+            length_secs: MIN_SONG_SECS,
+            bpm: "120".to_string(),
+            ..AppState::default()
+        };
+        let song_id = controller::song_id(&song, 42);
+
+        let (command_sender, commands) = crossbeam_channel::unbounded();
+        let out = SharedOutput::default();
+        let mut interface_out = out.clone();
+        let interface = thread::spawn(move || interface.run(commands, &mut interface_out));
+
+        command_sender.send(format!("l {}", song_id)).unwrap();
+        assert_eq!(out.wait_for("Loading"), format!("Loading {}...", song_id));
+        let now_playing = out.wait_for("Now playing: ");
+        assert!(now_playing.contains(", 120 BPM, ") && now_playing.ends_with(", seed 42"), "{}", now_playing);
+        assert_eq!(out.wait_for("Song ID: "), format!("Song ID: {}", song_id));
+
+        command_sender.send("p".to_string()).unwrap();
+        // Nobody plays the fake device, so the song hasn't moved on; it ends on a whole bar near 1:00
+        let paused = out.wait_for("Paused at ");
+        assert!(paused.starts_with("Paused at 00:00 of 01:0") || paused.starts_with("Paused at 00:00 of 00:5"), "{}", paused);
+        command_sender.send("s".to_string()).unwrap();
+        assert!(out.wait_for("Paused: ").contains(" at "));
+        command_sender.send("r".to_string()).unwrap();
+        out.wait_for("Rewound to the start.");
+        command_sender.send("q".to_string()).unwrap();
+        interface.join().unwrap().unwrap();
+        assert_eq!(out.lines().last().map(String::as_str), Some("Goodbye."));
+    }
+}
@@ -0,0 +1,228 @@
+/* ChangelogEntry - User-visible changes shipped in a single release.
+ *
+ * The changelog is compiled into the binary so the "What's new" popup never
+ * depends on files shipped alongside the executable.
+ *
+ * fields:
+ *     - version (&'static str): The release version, in MAJOR.MINOR.PATCH form.
+ *     - changes (&'static [&'static str]): One short sentence per user-visible change.
+ */
+pub struct ChangelogEntry {
+    pub version: &'static str,
+    pub changes: &'static [&'static str],
+}
+
+// CHANGELOG lists releases newest first.
+pub const CHANGELOG: &[ChangelogEntry] = &[ChangelogEntry {
+    version: "1.1.0",
+    changes: &[
+        "Master volume control: press +/- to change the volume in 5% steps.",
+        "Press m to mute or unmute without pausing the song.",
+        "Mixer controls for the melody, chord and bass levels in the Create New Track panel.",
+        "A level meter next to the progress bar shows the current output level.",
+        "Very slow songs play the melody an octave lower with softer note endings; very fast songs play it more detached.",
+        "Press o to show an oscilloscope of the playing waveform (needs a tall enough terminal).",
+        "Press s to show a spectrum analyzer next to (or instead of) the oscilloscope.",
+        "A beat indicator in the Now Playing panel pulses with the tempo and shows the current bar and beat.",
+        "Press / to jump straight to Load Song; typing replaces the current ID and Esc restores it.",
+        "The Now Playing panel shows the name of the chord currently playing.",
+        "Press L to loop the current song; it repeats seamlessly until you turn looping off.",
+        "Run with --a11y for a plain line-based interface that works with screen readers.",
+        "Skip is near-instant: the next song is prepared in the background while the current one plays.",
+        "After Generate Random, Skip picks another random song, just like when a song ends on its own.",
+        "Typing a share code into Load Song no longer quits the app or pauses the song when it contains q, p, r or f.",
+        "Without an audio device the app shows a notice instead of crashing, and stopping a song never freezes the screen.",
+        "Quitting stops the music before the terminal is restored, and never hangs waiting for a song that is still generating.",
+        "The progress bar follows the audio that has actually played, so it no longer drifts ahead after stalls or long sessions.",
+        "Starting a new song no longer lets the old one play over it for a moment.",
+        "A spinner in the Now Playing panel shows when a song is being generated, and the Generate buttons ignore presses until it starts.",
+        "Songs generate faster on multi-core machines: the lead, bass, drums and chords are synthesized side by side.",
+        "New songs start sooner and use less memory: they're generated a section at a time while they play.",
+        "Pick ∞ as the Length for an endless song that keeps generating as it plays, until you skip or stop it.",
+        "Songs can be any length from 1 to 60 minutes: type 7, 2.5, 90s or 2:30 on the Length field, or pick Custom… from its list.",
+        "Press Y to share a song as a short code like 8BB-LQMGEGnNBT, which loads just like its song ID.",
+        "Song IDs now start with a version, as in \"v2:C-Pop-120-5-42\"; loading an older ID warns that it may sound different.",
+        "Song IDs pasted with stray spaces, lowercase names or a length like \"5min\" now load, and sharp keys such as C# can be typed into Load Song.",
+        "A BPM outside 20-400, typed in or in a song ID, is turned down with an error instead of being quietly changed.",
+        "Song IDs with an unknown scale or style are turned down with a suggestion, e.g. \"did you mean 'Jazz'?\" for Jaz.",
+        "M and V replay the current song with a new melody or bass line, keeping everything else; the song ID remembers the variation.",
+        "The song ID and fields show the scale, BPM and length a song actually played with, and a tempo picked from the seed shows in Now Playing.",
+        "Song IDs and share codes also carry the drums, harmony, counter-melody, arp chords, comping, pulse width, brightness, fade-out and mix, so a shared song sounds the same for everyone.",
+        "Accessible mode announces a song's true length, which ends on a whole bar, rather than the length option.",
+        "Now Playing shows each song's measured loudness next to the volume, and songs aim a little quieter, at -16 dB, for less limiting.",
+        "Songs come out at a steady loudness through a master leveler and limiter, instead of the whole song turning down for its loudest peak.",
+        "Songs fade out over their last 3 seconds (6 for ambient) instead of stopping mid-chord; set fade_out in the config to change it.",
+        "Brightness: a low-pass filter softens square leads, darker for ambient and jazz, sweeping open for electronic; T picks your own.",
+        "NES mode: N keeps a song to two pulses, a triangle bass and noise drums, like the original console.",
+        "Lo-fi bit crusher: electronic and metal get a light 8-bit crunch, and B picks off, light or heavy for any song.",
+        "The melody echoes in time with the song, strongest for ambient and electronic; E turns it off.",
+        "Long melody notes swell into a style-dependent vibrato; short notes stay steady.",
+        "Square waves get chip-style pulse widths: a thin 12.5% electronic lead, 25% for pop and a sweeping PWM for ambient; P cycles them.",
+        "Choose the lead, chord and bass waveforms (square, sine, triangle, saw or noise) with W; basses now default to the NES triangle.",
+        "Melodies phrase by style: staccato electronic and classical runs, slightly detached pop, legato ambient, with a little seeded variation per note.",
+        "Melody notes have an attack, decay, sustain and release envelope (plucky for pop and electronic, soft swells for ambient), so they no longer click.",
+        "Held bass notes fade in and out over a few milliseconds, so chord changes no longer click.",
+        "Pop bass plays the root on beat 1 and the fifth on beat 3; folk bass alternates root and fifth in the waltz.",
+        "Jazz bass walks in quarter notes from each chord's root to the next instead of holding it.",
+        "Chords are comped in a rhythm per style (Charleston for jazz, eighths for rock, 1 and 3 for pop); R switches back to sustained chords.",
+        "Custom chord progressions typed in roman numerals (e.g. I V vi IV), carried in the song ID.",
+        "Chord progressions vary across the song: two progressions alternate every 8 bars or so, with a turnaround bar between.",
+        "More chord progressions (twelve-bar blues, doo-wop, Andalusian, ii-V-I turnaround, minor i-iv-v), picked per seed within a style.",
+        "Chip-style arp chords, rapidly cycling the chord notes on one square voice; the default for electronic ([A] to change).",
+        "Classical and jazz songs add a slow counter-melody that moves against the melody ([C] to change).",
+        "Optional harmony voice a third above the melody, in the choruses or the whole song ([H]).",
+        "Songs end on a cadence: the chords resolve home, the melody holds the root and the bass lands on it.",
+        "Longer songs can change key for their last section; press K to set the key change to Auto, On or Off.",
+        "Songs are arranged into an intro, verses, choruses, a bridge in the relative key and a fading outro; the current section is shown next to the chord.",
+        "Melodies are built from a short motif and its answer, repeated in an AABA or ABAB plan with variations, so tunes come back around.",
+        "Melodies breathe: every style rests now and then, most often after a long phrase, and picks up where it left off.",
+        "Melodies can play triplets: jazz and blues lean on them, and busier lines slip one in now and then.",
+        "Songs can be in 3/4 or 6/8 as well as 4/4: pick one with the new Time field, or leave it on Auto for folk waltzes and classical pieces in 3/4 or 6/8.",
+        "Jazz and blues swing their eighth notes, melody, bass and drums together; set Swing to override any style's feel.",
+        "The drums mark the end of every 4 or 8 bars with a fill: a snare roll, a run down the toms or a crash into the next bar.",
+        "Drums follow the style, with a swung ride for jazz, a shuffle for blues, a one drop for reggae and blast beats for metal, and each song gets its own variation and fills.",
+        "Songs have a drum track of kick, snare and hi-hat, with a groove that suits the style; press d (or set drums = false in the config file) to turn it off.",
+        "Reggae has its own sound: staccato chord skanks on the off-beats, a syncopated root and fifth bass and a sparse melody.",
+        "Metal has its own sound: distorted low power chords, a fast minor or Phrygian melody and a relentless sixteenth-note bass.",
+        "Folk has its own sound: a 3/4 waltz with the bass on beat one, chord stabs on two and three, and a stepwise melody.",
+        "Classical has its own sound: an Alberti-bass accompaniment, a soft sine melody and a bass note on the first beat of each chord.",
+        "Ambient has its own sound: slowly swelling pads over long chords, a sparse melody with plenty of rests and a soft sustained bass.",
+        "Electronic has its own sound: sixteenth-note arpeggios over a minor progression, an octave-bouncing square bass and a kick on every beat.",
+        "Rock has its own sound: power chords, a driving eighth-note melody and a pumping bass (rock song IDs from earlier versions sound different).",
+        "Blues melodies use a real blues scale (flat third, fifth and seventh), so blues song IDs from earlier versions sound different.",
+        "New Key control next to Scale: generate in Major or Minor (song IDs mark minor keys, e.g. Cm-Pop-120-5-123).",
+        "Long popup lists scroll, with a scrollbar; PgUp/PgDn and Home/End jump through them.",
+        "Type in the Scale, Style and Length popups to filter their options.",
+        "The Scale, Style and Length popups open with the current value highlighted.",
+        "Click or drag the progress bar to jump around in the current song.",
+        "Click buttons, fields and popup items with the mouse; scroll through popup lists with the wheel.",
+        "Terminals smaller than 80x25 (down to 60x18) get a compact layout instead of a resize warning.",
+        "Pick a color theme (default, gruvbox, monochrome) with theme = \"...\" in the config file; NO_COLOR turns colors off.",
+        "Set your default scale, style, BPM, length, volume and autoplay in ~/.config/8bitbeats/config.toml (or pass --config <path>).",
+        "Press . to stop after the current song instead of moving on to the next one.",
+        "The seed field stops at 20 digits, shows a counter near the limit and rejects seeds above 18446744073709551615.",
+        "Paste song IDs and seeds with Ctrl+V or your terminal's paste; characters a field doesn't accept are dropped.",
+        "The progress bar moves smoothly on long songs instead of jumping a percent at a time.",
+        "Press y to copy the current song ID to the clipboard (over SSH too, in terminals that support OSC 52).",
+        "Run 8bitbeats doctor to print an audio, terminal and storage report to paste into bug reports.",
+        "Press S to save the current song to your library (saved songs are starred) and v to browse, load or remove saved songs.",
+        "Terminals without colors, Unicode glyphs or an alternate screen get a simplified display; unusable ones (such as TERM=dumb) exit with a hint to use --a11y.",
+        "Press b (or the new Prev button) to go back to the previous song; the last 100 songs are remembered.",
+        "Rewind jumps to the start of the current 8-bar section; press it twice to restart the song (or run with --rewind-to-start).",
+        "Press x to crossfade between songs (off, or 1 to 5 seconds) on skip and when a song ends.",
+        "Song IDs with extreme tempos or lengths (such as a length of 0) now play a normal song instead of silence.",
+        "New songs start playing almost immediately: the opening plays while the rest is still being generated.",
+        "This popup: a short summary of changes shown once after an upgrade.",
+        "Generated audio can change between versions, so a saved song ID may sound different than when you saved it.",
+    ],
+}];
+
+/* parse_version - Parses a "MAJOR.MINOR.PATCH" version string.
+ *
+ * Missing minor or patch components are treated as 0, so "1.2" parses as 1.2.0.
+ *
+ * inputs:
+ *     - version (&str): The version string to parse.
+ *
+ * outputs:
+ *     - Option<(u32, u32, u32)>: The parsed version, or None if any component is not a number.
+ */
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/* should_show_whats_new - Decides whether the "What's new" popup should be shown at startup.
+ *
+ * The popup is only shown after an upgrade. A fresh install (no recorded version) has
+ * nothing "new" to report, and a downgrade or unparseable version is silently ignored.
+ *
+ * inputs:
+ *     - last_seen (Option<&str>): The version recorded in the session file, if any.
+ *     - current (&str): The version of the running binary.
+ *
+ * outputs:
+ *     - bool: True if the popup should be shown.
+ */
+pub fn should_show_whats_new(last_seen: Option<&str>, current: &str) -> bool {
+    match (last_seen.and_then(parse_version), parse_version(current)) {
+        (Some(last), Some(current)) => current > last,
+        _ => false,
+    }
+}
+
+/* whats_new_lines - Builds the popup text for every release newer than `last_seen`.
+ *
+ * inputs:
+ *     - last_seen (&str): The version the user last acknowledged.
+ *     - current (&str): The version of the running binary.
+ *
+ * outputs:
+ *     - Vec<String>: The lines to display, grouped under a heading per release.
+ */
+pub fn whats_new_lines(last_seen: &str, current: &str) -> Vec<String> {
+    let last_seen = parse_version(last_seen);
+    let current = parse_version(current);
+    let mut lines = Vec::new();
+    for entry in CHANGELOG {
+        let version = parse_version(entry.version);
+        if version <= last_seen || version > current {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(format!("Version {}:", entry.version));
+        lines.extend(entry.changes.iter().map(|change| format!("  - {}", change)));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_fresh_install_shows_nothing() {
+        assert!(!should_show_whats_new(None, "1.1.0"));
+    }
+
+    #[test]
+    fn the_same_version_shows_nothing() {
+        assert!(!should_show_whats_new(Some("1.1.0"), "1.1.0"));
+    }
+
+    #[test]
+    fn an_upgrade_shows_the_popup() {
+        assert!(should_show_whats_new(Some("1.0.1"), "1.1.0"));
+        assert!(should_show_whats_new(Some("1.1.0"), "1.1.1"));
+        assert!(should_show_whats_new(Some("0.9"), "1.0.0"));
+    }
+
+    #[test]
+    fn a_downgrade_or_garbage_shows_nothing() {
+        assert!(!should_show_whats_new(Some("1.1.0"), "1.0.1"));
+        assert!(!should_show_whats_new(Some("not a version"), "1.1.0"));
+    }
+
+    #[test]
+    fn the_newest_entry_is_the_running_version() {
+        // Otherwise anyone upgrading from the previous release never sees the popup
+        assert_eq!(CHANGELOG[0].version, env!("CARGO_PKG_VERSION"));
+        assert!(should_show_whats_new(Some("1.0.1"), env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn an_upgrade_lists_only_the_newer_releases() {
+        let lines = whats_new_lines("1.0.1", "1.1.0");
+        assert_eq!(lines[0], "Version 1.1.0:");
+        assert_eq!(lines.len(), CHANGELOG[0].changes.len() + 1);
+        assert!(whats_new_lines("1.1.0", "1.1.0").is_empty());
+    }
+}
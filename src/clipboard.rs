@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+// CLIPBOARD_PROVIDERS: Clipboard commands tried in order, with the arguments that make them read stdin.
+const CLIPBOARD_PROVIDERS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+    ("clip.exe", &[]),
+];
+
+// CLIPBOARD_READERS: Clipboard commands tried in order, with the arguments that make them print the clipboard.
+const CLIPBOARD_READERS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-out"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("pbpaste", &[]),
+    ("powershell.exe", &["-NoProfile", "-Command", "Get-Clipboard"]),
+];
+
+// BASE64_ALPHABET: Standard base64 alphabet, used to encode OSC 52 payloads.
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/* base64_encode - Encodes bytes as padded standard base64.
+ *
+ * inputs:
+ *     - bytes (&[u8]): The data to encode.
+ *
+ * outputs:
+ *     - String: The encoded data.
+ */
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | (chunk.get(1).copied().unwrap_or(0) as u32) << 8
+            | chunk.get(2).copied().unwrap_or(0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/* copy_with_provider - Pipes text into a clipboard command.
+ *
+ * inputs:
+ *     - program (&str): The clipboard command.
+ *     - args (&[&str]): Its arguments.
+ *     - text (&str): The text to copy.
+ *
+ * outputs:
+ *     - io::Result<()>: Ok if the command ran and succeeded, or an error otherwise.
+ */
+fn copy_with_provider(program: &str, args: &[&str], text: &str) -> io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+        // Dropping stdin closes the pipe so the command sees the end of the input
+    }
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed", program)))
+    }
+}
+
+/* copy - Copies text to the system clipboard.
+ *
+ * Tries the usual clipboard commands first. If none is available (e.g. over SSH), falls back
+ * to an OSC 52 escape sequence, which asks the terminal itself to set the clipboard; terminals
+ * without OSC 52 support ignore it.
+ *
+ * inputs:
+ *     - text (&str): The text to copy.
+ *
+ * outputs:
+ *     - io::Result<()>: Ok if the text was handed to a clipboard provider or the terminal.
+ */
+pub fn copy(text: &str) -> io::Result<()> {
+    if CLIPBOARD_PROVIDERS
+        .iter()
+        .any(|(program, args)| copy_with_provider(program, args, text).is_ok())
+    {
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))?;
+    stdout.flush()
+}
+
+/* paste - Reads text from the system clipboard.
+ *
+ * OSC 52 reads are rarely allowed by terminals, so over SSH pasting relies on the terminal's
+ * own paste, which arrives as a bracketed paste event instead.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - io::Result<String>: The clipboard text, or an error if no clipboard command worked.
+ */
+pub fn paste() -> io::Result<String> {
+    for (program, args) in CLIPBOARD_READERS {
+        let Ok(output) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        else {
+            continue;
+        };
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no clipboard command available"))
+}
@@ -0,0 +1,556 @@
+use crate::gen::{parse_song_length, MAX_BPM, MAX_FADE_OUT_SECS, MAX_SONG_SECS, MIN_BPM, MIN_SONG_SECS};
+use crate::theme::THEME_NAMES;
+use crate::tui::AppState;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// CONFIG_FLAG: Command-line flag that points at a config file other than the default one.
+pub const CONFIG_FLAG: &str = "--config";
+
+// CONFIG_FILE_NAME: Name of the config file inside the config directory.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+// MAX_VOLUME_PERCENT: Highest default volume, matching the volume control's 200% ceiling.
+const MAX_VOLUME_PERCENT: i64 = 200;
+
+/* Value - A config value, covering the parts of TOML the config file uses.
+ */
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String), // "quoted text"
+    Integer(i64),   // 120
+    Float(f64),     // 0.5
+    Boolean(bool),  // true or false
+}
+
+impl Value {
+    /* type_name - Names the value's type for error messages.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - &'static str: e.g. "a string".
+     */
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "a whole number",
+            Value::Float(_) => "a decimal number",
+            Value::Boolean(_) => "true or false",
+        }
+    }
+}
+
+/* Config - Startup defaults read from the config file.
+ *
+ * Every setting is optional; unset ones keep the built-in defaults.
+ *
+ * fields:
+ *     - scale (Option<String>): Default scale, one of the scale options.
+ *     - style (Option<String>): Default style, one of the style options.
+ *     - bpm (Option<u32>): Default tempo.
+ *     - length_secs (Option<u32>): Default song length, in seconds.
+ *     - volume (Option<f32>): Starting master volume (1.0 = 100%).
+ *     - autoplay (bool): True to generate and play a song right after startup.
+ *     - drums (Option<bool>): Whether songs start with the drum track on.
+ *     - fade_out_secs (Option<u32>): Seconds every song fades out over at its end (0 = none).
+ *     - theme (Option<String>): Color theme, one of `THEME_NAMES`.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub scale: Option<String>,
+    pub style: Option<String>,
+    pub bpm: Option<u32>,
+    pub length_secs: Option<u32>,
+    pub volume: Option<f32>,
+    pub autoplay: bool,
+    pub drums: Option<bool>,
+    pub fade_out_secs: Option<u32>,
+    pub theme: Option<String>,
+}
+
+/* config_dir - Returns the directory holding the config file.
+ *
+ * Follows the XDG base directory convention: $XDG_CONFIG_HOME/8bitbeats, falling back
+ * to ~/.config/8bitbeats when XDG_CONFIG_HOME is unset.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - Option<PathBuf>: The config directory, or None if no home directory can be determined.
+ */
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("8bitbeats"))
+}
+
+/* config_path_from_args - Reads the `--config <path>` flag.
+ *
+ * inputs:
+ *     - args (&[String]): The command-line arguments, without the program name.
+ *
+ * outputs:
+ *     - Result<Option<PathBuf>, String>: The given path, None if the flag is absent, or an
+ *       error if the flag has no path after it.
+ */
+pub fn config_path_from_args(args: &[String]) -> Result<Option<PathBuf>, String> {
+    let Some(index) = args.iter().position(|arg| arg == CONFIG_FLAG) else {
+        return Ok(None);
+    };
+    match args.get(index + 1) {
+        Some(path) if !path.starts_with("--") => Ok(Some(PathBuf::from(path))),
+        _ => Err(format!("{} needs a file path, e.g. {} my-config.toml", CONFIG_FLAG, CONFIG_FLAG)),
+    }
+}
+
+/* parse_value - Parses the right-hand side of a `key = value` line.
+ *
+ * inputs:
+ *     - text (&str): The value text, with any trailing comment already removed.
+ *
+ * outputs:
+ *     - Option<Value>: The value, or None if it isn't a supported TOML value.
+ */
+fn parse_value(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Some(inner) = text
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .or_else(|| text.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')))
+    {
+        return Some(Value::String(inner.to_string()));
+    }
+    match text {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        _ => {}
+    }
+    // TOML allows underscores between digits, e.g. 1_000
+    let number = text.replace('_', "");
+    if let Ok(integer) = number.parse::<i64>() {
+        return Some(Value::Integer(integer));
+    }
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|float| float.is_finite())
+        .map(Value::Float)
+}
+
+/* strip_comment - Removes a `#` comment from a line, leaving `#` inside strings alone.
+ *
+ * inputs:
+ *     - line (&str): A line of the config file.
+ *
+ * outputs:
+ *     - &str: The line without its comment.
+ */
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, ch) in line.char_indices() {
+        match (quote, ch) {
+            (None, '"' | '\'') => quote = Some(ch),
+            (Some(open), _) if ch == open => quote = None,
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/* parse_config - Parses and validates config file contents.
+ *
+ * inputs:
+ *     - contents (&str): The file contents.
+ *     - source (&str): The file name, used in messages.
+ *
+ * outputs:
+ *     - Result<(Config, Vec<String>), String>: The config and warnings about ignored keys, or
+ *       an error describing the first malformed line or out-of-range value.
+ */
+pub fn parse_config(contents: &str, source: &str) -> Result<(Config, Vec<String>), String> {
+    let defaults = AppState::default();
+    let mut config = Config::default();
+    let mut warnings = Vec::new();
+    let mut section: Option<String> = None;
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            warnings.push(format!(
+                "{} line {}: section [{}] is not used; its keys are ignored",
+                source,
+                line_number,
+                name.trim()
+            ));
+            section = Some(name.trim().to_string());
+            continue;
+        }
+        let Some((key, value_text)) = line.split_once('=') else {
+            return Err(format!(
+                "{} line {}: expected `key = value`, found `{}`",
+                source, line_number, line
+            ));
+        };
+        // Keys of a section are skipped before their values are read, so they can hold anything
+        if section.is_some() {
+            continue;
+        }
+        let key = key.trim();
+        let Some(value) = parse_value(value_text) else {
+            return Err(format!(
+                "{} line {}: `{}` is not a valid value for {} (strings need quotes)",
+                source,
+                line_number,
+                value_text.trim(),
+                key
+            ));
+        };
+
+        let wrong_type = |expected: &str| {
+            format!(
+                "{} line {}: {} must be {}, not {}",
+                source,
+                line_number,
+                key,
+                expected,
+                value.type_name()
+            )
+        };
+        let out_of_range = |shown: String, range: String| {
+            format!(
+                "{} line {}: {} = {} is out of range ({})",
+                source, line_number, key, shown, range
+            )
+        };
+        match key {
+            "scale" | "style" => {
+                let Value::String(text) = &value else {
+                    return Err(wrong_type("a string"));
+                };
+                let options = if key == "scale" { &defaults.scales } else { &defaults.styles };
+                // Matched case-insensitively, stored with the option's own spelling
+                let Some(option) = options.iter().find(|option| option.eq_ignore_ascii_case(text)) else {
+                    return Err(format!(
+                        "{} line {}: unknown {} \"{}\" (choose from {})",
+                        source,
+                        line_number,
+                        key,
+                        text,
+                        options.join(", ")
+                    ));
+                };
+                if key == "scale" {
+                    config.scale = Some(option.clone());
+                } else {
+                    config.style = Some(option.clone());
+                }
+            }
+            "bpm" => {
+                let &Value::Integer(bpm) = &value else {
+                    return Err(wrong_type("a whole number"));
+                };
+                if !(MIN_BPM as i64..=MAX_BPM as i64).contains(&bpm) {
+                    return Err(out_of_range(bpm.to_string(), format!("{}-{}", MIN_BPM, MAX_BPM)));
+                }
+                config.bpm = Some(bpm as u32);
+            }
+            "length" => {
+                // Either minutes (5) or a length as the Length field takes it ("5 min", "2:30", "90s", "inf")
+                let length_secs = match &value {
+                    Value::Integer(minutes) => {
+                        let range = (MIN_SONG_SECS / 60) as i64..=(MAX_SONG_SECS / 60) as i64;
+                        if !range.contains(minutes) {
+                            return Err(out_of_range(
+                                minutes.to_string(),
+                                format!("{}-{} minutes", range.start(), range.end()),
+                            ));
+                        }
+                        *minutes as u32 * 60
+                    }
+                    Value::String(text) => parse_song_length(text)
+                        .map_err(|error_message| format!("{} line {}: {}", source, line_number, error_message))?,
+                    _ => return Err(wrong_type("a number of minutes")),
+                };
+                config.length_secs = Some(length_secs);
+            }
+            "volume" => {
+                let &Value::Integer(percent) = &value else {
+                    return Err(wrong_type("a whole percentage"));
+                };
+                if !(0..=MAX_VOLUME_PERCENT).contains(&percent) {
+                    return Err(out_of_range(
+                        percent.to_string(),
+                        format!("0-{} percent", MAX_VOLUME_PERCENT),
+                    ));
+                }
+                config.volume = Some(percent as f32 / 100.0);
+            }
+            "autoplay" => {
+                let &Value::Boolean(autoplay) = &value else {
+                    return Err(wrong_type("true or false"));
+                };
+                config.autoplay = autoplay;
+            }
+            "drums" => {
+                let &Value::Boolean(drums) = &value else {
+                    return Err(wrong_type("true or false"));
+                };
+                config.drums = Some(drums);
+            }
+            "fade_out" => {
+                let &Value::Integer(seconds) = &value else {
+                    return Err(wrong_type("a whole number of seconds"));
+                };
+                if !(0..=MAX_FADE_OUT_SECS as i64).contains(&seconds) {
+                    return Err(out_of_range(
+                        seconds.to_string(),
+                        format!("0-{} seconds", MAX_FADE_OUT_SECS),
+                    ));
+                }
+                config.fade_out_secs = Some(seconds as u32);
+            }
+            "theme" => {
+                let Value::String(text) = &value else {
+                    return Err(wrong_type("a string"));
+                };
+                let Some(name) = THEME_NAMES.iter().find(|name| name.eq_ignore_ascii_case(text)) else {
+                    return Err(format!(
+                        "{} line {}: unknown theme \"{}\" (choose from {})",
+                        source,
+                        line_number,
+                        text,
+                        THEME_NAMES.join(", ")
+                    ));
+                };
+                config.theme = Some(name.to_string());
+            }
+            _ => warnings.push(format!(
+                "{} line {}: unknown key `{}` is ignored",
+                source, line_number, key
+            )),
+        }
+    }
+    Ok((config, warnings))
+}
+
+/* load - Reads the config file.
+ *
+ * A missing default file means built-in defaults; a missing file named with `--config` is
+ * an error, since the user asked for it explicitly.
+ *
+ * inputs:
+ *     - path_override (Option<PathBuf>): The file given with `--config`, if any.
+ *
+ * outputs:
+ *     - Result<(Config, Vec<String>), String>: The config and warnings, or an error message.
+ */
+pub fn load(path_override: Option<PathBuf>) -> Result<(Config, Vec<String>), String> {
+    let explicit = path_override.is_some();
+    let Some(path) = path_override.or_else(|| config_dir().map(|dir| dir.join(CONFIG_FILE_NAME))) else {
+        return Ok((Config::default(), Vec::new()));
+    };
+    read_config(&path, explicit)
+}
+
+/* read_config - Reads and parses a config file.
+ *
+ * inputs:
+ *     - path (&Path): The file to read.
+ *     - explicit (bool): True if the file was named with `--config`, so it must exist.
+ *
+ * outputs:
+ *     - Result<(Config, Vec<String>), String>: The config and warnings (the built-in defaults
+ *       if the default file is missing), or an error message.
+ */
+fn read_config(path: &Path, explicit: bool) -> Result<(Config, Vec<String>), String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound && !explicit => {
+            return Ok((Config::default(), Vec::new()));
+        }
+        Err(error) => return Err(format!("Could not read {}: {}", path.display(), error)),
+    };
+    parse_config(&contents, &path.display().to_string())
+}
+
+impl Config {
+    /* apply - Overrides the built-in defaults with the configured ones.
+     *
+     * inputs:
+     *     - &self
+     *     - app_state (&mut AppState): The state to update.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn apply(&self, app_state: &mut AppState) {
+        if let Some(scale) = &self.scale {
+            app_state.scale = scale.clone();
+        }
+        if let Some(style) = &self.style {
+            app_state.style = style.clone();
+        }
+        if let Some(bpm) = self.bpm {
+            app_state.bpm = bpm.to_string();
+        }
+        if let Some(length_secs) = self.length_secs {
+            app_state.length_secs = length_secs;
+        }
+        if let Some(volume) = self.volume {
+            app_state.volume = volume;
+        }
+        if let Some(drums) = self.drums {
+            app_state.drums = drums;
+        }
+        if self.fade_out_secs.is_some() {
+            app_state.fade_out_secs = self.fade_out_secs;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parses a config, failing the test on an error
+    fn parse(contents: &str) -> (Config, Vec<String>) {
+        parse_config(contents, "test.toml").unwrap_or_else(|error| panic!("{}: {}", contents, error))
+    }
+
+    // Parses a config that must be rejected, returning the error
+    fn parse_error(contents: &str) -> String {
+        match parse_config(contents, "test.toml") {
+            Ok(parsed) => panic!("{} was accepted as {:?}", contents, parsed),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn every_key_is_read_at_the_edges_of_its_range() {
+        let (config, warnings) = parse(
+            "scale = \"f#\"\nstyle = 'jazz'\nbpm = 20\nlength = 60\nvolume = 200\nautoplay = true\ndrums = false\nfade_out = 20\ntheme = \"Gruvbox\"\n",
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(
+            config,
+            Config {
+                scale: Some("F#".to_string()),
+                style: Some("Jazz".to_string()),
+                bpm: Some(MIN_BPM),
+                length_secs: Some(MAX_SONG_SECS),
+                volume: Some(2.0),
+                autoplay: true,
+                drums: Some(false),
+                fade_out_secs: Some(MAX_FADE_OUT_SECS),
+                theme: Some("gruvbox".to_string()),
+            }
+        );
+        let (config, _) = parse("bpm = 400\nlength = 1\nvolume = 0\nfade_out = 0\n");
+        assert_eq!(
+            (config.bpm, config.length_secs, config.volume, config.fade_out_secs),
+            (Some(MAX_BPM), Some(MIN_SONG_SECS), Some(0.0), Some(0))
+        );
+        // Lengths as the Length field takes them
+        for (length, secs) in [("\"2:30\"", 150), ("\"90s\"", 90), ("\"5 min\"", 300), ("\"inf\"", 0), ("1_0", 600)] {
+            assert_eq!(parse(&format!("length = {}", length)).0.length_secs, Some(secs), "for {}", length);
+        }
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        // (line, what the error names)
+        let cases = [
+            ("bpm = 19", "out of range (20-400)"),
+            ("bpm = 401", "out of range (20-400)"),
+            ("bpm = 0", "out of range"),
+            ("volume = 201", "out of range (0-200 percent)"),
+            ("volume = -1", "out of range"),
+            ("fade_out = 21", "out of range (0-20 seconds)"),
+            ("fade_out = -1", "out of range"),
+            ("length = 0", "out of range (1-60 minutes)"),
+            ("length = 61", "out of range"),
+            ("length = \"30s\"", "out of range"),
+            ("scale = \"H\"", "unknown scale \"H\""),
+            ("style = \"Polka\"", "unknown style"),
+            ("theme = \"neon\"", "unknown theme"),
+        ];
+        for (line, expected) in cases {
+            let error = parse_error(line);
+            assert!(error.starts_with("test.toml line 1:") && error.contains(expected), "{}: {}", line, error);
+        }
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_rejected() {
+        // (line, the type the error asks for)
+        let cases = [
+            ("bpm = \"120\"", "bpm must be a whole number, not a string"),
+            ("bpm = 120.5", "bpm must be a whole number, not a decimal number"),
+            ("volume = 0.5", "volume must be a whole percentage"),
+            ("autoplay = 1", "autoplay must be true or false, not a whole number"),
+            ("drums = \"yes\"", "drums must be true or false"),
+            ("fade_out = true", "fade_out must be a whole number of seconds"),
+            ("scale = 5", "scale must be a string"),
+            ("length = true", "length must be a number of minutes"),
+            ("theme = false", "theme must be a string"),
+        ];
+        for (line, expected) in cases {
+            let error = parse_error(line);
+            assert!(error.contains(expected), "{}: {}", line, error);
+        }
+        // Values that aren't TOML at all
+        for line in ["bpm = fast", "scale = C", "style = \"Pop"] {
+            assert!(parse_error(line).contains("is not a valid value"), "{}", line);
+        }
+        assert!(parse_error("bpm 120").contains("expected `key = value`"));
+    }
+
+    #[test]
+    fn unknown_keys_and_sections_warn_without_failing() {
+        let (config, warnings) = parse("bpm = 90\ncolour = \"red\"\n\n[extra]\nbpm = 999\nstyle = not toml\n");
+        assert_eq!(config.bpm, Some(90));
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].contains("line 2: unknown key `colour`"), "{}", warnings[0]);
+        assert!(warnings[1].contains("line 4: section [extra]"), "{}", warnings[1]);
+    }
+
+    #[test]
+    fn comments_end_a_line_except_inside_strings() {
+        let (config, warnings) = parse("# Defaults\nstyle = \"Pop\" # the usual\nscale = \"C#\" # sharp\n");
+        assert!(warnings.is_empty());
+        assert_eq!((config.style.as_deref(), config.scale.as_deref()), (Some("Pop"), Some("C#")));
+        assert_eq!(strip_comment("theme = \"#1 # best\" # not a theme"), "theme = \"#1 # best\" ");
+        assert_eq!(strip_comment("scale = 'C#' # sharp"), "scale = 'C#' ");
+        assert_eq!(parse_value("\"a # b\""), Some(Value::String("a # b".to_string())));
+    }
+
+    #[test]
+    fn a_missing_default_file_means_defaults_but_a_missing_given_file_is_an_error() {
+        let missing = std::env::temp_dir().join("8bitbeats-test-missing").join(CONFIG_FILE_NAME);
+        assert_eq!(read_config(&missing, false), Ok((Config::default(), Vec::new())));
+        let error = read_config(&missing, true).unwrap_err();
+        assert!(error.starts_with("Could not read"), "{}", error);
+    }
+
+    #[test]
+    fn the_config_flag_needs_a_path_after_it() {
+        let args = |text: &str| text.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(config_path_from_args(&args("--a11y")), Ok(None));
+        assert_eq!(config_path_from_args(&args("--config my.toml --a11y")), Ok(Some(PathBuf::from("my.toml"))));
+        for text in ["--config", "--a11y --config", "--config --a11y"] {
+            let error = config_path_from_args(&args(text)).unwrap_err();
+            assert!(error.contains("needs a file path"), "{}: {}", text, error);
+        }
+    }
+}
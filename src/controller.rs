@@ -0,0 +1,1353 @@
+use crate::gen::{self, AudioOutput, MusicControl, MusicProgress, PreparedSong, MAX_SWING_PERCENT, MINOR_KEY_SUFFIX, SWING_ID_PREFIX};
+use crate::progs::TimeSignature;
+use crate::tui::{AppState, BEATS_PER_BAR};
+use crate::workers::{self, TaskHandle};
+use crossbeam_channel::{Receiver as CrossbeamReceiver, RecvTimeoutError, Sender as CrossbeamSender, TryRecvError};
+use rand::{seq::SliceRandom, Rng};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const VOLUME_STEP: f32 = 0.05; // Volume change per +/- key press (5%)
+const MAX_VOLUME: f32 = 2.0; // Upper volume bound (200%)
+
+// BARS_PER_SECTION: Bars in one section, the unit the first Rewind press jumps back by.
+const BARS_PER_SECTION: u32 = 8;
+// REWIND_DOUBLE_PRESS: A second Rewind within this time goes back to the start of the song.
+const REWIND_DOUBLE_PRESS: Duration = Duration::from_secs(2);
+// REWIND_TO_START_FLAG: Command line flag that makes Rewind always restart the whole song.
+pub const REWIND_TO_START_FLAG: &str = "--rewind-to-start";
+// QUIT_SHUTDOWN_DEADLINE: How long quitting waits for the music service to stop its audio.
+pub const QUIT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(1);
+// STOP_DEADLINE: How long stopping or replacing a song waits for its service to go quiet
+// before leaving it to finish (silently) in the background, so the UI never stalls on it.
+const STOP_DEADLINE: Duration = Duration::from_millis(250);
+
+/* RewindTarget - Where a Rewind press moved playback to.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewindTarget {
+    Section, // The start of the current section
+    Start,   // The start of the song
+}
+
+/* VariedTrack - The track a Vary action gives a new seed.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariedTrack {
+    Melody, // The melody, counter-melody and electronic arpeggio
+    Bass,   // The bass line
+}
+
+/* effective_volume - Returns the volume the audio sink should actually use.
+ *
+ * inputs:
+ *     - volume (f32): The remembered master volume.
+ *     - is_muted (bool): Whether mute is currently active.
+ *
+ * outputs:
+ *     - f32 : 0.0 while muted, otherwise `volume`.
+ */
+fn effective_volume(volume: f32, is_muted: bool) -> f32 {
+    if is_muted {
+        0.0
+    } else {
+        volume
+    }
+}
+
+/* MusicService - Channels and thread handle of one running music service.
+ *
+ * Every service reports progress on its own channel, so a service that is fading out
+ * after a skip can't be confused with the one replacing it.
+ *
+ * fields:
+ *     - music_sender (CrossbeamSender<MusicControl>): Control channel of the service.
+ *     - progress_receiver (CrossbeamReceiver<MusicProgress>): Progress updates from the service.
+ *     - handle (JoinHandle<()>): The service thread.
+ *     - generation (u64): The id the service stamps on its progress (see `PlaybackController`).
+ *     - stopped (CrossbeamReceiver<()>): Acknowledges that the service has returned and its audio
+ *       has stopped; disconnects without a message if the service panicked.
+ *     - stop_requested (Arc<AtomicBool>): Set along with the Terminate, so a service that is
+ *       left to finish in the background never starts playing.
+ */
+struct MusicService {
+    music_sender: CrossbeamSender<MusicControl>,
+    progress_receiver: CrossbeamReceiver<MusicProgress>,
+    handle: JoinHandle<()>,
+    generation: u64,
+    stopped: CrossbeamReceiver<()>,
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl MusicService {
+    /* try_recv - Returns the service's next pending progress update, if any.
+     *
+     * Anything stamped with another generation is stale and skipped.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Option<MusicProgress>: The update, or None if none is pending.
+     */
+    fn try_recv(&self) -> Option<MusicProgress> {
+        self.progress_receiver
+            .try_iter()
+            .find(|progress| progress.generation == self.generation)
+    }
+
+    /* request_stop - Asks the service to stop, without waiting for it.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - None
+     */
+    fn request_stop(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        let _ = self.music_sender.send(MusicControl::Terminate);
+    }
+
+    /* wait_stopped - Waits until the service acknowledges that its audio has stopped.
+     *
+     * A service still rendering the opening of a song can't answer until it is done; past the
+     * deadline it is left to finish in the background, where `stop_requested` keeps it silent.
+     * A service that panicked is joined without passing the panic on. Dropping the service
+     * discards its pending progress along with the channel.
+     *
+     * inputs:
+     *     - self
+     *     - deadline (Instant): When to stop waiting.
+     *
+     * outputs:
+     *     - bool: True if the service stopped before the deadline.
+     */
+    fn wait_stopped(self, deadline: Instant) -> bool {
+        match self.stopped.recv_deadline(deadline) {
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+                let _ = self.handle.join();
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+        }
+    }
+
+    /* terminate - Stops the service and waits for it, at most `deadline`.
+     *
+     * inputs:
+     *     - self
+     *     - deadline (Duration): How long to wait (see `wait_stopped`).
+     *
+     * outputs:
+     *     - bool: True if the service stopped in time.
+     */
+    fn terminate(self, deadline: Duration) -> bool {
+        self.request_stop();
+        self.wait_stopped(Instant::now() + deadline)
+    }
+}
+
+/* FadingService - A service fading out after a skip while the next song fades in.
+ *
+ * fields:
+ *     - service (MusicService): The outgoing service.
+ *     - switch_at (Instant): The fade midpoint; progress is reported from this service until then.
+ *     - ends_at (Instant): When the fade-out is over and the service has stopped itself.
+ */
+struct FadingService {
+    service: MusicService,
+    switch_at: Instant,
+    ends_at: Instant,
+}
+
+/* spawn_music_service - Starts a new music service thread for the given app state.
+ *
+ * Creates fresh control and progress channels and runs `gen::run_music_service` on a new
+ * thread, which acknowledges on `stopped` once the service has returned. Because every service owns a brand new audio sink, the remembered volume is sent
+ * right away so it carries over between songs.
+ *
+ * inputs:
+ *     - app_state (AppState): The application state to generate the song from.
+ *     - initial_song (Option<Arc<PreparedSong>>): The song to play, if it was already rendered.
+ *     - fade_in (Duration): Fade-in for the first song (zero = none).
+ *     - volume (f32): The effective volume to apply to the new sink (0.0 while muted).
+ *     - generation (u64): The id the service stamps on its progress.
+ *     - output (AudioOutput): Where the service plays its audio.
+ *
+ * outputs:
+ *     - MusicService : The channels and thread handle of the new service.
+ */
+fn spawn_music_service(
+    app_state: AppState,
+    initial_song: Option<Arc<PreparedSong>>,
+    fade_in: Duration,
+    volume: f32,
+    generation: u64,
+    output: AudioOutput,
+) -> MusicService {
+    let (music_sender, music_receiver) = crossbeam_channel::unbounded::<MusicControl>();
+    let (progress_sender, progress_receiver) = crossbeam_channel::unbounded::<MusicProgress>();
+    let (stopped_sender, stopped) = crossbeam_channel::bounded::<()>(1);
+    let stop_requested = Arc::new(AtomicBool::new(false));
+    let service_stop_requested = Arc::clone(&stop_requested);
+    let handle = thread::spawn(move || {
+        gen::run_music_service(
+            app_state,
+            initial_song,
+            fade_in,
+            generation,
+            output,
+            service_stop_requested,
+            music_receiver,
+            progress_sender,
+        );
+        let _ = stopped_sender.send(());
+    });
+    let _ = music_sender.send(MusicControl::SetVolume(volume));
+    MusicService {
+        music_sender,
+        progress_receiver,
+        handle,
+        generation,
+        stopped,
+        stop_requested,
+    }
+}
+
+/* fresh_song_state - Prepares an app state for starting a new song.
+ *
+ * inputs:
+ *     - base (&AppState): The state to start from (usually the current UI state).
+ *
+ * outputs:
+ *     - AppState : A copy with playback progress and the song ID cleared and `is_playing` set.
+ */
+pub fn fresh_song_state(base: &AppState) -> AppState {
+    let mut app_state = base.clone();
+    // The new song's ID is only known once its seed is resolved
+    app_state.current_song_id_display = None;
+    // Clear progress fields to ensure the music service starts fresh
+    app_state.current_song_progress = 0.0;
+    app_state.current_song_elapsed_secs = 0.0;
+    app_state.current_song_duration_secs = 0.0;
+    app_state.is_playing = true; // Ensure we start in playing state
+    if app_state.seed.trim().is_empty() {
+        // A varied melody or bass belongs to the seed it was varied on, not a new random one
+        app_state.melody_seed = None;
+        app_state.bass_seed = None;
+    }
+    app_state
+}
+
+/* random_song_state - Picks random song parameters, keeping everything else from `base`.
+ *
+ * inputs:
+ *     - base (&AppState): The state to start from.
+ *
+ * outputs:
+ *     - AppState : A fresh song state with random scale, key quality, style, length, BPM and seed.
+ */
+pub fn random_song_state(base: &AppState) -> AppState {
+    let mut rng = rand::thread_rng();
+    let mut app_state = fresh_song_state(base);
+    app_state.is_random = true;
+    app_state.scale = gen::SCALE_NAMES.choose(&mut rng).unwrap().to_string();
+    app_state.quality = ["Major", "Minor"].choose(&mut rng).unwrap().to_string();
+
+    app_state.style = gen::STYLE_NAMES.choose(&mut rng).unwrap().to_string();
+
+    app_state.length_secs = *[60, 120, 180, 300, 600].choose(&mut rng).unwrap();
+    app_state.bpm = rng.gen_range(60..180).to_string();
+    app_state.seed = rand::random::<u64>().to_string();
+    app_state.melody_seed = None;
+    app_state.bass_seed = None;
+    app_state
+}
+
+/* next_song_state - Picks the parameters of the song that follows `current`.
+ *
+ * Used both when a song ends and when it is skipped: random songs are followed by another
+ * random song, anything else by the same settings with a fresh seed.
+ *
+ * inputs:
+ *     - current (&AppState): The parameters of the current song.
+ *
+ * outputs:
+ *     - AppState : The parameters of the next song.
+ */
+pub fn next_song_state(current: &AppState) -> AppState {
+    if current.is_random {
+        random_song_state(current)
+    } else {
+        let mut app_state = current.clone();
+        app_state.seed = "".to_string(); // Ensure a new random seed is used
+        fresh_song_state(&app_state)
+    }
+}
+
+/* varied_song_state - Picks the parameters of the current song with a new melody or bass.
+ *
+ * The song keeps its seed, so its chords, drums and structure stay, while the varied track is
+ * generated from a fresh seed of its own (kept in the song ID).
+ *
+ * inputs:
+ *     - current (&AppState): The parameters of the current song.
+ *     - seed (u64): The seed the current song was generated with.
+ *     - track (VariedTrack): The track to vary.
+ *
+ * outputs:
+ *     - AppState : The parameters of the varied song.
+ */
+pub fn varied_song_state(current: &AppState, seed: u64, track: VariedTrack) -> AppState {
+    let mut app_state = fresh_song_state(current);
+    app_state.seed = seed.to_string();
+    let varied_seed = rand::random::<u64>();
+    match track {
+        VariedTrack::Melody => app_state.melody_seed = Some(varied_seed),
+        VariedTrack::Bass => app_state.bass_seed = Some(varied_seed),
+    }
+    app_state
+}
+
+/* same_song_parameters - Checks whether two states would generate the same kind of song.
+ *
+ * The seed (and any melody or bass seed) is ignored, since the next song always gets a new one.
+ *
+ * inputs:
+ *     - a (&AppState): The first state.
+ *     - b (&AppState): The second state.
+ *
+ * outputs:
+ *     - bool : True if every other generation parameter matches.
+ */
+fn same_song_parameters(a: &AppState, b: &AppState) -> bool {
+    a.scale == b.scale
+        && a.quality == b.quality
+        && a.style == b.style
+        && a.bpm == b.bpm
+        && a.length_secs == b.length_secs
+        && a.is_random == b.is_random
+        && a.melody_gain == b.melody_gain
+        && a.chord_gain == b.chord_gain
+        && a.bass_gain == b.bass_gain
+        && a.drums == b.drums
+        && a.fade_out_secs == b.fade_out_secs
+        && a.harmony == b.harmony
+        && a.counter_melody == b.counter_melody
+        && a.arp_chords == b.arp_chords
+        && a.comping == b.comping
+        && a.pulse_width == b.pulse_width
+        && a.brightness == b.brightness
+        && a.swing == b.swing
+        && a.time_signature == b.time_signature
+        && a.modulation == b.modulation
+        && a.progression == b.progression
+        && a.waveforms == b.waveforms
+        && a.echo == b.echo
+        && a.lofi == b.lofi
+        && a.nes_mode == b.nes_mode
+}
+
+/* song_id - Builds the shareable song ID for a song.
+ *
+ * inputs:
+ *     - app_state (&AppState): The parameters the song was generated from.
+ *     - seed (u64): The seed the song was actually generated with.
+ *
+ * outputs:
+ *     - String : The ID in "vN:Scale-Style-BPM-Length-Seed" form, stamped with the current
+ *       `SONG_ID_VERSION` since that is how the song was generated, with `MINOR_KEY_SUFFIX` after
+ *       the scale for minor keys, and swing, time signature, key change, custom progression,
+ *       waveform, echo, lo-fi, NES mode, drums, harmony, counter-melody, arp chords, comping,
+ *       pulse width, brightness, fade-out, mix and melody and bass seed parts before the seed
+ *       when they are set (see `gen::parse_song_id_to_app_state`), so the ID reproduces the
+ *       song on any machine.
+ */
+pub fn song_id(app_state: &AppState, seed: u64) -> String {
+    let length_part = gen::length_id_part(app_state.length_secs);
+    let quality_suffix = if app_state.is_minor() { MINOR_KEY_SUFFIX } else { "" };
+    let swing_part = match app_state.swing.trim().parse::<u32>() {
+        Ok(percent) => format!("{}{}-", SWING_ID_PREFIX, percent.min(MAX_SWING_PERCENT)),
+        Err(_) => String::new(), // The style's default swing
+    };
+    let time_signature_part = match TimeSignature::from_label(&app_state.time_signature) {
+        Some(time_signature) => format!("{}-", gen::time_signature_id_part(time_signature)),
+        None => String::new(), // The style's time signature
+    };
+    let modulation_part = match app_state.modulation.as_str() {
+        "On" => format!("{}1-", gen::MODULATION_ID_PREFIX),
+        "Off" => format!("{}0-", gen::MODULATION_ID_PREFIX),
+        _ => String::new(), // Auto
+    };
+    let progression_chords: Vec<&str> = app_state.progression.split_whitespace().collect();
+    let progression_part = if progression_chords.is_empty() {
+        String::new() // The style's progressions
+    } else {
+        format!(
+            "{}{}-",
+            gen::PROGRESSION_ID_PREFIX,
+            progression_chords.join(&gen::PROGRESSION_ID_SEPARATOR.to_string())
+        )
+    };
+    let waveforms_part = match gen::waveforms_id_part(&app_state.waveforms) {
+        Some(part) => format!("{}-", part),
+        None => String::new(), // The style's waveforms
+    };
+    let echo_part = match app_state.echo.as_str() {
+        "Off" => format!("{}0-", gen::ECHO_ID_PREFIX),
+        _ => String::new(), // The style's echo
+    };
+    let lofi_part = match gen::lofi_id_part(&app_state.lofi) {
+        Some(part) => format!("{}-", part),
+        None => String::new(), // The style's lo-fi setting
+    };
+    let nes_part = if app_state.nes_mode {
+        format!("{}1-", gen::NES_ID_PREFIX)
+    } else {
+        String::new()
+    };
+    let drums_part = if app_state.drums {
+        String::new()
+    } else {
+        format!("{}0-", gen::DRUMS_ID_PREFIX)
+    };
+    let settings_part: String = gen::SETTING_ID_PARTS
+        .iter()
+        .filter_map(|part| part.id_part(app_state))
+        .map(|part| format!("{}-", part))
+        .collect();
+    let fade_out_part = match app_state.fade_out_secs {
+        Some(seconds) => format!("{}{}-", gen::FADE_OUT_ID_PREFIX, seconds.min(gen::MAX_FADE_OUT_SECS)),
+        None => String::new(), // The style's fade-out
+    };
+    let gains_part = match gen::gains_id_part(app_state) {
+        Some(part) => format!("{}-", part),
+        None => String::new(), // The default mix
+    };
+    let melody_seed_part = match app_state.melody_seed {
+        Some(melody_seed) => format!("{}{}-", gen::MELODY_SEED_ID_PREFIX, melody_seed),
+        None => String::new(), // The song's seed
+    };
+    let bass_seed_part = match app_state.bass_seed {
+        Some(bass_seed) => format!("{}{}-", gen::BASS_SEED_ID_PREFIX, bass_seed),
+        None => String::new(), // The song's seed
+    };
+    format!(
+        "{}{}{}{}{}-{}-{}-{}-{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}",
+        gen::SONG_ID_VERSION_PREFIX,
+        gen::SONG_ID_VERSION,
+        gen::SONG_ID_VERSION_SEPARATOR,
+        app_state.scale,
+        quality_suffix,
+        app_state.style,
+        app_state.bpm,
+        length_part,
+        swing_part,
+        time_signature_part,
+        modulation_part,
+        progression_part,
+        waveforms_part,
+        echo_part,
+        lofi_part,
+        nes_part,
+        drums_part,
+        settings_part,
+        fade_out_part,
+        gains_part,
+        melody_seed_part,
+        bass_seed_part,
+        seed
+    )
+}
+
+/* with_seed - Replaces the seed part of a song ID.
+ *
+ * A loaded ID may leave the seed empty (a random one is picked), so the shown ID only becomes
+ * shareable once the seed the song was actually generated with is filled in.
+ *
+ * inputs:
+ *     - song_id (&str): The ID in "Scale-Style-BPM-Length-Seed" form.
+ *     - seed (u64): The seed the song was actually generated with.
+ *
+ * outputs:
+ *     - String : The ID with that seed.
+ */
+pub fn with_seed(song_id: &str, seed: u64) -> String {
+    let prefix = song_id.rsplit_once('-').map_or(song_id, |(prefix, _)| prefix);
+    format!("{}-{}", prefix, seed)
+}
+
+/* Prefetch - The next song, rendered on the worker pool while the current one plays.
+ *
+ * fields:
+ *     - base (AppState): The song the prefetched one follows; it is only reused while the
+ *       generation parameters still match.
+ *     - receiver (Option<CrossbeamReceiver<PreparedSong>>): Delivers the render; None once it has arrived.
+ *     - task (Option<TaskHandle>): Keeps the render queued; dropping it cancels a render that hasn't started.
+ *     - song (Option<Arc<PreparedSong>>): The finished song.
+ */
+struct Prefetch {
+    base: AppState,
+    receiver: Option<CrossbeamReceiver<PreparedSong>>,
+    task: Option<TaskHandle>,
+    song: Option<Arc<PreparedSong>>,
+}
+
+/* PlaybackController - Owns the music service and the settings that outlive a single song.
+ *
+ * Both front ends (the full-screen TUI and the plain line interface) drive playback through
+ * this controller, so starting, stopping and adjusting songs behaves the same in each.
+ *
+ * fields:
+ *     - service (Option<MusicService>): The running music service, if any.
+ *     - fading (Option<FadingService>): The previous service, while it crossfades into `service`.
+ *     - volume (f32): The remembered master volume (1.0 = 100%).
+ *     - is_muted (bool): Whether output is muted.
+ *     - prefetch (Option<Prefetch>): The next song, if one is being (or has been) rendered.
+ *       Only one is kept, so at most one extra song is held in memory.
+ *     - current_beat (u32): The beat last reported by the running service.
+ *     - beats_per_bar (u32): Beats in a bar of the running song, as last reported.
+ *     - current_seed (Option<u64>): The seed of the running song, once its service has reported it.
+ *     - rewind_to_section (bool): True if Rewind first jumps to the start of the current section.
+ *     - last_section_rewind (Option<Instant>): When Rewind last jumped to a section start.
+ *     - stop_after_current (bool): True if playback stops when the current song ends. Cleared
+ *       whenever the song is changed or stopped.
+ *     - generation (u64): Counts the services started, so each one's progress carries an id
+ *       no other service uses.
+ *     - output (AudioOutput): Where every service plays its audio.
+ */
+pub struct PlaybackController {
+    service: Option<MusicService>,
+    fading: Option<FadingService>,
+    volume: f32,
+    is_muted: bool,
+    prefetch: Option<Prefetch>,
+    current_beat: u32,
+    beats_per_bar: u32,
+    current_seed: Option<u64>,
+    rewind_to_section: bool,
+    last_section_rewind: Option<Instant>,
+    stop_after_current: bool,
+    generation: u64,
+    output: AudioOutput,
+}
+
+impl PlaybackController {
+    /* new - Creates a controller with no song playing.
+     *
+     * inputs:
+     *     - volume (f32): The initial master volume.
+     *
+     * outputs:
+     *     - Self : The new controller.
+     */
+    pub fn new(volume: f32) -> Self {
+        Self {
+            service: None,
+            fading: None,
+            volume,
+            is_muted: false,
+            prefetch: None,
+            current_beat: 0,
+            beats_per_bar: BEATS_PER_BAR,
+            current_seed: None,
+            rewind_to_section: true,
+            last_section_rewind: None,
+            stop_after_current: false,
+            generation: 0,
+            output: AudioOutput::Device,
+        }
+    }
+
+    /* with_output - Creates a controller whose services play on the given output.
+     *
+     * inputs:
+     *     - volume (f32): The initial master volume.
+     *     - output (AudioOutput): Where the services play their audio.
+     *
+     * outputs:
+     *     - Self : The new controller.
+     */
+    #[cfg(test)]
+    pub fn with_output(volume: f32, output: AudioOutput) -> Self {
+        Self {
+            output,
+            ..Self::new(volume)
+        }
+    }
+
+    /* stop - Terminates the running music service, if any, and discards its pending progress.
+     *
+     * A service still fading out is cut off as well, and any prefetched next song is
+     * discarded, since it followed the stopped song.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn stop(&mut self) {
+        if let Some(fading) = self.fading.take() {
+            fading.service.terminate(STOP_DEADLINE);
+        }
+        if let Some(service) = self.service.take() {
+            service.terminate(STOP_DEADLINE);
+        }
+        self.prefetch = None;
+        self.stop_after_current = false;
+    }
+
+    /* shutdown - Terminates every music service and waits for their audio to stop, within a deadline.
+     *
+     * A service still busy rendering its first section can't answer until it is done, so it is
+     * left to exit with the process (see `MusicService::wait_stopped`).
+     *
+     * inputs:
+     *     - &mut self
+     *     - deadline (Duration): How long to wait for the services.
+     *
+     * outputs:
+     *     - bool: True if every service stopped within the deadline.
+     */
+    pub fn shutdown(&mut self, deadline: Duration) -> bool {
+        let services: Vec<MusicService> = self
+            .fading
+            .take()
+            .map(|fading| fading.service)
+            .into_iter()
+            .chain(self.service.take())
+            .collect();
+        self.prefetch = None;
+        for service in &services {
+            service.request_stop();
+        }
+        let deadline = Instant::now() + deadline;
+        let mut all_stopped = true;
+        for service in services {
+            all_stopped &= service.wait_stopped(deadline);
+        }
+        all_stopped
+    }
+
+    /* spawn - Starts a music service, replacing any running one.
+     *
+     * inputs:
+     *     - &mut self
+     *     - app_state (AppState): The parameters of the song.
+     *     - initial_song (Option<Arc<PreparedSong>>): The song to play, if it was already rendered.
+     *     - fade_in (Duration): Fade-in for the song (zero = none).
+     *
+     * outputs:
+     *     - None
+     */
+    fn spawn(&mut self, app_state: AppState, initial_song: Option<Arc<PreparedSong>>, fade_in: Duration) {
+        if let Some(service) = self.service.take() {
+            service.terminate(STOP_DEADLINE);
+        }
+        self.prefetch = None;
+        self.current_beat = 0;
+        self.current_seed = None;
+        self.stop_after_current = false;
+        self.generation += 1;
+        self.service = Some(spawn_music_service(
+            app_state,
+            initial_song,
+            fade_in,
+            effective_volume(self.volume, self.is_muted),
+            self.generation,
+            self.output.clone(),
+        ));
+    }
+
+    /* fade_out_current - Lets the running service fade out while its replacement starts.
+     *
+     * Its progress keeps being reported until the fade midpoint. A service that was
+     * already fading out is cut off.
+     *
+     * inputs:
+     *     - &mut self
+     *     - duration (Duration): Length of the fade.
+     *
+     * outputs:
+     *     - None
+     */
+    fn fade_out_current(&mut self, duration: Duration) {
+        if let Some(fading) = self.fading.take() {
+            fading.service.terminate(STOP_DEADLINE);
+        }
+        let Some(service) = self.service.take() else {
+            return;
+        };
+        // The service stops itself once the fade is over
+        let _ = service.music_sender.send(MusicControl::FadeOut(duration));
+        let now = Instant::now();
+        self.fading = Some(FadingService {
+            service,
+            switch_at: now + duration / 2,
+            ends_at: now + duration,
+        });
+    }
+
+    /* start_song - Replaces the running song with a new one generated from `app_state`.
+     *
+     * inputs:
+     *     - &mut self
+     *     - app_state (AppState): The parameters of the new song.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn start_song(&mut self, app_state: AppState) {
+        self.stop();
+        self.spawn(app_state, None, Duration::ZERO);
+    }
+
+    /* skip - Moves on to the song after the current one.
+     *
+     * Plays the prefetched song straight away when it is ready and `current` still has the
+     * parameters it was prepared for; otherwise the next song is generated as usual. With a
+     * crossfade set, the current song fades out while the next one fades in.
+     *
+     * inputs:
+     *     - &mut self
+     *     - current (&AppState): The current parameters (as shown in the UI).
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn skip(&mut self, current: &AppState) {
+        let prepared = self
+            .prefetch
+            .take()
+            .filter(|prefetch| same_song_parameters(&prefetch.base, current))
+            .and_then(|prefetch| prefetch.song);
+        let crossfade = gen::crossfade_duration(current.crossfade_secs);
+        if crossfade.is_zero() {
+            self.stop();
+        } else {
+            self.fade_out_current(crossfade);
+        }
+        match prepared {
+            Some(song) => self.spawn(fresh_song_state(current), Some(song), crossfade),
+            None => self.spawn(next_song_state(current), None, crossfade),
+        }
+    }
+
+    /* start_prefetch - Starts rendering the song that will follow `base`.
+     *
+     * Replaces (and cancels) any previous prefetch. Nothing is rendered after an endless song:
+     * it never ends by itself, and the song after a skip is streamed as it plays.
+     *
+     * inputs:
+     *     - &mut self
+     *     - base (AppState): The song that just started.
+     *
+     * outputs:
+     *     - None
+     */
+    fn start_prefetch(&mut self, base: AppState) {
+        self.prefetch = None;
+        if base.is_endless() {
+            return;
+        }
+        let next_state = next_song_state(&base);
+        let (song_sender, song_receiver) = crossbeam_channel::bounded(1);
+        let task = workers::global().submit(move || {
+            // The receiver is gone if the prefetch was replaced in the meantime
+            let _ = song_sender.send(gen::prepare_song(next_state));
+        });
+        self.prefetch = Some(Prefetch {
+            base,
+            receiver: Some(song_receiver),
+            task,
+            song: None,
+        });
+    }
+
+    /* poll_prefetch - Picks up a finished prefetch and offers it to the music service.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    fn poll_prefetch(&mut self) {
+        let Some(prefetch) = self.prefetch.as_mut() else {
+            return;
+        };
+        let result = match &prefetch.receiver {
+            Some(receiver) => receiver.try_recv(),
+            None => return,
+        };
+        match result {
+            Ok(song) => {
+                let song = Arc::new(song);
+                prefetch.receiver = None;
+                prefetch.task = None;
+                prefetch.song = Some(Arc::clone(&song));
+                // The service auto-advances to it when the current song ends
+                self.send(MusicControl::SetNextSong(song));
+            }
+            Err(TryRecvError::Disconnected) => self.prefetch = None,
+            Err(TryRecvError::Empty) => {}
+        }
+    }
+
+    /* send - Sends a control message to the running music service.
+     *
+     * inputs:
+     *     - &self
+     *     - control (MusicControl): The message to send.
+     *
+     * outputs:
+     *     - bool : True if a service was running to receive it.
+     */
+    pub fn send(&self, control: MusicControl) -> bool {
+        match &self.service {
+            Some(service) => {
+                let _ = service.music_sender.send(control);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /* try_recv_progress - Returns the next pending progress update, if any.
+     *
+     * Also drives prefetching: a new song starting begins rendering the one after it. During
+     * a crossfade the outgoing song is reported until the fade midpoint.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<MusicProgress> : The next update, or None if there is none yet.
+     */
+    pub fn try_recv_progress(&mut self) -> Option<MusicProgress> {
+        self.poll_prefetch();
+        if let Some(fading) = &self.fading {
+            let now = Instant::now();
+            if now < fading.switch_at {
+                return fading.service.try_recv();
+            }
+            if now >= fading.ends_at {
+                if let Some(fading) = self.fading.take() {
+                    fading.service.terminate(STOP_DEADLINE);
+                }
+            }
+        }
+        let progress = self.service.as_ref()?.try_recv()?;
+        if progress.error.is_some() {
+            // The service could not play and has already returned
+            if let Some(service) = self.service.take() {
+                service.terminate(STOP_DEADLINE);
+            }
+            self.prefetch = None;
+            self.stop_after_current = false;
+        }
+        self.current_beat = progress.current_beat;
+        self.beats_per_bar = progress.beats_per_bar;
+        if progress.total_samples > 0 {
+            self.current_seed = Some(progress.actual_seed);
+        }
+        if let Some(app_state) = &progress.app_state {
+            self.start_prefetch(app_state.clone());
+        }
+        Some(progress)
+    }
+
+    /* current_seed - Returns the seed the running song was generated with.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Option<u64> : The seed, or None until the running service has reported it.
+     */
+    pub fn current_seed(&self) -> Option<u64> {
+        self.current_seed
+    }
+
+    /* toggle_stop_after_current - Turns stopping at the end of the current song on or off.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<bool> : The new setting, or None if no song is playing.
+     */
+    pub fn toggle_stop_after_current(&mut self) -> Option<bool> {
+        self.service.as_ref()?;
+        self.stop_after_current = !self.stop_after_current;
+        self.send(MusicControl::SetStopAfterCurrent(self.stop_after_current));
+        Some(self.stop_after_current)
+    }
+
+    /* stop_after_current - Returns whether playback stops when the current song ends.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool : True if the current song is the last one.
+     */
+    pub fn stop_after_current(&self) -> bool {
+        self.stop_after_current
+    }
+
+    /* set_rewind_to_section - Chooses between section-aware and whole-song Rewind.
+     *
+     * inputs:
+     *     - &mut self
+     *     - enabled (bool): True to jump to the section start first, false to always restart the song.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_rewind_to_section(&mut self, enabled: bool) {
+        self.rewind_to_section = enabled;
+    }
+
+    /* rewind - Jumps back to the start of the current section, or of the song.
+     *
+     * Like a music player's previous-track button, the first press jumps to the start of the
+     * current section and a second press within `REWIND_DOUBLE_PRESS` restarts the song. In the
+     * first section, or with section rewind turned off, the song restarts straight away.
+     *
+     * inputs:
+     *     - &mut self
+     *     - now (Instant): The time of the press.
+     *
+     * outputs:
+     *     - Option<RewindTarget> : Where playback moved to, or None if no song is playing.
+     */
+    pub fn rewind(&mut self, now: Instant) -> Option<RewindTarget> {
+        let beats_per_section = BARS_PER_SECTION * self.beats_per_bar.max(1);
+        let section_start = self.current_beat / beats_per_section * beats_per_section;
+        let double_press = self
+            .last_section_rewind
+            .take()
+            .is_some_and(|last| now.duration_since(last) < REWIND_DOUBLE_PRESS);
+        if self.rewind_to_section && !double_press && section_start > 0 {
+            if !self.send(MusicControl::SeekToBeat(section_start)) {
+                return None;
+            }
+            self.current_beat = section_start;
+            self.last_section_rewind = Some(now);
+            return Some(RewindTarget::Section);
+        }
+        if !self.send(MusicControl::Rewind) {
+            return None;
+        }
+        self.current_beat = 0;
+        Some(RewindTarget::Start)
+    }
+
+    /* seek - Continues the current song from a fraction of its length.
+     *
+     * Paused songs stay paused at the new position.
+     *
+     * inputs:
+     *     - &self
+     *     - fraction (f32): Where to continue from, 0.0 (start) to 1.0 (end).
+     *
+     * outputs:
+     *     - bool : True if a song was playing to seek in.
+     */
+    pub fn seek(&self, fraction: f32) -> bool {
+        self.current_seed.is_some() && self.send(MusicControl::Seek(fraction))
+    }
+
+    /* step_volume - Raises or lowers the master volume by one step.
+     *
+     * While muted, only the remembered level changes; unmuting applies it.
+     *
+     * inputs:
+     *     - &mut self
+     *     - up (bool): True to raise the volume, false to lower it.
+     *
+     * outputs:
+     *     - f32 : The new master volume.
+     */
+    pub fn step_volume(&mut self, up: bool) -> f32 {
+        let step = if up { VOLUME_STEP } else { -VOLUME_STEP };
+        // Round to whole steps so repeated presses don't accumulate float drift
+        self.volume = ((self.volume + step) / VOLUME_STEP).round() * VOLUME_STEP;
+        self.volume = self.volume.clamp(0.0, MAX_VOLUME);
+        if !self.is_muted {
+            self.send(MusicControl::SetVolume(self.volume));
+        }
+        self.volume
+    }
+
+    /* toggle_mute - Mutes or unmutes output without pausing the song.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - bool : True if output is now muted.
+     */
+    pub fn toggle_mute(&mut self) -> bool {
+        self.is_muted = !self.is_muted;
+        // The sink keeps playing (or stays paused); only its volume changes
+        self.send(MusicControl::SetVolume(effective_volume(
+            self.volume,
+            self.is_muted,
+        )));
+        self.is_muted
+    }
+
+    /* volume - Returns the remembered master volume.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - f32 : The master volume (1.0 = 100%).
+     */
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /* is_muted - Returns whether output is muted.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool : True if muted.
+     */
+    pub fn is_muted(&self) -> bool {
+        self.is_muted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::queue::SourcesQueueOutput;
+
+    // WAIT_LIMIT: How long a test waits on a music service before failing.
+    const WAIT_LIMIT: Duration = Duration::from_secs(60);
+    // STOPPED_TAIL_SAMPLES: Samples a sink may still yield once stopped (rodio checks every 5ms).
+    const STOPPED_TAIL_SAMPLES: usize = gen::SAMPLE_RATE as usize / 100;
+
+    fn test_song() -> AppState {
+        // Endless, so no next song is prefetched in the background
+        AppState {
+            length_secs: gen::ENDLESS_SONG_SECS,
+            seed: "42".to_string(),
+            drums: false,
+            ..AppState::default()
+        }
+    }
+
+    fn fake_output() -> (AudioOutput, CrossbeamReceiver<SourcesQueueOutput<f32>>) {
+        let (sink_sender, sinks) = crossbeam_channel::unbounded();
+        (AudioOutput::Fake(Some(sink_sender)), sinks)
+    }
+
+    fn wait_for_progress(
+        controller: &mut PlaybackController,
+        accept: impl Fn(&MusicProgress) -> bool,
+    ) -> MusicProgress {
+        let started = Instant::now();
+        loop {
+            match controller.try_recv_progress() {
+                Some(progress) if accept(&progress) => return progress,
+                Some(_) => {}
+                None => {
+                    assert!(started.elapsed() < WAIT_LIMIT, "the music service never reported");
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+        }
+    }
+
+    // Pulls a stopped sink dry, returning how many samples it still yielded
+    fn drain(sink: &mut SourcesQueueOutput<f32>) -> usize {
+        let limit = gen::SAMPLE_RATE as usize;
+        let tail = sink.by_ref().take(limit + 1).count();
+        assert!(tail <= limit, "the sink kept playing after its service stopped");
+        tail
+    }
+
+    #[test]
+    fn a_missing_audio_device_is_reported_instead_of_panicking() {
+        let mut controller = PlaybackController::with_output(1.0, AudioOutput::Fake(None));
+        controller.start_song(test_song());
+        let progress = wait_for_progress(&mut controller, |progress| progress.error.is_some());
+        assert_eq!(progress.total_samples, 0);
+        assert!(controller.current_seed().is_none());
+
+        // Stopping, restarting and quitting after the failure must not panic either
+        controller.start_song(test_song());
+        wait_for_progress(&mut controller, |progress| progress.error.is_some());
+        controller.stop();
+        assert!(controller.shutdown(QUIT_SHUTDOWN_DEADLINE));
+    }
+
+    #[test]
+    fn a_replaced_song_is_silent_before_the_next_one_starts() {
+        let (output, sinks) = fake_output();
+        let mut controller = PlaybackController::with_output(1.0, output);
+        controller.start_song(test_song());
+        wait_for_progress(&mut controller, |progress| progress.app_state.is_some());
+        let mut first = sinks.recv_timeout(WAIT_LIMIT).expect("the first service opened no sink");
+        let opening: Vec<f32> = first.by_ref().take(gen::SAMPLE_RATE as usize).collect();
+        assert!(opening.iter().any(|sample| sample.abs() > 0.01), "the first song never played");
+
+        controller.start_song(test_song());
+        // The old sink ran dry before `start_song` returned, so it can't overlap the new one
+        assert!(drain(&mut first) <= STOPPED_TAIL_SAMPLES);
+        wait_for_progress(&mut controller, |progress| progress.app_state.is_some());
+        let mut second = sinks.recv_timeout(WAIT_LIMIT).expect("the second service opened no sink");
+        assert!(second.by_ref().take(gen::SAMPLE_RATE as usize).any(|sample| sample.abs() > 0.01));
+
+        assert!(controller.shutdown(QUIT_SHUTDOWN_DEADLINE));
+        assert!(drain(&mut second) <= STOPPED_TAIL_SAMPLES);
+    }
+
+    #[test]
+    fn quitting_stops_a_playing_service_within_the_deadline() {
+        let (output, sinks) = fake_output();
+        let mut controller = PlaybackController::with_output(1.0, output);
+        controller.start_song(test_song());
+        wait_for_progress(&mut controller, |progress| progress.app_state.is_some());
+        let mut sink = sinks.recv_timeout(WAIT_LIMIT).expect("the service opened no sink");
+
+        let started = Instant::now();
+        assert!(controller.shutdown(QUIT_SHUTDOWN_DEADLINE));
+        assert!(started.elapsed() <= QUIT_SHUTDOWN_DEADLINE);
+        assert!(drain(&mut sink) <= STOPPED_TAIL_SAMPLES);
+        assert!(controller.try_recv_progress().is_none());
+    }
+
+    #[test]
+    fn quitting_never_waits_past_the_deadline_for_a_song_being_generated() {
+        let (output, _sinks) = fake_output();
+        let mut controller = PlaybackController::with_output(1.0, output);
+        controller.start_song(test_song());
+        // Straight away, while the service may still be rendering the opening
+        let deadline = Duration::from_millis(50);
+        let started = Instant::now();
+        controller.shutdown(deadline);
+        assert!(started.elapsed() < deadline + Duration::from_millis(50));
+    }
+
+    fn report(generation: u64, actual_seed: u64) -> MusicProgress {
+        MusicProgress {
+            current_samples: 0,
+            total_samples: gen::SAMPLE_RATE as u64,
+            endless: false,
+            actual_seed,
+            app_state: None,
+            level: 0.0,
+            scope: Vec::new(),
+            spectrum: Vec::new(),
+            current_beat: 0,
+            beat_phase: 0.0,
+            beats_per_bar: BEATS_PER_BAR,
+            bpm: 120,
+            chord_timeline: None,
+            section_timeline: None,
+            loudness_db: None,
+            generating: false,
+            generation,
+            error: None,
+        }
+    }
+
+    // A service whose progress channel the test writes to itself, in place of a music thread
+    fn scripted_service(
+        generation: u64,
+    ) -> (MusicService, CrossbeamSender<MusicProgress>, CrossbeamReceiver<MusicControl>) {
+        let (music_sender, controls) = crossbeam_channel::unbounded();
+        let (progress_sender, progress_receiver) = crossbeam_channel::unbounded();
+        let (stopped_sender, stopped) = crossbeam_channel::bounded(1);
+        let _ = stopped_sender.send(());
+        let service = MusicService {
+            music_sender,
+            progress_receiver,
+            handle: thread::spawn(|| {}),
+            generation,
+            stopped,
+            stop_requested: Arc::new(AtomicBool::new(false)),
+        };
+        (service, progress_sender, controls)
+    }
+
+    // A controller playing a scripted service, with the playback position at `current_beat`
+    fn controller_at_beat(current_beat: u32) -> (PlaybackController, CrossbeamReceiver<MusicControl>) {
+        let mut controller = PlaybackController::new(1.0);
+        controller.generation = 1;
+        let (service, progress, controls) = scripted_service(1);
+        controller.service = Some(service);
+        progress.send(MusicProgress { current_beat, ..report(1, 42) }).unwrap();
+        controller.try_recv_progress().expect("the report was dropped");
+        (controller, controls)
+    }
+
+    #[test]
+    fn late_progress_from_an_older_generation_is_dropped() {
+        let mut controller = PlaybackController::new(1.0);
+        controller.generation = 2;
+        let (service, progress, _controls) = scripted_service(2);
+        controller.service = Some(service);
+
+        // Reports of the replaced service arriving after the new one started
+        progress.send(report(1, 7)).unwrap();
+        progress.send(report(1, 8)).unwrap();
+        assert!(controller.try_recv_progress().is_none());
+        assert!(controller.current_seed().is_none());
+
+        progress.send(report(1, 9)).unwrap();
+        progress.send(report(2, 42)).unwrap();
+        progress.send(report(1, 10)).unwrap();
+        let current = controller.try_recv_progress().expect("the current report was dropped");
+        assert_eq!((current.generation, current.actual_seed), (2, 42));
+        assert!(controller.try_recv_progress().is_none());
+        assert_eq!(controller.current_seed(), Some(42));
+
+        controller.stop();
+    }
+
+    #[test]
+    fn rewind_goes_to_the_section_start_then_the_song_start_on_a_second_press() {
+        // Beat 70 in 4/4 is in the third 8-bar section, which starts on beat 64
+        let (mut controller, controls) = controller_at_beat(70);
+        let pressed = Instant::now();
+        assert_eq!(controller.rewind(pressed), Some(RewindTarget::Section));
+        assert!(matches!(controls.try_recv(), Ok(MusicControl::SeekToBeat(64))));
+
+        assert_eq!(controller.rewind(pressed + REWIND_DOUBLE_PRESS / 2), Some(RewindTarget::Start));
+        assert!(matches!(controls.try_recv(), Ok(MusicControl::Rewind)));
+        controller.stop();
+    }
+
+    #[test]
+    fn a_slow_second_rewind_goes_to_the_section_start_again() {
+        let (mut controller, controls) = controller_at_beat(70);
+        let pressed = Instant::now();
+        assert_eq!(controller.rewind(pressed), Some(RewindTarget::Section));
+        // Playback has moved on into the section by the time of the second press
+        controller.current_beat = 66;
+        assert_eq!(controller.rewind(pressed + REWIND_DOUBLE_PRESS), Some(RewindTarget::Section));
+        assert!(matches!(controls.try_recv(), Ok(MusicControl::SeekToBeat(64))));
+        assert!(matches!(controls.try_recv(), Ok(MusicControl::SeekToBeat(64))));
+        controller.stop();
+    }
+
+    #[test]
+    fn rewind_restarts_the_song_without_an_earlier_section_to_go_to() {
+        let pressed = Instant::now();
+        // No position reported yet, and a position in the first section
+        for current_beat in [0, 20] {
+            let (mut controller, controls) = controller_at_beat(current_beat);
+            assert_eq!(controller.rewind(pressed), Some(RewindTarget::Start));
+            assert!(matches!(controls.try_recv(), Ok(MusicControl::Rewind)));
+            controller.stop();
+        }
+
+        let (mut controller, controls) = controller_at_beat(70);
+        controller.set_rewind_to_section(false);
+        assert_eq!(controller.rewind(pressed), Some(RewindTarget::Start));
+        assert!(matches!(controls.try_recv(), Ok(MusicControl::Rewind)));
+        controller.stop();
+
+        assert_eq!(PlaybackController::new(1.0).rewind(pressed), None);
+    }
+
+    // A song of the shortest length, which plays through and moves on
+    fn short_song() -> AppState {
+        AppState {
+            length_secs: gen::MIN_SONG_SECS,
+            ..test_song()
+        }
+    }
+
+    // Plays the sink faster than real time until a report is accepted, returning every report seen
+    fn play_until(
+        controller: &mut PlaybackController,
+        sink: &mut SourcesQueueOutput<f32>,
+        accept: impl Fn(&MusicProgress) -> bool,
+    ) -> Vec<MusicProgress> {
+        let started = Instant::now();
+        let mut reports = Vec::new();
+        loop {
+            sink.by_ref().take(gen::SAMPLE_RATE as usize / 10).for_each(drop);
+            while let Some(progress) = controller.try_recv_progress() {
+                let accepted = accept(&progress);
+                reports.push(progress);
+                if accepted {
+                    return reports;
+                }
+            }
+            assert!(started.elapsed() < WAIT_LIMIT, "the song never got there");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn stop_after_current_suppresses_exactly_one_advance() {
+        let (output, sinks) = fake_output();
+        let mut controller = PlaybackController::with_output(1.0, output);
+        assert_eq!(controller.toggle_stop_after_current(), None);
+        controller.start_song(short_song());
+        wait_for_progress(&mut controller, |progress| progress.app_state.is_some());
+        let mut sink = sinks.recv_timeout(WAIT_LIMIT).expect("the service opened no sink");
+        assert_eq!(controller.toggle_stop_after_current(), Some(true));
+
+        // The song ends without the service moving on to another one
+        let reports = play_until(&mut controller, &mut sink, |progress| progress.total_samples == 0);
+        assert!(reports.iter().all(|progress| progress.app_state.is_none() && !progress.generating));
+        assert!(controller.stop_after_current());
+        controller.stop();
+        assert!(!controller.stop_after_current());
+
+        // The flag fired once: the next song moves on when it ends
+        controller.start_song(short_song());
+        wait_for_progress(&mut controller, |progress| progress.app_state.is_some());
+        let mut sink = sinks.recv_timeout(WAIT_LIMIT).expect("the service opened no sink");
+        assert!(!controller.stop_after_current());
+        play_until(&mut controller, &mut sink, |progress| progress.app_state.is_some());
+        assert!(controller.shutdown(QUIT_SHUTDOWN_DEADLINE));
+    }
+
+    #[test]
+    fn changing_the_song_clears_stop_after_current() {
+        let (output, _sinks) = fake_output();
+        let mut controller = PlaybackController::with_output(1.0, output);
+        controller.start_song(test_song());
+        assert_eq!(controller.toggle_stop_after_current(), Some(true));
+        controller.start_song(test_song());
+        assert!(!controller.stop_after_current());
+        assert_eq!(controller.toggle_stop_after_current(), Some(true));
+        assert_eq!(controller.toggle_stop_after_current(), Some(false));
+        controller.shutdown(QUIT_SHUTDOWN_DEADLINE);
+    }
+
+    #[test]
+    fn song_ids_are_stamped_with_the_current_version() {
+        let app_state = AppState {
+            scale: "D".to_string(),
+            style: "Rock".to_string(),
+            bpm: "100".to_string(),
+            ..AppState::default()
+        };
+        let id = song_id(&app_state, 9);
+        assert_eq!(id, format!("v{}:D-Rock-100-5-9", gen::SONG_ID_VERSION));
+        assert_eq!(gen::parse_song_id_to_app_state(&id).unwrap().id_version, gen::SONG_ID_VERSION);
+        // A song loaded from an older ID plays as this version generates it, and is shared so
+        let old = gen::parse_song_id_to_app_state("D-Rock-100-5-9").unwrap();
+        assert_eq!(old.id_version, 1);
+        assert_eq!(song_id(&old, 9), id);
+    }
+}
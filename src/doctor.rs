@@ -0,0 +1,579 @@
+use crate::config;
+use crate::gen::{self, SAMPLE_RATE};
+use crate::session;
+use crate::terminal::{self, Support};
+use crate::tui::{AppState, MIN_HEIGHT, MIN_WIDTH};
+use rodio::cpal::traits::HostTrait;
+use rodio::{DeviceTrait, OutputStream, Sink};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// DOCTOR_COMMAND: The subcommand that prints the environment report instead of starting the app.
+pub const DOCTOR_COMMAND: &str = "doctor";
+
+// RENDER_PROBE_SECONDS: Audio rendered (silently) to time generation on this machine.
+const RENDER_PROBE_SECONDS: f32 = 1.0;
+
+// RENDER_PROBE_SEED: Fixed seed for the timing render, so runs are comparable across machines.
+const RENDER_PROBE_SEED: u64 = 8;
+
+/* Readiness - How well the app can run in the probed environment.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Readiness {
+    Full,     // Everything works
+    Degraded, // The app runs, but with missing features or a reduced display
+    Unusable, // The app can't play music here
+}
+
+impl Readiness {
+    /* exit_code - Returns the process exit code for this readiness.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - i32: 0 when fully ready, 1 when degraded, 2 when the app can't run.
+     */
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Readiness::Full => 0,
+            Readiness::Degraded => 1,
+            Readiness::Unusable => 2,
+        }
+    }
+
+    /* label - Returns a short description for the report.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - &'static str: The description.
+     */
+    fn label(self) -> &'static str {
+        match self {
+            Readiness::Full => "ready",
+            Readiness::Degraded => "runs with reduced features",
+            Readiness::Unusable => "cannot play music",
+        }
+    }
+}
+
+/* AudioProbe - What was found about audio output.
+ *
+ * fields:
+ *     - device (Option<String>): Name of the default output device, or None if there is none.
+ *     - sample_rates (Vec<(u32, u32)>): Supported sample rate ranges of the device, in Hz.
+ *     - sink (Result<(), String>): Ok if a playback sink could be created, or the error.
+ */
+pub struct AudioProbe {
+    pub device: Option<String>,
+    pub sample_rates: Vec<(u32, u32)>,
+    pub sink: Result<(), String>,
+}
+
+/* TerminalProbe - What was found about the terminal.
+ *
+ * fields:
+ *     - term (Option<String>): The TERM environment variable, if set.
+ *     - size (Option<(u16, u16)>): Width and height in cells, or None if unknown.
+ *     - support (Support): The outcome of the startup capability check.
+ */
+pub struct TerminalProbe {
+    pub term: Option<String>,
+    pub size: Option<(u16, u16)>,
+    pub support: Support,
+}
+
+/* StorageProbe - What was found about the data directory.
+ *
+ * fields:
+ *     - path (Option<PathBuf>): The data directory, or None if there is no home directory.
+ *     - writable (Result<(), String>): Ok if files can be written there, or the error.
+ */
+pub struct StorageProbe {
+    pub path: Option<PathBuf>,
+    pub writable: Result<(), String>,
+}
+
+/* ConfigProbe - What was found about the config file.
+ *
+ * fields:
+ *     - path (Option<PathBuf>): The config file in use, or None if there is no home directory.
+ *     - exists (bool): True if the file exists; otherwise built-in defaults are used.
+ *     - result (Result<Vec<String>, String>): Warnings from loading it, or the error that stops startup.
+ */
+pub struct ConfigProbe {
+    pub path: Option<PathBuf>,
+    pub exists: bool,
+    pub result: Result<Vec<String>, String>,
+}
+
+/* RenderProbe - How fast a short song opening rendered.
+ *
+ * fields:
+ *     - audio (Duration): Length of the audio rendered.
+ *     - elapsed (Duration): Time the render took.
+ */
+pub struct RenderProbe {
+    pub audio: Duration,
+    pub elapsed: Duration,
+}
+
+/* Probes - All probe results the report is assembled from.
+ *
+ * fields:
+ *     - audio (AudioProbe): Audio output.
+ *     - terminal (TerminalProbe): The terminal.
+ *     - storage (StorageProbe): The data directory.
+ *     - config (ConfigProbe): The config file.
+ *     - render (RenderProbe): Generation speed.
+ */
+pub struct Probes {
+    pub audio: AudioProbe,
+    pub terminal: TerminalProbe,
+    pub storage: StorageProbe,
+    pub config: ConfigProbe,
+    pub render: RenderProbe,
+}
+
+/* probe_audio - Looks up the default output device and tries to open a sink on it.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - AudioProbe: What was found.
+ */
+fn probe_audio() -> AudioProbe {
+    let device = rodio::cpal::default_host().default_output_device();
+    let name = device
+        .as_ref()
+        .map(|device| device.name().unwrap_or_else(|_| "(unnamed device)".to_string()));
+    let mut sample_rates: Vec<(u32, u32)> = device
+        .as_ref()
+        .and_then(|device| device.supported_output_configs().ok())
+        .map(|configs| {
+            configs
+                .map(|config| (config.min_sample_rate().0, config.max_sample_rate().0))
+                .collect()
+        })
+        .unwrap_or_default();
+    sample_rates.sort_unstable();
+    sample_rates.dedup();
+
+    // The same stream and sink the music service opens, dropped again right away
+    let sink = OutputStream::try_default()
+        .map_err(|error| error.to_string())
+        .and_then(|(_stream, handle)| {
+            Sink::try_new(&handle)
+                .map(|_| ())
+                .map_err(|error| error.to_string())
+        });
+    AudioProbe {
+        device: name,
+        sample_rates,
+        sink,
+    }
+}
+
+/* probe_terminal - Runs the startup capability check and reads the terminal size.
+ *
+ * The report is often redirected to a file for pasting, so the terminal counts as interactive
+ * if either stdout or stderr is one.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - TerminalProbe: What was found.
+ */
+fn probe_terminal() -> TerminalProbe {
+    let is_tty = io::stdout().is_terminal() || io::stderr().is_terminal();
+    TerminalProbe {
+        term: std::env::var("TERM").ok(),
+        size: crossterm::terminal::size().ok(),
+        support: terminal::detect_support(is_tty),
+    }
+}
+
+/* probe_storage - Checks that the data directory is writable.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - StorageProbe: What was found.
+ */
+fn probe_storage() -> StorageProbe {
+    let path = session::data_dir();
+    let writable = match &path {
+        Some(path) => session::probe_writable(path).map_err(|error| error.to_string()),
+        None => Err("no home directory".to_string()),
+    };
+    StorageProbe { path, writable }
+}
+
+/* probe_config - Loads the config file the app would use.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - ConfigProbe: What was found.
+ */
+fn probe_config() -> ConfigProbe {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path_override = match config::config_path_from_args(&args) {
+        Ok(path_override) => path_override,
+        Err(message) => {
+            return ConfigProbe {
+                path: None,
+                exists: false,
+                result: Err(message),
+            }
+        }
+    };
+    let path = path_override
+        .clone()
+        .or_else(|| config::config_dir().map(|dir| dir.join(config::CONFIG_FILE_NAME)));
+    ConfigProbe {
+        exists: path.as_ref().is_some_and(|path| path.exists()),
+        path,
+        result: config::load(path_override).map(|(_, warnings)| warnings),
+    }
+}
+
+/* probe_render - Times a silent render of a short song opening.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - RenderProbe: The audio length and the time it took.
+ */
+fn probe_render() -> RenderProbe {
+    let started = Instant::now();
+    let samples = gen::render_opening(&AppState::default(), RENDER_PROBE_SEED, RENDER_PROBE_SECONDS);
+    RenderProbe {
+        audio: Duration::from_secs_f64(samples as f64 / SAMPLE_RATE as f64),
+        elapsed: started.elapsed(),
+    }
+}
+
+/* build_report - Assembles the report lines and overall readiness from probe results.
+ *
+ * inputs:
+ *     - probes (&Probes): The probe results.
+ *
+ * outputs:
+ *     - (Vec<String>, Readiness): The report, one line per entry, and how well the app can run.
+ */
+pub fn build_report(probes: &Probes) -> (Vec<String>, Readiness) {
+    let mut readiness = Readiness::Full;
+    let mut problems: Vec<(Readiness, String)> = Vec::new();
+    let mut lines = vec![
+        "8BitBeats doctor".to_string(),
+        format!(
+            "Version:        8BitBeats {} ({}, {})",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ),
+        format!(
+            "Generator:      {} Hz mono, song IDs Scale-Style-BPM-Length-Seed",
+            SAMPLE_RATE
+        ),
+    ];
+
+    let audio = &probes.audio;
+    lines.push(format!(
+        "Audio device:   {}",
+        audio.device.as_deref().unwrap_or("none found")
+    ));
+    if !audio.sample_rates.is_empty() {
+        let rates: Vec<String> = audio
+            .sample_rates
+            .iter()
+            .map(|&(min, max)| {
+                if min == max {
+                    min.to_string()
+                } else {
+                    format!("{}-{}", min, max)
+                }
+            })
+            .collect();
+        lines.push(format!("Sample rates:   {} Hz", rates.join(", ")));
+    }
+    match &audio.sink {
+        Ok(()) => lines.push("Audio output:   ok".to_string()),
+        Err(error) => {
+            lines.push(format!("Audio output:   failed ({})", error));
+            problems.push((Readiness::Unusable, "No audio output could be opened.".to_string()));
+        }
+    }
+
+    let term = &probes.terminal;
+    let size = match term.size {
+        Some((width, height)) => format!("{}x{}", width, height),
+        None => "unknown size".to_string(),
+    };
+    lines.push(format!(
+        "Terminal:       TERM={}, {}",
+        term.term.as_deref().unwrap_or("(unset)"),
+        size
+    ));
+    match &term.support {
+        Support::Tui(capabilities) => {
+            let yes_no = |value: bool| if value { "yes" } else { "no" };
+            lines.push(format!(
+                "Display:        alternate screen {}, colors {}, unicode {}",
+                yes_no(capabilities.alternate_screen),
+                yes_no(capabilities.colors),
+                yes_no(capabilities.unicode)
+            ));
+            if !(capabilities.alternate_screen && capabilities.colors && capabilities.unicode) {
+                problems.push((
+                    Readiness::Degraded,
+                    "The TUI runs with a simplified display.".to_string(),
+                ));
+            }
+        }
+        Support::Unsupported(message) => {
+            lines.push("Display:        TUI unavailable".to_string());
+            problems.push((Readiness::Degraded, message.clone()));
+        }
+    }
+    if term
+        .size
+        .is_some_and(|(width, height)| width < MIN_WIDTH || height < MIN_HEIGHT)
+    {
+        problems.push((
+            Readiness::Degraded,
+            format!("The TUI needs at least {}x{} cells.", MIN_WIDTH, MIN_HEIGHT),
+        ));
+    }
+
+    let storage = &probes.storage;
+    let path = storage
+        .path
+        .as_ref()
+        .map_or("(none)".to_string(), |path| path.display().to_string());
+    match &storage.writable {
+        Ok(()) => lines.push(format!("Data directory: {} (writable)", path)),
+        Err(error) => {
+            lines.push(format!("Data directory: {} (not writable: {})", path, error));
+            problems.push((
+                Readiness::Degraded,
+                "Settings, history and the library won't be saved.".to_string(),
+            ));
+        }
+    }
+
+    let config_file = &probes.config;
+    let path = config_file
+        .path
+        .as_ref()
+        .map_or("(none)".to_string(), |path| path.display().to_string());
+    match &config_file.result {
+        Ok(warnings) => {
+            let status = if config_file.exists { "loaded" } else { "not found, using defaults" };
+            lines.push(format!("Config file:    {} ({})", path, status));
+            lines.extend(warnings.iter().map(|warning| format!("                {}", warning)));
+        }
+        Err(error) => {
+            lines.push(format!("Config file:    {} (invalid)", path));
+            problems.push((Readiness::Unusable, error.clone()));
+        }
+    }
+
+    let render = &probes.render;
+    let speed = render.audio.as_secs_f64() / render.elapsed.as_secs_f64().max(f64::EPSILON);
+    lines.push(format!(
+        "Render speed:   {:.1}s of audio in {}ms ({:.0}x real time)",
+        render.audio.as_secs_f64(),
+        render.elapsed.as_millis(),
+        speed
+    ));
+    if speed < 1.0 {
+        problems.push((
+            Readiness::Degraded,
+            "Songs render slower than real time, so they take a while to start.".to_string(),
+        ));
+    }
+
+    for (severity, _) in &problems {
+        readiness = readiness.max(*severity);
+    }
+    lines.push(String::new());
+    lines.push(format!(
+        "Result:         {} (exit code {})",
+        readiness.label(),
+        readiness.exit_code()
+    ));
+    lines.extend(problems.into_iter().map(|(_, problem)| format!("  - {}", problem)));
+    (lines, readiness)
+}
+
+/* run - Probes the environment and prints the report for pasting into bug reports.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - i32: The exit code, see `Readiness::exit_code`.
+ */
+pub fn run() -> i32 {
+    let probes = Probes {
+        audio: probe_audio(),
+        terminal: probe_terminal(),
+        storage: probe_storage(),
+        config: probe_config(),
+        render: probe_render(),
+    };
+    let (lines, readiness) = build_report(&probes);
+    for line in lines {
+        println!("{}", line);
+    }
+    readiness.exit_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::Capabilities;
+
+    // ProblemCase: A name, how it breaks a healthy machine, the readiness and a line expected after.
+    type ProblemCase = (&'static str, fn(&mut Probes), Readiness, &'static str);
+
+    // A machine on which everything works
+    fn healthy() -> Probes {
+        Probes {
+            audio: AudioProbe {
+                device: Some("Built-in Output".to_string()),
+                sample_rates: vec![(44100, 44100), (8000, 192000)],
+                sink: Ok(()),
+            },
+            terminal: TerminalProbe {
+                term: Some("xterm-256color".to_string()),
+                size: Some((MIN_WIDTH + 40, MIN_HEIGHT + 10)),
+                support: Support::Tui(Capabilities {
+                    alternate_screen: true,
+                    colors: true,
+                    unicode: true,
+                }),
+            },
+            storage: StorageProbe {
+                path: Some(PathBuf::from("/home/user/.local/share/8bitbeats")),
+                writable: Ok(()),
+            },
+            config: ConfigProbe {
+                path: Some(PathBuf::from("/home/user/.config/8bitbeats/config.toml")),
+                exists: true,
+                result: Ok(Vec::new()),
+            },
+            render: RenderProbe {
+                audio: Duration::from_secs(1),
+                elapsed: Duration::from_millis(100),
+            },
+        }
+    }
+
+    fn has_line(lines: &[String], text: &str) -> bool {
+        lines.iter().any(|line| line.contains(text))
+    }
+
+    #[test]
+    fn a_healthy_machine_is_fully_ready() {
+        let (lines, readiness) = build_report(&healthy());
+        assert_eq!(readiness, Readiness::Full);
+        assert!(has_line(&lines, "Audio device:   Built-in Output"));
+        assert!(has_line(&lines, "Sample rates:   44100, 8000-192000 Hz"));
+        assert!(has_line(&lines, "Render speed:   1.0s of audio in 100ms (10x real time)"));
+        assert_eq!(lines.last().map(String::as_str), Some("Result:         ready (exit code 0)"));
+    }
+
+    #[test]
+    fn each_problem_sets_the_readiness_it_deserves() {
+        let cases: [ProblemCase; 8] = [
+            (
+                "no sink",
+                |probes| probes.audio.sink = Err("no device".to_string()),
+                Readiness::Unusable,
+                "No audio output could be opened.",
+            ),
+            (
+                "invalid config",
+                |probes| probes.config.result = Err("bad volume".to_string()),
+                Readiness::Unusable,
+                "  - bad volume",
+            ),
+            (
+                "monochrome",
+                |probes| {
+                    probes.terminal.support = Support::Tui(Capabilities {
+                        alternate_screen: true,
+                        colors: false,
+                        unicode: true,
+                    })
+                },
+                Readiness::Degraded,
+                "simplified display",
+            ),
+            (
+                "no TUI",
+                |probes| probes.terminal.support = Support::Unsupported("TERM is dumb".to_string()),
+                Readiness::Degraded,
+                "  - TERM is dumb",
+            ),
+            (
+                "small terminal",
+                |probes| probes.terminal.size = Some((MIN_WIDTH - 1, MIN_HEIGHT)),
+                Readiness::Degraded,
+                "The TUI needs at least",
+            ),
+            (
+                "read-only data",
+                |probes| probes.storage.writable = Err("read-only".to_string()),
+                Readiness::Degraded,
+                "won't be saved",
+            ),
+            (
+                "slow render",
+                |probes| probes.render.elapsed = Duration::from_secs(2),
+                Readiness::Degraded,
+                "slower than real time",
+            ),
+            (
+                "missing config",
+                |probes| probes.config.exists = false,
+                Readiness::Full,
+                "not found, using defaults",
+            ),
+        ];
+        for (name, break_it, expected, line) in cases {
+            let mut probes = healthy();
+            break_it(&mut probes);
+            let (lines, readiness) = build_report(&probes);
+            assert_eq!(readiness, expected, "{}", name);
+            assert!(has_line(&lines, line), "{}: {:?}", name, lines);
+        }
+    }
+
+    #[test]
+    fn the_worst_problem_decides_the_readiness() {
+        let mut probes = healthy();
+        probes.storage.writable = Err("read-only".to_string());
+        probes.audio.sink = Err("no device".to_string());
+        probes.audio.device = None;
+        let (lines, readiness) = build_report(&probes);
+        assert_eq!(readiness, Readiness::Unusable);
+        assert_eq!(readiness.exit_code(), 2);
+        assert!(has_line(&lines, "Audio device:   none found"));
+        assert!(has_line(&lines, "Result:         cannot play music (exit code 2)"));
+        // Both problems are still listed
+        assert_eq!(lines.iter().filter(|line| line.starts_with("  - ")).count(), 2);
+    }
+}
@@ -0,0 +1,784 @@
+use crate::melodies;
+use crate::progs::TimeSignature;
+use crate::synth::{self, Waveform};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::ops::Range;
+
+// SAMPLE_RATE: Audio sample rate in Hz, matching the other tracks.
+const SAMPLE_RATE: f32 = 44100.0;
+
+// DRUM_PATTERN_SEED_SALT / DRUM_NOISE_SEED_SALT: Mixed into the song seed so the pattern choice and
+// the noise of each hit use their own random streams and leave the other tracks untouched.
+const DRUM_PATTERN_SEED_SALT: u64 = 0x6472_756d_5f70_6174;
+const DRUM_NOISE_SEED_SALT: u64 = 0x6472_756d_5f6e_6f69;
+
+// KICK_LEVEL / TOM_LEVEL / SNARE_LEVEL / HAT_LEVEL / RIDE_LEVEL / CRASH_LEVEL: Peak level of each drum.
+const KICK_LEVEL: f32 = 0.5;
+const TOM_LEVEL: f32 = 0.35;
+const SNARE_LEVEL: f32 = 0.3;
+const HAT_LEVEL: f32 = 0.12;
+const RIDE_LEVEL: f32 = 0.08;
+const CRASH_LEVEL: f32 = 0.1;
+
+// KICK_START_HZ / KICK_END_HZ: The kick's pitch drop, from its attack to its tail.
+const KICK_START_HZ: f32 = 120.0;
+const KICK_END_HZ: f32 = 45.0;
+
+// SNARE_BODY_HZ: Pitch of the tone under the snare's noise.
+const SNARE_BODY_HZ: f32 = 180.0;
+
+// TOM_PITCHES_HZ / TOM_PITCH_DROP: Toms from high to low, each starting this fraction above its pitch.
+const TOM_PITCHES_HZ: [f32; 4] = [220.0, 175.0, 140.0, 110.0];
+const TOM_PITCH_DROP: f32 = 0.3;
+
+// RIDE_PARTIALS_HZ: Inharmonic partials that give the ride its bell-like ping.
+const RIDE_PARTIALS_HZ: [f32; 2] = [3150.0, 4730.0];
+
+/* DrumVoice - One of the synthesized drums.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DrumVoice {
+    Kick,       // Short low sine with a pitch drop.
+    Tom(usize), // Pitched sine with a small drop; the index picks from `TOM_PITCHES_HZ`, high to low.
+    Snare,      // Low-passed noise burst over a short tone.
+    HiHat,      // Very short high-passed noise.
+    Ride,       // Longer, quieter high-passed noise with a metallic ping.
+    Crash,      // Long high-passed noise.
+}
+
+impl DrumVoice {
+    /* seconds - The longest a hit of this drum rings for.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - f32: The length in seconds.
+     */
+    fn seconds(&self) -> f32 {
+        match self {
+            DrumVoice::Kick => 0.15,
+            DrumVoice::Tom(_) => 0.25,
+            DrumVoice::Snare => 0.12,
+            DrumVoice::HiHat => 0.04,
+            DrumVoice::Ride => 0.3,
+            DrumVoice::Crash => 1.2,
+        }
+    }
+
+    /* channel - Groups drums that cut each other off, like one drummer's limb.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - usize: The channel; toms share the kick's and the ride the crash's.
+     */
+    fn channel(&self) -> usize {
+        match self {
+            DrumVoice::Kick | DrumVoice::Tom(_) => 0,
+            DrumVoice::Snare => 1,
+            DrumVoice::HiHat => 2,
+            DrumVoice::Ride | DrumVoice::Crash => 3,
+        }
+    }
+
+    /* noise_pitch - The pitch the drum plays at on a noise channel, as in NES mode.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - f64: The pitch of the noise waveform in Hz (see `synth::Waveform::Noise`): a low
+     *       rumble for the kick and toms, a hiss for the snare and a bright fizz for the cymbals.
+     */
+    fn noise_pitch(&self) -> f64 {
+        match self {
+            DrumVoice::Kick => 90.0,
+            DrumVoice::Tom(index) => 2.0 * TOM_PITCHES_HZ[(*index).min(TOM_PITCHES_HZ.len() - 1)] as f64,
+            DrumVoice::Snare => 1200.0,
+            DrumVoice::HiHat | DrumVoice::Crash => 6000.0,
+            DrumVoice::Ride => 3000.0,
+        }
+    }
+
+    /* level - Returns the drum's peak level.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - f32: The level, e.g. `KICK_LEVEL`.
+     */
+    fn level(&self) -> f32 {
+        match self {
+            DrumVoice::Kick => KICK_LEVEL,
+            DrumVoice::Tom(_) => TOM_LEVEL,
+            DrumVoice::Snare => SNARE_LEVEL,
+            DrumVoice::HiHat => HAT_LEVEL,
+            DrumVoice::Ride => RIDE_LEVEL,
+            DrumVoice::Crash => CRASH_LEVEL,
+        }
+    }
+}
+
+/* DrumPattern - One bar of drums on a step grid.
+ *
+ * Each line has one character per step: 'x' plays the drum, anything else is a rest. All
+ * lines have the same length, which with `steps_per_beat` sets the time signature: 16
+ * sixteenth steps make a 4/4 bar, 12 sixteenth steps a 3/4 bar, and 12 half-beat steps a 6/8 bar.
+ *
+ * fields:
+ *     - steps_per_beat (usize): Grid steps per beat, 4 for sixteenths, 2 for the eighth-note beats
+ *       of 6/8 or 3 for triplet eighths
+ *       (which are swung already, so the song's swing leaves them alone).
+ *     - lines (&[(DrumVoice, &str)]): Steps of each drum in the pattern.
+ */
+struct DrumPattern {
+    steps_per_beat: usize,
+    lines: &'static [(DrumVoice, &'static str)],
+}
+
+impl DrumPattern {
+    /* steps_per_bar - Returns the length of the pattern in grid steps.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - usize: Steps per bar.
+     */
+    fn steps_per_bar(&self) -> usize {
+        self.lines.first().map_or(0, |(_, steps)| steps.len())
+    }
+}
+
+// BACKBEAT_PATTERNS: 4/4 pop and rock grooves, with the snare on beats 2 and 4.
+const BACKBEAT_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.......x......."),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.....x.x......."),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.......x.x....."),
+            (DrumVoice::Snare, "....x.......x..x"),
+            (DrumVoice::HiHat, "x.xxx.x.x.xxx.x."),
+        ],
+    },
+];
+
+// JAZZ_PATTERNS: Ride ("ding, ding-a ding, ding-a"), swung by the song's swing, with the
+// hi-hat closing on 2 and 4 and light snare comping.
+const JAZZ_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Ride, "x...x.x.x...x.x."),
+            (DrumVoice::HiHat, "....x.......x..."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Ride, "x...x.x.x...x.x."),
+            (DrumVoice::HiHat, "....x.......x..."),
+            (DrumVoice::Snare, "......x.......x."),
+            (DrumVoice::Kick, "x..............."),
+        ],
+    },
+];
+
+// SHUFFLE_PATTERNS: Blues shuffles: eighth-note hi-hats that the song's swing turns long-short.
+const SHUFFLE_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.......x......."),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.x.....x.x....."),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+];
+
+// METAL_PATTERNS: Relentless double kicks and a blast beat.
+const METAL_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "xxxxxxxxxxxxxxxx"),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "x...x...x...x..."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.xxx.xxx.xxx.xx"),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.x.x.x.x.x.x.x."),
+            (DrumVoice::Snare, ".x.x.x.x.x.x.x.x"),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+];
+
+// ELECTRONIC_PATTERNS: Four-on-the-floor kicks under off-beat hats and claps on 2 and 4.
+const ELECTRONIC_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x...x...x...x..."),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "..x...x...x...x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x...x...x...x..."),
+            (DrumVoice::Snare, "....x.......x..."),
+            (DrumVoice::HiHat, "..xx..x...xx..x."),
+        ],
+    },
+];
+
+// REGGAE_PATTERNS: One drop, with kick and snare together on beat 3 only.
+const REGGAE_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "........x......."),
+            (DrumVoice::Snare, "........x......."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "........x......."),
+            (DrumVoice::Snare, "........x......."),
+            (DrumVoice::HiHat, "..x...x...x...x."),
+        ],
+    },
+];
+
+// WALTZ_PATTERNS: 3/4 bars, for every style with a kit: kick on 1, snare on 2 and 3.
+const WALTZ_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x..........."),
+            (DrumVoice::Snare, "....x...x..."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 4,
+        lines: &[
+            (DrumVoice::Kick, "x.......x..."),
+            (DrumVoice::Snare, "....x......."),
+            (DrumVoice::HiHat, "x...x...x..."),
+        ],
+    },
+];
+
+// SIX_EIGHT_PATTERNS: 6/8 bars of six eighth-note beats: kick on 1, snare on 4, hi-hat on every beat.
+const SIX_EIGHT_PATTERNS: &[DrumPattern] = &[
+    DrumPattern {
+        steps_per_beat: 2,
+        lines: &[
+            (DrumVoice::Kick, "x..........."),
+            (DrumVoice::Snare, "......x....."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x."),
+        ],
+    },
+    DrumPattern {
+        steps_per_beat: 2,
+        lines: &[
+            (DrumVoice::Kick, "x.......x..."),
+            (DrumVoice::Snare, "......x....."),
+            (DrumVoice::HiHat, "x.x.x.x.x.x."),
+        ],
+    },
+];
+
+/* style_patterns - Picks the drum grooves that suit a style and time signature.
+ *
+ * Every style shares the same grooves in 3/4 and 6/8; the style-specific ones are in 4/4.
+ *
+ * inputs:
+ *     - style (&str): The musical style (case-insensitive).
+ *     - time_signature (TimeSignature): The song's time signature.
+ *
+ * outputs:
+ *     - &[DrumPattern]: The grooves to choose from; empty for styles without drums
+ *       (ambient stays beatless, classical has no kit).
+ */
+fn style_patterns(style: &str, time_signature: TimeSignature) -> &'static [DrumPattern] {
+    match (style.to_lowercase().as_str(), time_signature) {
+        ("ambient" | "classical", _) => &[],
+        (_, TimeSignature::ThreeFour) => WALTZ_PATTERNS,
+        (_, TimeSignature::SixEight) => SIX_EIGHT_PATTERNS,
+        ("jazz", _) => JAZZ_PATTERNS,
+        ("blues", _) => SHUFFLE_PATTERNS,
+        ("metal", _) => METAL_PATTERNS,
+        ("electronic", _) => ELECTRONIC_PATTERNS,
+        ("reggae", _) => REGGAE_PATTERNS,
+        _ => BACKBEAT_PATTERNS,
+    }
+}
+
+/* FillKind - How the last bar of a phrase is played.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FillKind {
+    SnareRoll,         // Snare hits that get denser towards the next downbeat.
+    TomRun,            // Toms stepping down in pitch.
+    CrashIntoDownbeat, // Kick and snare together on each beat, then a crash on the next downbeat.
+}
+
+// FILL_KINDS: Fills to choose from for each phrase.
+const FILL_KINDS: [FillKind; 3] = [FillKind::SnareRoll, FillKind::TomRun, FillKind::CrashIntoDownbeat];
+
+/* fill_phrase_bars - Returns how long a style's phrases are; the last bar of each is a fill.
+ *
+ * inputs:
+ *     - style (&str): The musical style (case-insensitive).
+ *
+ * outputs:
+ *     - usize: Bars per phrase.
+ */
+fn fill_phrase_bars(style: &str) -> usize {
+    match style.to_lowercase().as_str() {
+        "rock" | "blues" | "metal" => 4,
+        _ => 8,
+    }
+}
+
+/* fill_hits - Builds the hits of a fill.
+ *
+ * The fill runs from `start_step` to the end of the bar and never past it, so the next bar's
+ * downbeat stays where it is.
+ *
+ * inputs:
+ *     - kind (FillKind): The fill to play.
+ *     - pattern (&DrumPattern): The song's groove, which sets the grid.
+ *     - start_step (usize): The step the fill starts on, at the start of a beat.
+ *
+ * outputs:
+ *     - Vec<(usize, DrumVoice)>: The step and drum of every hit.
+ */
+fn fill_hits(kind: FillKind, pattern: &DrumPattern, start_step: usize) -> Vec<(usize, DrumVoice)> {
+    let fill_steps = pattern.steps_per_bar().saturating_sub(start_step);
+    let half_beat = (pattern.steps_per_beat / 2).max(1);
+    let mut hits = Vec::new();
+    match kind {
+        FillKind::SnareRoll => {
+            hits.push((start_step, DrumVoice::Kick));
+            for offset in 0..fill_steps {
+                // A hit per beat, then per half beat, then on every step
+                let spacing = match offset * 3 / fill_steps {
+                    0 => pattern.steps_per_beat,
+                    1 => half_beat,
+                    _ => 1,
+                };
+                if offset % spacing == 0 {
+                    hits.push((start_step + offset, DrumVoice::Snare));
+                }
+            }
+        }
+        FillKind::TomRun => {
+            let offsets: Vec<usize> = (0..fill_steps).step_by(half_beat).collect();
+            for (index, offset) in offsets.iter().enumerate() {
+                let tom = index * TOM_PITCHES_HZ.len() / offsets.len();
+                hits.push((start_step + offset, DrumVoice::Tom(tom)));
+            }
+        }
+        FillKind::CrashIntoDownbeat => {
+            for offset in (0..fill_steps).step_by(pattern.steps_per_beat) {
+                hits.push((start_step + offset, DrumVoice::Kick));
+                hits.push((start_step + offset, DrumVoice::Snare));
+            }
+        }
+    }
+    hits
+}
+
+/* add_noise_hit - Mixes one drum hit, played on a noise channel, into a buffer.
+ *
+ * Every drum is a burst of noise at its own pitch (see `DrumVoice::noise_pitch`) that fades
+ * out over the drum's length, as the NES noise channel plays percussion.
+ *
+ * inputs:
+ *     - voice (DrumVoice): The drum to play.
+ *     - samples (&mut [f32]): Where the hit goes; its length is the length of the hit.
+ *     - noise_seed (u64): Seed for where in the noise the hit starts.
+ *
+ * outputs:
+ *     - None
+ */
+fn add_noise_hit(voice: DrumVoice, samples: &mut [f32], noise_seed: u64) {
+    let hit_samples = (voice.seconds() * SAMPLE_RATE) as usize;
+    let start_phase = (noise_seed % (1 << 20)) as f64;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let decay = (1.0 - i as f32 / hit_samples.max(1) as f32).max(0.0);
+        let phase = start_phase + i as f64 * voice.noise_pitch() / SAMPLE_RATE as f64;
+        *sample += synth::wave(Waveform::Noise, phase) * voice.level() * decay * decay;
+    }
+}
+
+/* add_hit - Mixes one drum hit into a buffer.
+ *
+ * inputs:
+ *     - voice (DrumVoice): The drum to play.
+ *     - samples (&mut [f32]): Where the hit goes; its length is the length of the hit.
+ *     - noise_seed (u64): Seed for the noise of snares and cymbals.
+ *
+ * outputs:
+ *     - None
+ */
+fn add_hit(voice: DrumVoice, samples: &mut [f32], noise_seed: u64) {
+    let hit_samples = (voice.seconds() * SAMPLE_RATE) as usize;
+    let mut rng = StdRng::seed_from_u64(noise_seed);
+    let mut phase = 0.0f32;
+    let mut low_passed = 0.0f32;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let progress = i as f32 / hit_samples.max(1) as f32;
+        let decay = (1.0 - progress).max(0.0);
+        let time = i as f32 / SAMPLE_RATE;
+        *sample += match voice {
+            DrumVoice::Kick => {
+                let frequency = KICK_START_HZ * (KICK_END_HZ / KICK_START_HZ).powf(progress);
+                phase = (phase + frequency / SAMPLE_RATE).fract();
+                (phase * std::f32::consts::TAU).sin() * KICK_LEVEL * decay
+            }
+            DrumVoice::Tom(index) => {
+                let pitch = TOM_PITCHES_HZ[index.min(TOM_PITCHES_HZ.len() - 1)];
+                let frequency = pitch * (1.0 + TOM_PITCH_DROP * (1.0 - progress));
+                phase = (phase + frequency / SAMPLE_RATE).fract();
+                (phase * std::f32::consts::TAU).sin() * TOM_LEVEL * decay
+            }
+            DrumVoice::Snare => {
+                let noise = rng.gen_range(-1.0f32..=1.0);
+                low_passed += (noise - low_passed) * 0.5; // Takes the hiss off the top
+                let body = (time * SNARE_BODY_HZ * std::f32::consts::TAU).sin();
+                (low_passed * 0.7 + body * 0.3) * SNARE_LEVEL * decay * decay
+            }
+            DrumVoice::HiHat => {
+                let noise = rng.gen_range(-1.0f32..=1.0);
+                low_passed += (noise - low_passed) * 0.5;
+                // Noise minus its low-passed copy keeps only the bright part
+                (noise - low_passed) * 0.5 * HAT_LEVEL * decay * decay
+            }
+            DrumVoice::Ride => {
+                let noise = rng.gen_range(-1.0f32..=1.0);
+                low_passed += (noise - low_passed) * 0.5;
+                let ping: f32 = RIDE_PARTIALS_HZ
+                    .iter()
+                    .map(|frequency| (time * frequency * std::f32::consts::TAU).sin())
+                    .sum();
+                ((noise - low_passed) * 0.25 + ping * 0.25) * RIDE_LEVEL * decay * decay
+            }
+            DrumVoice::Crash => {
+                let noise = rng.gen_range(-1.0f32..=1.0);
+                low_passed += (noise - low_passed) * 0.5;
+                (noise - low_passed) * 0.5 * CRASH_LEVEL * decay * decay
+            }
+        };
+    }
+}
+
+/* plan_hits - Places every hit of a drum track on the sample grid.
+ *
+ * inputs:
+ *     - style (&str): The musical style (case-insensitive), which sets the phrase length.
+ *     - pattern (&DrumPattern): The song's groove.
+ *     - bpm (u32): Beats per minute, above zero.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - total_samples (usize): Length of the track in samples; hits from here on are dropped.
+ *     - end (usize): The sample the plan stops at: bars starting there or later are left out.
+ *     - rng (&mut StdRng): The song's pattern stream, which draws the fills in bar order.
+ *
+ * outputs:
+ *     - Vec<(usize, DrumVoice)>: The start sample and drum of every hit, bar by bar.
+ */
+fn plan_hits(
+    style: &str,
+    pattern: &DrumPattern,
+    bpm: u32,
+    swing: f32,
+    total_samples: usize,
+    end: usize,
+    rng: &mut StdRng,
+) -> Vec<(usize, DrumVoice)> {
+    let steps_per_bar = pattern.steps_per_bar();
+    let beats_per_bar = steps_per_bar / pattern.steps_per_beat;
+    let phrase_bars = fill_phrase_bars(style);
+    let beat_samples = SAMPLE_RATE as f64 * 60.0 / bpm as f64;
+    let swung = swing > 0.0 && pattern.steps_per_beat.is_multiple_of(2);
+    let step_start = |step: usize| {
+        let beats = step as f64 / pattern.steps_per_beat as f64;
+        let beats = if swung { melodies::swing_position(beats, swing) } else { beats };
+        (beats * beat_samples).round() as usize
+    };
+
+    // The fills are drawn in bar order, so every bar up to the end is planned
+    let mut hits: Vec<(usize, DrumVoice)> = Vec::new();
+    let mut crash_on_downbeat = false;
+    for bar in 0.. {
+        let bar_step = bar * steps_per_bar;
+        if step_start(bar_step) >= end {
+            break;
+        }
+        let mut bar_hits = Vec::new();
+        if crash_on_downbeat {
+            bar_hits.push((0, DrumVoice::Crash));
+            crash_on_downbeat = false;
+        }
+        let groove_steps = if (bar + 1) % phrase_bars == 0 {
+            let kind = *FILL_KINDS.choose(rng).unwrap();
+            let start_beat = *[0, beats_per_bar / 2].choose(rng).unwrap();
+            let start_step = start_beat * pattern.steps_per_beat;
+            bar_hits.extend(fill_hits(kind, pattern, start_step));
+            crash_on_downbeat = kind == FillKind::CrashIntoDownbeat;
+            start_step
+        } else {
+            steps_per_bar
+        };
+        for &(voice, steps) in pattern.lines {
+            for (step, symbol) in steps.bytes().enumerate().take(groove_steps) {
+                if symbol == b'x' {
+                    bar_hits.push((step, voice));
+                }
+            }
+        }
+        for (step, voice) in bar_hits {
+            let start = step_start(bar_step + step);
+            if start < total_samples {
+                hits.push((start, voice));
+            }
+        }
+    }
+    hits
+}
+
+/* get_drums - Generates the drum track for a song.
+ *
+ * The style sets the groove: a backbeat for pop and rock, four-on-the-floor for electronic,
+ * a one drop for reggae, a swung ride for jazz, a shuffle for blues, double kicks and blast
+ * beats for metal, and a waltz in 3/4 or a lilt in 6/8 for any style (see `style_patterns`);
+ * ambient and classical have no drums. The song
+ * seed picks one of the style's grooves, so songs in the same style don't all share one beat.
+ *
+ * The last bar of every phrase (4 or 8 bars, depending on the style) is a fill: a snare roll,
+ * a run down the toms or kick and snare into a crash on the next downbeat, over the whole
+ * bar or its second half (in 6/8, its second group of three). The fills are drawn from the seed
+ * in bar order.
+ *
+ * With swing, every off-beat eighth of the grid (and the sixteenths around it) moves later by
+ * the same amount as the melody's and bass's (see `melodies::swing_position`).
+ *
+ * Every hit sits on a fixed position of the grid and its noise has its own seed, so the track
+ * is the same for the same seed, a shorter render is an exact prefix of a longer one, and any
+ * stretch of the track can be rendered on its own (see `window`). A
+ * hit is cut short when its channel plays again: toms share the kick's, and the ride the
+ * crash's. On a single noise channel (NES mode) every drum is a
+ * noise burst and cuts off the one before, the first drum listed at a start winning.
+ *
+ * inputs:
+ *     - style (&str): The musical style (case-insensitive).
+ *     - bpm (u32): Beats per minute.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - time_signature (TimeSignature): The song's time signature, which picks the grooves.
+ *     - total_samples (usize): Length of the track in samples, normally the melody's length.
+ *     - window (Range<usize>): The samples of the track to render, within `total_samples`.
+ *     - seed (u64): The song seed.
+ *     - noise_channel (bool): True to play every drum on one noise channel (see `add_noise_hit`).
+ *
+ * outputs:
+ *     - Vec<f32>: The samples of `window`; silent for styles without drums or a zero BPM.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn get_drums(
+    style: &str,
+    bpm: u32,
+    swing: f32,
+    time_signature: TimeSignature,
+    total_samples: usize,
+    window: Range<usize>,
+    seed: u64,
+    noise_channel: bool,
+) -> Vec<f32> {
+    let window = window.start.min(total_samples)..window.end.min(total_samples);
+    let mut drums = vec![0.0; window.len()];
+    let mut rng = StdRng::seed_from_u64(seed ^ DRUM_PATTERN_SEED_SALT);
+    let Some(pattern) = style_patterns(style, time_signature).choose(&mut rng) else {
+        return drums;
+    };
+    if bpm == 0 {
+        return drums;
+    }
+    let mut hits = plan_hits(style, pattern, bpm, swing, total_samples, window.end, &mut rng);
+
+    // One hit per channel and start, the first one added winning (a crash over the ride)
+    let channel = |voice: DrumVoice| if noise_channel { 0 } else { voice.channel() };
+    hits.sort_by_key(|&(start, voice)| (channel(voice), start));
+    hits.dedup_by(|later, earlier| later.0 == earlier.0 && channel(later.1) == channel(earlier.1));
+
+    for (index, &(start, voice)) in hits.iter().enumerate() {
+        let next_start = match hits.get(index + 1) {
+            Some(&(next_start, next_voice)) if channel(next_voice) == channel(voice) => next_start,
+            _ => total_samples,
+        };
+        let hit_samples = (voice.seconds() * SAMPLE_RATE) as usize;
+        let end = (start + hit_samples).min(next_start);
+        if end <= window.start || start >= window.end {
+            continue;
+        }
+        // The whole hit is synthesized, and the part inside the window mixed in
+        let mut hit = vec![0.0; end - start];
+        let noise_seed = seed ^ DRUM_NOISE_SEED_SALT ^ start as u64 ^ ((voice.channel() as u64) << 40);
+        if noise_channel {
+            add_noise_hit(voice, &mut hit, noise_seed);
+        } else {
+            add_hit(voice, &mut hit, noise_seed);
+        }
+        let from = start.max(window.start);
+        let to = end.min(window.end);
+        for (sample, value) in drums[from - window.start..to - window.start].iter_mut().zip(&hit[from - start..to - start]) {
+            *sample += value;
+        }
+    }
+
+    drums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GridCase: (style, time signature, drum, beats of the bar it must play on, beats it must not)
+    type GridCase = (&'static str, TimeSignature, DrumVoice, &'static [f64], &'static [f64]);
+
+    // GRID_CASES: The beat grid every groove of a style keeps to.
+    const GRID_CASES: &[GridCase] = &[
+        ("pop", TimeSignature::FourFour, DrumVoice::Kick, &[0.0], &[1.0, 3.0]),
+        ("pop", TimeSignature::FourFour, DrumVoice::Snare, &[1.0, 3.0], &[0.0, 2.0]),
+        ("rock", TimeSignature::FourFour, DrumVoice::Snare, &[1.0, 3.0], &[0.0, 2.0]),
+        ("rock", TimeSignature::FourFour, DrumVoice::HiHat, &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5], &[]),
+        ("electronic", TimeSignature::FourFour, DrumVoice::Kick, &[0.0, 1.0, 2.0, 3.0], &[0.5, 1.5, 2.5, 3.5]),
+        ("electronic", TimeSignature::FourFour, DrumVoice::HiHat, &[0.5, 1.5, 2.5, 3.5], &[0.0, 1.0, 2.0, 3.0]),
+        ("electronic", TimeSignature::FourFour, DrumVoice::Snare, &[1.0, 3.0], &[0.0, 2.0]),
+        ("reggae", TimeSignature::FourFour, DrumVoice::Kick, &[2.0], &[0.0, 1.0, 3.0]),
+        ("reggae", TimeSignature::FourFour, DrumVoice::Snare, &[2.0], &[0.0, 1.0, 3.0]),
+        ("jazz", TimeSignature::FourFour, DrumVoice::Ride, &[0.0, 1.0, 1.5, 2.0, 3.0, 3.5], &[0.5, 2.5]),
+        ("jazz", TimeSignature::FourFour, DrumVoice::HiHat, &[1.0, 3.0], &[0.0, 2.0]),
+        ("blues", TimeSignature::FourFour, DrumVoice::Snare, &[1.0, 3.0], &[0.0, 2.0]),
+        ("blues", TimeSignature::FourFour, DrumVoice::HiHat, &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5], &[]),
+        ("metal", TimeSignature::FourFour, DrumVoice::Kick, &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5], &[]),
+        ("pop", TimeSignature::ThreeFour, DrumVoice::Kick, &[0.0], &[1.0]),
+        ("jazz", TimeSignature::ThreeFour, DrumVoice::Snare, &[1.0], &[0.0]),
+        ("rock", TimeSignature::SixEight, DrumVoice::Kick, &[0.0], &[1.0, 2.0, 3.0, 5.0]),
+        ("rock", TimeSignature::SixEight, DrumVoice::Snare, &[3.0], &[0.0, 1.0, 2.0, 4.0, 5.0]),
+        ("blues", TimeSignature::SixEight, DrumVoice::HiHat, &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0], &[]),
+    ];
+
+    #[test]
+    fn every_groove_plays_on_its_styles_beat_grid_at_any_tempo() {
+        for &bpm in &[70, 120, 173] {
+            let beat_samples = SAMPLE_RATE as f64 * 60.0 / bpm as f64;
+            for &(style, time_signature, voice, on_beats, off_beats) in GRID_CASES {
+                let patterns = style_patterns(style, time_signature);
+                assert!(!patterns.is_empty(), "{style} has no grooves");
+                for (index, pattern) in patterns.iter().enumerate() {
+                    let beats_per_bar = pattern.steps_per_bar() / pattern.steps_per_beat;
+                    // The bars before the first fill, which comes no earlier than the fourth
+                    let groove_bars = 3;
+                    let total_samples = (groove_bars as f64 * beats_per_bar as f64 * beat_samples) as usize;
+                    let mut rng = StdRng::seed_from_u64(0);
+                    let hits = plan_hits(style, pattern, bpm, 0.0, total_samples, total_samples, &mut rng);
+                    for bar in 0..groove_bars {
+                        let sample_at = |beat: f64| (((bar * beats_per_bar) as f64 + beat) * beat_samples).round() as usize;
+                        for &beat in on_beats {
+                            assert!(
+                                hits.contains(&(sample_at(beat), voice)),
+                                "{style} {time_signature:?} groove {index} at {bpm} BPM: no {voice:?} on beat {beat} of bar {bar}"
+                            );
+                        }
+                        for &beat in off_beats {
+                            assert!(
+                                !hits.contains(&(sample_at(beat), voice)),
+                                "{style} {time_signature:?} groove {index} at {bpm} BPM: a {voice:?} on beat {beat} of bar {bar}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_hit_starts_on_a_grid_step() {
+        let bpm = 120;
+        let beat_samples = SAMPLE_RATE as f64 * 60.0 / bpm as f64;
+        for style in ["pop", "rock", "jazz", "blues", "metal", "electronic", "reggae"] {
+            for time_signature in [TimeSignature::FourFour, TimeSignature::ThreeFour, TimeSignature::SixEight] {
+                for pattern in style_patterns(style, time_signature) {
+                    let step_samples = beat_samples / pattern.steps_per_beat as f64;
+                    let total_samples = (16.0 * pattern.steps_per_bar() as f64 * step_samples) as usize;
+                    let mut rng = StdRng::seed_from_u64(7);
+                    let hits = plan_hits(style, pattern, bpm, 0.0, total_samples, total_samples, &mut rng);
+                    assert!(!hits.is_empty());
+                    for (start, voice) in hits {
+                        let step = (start as f64 / step_samples).round();
+                        assert_eq!(
+                            start,
+                            (step * step_samples).round() as usize,
+                            "{style} {time_signature:?}: {voice:?} at sample {start} is off the grid"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ambient_and_classical_have_no_drums() {
+        for style in ["ambient", "Classical"] {
+            for time_signature in [TimeSignature::FourFour, TimeSignature::ThreeFour, TimeSignature::SixEight] {
+                assert!(style_patterns(style, time_signature).is_empty());
+                let drums = get_drums(style, 120, 0.0, time_signature, 44100, 0..44100, 1, false);
+                assert!(drums.iter().all(|&sample| sample == 0.0));
+            }
+        }
+    }
+}
@@ -0,0 +1,521 @@
+/* LowPass - A one-pole low-pass filter, fed a track one sample after another.
+ *
+ * Frequencies below the cutoff pass almost untouched; above it they fall away by 6 dB per
+ * octave, down 3 dB at the cutoff itself. The output always lies between the input's lowest
+ * and highest samples so far, so filtering never raises a track's peak.
+ *
+ * fields:
+ *     - sample_rate (f32): Samples per second.
+ *     - coefficient (f32): How far the output moves toward each new sample, from 0.0 (not at
+ *       all) to 1.0 (all the way, no filtering).
+ *     - output (f32): The last sample the filter returned.
+ */
+pub struct LowPass {
+    sample_rate: f32,
+    coefficient: f32,
+    output: f32,
+}
+
+impl LowPass {
+    /* new - Creates a low-pass filter at rest.
+     *
+     * inputs:
+     *     - cutoff_hz (f32): The cutoff frequency (see `set_cutoff`).
+     *     - sample_rate (f32): Samples per second.
+     *
+     * outputs:
+     *     - LowPass: The filter, holding silence.
+     */
+    pub fn new(cutoff_hz: f32, sample_rate: f32) -> LowPass {
+        let mut filter = LowPass {
+            sample_rate,
+            coefficient: 1.0,
+            output: 0.0,
+        };
+        filter.set_cutoff(cutoff_hz);
+        filter
+    }
+
+    /* set_cutoff - Moves the cutoff, keeping what the filter holds, so it can sweep smoothly.
+     *
+     * inputs:
+     *     - &mut self
+     *     - cutoff_hz (f32): The cutoff frequency; at or above half the sample rate the filter
+     *       lets everything through.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_cutoff(&mut self, cutoff_hz: f32) {
+        let nyquist = self.sample_rate / 2.0;
+        self.coefficient = if cutoff_hz >= nyquist {
+            1.0
+        } else {
+            1.0 - (-std::f32::consts::TAU * cutoff_hz.max(0.0) / self.sample_rate).exp()
+        };
+    }
+
+    /* process - Filters the next sample of the track.
+     *
+     * inputs:
+     *     - &mut self
+     *     - sample (f32): The next sample.
+     *
+     * outputs:
+     *     - f32: The filtered sample.
+     */
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.output += (sample - self.output) * self.coefficient;
+        self.output
+    }
+}
+
+/* smoothing_coefficient - Returns how far a one-pole follower moves toward its input per sample.
+ *
+ * inputs:
+ *     - seconds (f32): The follower's time constant; it covers about 63% of a step in this time.
+ *     - sample_rate (f32): Samples per second.
+ *
+ * outputs:
+ *     - f32: The coefficient, from 0.0 to 1.0 (1.0 for a zero time constant, following at once).
+ */
+fn smoothing_coefficient(seconds: f32, sample_rate: f32) -> f32 {
+    if seconds <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / (seconds * sample_rate)).exp()
+    }
+}
+
+/* to_db - Converts a level to decibels relative to full scale.
+ *
+ * inputs:
+ *     - level (f32): The level, 1.0 for full scale.
+ *
+ * outputs:
+ *     - f32: The level in dBFS; silence comes out very low rather than minus infinity.
+ */
+fn to_db(level: f32) -> f32 {
+    20.0 * level.max(1e-9).log10()
+}
+
+/* from_db - Converts decibels to a level.
+ *
+ * inputs:
+ *     - db (f32): The level in dB.
+ *
+ * outputs:
+ *     - f32: The level, 1.0 for 0 dB.
+ */
+fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/* Limiter - A soft-knee peak limiter, fed the mix one sample after another.
+ *
+ * An envelope follows the signal's peaks, rising over the attack time and falling over the
+ * release time, and the gain pulls the envelope back to the threshold wherever it goes past,
+ * easing in over the knee. A peak too sudden for the attack is rounded off by a curve that
+ * starts at the threshold and flattens out at full scale, so the output never clips.
+ *
+ * fields:
+ *     - threshold (f32): The level the envelope is held to, as a linear level.
+ *     - knee_db (f32): Width of the soft knee, centred on the threshold, in dB.
+ *     - attack (f32): How fast the envelope rises (see `smoothing_coefficient`).
+ *     - release (f32): How fast the envelope falls.
+ *     - envelope (f32): The current peak level.
+ */
+pub struct Limiter {
+    threshold: f32,
+    knee_db: f32,
+    attack: f32,
+    release: f32,
+    envelope: f32,
+}
+
+impl Limiter {
+    /* new - Creates a limiter at rest.
+     *
+     * inputs:
+     *     - threshold_db (f32): The highest level the limiter lets through, in dBFS (below 0).
+     *     - knee_db (f32): Width of the soft knee, in dB.
+     *     - attack_seconds (f32): How fast gain reduction sets in.
+     *     - release_seconds (f32): How fast it lets go once the peak has passed.
+     *     - sample_rate (f32): Samples per second.
+     *
+     * outputs:
+     *     - Limiter: The limiter, not reducing the gain yet.
+     */
+    pub fn new(threshold_db: f32, knee_db: f32, attack_seconds: f32, release_seconds: f32, sample_rate: f32) -> Limiter {
+        Limiter {
+            threshold: from_db(threshold_db.min(0.0)),
+            knee_db: knee_db.max(0.0),
+            attack: smoothing_coefficient(attack_seconds, sample_rate),
+            release: smoothing_coefficient(release_seconds, sample_rate),
+            envelope: 0.0,
+        }
+    }
+
+    /* gain_db - Returns the gain reduction for an envelope level.
+     *
+     * inputs:
+     *     - &self
+     *     - level_db (f32): The envelope, in dBFS.
+     *
+     * outputs:
+     *     - f32: The gain in dB: 0.0 below the knee, bringing the level back to the threshold
+     *       above it, and a smooth curve between the two.
+     */
+    fn gain_db(&self, level_db: f32) -> f32 {
+        let over = level_db - to_db(self.threshold);
+        if over <= -self.knee_db / 2.0 {
+            0.0
+        } else if over >= self.knee_db / 2.0 {
+            -over
+        } else {
+            let into_knee = over + self.knee_db / 2.0;
+            -into_knee * into_knee / (2.0 * self.knee_db)
+        }
+    }
+
+    /* process - Limits the next sample of the mix.
+     *
+     * inputs:
+     *     - &mut self
+     *     - sample (f32): The next sample, at any level.
+     *
+     * outputs:
+     *     - f32: The limited sample, never past full scale (-1.0 to 1.0).
+     */
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let level = sample.abs();
+        let speed = if level > self.envelope { self.attack } else { self.release };
+        self.envelope += (level - self.envelope) * speed;
+        let limited = sample * from_db(self.gain_db(to_db(self.envelope)));
+        // What the envelope hasn't caught yet bends toward full scale instead of passing it
+        let over = limited.abs() - self.threshold;
+        if over <= 0.0 {
+            return limited;
+        }
+        let room = 1.0 - self.threshold;
+        (self.threshold + room * (over / room).tanh()).copysign(limited)
+    }
+}
+
+// LEVELER_GATE_SECONDS: Time constant of the short loudness that tells the leveler the mix
+// has gone quiet, quick enough to catch a rest within a beat.
+const LEVELER_GATE_SECONDS: f32 = 0.05;
+
+/* Leveler - A slow gain that brings the mix toward a target loudness.
+ *
+ * The loudness is the mix's mean square, averaged with a long time constant. The gain moves
+ * toward the one that would bring it to the target, no faster than its slew rate and no
+ * further than its range. While the mix is close to silence (by a short average, see
+ * `LEVELER_GATE_SECONDS`) both the loudness and the gain hold still, so rests and quiet
+ * passages aren't pumped up and the music after a rest comes back at the level it left.
+ *
+ * fields:
+ *     - target_db (f32): The loudness aimed for, as an RMS level in dBFS.
+ *     - max_db (f32): How far the gain may go either way from 0 dB.
+ *     - gate_db (f32): Below this short-term loudness the loudness and gain hold still.
+ *     - averaging (f32): How fast the loudness follows the mix (see `smoothing_coefficient`).
+ *     - slew_db (f32): The most the gain moves per sample, in dB.
+ *     - gate_averaging (f32): How fast the short-term loudness follows the mix.
+ *     - mean_square (f32): The mix's current loudness, as a mean square.
+ *     - short_square (f32): The mix's short-term loudness, as a mean square.
+ *     - gain_db (f32): The current gain, in dB.
+ */
+pub struct Leveler {
+    target_db: f32,
+    max_db: f32,
+    gate_db: f32,
+    averaging: f32,
+    slew_db: f32,
+    gate_averaging: f32,
+    mean_square: f32,
+    short_square: f32,
+    gain_db: f32,
+}
+
+impl Leveler {
+    /* new - Creates a leveler at 0 dB.
+     *
+     * inputs:
+     *     - target_db (f32): The RMS level aimed for, in dBFS.
+     *     - max_db (f32): How far the gain may go either way, in dB.
+     *     - gate_db (f32): The short-term loudness below which the loudness and gain hold still, in dBFS.
+     *     - averaging_seconds (f32): Time constant of the loudness measurement.
+     *     - slew_db_per_second (f32): The fastest the gain moves.
+     *     - sample_rate (f32): Samples per second.
+     *
+     * outputs:
+     *     - Leveler: The leveler, taking the mix to be at the target until it has heard some.
+     */
+    pub fn new(
+        target_db: f32,
+        max_db: f32,
+        gate_db: f32,
+        averaging_seconds: f32,
+        slew_db_per_second: f32,
+        sample_rate: f32,
+    ) -> Leveler {
+        Leveler {
+            target_db,
+            max_db: max_db.max(0.0),
+            gate_db,
+            averaging: smoothing_coefficient(averaging_seconds, sample_rate),
+            slew_db: slew_db_per_second.max(0.0) / sample_rate,
+            gate_averaging: smoothing_coefficient(LEVELER_GATE_SECONDS, sample_rate),
+            mean_square: from_db(target_db).powi(2),
+            short_square: 0.0,
+            gain_db: 0.0,
+        }
+    }
+
+    /* process - Levels the next sample of the mix.
+     *
+     * inputs:
+     *     - &mut self
+     *     - sample (f32): The next sample.
+     *
+     * outputs:
+     *     - f32: The sample with the current gain applied; it may go past full scale, so a
+     *       `Limiter` should follow.
+     */
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.short_square += (sample * sample - self.short_square) * self.gate_averaging;
+        if 10.0 * self.short_square.max(1e-18).log10() > self.gate_db {
+            self.mean_square += (sample * sample - self.mean_square) * self.averaging;
+            let loudness_db = 10.0 * self.mean_square.max(1e-18).log10();
+            let wanted = (self.target_db - loudness_db).clamp(-self.max_db, self.max_db);
+            self.gain_db += (wanted - self.gain_db).clamp(-self.slew_db, self.slew_db);
+        }
+        sample * from_db(self.gain_db)
+    }
+}
+
+// LOUDNESS_BLOCK_SECONDS: Length of the blocks `integrated_loudness` measures, overlapping by 75%.
+const LOUDNESS_BLOCK_SECONDS: f32 = 0.4;
+// LOUDNESS_ABSOLUTE_GATE_DB: Blocks quieter than this are silence and never counted.
+const LOUDNESS_ABSOLUTE_GATE_DB: f32 = -70.0;
+// LOUDNESS_RELATIVE_GATE_DB: Blocks this far below the song's average are left out as well.
+const LOUDNESS_RELATIVE_GATE_DB: f32 = 10.0;
+
+/* integrated_loudness - Measures how loud a whole track sounds.
+ *
+ * Like LUFS, without the frequency weighting: the track is split into overlapping blocks,
+ * silent blocks and blocks well below the average are left out, and the rest are averaged,
+ * so rests, fades and quiet intros don't make a song read quieter than it sounds. Only the
+ * running sums at block edges are kept, so a long track is measured in little memory.
+ *
+ * inputs:
+ *     - samples (impl IntoIterator<Item = f32>): The track.
+ *     - sample_rate (f32): Samples per second.
+ *
+ * outputs:
+ *     - Option<f32>: The loudness as an RMS level in dBFS, or None if the track is shorter than
+ *       one block or silent.
+ */
+pub fn integrated_loudness(samples: impl IntoIterator<Item = f32>, sample_rate: f32) -> Option<f32> {
+    let block_len = (LOUDNESS_BLOCK_SECONDS * sample_rate) as usize;
+    if block_len == 0 {
+        return None;
+    }
+    let hop = (block_len / 4).max(1);
+    // The running sum of squares where each block starts and ends, so every block's mean
+    // square is one subtraction
+    let (mut starts, mut ends) = (Vec::new(), Vec::new());
+    let mut sum = 0.0f64;
+    let mut edge = |position: usize, sum: f64| {
+        if position.is_multiple_of(hop) {
+            starts.push(sum);
+        }
+        if position >= block_len && (position - block_len).is_multiple_of(hop) {
+            ends.push(sum);
+        }
+    };
+    let mut len = 0;
+    for sample in samples {
+        edge(len, sum);
+        sum += (sample as f64) * (sample as f64);
+        len += 1;
+    }
+    edge(len, sum);
+    let power_db = |mean_square: f64| 10.0 * mean_square.max(1e-18).log10() as f32;
+    let blocks: Vec<f64> = ends
+        .iter()
+        .zip(&starts)
+        .map(|(end, start)| (end - start) / block_len as f64)
+        .filter(|&mean_square| power_db(mean_square) > LOUDNESS_ABSOLUTE_GATE_DB)
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+    let average = blocks.iter().sum::<f64>() / blocks.len() as f64;
+    let gate_db = power_db(average) - LOUDNESS_RELATIVE_GATE_DB;
+    let loud: Vec<f64> = blocks.into_iter().filter(|&mean_square| power_db(mean_square) > gate_db).collect();
+    // The average itself is always above the relative gate, so some block is too
+    Some(power_db(loud.iter().sum::<f64>() / loud.len() as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SAMPLE_RATE: Sample rate of the test signals.
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    fn sine(frequency: f32, seconds: f32, level: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE) as usize)
+            .map(|i| level * (std::f32::consts::TAU * frequency * i as f32 / SAMPLE_RATE).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    // The gain in dB a low-pass at `cutoff_hz` gives a sine, once it has settled
+    fn low_pass_gain_db(cutoff_hz: f32, frequency: f32) -> f32 {
+        let input = sine(frequency, 0.5, 0.5);
+        let mut filter = LowPass::new(cutoff_hz, SAMPLE_RATE);
+        let output: Vec<f32> = input.iter().map(|&sample| filter.process(sample)).collect();
+        let settled = input.len() / 2;
+        to_db(rms(&output[settled..]) / rms(&input[settled..]))
+    }
+
+    #[test]
+    fn low_pass_passes_the_lows_and_falls_away_by_6_db_an_octave() {
+        let cutoff = 1000.0;
+        // (frequency, expected gain in dB, tolerance)
+        let cases = [
+            (62.5, 0.0, 0.1),
+            (250.0, -0.26, 0.2),
+            (1000.0, -3.0, 0.3),
+            (2000.0, -7.0, 0.6),
+            (4000.0, -12.3, 0.8),
+            (8000.0, -18.0, 1.2),
+        ];
+        let mut previous = f32::INFINITY;
+        for (frequency, expected, tolerance) in cases {
+            let gain = low_pass_gain_db(cutoff, frequency);
+            assert!((gain - expected).abs() < tolerance, "{} Hz through {} Hz: {} dB, expected {}", frequency, cutoff, gain, expected);
+            assert!(gain < previous, "{} Hz is louder than the octave below", frequency);
+            previous = gain;
+        }
+    }
+
+    #[test]
+    fn low_pass_response_follows_the_cutoff() {
+        // Brighter cutoffs let the same tone through louder
+        let gains: Vec<f32> = [500.0, 2000.0, 8000.0].iter().map(|&cutoff| low_pass_gain_db(cutoff, 3000.0)).collect();
+        assert!(gains.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", gains);
+        // At or above half the sample rate nothing is filtered
+        let input = sine(5000.0, 0.1, 0.8);
+        let mut open = LowPass::new(SAMPLE_RATE / 2.0, SAMPLE_RATE);
+        assert!(input.iter().all(|&sample| (open.process(sample) - sample).abs() < 1e-6));
+    }
+
+    #[test]
+    fn low_pass_never_overshoots_and_sweeps_without_jumps() {
+        let mut filter = LowPass::new(300.0, SAMPLE_RATE);
+        // A square wave's steps settle toward each edge without passing it
+        for i in 0..20_000 {
+            let input = if (i / 500) % 2 == 0 { 0.7 } else { -0.7 };
+            let output = filter.process(input);
+            assert!(output.abs() <= 0.7 + 1e-6, "{} overshoots", output);
+        }
+        // Moving the cutoff keeps what the filter holds
+        let before = filter.process(0.7);
+        filter.set_cutoff(6000.0);
+        let after = filter.process(0.7);
+        assert!(after >= before && after <= 0.7, "{} then {}", before, after);
+    }
+
+    // The master bus's limiter: held to -1 dBFS with a 4 dB knee, 5 ms attack and 100 ms release
+    fn master_limiter() -> Limiter {
+        Limiter::new(-1.0, 4.0, 0.005, 0.1, SAMPLE_RATE)
+    }
+
+    // The master bus's leveler: toward -16 dBFS RMS by up to 6 dB, gated at -40 dBFS
+    fn master_leveler() -> Leveler {
+        Leveler::new(-16.0, 6.0, -40.0, 3.0, 1.0, SAMPLE_RATE)
+    }
+
+    // A sine whose RMS level is `rms_db`
+    fn sine_at_rms(frequency: f32, seconds: f32, rms_db: f32) -> Vec<f32> {
+        sine(frequency, seconds, from_db(rms_db) * std::f32::consts::SQRT_2)
+    }
+
+    #[test]
+    fn limiter_never_clips_and_holds_loud_peaks_near_its_threshold() {
+        for level in [1.0, 2.0, 4.0, 10.0] {
+            let mut limiter = master_limiter();
+            // A sudden start at full level gives the attack nothing to go on
+            let output: Vec<f32> = sine(220.0, 1.0, level).iter().map(|&sample| limiter.process(sample)).collect();
+            let peak = output.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(peak <= 1.0, "{}x full scale peaks at {}", level, peak);
+            let settled = output[output.len() / 2..].iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(to_db(settled) > -3.0 && settled <= 1.0, "{}x full scale settles at {} dBFS", level, to_db(settled));
+        }
+        // Below the knee nothing changes
+        let mut limiter = master_limiter();
+        let quiet = sine(440.0, 0.5, from_db(-6.0));
+        assert!(quiet.iter().all(|&sample| (limiter.process(sample) - sample).abs() < 1e-6));
+    }
+
+    #[test]
+    fn one_huge_transient_leaves_the_rest_of_the_track_alone() {
+        let mut track = sine_at_rms(330.0, 4.0, -18.0);
+        let clean = track.clone();
+        let transient = SAMPLE_RATE as usize;
+        for sample in &mut track[transient..transient + 20] {
+            *sample = 4.0;
+        }
+        let master = |track: &[f32]| -> Vec<f32> {
+            let (mut leveler, mut limiter) = (master_leveler(), master_limiter());
+            track.iter().map(|&sample| limiter.process(leveler.process(sample))).collect()
+        };
+        let (hit, unhit) = (master(&track), master(&clean));
+        assert!(hit.iter().all(|sample| sample.abs() <= 1.0), "the transient clips");
+        // Half a second on, once the limiter has let go, only the leveler's slow loudness
+        // measurement still hears the transient, by a fraction of a dB
+        let after = transient + SAMPLE_RATE as usize / 2;
+        let difference = to_db(rms(&hit[after..])) - to_db(rms(&unhit[after..]));
+        assert!(difference.abs() < 0.5, "the track after the transient is {} dB off", difference);
+        // Scaling the whole track down by its peak, as before, would have cost it 12 dB
+        let peak = track.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(to_db(peak) > 12.0 - 0.1);
+    }
+
+    #[test]
+    fn leveler_brings_songs_toward_the_target_within_its_range() {
+        // (input RMS in dBFS, gain it settles at)
+        let cases = [(-20.0, 4.0), (-13.0, -3.0), (-28.0, 6.0), (-6.0, -6.0), (-50.0, 0.0)];
+        for (input_db, expected_gain) in cases {
+            let mut leveler = master_leveler();
+            let input = sine_at_rms(200.0, 20.0, input_db);
+            let output: Vec<f32> = input.iter().map(|&sample| leveler.process(sample)).collect();
+            assert!((leveler.gain_db - expected_gain).abs() < 0.3, "{} dBFS settles at {} dB", input_db, leveler.gain_db);
+            let last_second = output.len() - SAMPLE_RATE as usize;
+            let output_db = to_db(rms(&output[last_second..]));
+            assert!((output_db - (input_db + expected_gain)).abs() < 0.3, "{} dBFS comes out at {}", input_db, output_db);
+        }
+        // However far off, the gain moves no faster than its slew rate
+        let mut leveler = master_leveler();
+        for sample in sine_at_rms(200.0, 1.0, -30.0) {
+            leveler.process(sample);
+        }
+        assert!(leveler.gain_db <= 1.0 + 1e-3, "{} dB in a second", leveler.gain_db);
+        // A rest near silence leaves the gain where the music left it
+        let mut leveler = master_leveler();
+        for sample in sine_at_rms(200.0, 20.0, -20.0) {
+            leveler.process(sample);
+        }
+        let gain = leveler.gain_db;
+        for sample in sine_at_rms(200.0, 20.0, -60.0) {
+            leveler.process(sample);
+        }
+        assert!((leveler.gain_db - gain).abs() < 0.5, "a rest moved the gain from {} to {} dB", gain, leveler.gain_db);
+    }
+}
@@ -1,42 +1,302 @@
+use crate::controller;
+use crate::drums;
+use crate::dsp::{self, Leveler, Limiter, LowPass};
 use crate::melodies;
 use crate::progs;
-use crate::tui::AppState;
+use crate::share;
+use crate::synth::{self, PulseWidth, Waveform};
+use crate::tui::{AppState, DEFAULT_TRACK_GAIN_PERCENT};
+use crate::workers::{self, TaskHandle};
 use crossbeam_channel::{Receiver as CrossbeamReceiver, Sender as CrossbeamSender};
-use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
-use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
-use std::thread;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+#[cfg(test)]
+use rodio::queue::SourcesQueueOutput;
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-const SAMPLE_RATE: u32 = 44100; // Audio sample rate in Hz
+pub const SAMPLE_RATE: u32 = 44100; // Audio sample rate in Hz
 
-/* play_progression - Generates an audio sequence for a musical chord progression.
+// Track gains used at the default 50% mixer setting.
+const DEFAULT_MELODY_GAIN: f32 = 0.125;
+const DEFAULT_CHORD_GAIN: f32 = 0.5;
+const DEFAULT_BASS_GAIN: f32 = 0.6;
+
+// DRUM_GAIN: Gain of the drum track, which has no mixer level of its own, only an on/off toggle.
+const DRUM_GAIN: f32 = 0.35;
+
+/* track_gain - Converts a mixer percentage into a linear track gain.
+ *
+ * 50% reproduces the track's default gain and 100% doubles it, so the default mixer
+ * settings keep existing song IDs sounding the same. Invalid input falls back to the default.
+ *
+ * inputs:
+ *     - percent (&str): The mixer level in percent (0-100).
+ *     - default_gain (f32): The gain the track uses at 50%.
+ *
+ * outputs:
+ *     - f32: The linear gain to apply to the track.
+ */
+fn track_gain(percent: &str, default_gain: f32) -> f32 {
+    match percent.trim().parse::<f32>() {
+        Ok(percent) => percent.clamp(0.0, 100.0) / 50.0 * default_gain,
+        Err(_) => default_gain,
+    }
+}
+
+/* soft_clip - Distorts a track with a tanh waveshaper, keeping its peak level.
+ *
+ * The curve is scaled so that a sample at `peak` stays at `peak`, and nothing goes past it,
+ * so the track sits at the same level in the mix.
+ *
+ * inputs:
+ *     - samples (&mut [f32]): The track to distort in place.
+ *     - drive (f32): How hard the track is pushed into the curve (higher = more distortion).
+ *     - peak (f32): The track's peak amplitude.
+ *
+ * outputs:
+ *     - None
+ */
+fn soft_clip(samples: &mut [f32], drive: f32, peak: f32) {
+    let scale = peak / drive.tanh();
+    for sample in samples {
+        *sample = scale * (drive * *sample / peak).tanh();
+    }
+}
+
+/* EchoDelay - A feedback delay line, fed one stretch of a track after another.
+ *
+ * The line carries over between calls to `process`, so echoes ring on from one section of a
+ * song into the next, and a render cut short is a prefix of the full one.
+ *
+ * fields:
+ *     - line (Vec<f32>): The last delay time of echoes, as a ring buffer.
+ *     - position (usize): The ring buffer slot read and written next.
+ *     - feedback (f32): How much of each echo comes back in the next.
+ *     - wet (f32): Level of the echoes under the dry track.
+ */
+struct EchoDelay {
+    line: Vec<f32>,
+    position: usize,
+    feedback: f32,
+    wet: f32,
+}
+
+impl EchoDelay {
+    /* new - Creates a silent delay line.
+     *
+     * inputs:
+     *     - delay_samples (usize): Time between echoes, in samples (at least 1).
+     *     - feedback (f32): How much of each echo comes back in the next.
+     *     - wet (f32): Level of the echoes under the dry track.
+     *
+     * outputs:
+     *     - EchoDelay: The delay line.
+     */
+    fn new(delay_samples: usize, feedback: f32, wet: f32) -> EchoDelay {
+        EchoDelay {
+            line: vec![0.0; delay_samples.max(1)],
+            position: 0,
+            feedback,
+            wet,
+        }
+    }
+
+    /* process - Adds the echoes to the next stretch of the track, in place.
+     *
+     * inputs:
+     *     - &mut self
+     *     - samples (&mut [f32]): The track, continuing from the end of the last stretch.
+     *
+     * outputs:
+     *     - None
+     */
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let echo = self.line[self.position];
+            self.line[self.position] = *sample + echo * self.feedback;
+            *sample += echo * self.wet;
+            self.position = (self.position + 1) % self.line.len();
+        }
+    }
+}
+
+/* LoFi - A bit crusher and downsampler, fed the mix one sample after another.
+ *
+ * fields:
+ *     - steps (f32): Quantization steps either side of zero; a sample is rounded to the
+ *       nearest multiple of 1 / steps.
+ *     - hold_samples (usize): Each crushed sample is held for this many samples, lowering the
+ *       effective sample rate.
+ *     - held (f32): The sample being held.
+ *     - position (usize): Samples processed so far.
+ */
+struct LoFi {
+    steps: f32,
+    hold_samples: usize,
+    held: f32,
+    position: usize,
+}
+
+impl LoFi {
+    /* new - Creates a lo-fi stage.
+     *
+     * inputs:
+     *     - bits (u32): Bit depth to quantize to, clamped to `LOFI_BITS`.
+     *     - sample_rate (u32): Effective sample rate, e.g. 11025 for a quarter of `SAMPLE_RATE`.
+     *
+     * outputs:
+     *     - LoFi: The stage, holding silence.
+     */
+    fn new(bits: u32, sample_rate: u32) -> LoFi {
+        let bits = bits.clamp(*LOFI_BITS.start(), *LOFI_BITS.end());
+        LoFi {
+            steps: (1u32 << (bits - 1)) as f32,
+            hold_samples: (SAMPLE_RATE / sample_rate.clamp(1, SAMPLE_RATE)) as usize,
+            held: 0.0,
+            position: 0,
+        }
+    }
+
+    /* process - Crushes the next sample of the mix.
+     *
+     * inputs:
+     *     - &mut self
+     *     - sample (f32): The next sample, from -1.0 to 1.0.
+     *
+     * outputs:
+     *     - f32: The quantized sample, or the one still being held.
+     */
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.position.is_multiple_of(self.hold_samples) {
+            self.held = (sample.clamp(-1.0, 1.0) * self.steps).round() / self.steps;
+        }
+        self.position += 1;
+        self.held
+    }
+}
+
+/* ChordBuffer - One rendered chord, ready to be placed on the chord timeline.
+ *
+ * fields:
+ *     - samples (Vec<f32>): The chord's audio, one chord long (the closing tonic may be longer).
+ *     - root (u8): The MIDI-like root note of the chord, for the bass.
+ *     - label (String): The chord's display name.
+ *     - tones (progs::ChordTones): The chord's pitch classes, for the counter-melody.
+ */
+#[derive(Debug, Clone)]
+struct ChordBuffer {
+    samples: Vec<f32>,
+    root: u8,
+    label: String,
+    tones: progs::ChordTones,
+}
+
+/* chord_buffers - Pairs up the rendered chords with their roots, labels and tones.
+ *
+ * inputs:
+ *     - (samples, roots, labels, tones): The chords, as `progs::get_progression` and
+ *       `progs::get_cadence` return them.
+ *
+ * outputs:
+ *     - Vec<ChordBuffer>: One buffer per chord, in order.
+ */
+fn chord_buffers(
+    (samples, roots, labels, tones): (Vec<Vec<f32>>, Vec<u8>, Vec<String>, Vec<progs::ChordTones>),
+) -> Vec<ChordBuffer> {
+    samples
+        .into_iter()
+        .zip(roots)
+        .zip(labels)
+        .zip(tones)
+        .map(|(((samples, root), label), tones)| ChordBuffer { samples, root, label, tones })
+        .collect()
+}
+
+/* play_progression - Renders each chord of a musical chord progression.
  *
  * Given a progression name (e.g., "blues", "pop"), a root note, and duration for each chord,
- * this function retrieves the chord voicings and their root notes. It then concatenates
- * the audio samples of these chords to form a continuous audio sequence.
+ * this function retrieves the chord voicings and their root notes, one buffer per chord, so
+ * the chord timeline can place them in any order.
  *
  * inputs:
  *     - prog_name (String): The name of the chord progression to use.
  *     - root_note (u8): The MIDI root note for the first chord of the progression.
+ *     - minor (bool): True for a minor key, false for a major one.
  *     - chord_duration (f32): The duration in seconds for each chord in the progression.
+ *     - envelope (progs::ChordEnvelope): Fade-in and fade-out applied to every chord.
+ *     - pattern (progs::ChordPattern): Whether each chord's notes sound together or are broken up.
+ *     - custom (&[progs::RomanChord]): A custom progression to play instead; empty for none.
+ *     - waveform (Waveform): The tone of the chords.
  *
  * outputs:
- *     - (Vec<f32>, Vec<u8>): A tuple containing:
- *         - Vec<f32>: The concatenated audio samples of the chord progression.
- *         - Vec<u8>: A list of the root notes for each chord in the generated progression.
+ *     - Vec<ChordBuffer>: The chords of the progression, in order.
  */
-fn play_progression(prog_name: String, root_note: u8, chord_duration: f32) -> (Vec<f32>, Vec<u8>) {
-    let (progression_chords, progression_root_notes) =
-        progs::get_progression(prog_name, root_note, chord_duration);
-
+#[allow(clippy::too_many_arguments)]
+fn play_progression(
+    prog_name: String,
+    root_note: u8,
+    minor: bool,
+    chord_duration: f32,
+    envelope: progs::ChordEnvelope,
+    pattern: progs::ChordPattern,
+    custom: &[progs::RomanChord],
+    waveform: Waveform,
+) -> Vec<ChordBuffer> {
+    chord_buffers(progs::get_progression(
+        prog_name,
+        root_note,
+        minor,
+        chord_duration,
+        envelope,
+        pattern,
+        waveform,
+        custom,
+    ))
+}
 
-    let mut audio_sequence = Vec::new();
-    for chord in progression_chords {
-        audio_sequence.extend_from_slice(&chord);
-    }
+/* ChordSlot - Which chord plays in one chord-length slot of the chord timeline.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChordSlot {
+    A(usize),   // The nth chord of the A progression, wrapping around.
+    B(usize),   // The nth chord of the B progression, wrapping around.
+    Turnaround, // The chord that turns the B progression back to the A one.
+}
 
+/* KeyChords - The chords a song plays in one key.
+ *
+ * fields:
+ *     - a (Vec<ChordBuffer>): The A progression, the one the song opens with.
+ *     - b (Vec<ChordBuffer>): The B progression it alternates with.
+ *     - turnaround (ChordBuffer): The chord that leads from B back to A.
+ */
+struct KeyChords {
+    a: Vec<ChordBuffer>,
+    b: Vec<ChordBuffer>,
+    turnaround: ChordBuffer,
+}
 
-    (audio_sequence, progression_root_notes)
+impl KeyChords {
+    /* chord - Returns the chord that plays in a slot of the chord timeline.
+     *
+     * inputs:
+     *     - self (&Self)
+     *     - slot (ChordSlot): The slot.
+     *
+     * outputs:
+     *     - &ChordBuffer: The chord; slot indices wrap around the progression, so a timeline
+     *       planned for one key also fits another key's progressions.
+     */
+    fn chord(&self, slot: ChordSlot) -> &ChordBuffer {
+        match slot {
+            ChordSlot::A(index) => &self.a[index % self.a.len()],
+            ChordSlot::B(index) => &self.b[index % self.b.len()],
+            ChordSlot::Turnaround => &self.turnaround,
+        }
+    }
 }
 
 /* note_to_freq - Converts a MIDI-like note number to its corresponding frequency in Hertz.
@@ -55,6 +315,447 @@ fn note_to_freq(note: u8) -> f32 {
     440.0 * (2.0f32).powf((note as f32 - 57.0) / 12.0) // MIDI A4 = 57 (0-indexed)
 }
 
+// BASS_FILL_SEED_SALT: Mixed into the song seed so fill decisions use their own random stream.
+const BASS_FILL_SEED_SALT: u64 = 0x6261_7373_5f66_696c;
+const BASS_NOTE_ATTACK_SECONDS: f32 = 0.005; // Attack of articulated bass notes
+const BASS_NOTE_RELEASE_SECONDS: f32 = 0.03; // Release of articulated bass notes
+const BASS_NOTE_DECLICK_SECONDS: f32 = 0.005; // Fade in and out of held bass notes, so chord changes don't click
+// ELECTRONIC_BASS_LEVEL / ELECTRONIC_KICK_LEVEL: Peak levels of the electronic style's square
+// bass and kick; together they stay within the sine bass's 0.6 (`BASS_TRACK_PEAK`).
+const ELECTRONIC_BASS_LEVEL: f32 = 0.3;
+const ELECTRONIC_KICK_LEVEL: f32 = 0.3;
+// ELECTRONIC_KICK_SECONDS: Length of the low sine thump marking each electronic beat.
+const ELECTRONIC_KICK_SECONDS: f32 = 0.12;
+// ELECTRONIC_KICK_START_HZ / ELECTRONIC_KICK_END_HZ: The thump's pitch drop, from its attack to its tail.
+const ELECTRONIC_KICK_START_HZ: f32 = 150.0;
+const ELECTRONIC_KICK_END_HZ: f32 = 45.0;
+// REGGAE_BASS_FIGURE: The reggae bass figure, repeated every bar from each chord change: (eighth
+// note it starts on, semitones above the root, length in eighth notes). Written for a 4/4 bar;
+// shorter bars leave out or cut what doesn't fit.
+const REGGAE_BASS_FIGURE: [(usize, i32, usize); 3] = [(0, 0, 2), (3, 7, 1), (6, 0, 1)];
+// CLASSICAL_BASS_FIGURE / FOLK_BASS_FIGURE / POP_BASS_FIGURE: Bass figures repeated every group
+// of beats (see `TimeSignature::beats_per_group`): (beat it starts on, semitones above the root,
+// length in beats). Classical plays the downbeat only, folk alternates root and fifth on the
+// first two beats, and pop holds the root from beat 1 and the fifth from beat 3.
+const CLASSICAL_BASS_FIGURE: [(usize, i32, usize); 1] = [(0, 0, 1)];
+const FOLK_BASS_FIGURE: [(usize, i32, usize); 2] = [(0, 0, 1), (1, 7, 1)];
+const POP_BASS_FIGURE: [(usize, i32, usize); 2] = [(0, 0, 2), (2, 7, 2)];
+// METAL_SIXTEENTHS_MAX_BPM: Up to this tempo the metal bass runs in sixteenth notes; faster
+// songs get eighths.
+const METAL_SIXTEENTHS_MAX_BPM: u32 = 140;
+// AMBIENT_BASS_FADE_SECONDS: Fade-in and fade-out of the ambient style's sustained bass notes.
+const AMBIENT_BASS_FADE_SECONDS: f32 = 0.5;
+// WALKING_BASS_LOWEST / WALKING_BASS_HIGHEST: The register of the jazz walking bass, from a low G
+// up to the highest chord root the bass plays.
+const WALKING_BASS_LOWEST: i32 = 19;
+const WALKING_BASS_HIGHEST: i32 = 46;
+// WALKING_BASS_FIFTH_CHANCE: Chance that the second beat of a chord leaps to the root's fifth
+// instead of stepping toward the next chord.
+const WALKING_BASS_FIFTH_CHANCE: f32 = 0.35;
+// WALKING_BASS_APPROACHES: Where the last beat of a chord lands relative to the next root:
+// a half step below or above, or a whole step above.
+const WALKING_BASS_APPROACHES: [i32; 3] = [-1, 1, 2];
+
+/* walking_bass_notes - Plans the quarter notes a walking bass plays over one chord.
+ *
+ * The first beat is the chord's root. The last beat approaches the next chord's root (in
+ * whichever octave is nearer, within the register) from a half step below or above or a
+ * whole step above, and the beats between step toward it by half or whole steps, the second
+ * sometimes leaping to the fifth instead. Every note stays within `WALKING_BASS_LOWEST` and
+ * `WALKING_BASS_HIGHEST`, and no note repeats the one before it.
+ *
+ * inputs:
+ *     - root (i32): The bass note of the chord's root.
+ *     - next_root (i32): The bass note of the next chord's root.
+ *     - beats (usize): The number of beats the chord lasts.
+ *     - rng (&mut StdRng): The random stream that picks the path.
+ *
+ * outputs:
+ *     - Vec<i32>: One note per beat, `beats` of them (at least the root).
+ */
+fn walking_bass_notes(root: i32, next_root: i32, beats: usize, rng: &mut StdRng) -> Vec<i32> {
+    let register = WALKING_BASS_LOWEST..=WALKING_BASS_HIGHEST;
+    let mut notes = vec![root];
+    if beats < 2 {
+        return notes;
+    }
+    let target = register
+        .clone()
+        .filter(|note| (note - next_root).rem_euclid(12) == 0)
+        .min_by_key(|note| (note - root).abs())
+        .unwrap_or(next_root);
+    let approach_choice = rng.gen_range(0..WALKING_BASS_APPROACHES.len());
+    let approach_with = |choice: usize| {
+        let offset = WALKING_BASS_APPROACHES[choice % WALKING_BASS_APPROACHES.len()];
+        (target + offset).clamp(WALKING_BASS_LOWEST, WALKING_BASS_HIGHEST)
+    };
+    let approach = approach_with(approach_choice);
+    for beat in 1..beats - 1 {
+        let previous = notes[beat - 1];
+        let fifth = [root + 7, root - 5].into_iter().find(|note| register.contains(note));
+        let mut note = match fifth {
+            Some(fifth) if beat == 1 && rng.gen::<f32>() < WALKING_BASS_FIFTH_CHANCE => fifth,
+            _ => {
+                // A half or whole step toward the approach note, away from it once there
+                let step = rng.gen_range(1..=2);
+                if approach >= previous { previous + step } else { previous - step }
+            }
+        };
+        note = note.clamp(WALKING_BASS_LOWEST, WALKING_BASS_HIGHEST);
+        if note == previous {
+            note = if previous > WALKING_BASS_LOWEST { previous - 1 } else { previous + 1 };
+        }
+        notes.push(note);
+    }
+    // If the walk already sits on the approach note, come in from another side
+    let last = notes[beats - 2];
+    let approach = (approach_choice..approach_choice + WALKING_BASS_APPROACHES.len())
+        .map(approach_with)
+        .find(|&note| note != last && note != target)
+        .unwrap_or(approach);
+    notes.push(approach);
+    notes
+}
+
+/* bass_fill_probability - Returns how often the bass plays an approach fill into a chord change.
+ *
+ * inputs:
+ *     - style (&str): The musical style (case-insensitive).
+ *
+ * outputs:
+ *     - (f32, f32): The chance of a fill per eligible chord change, and the chance that
+ *                   a fill is chromatic from below (otherwise it steps down from above).
+ */
+fn bass_fill_probability(style: &str) -> (f32, f32) {
+    match style.to_lowercase().as_str() {
+        "jazz" => (0.6, 0.7),
+        "blues" => (0.45, 0.6),
+        "pop" => (0.25, 0.3),
+        "electronic" => (0.0, 0.0), // The octave pattern never breaks for a fill
+        "ambient" => (0.0, 0.0),    // One sustained note per chord
+        "classical" => (0.0, 0.0),  // Only the downbeat is played
+        "folk" => (0.0, 0.0),       // The root and fifth of the waltz, every bar
+        "metal" => (0.0, 0.0),      // Relentless notes on the root
+        "reggae" => (0.0, 0.0),     // The figure's gaps are part of the groove
+        _ => (0.2, 0.5),
+    }
+}
+
+/* BassVoice - How the bass line sounds.
+ *
+ * fields:
+ *     - waveform (Waveform): The tone of every note.
+ *     - envelope (bool): Whether notes get the style's attack, release and fades (and the
+ *       electronic style its kick). Without it, as on the NES triangle channel, which has no
+ *       volume control, every note plays at full level and is only declicked at its edges.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BassVoice {
+    pub waveform: Waveform,
+    pub envelope: bool,
+}
+
+/* bass_wave - Returns one sample of a bass note.
+ *
+ * The bass is the one voice that stays off `synth::Oscillator`. An oscillator adds up its
+ * phase a step at a time, and the rounding in that sum lands some square, saw and noise
+ * edges a sample early or late: 52 square, 2984 saw and 140 noise samples in 8 s of each
+ * style's bass moved by a whole edge when it was tried. Every v2 song ID would then play a
+ * slightly different bass, so the phase is still worked out from the sample's index, as it
+ * always has been. A sine or triangle comes out the same either way, to within 6e-8. Moving
+ * the bass onto the oscillator means raising `SONG_ID_VERSION` and keeping this for older IDs.
+ *
+ * inputs:
+ *     - waveform (Waveform): The tone of the note.
+ *     - frequency (f32): The pitch, in Hz.
+ *     - index (usize): The sample, counted from the start of the note.
+ *
+ * outputs:
+ *     - f32: The sample, from -1.0 to 1.0.
+ */
+fn bass_wave(waveform: Waveform, frequency: f32, index: usize) -> f32 {
+    synth::wave(waveform, index as f64 * frequency as f64 / SAMPLE_RATE as f64)
+}
+
+/* push_bass_note - Appends one bass note to a buffer.
+ *
+ * When `articulate` is set, a short attack and release envelope is applied so the note
+ * is heard as a separate event instead of blending into its neighbors. A held note only
+ * fades in and out over `BASS_NOTE_DECLICK_SECONDS`, so it starts and ends near silence
+ * and the wave restarting at the next note doesn't click.
+ *
+ * inputs:
+ *     - bass_line (&mut Vec<f32>): The buffer to append to.
+ *     - note (i32): The MIDI-like note number (0-indexed, C4 = 48).
+ *     - num_samples (usize): The length of the note in samples.
+ *     - articulate (bool): Whether to apply the articulated attack/release envelope (ignored
+ *       for a voice without an envelope).
+ *     - voice (BassVoice): The tone of the note.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_bass_note(bass_line: &mut Vec<f32>, note: i32, num_samples: usize, articulate: bool, voice: BassVoice) {
+    let bass_note_freq = note_to_freq(note.clamp(0, 127) as u8);
+    let (attack_seconds, release_seconds) = if articulate && voice.envelope {
+        (BASS_NOTE_ATTACK_SECONDS, BASS_NOTE_RELEASE_SECONDS)
+    } else {
+        (BASS_NOTE_DECLICK_SECONDS, BASS_NOTE_DECLICK_SECONDS)
+    };
+    let attack_samples = (attack_seconds * SAMPLE_RATE as f32) as usize;
+    let release_samples = (release_seconds * SAMPLE_RATE as f32) as usize;
+
+    for i in 0..num_samples {
+        let sample = bass_wave(voice.waveform, bass_note_freq, i);
+        let attack = (i as f32 / attack_samples.max(1) as f32).min(1.0);
+        let release = ((num_samples - i) as f32 / release_samples.max(1) as f32).min(1.0);
+        bass_line.push(sample * 0.6 * attack.min(release));
+    }
+}
+
+/* swung_offset - Moves an offset within a span by the song's swing.
+ *
+ * The span must start on a beat. Off-beat eighths move later by the same amount as the
+ * melody's (see `melodies::swing_position`), and beats stay where they are.
+ *
+ * inputs:
+ *     - offset (usize): The straight offset from the start of the span, in samples.
+ *     - beat_samples (usize): The length of a beat in samples.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *
+ * outputs:
+ *     - usize: The swung offset; `offset` itself when there is no swing.
+ */
+fn swung_offset(offset: usize, beat_samples: usize, swing: f32) -> usize {
+    if swing <= 0.0 || beat_samples == 0 {
+        return offset;
+    }
+    let beats = offset as f64 / beat_samples as f64;
+    (melodies::swing_position(beats, swing) * beat_samples as f64).round() as usize
+}
+
+/* push_electronic_bass_beat - Appends one beat of the electronic bass.
+ *
+ * The beat is the root and the octave above it (a square wave unless another bass waveform
+ * is chosen), half a beat each (the octave comes later with swing), over a low sine kick
+ * whose pitch drops quickly. A voice without an envelope holds both notes at full level and
+ * leaves the kick to the drums.
+ *
+ * inputs:
+ *     - bass_line (&mut Vec<f32>): The buffer to append to.
+ *     - note (i32): The MIDI-like note number (0-indexed, C4 = 48).
+ *     - num_samples (usize): The length of the beat in samples.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - voice (BassVoice): The tone of the root and octave.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_electronic_bass_beat(bass_line: &mut Vec<f32>, note: i32, num_samples: usize, swing: f32, voice: BassVoice) {
+    let beat_start = bass_line.len();
+    let first_half = swung_offset(num_samples / 2, num_samples, swing);
+    if !voice.envelope {
+        push_bass_note(bass_line, note, first_half, true, voice);
+        push_bass_note(bass_line, note + 12, num_samples - first_half, true, voice);
+        return;
+    }
+    let attack_samples = (BASS_NOTE_ATTACK_SECONDS * SAMPLE_RATE as f32) as usize;
+    let release_samples = (BASS_NOTE_RELEASE_SECONDS * SAMPLE_RATE as f32) as usize;
+    for (octave_offset, length) in [(0, first_half), (12, num_samples - first_half)] {
+        let frequency = note_to_freq((note + octave_offset).clamp(0, 127) as u8);
+        for i in 0..length {
+            let sample = bass_wave(voice.waveform, frequency, i);
+            let attack = (i as f32 / attack_samples.max(1) as f32).min(1.0);
+            let release = ((length - i) as f32 / release_samples.max(1) as f32).min(1.0);
+            bass_line.push(sample * ELECTRONIC_BASS_LEVEL * attack.min(release));
+        }
+    }
+
+    let kick_samples = ((ELECTRONIC_KICK_SECONDS * SAMPLE_RATE as f32) as usize).min(num_samples);
+    let mut phase = 0.0f32;
+    for (i, sample) in bass_line[beat_start..beat_start + kick_samples].iter_mut().enumerate() {
+        let progress = i as f32 / kick_samples as f32;
+        let frequency = ELECTRONIC_KICK_START_HZ * (ELECTRONIC_KICK_END_HZ / ELECTRONIC_KICK_START_HZ).powf(progress);
+        phase = (phase + frequency / SAMPLE_RATE as f32).fract();
+        *sample += (phase * std::f32::consts::TAU).sin() * ELECTRONIC_KICK_LEVEL * (1.0 - progress);
+    }
+}
+
+/* push_sustained_bass_note - Appends one bass note that fades in and out slowly.
+ *
+ * The fades are cut to half the note each, so the note always starts and ends at silence. A
+ * voice without an envelope only declicks the note (see `push_bass_note`).
+ *
+ * inputs:
+ *     - bass_line (&mut Vec<f32>): The buffer to append to.
+ *     - note (i32): The MIDI-like note number (0-indexed, C4 = 48).
+ *     - num_samples (usize): The length of the note in samples.
+ *     - voice (BassVoice): The tone of the note.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_sustained_bass_note(bass_line: &mut Vec<f32>, note: i32, num_samples: usize, voice: BassVoice) {
+    if !voice.envelope {
+        push_bass_note(bass_line, note, num_samples, false, voice);
+        return;
+    }
+    let bass_note_freq = note_to_freq(note.clamp(0, 127) as u8);
+    let fade_samples = ((AMBIENT_BASS_FADE_SECONDS * SAMPLE_RATE as f32) as usize).min(num_samples / 2);
+
+    for i in 0..num_samples {
+        let sample = bass_wave(voice.waveform, bass_note_freq, i);
+        let envelope = if fade_samples > 0 {
+            let attack = i as f32 / fade_samples as f32;
+            let release = (num_samples - i - 1) as f32 / fade_samples as f32;
+            attack.min(release).min(1.0)
+        } else {
+            1.0
+        };
+        bass_line.push(sample * 0.6 * envelope);
+    }
+}
+
+/* push_grouped_bass_figure - Appends a bass figure once per group of beats in a span, its notes
+ * articulated with silence between them.
+ *
+ * The span is split into as many equal groups as fit best, at least one, so a span shorter
+ * than a group gets the start of the figure only; notes are cut or left out to fit their group.
+ *
+ * inputs:
+ *     - bass_line (&mut Vec<f32>): The buffer to append to.
+ *     - note (i32): The MIDI-like note number (0-indexed, C4 = 48).
+ *     - num_samples (usize): The length of the span in samples.
+ *     - beat_samples (usize): The length of a beat.
+ *     - group_samples (usize): The length of a group of beats (see `TimeSignature::beats_per_group`).
+ *     - figure (&[(usize, i32, usize)]): The figure's notes, in order: (beat it starts on,
+ *       semitones above the root, length in beats).
+ *     - voice (BassVoice): The tone of the notes.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_grouped_bass_figure(
+    bass_line: &mut Vec<f32>,
+    note: i32,
+    num_samples: usize,
+    beat_samples: usize,
+    group_samples: usize,
+    figure: &[(usize, i32, usize)],
+    voice: BassVoice,
+) {
+    let groups = ((num_samples as f32 / group_samples.max(1) as f32).round() as usize).max(1);
+    for group in 0..groups {
+        let group_len = (group + 1) * num_samples / groups - group * num_samples / groups;
+        let group_start = bass_line.len();
+        for &(beat, interval, beats) in figure {
+            let note_start = beat * beat_samples;
+            if note_start >= group_len {
+                break;
+            }
+            bass_line.resize(group_start + note_start, 0.0);
+            let note_samples = (beats * beat_samples).min(group_len - note_start);
+            push_bass_note(bass_line, note + interval, note_samples, true, voice);
+        }
+        bass_line.resize(group_start + group_len, 0.0);
+    }
+}
+
+/* push_reggae_bass_notes - Appends the syncopated reggae bass figure over a span.
+ *
+ * The root and fifth of `REGGAE_BASS_FIGURE` are articulated, with silence between them.
+ *
+ * inputs:
+ *     - bass_line (&mut Vec<f32>): The buffer to append to.
+ *     - note (i32): The MIDI-like root note number (0-indexed, C4 = 48).
+ *     - num_samples (usize): The length of the span in samples.
+ *     - beat_samples (usize): The length of a beat; notes past the span are cut or left out.
+ *     - beats_per_bar (usize): Beats in a bar; the figure starts again every bar.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - voice (BassVoice): The tone of the notes.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_reggae_bass_notes(
+    bass_line: &mut Vec<f32>,
+    note: i32,
+    num_samples: usize,
+    beat_samples: usize,
+    beats_per_bar: usize,
+    swing: f32,
+    voice: BassVoice,
+) {
+    let span_start = bass_line.len();
+    let eighth_samples = (beat_samples / 2).max(1);
+    let bar_eighths = beats_per_bar.max(1) * 2;
+    bass_line.resize(span_start + num_samples, 0.0);
+    let mut figure_note = Vec::new();
+    for bar_start in (0..num_samples).step_by(eighth_samples * bar_eighths) {
+        let bar_end = (bar_start + eighth_samples * bar_eighths).min(num_samples);
+        for &(eighth, interval, eighths) in &REGGAE_BASS_FIGURE {
+            let note_start = bar_start + swung_offset(eighth * eighth_samples, beat_samples, swing);
+            if eighth >= bar_eighths || note_start >= num_samples {
+                break;
+            }
+            let end_eighth = (eighth + eighths).min(bar_eighths);
+            let note_end = bar_start + swung_offset(end_eighth * eighth_samples, beat_samples, swing);
+            let note_samples = note_end.min(bar_end) - note_start;
+            figure_note.clear();
+            push_bass_note(&mut figure_note, note + interval, note_samples, true, voice);
+            let start = span_start + note_start;
+            bass_line[start..start + note_samples].copy_from_slice(&figure_note);
+        }
+    }
+}
+
+// PushBassPulse: Appends one repetition of a bass figure (buffer, MIDI-like note, length in samples,
+// swing, voice).
+type PushBassPulse = fn(&mut Vec<f32>, i32, usize, f32, BassVoice);
+
+/* push_pulsing_bass_notes - Fills a span by repeating a bass figure at a steady pulse.
+ *
+ * With swing, the repetitions start on the swung grid (see `swung_offset`), so eighth-note
+ * pulses play long-short.
+ *
+ * inputs:
+ *     - bass_line (&mut Vec<f32>): The buffer to append to.
+ *     - note (i32): The MIDI-like note number (0-indexed, C4 = 48).
+ *     - num_samples (usize): The length of the span in samples.
+ *     - pulse_samples (usize): The length of each repetition; the last one is cut to fit.
+ *     - beat_samples (usize): The length of a beat in samples.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - push_pulse (PushBassPulse): Appends one repetition of the given length.
+ *     - voice (BassVoice): The tone of the repetitions.
+ *
+ * outputs:
+ *     - None
+ */
+#[allow(clippy::too_many_arguments)]
+fn push_pulsing_bass_notes(
+    bass_line: &mut Vec<f32>,
+    note: i32,
+    num_samples: usize,
+    pulse_samples: usize,
+    beat_samples: usize,
+    swing: f32,
+    push_pulse: PushBassPulse,
+    voice: BassVoice,
+) {
+    let mut straight_end = 0;
+    let mut pushed = 0;
+    while pushed < num_samples {
+        straight_end = (straight_end + pulse_samples.max(1)).min(num_samples);
+        let end = if straight_end == num_samples {
+            num_samples
+        } else {
+            swung_offset(straight_end, beat_samples, swing).min(num_samples)
+        };
+        push_pulse(bass_line, note, end - pushed, swing, voice);
+        pushed = end;
+    }
+}
+
 /* get_bass_line - Generates a simple bass line based on a chord progression.
  *
  * The bass line plays the root note of each chord, transposed one octave lower.
@@ -64,24 +765,50 @@ fn note_to_freq(note: u8) -> f32 {
  * the original note is used (this effectively means notes below C1 will not be transposed further down).
  * The output is a sequence of raw audio samples representing a sine wave for each bass note.
  *
+ * Rock pumps the root in articulated eighth notes instead of holding it, metal in sixteenths
+ * (eighths above `METAL_SIXTEENTHS_MAX_BPM`), electronic plays
+ * the root and its octave on every beat over a kick (see `push_electronic_bass_beat`), and
+ * ambient fades each root in and out (see `push_sustained_bass_note`), and classical plays the
+ * root on the first beat of each chord only. Folk alternates the root and its fifth on the
+ * first two beats, under the chord track's waltz stabs, pop holds the root from beat 1 and
+ * the fifth from beat 3 (see `push_grouped_bass_figure`), and reggae plays a syncopated root
+ * and fifth figure (see `push_reggae_bass_notes`). Jazz walks in quarter notes from each root toward the next (see
+ * `walking_bass_notes`; dotted quarters in 6/8), holding a chord only when there is no other
+ * to walk to.
+ *
+ * On the last beat before a chord change (except the change back to the start of the
+ * progression), the bass may replace the held root with a two-note approach figure into
+ * the next root: chromatic from below, or stepping down from above. How often this
+ * happens and which figure is chosen depends on the style and the seed. Eighth-note
+ * figures and fills are swung by the song's swing (see `swung_offset`). The time signature sets
+ * where the reggae figure starts again and, in 6/8, repeats the classical,
+ * folk and pop figures halfway through each bar.
+ *
  * inputs:
- *     - _style (&str): Style of the bass line (currently unused, for future variations).
+ *     - style (&str): Style of the bass line, which sets its pattern and how often approach fills occur.
  *     - chord_root_notes (&Vec<u8>): A vector of MIDI-like note numbers representing the root of each chord in the progression cycle.
  *     - samples_per_chord (usize): The number of audio samples each bass note (corresponding to a chord) should last.
  *     - total_samples (usize): The total desired length of the bass line in audio samples, typically to match a melody.
- *     - _bpm (u32): Beats per minute (currently unused, for future rhythmic variations).
- *     - _seed (u64): Seed for randomization (currently unused, for future randomization).
+ *     - bpm (u32): Beats per minute, used to place approach fills on the last beat of a chord.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - time_signature (progs::TimeSignature): The song's time signature.
+ *     - seed (u64): Seed for randomization of the approach fills and the walking bass's path.
+ *     - voice (BassVoice): The tone of the bass.
  *
  * outputs:
  *     - Vec<f32>: A vector of f32 audio samples representing the generated bass line.
  */
+#[allow(clippy::too_many_arguments)]
 pub fn get_bass_line(
-    _style: &str,
+    style: &str,
     chord_root_notes: &[u8],
     samples_per_chord: usize,
     total_samples: usize,
-    _bpm: u32,
-    _seed: u64,
+    bpm: u32,
+    swing: f32,
+    time_signature: progs::TimeSignature,
+    seed: u64,
+    voice: BassVoice,
 ) -> Vec<f32> {
     if chord_root_notes.is_empty() || samples_per_chord == 0 {
         return vec![0.0; total_samples];
@@ -89,23 +816,131 @@ pub fn get_bass_line(
 
     let mut bass_line = Vec::with_capacity(total_samples);
     let num_chords_in_progression = chord_root_notes.len();
+    let samples_per_beat = if bpm > 0 {
+        (SAMPLE_RATE as f32 * 60.0 / bpm as f32) as usize
+    } else {
+        samples_per_chord
+    };
+    let (fill_probability, chromatic_probability) = bass_fill_probability(style);
+    // Rock, metal and electronic repeat a figure on the root, ambient plays one faded note per chord;
+    // other styles hold it for the whole chord
+    let pulse: Option<(usize, PushBassPulse)> = match style.to_lowercase().as_str() {
+        "rock" => Some((samples_per_beat / 2, |bass_line, note, num_samples, _, voice| {
+            push_bass_note(bass_line, note, num_samples, true, voice)
+        })),
+        "metal" => {
+            let notes_per_beat = if bpm <= METAL_SIXTEENTHS_MAX_BPM { 4 } else { 2 };
+            Some((samples_per_beat / notes_per_beat, |bass_line, note, num_samples, _, voice| {
+                push_bass_note(bass_line, note, num_samples, true, voice)
+            }))
+        }
+        "electronic" => Some((samples_per_beat, push_electronic_bass_beat)),
+        "ambient" => Some((samples_per_chord, |bass_line, note, num_samples, _, voice| {
+            push_sustained_bass_note(bass_line, note, num_samples, voice)
+        })),
+        _ => None,
+    };
+    let pulse = pulse.filter(|&(pulse_samples, _)| pulse_samples > 0);
+    let group_figure: Option<&[(usize, i32, usize)]> = match style.to_lowercase().as_str() {
+        "classical" => Some(&CLASSICAL_BASS_FIGURE),
+        "folk" => Some(&FOLK_BASS_FIGURE),
+        "pop" => Some(&POP_BASS_FIGURE),
+        _ => None,
+    };
+    let reggae = style.eq_ignore_ascii_case("reggae");
+    let push_root = |bass_line: &mut Vec<f32>, note: i32, num_samples: usize, articulate: bool| {
+        match (pulse, group_figure) {
+            (Some((pulse_samples, push_pulse)), _) => push_pulsing_bass_notes(
+                bass_line,
+                note,
+                num_samples,
+                pulse_samples,
+                samples_per_beat,
+                swing,
+                push_pulse,
+                voice,
+            ),
+            (None, Some(figure)) => push_grouped_bass_figure(
+                bass_line,
+                note,
+                num_samples,
+                samples_per_beat,
+                samples_per_beat * time_signature.beats_per_group(),
+                figure,
+                voice,
+            ),
+            (None, None) if reggae => push_reggae_bass_notes(
+                bass_line,
+                note,
+                num_samples,
+                samples_per_beat,
+                time_signature.beats_per_bar(),
+                swing,
+                voice,
+            ),
+            (None, None) => push_bass_note(bass_line, note, num_samples, articulate, voice),
+        }
+    };
+    let mut rng = StdRng::seed_from_u64(seed ^ BASS_FILL_SEED_SALT);
+    // A single chord (such as the closing tonic) has nowhere to walk to
+    let walk_samples = samples_per_beat * if time_signature == progs::TimeSignature::SixEight { 3 } else { 1 };
+    let walking = style.eq_ignore_ascii_case("jazz") && num_chords_in_progression > 1 && walk_samples > 0;
 
-    for i in 0..total_samples {
-        let current_chord_index = (i / samples_per_chord) % num_chords_in_progression;
-        let chord_root = chord_root_notes[current_chord_index];
-
-        // Play bass note one octave lower than the chord root.
-        let bass_note_midi = if chord_root >= 12 {
-            chord_root - 12
+    // Play bass notes one octave lower than the chord root.
+    let bass_note_for = |chord_root: u8| -> i32 {
+        if chord_root >= 12 {
+            chord_root as i32 - 12
         } else {
-            chord_root
-        };
-        let bass_note_freq = note_to_freq(bass_note_midi);
+            chord_root as i32
+        }
+    };
+
+    let mut chord_counter = 0;
+    while bass_line.len() < total_samples {
+        let chord_samples = samples_per_chord.min(total_samples - bass_line.len());
+        let current_bass_note = bass_note_for(chord_root_notes[chord_counter % num_chords_in_progression]);
+        let next_chord_index = (chord_counter + 1) % num_chords_in_progression;
+        let next_bass_note = bass_note_for(chord_root_notes[next_chord_index]);
+
+        if walking {
+            let beats = (samples_per_chord / walk_samples).max(1);
+            let notes = walking_bass_notes(current_bass_note, next_bass_note, beats, &mut rng);
+            let chord_start = bass_line.len();
+            for (beat, note) in notes.into_iter().enumerate() {
+                // The last beat takes whatever is left of the chord
+                let start = beat * walk_samples;
+                let end = if beat + 1 == beats { samples_per_chord } else { start + walk_samples };
+                let end = end.min(chord_samples);
+                if start >= end {
+                    break;
+                }
+                push_bass_note(&mut bass_line, note, end - start, true, voice);
+            }
+            bass_line.resize(chord_start + chord_samples, 0.0);
+            chord_counter += 1;
+            continue;
+        }
 
-        let time = (i % samples_per_chord) as f32 / SAMPLE_RATE as f32;
-        let sample = (time * bass_note_freq * 2.0 * std::f32::consts::PI).sin();
+        // Only full chords that lead into a new chord (not bar 1 of the cycle) get a fill
+        let can_fill = next_chord_index != 0
+            && chord_samples == samples_per_chord
+            && samples_per_beat > 1
+            && samples_per_chord > samples_per_beat;
 
-        bass_line.push(sample * 0.6);
+        if can_fill && rng.gen::<f32>() < fill_probability {
+            let approach = if rng.gen::<f32>() < chromatic_probability {
+                [next_bass_note - 2, next_bass_note - 1] // Chromatic from below
+            } else {
+                [next_bass_note + 4, next_bass_note + 2] // Scale-wise from above
+            };
+            let first_half = swung_offset(samples_per_beat / 2, samples_per_beat, swing);
+            push_root(&mut bass_line, current_bass_note, chord_samples - samples_per_beat, true);
+            push_bass_note(&mut bass_line, approach[0], first_half, true, voice);
+            push_bass_note(&mut bass_line, approach[1], samples_per_beat - first_half, true, voice);
+        } else {
+            push_root(&mut bass_line, current_bass_note, chord_samples, false);
+        }
+        chord_counter += 1;
     }
 
     bass_line
@@ -117,549 +952,5402 @@ pub fn get_bass_line(
  * and playback thread to manage its state.
  */
 pub enum MusicControl {
-    Pause,     // Pauses current playback.
-    Resume,    // Resumes current playback.
-    Terminate, // Stops playback and terminates the music service thread.
-    Rewind,    // Restarts the current song from the beginning.
+    Pause,          // Pauses current playback.
+    Resume,         // Resumes current playback.
+    Terminate,      // Stops playback and terminates the music service thread.
+    Rewind,         // Restarts the current song from the beginning.
+    SeekToBeat(u32), // Continues the current song from the start of the given beat.
+    Seek(f32),      // Continues the current song from a fraction (0.0-1.0) of its length.
+    SetVolume(f32), // Sets the sink volume (1.0 = 100%), clamped to 0.0-2.0.
+    SetLoop(bool),  // Turns looping of the current song on or off.
+    SetNextSong(Arc<PreparedSong>), // Pre-rendered song to auto-advance to instead of generating one.
+    SetCrossfade(u32), // Sets the crossfade into the next song, in seconds (0 = none).
+    FadeOut(Duration), // Fades the current song out over the duration, then terminates the service.
+    SetStopAfterCurrent(bool), // Ends the service when the current song finishes, instead of looping or moving on.
 }
 
-/* MusicProgress - Reports the playback progress of the current song.
+// MAX_CROSSFADE_SECS: Longest selectable crossfade between songs, in seconds.
+pub const MAX_CROSSFADE_SECS: u32 = 5;
+
+// FADE_TICK: How often the service loop wakes while a fade is running, so volume steps stay inaudible.
+const FADE_TICK: Duration = Duration::from_millis(10);
+
+/* crossfade_duration - Converts a crossfade setting into a duration.
  *
- * Transmitted from the music service to the TUI to update progress indicators.
+ * inputs:
+ *     - seconds (u32): The crossfade setting, clamped to `MAX_CROSSFADE_SECS`.
  *
- * fields:
- *     - current_samples (u64): Number of audio samples played so far.
- *     - total_samples (u64): Total number of audio samples in the current song.
- *     - actual_seed (u64): The seed value that was actually used to generate the current song.
- *     - app_state (Option<AppState>): The current app state used to generate the song, if any.
+ * outputs:
+ *     - Duration: The crossfade length (zero means songs change abruptly).
  */
-pub struct MusicProgress {
-    pub current_samples: u64,
-    pub total_samples: u64,
-    pub actual_seed: u64,
-    pub app_state: Option<AppState>,
+pub fn crossfade_duration(seconds: u32) -> Duration {
+    Duration::from_secs(seconds.min(MAX_CROSSFADE_SECS) as u64)
 }
 
-/* MusicPlayer - Manages audio playback state and hardware interaction.
- *
- * This struct encapsulates the Rodio sink and stream, handles playback control messages,
- * and keeps track of the current audio data and playback position.
+/* VolumeRamp - A linear fade from a starting volume down to silence.
  *
  * fields:
- *     - receiver (CrossbeamReceiver<MusicControl>): Receives control messages.
- *     - sink (Sink): The Rodio audio sink for playing samples.
- *     - _stream (OutputStream): The Rodio output stream (held to keep audio active).
- *     - current_audio_data (Option<Vec<f32>>): Buffer for the currently loaded song's audio samples.
- *     - current_sample_rate (Option<u32>): Sample rate of the current audio data.
- *     - total_samples (u64): Total samples in `current_audio_data`.
- *     - playback_start_time (Option<Instant>): Timestamp of when playback last (re)started.
- *     - samples_played_at_pause (u64): Number of samples played before the last pause.
- *     - should_terminate (bool): Flag to signal the playback loop to exit.
- *     - is_manually_paused (bool): Tracks whether the user explicitly paused playback.
+ *     - started (Instant): When the fade began.
+ *     - duration (Duration): How long the fade lasts.
+ *     - from (f32): The volume at the start of the fade.
  */
-pub struct MusicPlayer {
-    receiver: CrossbeamReceiver<MusicControl>,
-    sink: Sink,
-    _stream: OutputStream,
-    current_audio_data: Option<Vec<f32>>,
-    current_sample_rate: Option<u32>,
-    total_samples: u64,
-    playback_start_time: Option<Instant>,
-    samples_played_at_pause: u64,
-    should_terminate: bool,
-    is_manually_paused: bool,
-    last_progress_update: Instant,
-    was_paused: bool,
-    last_reported_samples: u64,
+struct VolumeRamp {
+    started: Instant,
+    duration: Duration,
+    from: f32,
 }
 
-impl MusicPlayer {
-    /* new - Creates a new `MusicPlayer` instance.
-     *
-     * Initializes the audio output stream and sink, preparing for playback.
-     * The sink starts in a paused state, but is_manually_paused is false
-     * (meaning it will auto-play when audio is loaded).
+impl VolumeRamp {
+    /* new - Starts a fade right now.
      *
      * inputs:
-     *     - receiver (CrossbeamReceiver<MusicControl>): Channel to receive playback control messages.
+     *     - duration (Duration): How long the fade lasts.
+     *     - from (f32): The volume to fade from.
      *
      * outputs:
-     *     - Self: A new `MusicPlayer` instance.
+     *     - Self: The running fade.
      */
-    pub fn new(receiver: CrossbeamReceiver<MusicControl>) -> Self {
-        let (_stream, stream_handle) =
-            OutputStream::try_default().expect("Failed to get output stream");
-        let sink = Sink::try_new(&stream_handle).expect("Failed to create audio sink");
-        sink.pause();
-        MusicPlayer {
-            receiver,
-            sink,
-            _stream,
-            current_audio_data: None,
-            current_sample_rate: None,
-            total_samples: 0,
-            playback_start_time: None,
-            samples_played_at_pause: 0,
-            should_terminate: false,
-            is_manually_paused: false,
-            last_progress_update: Instant::now(),
-            was_paused: false,
-            last_reported_samples: 0,
+    fn new(duration: Duration, from: f32) -> Self {
+        VolumeRamp {
+            started: Instant::now(),
+            duration,
+            from,
         }
     }
 
-    /* play_audio - Loads new audio data into the player and prepares it for playback.
-     *
-     * Stops any currently playing audio, replaces it with the new data, and resets
-     * playback progress. If not manually paused, playback will start automatically.
+    /* volume - Returns the volume the fading sink should have now.
      *
      * inputs:
-     *     - &mut self
-     *     - audio_data (Vec<f32>): The raw audio samples to play.
-     *     - sample_rate (u32): The sample rate of the provided `audio_data`.
+     *     - &self
      *
      * outputs:
-     *     - None
+     *     - Option<f32>: The current volume, or None once the fade has finished.
      */
-    pub fn play_audio(&mut self, audio_data: Vec<f32>, sample_rate: u32) {
-        self.sink.stop();
+    fn volume(&self) -> Option<f32> {
+        let elapsed = self.started.elapsed();
+        if elapsed >= self.duration {
+            return None;
+        }
+        Some(self.from * (1.0 - elapsed.as_secs_f32() / self.duration.as_secs_f32()))
+    }
+}
+
+/* MusicProgress - Reports the playback progress of the current song.
+ *
+ * Transmitted from the music service to the TUI to update progress indicators.
+ *
+ * fields:
+ *     - current_samples (u64): Number of audio samples played so far.
+ *     - total_samples (u64): Total number of audio samples in the current song; for an endless
+ *       song, the samples rendered so far.
+ *     - endless (bool): True for an endless song, which only has an elapsed time.
+ *     - actual_seed (u64): The seed value that was actually used to generate the current song.
+ *     - app_state (Option<AppState>): The current app state used to generate the song, if any.
+ *     - level (f32): RMS output level (0.0-1.0) around the playback position, scaled by the sink volume; 0.0 while paused.
+ *     - scope (Vec<f32>): Downsampled waveform around the playback position for the oscilloscope; empty while paused.
+ *     - spectrum (Vec<f32>): Magnitudes (0.0-1.0) of `SPECTRUM_BANDS` log-spaced bands, low to high; empty while paused.
+ *     - current_beat (u32): Zero-based beat at the playback position, derived from the sample position.
+ *     - beat_phase (f32): Position within the current beat (0.0 on the beat, approaching 1.0 before the next).
+ *     - beats_per_bar (u32): Beats in a bar of the current song's time signature.
+ *     - bpm (u32): The current song's tempo, including one picked from the seed for an empty BPM.
+ *     - chord_timeline (Option<Vec<(u64, String)>>): Start sample and name of every chord, sent along with `app_state`.
+ *     - section_timeline (Option<Vec<(u64, SongSection)>>): Start sample of every section, sent along with `app_state`.
+ *     - loudness_db (Option<f32>): Loudness of the current song (see `dsp::integrated_loudness`), once it is fully rendered.
+ *     - generating (bool): True while the service is generating the opening of a new song; the
+ *       rest of such a report describes no song.
+ *     - generation (u64): The id of the service that sent the report, so reports from a service
+ *       that was replaced can be told apart and dropped.
+ *     - error (Option<String>): Why the service could not play (e.g. no audio device); it has
+ *       stopped, and the rest of the report describes no song.
+ */
+pub struct MusicProgress {
+    pub current_samples: u64,
+    pub total_samples: u64,
+    pub endless: bool,
+    pub actual_seed: u64,
+    pub app_state: Option<AppState>,
+    pub level: f32,
+    pub scope: Vec<f32>,
+    pub spectrum: Vec<f32>,
+    pub current_beat: u32,
+    pub beat_phase: f32,
+    pub beats_per_bar: u32,
+    pub bpm: u32,
+    pub chord_timeline: Option<Vec<(u64, String)>>,
+    pub section_timeline: Option<Vec<(u64, SongSection)>>,
+    pub loudness_db: Option<f32>,
+    pub generating: bool,
+    pub generation: u64,
+    pub error: Option<String>,
+}
 
-        // Store the audio data and sample rate
-        self.current_audio_data = Some(audio_data.clone());
-        self.current_sample_rate = Some(sample_rate);
+// LEVEL_WINDOW_SAMPLES: Samples analyzed per level reading (~100ms at 44.1kHz).
+const LEVEL_WINDOW_SAMPLES: usize = 4410;
+// SCOPE_WINDOW_SAMPLES: Samples shown by the oscilloscope (~50ms at 44.1kHz).
+const SCOPE_WINDOW_SAMPLES: usize = 2205;
+// SCOPE_POINTS: Points sent per oscilloscope frame; the window is decimated down to this many.
+const SCOPE_POINTS: usize = 245;
 
-        let source = SamplesBuffer::new(1, sample_rate, audio_data);
-        self.total_samples = self
-            .current_audio_data
-            .as_ref()
-            .map_or(0, |d| d.len() as u64);
-        self.samples_played_at_pause = 0;
-        self.playback_start_time = None;
+// SPECTRUM_FFT_SIZE: Samples analyzed per spectrum frame (~23ms at 44.1kHz); must be a power of two.
+const SPECTRUM_FFT_SIZE: usize = 1024;
+// ANALYSIS_RADIUS_SAMPLES: Audio copied on either side of the playback position for the level,
+// scope and spectrum; at least half of the widest of their windows.
+const ANALYSIS_RADIUS_SAMPLES: usize = LEVEL_WINDOW_SAMPLES;
+// SPECTRUM_BANDS: Number of log-spaced bands sent to the TUI, which merges them to fit its width.
+pub const SPECTRUM_BANDS: usize = 32;
+// Frequency range covered by the spectrum bands, in Hz.
+const SPECTRUM_MIN_HZ: f32 = 40.0;
+const SPECTRUM_MAX_HZ: f32 = 10000.0;
 
-        self.sink.append(source);
+/* fft_magnitudes - Computes the magnitude spectrum of a block with an in-place radix-2 FFT.
+ *
+ * inputs:
+ *     - samples (&[f32]): The input block; its length must be a power of two.
+ *
+ * outputs:
+ *     - Vec<f32>: Magnitudes of the first `samples.len() / 2` frequency bins.
+ */
+fn fft_magnitudes(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    let mut re: Vec<f32> = samples.to_vec();
+    let mut im = vec![0.0f32; n];
 
-        // Auto-play unless manually paused
-        if !self.is_manually_paused && self.total_samples > 0 {
-            self.playback_start_time = Some(Instant::now());
-            self.last_progress_update = Instant::now();
-            self.sink.play();
+    // Bit-reversal permutation
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
         }
     }
 
-    /* should_continue - Checks if the music service should continue its playback loop.
-     *
-     * inputs:
-     *     - &self
-     *
-     * outputs:
-     *     - bool: True if the service should continue, false if termination is requested.
-     */
-    pub fn should_continue(&self) -> bool {
-        !self.should_terminate
+    // Butterflies
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
     }
+
+    (0..n / 2).map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt()).collect()
 }
 
-/* generate_audio_from_state - Generates raw audio samples based on application state.
+/* spectrum_bands - Measures the audio around a playback position in log-spaced frequency bands.
  *
- * This internal function takes the current `AppState` (scale, style, BPM, etc.) and
- * orchestrates calls to melody, chord progression, and bass line generation modules.
- * It then mixes these components and applies basic normalization.
+ * A Hann-windowed `SPECTRUM_FFT_SIZE` block is transformed and each band takes the loudest
+ * bin within its range, scaled so a full-scale sine reads 1.0. One frame costs well under a
+ * millisecond, so it runs inline in the service loop without delaying control messages.
  *
  * inputs:
- *     - app_state (&AppState): The current application state defining music parameters.
+ *     - audio_data (&[f32]): The song's audio samples.
+ *     - position (u64): The current playback position in samples.
  *
  * outputs:
- *     - (Vec<f32>, u32, u64): A tuple containing:
- *         - Vec<f32>: The generated and mixed audio samples.
- *         - u32: The sample rate of the generated audio (typically `SAMPLE_RATE_AUDIO_GEN`).
- *         - u64: The actual seed value used for random number generation.
- */
-fn generate_audio_from_state(app_state: &AppState) -> (Vec<f32>, u32, u64) {
-    const SAMPLE_RATE_AUDIO_GEN: u32 = 44100;
-
-    let root_note = match app_state.scale.to_owned().as_str() {
-        "C" => 0,
-        "C#" => 1,
-        "D" => 2,
-        "D#" => 3,
-        "E" => 4,
-        "F" => 5,
-        "F#" => 6,
-        "G" => 7,
-        "G#" => 8,
-        "A" => 9,
-        "A#" => 10,
-        "B" => 11,
-        _ => 0, // Default to C
-    };
-    let duration_minutes = app_state
-        .length
-        .split_whitespace()
-        .next()
-        .unwrap_or("5")
-        .parse::<f32>()
-        .unwrap_or(5.0);
-    let duration_seconds = duration_minutes * 60.0;
-    let style = app_state.style.as_str();
-
-    // Determine the actual seed to be used for generation
-    let actual_generated_seed = app_state.seed.parse::<u64>().unwrap_or_else(|_| {
-        // If seed string is empty or invalid, generate a truly random u64 seed value
-        rand::random::<u64>()
-    });
-    let mut rng = StdRng::seed_from_u64(actual_generated_seed);
-
-    let bpm_str = app_state.bpm.as_str();
-    let bpm = match bpm_str.parse::<u32>() {
-        Ok(val) if !bpm_str.is_empty() && val > 0 => val,
-        _ => rng.gen_range(80..=160), // Corrected based on rand docs, will see if compiler still complains
-    };
-
-    let sec_per_beat: f32 = 60.0 / bpm as f32;
-    let num_beats_per_chord = rng.gen_range(2..=4);
-    let chord_duration: f32 = num_beats_per_chord as f32 * sec_per_beat;
-    let samples_per_chord = (chord_duration * SAMPLE_RATE_AUDIO_GEN as f32) as usize;
-
-    // Call get_melody and get_bass_line with their original signatures
-    let melody = melodies::get_melody(
-        style,
-        root_note,
-        duration_seconds as u32,
-        sec_per_beat,
-        actual_generated_seed,
-    );
-    let (chord_sequence, chord_root_notes) = match style.to_lowercase().as_str() {
-        "blues" => play_progression(String::from("blues"), root_note, chord_duration),
-        "pop" => play_progression(String::from("pop"), root_note, chord_duration),
-        "jazz" => play_progression(String::from("jazz"), root_note, chord_duration),
-        _ => play_progression(String::from("default"), root_note, chord_duration),
-    };
-    let melody_len = melody.len();
-    let chord_len = chord_sequence.len();
-    let target_len = melody_len;
-    let bass_line = get_bass_line(
-        style,
-        &chord_root_notes,
-        samples_per_chord,
-        target_len,
-        bpm,
-        actual_generated_seed,
-    );
-
-    let mut mixed_audio = Vec::with_capacity(target_len);
-    let chord_gain = 0.5;
-    let melody_gain = 0.125;
-    let bass_gain = 0.6;
-    for i in 0..target_len {
-        let chord_sample_val = if chord_len > 0 {
-            chord_sequence.get(i % chord_len).copied().unwrap_or(0.0) * chord_gain
-        } else {
-            0.0
-        };
-        let melody_sample_val = melody.get(i).copied().unwrap_or(0.0) * melody_gain;
-        let bass_sample_val = bass_line.get(i).copied().unwrap_or(0.0) * bass_gain;
-        mixed_audio.push(melody_sample_val + chord_sample_val + bass_sample_val);
-    }
-    if !mixed_audio.is_empty() {
-        let max_abs_val = mixed_audio
-            .iter()
-            .fold(0.0f32, |max, &val| max.max(val.abs()));
-        if max_abs_val > 1.0 {
-            for sample in &mut mixed_audio {
-                *sample /= max_abs_val;
-            }
-        }
-    }
+ *     - Vec<f32>: `SPECTRUM_BANDS` magnitudes, lowest band first.
+ */
+fn spectrum_bands(audio_data: &[f32], position: u64) -> Vec<f32> {
+    let center = (position as usize).min(audio_data.len());
+    let start = center.saturating_sub(SPECTRUM_FFT_SIZE / 2);
+    let window_sum = SPECTRUM_FFT_SIZE as f32 / 2.0; // Sum of the Hann window
+    let block: Vec<f32> = (0..SPECTRUM_FFT_SIZE)
+        .map(|i| {
+            let hann = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / SPECTRUM_FFT_SIZE as f32).cos();
+            audio_data.get(start + i).copied().unwrap_or(0.0) * hann
+        })
+        .collect();
+    let magnitudes = fft_magnitudes(&block);
 
-    (mixed_audio, SAMPLE_RATE_AUDIO_GEN, actual_generated_seed)
+    let hz_per_bin = SAMPLE_RATE as f32 / SPECTRUM_FFT_SIZE as f32;
+    let ratio = SPECTRUM_MAX_HZ / SPECTRUM_MIN_HZ;
+    (0..SPECTRUM_BANDS)
+        .map(|band| {
+            let low_hz = SPECTRUM_MIN_HZ * ratio.powf(band as f32 / SPECTRUM_BANDS as f32);
+            let high_hz = SPECTRUM_MIN_HZ * ratio.powf((band + 1) as f32 / SPECTRUM_BANDS as f32);
+            let low_bin = (low_hz / hz_per_bin).round() as usize;
+            let high_bin = ((high_hz / hz_per_bin).round() as usize).max(low_bin + 1);
+            let peak = magnitudes[low_bin.min(magnitudes.len() - 1)..high_bin.min(magnitudes.len())]
+                .iter()
+                .fold(0.0f32, |max, &m| max.max(m));
+            (2.0 * peak / window_sum).min(1.0)
+        })
+        .collect()
 }
 
-/* run_music_service - Main function for the music generation and playback thread.
+/* scope_window - Extracts a decimated waveform window around a playback position.
  *
- * This function initializes a `MusicPlayer`, generates initial audio based on `initial_app_state`,
- * and then enters a loop to handle control messages (Pause, Resume, Rewind, Terminate)
- * and report playback progress. Music plays automatically unless explicitly paused.
+ * Samples are picked at a fixed stride rather than averaged, so square-wave edges stay sharp.
  *
  * inputs:
- *     - initial_app_state (AppState): The application state to use for generating the first song.
- *     - receiver (CrossbeamReceiver<MusicControl>): Channel to receive control messages.
- *     - progress_sender (CrossbeamSender<MusicProgress>): Channel to send progress updates.
+ *     - audio_data (&[f32]): The song's audio samples.
+ *     - position (u64): The current playback position in samples.
  *
  * outputs:
- *     - None (runs in a separate thread until Terminate is received).
+ *     - Vec<f32>: Up to `SCOPE_POINTS` samples from a `SCOPE_WINDOW_SAMPLES` window centered on `position`.
  */
-pub fn run_music_service(
-    initial_app_state: AppState,
-    receiver: CrossbeamReceiver<MusicControl>,
-    progress_sender: CrossbeamSender<MusicProgress>,
-) {
-    const SAMPLE_RATE_PROGRESS: f32 = SAMPLE_RATE as f32; // Use the same sample rate as audio generation
-    const PROGRESS_UPDATE_INTERVAL: Duration = Duration::from_millis(33); // Update progress every ~33ms for ~30fps updates
-    const MIN_PROGRESS_DELTA: u64 = (SAMPLE_RATE_PROGRESS * 0.05) as u64; // Minimum 50ms change to report
+fn scope_window(audio_data: &[f32], position: u64) -> Vec<f32> {
+    let center = (position as usize).min(audio_data.len());
+    let start = center.saturating_sub(SCOPE_WINDOW_SAMPLES / 2);
+    let end = (start + SCOPE_WINDOW_SAMPLES).min(audio_data.len());
+    let stride = SCOPE_WINDOW_SAMPLES.div_ceil(SCOPE_POINTS);
+    audio_data[start..end].iter().step_by(stride).copied().collect()
+}
 
-    thread::spawn(move || {
-        let mut player = MusicPlayer::new(receiver);
-        let mut current_app_state_for_generation = initial_app_state;
-        let mut actual_seed_for_current_song: u64;
+/* rms_level - Computes the RMS level of the audio around a playback position.
+ *
+ * inputs:
+ *     - audio_data (&[f32]): The song's audio samples.
+ *     - position (u64): The current playback position in samples.
+ *
+ * outputs:
+ *     - f32: The RMS of a `LEVEL_WINDOW_SAMPLES` window centered on `position`, or 0.0 past the end.
+ */
+fn rms_level(audio_data: &[f32], position: u64) -> f32 {
+    let center = (position as usize).min(audio_data.len());
+    let start = center.saturating_sub(LEVEL_WINDOW_SAMPLES / 2);
+    let end = (start + LEVEL_WINDOW_SAMPLES).min(audio_data.len());
+    if end <= start {
+        return 0.0;
+    }
+    let sum_of_squares: f32 = audio_data[start..end].iter().map(|s| s * s).sum();
+    (sum_of_squares / (end - start) as f32).sqrt()
+}
 
-        // Initial audio generation based on initial_app_state
-        {
-            let (audio_data, sample_rate, seed) =
-                generate_audio_from_state(&current_app_state_for_generation);
-            actual_seed_for_current_song = seed;
-            player.play_audio(audio_data, sample_rate); // Now auto-plays unless manually paused
-            let _ = progress_sender.send(MusicProgress {
-                // Send initial state
-                current_samples: 0,
-                total_samples: player.total_samples,
-                actual_seed: actual_seed_for_current_song,
-                app_state: Some(current_app_state_for_generation.clone()),
-            });
-        }
+/* CountedSource - A rodio source that counts the samples the output stream pulls from it.
+ *
+ * The sink stops pulling while it is paused and runs dry when rendering falls behind, so the
+ * count is the song position the listener has actually reached.
+ *
+ * fields:
+ *     - source (S): The wrapped audio.
+ *     - played (Arc<AtomicU64>): The counter every pulled sample is added to.
+ */
+struct CountedSource<S> {
+    source: S,
+    played: Arc<AtomicU64>,
+}
 
-        'service_loop: loop {
-            // Process all pending control messages first
-            loop {
-                match player.receiver.try_recv() {
-                    Ok(MusicControl::Pause) => {
-                        player.is_manually_paused = true;
-                        if !player.sink.is_paused() && player.playback_start_time.is_some() {
-                            let elapsed_since_last_play = player.playback_start_time.unwrap().elapsed();
-                            player.samples_played_at_pause = player.samples_played_at_pause
-                                .saturating_add((elapsed_since_last_play.as_secs_f32() * SAMPLE_RATE_PROGRESS) as u64)
-                                .min(player.total_samples);
-                            player.playback_start_time = None;
-                            
-                            // Send immediate update when pausing
-                            let _ = progress_sender.try_send(MusicProgress {
-                                current_samples: player.samples_played_at_pause,
-                                total_samples: player.total_samples,
-                                actual_seed: actual_seed_for_current_song,
-                                app_state: None,
-                            });
-                        }
-                        player.sink.pause();
-                    }
-                    Ok(MusicControl::Resume) => {
-                        player.is_manually_paused = false;
-                        if player.sink.is_paused() && player.total_samples > 0 {
-                            player.playback_start_time = Some(Instant::now());
-                            player.last_progress_update = Instant::now();
-                            player.sink.play();
-                            
-                            // Send immediate update when resuming
-                            let _ = progress_sender.try_send(MusicProgress {
-                                current_samples: player.samples_played_at_pause,
-                                total_samples: player.total_samples,
-                                actual_seed: actual_seed_for_current_song,
-                                app_state: None,
-                            });
-                        }
-                    }
-                    Ok(MusicControl::Rewind) => {
-                        if let (Some(audio_data_ref), Some(sample_rate_val)) =
-                            (&player.current_audio_data, player.current_sample_rate)
-                        {
-                            // Clone the audio data to pass to play_audio
-                            let audio_data_clone = audio_data_ref.clone();
-                            player.samples_played_at_pause = 0;
-                            player.play_audio(audio_data_clone, sample_rate_val); // Auto-plays unless manually paused
-
-                            let _ = progress_sender.send(MusicProgress {
-                                current_samples: 0,
-                                total_samples: player.total_samples,
-                                actual_seed: actual_seed_for_current_song,
-                                app_state: None,
-                            });
-                        }
-                    }
-                    Ok(MusicControl::Terminate) => {
-                        player.should_terminate = true;
-                        player.sink.stop();
-                        break 'service_loop;
-                    }
-                    Err(crossbeam_channel::TryRecvError::Empty) => {
-                        break; // No more messages, exit inner message loop
-                    }
-                    Err(crossbeam_channel::TryRecvError::Disconnected) => {
-                        player.should_terminate = true;
-                        break 'service_loop;
-                    }
-                }
-            }
+impl<S: Source<Item = f32>> Iterator for CountedSource<S> {
+    type Item = f32;
 
-            if !player.should_continue() {
-                break 'service_loop;
-            }
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+        self.played.fetch_add(1, Ordering::Relaxed);
+        Some(sample)
+    }
 
-            // Progress Reporting
-            if player.total_samples > 0 && !player.should_terminate {
-                let now = Instant::now();
-                let should_update = match (player.playback_start_time.is_some(), player.sink.is_paused()) {
-                    (true, false) => {
-                        // If playing, check if enough time has passed since last update
-                        now.duration_since(player.last_progress_update) >= PROGRESS_UPDATE_INTERVAL
-                    }
-                    (_, true) => {
-                        // If paused, only update if we haven't sent the paused state yet
-                        player.last_reported_samples != player.samples_played_at_pause
-                    }
-                    _ => false,
-                };
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
 
-                if should_update {
-                    let current_samples = if let Some(start_time) = player.playback_start_time {
-                        if player.sink.is_paused() {
-                            player.samples_played_at_pause
-                        } else {
-                            let elapsed = now.duration_since(start_time);
-                            let new_samples = player.samples_played_at_pause.saturating_add(
-                                (elapsed.as_secs_f64() * SAMPLE_RATE_PROGRESS as f64) as u64,
-                            );
-                            new_samples.min(player.total_samples)
-                        }
-                    } else {
-                        player.samples_played_at_pause
-                    };
+impl<S: Source<Item = f32>> Source for CountedSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
 
-                    // Always send updates when changing play/pause state
-                    // Otherwise, only send if we have a significant change in progress
-                    let last_samples = player.last_reported_samples;
-                    if player.sink.is_paused() != player.was_paused ||
-                       (current_samples as i64 - last_samples as i64).abs() as u64 > MIN_PROGRESS_DELTA
-                    {
-                        let _ = progress_sender.try_send(MusicProgress {
-                            current_samples,
-                            total_samples: player.total_samples,
-                            actual_seed: actual_seed_for_current_song,
-                            app_state: None,
-                        });
-                        player.last_reported_samples = current_samples;
-                        player.was_paused = player.sink.is_paused();
-                    }
-                    player.last_progress_update = now;
-                    
-                    // Check if we've reached the end of the current song
-                    if current_samples >= player.total_samples && !player.sink.is_paused() {
-                        player.sink.pause();
-                        player.playback_start_time = None;
-                        player.samples_played_at_pause = player.total_samples;
-
-                        // If not manually paused, generate a new song
-                        if !player.is_manually_paused {
-                            let new_app_state = if current_app_state_for_generation.is_random {
-                                // Create a completely new random state
-                                let mut rng = rand::thread_rng();
-                                let mut new_state = current_app_state_for_generation.clone();
-                                new_state.scale = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"]
-                                    .choose(&mut rng)
-                                    .unwrap()
-                                    .to_string();
-                                new_state.style = ["Pop", "Rock", "Jazz", "Blues", "Electronic", "Ambient", "Classical", "Folk", "Metal", "Reggae"]
-                                    .choose(&mut rng)
-                                    .unwrap()
-                                    .to_string();
-                                new_state.length = ["1 min", "2 min", "3 min", "5 min", "10 min"]
-                                    .choose(&mut rng)
-                                    .unwrap()
-                                    .to_string();
-                                new_state.bpm = rng.gen_range(60..180).to_string();
-                                new_state.seed = rand::random::<u64>().to_string();
-                                new_state
-                            } else {
-                                // Reuse the current state but with a new seed
-                                let mut new_state = current_app_state_for_generation.clone();
-                                new_state.seed = rand::random::<u64>().to_string();
-                                new_state
-                            };
-
-                            // Generate and play new audio
-                            let (audio_data, sample_rate, seed) = generate_audio_from_state(&new_app_state);
-                            actual_seed_for_current_song = seed;
-                            player.play_audio(audio_data, sample_rate);
-                            
-                            // Update the current app state
-                            current_app_state_for_generation = new_app_state;
-                            
-                            // Reset playback state
-                            player.is_manually_paused = false;
-                            player.samples_played_at_pause = 0;
-
-                            // Send progress update with new state
-                            let _ = progress_sender.send(MusicProgress {
-                                current_samples: 0,
-                                total_samples: player.total_samples,
-                                actual_seed: actual_seed_for_current_song,
-                                app_state: Some(current_app_state_for_generation.clone()),
-                            });
-                        }
-                    }
-                }
-            }
-            thread::sleep(Duration::from_millis(100));
-        }
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
 
-    });
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
 }
 
-/* parse_song_id_to_app_state - Parses a song ID string into an `AppState`.
+/* SharedSamples - A rodio source playing a buffer it shares with the player's `SongAudio`.
  *
- * The song ID format is expected to be "Scale-Style-BPM-Length-Seed", e.g., "C-Pop-120-5min-12345".
- * This function attempts to parse these components and construct an `AppState` suitable for
- * regenerating or loading the described song.
+ * fields:
+ *     - samples (Arc<Vec<f32>>): The buffer, mono at `SAMPLE_RATE`.
+ *     - next (usize): The index of the next sample to play.
+ */
+struct SharedSamples {
+    samples: Arc<Vec<f32>>,
+    next: usize,
+}
+
+impl SharedSamples {
+    /* new - Plays a shared buffer from one of its samples on.
+     *
+     * inputs:
+     *     - samples (Arc<Vec<f32>>): The buffer.
+     *     - from (usize): The index of the first sample to play.
+     *
+     * outputs:
+     *     - SharedSamples: The source.
+     */
+    fn new(samples: Arc<Vec<f32>>, from: usize) -> SharedSamples {
+        SharedSamples { samples, next: from }
+    }
+}
+
+impl Iterator for SharedSamples {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = *self.samples.get(self.next)?;
+        self.next += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.samples.len().saturating_sub(self.next);
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for SharedSamples {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let remaining = self.samples.len().saturating_sub(self.next);
+        Some(Duration::from_secs_f64(remaining as f64 / SAMPLE_RATE as f64))
+    }
+}
+
+/* SongAudio - The rendered audio of the current song, in the chunks it was queued in.
  *
- * inputs:
- *     - id_string (&str): The song ID string to parse.
+ * Each chunk is shared with the `SharedSamples` sources playing it, so a sample is held once
+ * however often it is queued: a seek or a repeat queues the same chunks again.
  *
- * outputs:
- *     - Result<AppState, String>: Ok with the parsed `AppState` if successful,
- *                               or an Err with a descriptive message if parsing fails.
+ * fields:
+ *     - chunks (VecDeque<(u64, Arc<Vec<f32>>)>): The song position and samples of every chunk held, in order.
+ *     - end (u64): The song position just past the last chunk.
  */
-pub fn parse_song_id_to_app_state(id_string: &str) -> Result<AppState, String> {
-    let parts: Vec<&str> = id_string.split('-').collect();
-    if parts.len() != 5 {
-        return Err(format!(
-            "Invalid Song ID: Expected 5 parts separated by '-'. Got {}. Format: Scale-Style-BPM-LengthInMinutes-Seed", 
-            parts.len()
-        ));
+#[derive(Default)]
+struct SongAudio {
+    chunks: VecDeque<(u64, Arc<Vec<f32>>)>,
+    end: u64,
+}
+
+impl SongAudio {
+    /* is_empty - Checks whether any audio is held.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: True if no chunk is held.
+     */
+    fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
     }
 
-    let scale = parts[0].to_string();
-    let style = parts[1].to_string();
-    let bpm_str = parts[2].to_string();
-    let length_minutes_str = parts[3];
-    let seed_str = parts[4].to_string();
+    /* start - Returns the song position the held audio starts at.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - u64: 0 unless an endless song has let go of its oldest chunks.
+     */
+    fn start(&self) -> u64 {
+        self.chunks.front().map_or(self.end, |(start, _)| *start)
+    }
 
-    if bpm_str.parse::<u32>().is_err() && !bpm_str.is_empty() {
-        return Err(format!(
-            "Invalid BPM in Song ID: '{}' is not a valid number. Format: Scale-Style-BPM-LengthInMinutes-Seed", 
-            bpm_str
-        ));
+    /* end - Returns the song position just past the held audio.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - u64: How much of the song has been rendered.
+     */
+    fn end(&self) -> u64 {
+        self.end
     }
 
-    let length_in_mins = match length_minutes_str.parse::<u32>() {
-        Ok(mins) => format!("{} min", mins),
-        Err(_) => {
-            return Err(format!(
-                "Invalid Length in Song ID: '{}' is not a valid number of minutes. Format: Scale-Style-BPM-LengthInMinutes-Seed", 
-                length_minutes_str
-            ));
+    /* push - Adds the next chunk of the song.
+     *
+     * inputs:
+     *     - &mut self
+     *     - samples (Arc<Vec<f32>>): The chunk, which starts where the held audio ends.
+     *
+     * outputs:
+     *     - None
+     */
+    fn push(&mut self, samples: Arc<Vec<f32>>) {
+        if samples.is_empty() {
+            return;
         }
-    };
-
-    if seed_str.parse::<u64>().is_err() && !seed_str.is_empty() {
-        return Err(format!(
-           "Invalid Seed in Song ID: '{}' is not a valid number. Format: Scale-Style-BPM-LengthInMinutes-Seed", 
-           seed_str
-        ));
+        let start = self.end;
+        self.end += samples.len() as u64;
+        self.chunks.push_back((start, samples));
     }
 
-    Ok(AppState {
-        scale,
-        style,
-        bpm: bpm_str,
-        length: length_in_mins,
-        seed: seed_str,
-        ..Default::default()
-    })
+    /* forget_before - Lets go of the chunks that end before a song position.
+     *
+     * inputs:
+     *     - &mut self
+     *     - position (u64): The earliest position to keep.
+     *
+     * outputs:
+     *     - None
+     */
+    fn forget_before(&mut self, position: u64) {
+        while self
+            .chunks
+            .front()
+            .is_some_and(|(start, samples)| start + samples.len() as u64 <= position)
+        {
+            self.chunks.pop_front();
+        }
+    }
+
+    /* sources_from - Returns sources that play the held audio from a song position on.
+     *
+     * inputs:
+     *     - &self
+     *     - position (u64): Where to start, within the held audio.
+     *
+     * outputs:
+     *     - Vec<SharedSamples>: One source per chunk, to be queued in order.
+     */
+    fn sources_from(&self, position: u64) -> Vec<SharedSamples> {
+        self.chunks
+            .iter()
+            .filter(|(start, samples)| start + samples.len() as u64 > position)
+            .map(|(start, samples)| SharedSamples::new(Arc::clone(samples), position.saturating_sub(*start) as usize))
+            .collect()
+    }
+
+    /* samples - Returns every held sample, in order.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - impl Iterator<Item = f32>: The samples.
+     */
+    fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.chunks.iter().flat_map(|(_, samples)| samples.iter().copied())
+    }
+
+    /* window - Copies the audio around a song position.
+     *
+     * inputs:
+     *     - &self
+     *     - position (u64): The song position, clamped to the held audio.
+     *     - radius (u64): How many samples to copy on either side, where there are any.
+     *
+     * outputs:
+     *     - (Vec<f32>, u64): The copied samples and the position's index in them.
+     */
+    fn window(&self, position: u64, radius: u64) -> (Vec<f32>, u64) {
+        let position = position.clamp(self.start(), self.end);
+        let from = position.saturating_sub(radius).max(self.start());
+        let to = (position + radius).min(self.end);
+        let mut window = Vec::with_capacity((to - from) as usize);
+        for (start, samples) in &self.chunks {
+            let end = start + samples.len() as u64;
+            if end > from && *start < to {
+                window.extend_from_slice(&samples[(from.max(*start) - start) as usize..(to.min(end) - start) as usize]);
+            }
+        }
+        (window, position - from)
+    }
+}
+
+/* AudioOutput - Where a music service plays its audio.
+ */
+#[derive(Clone)]
+pub enum AudioOutput {
+    Device, // The default output device
+    // Sinks nothing plays, each handed over through the channel so a test can pull its samples;
+    // without a channel, opening the output fails as if there were no device
+    #[cfg(test)]
+    Fake(Option<CrossbeamSender<SourcesQueueOutput<f32>>>),
+}
+
+/* OpenOutput - An opened `AudioOutput`, which the player's sinks are created on.
+ */
+enum OpenOutput {
+    Device {
+        _stream: OutputStream, // Held to keep audio active
+        handle: OutputStreamHandle,
+    },
+    #[cfg(test)]
+    Fake(CrossbeamSender<SourcesQueueOutput<f32>>),
+}
+
+impl OpenOutput {
+    /* open - Opens an audio output.
+     *
+     * inputs:
+     *     - output (AudioOutput): The output to open.
+     *
+     * outputs:
+     *     - Result<Self, String>: The opened output, or why it could not be opened.
+     */
+    fn open(output: AudioOutput) -> Result<Self, String> {
+        match output {
+            AudioOutput::Device => OutputStream::try_default()
+                .map(|(_stream, handle)| OpenOutput::Device { _stream, handle })
+                .map_err(|e| format!("No audio output available: {}", e)),
+            #[cfg(test)]
+            AudioOutput::Fake(Some(sender)) => Ok(OpenOutput::Fake(sender)),
+            #[cfg(test)]
+            AudioOutput::Fake(None) => Err("No audio output available: no fake device".to_string()),
+        }
+    }
+
+    /* new_sink - Creates a paused sink on the output.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Result<Sink, String>: The sink, or why it could not be created.
+     */
+    fn new_sink(&self) -> Result<Sink, String> {
+        let sink = match self {
+            OpenOutput::Device { handle, .. } => {
+                Sink::try_new(handle).map_err(|e| format!("Failed to create audio sink: {}", e))?
+            }
+            #[cfg(test)]
+            OpenOutput::Fake(sender) => {
+                let (sink, queue) = Sink::new_idle();
+                let _ = sender.send(queue);
+                sink
+            }
+        };
+        sink.pause();
+        Ok(sink)
+    }
+}
+
+/* MusicPlayer - Manages audio playback state and hardware interaction.
+ *
+ * This struct encapsulates the Rodio sink and stream, handles playback control messages,
+ * and keeps track of the current audio data and playback position.
+ *
+ * fields:
+ *     - receiver (CrossbeamReceiver<MusicControl>): Receives control messages.
+ *     - sink (Sink): The Rodio audio sink for playing samples.
+ *     - output (OpenOutput): The audio output, also used to open a second sink for crossfades.
+ *     - audio (SongAudio): The rendered audio of the current song, shared with the sink.
+ *     - total_samples (u64): Length of the current song in samples, as far as it is known.
+ *     - played (Arc<AtomicU64>): The playback position, counted by the `CountedSource`s queued
+ *       on the sink; replaced whenever the sink is restarted so stale audio can't move it.
+ *     - should_terminate (bool): Flag to signal the playback loop to exit.
+ *     - is_manually_paused (bool): Tracks whether the user explicitly paused playback.
+ *     - bpm (u32): The resolved tempo of the current song, used to locate beats.
+ *     - beats_per_bar (u32): Beats in a bar of the current song, reported with every beat.
+ *     - chord_timeline (Vec<(u64, String)>): Chord start samples and names of the current song.
+ *     - section_timeline (Vec<(u64, SongSection)>): Section start samples of the current song.
+ *     - loudness_db (Option<f32>): Loudness of the current song, once it is fully rendered.
+ *     - is_looping (bool): True if the current song repeats instead of moving on to a new one.
+ *     - loop_queued (bool): True once another copy of the song has been queued behind the playing one.
+ *     - crossfade (Duration): How long the current song overlaps the next one (zero = no crossfade).
+ *     - fade_in (Option<Duration>): Fade applied to the next audio handed to `play_audio`.
+ *     - fading_sink (Option<(Sink, VolumeRamp)>): The previous song, fading out under the current one.
+ *     - fade_out (Option<VolumeRamp>): Set when the whole service is fading out before terminating.
+ *     - stop_after_current (bool): True if the service ends with the current song, overriding looping.
+ *     - stream (Option<SongStream>): Set while the current song is still being rendered (see
+ *       `stream_sections`); an endless song always is.
+ *     - generation (u64): The service id stamped on every progress report.
+ *     - stop_requested (Arc<AtomicBool>): Set by the controller along with a Terminate, so a
+ *       service still rendering when it gives up waiting never starts playing.
+ */
+pub struct MusicPlayer {
+    receiver: CrossbeamReceiver<MusicControl>,
+    sink: Sink,
+    output: OpenOutput,
+    audio: SongAudio,
+    total_samples: u64,
+    played: Arc<AtomicU64>,
+    should_terminate: bool,
+    is_manually_paused: bool,
+    last_progress_update: Instant,
+    was_paused: bool,
+    last_reported_samples: u64,
+    bpm: u32,
+    beats_per_bar: u32,
+    chord_timeline: Vec<(u64, String)>,
+    section_timeline: Vec<(u64, SongSection)>,
+    loudness_db: Option<f32>,
+    is_looping: bool,
+    loop_queued: bool,
+    crossfade: Duration,
+    fade_in: Option<Duration>,
+    fading_sink: Option<(Sink, VolumeRamp)>,
+    fade_out: Option<VolumeRamp>,
+    stop_after_current: bool,
+    stream: Option<SongStream>,
+    generation: u64,
+    stop_requested: Arc<AtomicBool>,
+}
+
+/* SongStream - A song's generator, and the section it is rendering.
+ *
+ * fields:
+ *     - generator (Option<SongGenerator>): The song's generator, while no section is being rendered.
+ *     - pending (Option<CrossbeamReceiver<(SongGenerator, Option<Vec<f32>>)>>): Delivers the next
+ *       section (None once the song is done), and the generator back, from the worker pool.
+ *     - task (Option<TaskHandle>): Keeps the queued section wanted; dropping it cancels the
+ *       render if it has not started yet.
+ *     - endless (bool): True for an endless song, which is never done.
+ */
+struct SongStream {
+    generator: Option<SongGenerator>,
+    pending: Option<CrossbeamReceiver<(SongGenerator, Option<Vec<f32>>)>>,
+    task: Option<TaskHandle>,
+    endless: bool,
+}
+
+impl MusicPlayer {
+    /* new - Creates a new `MusicPlayer` instance.
+     *
+     * Opens the audio output and a sink on it, preparing for playback.
+     * The sink starts in a paused state, but is_manually_paused is false
+     * (meaning it will auto-play when audio is loaded).
+     *
+     * inputs:
+     *     - receiver (CrossbeamReceiver<MusicControl>): Channel to receive playback control messages.
+     *     - output (AudioOutput): Where to play the audio.
+     *
+     * outputs:
+     *     - Result<Self, String>: A new `MusicPlayer` instance, or why the output could not be opened.
+     */
+    pub fn new(receiver: CrossbeamReceiver<MusicControl>, output: AudioOutput) -> Result<Self, String> {
+        let output = OpenOutput::open(output)?;
+        let sink = output.new_sink()?;
+        Ok(MusicPlayer {
+            receiver,
+            sink,
+            output,
+            audio: SongAudio::default(),
+            total_samples: 0,
+            played: Arc::new(AtomicU64::new(0)),
+            should_terminate: false,
+            is_manually_paused: false,
+            last_progress_update: Instant::now(),
+            was_paused: false,
+            last_reported_samples: 0,
+            bpm: 0,
+            beats_per_bar: progs::TimeSignature::default().beats_per_bar() as u32,
+            chord_timeline: Vec::new(),
+            section_timeline: Vec::new(),
+            loudness_db: None,
+            is_looping: false,
+            loop_queued: false,
+            crossfade: Duration::ZERO,
+            fade_in: None,
+            fading_sink: None,
+            fade_out: None,
+            stop_after_current: false,
+            stream: None,
+            generation: 0,
+            stop_requested: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /* play_audio - Loads new audio data into the player and prepares it for playback.
+     *
+     * Stops any currently playing audio, replaces it with the new data, and resets
+     * playback progress. If not manually paused, playback will start automatically.
+     * The sink plays the same buffer the player keeps for seeking and looping.
+     *
+     * inputs:
+     *     - &mut self
+     *     - audio_data (Arc<Vec<f32>>): The raw audio samples to play, at `SAMPLE_RATE`.
+     *     - total_samples (u64): Length of the whole song, which is longer than `audio_data` while
+     *       only its opening has been rendered.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn play_audio(&mut self, audio_data: Arc<Vec<f32>>, total_samples: u64) {
+        self.sink.stop();
+
+        let source = SharedSamples::new(Arc::clone(&audio_data), 0);
+        self.audio = SongAudio::default();
+        self.audio.push(audio_data);
+        self.total_samples = self.audio.end().max(total_samples);
+        self.restart_count(0);
+        self.loop_queued = false; // Stopping the sink dropped any queued repeat
+        self.stream = None;
+
+        match self.fade_in.take() {
+            Some(fade) => self.sink.append(self.counted(source.fade_in(fade))),
+            None => self.sink.append(self.counted(source)),
+        }
+
+        // Auto-play unless manually paused
+        if self.should_auto_play() && self.total_samples > 0 {
+            self.last_progress_update = Instant::now();
+            self.sink.play();
+        }
+    }
+
+    /* start_song - Generates a song and starts playing it as soon as its opening is ready.
+     *
+     * Only the first section is rendered before playback starts; `stream_sections` has the
+     * worker pool render the rest while it plays. Any section still pending for the previous
+     * song is abandoned.
+     *
+     * inputs:
+     *     - &mut self
+     *     - app_state (&AppState): The parameters of the song to generate.
+     *
+     * outputs:
+     *     - u64: The seed the song was generated with.
+     */
+    fn start_song(&mut self, app_state: &AppState) -> u64 {
+        let seed = resolve_seed(app_state);
+        self.start_stream(app_state, seed);
+        seed
+    }
+
+    /* start_stream - Starts a song from its first section.
+     *
+     * The first section is rendered right away and played; `stream_sections` has the worker
+     * pool render each one after it.
+     *
+     * inputs:
+     *     - &mut self
+     *     - app_state (&AppState): The parameters of the song.
+     *     - seed (u64): The seed to generate with.
+     *
+     * outputs:
+     *     - None
+     */
+    fn start_stream(&mut self, app_state: &AppState, seed: u64) {
+        let endless = app_state.is_endless();
+        let mut generator = SongGenerator::new(app_state, seed, None, endless);
+        let first_section = generator.next_section().unwrap_or_default();
+        self.bpm = generator.bpm;
+        self.beats_per_bar = generator.beats_per_bar as u32;
+        self.chord_timeline = generator.chord_timeline.clone();
+        self.section_timeline = generator.section_timeline.clone();
+        self.loudness_db = None; // Measured once the whole song is rendered
+        // An endless song's length grows with every section it queues
+        let song_len = generator.song_len.unwrap_or(0) as u64;
+        self.play_audio(Arc::new(first_section), song_len);
+        self.stream = Some(SongStream {
+            generator: Some(generator),
+            pending: None,
+            task: None,
+            endless,
+        });
+    }
+
+    /* stream_sections - Keeps a song's audio queued ahead of playback while it is being rendered.
+     *
+     * Queues a section once the worker pool has rendered it, and has the next one rendered right
+     * away; an endless song waits until less than `ENDLESS_QUEUE_AHEAD_SAMPLES` is left to play,
+     * and lets go of sections that finished more than `ENDLESS_HISTORY_SAMPLES` ago, so it can play
+     * for hours. Once a song is fully rendered its loudness is measured. If the pool has shut
+     * down, the song ends when its queued audio runs out.
+     *
+     * inputs:
+     *     - &mut self
+     *     - current_samples (u64): The current playback position.
+     *
+     * outputs:
+     *     - bool: True if a section was queued, which may extend the timelines.
+     */
+    fn stream_sections(&mut self, current_samples: u64) -> bool {
+        let Some(stream) = self.stream.as_mut() else {
+            return false;
+        };
+        match stream.pending.as_ref().map(|receiver| receiver.try_recv()) {
+            Some(Ok((generator, Some(section)))) => {
+                stream.pending = None;
+                stream.task = None;
+                self.chord_timeline = generator.chord_timeline.clone();
+                self.section_timeline = generator.section_timeline.clone();
+                stream.generator = Some(generator);
+                let endless = stream.endless;
+                // Queued behind the previous section, so it starts on the exact next sample
+                let section = Arc::new(section);
+                self.sink.append(self.counted(SharedSamples::new(Arc::clone(&section), 0)));
+                self.audio.push(section);
+                if endless {
+                    self.total_samples = self.audio.end();
+                    self.audio.forget_before(current_samples.saturating_sub(ENDLESS_HISTORY_SAMPLES));
+                }
+                true
+            }
+            Some(Ok((_, None))) => {
+                self.stream = None;
+                self.loudness_db = dsp::integrated_loudness(self.audio.samples(), SAMPLE_RATE as f32);
+                false
+            }
+            Some(Err(crossbeam_channel::TryRecvError::Disconnected)) => {
+                self.stream = None;
+                self.total_samples = self.audio.end();
+                false
+            }
+            Some(Err(crossbeam_channel::TryRecvError::Empty)) => false,
+            None => {
+                if stream.endless
+                    && self.total_samples.saturating_sub(current_samples) > ENDLESS_QUEUE_AHEAD_SAMPLES
+                {
+                    return false;
+                }
+                let Some(mut generator) = stream.generator.take() else {
+                    return false;
+                };
+                let (section_sender, section_receiver) = crossbeam_channel::bounded(1);
+                // If the pool has shut down the job is dropped, which disconnects the receiver
+                stream.task = workers::global().submit(move || {
+                    let section = generator.next_section();
+                    // The receiver is gone if the song was skipped or the service stopped
+                    let _ = section_sender.send((generator, section));
+                });
+                stream.pending = Some(section_receiver);
+                false
+            }
+        }
+    }
+
+    /* is_endless - Checks whether an endless song is playing.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: True while the current song is endless and still streaming.
+     */
+    fn is_endless(&self) -> bool {
+        self.stream.as_ref().is_some_and(|stream| stream.endless)
+    }
+
+    /* start_prepared_song - Plays a song that was rendered ahead of time.
+     *
+     * inputs:
+     *     - &mut self
+     *     - song (&PreparedSong): The rendered song.
+     *
+     * outputs:
+     *     - u64: The seed the song was generated with.
+     */
+    fn start_prepared_song(&mut self, song: &PreparedSong) -> u64 {
+        self.bpm = song.render.bpm;
+        self.beats_per_bar = song.render.beats_per_bar;
+        self.chord_timeline = song.render.chord_timeline.clone();
+        self.section_timeline = song.render.section_timeline.clone();
+        self.loudness_db = song.render.loudness_db;
+        self.play_audio(Arc::clone(&song.render.samples), song.render.song_len as u64);
+        song.seed
+    }
+
+    /* seek - Continues the current song from another position.
+     *
+     * Whatever is rendered from `position` on is queued in place of the playing audio; if the
+     * song is still being rendered, `stream_sections` appends the rest as usual.
+     *
+     * inputs:
+     *     - &mut self
+     *     - position (u64): The sample to continue from, clamped to the rendered audio (for an
+     *       endless song, what it still holds).
+     *
+     * outputs:
+     *     - u64: The position playback continues from.
+     */
+    fn seek(&mut self, position: u64) -> u64 {
+        if self.audio.is_empty() {
+            return 0;
+        }
+        let position = position.clamp(self.audio.start(), self.audio.end());
+        self.sink.stop();
+        self.loop_queued = false; // Stopping the sink dropped any queued repeat
+        self.restart_count(position);
+        for source in self.audio.sources_from(position) {
+            self.sink.append(self.counted(source));
+        }
+
+        // Auto-play unless manually paused, like `play_audio`
+        if self.should_auto_play() {
+            self.last_progress_update = Instant::now();
+            self.sink.play();
+        }
+        position
+    }
+
+    /* queue_loop - Queues another copy of the song shortly before the playing one ends.
+     *
+     * Queuing ahead (rather than at the end) keeps the repeat gapless even though the
+     * service loop only wakes every 100ms. The copy is only queued once the whole song
+     * has been rendered, so its opening is never looped on its own.
+     *
+     * inputs:
+     *     - &mut self
+     *     - current_samples (u64): The current playback position.
+     *
+     * outputs:
+     *     - None
+     */
+    fn queue_loop(&mut self, current_samples: u64) {
+        if !self.is_looping
+            || self.stop_after_current
+            || self.loop_queued
+            || self.sink.is_paused()
+            || self.stream.is_some()
+        {
+            return;
+        }
+        if self.audio.is_empty()
+            || self.audio.end() < self.total_samples
+            || self.total_samples.saturating_sub(current_samples) > LOOP_QUEUE_AHEAD_SAMPLES
+        {
+            return;
+        }
+        // Reuse the rendered chunks; the song is never generated, or copied, again
+        for source in self.audio.sources_from(0) {
+            self.sink.append(self.counted(source));
+        }
+        self.loop_queued = true;
+    }
+
+    /* counted - Wraps audio for the sink so the samples it plays advance the position.
+     *
+     * inputs:
+     *     - &self
+     *     - source (S): The audio to queue next.
+     *
+     * outputs:
+     *     - CountedSource<S>: The audio, counting into the current position.
+     */
+    fn counted<S: Source<Item = f32>>(&self, source: S) -> CountedSource<S> {
+        CountedSource {
+            source,
+            played: Arc::clone(&self.played),
+        }
+    }
+
+    /* restart_count - Starts counting the position afresh after the sink was stopped.
+     *
+     * A new counter is used rather than resetting the old one, so samples the output stream
+     * still pulls from the stopped audio can't move the new position.
+     *
+     * inputs:
+     *     - &mut self
+     *     - position (u64): The song position the next queued audio starts at.
+     *
+     * outputs:
+     *     - None
+     */
+    fn restart_count(&mut self, position: u64) {
+        self.played = Arc::new(AtomicU64::new(position));
+    }
+
+    /* position - Returns how far into the current song the output stream has played.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - u64: Samples pulled since the song started, past `total_samples` once a queued
+     *       repeat has begun.
+     */
+    fn position(&self) -> u64 {
+        self.played.load(Ordering::Relaxed)
+    }
+
+    /* progress_message - Builds a `MusicProgress` report for the current song.
+     *
+     * inputs:
+     *     - &self
+     *     - current_samples (u64): The playback position to report.
+     *     - actual_seed (u64): The seed used to generate the current song.
+     *     - app_state (Option<AppState>): The app state to report, when a new song starts; it is
+     *       reported with the song's effective parameters (see `effective_app_state`).
+     *
+     * outputs:
+     *     - MusicProgress: The progress report, including the current output level, waveform and spectrum.
+     */
+    fn progress_message(
+        &self,
+        current_samples: u64,
+        actual_seed: u64,
+        app_state: Option<AppState>,
+    ) -> MusicProgress {
+        let (level, scope, spectrum) = if !self.audio.is_empty() && !self.is_manually_paused && !self.sink.is_paused() {
+            let volume = self.sink.volume();
+            let (audio_data, position) = self.audio.window(current_samples, ANALYSIS_RADIUS_SAMPLES as u64);
+            let scope = scope_window(&audio_data, position)
+                .into_iter()
+                .map(|sample| sample * volume)
+                .collect();
+            let spectrum = spectrum_bands(&audio_data, position)
+                .into_iter()
+                .map(|magnitude| (magnitude * volume).min(1.0))
+                .collect();
+            (rms_level(&audio_data, position) * volume, scope, spectrum)
+        } else {
+            (0.0, Vec::new(), Vec::new())
+        };
+        // Derived from the sample position, so beats stay aligned across pause, resume and rewind
+        let beats = if self.bpm > 0 {
+            current_samples as f64 * self.bpm as f64 / (60.0 * SAMPLE_RATE as f64)
+        } else {
+            0.0
+        };
+        MusicProgress {
+            current_samples,
+            total_samples: self.total_samples,
+            endless: self.is_endless(),
+            actual_seed,
+            level,
+            scope,
+            spectrum,
+            current_beat: beats.floor() as u32,
+            beat_phase: beats.fract() as f32,
+            beats_per_bar: self.beats_per_bar,
+            bpm: self.bpm,
+            chord_timeline: app_state.is_some().then(|| self.chord_timeline.clone()),
+            section_timeline: app_state.is_some().then(|| self.section_timeline.clone()),
+            loudness_db: self.loudness_db,
+            app_state: app_state.map(|app_state| effective_app_state(&app_state)),
+            generating: false,
+            generation: self.generation,
+            error: None,
+        }
+    }
+
+    /* generating_message - Builds the report sent before a new song is generated.
+     *
+     * inputs:
+     *     - &self
+     *     - actual_seed (u64): The seed of the song playing until now.
+     *
+     * outputs:
+     *     - MusicProgress: A report with `generating` set and no song in it.
+     */
+    fn generating_message(&self, actual_seed: u64) -> MusicProgress {
+        let mut progress = self.progress_message(0, actual_seed, None);
+        progress.total_samples = 0;
+        progress.endless = false;
+        progress.level = 0.0;
+        progress.scope.clear();
+        progress.spectrum.clear();
+        progress.generating = true;
+        progress
+    }
+
+    /* timeline_message - Builds a progress report that also carries the current timelines.
+     *
+     * Sent when a song's timelines may have grown, or an endless song starts over.
+     *
+     * inputs:
+     *     - &self
+     *     - current_samples (u64): The playback position to report.
+     *     - actual_seed (u64): The seed used to generate the current song.
+     *
+     * outputs:
+     *     - MusicProgress: The progress report, with `chord_timeline` and `section_timeline` set.
+     */
+    fn timeline_message(&self, current_samples: u64, actual_seed: u64) -> MusicProgress {
+        let mut progress = self.progress_message(current_samples, actual_seed, None);
+        progress.chord_timeline = Some(self.chord_timeline.clone());
+        progress.section_timeline = Some(self.section_timeline.clone());
+        progress
+    }
+
+    /* advance - Starts the song that follows the current one.
+     *
+     * inputs:
+     *     - &mut self
+     *     - next_song (Option<Arc<PreparedSong>>): The pre-rendered next song, if the controller sent one.
+     *     - current (&AppState): The parameters of the current song.
+     *
+     * outputs:
+     *     - (AppState, u64): The parameters of the new song and the seed it was generated with.
+     */
+    fn advance(&mut self, next_song: Option<Arc<PreparedSong>>, current: &AppState) -> (AppState, u64) {
+        let (mut app_state, seed) = match next_song {
+            Some(song) => {
+                let seed = self.start_prepared_song(&song);
+                (song.app_state.clone(), seed)
+            }
+            None => {
+                let app_state = controller::next_song_state(current);
+                let seed = self.start_song(&app_state);
+                (app_state, seed)
+            }
+        };
+        // A prepared song may predate a loop or crossfade change
+        app_state.is_looping = self.is_looping;
+        app_state.crossfade_secs = self.crossfade.as_secs() as u32;
+        (app_state, seed)
+    }
+
+    /* should_crossfade - Checks whether the next song should start fading in now.
+     *
+     * inputs:
+     *     - &self
+     *     - current_samples (u64): The current playback position.
+     *
+     * outputs:
+     *     - bool: True if crossfading is on and the song is within the crossfade of its end.
+     */
+    fn should_crossfade(&self, current_samples: u64) -> bool {
+        let crossfade_samples = (self.crossfade.as_secs_f64() * SAMPLE_RATE as f64) as u64;
+        let fully_rendered = !self.audio.is_empty() && self.audio.end() >= self.total_samples;
+        crossfade_samples > 0
+            && fully_rendered
+            && !self.is_endless()
+            && !self.is_looping
+            && !self.stop_after_current
+            && !self.is_manually_paused
+            && self.fading_sink.is_none()
+            && self.fade_out.is_none()
+            && self.total_samples > 2 * crossfade_samples
+            && self.total_samples.saturating_sub(current_samples) <= crossfade_samples
+    }
+
+    /* begin_crossfade - Moves the current song to a fading sink, leaving a fresh sink for the next one.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - bool: True if the crossfade started; false if no second sink could be opened.
+     */
+    fn begin_crossfade(&mut self) -> bool {
+        let Ok(next_sink) = self.output.new_sink() else {
+            return false;
+        };
+        next_sink.set_volume(self.sink.volume());
+        let outgoing = std::mem::replace(&mut self.sink, next_sink);
+        let ramp = VolumeRamp::new(self.crossfade, outgoing.volume());
+        self.fading_sink = Some((outgoing, ramp));
+        self.fade_in = Some(self.crossfade);
+        true
+    }
+
+    /* step_fades - Updates the volume of anything fading out.
+     *
+     * A finished crossfade drops the outgoing sink; a finished service fade-out terminates the service.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    fn step_fades(&mut self) {
+        if let Some((sink, ramp)) = &self.fading_sink {
+            match ramp.volume() {
+                Some(volume) => sink.set_volume(volume),
+                None => self.fading_sink = None, // Dropping the sink stops it
+            }
+        }
+        if let Some(ramp) = &self.fade_out {
+            match ramp.volume() {
+                Some(volume) => self.sink.set_volume(volume),
+                None => {
+                    self.sink.stop();
+                    self.should_terminate = true;
+                }
+            }
+        }
+    }
+
+    /* is_fading - Checks whether a fade is in progress.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: True while a crossfade or service fade-out is running.
+     */
+    fn is_fading(&self) -> bool {
+        self.fading_sink.is_some() || self.fade_out.is_some()
+    }
+
+    /* should_auto_play - Checks whether newly queued audio should start playing right away.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: False while manually paused, or once the controller has asked the service to stop.
+     */
+    fn should_auto_play(&self) -> bool {
+        !self.is_manually_paused && !self.stop_requested.load(Ordering::SeqCst)
+    }
+
+    /* should_continue - Checks if the music service should continue its playback loop.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: True if the service should continue, false if termination is requested.
+     */
+    pub fn should_continue(&self) -> bool {
+        !self.should_terminate
+    }
+}
+
+// LOOP_QUEUE_AHEAD_SAMPLES: How close to the end of a looping song its next repeat is queued (0.5s).
+const LOOP_QUEUE_AHEAD_SAMPLES: u64 = SAMPLE_RATE as u64 / 2;
+
+// ENDLESS_QUEUE_AHEAD_SAMPLES: How little of an endless song may be left queued before its next
+// section is rendered (15s), leaving the worker pool plenty of time.
+const ENDLESS_QUEUE_AHEAD_SAMPLES: u64 = SAMPLE_RATE as u64 * 15;
+// ENDLESS_HISTORY_SAMPLES: How long an endless song keeps audio that has played (1 min), for
+// rewinding to a section start.
+const ENDLESS_HISTORY_SAMPLES: u64 = SAMPLE_RATE as u64 * 60;
+
+// Peak amplitude of the chord and bass tracks before their gain, which metal's drive keeps:
+// chords average sines at 0.4, and bass sines sit at 0.6.
+const CHORD_TRACK_PEAK: f32 = 0.4;
+const BASS_TRACK_PEAK: f32 = 0.6;
+
+// MASTER_TARGET_RMS_DB: The loudness the master bus brings every song toward, as an RMS level,
+// so songs played back to back sound about as loud as each other.
+const MASTER_TARGET_RMS_DB: f32 = -16.0;
+// MASTER_MAX_MAKEUP_DB: How far the master bus may raise or lower a song to reach the target.
+const MASTER_MAX_MAKEUP_DB: f32 = 6.0;
+// MASTER_GATE_DB: Below this loudness the master bus holds its gain, so rests aren't pumped up.
+const MASTER_GATE_DB: f32 = -40.0;
+// MASTER_LEVELER_SECONDS / MASTER_LEVELER_SLEW_DB: How long the master bus listens to judge
+// the loudness, and the most its gain moves in a second, slow enough to keep a chorus's lift.
+const MASTER_LEVELER_SECONDS: f32 = 3.0;
+const MASTER_LEVELER_SLEW_DB: f32 = 1.0;
+// LIMITER_THRESHOLD_DB / LIMITER_KNEE_DB: The peak level the master limiter holds the mix to,
+// and the width of its soft knee around it.
+const LIMITER_THRESHOLD_DB: f32 = -1.0;
+const LIMITER_KNEE_DB: f32 = 4.0;
+// LIMITER_ATTACK_SECONDS / LIMITER_RELEASE_SECONDS: How fast the limiter clamps down on a peak
+// and lets go after it.
+const LIMITER_ATTACK_SECONDS: f32 = 0.005;
+const LIMITER_RELEASE_SECONDS: f32 = 0.1;
+
+// AMBIENT_CHORD_STRETCH: Ambient chords last this many times the usual chord length (picked per song).
+const AMBIENT_CHORD_STRETCH: std::ops::RangeInclusive<u32> = 2..=4;
+// AMBIENT_CHORD_ENVELOPE: Multi-second swells that turn ambient chords into pads.
+const AMBIENT_CHORD_ENVELOPE: progs::ChordEnvelope = progs::ChordEnvelope {
+    attack_seconds: 2.0,
+    release_seconds: 2.0,
+};
+// AMBIENT_MELODY_LEVEL: Ambient melodies sit this far under the mixer's melody gain.
+const AMBIENT_MELODY_LEVEL: f32 = 0.5;
+// METAL_DRIVE: How hard metal's chord and bass tracks are pushed into `soft_clip`.
+const METAL_DRIVE: f32 = 4.0;
+// CLASSICAL_SIXTEENTHS_MAX_BPM: Up to this tempo the classical Alberti bass runs in sixteenth
+// notes; faster songs break their chords in eighths.
+const CLASSICAL_SIXTEENTHS_MAX_BPM: u32 = 100;
+// CHIP_ARP_RATE_HZ: Arp chords step through their notes at a 50 Hz frame rate, like the music
+// of 8-bit consoles and home computers.
+const CHIP_ARP_RATE_HZ: f32 = 50.0;
+
+/* SongSection - One block of a song's arrangement (see `plan_sections`).
+ *
+ * Every section is a whole number of progression cycles, so sections change on a chord change;
+ * the last one also plays the song's closing cadence.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SongSection {
+    Intro { melody: bool }, // The melody alone (true) or the chords alone (false).
+    Verse,                  // The full arrangement.
+    Chorus,                 // The full arrangement, louder, with the melody an octave up.
+    Bridge,                 // The full arrangement in the relative key.
+    Outro,                  // The full arrangement, fading down into the closing cadence.
+}
+
+impl SongSection {
+    /* label - Returns the section's display name.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - &'static str: "Intro", "Verse", "Chorus", "Bridge" or "Outro".
+     */
+    pub fn label(self) -> &'static str {
+        match self {
+            SongSection::Intro { .. } => "Intro",
+            SongSection::Verse => "Verse",
+            SongSection::Chorus => "Chorus",
+            SongSection::Bridge => "Bridge",
+            SongSection::Outro => "Outro",
+        }
+    }
+
+    /* key - Returns the key the section plays in.
+     *
+     * The bridge moves to the relative key (the relative minor of a major key, and the
+     * relative major of a minor one); every other section stays in the song's key.
+     *
+     * inputs:
+     *     - self
+     *     - root (u8): The song's root note (0-11).
+     *     - minor (bool): True if the song is in a minor key.
+     *
+     * outputs:
+     *     - (u8, bool): The section's root note (0-11) and whether it is minor.
+     */
+    fn key(self, root: u8, minor: bool) -> (u8, bool) {
+        match (self, minor) {
+            (SongSection::Bridge, false) => ((root + 9) % 12, true),
+            (SongSection::Bridge, true) => ((root + 3) % 12, false),
+            _ => (root, minor),
+        }
+    }
+
+    /* seed - Returns the seed the section's melody and bass are generated with.
+     *
+     * Sections of the same kind share a seed, so every verse (and the outro) plays the same
+     * tune, as does every chorus; the melodic intro previews the chorus.
+     *
+     * inputs:
+     *     - self
+     *     - song_seed (u64): The song's seed, or the melody or bass seed when it was varied.
+     *
+     * outputs:
+     *     - u64: The section's seed.
+     */
+    fn seed(self, song_seed: u64) -> u64 {
+        match self {
+            SongSection::Verse | SongSection::Outro => song_seed,
+            SongSection::Chorus | SongSection::Intro { .. } => song_seed.wrapping_add(1),
+            SongSection::Bridge => song_seed.wrapping_add(2),
+        }
+    }
+
+    /* octave_lift - Returns how many octaves the section raises its melody by.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - i8: 1 for the chorus, 0 otherwise.
+     */
+    fn octave_lift(self) -> i8 {
+        match self {
+            SongSection::Chorus => 1,
+            _ => 0,
+        }
+    }
+
+    /* has_harmony - Checks whether the section's melody gets a harmony voice.
+     *
+     * inputs:
+     *     - self
+     *     - setting (&str): The harmony setting: `HARMONY_CHORUS` for choruses only,
+     *       `HARMONY_SONG` for every section, anything else for none.
+     *
+     * outputs:
+     *     - bool: True if the section is harmonized.
+     */
+    fn has_harmony(self, setting: &str) -> bool {
+        match setting {
+            HARMONY_SONG => true,
+            HARMONY_CHORUS => self == SongSection::Chorus,
+            _ => false,
+        }
+    }
+
+    /* levels - Returns how loud each track plays in the section.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - [f32; 4]: Levels of the melody, chords, bass and drums (0.0 = silent, 1.0 = as mixed).
+     */
+    fn levels(self) -> [f32; 4] {
+        match self {
+            SongSection::Intro { melody: true } => [1.0, 0.0, 0.0, 0.0],
+            SongSection::Intro { melody: false } => [0.0, 1.0, 0.0, 0.0],
+            SongSection::Chorus => [CHORUS_LEVEL; 4],
+            _ => [1.0; 4],
+        }
+    }
+}
+
+// SECTION_SEED_SALT: Mixed into the song seed so the section plan uses its own random stream.
+const SECTION_SEED_SALT: u64 = 0x7365_6374_696f_6e73;
+// SECTION_SECONDS: Range a verse or chorus lasts (picked per song), before rounding to whole
+// progression cycles.
+const SECTION_SECONDS: std::ops::Range<f32> = 12.0..24.0;
+// BRIDGE_SECONDS / INTRO_SECONDS: How long the bridge, and the intro and outro, last, before
+// rounding to whole progression cycles.
+const BRIDGE_SECONDS: f32 = 12.0;
+const INTRO_SECONDS: f32 = 8.0;
+// SECTION_ORDER: The sections between the intro and the outro; after the last chorus, verses
+// and choruses alternate until the song is full.
+const SECTION_ORDER: [SongSection; 6] = [
+    SongSection::Verse,
+    SongSection::Chorus,
+    SongSection::Verse,
+    SongSection::Chorus,
+    SongSection::Bridge,
+    SongSection::Chorus,
+];
+// CHORUS_LEVEL: How much louder the chorus plays than the other sections.
+const CHORUS_LEVEL: f32 = 1.25;
+// HARMONY_OFF / HARMONY_CHORUS / HARMONY_SONG: The harmony voice settings: none, in the
+// choruses, or under the whole melody (see `SongSection::has_harmony`).
+pub const HARMONY_OFF: &str = "Off";
+pub const HARMONY_CHORUS: &str = "Chorus";
+pub const HARMONY_SONG: &str = "Song";
+// HARMONY_SETTINGS: The harmony settings, in the order the hotkey cycles through them.
+const HARMONY_SETTINGS: [&str; 3] = [HARMONY_OFF, HARMONY_CHORUS, HARMONY_SONG];
+// HARMONY_LEVEL: How loud the harmony voice plays, relative to the melody.
+const HARMONY_LEVEL: f32 = 0.5;
+
+/* next_harmony - Returns the harmony setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `HARMONY_OFF`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `HARMONY_SETTINGS`, wrapping around.
+ */
+pub fn next_harmony(setting: &str) -> &'static str {
+    let index = HARMONY_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    HARMONY_SETTINGS[(index + 1) % HARMONY_SETTINGS.len()]
+}
+
+// AUTO_COUNTER_MELODY: The counter-melody setting that lets the style decide (see `resolve_counter_melody`).
+pub const AUTO_COUNTER_MELODY: &str = "Auto";
+// COUNTER_MELODY_SETTINGS: The counter-melody settings, in the order the hotkey cycles through them.
+const COUNTER_MELODY_SETTINGS: [&str; 3] = [AUTO_COUNTER_MELODY, "On", "Off"];
+// COUNTER_MELODY_LEVEL: How loud the counter-melody plays, relative to the melody; quieter
+// than the harmony, as it sits under the melody rather than beside it.
+const COUNTER_MELODY_LEVEL: f32 = 0.35;
+
+/* next_counter_melody - Returns the counter-melody setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_COUNTER_MELODY`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `COUNTER_MELODY_SETTINGS`, wrapping around.
+ */
+pub fn next_counter_melody(setting: &str) -> &'static str {
+    let index = COUNTER_MELODY_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    COUNTER_MELODY_SETTINGS[(index + 1) % COUNTER_MELODY_SETTINGS.len()]
+}
+
+/* resolve_counter_melody - Decides whether a song plays a counter-melody.
+ *
+ * inputs:
+ *     - setting (&str): "On" or "Off"; `AUTO_COUNTER_MELODY` or anything else plays one in
+ *       classical and jazz songs only.
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - bool: True if the song plays a counter-melody.
+ */
+fn resolve_counter_melody(setting: &str, style: &str) -> bool {
+    match setting {
+        "On" => true,
+        "Off" => false,
+        _ => matches!(style.to_lowercase().as_str(), "classical" | "jazz"),
+    }
+}
+
+// PROGRESSION_SEED_SALT: Mixed into the song seed so the choice of progression uses its own random stream.
+const PROGRESSION_SEED_SALT: u64 = 0x7072_6f67_7265_7373;
+
+/* pick_progressions - Picks the A and B chord progressions a song plays in a key.
+ *
+ * inputs:
+ *     - prog_name (&str): The style's own progression (see `progs::style_progressions`).
+ *     - minor (bool): True for a minor key, false for a major one.
+ *     - seed (u64): The song seed.
+ *
+ * outputs:
+ *     - (&'static str, &'static str): Two of the style's progressions for the key, the same
+ *       for the same seed; they are the same one only if the style has no other.
+ */
+fn pick_progressions(prog_name: &str, minor: bool, seed: u64) -> (&'static str, &'static str) {
+    let mut rng = StdRng::seed_from_u64(seed ^ PROGRESSION_SEED_SALT);
+    let progressions = progs::style_progressions(prog_name, minor);
+    let a = progressions.choose(&mut rng).copied().unwrap();
+    let others: Vec<&'static str> = progressions.into_iter().filter(|&name| name != a).collect();
+    (a, others.choose(&mut rng).copied().unwrap_or(a))
+}
+
+// PROGRESSION_BLOCK_BARS: About how many bars the A and B progressions each play before
+// handing over to the other.
+const PROGRESSION_BLOCK_BARS: usize = 8;
+
+/* plan_chord_timeline - Plans which chord plays in every chord slot of a song.
+ *
+ * The A progression plays for about `PROGRESSION_BLOCK_BARS` bars (whole cycles of it), then
+ * the B progression for as long, its last bar replaced by the turnaround chord, and so on.
+ *
+ * inputs:
+ *     - slots (usize): The number of chord slots to plan, up to the cadence.
+ *     - a_chords (usize): Chords in a cycle of the A progression.
+ *     - b_chords (usize): Chords in a cycle of the B progression.
+ *     - bar_len (usize): Length of a bar in samples.
+ *     - chord_len (usize): Length of a chord slot in samples.
+ *
+ * outputs:
+ *     - Vec<ChordSlot>: The chord of every slot, in order.
+ */
+fn plan_chord_timeline(slots: usize, a_chords: usize, b_chords: usize, bar_len: usize, chord_len: usize) -> Vec<ChordSlot> {
+    let block_len = |chords: usize| {
+        let cycle_len = (chords * chord_len).max(1);
+        let cycles = ((PROGRESSION_BLOCK_BARS * bar_len) as f32 / cycle_len as f32).round() as usize;
+        cycles.max(1) * chords
+    };
+    let (a_block, b_block) = (block_len(a_chords), block_len(b_chords));
+    // A bar's worth of slots, leaving at least half of the B block
+    let turnaround = (bar_len / chord_len.max(1)).max(1).min(b_block / 2);
+    let mut plan = Vec::with_capacity(slots + a_block + b_block);
+    while plan.len() < slots {
+        plan.extend((0..a_block).map(ChordSlot::A));
+        plan.extend((0..b_block).map(|index| {
+            if index < b_block - turnaround { ChordSlot::B(index) } else { ChordSlot::Turnaround }
+        }));
+    }
+    plan.truncate(slots);
+    plan
+}
+
+// AUTO_ARP_CHORDS: The arp chords setting that lets the style decide (see `resolve_arp_chords`).
+pub const AUTO_ARP_CHORDS: &str = "Auto";
+// ARP_CHORDS_SETTINGS: The arp chords settings, in the order the hotkey cycles through them.
+const ARP_CHORDS_SETTINGS: [&str; 3] = [AUTO_ARP_CHORDS, "On", "Off"];
+
+/* next_arp_chords - Returns the arp chords setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_ARP_CHORDS`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `ARP_CHORDS_SETTINGS`, wrapping around.
+ */
+pub fn next_arp_chords(setting: &str) -> &'static str {
+    let index = ARP_CHORDS_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    ARP_CHORDS_SETTINGS[(index + 1) % ARP_CHORDS_SETTINGS.len()]
+}
+
+/* resolve_arp_chords - Decides whether a song's chords are played as chip arpeggios.
+ *
+ * Arp chords replace the style's own chord pattern (block, broken, waltz or skank).
+ *
+ * inputs:
+ *     - setting (&str): "On" or "Off"; `AUTO_ARP_CHORDS` or anything else arpeggiates
+ *       electronic songs only.
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - bool: True if the chords are played as chip arpeggios.
+ */
+fn resolve_arp_chords(setting: &str, style: &str) -> bool {
+    match setting {
+        "On" => true,
+        "Off" => false,
+        _ => style.eq_ignore_ascii_case("electronic"),
+    }
+}
+
+// AUTO_WAVEFORM: The waveform setting that lets the style pick a track's waveform (see
+// `style_waveforms`).
+pub const AUTO_WAVEFORM: &str = "Auto";
+// WAVEFORM_TRACKS: The tracks whose waveform can be chosen, in the order of `AppState::waveforms`.
+pub const WAVEFORM_TRACKS: [&str; 3] = ["Lead", "Chords", "Bass"];
+// WAVEFORM_ID_PREFIX / AUTO_WAVEFORM_ID_CHAR: The optional song ID part holding the waveform
+// settings starts with "w", followed by one letter per track of `WAVEFORM_TRACKS` (see
+// `Waveform::id_char`), 'a' for `AUTO_WAVEFORM`; e.g. "wata" plays a triangle chord track.
+const WAVEFORM_ID_PREFIX: &str = "w";
+const AUTO_WAVEFORM_ID_CHAR: char = 'a';
+
+/* next_waveform - Returns the waveform setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_WAVEFORM`.
+ *
+ * outputs:
+ *     - &'static str: `AUTO_WAVEFORM`, then the label of every waveform in `Waveform::ALL`,
+ *       wrapping around.
+ */
+pub fn next_waveform(setting: &str) -> &'static str {
+    match Waveform::from_label(setting) {
+        None => Waveform::ALL[0].label(),
+        Some(waveform) => {
+            let index = Waveform::ALL.iter().position(|&w| w == waveform).unwrap_or(0);
+            Waveform::ALL.get(index + 1).map_or(AUTO_WAVEFORM, |w| w.label())
+        }
+    }
+}
+
+/* style_waveforms - Returns the waveforms a style plays its tracks in.
+ *
+ * Like the NES, the lead is a square and the bass a triangle. Classical leads and block
+ * chords are sines, arp chords are squares, electronic basses are squares and ambient basses
+ * sines.
+ *
+ * inputs:
+ *     - style (&str): The song's style.
+ *     - arp_chords (bool): Whether the chords are played as chip arpeggios.
+ *
+ * outputs:
+ *     - [Waveform; 3]: The lead, chord and bass waveforms.
+ */
+fn style_waveforms(style: &str, arp_chords: bool) -> [Waveform; 3] {
+    let lead = if style.eq_ignore_ascii_case("classical") { Waveform::Sine } else { Waveform::Square };
+    let chords = if arp_chords { Waveform::Square } else { Waveform::Sine };
+    let bass = match style.to_lowercase().as_str() {
+        "electronic" => Waveform::Square,
+        "ambient" => Waveform::Sine,
+        _ => Waveform::Triangle,
+    };
+    [lead, chords, bass]
+}
+
+/* resolve_waveforms - Decides the waveform of each track of a song.
+ *
+ * inputs:
+ *     - settings (&[String; 3]): The lead, chord and bass settings: a waveform label, or
+ *       `AUTO_WAVEFORM` (or anything else) for the style's choice.
+ *     - style (&str): The song's style.
+ *     - arp_chords (bool): Whether the chords are played as chip arpeggios.
+ *
+ * outputs:
+ *     - [Waveform; 3]: The lead, chord and bass waveforms.
+ */
+fn resolve_waveforms(settings: &[String; 3], style: &str, arp_chords: bool) -> [Waveform; 3] {
+    let mut waveforms = style_waveforms(style, arp_chords);
+    for (waveform, setting) in waveforms.iter_mut().zip(settings) {
+        if let Some(chosen) = Waveform::from_label(setting) {
+            *waveform = chosen;
+        }
+    }
+    waveforms
+}
+
+/* waveforms_id_part - Formats the waveform settings as a song ID part.
+ *
+ * inputs:
+ *     - settings (&[String; 3]): The lead, chord and bass settings.
+ *
+ * outputs:
+ *     - Option<String>: The part (e.g. "wata"), or None when every track is `AUTO_WAVEFORM`.
+ */
+pub fn waveforms_id_part(settings: &[String; 3]) -> Option<String> {
+    let letters: String = settings
+        .iter()
+        .map(|setting| Waveform::from_label(setting).map_or(AUTO_WAVEFORM_ID_CHAR, Waveform::id_char))
+        .collect();
+    letters
+        .chars()
+        .any(|letter| letter != AUTO_WAVEFORM_ID_CHAR)
+        .then(|| format!("{}{}", WAVEFORM_ID_PREFIX, letters))
+}
+
+/* parse_waveforms_id_part - Reads the waveform settings from a song ID part.
+ *
+ * inputs:
+ *     - letters (&str): The part without its "w" prefix, one letter per track.
+ *
+ * outputs:
+ *     - Option<[String; 3]>: The lead, chord and bass settings, or None if there aren't exactly
+ *       three known letters.
+ */
+fn parse_waveforms_id_part(letters: &str) -> Option<[String; 3]> {
+    let settings: Vec<String> = letters
+        .chars()
+        .map(|letter| match letter {
+            AUTO_WAVEFORM_ID_CHAR => Some(AUTO_WAVEFORM.to_string()),
+            _ => Waveform::from_id_char(letter).map(|waveform| waveform.label().to_string()),
+        })
+        .collect::<Option<_>>()?;
+    settings.try_into().ok()
+}
+
+// AUTO_PULSE_WIDTH: The pulse width setting that lets the style pick (see `resolve_pulse_width`).
+pub const AUTO_PULSE_WIDTH: &str = "Auto";
+// PULSE_WIDTH_SETTINGS: The pulse width settings, in the order the hotkey cycles through them;
+// "PWM" sweeps the width over each note.
+const PULSE_WIDTH_SETTINGS: [&str; 5] = [AUTO_PULSE_WIDTH, "12.5%", "25%", "50%", "PWM"];
+// THIN_PULSE_WIDTH / QUARTER_PULSE_WIDTH: The 12.5% and 25% pulses of the NES and Game Boy
+// pulse channels, nasal and hollow next to the even square.
+const THIN_PULSE_WIDTH: PulseWidth = PulseWidth { duty: 0.125, sweep: 0.0 };
+const QUARTER_PULSE_WIDTH: PulseWidth = PulseWidth { duty: 0.25, sweep: 0.0 };
+// SWEEPING_PULSE_WIDTH: Pulse width modulation, swinging the duty between 12.5% and 50%.
+const SWEEPING_PULSE_WIDTH: PulseWidth = PulseWidth { duty: 0.3125, sweep: 0.1875 };
+
+/* next_pulse_width - Returns the pulse width setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_PULSE_WIDTH`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `PULSE_WIDTH_SETTINGS`, wrapping around.
+ */
+pub fn next_pulse_width(setting: &str) -> &'static str {
+    let index = PULSE_WIDTH_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    PULSE_WIDTH_SETTINGS[(index + 1) % PULSE_WIDTH_SETTINGS.len()]
+}
+
+/* resolve_pulse_width - Decides the duty of a song's square lead and arp chords.
+ *
+ * The style picks a thin 12.5% pulse for electronic, 25% for pop, a slow sweep for ambient
+ * and the even square for everything else.
+ *
+ * inputs:
+ *     - setting (&str): "12.5%", "25%", "50%" or "PWM"; `AUTO_PULSE_WIDTH` or anything else
+ *       lets the style pick.
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - PulseWidth: The pulse width.
+ */
+fn resolve_pulse_width(setting: &str, style: &str) -> PulseWidth {
+    match setting {
+        "12.5%" => THIN_PULSE_WIDTH,
+        "25%" => QUARTER_PULSE_WIDTH,
+        "50%" => PulseWidth::EVEN,
+        "PWM" => SWEEPING_PULSE_WIDTH,
+        _ => match style.to_lowercase().as_str() {
+            "electronic" => THIN_PULSE_WIDTH,
+            "pop" => QUARTER_PULSE_WIDTH,
+            "ambient" => SWEEPING_PULSE_WIDTH,
+            _ => PulseWidth::EVEN,
+        },
+    }
+}
+
+// AUTO_BRIGHTNESS: The brightness setting that lets the style pick the tone (see `style_cutoff`).
+pub const AUTO_BRIGHTNESS: &str = "Auto";
+// BRIGHTNESS_SETTINGS: The brightness settings, in the order the hotkey cycles through them;
+// "Open" plays the tracks unfiltered.
+const BRIGHTNESS_SETTINGS: [&str; 5] = [AUTO_BRIGHTNESS, "Dark", "Warm", "Bright", "Open"];
+// DARK_CUTOFF_HZ / WARM_CUTOFF_HZ / BRIGHT_CUTOFF_HZ: Low-pass cutoffs of the brightness
+// settings chosen by hand.
+const DARK_CUTOFF_HZ: f32 = 1500.0;
+const WARM_CUTOFF_HZ: f32 = 3500.0;
+const BRIGHT_CUTOFF_HZ: f32 = 9000.0;
+// SWEEP_START_RATIO: Where a sweeping cutoff starts each section, as a fraction of the
+// style's cutoff; it opens up to the full cutoff by the section's end.
+const SWEEP_START_RATIO: f32 = 0.2;
+
+/* next_brightness - Returns the brightness setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_BRIGHTNESS`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `BRIGHTNESS_SETTINGS`, wrapping around.
+ */
+pub fn next_brightness(setting: &str) -> &'static str {
+    let index = BRIGHTNESS_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    BRIGHTNESS_SETTINGS[(index + 1) % BRIGHTNESS_SETTINGS.len()]
+}
+
+/* style_cutoff - Returns the low-pass cutoff a style softens its tracks with.
+ *
+ * inputs:
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - (f32, bool): The cutoff in Hz, dark for ambient and jazz and bright for electronic and
+ *       metal, and whether it sweeps open over each section (electronic only).
+ */
+fn style_cutoff(style: &str) -> (f32, bool) {
+    match style.to_lowercase().as_str() {
+        "ambient" => (1800.0, false),
+        "jazz" => (2500.0, false),
+        "classical" | "folk" | "blues" | "reggae" => (4000.0, false),
+        "electronic" => (9000.0, true),
+        "metal" => (10000.0, false),
+        _ => (6000.0, false),
+    }
+}
+
+/* resolve_brightness - Decides how a song's melody, chords and bass are filtered.
+ *
+ * inputs:
+ *     - setting (&str): "Dark", "Warm", "Bright" or "Open"; `AUTO_BRIGHTNESS` or anything else
+ *       uses the style's cutoff (see `style_cutoff`).
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - Option<(f32, bool)>: The cutoff in Hz and whether it sweeps, or None to leave the
+ *       tracks unfiltered. A cutoff chosen by hand holds still.
+ */
+fn resolve_brightness(setting: &str, style: &str) -> Option<(f32, bool)> {
+    match setting {
+        "Dark" => Some((DARK_CUTOFF_HZ, false)),
+        "Warm" => Some((WARM_CUTOFF_HZ, false)),
+        "Bright" => Some((BRIGHT_CUTOFF_HZ, false)),
+        "Open" => None,
+        _ => Some(style_cutoff(style)),
+    }
+}
+
+// AUTO_ECHO: The echo setting that lets the style set the melody's echo (see `style_echo`).
+pub const AUTO_ECHO: &str = "Auto";
+// ECHO_SETTINGS: The echo settings, in the order the hotkey cycles through them.
+const ECHO_SETTINGS: [&str; 2] = [AUTO_ECHO, "Off"];
+// ECHO_ID_PREFIX: Starts the optional song ID part that turns the echo off ("e0"); "e1" leaves
+// it to the style, as an ID without the part does.
+pub const ECHO_ID_PREFIX: &str = "e";
+// ECHO_FEEDBACK: How much of each melody echo comes back in the next.
+const ECHO_FEEDBACK: f32 = 0.35;
+
+/* next_echo - Returns the echo setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_ECHO`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `ECHO_SETTINGS`, wrapping around.
+ */
+pub fn next_echo(setting: &str) -> &'static str {
+    let index = ECHO_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    ECHO_SETTINGS[(index + 1) % ECHO_SETTINGS.len()]
+}
+
+/* style_echo - Returns how a style's melody echoes.
+ *
+ * inputs:
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - (f32, f32): The time between echoes in beats (a dotted eighth for ambient, electronic
+ *       and reggae's dub echo, an eighth otherwise) and the echoes' level: strong for ambient,
+ *       electronic and reggae, subtle for pop and the others, none for classical.
+ */
+fn style_echo(style: &str) -> (f32, f32) {
+    match style.to_lowercase().as_str() {
+        "ambient" => (0.75, 0.45),
+        "electronic" => (0.75, 0.35),
+        "reggae" => (0.75, 0.3),
+        "pop" => (0.5, 0.15),
+        "classical" => (0.5, 0.0),
+        _ => (0.5, 0.1),
+    }
+}
+
+/* melody_echo - Builds the delay line for a song's melody.
+ *
+ * inputs:
+ *     - setting (&str): "Off" turns the echo off; `AUTO_ECHO` or anything else uses the style's.
+ *     - style (&str): The song's style.
+ *     - bpm (u32): The song's tempo, which the time between echoes follows.
+ *
+ * outputs:
+ *     - Option<EchoDelay>: The delay line, or None if the melody plays dry.
+ */
+fn melody_echo(setting: &str, style: &str, bpm: u32) -> Option<EchoDelay> {
+    let (beats, wet) = style_echo(style);
+    if setting == "Off" || wet <= 0.0 {
+        return None;
+    }
+    let delay_samples = (beats * 60.0 / bpm.max(1) as f32 * SAMPLE_RATE as f32) as usize;
+    Some(EchoDelay::new(delay_samples, ECHO_FEEDBACK, wet))
+}
+
+// AUTO_LOFI: The lo-fi setting that lets the style decide (see `resolve_lofi`).
+pub const AUTO_LOFI: &str = "Auto";
+// LOFI_SETTINGS: The lo-fi settings, in the order the hotkey cycles through them.
+pub const LOFI_SETTINGS: [&str; 4] = [AUTO_LOFI, "Off", "Light", "Heavy"];
+// LOFI_ID_PREFIX: Starts the optional song ID part for a lo-fi setting chosen by hand: "l0"
+// (off), "l1" (light) or "l2" (heavy).
+pub const LOFI_ID_PREFIX: &str = "l";
+// LOFI_BITS: The bit depths the lo-fi stage can quantize to.
+const LOFI_BITS: std::ops::RangeInclusive<u32> = 4..=12;
+// LIGHT_LOFI / HEAVY_LOFI: Bit depth and effective sample rate of each lo-fi setting: the
+// grit of an 8-bit sampler, or a 5-bit crunch with the aliasing of a quarter sample rate.
+const LIGHT_LOFI: (u32, u32) = (8, 22050);
+const HEAVY_LOFI: (u32, u32) = (5, 11025);
+
+/* next_lofi - Returns the lo-fi setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_LOFI`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `LOFI_SETTINGS`, wrapping around.
+ */
+pub fn next_lofi(setting: &str) -> &'static str {
+    let index = LOFI_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    LOFI_SETTINGS[(index + 1) % LOFI_SETTINGS.len()]
+}
+
+/* lofi_id_part - Formats a lo-fi setting as a song ID part.
+ *
+ * inputs:
+ *     - setting (&str): The lo-fi setting.
+ *
+ * outputs:
+ *     - Option<String>: The part (e.g. "l2"), or None for `AUTO_LOFI`.
+ */
+pub fn lofi_id_part(setting: &str) -> Option<String> {
+    let index = LOFI_SETTINGS.iter().position(|&s| s == setting).filter(|&index| index > 0)?;
+    Some(format!("{}{}", LOFI_ID_PREFIX, index - 1))
+}
+
+/* resolve_lofi - Builds the lo-fi stage for a song.
+ *
+ * inputs:
+ *     - setting (&str): "Off", "Light" or "Heavy"; `AUTO_LOFI` or anything else crushes
+ *       electronic and metal songs lightly and leaves the rest clean.
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - Option<LoFi>: The stage, or None for a clean mix.
+ */
+fn resolve_lofi(setting: &str, style: &str) -> Option<LoFi> {
+    let (bits, sample_rate) = match setting {
+        "Off" => return None,
+        "Light" => LIGHT_LOFI,
+        "Heavy" => HEAVY_LOFI,
+        _ => match style.to_lowercase().as_str() {
+            "electronic" | "metal" => LIGHT_LOFI,
+            _ => return None,
+        },
+    };
+    Some(LoFi::new(bits, sample_rate))
+}
+
+// NES_ID_PREFIX: Starts the optional song ID part that turns NES mode on ("n1"); "n0" leaves it
+// off, as an ID without the part does.
+pub const NES_ID_PREFIX: &str = "n";
+// NES_WAVEFORMS: The lead, chord and bass waveforms of NES mode: its two pulse channels and
+// its triangle, whatever waveforms are chosen.
+const NES_WAVEFORMS: [Waveform; 3] = [Waveform::Square, Waveform::Square, Waveform::Triangle];
+
+// MELODY_SEED_ID_PREFIX / BASS_SEED_ID_PREFIX: Start the optional song ID parts holding a melody
+// or bass seed picked apart from the song's seed ("m123"), after the melody or bass was varied.
+pub const MELODY_SEED_ID_PREFIX: &str = "m";
+pub const BASS_SEED_ID_PREFIX: &str = "b";
+// DRUMS_ID_PREFIX: Starts the optional song ID part that leaves the drums out ("d0"); "d1" keeps
+// them, as an ID without the part does.
+pub const DRUMS_ID_PREFIX: &str = "d";
+// FADE_OUT_ID_PREFIX: Starts the optional song ID part holding a fade-out set in the config, in
+// seconds ("o8"); IDs without it fade out over the style's length.
+pub const FADE_OUT_ID_PREFIX: &str = "o";
+// GAINS_ID_PREFIX: Starts the optional song ID part holding the melody, chord and bass mixer
+// levels in percent, separated like a progression's chords ("g70.50.40"); IDs without it mix
+// every track at `DEFAULT_TRACK_GAIN_PERCENT`.
+pub const GAINS_ID_PREFIX: &str = "g";
+
+/* SettingIdPart - An optional song ID part for a setting picked from a list, holding the
+ * setting's index in the list ("h2" is the third harmony setting). The first setting is the
+ * default, which IDs leave out.
+ *
+ * fields:
+ *     - prefix (&'static str): Starts the part.
+ *     - name (&'static str): The setting, as errors name it.
+ *     - settings (&'static [&'static str]): The settings the index picks from.
+ *     - get (fn(&AppState) -> &str): Reads the setting from a state.
+ *     - get_mut (fn(&mut AppState) -> &mut String): Gives the setting to change in a state.
+ */
+pub struct SettingIdPart {
+    pub prefix: &'static str,
+    pub name: &'static str,
+    pub settings: &'static [&'static str],
+    pub get: fn(&AppState) -> &str,
+    pub get_mut: fn(&mut AppState) -> &mut String,
+}
+
+// SETTING_ID_PARTS: The optional song ID parts of the settings picked from a list: harmony ("h1"
+// in the choruses, "h2" throughout), counter-melody and arp chords ("c1"/"a1" on, "c2"/"a2" off),
+// comping ("r1" sustained), pulse width ("u1" 12.5% to "u4" PWM) and brightness ("f1" dark to
+// "f4" open).
+pub const SETTING_ID_PARTS: [SettingIdPart; 6] = [
+    SettingIdPart {
+        prefix: "h",
+        name: "Harmony",
+        settings: &HARMONY_SETTINGS,
+        get: |state| &state.harmony,
+        get_mut: |state| &mut state.harmony,
+    },
+    SettingIdPart {
+        prefix: "c",
+        name: "Counter-melody",
+        settings: &COUNTER_MELODY_SETTINGS,
+        get: |state| &state.counter_melody,
+        get_mut: |state| &mut state.counter_melody,
+    },
+    SettingIdPart {
+        prefix: "a",
+        name: "Arp Chords",
+        settings: &ARP_CHORDS_SETTINGS,
+        get: |state| &state.arp_chords,
+        get_mut: |state| &mut state.arp_chords,
+    },
+    SettingIdPart {
+        prefix: "r",
+        name: "Comping",
+        settings: &COMPING_SETTINGS,
+        get: |state| &state.comping,
+        get_mut: |state| &mut state.comping,
+    },
+    SettingIdPart {
+        prefix: "u",
+        name: "Pulse Width",
+        settings: &PULSE_WIDTH_SETTINGS,
+        get: |state| &state.pulse_width,
+        get_mut: |state| &mut state.pulse_width,
+    },
+    SettingIdPart {
+        prefix: "f",
+        name: "Brightness",
+        settings: &BRIGHTNESS_SETTINGS,
+        get: |state| &state.brightness,
+        get_mut: |state| &mut state.brightness,
+    },
+];
+
+impl SettingIdPart {
+    /* index - Finds a state's setting in the list.
+     *
+     * inputs:
+     *     - &self
+     *     - app_state (&AppState): The state to read.
+     *
+     * outputs:
+     *     - Option<usize>: The setting's index, or None for the default or an unknown setting,
+     *       which plays as the default.
+     */
+    pub fn index(&self, app_state: &AppState) -> Option<usize> {
+        let setting = (self.get)(app_state);
+        self.settings.iter().position(|&s| s == setting).filter(|&index| index > 0)
+    }
+
+    /* id_part - Formats a state's setting as a song ID part.
+     *
+     * inputs:
+     *     - &self
+     *     - app_state (&AppState): The state to read.
+     *
+     * outputs:
+     *     - Option<String>: The part (e.g. "h1"), or None for the default.
+     */
+    pub fn id_part(&self, app_state: &AppState) -> Option<String> {
+        self.index(app_state).map(|index| format!("{}{}", self.prefix, index))
+    }
+}
+
+/* gains_id_part - Formats the mixer levels as a song ID part.
+ *
+ * inputs:
+ *     - app_state (&AppState): The state to read.
+ *
+ * outputs:
+ *     - Option<String>: The part (e.g. "g70.50.40"), or None when every track is at
+ *       `DEFAULT_TRACK_GAIN_PERCENT`. Levels are written as `track_gain` plays them: clamped to
+ *       100, and the default for anything that isn't a number.
+ */
+pub fn gains_id_part(app_state: &AppState) -> Option<String> {
+    let default_percent: u32 = DEFAULT_TRACK_GAIN_PERCENT.parse().unwrap_or_default();
+    let levels = [&app_state.melody_gain, &app_state.chord_gain, &app_state.bass_gain]
+        .map(|gain| gain.trim().parse::<u32>().map_or(default_percent, |percent| percent.min(100)));
+    levels.iter().any(|&level| level != default_percent).then(|| {
+        let levels: Vec<String> = levels.iter().map(u32::to_string).collect();
+        format!("{}{}", GAINS_ID_PREFIX, levels.join(&PROGRESSION_ID_SEPARATOR.to_string()))
+    })
+}
+// SONG_ID_VERSION: The version new song IDs are stamped with ("v2:C-Pop-120-5-42"); raise it when
+// a change to generation makes old IDs sound different. IDs without a version are version 1.
+pub const SONG_ID_VERSION: u32 = 2;
+// SONG_ID_VERSION_PREFIX / SONG_ID_VERSION_SEPARATOR: Mark the version at the start of a song ID.
+pub const SONG_ID_VERSION_PREFIX: &str = "v";
+pub const SONG_ID_VERSION_SEPARATOR: char = ':';
+
+// AUTO_COMPING: The comping setting that lets the style pick a rhythm (see `comp_rhythm`).
+pub const AUTO_COMPING: &str = "Auto";
+// COMPING_SETTINGS: The comping settings, in the order the hotkey cycles through them; "Sustain"
+// holds every chord for its full length, as before comping.
+const COMPING_SETTINGS: [&str; 2] = [AUTO_COMPING, "Sustain"];
+
+/* next_comping - Returns the comping setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_COMPING`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `COMPING_SETTINGS`, wrapping around.
+ */
+pub fn next_comping(setting: &str) -> &'static str {
+    let index = COMPING_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    COMPING_SETTINGS[(index + 1) % COMPING_SETTINGS.len()]
+}
+
+// CompHit: One chord hit of a comping rhythm: its start and length, in beats from the start of
+// the rhythm.
+type CompHit = (f32, f32);
+
+// COMP_RHYTHM_BEATS: Length of every comping rhythm, in beats; it starts again every two beats
+// from the start of each chord.
+const COMP_RHYTHM_BEATS: f32 = 2.0;
+
+// COMPING_SEED_SALT: Mixed into the song seed for the choice among a style's comping rhythms.
+const COMPING_SEED_SALT: u64 = 0x636f_6d70_696e_6721;
+
+// POP_COMP_RHYTHMS: Half notes on 1 and 3, or a quarter pushed by an eighth on the "and" of 2 and 4.
+const POP_COMP_RHYTHMS: [&[CompHit]; 2] = [&[(0.0, 1.75)], &[(0.0, 1.0), (1.5, 0.5)]];
+// ROCK_COMP_RHYTHMS: Driving eighths, or quarter-note stabs.
+const ROCK_COMP_RHYTHMS: [&[CompHit]; 2] = [
+    &[(0.0, 0.4), (0.5, 0.4), (1.0, 0.4), (1.5, 0.4)],
+    &[(0.0, 0.75), (1.0, 0.75)],
+];
+// METAL_COMP_RHYTHMS: Tight eighth-note chugs, or a held hit and two chugs.
+const METAL_COMP_RHYTHMS: [&[CompHit]; 2] = [
+    &[(0.0, 0.3), (0.5, 0.3), (1.0, 0.3), (1.5, 0.3)],
+    &[(0.0, 1.0), (1.0, 0.3), (1.5, 0.3)],
+];
+// JAZZ_COMP_RHYTHMS: The Charleston (1 and the "and" of 2), or its reverse, anticipating the beat.
+const JAZZ_COMP_RHYTHMS: [&[CompHit]; 2] = [&[(0.0, 0.75), (1.5, 0.4)], &[(0.5, 0.4), (1.5, 0.4)]];
+// BLUES_COMP_RHYTHMS: Quarter-note stabs, or a hit on the beat and a push on the "and" of 2.
+const BLUES_COMP_RHYTHMS: [&[CompHit]; 2] = [&[(0.0, 0.6), (1.0, 0.6)], &[(0.0, 0.75), (1.5, 0.4)]];
+// OFF_BEAT_COMP_RHYTHMS: Stabs on the "and" of every beat, for electronic without arp chords.
+const OFF_BEAT_COMP_RHYTHMS: [&[CompHit]; 1] = [&[(0.5, 0.3), (1.5, 0.3)]];
+// DEFAULT_COMP_RHYTHMS: A half note and a quarter note on every other beat, for any other style.
+const DEFAULT_COMP_RHYTHMS: [&[CompHit]; 2] = [&[(0.0, 1.75)], &[(0.0, 0.75), (1.0, 0.75)]];
+
+// COMP_ATTACK_SECONDS / COMP_RELEASE_SECONDS: Fade-in and fade-out of every comping hit, so the
+// gated chord doesn't click.
+const COMP_ATTACK_SECONDS: f32 = 0.005;
+const COMP_RELEASE_SECONDS: f32 = 0.03;
+
+/* comp_rhythm - Picks the rhythm a song's block chords are comped in.
+ *
+ * Only block chords in 4/4 are comped: classical, folk, reggae and arp chords keep their own
+ * patterns, ambient pads and songs in other time signatures stay sustained.
+ *
+ * inputs:
+ *     - setting (&str): "Sustain" keeps every chord sustained; `AUTO_COMPING` or anything else
+ *       lets the style pick.
+ *     - style (&str): The song's style.
+ *     - pattern (progs::ChordPattern): The pattern the chords are played in.
+ *     - time_signature (progs::TimeSignature): The song's time signature.
+ *     - seed (u64): The song seed, which picks among the style's rhythms.
+ *
+ * outputs:
+ *     - Option<&'static [CompHit]>: The rhythm, or None to sustain the chords.
+ */
+fn comp_rhythm(
+    setting: &str,
+    style: &str,
+    pattern: progs::ChordPattern,
+    time_signature: progs::TimeSignature,
+    seed: u64,
+) -> Option<&'static [CompHit]> {
+    if setting == "Sustain" || pattern != progs::ChordPattern::Block || time_signature != progs::TimeSignature::FourFour {
+        return None;
+    }
+    let rhythms: &[&'static [CompHit]] = match style.to_lowercase().as_str() {
+        "ambient" => return None,
+        "pop" => &POP_COMP_RHYTHMS,
+        "rock" => &ROCK_COMP_RHYTHMS,
+        "metal" => &METAL_COMP_RHYTHMS,
+        "jazz" => &JAZZ_COMP_RHYTHMS,
+        "blues" => &BLUES_COMP_RHYTHMS,
+        "electronic" => &OFF_BEAT_COMP_RHYTHMS,
+        _ => &DEFAULT_COMP_RHYTHMS,
+    };
+    let mut rng = StdRng::seed_from_u64(seed ^ COMPING_SEED_SALT);
+    rhythms.choose(&mut rng).copied()
+}
+
+/* comp_chord - Gates a sustained chord into the hits of a comping rhythm.
+ *
+ * The rhythm repeats every `COMP_RHYTHM_BEATS` from the start of the chord, its off-beat hits
+ * swung like the melody's; each hit fades in over `COMP_ATTACK_SECONDS` and out over
+ * `COMP_RELEASE_SECONDS`, and everything between the hits is silenced.
+ *
+ * inputs:
+ *     - chord_samples (&mut [f32]): The sustained chord, starting on a beat.
+ *     - rhythm (&[CompHit]): The hits of the rhythm.
+ *     - sec_per_beat (f32): The length of a beat in seconds.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *
+ * outputs:
+ *     - None
+ */
+fn comp_chord(chord_samples: &mut [f32], rhythm: &[CompHit], sec_per_beat: f32, swing: f32) {
+    let beat_samples = sec_per_beat as f64 * SAMPLE_RATE as f64;
+    let cycle_samples = ((COMP_RHYTHM_BEATS as f64 * beat_samples) as usize).max(1);
+    let to_samples = |beats: f32| (melodies::swing_position(beats as f64, swing) * beat_samples) as usize;
+    let hits: Vec<(usize, usize)> = rhythm
+        .iter()
+        .map(|&(start, length)| (to_samples(start), to_samples(start + length)))
+        .collect();
+    let attack_samples = (COMP_ATTACK_SECONDS * SAMPLE_RATE as f32) as usize;
+    let release_samples = (COMP_RELEASE_SECONDS * SAMPLE_RATE as f32) as usize;
+    for (i, sample) in chord_samples.iter_mut().enumerate() {
+        let position = i % cycle_samples;
+        let level = match hits.iter().find(|&&(start, end)| position >= start && position < end) {
+            Some(&(start, end)) => {
+                let attack = (position - start) as f32 / attack_samples.max(1) as f32;
+                let release = (end - position - 1) as f32 / release_samples.max(1) as f32;
+                attack.min(release).min(1.0)
+            }
+            None => 0.0,
+        };
+        *sample *= level;
+    }
+}
+
+// OUTRO_END_LEVEL: The level the outro fades down to, so the closing cadence is still heard.
+const OUTRO_END_LEVEL: f32 = 0.35;
+// DEFAULT_FADE_OUT_SECS / AMBIENT_FADE_OUT_SECS: How long a song fades to silence at its end,
+// unless the config sets a length; ambient drifts away more slowly.
+const DEFAULT_FADE_OUT_SECS: u32 = 3;
+const AMBIENT_FADE_OUT_SECS: u32 = 6;
+// MAX_FADE_OUT_SECS: Longest fade-out the config can set, in seconds.
+pub const MAX_FADE_OUT_SECS: u32 = 20;
+// FADE_IN_SECONDS: The short fade-in at the start of every song, so it doesn't start with a click.
+const FADE_IN_SECONDS: f32 = 0.1;
+
+/* fade_out_seconds - Returns how long a song fades out at its end.
+ *
+ * inputs:
+ *     - setting (Option<u32>): Seconds set in the config (0 for no fade-out), or None for the
+ *       style's.
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - u32: The fade-out in seconds, at most `MAX_FADE_OUT_SECS`.
+ */
+fn fade_out_seconds(setting: Option<u32>, style: &str) -> u32 {
+    match setting {
+        Some(seconds) => seconds.min(MAX_FADE_OUT_SECS),
+        None if style.eq_ignore_ascii_case("ambient") => AMBIENT_FADE_OUT_SECS,
+        None => DEFAULT_FADE_OUT_SECS,
+    }
+}
+
+/* plan_sections - Divides a song into intro, verse, chorus, bridge and outro sections.
+ *
+ * The song opens with an intro and closes with an outro, with `SECTION_ORDER` in between,
+ * the last section cut short to fit. A song too short for that is a single verse.
+ *
+ * inputs:
+ *     - total_cycles (usize): Length of the song in progression cycles.
+ *     - cycle_seconds (f32): Length of one progression cycle in seconds.
+ *     - seed (u64): The song's seed; the plan uses its own stream derived from it.
+ *
+ * outputs:
+ *     - Vec<(SongSection, usize)>: Each section and its length in progression cycles, in order.
+ */
+fn plan_sections(total_cycles: usize, cycle_seconds: f32, seed: u64) -> Vec<(SongSection, usize)> {
+    let mut rng = StdRng::seed_from_u64(seed ^ SECTION_SEED_SALT);
+    let cycles_for = |seconds: f32| ((seconds / cycle_seconds).round() as usize).max(1);
+    let verse_cycles = cycles_for(rng.gen_range(SECTION_SECONDS));
+    let chorus_cycles = cycles_for(rng.gen_range(SECTION_SECONDS));
+    let edge_cycles = cycles_for(INTRO_SECONDS);
+    let intro = SongSection::Intro { melody: rng.gen::<bool>() };
+    if total_cycles < 2 * edge_cycles + verse_cycles + chorus_cycles {
+        return vec![(SongSection::Verse, total_cycles)];
+    }
+
+    let mut plan = vec![(intro, edge_cycles)];
+    let mut remaining = total_cycles - 2 * edge_cycles;
+    for &section in SECTION_ORDER.iter().chain(SECTION_ORDER[..2].iter().cycle()) {
+        if remaining == 0 {
+            break;
+        }
+        let cycles = match section {
+            SongSection::Verse => verse_cycles,
+            SongSection::Chorus => chorus_cycles,
+            _ => cycles_for(BRIDGE_SECONDS),
+        }
+        .min(remaining);
+        plan.push((section, cycles));
+        remaining -= cycles;
+    }
+    plan.push((SongSection::Outro, edge_cycles));
+    plan
+}
+
+// Tempo range generation accepts. Slower tempos make single chords longer than a short song,
+// and much faster ones plan millions of notes; `parse_bpm` turns down anything outside it.
+pub const MIN_BPM: u32 = 20;
+pub const MAX_BPM: u32 = 400;
+
+// Song length range generation accepts, in seconds. A zero length would render no audio at all,
+// and absurd lengths would exhaust memory; out-of-range values are clamped.
+pub const MIN_SONG_SECS: u32 = 60;
+pub const MAX_SONG_SECS: u32 = 3600;
+// DEFAULT_SONG_SECS: Length of a song when none is chosen.
+pub const DEFAULT_SONG_SECS: u32 = 300;
+// ENDLESS_SONG_SECS: The length that makes a song endless: it plays on, generated as it goes,
+// until it is skipped or stopped. Shown as "∞".
+pub const ENDLESS_SONG_SECS: u32 = 0;
+// ENDLESS_ROUND_SECS: How much of an endless song is planned at a time (see `SongGenerator`).
+const ENDLESS_ROUND_SECS: u32 = DEFAULT_SONG_SECS;
+// ENDLESS_ROUND_SEED_STEP: Mixed into an endless song's seed once per round, so every round
+// plays tunes of its own (see `round_seed`).
+const ENDLESS_ROUND_SEED_STEP: u64 = 0x9e37_79b9_7f4a_7c15;
+// ENDLESS_LENGTH_LABEL / ENDLESS_LENGTH_ID_PART: How the Length option shows an endless song,
+// and how song IDs write it; `parse_song_length` also reads "endless".
+pub const ENDLESS_LENGTH_LABEL: &str = "∞";
+const ENDLESS_LENGTH_ID_PART: &str = "inf";
+
+// SCALE_NAMES: The scales a song can be in, in semitones above C; anything else plays in C.
+pub const SCALE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+// STYLE_NAMES: The styles a song can be in, as the UI lists them.
+pub const STYLE_NAMES: [&str; 10] = [
+    "Pop",
+    "Rock",
+    "Jazz",
+    "Blues",
+    "Electronic",
+    "Ambient",
+    "Classical",
+    "Folk",
+    "Metal",
+    "Reggae",
+];
+
+/* parse_bpm - Checks a BPM typed in the UI or read from a song ID.
+ *
+ * inputs:
+ *     - bpm (&str): The BPM as typed.
+ *
+ * outputs:
+ *     - Result<Option<u32>, String>: Ok with the tempo, Ok(None) for an empty BPM (picked from
+ *       the seed), or an Err explaining the `MIN_BPM`-`MAX_BPM` range.
+ */
+pub fn parse_bpm(bpm: &str) -> Result<Option<u32>, String> {
+    if bpm.is_empty() {
+        return Ok(None);
+    }
+    match bpm.parse::<u32>() {
+        Ok(tempo) if (MIN_BPM..=MAX_BPM).contains(&tempo) => Ok(Some(tempo)),
+        _ => Err(format!(
+            "BPM '{}' is out of range: use {} to {}, or leave it empty to pick one from the seed.",
+            bpm, MIN_BPM, MAX_BPM
+        )),
+    }
+}
+
+/* song_bpm - Returns the tempo a song is generated at, when one is given.
+ *
+ * Every BPM that gets here has passed `parse_bpm`: the UI, song IDs and the config check it,
+ * and `run_music_service` turns down a song whose BPM doesn't, so it is never played at some
+ * other tempo.
+ *
+ * inputs:
+ *     - bpm (&str): The BPM option.
+ *
+ * outputs:
+ *     - Option<u32>: The tempo, or None for an empty BPM, which the song picks from its seed.
+ */
+fn song_bpm(bpm: &str) -> Option<u32> {
+    let tempo = parse_bpm(bpm);
+    debug_assert!(tempo.is_ok(), "songs are only generated at a BPM parse_bpm accepts: {:?}", tempo);
+    tempo.ok().flatten()
+}
+
+/* parse_song_length - Reads a song length typed in the UI, a config file or a song ID.
+ *
+ * A bare number is minutes and may have a fraction ("7", "2.5"); "min" or "m" after it says
+ * the same. Seconds are written "90s", and minutes and seconds "2:30". An endless song is
+ * "∞", "inf" or "endless".
+ *
+ * inputs:
+ *     - length (&str): The length as written.
+ *
+ * outputs:
+ *     - Result<u32, String>: The length in whole seconds (`ENDLESS_SONG_SECS` for an endless
+ *       song), or an Err if it can't be read or is outside `MIN_SONG_SECS`-`MAX_SONG_SECS`.
+ */
+pub fn parse_song_length(length: &str) -> Result<u32, String> {
+    let text = length.trim().to_ascii_lowercase();
+    if [ENDLESS_LENGTH_LABEL, ENDLESS_LENGTH_ID_PART, "endless"].contains(&text.as_str()) {
+        return Ok(ENDLESS_SONG_SECS);
+    }
+    let number = |digits: &str| digits.trim().parse::<f64>().ok().filter(|value| value.is_finite() && *value >= 0.0);
+    let seconds = if let Some((minutes, seconds)) = text.split_once(':') {
+        // Whole minutes, and seconds under a minute
+        match (minutes.trim().parse::<u32>(), seconds.trim().parse::<u32>()) {
+            (Ok(minutes), Ok(seconds)) if seconds < 60 => {
+                Some(minutes as f64 * 60.0 + seconds as f64)
+            }
+            _ => None,
+        }
+    } else if let Some(seconds) = ["secs", "sec", "s"].iter().find_map(|unit| text.strip_suffix(unit)) {
+        number(seconds)
+    } else {
+        let minutes = ["min", "m"].iter().find_map(|unit| text.strip_suffix(unit)).unwrap_or(&text);
+        number(minutes).map(|minutes| minutes * 60.0)
+    };
+    let Some(seconds) = seconds else {
+        return Err(format!(
+            "Length '{}' can't be read: use minutes (7 or 2.5), seconds (90s), both (2:30) or inf.",
+            length.trim()
+        ));
+    };
+    let seconds = seconds.round();
+    if !(MIN_SONG_SECS as f64..=MAX_SONG_SECS as f64).contains(&seconds) {
+        return Err(format!(
+            "Length '{}' is out of range: use {} to {} minutes.",
+            length.trim(),
+            MIN_SONG_SECS / 60,
+            MAX_SONG_SECS / 60
+        ));
+    }
+    Ok(seconds as u32)
+}
+
+/* format_song_length - Writes a song length the way the Length option shows it.
+ *
+ * inputs:
+ *     - secs (u32): The length in seconds.
+ *
+ * outputs:
+ *     - String: "5 min" for whole minutes, otherwise minutes and seconds, e.g. "2:30";
+ *       `ENDLESS_LENGTH_LABEL` for an endless song.
+ */
+pub fn format_song_length(secs: u32) -> String {
+    if secs == ENDLESS_SONG_SECS {
+        ENDLESS_LENGTH_LABEL.to_string()
+    } else if secs.is_multiple_of(60) {
+        format!("{} min", secs / 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/* length_id_part - Writes a song length the way song IDs carry it.
+ *
+ * inputs:
+ *     - secs (u32): The length in seconds.
+ *
+ * outputs:
+ *     - String: Whole minutes as before lengths could be custom ("5"), otherwise seconds
+ *       ("150s"); "inf" for an endless song.
+ */
+pub fn length_id_part(secs: u32) -> String {
+    if secs == ENDLESS_SONG_SECS {
+        ENDLESS_LENGTH_ID_PART.to_string()
+    } else if secs.is_multiple_of(60) {
+        (secs / 60).to_string()
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/* song_secs - Returns the length a song is generated at.
+ *
+ * inputs:
+ *     - length_secs (u32): The chosen length in seconds.
+ *
+ * outputs:
+ *     - u32: The length clamped to `MIN_SONG_SECS`-`MAX_SONG_SECS`.
+ */
+fn song_secs(length_secs: u32) -> u32 {
+    length_secs.clamp(MIN_SONG_SECS, MAX_SONG_SECS)
+}
+
+// MAX_SWING_PERCENT: Full swing, where off-beat eighths fall on the last triplet of the beat.
+pub const MAX_SWING_PERCENT: u32 = 100;
+
+/* default_swing_percent - Returns the swing a style plays with when none is set.
+ *
+ * inputs:
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - u32: The swing in percent (0 = straight eighths).
+ */
+fn default_swing_percent(style: &str) -> u32 {
+    match style.to_lowercase().as_str() {
+        "jazz" => 66,
+        "blues" => 60, // A lazier shuffle than jazz's
+        _ => 0,
+    }
+}
+
+/* resolve_swing - Returns the swing a song is generated with.
+ *
+ * inputs:
+ *     - swing (&str): The swing setting in percent; empty or invalid input uses the style's default.
+ *     - style (&str): The song's style.
+ *
+ * outputs:
+ *     - f32: The swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ */
+fn resolve_swing(swing: &str, style: &str) -> f32 {
+    let percent = swing
+        .trim()
+        .parse::<u32>()
+        .map(|percent| percent.min(MAX_SWING_PERCENT))
+        .unwrap_or_else(|_| default_swing_percent(style));
+    percent as f32 / MAX_SWING_PERCENT as f32
+}
+
+// AUTO_TIME_SIGNATURE: The time signature setting that lets the style decide (see `resolve_time_signature`).
+pub const AUTO_TIME_SIGNATURE: &str = "Auto";
+
+// TIME_SIGNATURE_SEED_SALT: Mixed into the song seed for classical's choice between 3/4 and 6/8,
+// so the rest of the song draws the same random numbers either way.
+const TIME_SIGNATURE_SEED_SALT: u64 = 0x6d65_7465_725f_7369;
+
+/* resolve_time_signature - Returns the time signature a song is generated with.
+ *
+ * inputs:
+ *     - setting (&str): A time signature label ("4/4", "3/4" or "6/8"); `AUTO_TIME_SIGNATURE`
+ *       or anything else lets the style decide: 3/4 for folk's waltz, 3/4 or 6/8 (picked by
+ *       the seed) for classical and 4/4 for everything else.
+ *     - style (&str): The song's style.
+ *     - seed (u64): The song seed.
+ *
+ * outputs:
+ *     - progs::TimeSignature: The time signature.
+ */
+fn resolve_time_signature(setting: &str, style: &str, seed: u64) -> progs::TimeSignature {
+    if let Some(time_signature) = progs::TimeSignature::from_label(setting) {
+        return time_signature;
+    }
+    match style.to_lowercase().as_str() {
+        "folk" => progs::TimeSignature::ThreeFour,
+        "classical" => {
+            let mut rng = StdRng::seed_from_u64(seed ^ TIME_SIGNATURE_SEED_SALT);
+            if rng.gen::<bool>() {
+                progs::TimeSignature::ThreeFour
+            } else {
+                progs::TimeSignature::SixEight
+            }
+        }
+        _ => progs::TimeSignature::FourFour,
+    }
+}
+
+// AUTO_MODULATION: The key change setting that lets the song decide (see `resolve_modulation`).
+pub const AUTO_MODULATION: &str = "Auto";
+// MODULATION_SETTINGS: The key change settings, in the order the hotkey cycles through them.
+const MODULATION_SETTINGS: [&str; 3] = [AUTO_MODULATION, "On", "Off"];
+
+/* next_modulation - Returns the key change setting after the given one.
+ *
+ * inputs:
+ *     - setting (&str): The current setting; anything unknown counts as `AUTO_MODULATION`.
+ *
+ * outputs:
+ *     - &'static str: The next entry of `MODULATION_SETTINGS`, wrapping around.
+ */
+pub fn next_modulation(setting: &str) -> &'static str {
+    let index = MODULATION_SETTINGS.iter().position(|&s| s == setting).unwrap_or(0);
+    MODULATION_SETTINGS[(index + 1) % MODULATION_SETTINGS.len()]
+}
+
+// MODULATION_SEED_SALT: Mixed into the song seed so the key change decision uses its own random stream.
+const MODULATION_SEED_SALT: u64 = 0x6d6f_6475_6c61_7465;
+// MODULATION_MIN_SECONDS: Auto only considers a key change in songs longer than this.
+const MODULATION_MIN_SECONDS: f32 = 120.0;
+// MODULATION_CHANCE: How often Auto changes key in a song long enough for it.
+const MODULATION_CHANCE: f64 = 0.5;
+
+/* resolve_modulation - Decides whether a song changes key for its last section.
+ *
+ * inputs:
+ *     - setting (&str): "On" or "Off"; `AUTO_MODULATION` or anything else changes key in
+ *       `MODULATION_CHANCE` of the songs longer than `MODULATION_MIN_SECONDS` (picked by the seed).
+ *     - song_seconds (f32): Length of the song in seconds.
+ *     - seed (u64): The song seed.
+ *
+ * outputs:
+ *     - bool: True if the last section changes key.
+ */
+fn resolve_modulation(setting: &str, song_seconds: f32, seed: u64) -> bool {
+    match setting {
+        "On" => true,
+        "Off" => false,
+        _ => {
+            let mut rng = StdRng::seed_from_u64(seed ^ MODULATION_SEED_SALT);
+            song_seconds > MODULATION_MIN_SECONDS && rng.gen_bool(MODULATION_CHANCE)
+        }
+    }
+}
+
+/* modulated_key - Returns the key a song changes to for its last section.
+ *
+ * Pop, rock, electronic and metal lift the key a whole step; the other styles move to the
+ * relative key (the relative minor of a major key, and the relative major of a minor one).
+ *
+ * inputs:
+ *     - style (&str): The song's style.
+ *     - root (u8): The song's root note (0-11).
+ *     - minor (bool): True if the song is in a minor key.
+ *
+ * outputs:
+ *     - (u8, bool): The new root note (0-11) and whether it is minor.
+ */
+fn modulated_key(style: &str, root: u8, minor: bool) -> (u8, bool) {
+    match style.to_lowercase().as_str() {
+        "pop" | "rock" | "electronic" | "metal" => ((root + 2) % 12, minor),
+        _ => SongSection::Bridge.key(root, minor),
+    }
+}
+
+// MINOR_KEY_SUFFIX: Follows the root in a song ID's key for minor keys ("Cm"); major keys are
+// the bare root ("C"), as in IDs from before key qualities existed.
+pub const MINOR_KEY_SUFFIX: &str = "m";
+
+// SWING_ID_PREFIX: Starts the optional swing part of a song ID ("s66" = 66% swing), which goes
+// before the seed; IDs without it use the style's default swing.
+pub const SWING_ID_PREFIX: &str = "s";
+
+// TIME_SIGNATURE_ID_PREFIX: Starts the optional time signature part of a song ID ("t68" = 6/8),
+// which goes before the seed; IDs without it use the style's time signature (see `resolve_time_signature`).
+pub const TIME_SIGNATURE_ID_PREFIX: &str = "t";
+
+// MODULATION_ID_PREFIX: Starts the optional key change part of a song ID ("k1" = on, "k0" = off),
+// which goes before the seed; IDs without it use `AUTO_MODULATION`.
+pub const MODULATION_ID_PREFIX: &str = "k";
+
+// PROGRESSION_ID_PREFIX: Starts the optional custom progression part of a song ID ("pI.V.vi.IV"),
+// which goes before the seed; the chords are separated by dots, as an ID can't hold spaces.
+pub const PROGRESSION_ID_PREFIX: &str = "p";
+
+// PROGRESSION_ID_SEPARATOR: Separates the chords of the custom progression part of a song ID.
+pub const PROGRESSION_ID_SEPARATOR: char = '.';
+
+/* time_signature_id_part - Builds the time signature part of a song ID.
+ *
+ * inputs:
+ *     - time_signature (progs::TimeSignature): The time signature.
+ *
+ * outputs:
+ *     - String: `TIME_SIGNATURE_ID_PREFIX` and the label without its slash, e.g. "t34".
+ */
+pub fn time_signature_id_part(time_signature: progs::TimeSignature) -> String {
+    format!("{}{}", TIME_SIGNATURE_ID_PREFIX, time_signature.label().replace('/', ""))
+}
+
+/* resolve_seed - Returns the seed a song will be generated with.
+ *
+ * inputs:
+ *     - app_state (&AppState): The application state holding the seed input.
+ *
+ * outputs:
+ *     - u64: The parsed seed, or a fresh random seed if the input is empty or invalid.
+ */
+fn resolve_seed(app_state: &AppState) -> u64 {
+    app_state.seed.parse::<u64>().unwrap_or_else(|_| {
+        // If seed string is empty or invalid, generate a truly random u64 seed value
+        rand::random::<u64>()
+    })
+}
+
+/* effective_app_state - Replaces the parameters a song reads loosely with what it played.
+ *
+ * An unknown scale plays in C, the BPM is read and the length (unless endless) clamped, so the returned
+ * state shows (and its song ID reproduces) what is playing. A BPM picked from the seed stays
+ * empty: giving it explicitly would skip that pick and change every later random choice, so
+ * only an empty BPM reproduces the song (its tempo is reported as `MusicProgress::bpm`).
+ *
+ * inputs:
+ *     - app_state (&AppState): The state the song was generated from.
+ *
+ * outputs:
+ *     - AppState: A copy with the scale, BPM and length replaced by their effective values.
+ */
+fn effective_app_state(app_state: &AppState) -> AppState {
+    let mut effective = app_state.clone();
+    if !SCALE_NAMES.contains(&effective.scale.as_str()) {
+        effective.scale = SCALE_NAMES[0].to_string();
+    }
+    effective.bpm = song_bpm(&app_state.bpm).map_or_else(String::new, |bpm| bpm.to_string());
+    if !app_state.is_endless() {
+        effective.length_secs = song_secs(app_state.length_secs);
+    }
+    effective
+}
+
+/* RenderedSong - The output of `generate_audio_from_state`.
+ *
+ * fields:
+ *     - samples (Arc<Vec<f32>>): The mixed audio at `SAMPLE_RATE`; only the opening when a preview
+ *       was requested. Shared with the player rather than copied when the song plays.
+ *     - song_len (usize): The length of the full song in samples.
+ *     - bpm (u32): The resolved tempo, including the random fallback for an empty BPM.
+ *     - beats_per_bar (u32): Beats in a bar of the resolved time signature.
+ *     - chord_timeline (Vec<(u64, String)>): Start sample and name of every chord in the full song.
+ *     - section_timeline (Vec<(u64, SongSection)>): Start sample of every section in the full song.
+ *     - loudness_db (Option<f32>): The song's loudness (see `dsp::integrated_loudness`); None for a preview.
+ */
+struct RenderedSong {
+    samples: Arc<Vec<f32>>,
+    song_len: usize,
+    bpm: u32,
+    beats_per_bar: u32,
+    chord_timeline: Vec<(u64, String)>,
+    section_timeline: Vec<(u64, SongSection)>,
+    loudness_db: Option<f32>,
+}
+
+/* PreparedSong - A fully rendered song waiting to be played.
+ *
+ * fields:
+ *     - app_state (AppState): The parameters the song was generated from.
+ *     - seed (u64): The seed the song was generated with.
+ *     - render (RenderedSong): The complete render.
+ */
+pub struct PreparedSong {
+    pub app_state: AppState,
+    seed: u64,
+    render: RenderedSong,
+}
+
+/* prepare_song - Renders a whole song ahead of time.
+ *
+ * The seed is resolved here, so an empty seed input picks a fresh random seed just as it
+ * would when the song is generated on demand.
+ *
+ * inputs:
+ *     - app_state (AppState): The parameters of the song.
+ *
+ * outputs:
+ *     - PreparedSong: The rendered song.
+ */
+pub fn prepare_song(app_state: AppState) -> PreparedSong {
+    let seed = resolve_seed(&app_state);
+    let render = generate_audio_from_state(&app_state, seed, None);
+    PreparedSong {
+        app_state,
+        seed,
+        render,
+    }
+}
+
+/* render_opening - Renders the opening of a song without playing it.
+ *
+ * Used to measure how fast this machine generates audio.
+ *
+ * inputs:
+ *     - app_state (&AppState): The parameters of the song.
+ *     - seed (u64): The seed to generate with.
+ *     - seconds (f32): Render about this many seconds (rounded up to whole chords).
+ *
+ * outputs:
+ *     - usize: The number of samples rendered, at `SAMPLE_RATE`.
+ */
+pub fn render_opening(app_state: &AppState, seed: u64, seconds: f32) -> usize {
+    generate_audio_from_state(app_state, seed, Some(seconds)).samples.len()
+}
+
+/* SongGenerator - Renders a song one section at a time (see `generate_audio_from_state`).
+ *
+ * Everything the sections share is resolved when the generator is created, and the effects,
+ * filters and master bus run on from one section to the next, so the sections join without a
+ * seam. An endless song has no outro and no cadence: whenever its plan runs out, another
+ * round of `ENDLESS_ROUND_SECS` is planned, its sections playing new tunes from a seed derived
+ * from the song's (see `round_seed`), so it plays on until it is stopped.
+ *
+ * fields:
+ *     - style (String): The song's style.
+ *     - prog_name (&'static str): The style's progression family (see `progs::style_progressions`).
+ *     - root_note (u8): The song's root note (0-11).
+ *     - minor (bool): True if the song is in a minor key.
+ *     - bpm (u32): The resolved tempo, including the random fallback for an empty BPM.
+ *     - beats_per_bar (usize): Beats in a bar of the resolved time signature.
+ *     - time_signature (progs::TimeSignature): The resolved time signature.
+ *     - swing (f32): The resolved swing, shared by the melody, bass and drums.
+ *     - sec_per_beat (f32): Length of a beat in seconds.
+ *     - chord_duration (f32): Length of a chord in seconds.
+ *     - chord_envelope (progs::ChordEnvelope): Fade-in and fade-out of every chord.
+ *     - chord_pattern (progs::ChordPattern): How the chords are played.
+ *     - chord_waveform (Waveform): The tone of the chords.
+ *     - lead_waveform (Waveform): The tone of the melody.
+ *     - pulse_width (PulseWidth): Duty cycle of pulse waves.
+ *     - bass_voice (BassVoice): The tone of the bass.
+ *     - comping (Option<&'static [CompHit]>): The comping rhythm of the chords, if any.
+ *     - nes (bool): True in NES mode.
+ *     - seed (u64): The song's seed.
+ *     - melody_seed (u64): The seed of the melody (the song's unless it was varied).
+ *     - bass_seed (u64): The seed of the bass line (the song's unless it was varied).
+ *     - song_chords (KeyChords): The chords in the song's key.
+ *     - bridge_chords (KeyChords): The chords in the bridge's key.
+ *     - modulation (Option<(usize, (u8, bool), KeyChords)>): The first section in a new key, the key and its chords.
+ *     - samples_per_chord (usize): Length of a chord in samples.
+ *     - chord_len (usize): Length of a chord slot in samples (at least one).
+ *     - chords_per_cycle (usize): Chords in a cycle of the A progression.
+ *     - cycle_len (usize): Length of a progression cycle in samples.
+ *     - bar_len (usize): Length of a bar in samples.
+ *     - song_len (Option<usize>): Length of the whole song in samples, or None for an endless song.
+ *     - round_cycles (usize): Progression cycles in a round of an endless song.
+ *     - tail_chords (usize): Chords of an unfinished cycle before the cadence.
+ *     - final_len (usize): Length of the closing tonic in samples.
+ *     - target_len (usize): Samples to render before stopping.
+ *     - plan (Vec<(SongSection, usize)>): Each section and its length in cycles (see `plan_sections`).
+ *     - chord_plan (Vec<ChordSlot>): The chord of every slot up to the cadence (see `plan_chord_timeline`).
+ *     - rounds (u64): Rounds an endless song has planned so far.
+ *     - section_index (usize): The next section to render.
+ *     - section_start (usize): Start sample of the next section.
+ *     - drums (bool): True if the song has drums.
+ *     - drum_start (usize): The sample the drum track starts at; every round of an endless song has its own.
+ *     - drum_len (usize): Length of the drum track in samples; each section renders its stretch of it.
+ *     - drum_gain (f32): Level of the drums.
+ *     - chord_gain (f32): Level of the chords.
+ *     - melody_gain (f32): Level of the melody.
+ *     - bass_gain (f32): Level of the bass.
+ *     - harmony_setting (String): The harmony setting (see `SongSection::has_harmony`).
+ *     - counter_level (f32): Level of the counter-melody, or 0.0 for none.
+ *     - echo (Option<EchoDelay>): The melody's echo, if any.
+ *     - lofi (Option<LoFi>): The lo-fi stage, if any.
+ *     - brightness (Option<(f32, bool)>): The low-pass cutoff and whether it sweeps open, if any.
+ *     - track_filters (Option<[LowPass; 3]>): Low-pass filters of the melody, chords and bass.
+ *     - leveler (Leveler): The master bus's slow leveler.
+ *     - limiter (Limiter): The master bus's peak limiter.
+ *     - fade_in_len (usize): Length of the song's fade-in in samples.
+ *     - fade_out_len (usize): Length of the song's fade-out in samples (0 = none).
+ *     - chord_timeline (Vec<(u64, String)>): Start sample and name of every chord planned so far.
+ *     - section_timeline (Vec<(u64, SongSection)>): Start sample of every section planned so far.
+ */
+struct SongGenerator {
+    style: String,
+    prog_name: &'static str,
+    root_note: u8,
+    minor: bool,
+    bpm: u32,
+    beats_per_bar: usize,
+    time_signature: progs::TimeSignature,
+    swing: f32,
+    sec_per_beat: f32,
+    chord_duration: f32,
+    chord_envelope: progs::ChordEnvelope,
+    chord_pattern: progs::ChordPattern,
+    chord_waveform: Waveform,
+    lead_waveform: Waveform,
+    pulse_width: PulseWidth,
+    bass_voice: BassVoice,
+    comping: Option<&'static [CompHit]>,
+    nes: bool,
+    seed: u64,
+    melody_seed: u64,
+    bass_seed: u64,
+    song_chords: KeyChords,
+    bridge_chords: KeyChords,
+    modulation: Option<(usize, (u8, bool), KeyChords)>,
+    samples_per_chord: usize,
+    chord_len: usize,
+    chords_per_cycle: usize,
+    cycle_len: usize,
+    bar_len: usize,
+    song_len: Option<usize>,
+    round_cycles: usize,
+    tail_chords: usize,
+    final_len: usize,
+    target_len: usize,
+    plan: Vec<(SongSection, usize)>,
+    chord_plan: Vec<ChordSlot>,
+    rounds: u64,
+    section_index: usize,
+    section_start: usize,
+    drums: bool,
+    drum_start: usize,
+    drum_len: usize,
+    drum_gain: f32,
+    chord_gain: f32,
+    melody_gain: f32,
+    bass_gain: f32,
+    harmony_setting: String,
+    counter_level: f32,
+    echo: Option<EchoDelay>,
+    lofi: Option<LoFi>,
+    brightness: Option<(f32, bool)>,
+    track_filters: Option<[LowPass; 3]>,
+    leveler: Leveler,
+    limiter: Limiter,
+    fade_in_len: usize,
+    fade_out_len: usize,
+    chord_timeline: Vec<(u64, String)>,
+    section_timeline: Vec<(u64, SongSection)>,
+}
+
+impl SongGenerator {
+    /* new - Resolves everything the sections of a song share.
+     *
+     * Every random decision about the whole song is made here, before the first section is
+     * rendered.
+     *
+     * inputs:
+     *     - app_state (&AppState): The parameters of the song.
+     *     - seed (u64): The seed for random number generation (see `resolve_seed`).
+     *     - render_seconds (Option<f32>): Render only about this many seconds, or None for the whole song.
+     *     - endless (bool): True to play on in rounds instead of closing (see `SongGenerator`).
+     *
+     * outputs:
+     *     - Self: A generator at the start of the song.
+     */
+    fn new(app_state: &AppState, seed: u64, render_seconds: Option<f32>, endless: bool) -> Self {
+        let root_note = SCALE_NAMES.iter().position(|name| *name == app_state.scale).unwrap_or(0) as u8; // Default to C
+        // An endless song is planned a round at a time; rendered whole, it plays as a song of one round
+        let duration_seconds = if app_state.is_endless() {
+            ENDLESS_ROUND_SECS
+        } else {
+            song_secs(app_state.length_secs)
+        } as f32;
+        let style = app_state.style.as_str();
+        let minor = app_state.is_minor();
+        let swing = resolve_swing(&app_state.swing, style);
+        let time_signature = resolve_time_signature(&app_state.time_signature, style, seed);
+        let beats_per_bar = time_signature.beats_per_bar();
+
+        let actual_generated_seed = seed;
+        // A varied melody or bass has a seed of its own; the chords and drums keep the song's
+        let melody_seed = app_state.melody_seed.unwrap_or(seed);
+        let bass_seed = app_state.bass_seed.unwrap_or(seed);
+        let mut rng = StdRng::seed_from_u64(actual_generated_seed);
+
+        let bpm = match song_bpm(&app_state.bpm) {
+            Some(bpm) => bpm,
+            None => rng.gen_range(80..=160), // Corrected based on rand docs, will see if compiler still complains
+        };
+
+        let sec_per_beat: f32 = 60.0 / bpm as f32;
+        let num_beats_per_chord = rng.gen_range(2..=4);
+        let folk = style.eq_ignore_ascii_case("folk");
+        let mut chord_duration: f32 = match time_signature {
+            progs::TimeSignature::FourFour => num_beats_per_chord as f32 * sec_per_beat,
+            _ => beats_per_bar as f32 * sec_per_beat, // One chord per bar
+        };
+        let ambient = style.eq_ignore_ascii_case("ambient");
+        if ambient {
+            chord_duration *= rng.gen_range(AMBIENT_CHORD_STRETCH) as f32;
+        }
+        // A song closes on its last two chords, so a chord is cut back to the whole bars that leave
+        // room for two of them; otherwise a slow, short song would run past its length
+        let bar_seconds = beats_per_bar as f32 * sec_per_beat;
+        let bars_per_chord = (duration_seconds / 2.0 / bar_seconds).floor().max(1.0);
+        chord_duration = chord_duration.min(bars_per_chord * bar_seconds);
+        let chord_envelope = if ambient {
+            AMBIENT_CHORD_ENVELOPE
+        } else {
+            progs::ChordEnvelope::default()
+        };
+        // NES mode keeps to the 2A03's channels: the melody on one pulse, the chords arpeggiated on
+        // the other, the bass on the triangle and the drums on the noise channel
+        let nes = app_state.nes_mode;
+        let arp_chords = nes || resolve_arp_chords(&app_state.arp_chords, style);
+        let [lead_waveform, chord_waveform, bass_waveform] = if nes {
+            NES_WAVEFORMS
+        } else {
+            resolve_waveforms(&app_state.waveforms, style, arp_chords)
+        };
+        let bass_voice = BassVoice {
+            waveform: bass_waveform,
+            envelope: !nes,
+        };
+        let pulse_width = resolve_pulse_width(&app_state.pulse_width, style);
+        let chord_pattern = if arp_chords {
+            progs::ChordPattern::ChipArp {
+                rate_hz: CHIP_ARP_RATE_HZ,
+                pulse_width,
+            }
+        } else if style.eq_ignore_ascii_case("classical") {
+            let notes_per_beat = if bpm <= CLASSICAL_SIXTEENTHS_MAX_BPM { 4.0 } else { 2.0 };
+            progs::ChordPattern::Alberti {
+                step_seconds: sec_per_beat / notes_per_beat,
+            }
+        } else if folk {
+            progs::ChordPattern::Waltz {
+                beat_seconds: sec_per_beat,
+                time_signature,
+            }
+        } else if style.eq_ignore_ascii_case("reggae") {
+            progs::ChordPattern::Skank {
+                beat_seconds: sec_per_beat,
+            }
+        } else {
+            progs::ChordPattern::Block
+        };
+        let comping = comp_rhythm(&app_state.comping, style, chord_pattern, time_signature, actual_generated_seed);
+        let samples_per_chord = (chord_duration * SAMPLE_RATE as f32) as usize;
+        let sample_limit = match render_seconds {
+            Some(seconds) if samples_per_chord > 0 => {
+                let chords = (seconds * SAMPLE_RATE as f32 / samples_per_chord as f32).ceil() as usize;
+                chords.max(1) * samples_per_chord
+            }
+            _ => usize::MAX,
+        };
+
+        let prog_name = match style.to_lowercase().as_str() {
+            "electronic" => "electronic",
+            "blues" => "blues",
+            "pop" => "pop",
+            "jazz" => "jazz",
+            "rock" => "rock",
+            "classical" => "classical",
+            "folk" => "folk",
+            "metal" => "metal",
+            _ => "default",
+        };
+        // An invalid custom progression is turned down when typed, so it can only be left empty here
+        let custom_progression = progs::parse_roman_progression(&app_state.progression).unwrap_or_default();
+        // The chords of the song's key and the bridge's, each rendered once; the chord timeline
+        // places them. Each key plays two of the progressions its style can, picked by the seed
+        // (or the custom progression, answered from its middle), and the chord that leads into the style's cadence as its turnaround
+        let key_chords = |(key_root, key_minor): (u8, bool)| {
+            // Every chord is comped and fills exactly one chord slot
+            let render = |chords: Vec<ChordBuffer>| {
+                let mut chords = chords;
+                for chord in &mut chords {
+                    if style.eq_ignore_ascii_case("metal") {
+                        soft_clip(&mut chord.samples, METAL_DRIVE, CHORD_TRACK_PEAK);
+                    }
+                    if let Some(rhythm) = comping {
+                        comp_chord(&mut chord.samples, rhythm, sec_per_beat, swing);
+                    }
+                    chord.samples.resize(samples_per_chord.max(1), 0.0);
+                }
+                chords
+            };
+            let play = |name: &str| {
+                render(play_progression(
+                    String::from(name),
+                    key_root,
+                    key_minor,
+                    chord_duration,
+                    chord_envelope,
+                    chord_pattern,
+                    &custom_progression,
+                    chord_waveform,
+                ))
+            };
+            let (a_name, b_name) = pick_progressions(prog_name, key_minor, actual_generated_seed);
+            let a = play(a_name);
+            let b = if b_name == a_name || !custom_progression.is_empty() {
+                // A custom progression, or a style with a single one, answers it from its middle
+                let mut b = a.clone();
+                b.rotate_left(a.len() / 2);
+                b
+            } else {
+                play(b_name)
+            };
+            let cadence = progs::get_cadence(
+                prog_name,
+                key_root,
+                key_minor,
+                chord_duration,
+                chord_duration,
+                chord_envelope,
+                chord_pattern,
+                chord_waveform,
+            );
+            let turnaround = render(chord_buffers(cadence)).swap_remove(0);
+            KeyChords { a, b, turnaround }
+        };
+        let song_chords = key_chords((root_note, minor));
+        let bridge_chords = key_chords(SongSection::Bridge.key(root_note, minor));
+        let chords_per_cycle = song_chords.a.len().max(1);
+        let chord_len = samples_per_chord.max(1);
+        let cycle_len = chords_per_cycle * chord_len;
+        // The song ends on a bar line: the chord timeline runs until two chords before the end, where
+        // the cadence takes over, its closing tonic stretched to fill the rest
+        let bar_len = ((beats_per_bar as f32 * sec_per_beat * SAMPLE_RATE as f32) as usize).max(1);
+        let bars = ((duration_seconds * SAMPLE_RATE as f32 / bar_len as f32).round() as usize).max(1);
+        let song_len = (bars * bar_len).max(2 * chord_len);
+        let body_chords = song_len / chord_len - 2;
+        let total_cycles = body_chords / chords_per_cycle;
+        let tail_chords = body_chords % chords_per_cycle; // Chords of an unfinished cycle before the cadence
+        let final_len = song_len - (body_chords + 1) * chord_len;
+        let target_len = if endless { sample_limit } else { song_len.min(sample_limit) };
+        // An endless song plans its first round when its first section is rendered
+        let (plan, chord_plan) = if endless {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                plan_sections(total_cycles, cycle_len as f32 / SAMPLE_RATE as f32, actual_generated_seed),
+                plan_chord_timeline(body_chords, song_chords.a.len(), song_chords.b.len(), bar_len, chord_len),
+            )
+        };
+        // A key change covers the last section before the outro and the outro itself
+        let song_seconds = song_len as f32 / SAMPLE_RATE as f32;
+        let modulation = (plan.len() > 1
+            && resolve_modulation(&app_state.modulation, song_seconds, actual_generated_seed))
+        .then(|| {
+            let key = modulated_key(style, root_note, minor);
+            (plan.len() - 2, key, key_chords(key))
+        });
+
+        // The drums run across the whole song; an endless song's across each round
+        let drum_gain = if app_state.drums { DRUM_GAIN } else { 0.0 };
+        let chord_gain = track_gain(&app_state.chord_gain, DEFAULT_CHORD_GAIN);
+        let mut melody_gain = track_gain(&app_state.melody_gain, DEFAULT_MELODY_GAIN);
+        if ambient {
+            melody_gain *= AMBIENT_MELODY_LEVEL;
+        }
+        let bass_gain = track_gain(&app_state.bass_gain, DEFAULT_BASS_GAIN);
+        // The second pulse channel is busy with the chords in NES mode, and the first plays the
+        // melody alone
+        let harmony_setting = if nes { HARMONY_OFF } else { app_state.harmony.as_str() };
+        let counter_level = if !nes && resolve_counter_melody(&app_state.counter_melody, style) {
+            COUNTER_MELODY_LEVEL
+        } else {
+            0.0
+        };
+        let echo = melody_echo(&app_state.echo, style, bpm).filter(|_| !nes);
+        let lofi = resolve_lofi(&app_state.lofi, style);
+        // One low-pass filter each for the melody, chords and bass, running on across sections
+        let brightness = resolve_brightness(&app_state.brightness, style);
+        let track_filters: Option<[LowPass; 3]> =
+            brightness.map(|(cutoff, _)| std::array::from_fn(|_| LowPass::new(cutoff, SAMPLE_RATE as f32)));
+
+        // The master bus brings the mix toward a steady loudness and keeps its peaks below full
+        // scale; both run on from one sample to the next, so a shorter render is still a prefix
+        let leveler = Leveler::new(
+            MASTER_TARGET_RMS_DB,
+            MASTER_MAX_MAKEUP_DB,
+            MASTER_GATE_DB,
+            MASTER_LEVELER_SECONDS,
+            MASTER_LEVELER_SLEW_DB,
+            SAMPLE_RATE as f32,
+        );
+        let limiter = Limiter::new(
+            LIMITER_THRESHOLD_DB,
+            LIMITER_KNEE_DB,
+            LIMITER_ATTACK_SECONDS,
+            LIMITER_RELEASE_SECONDS,
+            SAMPLE_RATE as f32,
+        );
+        // Faded by position in the whole song, so the song keeps its length and a shorter render
+        // is still a prefix
+        let fade_in_len = ((FADE_IN_SECONDS * SAMPLE_RATE as f32) as usize).max(1);
+        let fade_out_len = (fade_out_seconds(app_state.fade_out_secs, style) * SAMPLE_RATE) as usize;
+
+        SongGenerator {
+            style: style.to_string(),
+            prog_name,
+            root_note,
+            minor,
+            bpm,
+            beats_per_bar,
+            time_signature,
+            swing,
+            sec_per_beat,
+            chord_duration,
+            chord_envelope,
+            chord_pattern,
+            chord_waveform,
+            lead_waveform,
+            pulse_width,
+            bass_voice,
+            comping,
+            nes,
+            seed: actual_generated_seed,
+            melody_seed,
+            bass_seed,
+            song_chords,
+            bridge_chords,
+            modulation,
+            samples_per_chord,
+            chord_len,
+            chords_per_cycle,
+            cycle_len,
+            bar_len,
+            song_len: (!endless).then_some(song_len),
+            round_cycles: total_cycles.max(1),
+            tail_chords,
+            final_len,
+            target_len,
+            plan,
+            chord_plan,
+            rounds: 0,
+            section_index: 0,
+            section_start: 0,
+            drums: app_state.drums,
+            drum_start: 0,
+            drum_len: if endless { 0 } else { target_len },
+            drum_gain,
+            chord_gain,
+            melody_gain,
+            bass_gain,
+            harmony_setting: harmony_setting.to_string(),
+            counter_level,
+            echo,
+            lofi,
+            brightness,
+            track_filters,
+            leveler,
+            limiter,
+            fade_in_len,
+            fade_out_len,
+            chord_timeline: Vec::new(),
+            section_timeline: Vec::new(),
+        }
+    }
+
+    /* plan_round - Plans the next round of an endless song.
+     *
+     * A round is planned like a song of `ENDLESS_ROUND_SECS` from the round's seed, without its
+     * outro; only the first round keeps its intro. The round's chord timeline starts over with
+     * the A progression, and its drum track with its first bar.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    fn plan_round(&mut self) {
+        let cycle_seconds = self.cycle_len as f32 / SAMPLE_RATE as f32;
+        let mut sections = plan_sections(self.round_cycles, cycle_seconds, round_seed(self.seed, self.rounds));
+        if sections.len() > 1 {
+            sections.pop(); // The outro
+        }
+        if self.rounds > 0 && matches!(sections.first(), Some((SongSection::Intro { .. }, _))) {
+            sections.remove(0);
+        }
+        let slots = sections.iter().map(|&(_, cycles)| cycles).sum::<usize>() * self.chords_per_cycle;
+        self.chord_plan.extend(plan_chord_timeline(
+            slots,
+            self.song_chords.a.len(),
+            self.song_chords.b.len(),
+            self.bar_len,
+            self.chord_len,
+        ));
+        self.drum_start = self.section_start;
+        self.drum_len = slots * self.chord_len;
+        self.plan.extend(sections);
+        self.rounds += 1;
+    }
+
+    /* next_section - Renders the next section of the song.
+     *
+     * Each section is generated on its own, with its own key, seed and melody octave. Its chords
+     * and start are added to the timelines even past the render limit, so a preview knows the
+     * whole song's.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<Vec<f32>>: The section's mixed audio (cut short or empty past the render
+     *       limit), or None once the song is over; an endless song never is.
+     */
+    fn next_section(&mut self) -> Option<Vec<f32>> {
+        if self.song_len.is_none() && self.section_index == self.plan.len() {
+            self.plan_round();
+        }
+        let index = self.section_index;
+        let &(section, section_cycles) = self.plan.get(index)?;
+        self.section_index += 1;
+        let style = self.style.as_str();
+        let (root_note, minor) = (self.root_note, self.minor);
+        let (chord_len, chords_per_cycle, samples_per_chord) =
+            (self.chord_len, self.chords_per_cycle, self.samples_per_chord);
+        let (chord_duration, sec_per_beat, swing) = (self.chord_duration, self.sec_per_beat, self.swing);
+        let final_len = self.final_len;
+        let section_start = self.section_start;
+        let (key, chords) = match &self.modulation {
+            Some((first_index, key, chords)) if index >= *first_index => (*key, chords),
+            _ if section == SongSection::Bridge => (section.key(root_note, minor), &self.bridge_chords),
+            _ => ((root_note, minor), &self.song_chords),
+        };
+        let body_len = section_cycles * self.cycle_len;
+        self.section_timeline.push((section_start as u64, section));
+
+        // The section's chords from the timeline, with their start and length; the last
+        // section of a song that ends also plays the chords of the unfinished cycle and the cadence
+        let last = self.song_len.is_some() && index + 1 == self.plan.len();
+        let first_slot = section_start / chord_len;
+        let slot_count = section_cycles * chords_per_cycle + if last { self.tail_chords } else { 0 };
+        let cadence = if last {
+            let cadence = progs::get_cadence(
+                self.prog_name,
+                key.0,
+                key.1,
+                chord_duration,
+                final_len as f32 / SAMPLE_RATE as f32,
+                self.chord_envelope,
+                self.chord_pattern,
+                self.chord_waveform,
+            );
+            let mut cadence = chord_buffers(cadence);
+            // The chord before the closing tonic is comped; the tonic rings out
+            for (i, (chord, len)) in cadence.iter_mut().zip([chord_len, final_len]).enumerate() {
+                if style.eq_ignore_ascii_case("metal") {
+                    soft_clip(&mut chord.samples, METAL_DRIVE, CHORD_TRACK_PEAK);
+                }
+                if let Some(rhythm) = self.comping.filter(|_| i == 0) {
+                    comp_chord(&mut chord.samples, rhythm, sec_per_beat, swing);
+                }
+                chord.samples.resize(len, 0.0);
+            }
+            cadence
+        } else {
+            Vec::new()
+        };
+        let mut section_chords: Vec<(usize, &ChordBuffer)> = Vec::with_capacity(slot_count + cadence.len());
+        for (i, &slot) in self.chord_plan[first_slot..first_slot + slot_count].iter().enumerate() {
+            section_chords.push((i * chord_len, chords.chord(slot)));
+        }
+        for (i, chord) in cadence.iter().enumerate() {
+            section_chords.push(((slot_count + i) * chord_len, chord));
+        }
+        for (start, chord) in &section_chords {
+            self.chord_timeline.push(((section_start + start) as u64, chord.label.clone()));
+        }
+        let roots: Vec<u8> = section_chords.iter().map(|(_, chord)| chord.root).collect();
+        let (body_roots, tail_roots) = roots.split_at(section_cycles * chords_per_cycle);
+        let section_len: usize = section_chords.iter().map(|(_, chord)| chord.samples.len()).sum();
+        self.section_start += section_len;
+        if section_start >= self.target_len {
+            return Some(Vec::new());
+        }
+
+        let render_len = section_len.min(self.target_len - section_start);
+        // Every round of an endless song plays new tunes
+        let round = self.rounds.saturating_sub(1);
+        let section_seed = section.seed(round_seed(self.melody_seed, round));
+        let bass_section_seed = section.seed(round_seed(self.bass_seed, round));
+        let section_seconds = section_len as f32 / SAMPLE_RATE as f32;
+        let (bpm, nes) = (self.bpm, self.nes);
+        let counter_level = self.counter_level;
+        // The lead, bass and drums share no state (each takes its own seed), so the bass and
+        // drums are forked to the worker pool while the lead is synthesized here
+        let pool = workers::global();
+        let (time_signature, bass_voice) = (self.time_signature, self.bass_voice);
+        let bass_line = {
+            let style = style.to_string();
+            let (body_roots, tail_roots) = (body_roots.to_vec(), tail_roots.to_vec());
+            pool.fork(move || {
+                let style = style.as_str();
+                let mut bass_line = get_bass_line(
+                    style,
+                    &body_roots,
+                    samples_per_chord,
+                    render_len.min(body_len),
+                    bpm,
+                    swing,
+                    time_signature,
+                    bass_section_seed,
+                    bass_voice,
+                );
+                if let Some((&tonic, lead_in)) = tail_roots.split_last().filter(|_| render_len > body_len) {
+                    // The lead-in ends on the cadence's first chord, so no fill runs into the tonic,
+                    // and the bass lands on the tonic's root and holds it to the end. A walking bass
+                    // sees the tonic too, so its last beats approach it
+                    let lead_in_len = lead_in.len() * samples_per_chord;
+                    let lead_in_roots = if style.eq_ignore_ascii_case("jazz") { &tail_roots[..] } else { lead_in };
+                    bass_line.extend(get_bass_line(
+                        style,
+                        lead_in_roots,
+                        samples_per_chord,
+                        lead_in_len,
+                        bpm,
+                        swing,
+                        time_signature,
+                        bass_section_seed,
+                        bass_voice,
+                    ));
+                    bass_line.extend(get_bass_line(
+                        style,
+                        &[tonic],
+                        final_len,
+                        final_len,
+                        bpm,
+                        swing,
+                        time_signature,
+                        bass_section_seed,
+                        bass_voice,
+                    ));
+                    bass_line.truncate(render_len);
+                }
+                if style.eq_ignore_ascii_case("metal") {
+                    soft_clip(&mut bass_line, METAL_DRIVE, BASS_TRACK_PEAK);
+                }
+                bass_line
+            })
+        };
+        let drum_track = {
+            let style = style.to_string();
+            let (drums, drum_start, drum_len, seed) = (self.drums, self.drum_start, self.drum_len, self.seed);
+            pool.fork(move || {
+                if drums {
+                    let start = section_start - drum_start;
+                    drums::get_drums(
+                        &style,
+                        bpm,
+                        swing,
+                        time_signature,
+                        drum_len,
+                        start..start + render_len,
+                        seed,
+                        nes,
+                    )
+                } else {
+                    Vec::new()
+                }
+            })
+        };
+        let (mut melody, harmony, counter_melody) = {
+            // Melody parameters depend on the resolved BPM (including the random fallback);
+            // electronic replaces the melody with an arpeggio of the chords
+            let (mut melody, harmony, contour) = if style.eq_ignore_ascii_case("electronic") {
+                // The closing tonic is listed twice, as it lasts up to two chords
+                let mut arpeggio_roots = roots.clone();
+                if last {
+                    arpeggio_roots.extend(roots.last());
+                }
+                let (arpeggio, _) = melodies::get_arpeggio(
+                    key.0,
+                    &arpeggio_roots,
+                    chord_duration,
+                    section_seconds,
+                    bpm,
+                    swing,
+                    section.octave_lift(),
+                    last,
+                    self.lead_waveform,
+                    self.pulse_width,
+                    render_len,
+                    section_seed,
+                );
+                (arpeggio, Vec::new(), Vec::new()) // The arpeggio already spells out the chords
+            } else {
+                let (melody, harmony, contour, _) = melodies::get_melody(
+                    style,
+                    key.0,
+                    key.1,
+                    section_seconds,
+                    bpm,
+                    swing,
+                    self.beats_per_bar,
+                    section.octave_lift(),
+                    last,
+                    section.has_harmony(&self.harmony_setting),
+                    !nes,
+                    self.lead_waveform,
+                    self.pulse_width,
+                    render_len,
+                    section_seed,
+                );
+                (melody, harmony, contour)
+            };
+            melody.resize(render_len, 0.0);
+            let counter_melody = if counter_level > 0.0 {
+                // The chords under this section, timed from its start
+                let seconds = |samples: usize| samples as f32 / SAMPLE_RATE as f32;
+                let chord_spans: Vec<progs::ChordSpan> = section_chords
+                    .iter()
+                    .map(|(start, chord)| progs::ChordSpan {
+                        start_seconds: seconds(*start),
+                        duration_seconds: seconds(chord.samples.len()),
+                        tones: chord.tones.clone(),
+                    })
+                    .collect();
+                melodies::get_counter_melody(
+                    key.0,
+                    &chord_spans,
+                    &contour,
+                    bpm,
+                    section.octave_lift(),
+                    last,
+                    render_len,
+                    section_seed,
+                )
+            } else {
+                Vec::new()
+            };
+            (melody, harmony, counter_melody)
+        };
+        let (bass_line, drum_track) = (bass_line.join(), drum_track.join());
+        if let Some(echo) = &mut self.echo {
+            echo.process(&mut melody);
+        }
+
+        // The NES triangle has no volume control, so its bass is either on or off
+        let [melody_level, chord_level, bass_level, drum_level] = {
+            let mut levels = section.levels();
+            if nes {
+                levels[2] = if levels[2] > 0.0 { 1.0 } else { 0.0 };
+            }
+            levels
+        };
+        let (fade_in_len, fade_out_len, song_len) = (self.fade_in_len, self.fade_out_len, self.song_len);
+        let song_fade = |position: usize| {
+            let fade_in = (position as f32 / fade_in_len as f32).min(1.0);
+            let fade_out = match song_len {
+                Some(song_len) if fade_out_len > 0 => ((song_len - position) as f32 / fade_out_len as f32).min(1.0),
+                _ => 1.0,
+            };
+            fade_in.min(fade_out)
+        };
+        let mut mixed_audio = Vec::with_capacity(render_len);
+        let mut chord_index = 0;
+        for (i, melody_sample) in melody.iter().enumerate() {
+            while section_chords.get(chord_index + 1).is_some_and(|&(start, _)| start <= i) {
+                chord_index += 1;
+            }
+            let chord_sample = section_chords
+                .get(chord_index)
+                .and_then(|(start, chord)| chord.samples.get(i - start));
+            let chord_sample_val = chord_sample.copied().unwrap_or(0.0) * self.chord_gain * chord_level;
+            // Mono for now, so the counter-melody shares the lead's position
+            let melody_sample_val = (melody_sample
+                + harmony.get(i).copied().unwrap_or(0.0) * HARMONY_LEVEL
+                + counter_melody.get(i).copied().unwrap_or(0.0) * counter_level)
+                * self.melody_gain
+                * melody_level;
+            let bass_sample_val = bass_line.get(i).copied().unwrap_or(0.0) * self.bass_gain * bass_level;
+            let (melody_sample_val, chord_sample_val, bass_sample_val) = match (&mut self.track_filters, self.brightness) {
+                (Some([melody_filter, chord_filter, bass_filter]), Some((cutoff, sweep))) => {
+                    if sweep {
+                        // Opens from a muffled start to the full cutoff by the section's end
+                        let progress = i as f32 / section_len as f32;
+                        let swept = cutoff * SWEEP_START_RATIO.powf(1.0 - progress);
+                        for filter in [&mut *melody_filter, &mut *chord_filter, &mut *bass_filter] {
+                            filter.set_cutoff(swept);
+                        }
+                    }
+                    (
+                        melody_filter.process(melody_sample_val),
+                        chord_filter.process(chord_sample_val),
+                        bass_filter.process(bass_sample_val),
+                    )
+                }
+                _ => (melody_sample_val, chord_sample_val, bass_sample_val),
+            };
+            let drum_sample_val = drum_track.get(i).copied().unwrap_or(0.0) * self.drum_gain * drum_level;
+            let fade = if section == SongSection::Outro {
+                1.0 - (1.0 - OUTRO_END_LEVEL) * i as f32 / section_len as f32
+            } else {
+                1.0
+            };
+            let mix = melody_sample_val + chord_sample_val + bass_sample_val + drum_sample_val;
+            // The fades come after the master bus, which would otherwise level them back up
+            let sample = self.limiter.process(self.leveler.process(mix)) * fade * song_fade(section_start + i);
+            // The lo-fi stage comes last, so the output holds only its quantized levels
+            mixed_audio.push(match &mut self.lofi {
+                Some(lofi) => lofi.process(sample),
+                None => sample,
+            });
+        }
+        Some(mixed_audio)
+    }
+}
+
+/* round_seed - Returns the seed one round of an endless song plays from.
+ *
+ * inputs:
+ *     - seed (u64): The song's seed, or its melody or bass seed.
+ *     - round (u64): The round, from 0.
+ *
+ * outputs:
+ *     - u64: The seed itself for the first round, so an endless song opens like the song
+ *       with its seed, and a seed of its own for every later one.
+ */
+fn round_seed(seed: u64, round: u64) -> u64 {
+    seed ^ round.wrapping_mul(ENDLESS_ROUND_SEED_STEP)
+}
+
+/* generate_audio_from_state - Generates raw audio samples based on application state.
+ *
+ * This internal function takes the current `AppState` (scale, style, BPM, etc.) and
+ * orchestrates calls to melody, chord progression, bass line and drum generation modules
+ * (drums only when `app_state.drums` is set).
+ * It then mixes these components and passes the mix through a slow leveler and a peak limiter
+ * (see `dsp::Leveler` and `dsp::Limiter`), so songs come out at a similar loudness and never
+ * clip; a full render also measures the loudness it came out at. Ambient stretches each chord
+ * to 2-4 times its usual length, fades it in and out, and mixes its melody quieter; classical
+ * breaks its chords into an Alberti pattern, folk plays one chord per 3/4 bar as waltz stabs,
+ * metal distorts its chords and bass, and reggae skanks its chords on the off-beats.
+ * The melody, bass and drums share the song's swing (see `resolve_swing`) and time signature
+ * (see `resolve_time_signature`). In 4/4 chords last 2-4 beats; in 3/4 and 6/8 each lasts a bar.
+ *
+ * The song is rounded to whole bars. The progression loops until two chords before the end and
+ * the song closes on a cadence (see `progs::get_cadence`) whose tonic fills the last bars; the
+ * melody ends on the root, held for at least a beat, and the bass lands on the tonic's root.
+ * The whole progression cycles are divided into sections (see `plan_sections`).
+ * Each section generates its own melody and bass line in its key, with its own seed, and
+ * mixes the tracks at its own levels (see `SongSection`); the drums run across the whole song.
+ * With a key change (see `resolve_modulation`), the last section before the outro and the
+ * outro play in the new key (see `modulated_key`). The sections are rendered one after
+ * another by a `SongGenerator`.
+ *
+ * Every random decision is made up front, and the master bus only looks back at the samples
+ * before the current one, so rendering only the opening seconds produces an exact prefix of the
+ * full render. The limit is rounded up to a whole number of chords, which keeps chord
+ * level decisions (such as bass fills) identical between the two.
+ *
+ * The BPM must already be within `MIN_BPM`-`MAX_BPM` (see `song_bpm`) and the length is clamped
+ * to `MIN_SONG_SECS`-`MAX_SONG_SECS`, so any parseable song ID produces a playable, non-empty
+ * song. An endless song, which can't be rendered whole, comes out as a song the length of one
+ * of its rounds.
+ *
+ * inputs:
+ *     - app_state (&AppState): The current application state defining music parameters.
+ *     - seed (u64): The seed for random number generation (see `resolve_seed`).
+ *     - render_seconds (Option<f32>): Render only about this many seconds, or None for the whole song.
+ *
+ * outputs:
+ *     - RenderedSong: The mixed audio samples along with the full song length, resolved BPM, bar length and timelines.
+ */
+fn generate_audio_from_state(
+    app_state: &AppState,
+    seed: u64,
+    render_seconds: Option<f32>,
+) -> RenderedSong {
+    let mut generator = SongGenerator::new(app_state, seed, render_seconds, false);
+    let mut mixed_audio = Vec::with_capacity(generator.target_len);
+    while let Some(section) = generator.next_section() {
+        mixed_audio.extend(section);
+    }
+    let song_len = generator.song_len.unwrap_or(mixed_audio.len());
+
+    // Only a full render is measured; a preview's opening says little about the whole song
+    let loudness_db = if mixed_audio.len() >= song_len {
+        dsp::integrated_loudness(mixed_audio.iter().copied(), SAMPLE_RATE as f32)
+    } else {
+        None
+    };
+    RenderedSong {
+        samples: Arc::new(mixed_audio),
+        song_len,
+        bpm: generator.bpm,
+        beats_per_bar: generator.beats_per_bar as u32,
+        chord_timeline: generator.chord_timeline,
+        section_timeline: generator.section_timeline,
+        loudness_db,
+    }
+}
+
+/* failed_message - Builds the report a service sends when it can't play at all.
+ *
+ * inputs:
+ *     - generation (u64): The id of the service.
+ *     - error (String): Why it can't play.
+ *
+ * outputs:
+ *     - MusicProgress: A report with `error` set and no song in it.
+ */
+fn failed_message(generation: u64, error: String) -> MusicProgress {
+    MusicProgress {
+        current_samples: 0,
+        total_samples: 0,
+        endless: false,
+        actual_seed: 0,
+        app_state: None,
+        level: 0.0,
+        scope: Vec::new(),
+        spectrum: Vec::new(),
+        current_beat: 0,
+        beat_phase: 0.0,
+        beats_per_bar: progs::TimeSignature::default().beats_per_bar() as u32,
+        bpm: 0,
+        chord_timeline: None,
+        section_timeline: None,
+        loudness_db: None,
+        generating: false,
+        generation,
+        error: Some(error),
+    }
+}
+
+/* run_music_service - Main function for the music generation and playback thread.
+ *
+ * This function initializes a `MusicPlayer`, generates initial audio based on `initial_app_state`,
+ * and then enters a loop to handle control messages (Pause, Resume, Rewind, Terminate)
+ * and report playback progress. Music plays automatically unless explicitly paused.
+ *
+ * The service runs on the calling thread and only returns once its audio has stopped and its
+ * output stream is closed, so joining that thread after a Terminate guarantees the song is
+ * silent before another service starts. If the song's BPM is out of range (see `parse_bpm`)
+ * or the audio output can't be opened, a report with `error` set is sent and the service
+ * returns right away.
+ *
+ * inputs:
+ *     - initial_app_state (AppState): The application state to use for generating the first song.
+ *     - initial_song (Option<Arc<PreparedSong>>): The first song, if it was already rendered
+ *       (in which case its app state is used, apart from the loop and crossfade settings in `initial_app_state`).
+ *     - fade_in (Duration): Fade-in for the first song, used when it crossfades in after a skip (zero = none).
+ *     - generation (u64): The id stamped on every progress report (see `MusicProgress`).
+ *     - output (AudioOutput): Where to play the audio.
+ *     - stop_requested (Arc<AtomicBool>): Set by the controller along with a Terminate; the
+ *       service never starts playing once it is set.
+ *     - receiver (CrossbeamReceiver<MusicControl>): Channel to receive control messages.
+ *     - progress_sender (CrossbeamSender<MusicProgress>): Channel to send progress updates.
+ *
+ * outputs:
+ *     - None (returns once terminated, or once the control channel is closed).
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn run_music_service(
+    initial_app_state: AppState,
+    initial_song: Option<Arc<PreparedSong>>,
+    fade_in: Duration,
+    generation: u64,
+    output: AudioOutput,
+    stop_requested: Arc<AtomicBool>,
+    receiver: CrossbeamReceiver<MusicControl>,
+    progress_sender: CrossbeamSender<MusicProgress>,
+) {
+    const SAMPLE_RATE_PROGRESS: f32 = SAMPLE_RATE as f32; // Use the same sample rate as audio generation
+    const PROGRESS_UPDATE_INTERVAL: Duration = Duration::from_millis(33); // Update progress every ~33ms for ~30fps updates
+    const MIN_PROGRESS_DELTA: u64 = (SAMPLE_RATE_PROGRESS * 0.05) as u64; // Minimum 50ms change to report
+
+    // A BPM out of range is turned down rather than played at some other tempo
+    let song_state = initial_song.as_ref().map_or(&initial_app_state, |song| &song.app_state);
+    if let Err(error) = parse_bpm(&song_state.bpm) {
+        let _ = progress_sender.send(failed_message(generation, error));
+        return;
+    }
+    let mut player = match MusicPlayer::new(receiver, output) {
+        Ok(player) => player,
+        Err(error) => {
+            let _ = progress_sender.send(failed_message(generation, error));
+            return;
+        }
+    };
+    // Looping follows the caller even when the song was rendered before a loop toggle
+    player.is_looping = initial_app_state.is_looping;
+    player.crossfade = crossfade_duration(initial_app_state.crossfade_secs);
+    player.fade_in = (!fade_in.is_zero()).then_some(fade_in);
+    player.generation = generation;
+    player.stop_requested = stop_requested;
+    let mut current_app_state_for_generation = match &initial_song {
+        Some(song) => song.app_state.clone(),
+        None => initial_app_state,
+    };
+    current_app_state_for_generation.is_looping = player.is_looping;
+    current_app_state_for_generation.crossfade_secs = player.crossfade.as_secs() as u32;
+    let mut actual_seed_for_current_song: u64;
+    // Song to auto-advance to, if the controller has rendered one in advance
+    let mut next_song: Option<Arc<PreparedSong>> = None;
+    // During a crossfade the new song is announced at the fade midpoint, not when it starts
+    let mut announce_song_at: Option<Instant> = None;
+    // The control message that ended the last wait, handled first
+    let mut woken_by: Option<MusicControl> = None;
+
+    // Initial audio generation based on initial_app_state
+    {
+        // Auto-plays unless manually paused
+        actual_seed_for_current_song = match &initial_song {
+            Some(song) => player.start_prepared_song(song),
+            None => {
+                let _ = progress_sender.send(player.generating_message(0));
+                player.start_song(&current_app_state_for_generation)
+            }
+        };
+        let _ = progress_sender.send(player.progress_message(0, actual_seed_for_current_song, Some(current_app_state_for_generation.clone())));
+    }
+
+    'service_loop: loop {
+        // Process all pending control messages first
+        loop {
+            match woken_by.take().map(Ok).unwrap_or_else(|| player.receiver.try_recv()) {
+                Ok(MusicControl::Pause) => {
+                    player.is_manually_paused = true;
+                    // A pause cuts a crossfade short rather than leaving the old song playing
+                    player.fading_sink = None;
+                    if !player.sink.is_paused() {
+                        player.sink.pause();
+                        // Send immediate update when pausing
+                        let position = player.position().min(player.total_samples);
+                        let _ = progress_sender.try_send(player.progress_message(position, actual_seed_for_current_song, None));
+                    }
+                }
+                Ok(MusicControl::Resume) => {
+                    player.is_manually_paused = false;
+                    if player.sink.is_paused() && player.total_samples > 0 {
+                        player.last_progress_update = Instant::now();
+                        player.sink.play();
+                        
+                        // Send immediate update when resuming
+                        let position = player.position().min(player.total_samples);
+                        let _ = progress_sender.try_send(player.progress_message(position, actual_seed_for_current_song, None));
+                    }
+                }
+                Ok(MusicControl::Rewind) => {
+                    if player.is_endless() {
+                        // An endless song only keeps its recent audio, so it starts over from its seed
+                        player.start_stream(&current_app_state_for_generation, actual_seed_for_current_song);
+                        let _ = progress_sender.send(player.timeline_message(0, actual_seed_for_current_song));
+                    } else if !player.audio.is_empty() {
+                        // Keeps rendering the rest if the song isn't done yet; auto-plays unless manually paused
+                        let position = player.seek(0);
+                        let _ = progress_sender.send(player.progress_message(position, actual_seed_for_current_song, None));
+                    }
+                }
+                Ok(MusicControl::SeekToBeat(beat)) => {
+                    if player.bpm > 0 {
+                        let position = (beat as f64 * 60.0 * SAMPLE_RATE as f64 / player.bpm as f64) as u64;
+                        let position = player.seek(position);
+                        let _ = progress_sender.send(player.progress_message(position, actual_seed_for_current_song, None));
+                        player.last_reported_samples = position;
+                    }
+                }
+                Ok(MusicControl::Seek(fraction)) => {
+                    // An endless song has no length to seek within
+                    if player.total_samples > 0 && !player.is_endless() {
+                        let position = (fraction.clamp(0.0, 1.0) as f64 * player.total_samples as f64) as u64;
+                        let position = player.seek(position);
+                        let _ = progress_sender.send(player.progress_message(position, actual_seed_for_current_song, None));
+                        player.last_reported_samples = position;
+                    }
+                }
+                Ok(MusicControl::SetVolume(volume)) => {
+                    let volume = volume.clamp(0.0, 2.0);
+                    // Fades continue from the new level, so muting mid-fade is immediate
+                    if let Some((_, ramp)) = &mut player.fading_sink {
+                        ramp.from = volume;
+                    }
+                    match &mut player.fade_out {
+                        Some(ramp) => ramp.from = volume,
+                        None => player.sink.set_volume(volume),
+                    }
+                }
+                Ok(MusicControl::SetNextSong(song)) => {
+                    next_song = Some(song);
+                }
+                Ok(MusicControl::SetLoop(is_looping)) => {
+                    // A repeat that is already queued is dropped when the song ends
+                    player.is_looping = is_looping;
+                    current_app_state_for_generation.is_looping = is_looping;
+                }
+                Ok(MusicControl::SetStopAfterCurrent(enabled)) => {
+                    // A repeat that is already queued is dropped when the song ends
+                    player.stop_after_current = enabled;
+                }
+                Ok(MusicControl::SetCrossfade(seconds)) => {
+                    player.crossfade = crossfade_duration(seconds);
+                    current_app_state_for_generation.crossfade_secs = player.crossfade.as_secs() as u32;
+                }
+                Ok(MusicControl::FadeOut(duration)) => {
+                    if player.sink.is_paused() || duration.is_zero() {
+                        // Nothing audible to fade
+                        player.should_terminate = true;
+                        player.sink.stop();
+                        break 'service_loop;
+                    }
+                    player.fade_out = Some(VolumeRamp::new(duration, player.sink.volume()));
+                }
+                Ok(MusicControl::Terminate) => {
+                    player.should_terminate = true;
+                    player.sink.stop();
+                    break 'service_loop;
+                }
+                Err(crossbeam_channel::TryRecvError::Empty) => {
+                    break; // No more messages, exit inner message loop
+                }
+                Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                    player.should_terminate = true;
+                    break 'service_loop;
+                }
+            }
+        }
+
+        if !player.should_continue() {
+            break 'service_loop;
+        }
+
+        player.step_fades();
+        if !player.should_continue() {
+            break 'service_loop;
+        }
+
+        let current_samples = player.position();
+        // Keeps rendering while paused, so a finite song is ready to loop or seek sooner
+        if player.stream_sections(current_samples) {
+            let _ = progress_sender.send(player.timeline_message(current_samples, actual_seed_for_current_song));
+        }
+        if !player.sink.is_paused() {
+            player.queue_loop(current_samples);
+            if player.should_crossfade(current_samples) && player.begin_crossfade() {
+                let (new_app_state, seed) = player.advance(next_song.take(), &current_app_state_for_generation);
+                current_app_state_for_generation = new_app_state;
+                actual_seed_for_current_song = seed;
+                announce_song_at = Some(Instant::now() + player.crossfade / 2);
+            }
+        }
+
+        if announce_song_at.is_some_and(|at| Instant::now() >= at) {
+            announce_song_at = None;
+            let current_samples = player.position().min(player.total_samples);
+            let _ = progress_sender.send(player.progress_message(current_samples, actual_seed_for_current_song, Some(current_app_state_for_generation.clone())));
+            player.last_reported_samples = current_samples;
+            player.last_progress_update = Instant::now();
+        }
+
+        // Progress Reporting (held back until a crossfading song is announced)
+        if player.total_samples > 0 && !player.should_terminate && announce_song_at.is_none() {
+            let now = Instant::now();
+            let should_update = if player.sink.is_paused() {
+                // If paused, only update if we haven't sent the paused state yet
+                player.last_reported_samples != player.position().min(player.total_samples)
+            } else {
+                // If playing, check if enough time has passed since last update
+                now.duration_since(player.last_progress_update) >= PROGRESS_UPDATE_INTERVAL
+            };
+
+            if should_update {
+                let position = player.position();
+                let current_samples = if player.loop_queued
+                    && player.is_looping
+                    && !player.stop_after_current
+                    && position >= player.total_samples
+                {
+                    // The queued repeat has started: wrap the count around and keep going
+                    player.played.fetch_sub(player.total_samples, Ordering::Relaxed);
+                    player.loop_queued = false;
+                    (position - player.total_samples).min(player.total_samples)
+                } else {
+                    position.min(player.total_samples)
+                };
+
+                // Always send updates when changing play/pause state
+                // Otherwise, only send if we have a significant change in progress
+                let last_samples = player.last_reported_samples;
+                if player.sink.is_paused() != player.was_paused ||
+                   (current_samples as i64 - last_samples as i64).unsigned_abs() > MIN_PROGRESS_DELTA
+                {
+                    let _ = progress_sender.try_send(player.progress_message(current_samples, actual_seed_for_current_song, None));
+                    player.last_reported_samples = current_samples;
+                    player.was_paused = player.sink.is_paused();
+                }
+                player.last_progress_update = now;
+                
+                // Check if we've reached the end of the current song
+                // An endless song only catches up with its rendered audio if the workers fall behind
+                if current_samples >= player.total_samples && !player.sink.is_paused() && !player.is_endless() {
+                    if player.loop_queued {
+                        // Looping was turned off after the repeat was queued
+                        player.sink.stop();
+                        player.loop_queued = false;
+                    }
+                    player.sink.pause();
+
+                    if player.stop_after_current {
+                        // Report the end as an empty song, then shut down like a Stop
+                        let mut finished = player.progress_message(0, actual_seed_for_current_song, None);
+                        finished.total_samples = 0;
+                        let _ = progress_sender.send(finished);
+                        player.should_terminate = true;
+                    } else if !player.is_manually_paused && player.fade_out.is_none() {
+                        // Not manually paused (or fading out after a skip): generate a new song
+                        // Uses the pre-rendered next song if the controller sent one
+                        if next_song.is_none() {
+                            let _ = progress_sender.send(player.generating_message(actual_seed_for_current_song));
+                        }
+                        let (new_app_state, seed) = player.advance(next_song.take(), &current_app_state_for_generation);
+                        current_app_state_for_generation = new_app_state;
+                        actual_seed_for_current_song = seed;
+                        
+                        // Reset playback state
+                        player.is_manually_paused = false;
+
+                        // Send progress update with new state
+                        let _ = progress_sender.send(player.progress_message(0, actual_seed_for_current_song, Some(current_app_state_for_generation.clone())));
+                    }
+                }
+            }
+        }
+        // Wakes early for a control message, so a Terminate stops the audio right away
+        let tick = if player.is_fading() { FADE_TICK } else { Duration::from_millis(100) };
+        woken_by = player.receiver.recv_timeout(tick).ok();
+    }
+}
+
+/* edit_distance - Counts the single-letter edits that turn one word into another.
+ *
+ * inputs:
+ *     - a (&str): The first word.
+ *     - b (&str): The second word.
+ *
+ * outputs:
+ *     - usize: The Levenshtein distance between the two, ignoring case.
+ */
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    // Distances from the start of `a` so far to every start of `b`, one row at a time
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &a_char) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/* did_you_mean - Suggests the name a mistyped word was most likely meant to be.
+ *
+ * A name the word starts ("Electro") wins; otherwise the nearest name by `edit_distance`, as
+ * long as no more than half its letters are wrong. Of equally near names, one starting with the
+ * word's first letter wins, so "E#" is taken for 'E' rather than 'C#'.
+ *
+ * inputs:
+ *     - word (&str): The word as typed.
+ *     - names (&[&str]): The names it should have been one of.
+ *
+ * outputs:
+ *     - String: " Did you mean 'Name'?" to add to an error message, or empty if nothing is close.
+ */
+fn did_you_mean(word: &str, names: &[&str]) -> String {
+    let typed = word.to_lowercase();
+    let prefix_of = names.iter().find(|name| !typed.is_empty() && name.to_lowercase().starts_with(&typed));
+    let first_letter = typed.chars().next();
+    let nearest = || {
+        names
+            .iter()
+            .map(|name| (edit_distance(word, name), name))
+            .filter(|&(distance, name)| distance <= name.chars().count().max(word.chars().count()) / 2)
+            .min_by_key(|&(distance, name)| (distance, name.to_lowercase().chars().next() != first_letter))
+            .map(|(_, name)| name)
+    };
+    match prefix_of.or_else(nearest) {
+        Some(name) => format!(" Did you mean '{}'?", name),
+        None => String::new(),
+    }
+}
+
+/* parse_song_id_to_app_state - Parses a song ID string into an `AppState`.
+ *
+ * The song ID format is expected to be "Scale-Style-BPM-Length-Seed", e.g., "C-Pop-120-5-12345".
+ * The scale is the root note, followed by `MINOR_KEY_SUFFIX` for minor keys ("Cm-Pop-120-5-12345");
+ * IDs from before key qualities existed have a bare root and load as major. A swing, time
+ * signature or key change set by hand adds a part before the seed, in that order
+ * ("C-Jazz-120-5-s50-t34-k1-12345", see `SWING_ID_PREFIX`, `TIME_SIGNATURE_ID_PREFIX` and
+ * `MODULATION_ID_PREFIX`); IDs without them use the style's own and `AUTO_MODULATION`.
+ * A custom progression, chosen waveforms, a melody echo turned off, a lo-fi setting and NES
+ * mode follow (see `PROGRESSION_ID_PREFIX`, `WAVEFORM_ID_PREFIX`, `ECHO_ID_PREFIX`,
+ * `LOFI_ID_PREFIX` and `NES_ID_PREFIX`); without them the style picks them all and NES mode is off.
+ * Then come the drums left out, the harmony, counter-melody, arp chords, comping, pulse width
+ * and brightness settings, a fade-out and the mix (see `DRUMS_ID_PREFIX`, `SETTING_ID_PARTS`,
+ * `FADE_OUT_ID_PREFIX` and `GAINS_ID_PREFIX`), so the ID plays the same on every machine;
+ * without them the drums play, the settings are their defaults and every track is at 50%.
+ * Last come the seeds of a varied melody and bass (see `MELODY_SEED_ID_PREFIX` and
+ * `BASS_SEED_ID_PREFIX`); without them both are generated from the song's seed, as before.
+ * The whole ID may be preceded by its version ("v2:", see `SONG_ID_VERSION`), which is kept in
+ * the `AppState`; IDs from before versions existed are version 1. A share code (see
+ * `share::SHARE_CODE_PREFIX`) is unpacked into its song ID first.
+ * This function attempts to parse these components and construct an `AppState` suitable for
+ * regenerating or loading the described song. IDs pasted from elsewhere are forgiven stray
+ * spaces around the whole ID and its parts, any case in the scale and style, and a "min"
+ * after the length ("C-Pop-120-5min-12345"). The length may also be fractional or in seconds
+ * ("2.5", "150s" or "2:30", see `parse_song_length`).
+ *
+ * inputs:
+ *     - id_string (&str): The song ID string to parse.
+ *
+ * outputs:
+ *     - Result<AppState, String>: Ok with the parsed `AppState` if successful,
+ *                               or an Err with a descriptive message if parsing fails.
+ */
+pub fn parse_song_id_to_app_state(id_string: &str) -> Result<AppState, String> {
+    let id_string = id_string.trim();
+    if let Some(code) = share::strip_share_code_prefix(id_string) {
+        return parse_song_id_to_app_state(&share::song_id_from_share_code(code)?);
+    }
+    // A ':' after the first '-' is a length in minutes and seconds, not the version
+    let version_split = id_string
+        .split_once(SONG_ID_VERSION_SEPARATOR)
+        .filter(|(version_part, _)| !version_part.contains('-'));
+    let (id_version, id_string) = match version_split {
+        Some((version_part, rest)) => {
+            let version_part = version_part.trim();
+            let version = version_part
+                .get(..SONG_ID_VERSION_PREFIX.len())
+                .filter(|prefix| prefix.eq_ignore_ascii_case(SONG_ID_VERSION_PREFIX))
+                .and_then(|_| version_part[SONG_ID_VERSION_PREFIX.len()..].parse::<u32>().ok());
+            match version {
+                Some(version) if (1..=SONG_ID_VERSION).contains(&version) => (version, rest),
+                Some(version) if version > SONG_ID_VERSION => {
+                    return Err(format!(
+                        "Song ID is version {}, but this 8BitBeats plays IDs up to version {}. Update 8BitBeats to load it.",
+                        version, SONG_ID_VERSION
+                    ));
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid Version in Song ID: '{}' should be '{}' followed by a version number (e.g. {}{}{}C-Pop-120-5-42).",
+                        version_part, SONG_ID_VERSION_PREFIX, SONG_ID_VERSION_PREFIX, SONG_ID_VERSION, SONG_ID_VERSION_SEPARATOR
+                    ));
+                }
+            }
+        }
+        None => (1, id_string),
+    };
+    let mut parts: Vec<&str> = id_string.split('-').map(str::trim).collect();
+    if !(5..=24).contains(&parts.len()) {
+        return Err(format!(
+            "Invalid Song ID: Expected 5 parts separated by '-', or up to 24 with a swing, time signature, key change, progression, waveforms, echo, lo-fi, NES mode, drums, harmony, counter-melody, arp chords, comping, pulse width, brightness, fade-out and mix setting and a melody and bass seed. Got {}. Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+            parts.len()
+        ));
+    }
+
+    // Optional parts sit between the length and the seed, each marked by its prefix
+    let mut swing = String::new();
+    let mut time_signature = AUTO_TIME_SIGNATURE.to_string();
+    let mut modulation = AUTO_MODULATION.to_string();
+    let mut progression = String::new();
+    let mut waveforms = AppState::default().waveforms;
+    let mut echo = AUTO_ECHO.to_string();
+    let mut lofi = AUTO_LOFI.to_string();
+    let mut nes_mode = false;
+    let mut drums = true;
+    let mut fade_out_secs = None;
+    let mut gains = None;
+    let mut settings: Vec<(&SettingIdPart, usize)> = Vec::new();
+    let mut melody_seed = None;
+    let mut bass_seed = None;
+    let seed_index = parts.len() - 1;
+    for optional_part in parts.drain(4..seed_index) {
+        if let Some(percent) = optional_part.strip_prefix(SWING_ID_PREFIX) {
+            match percent.parse::<u32>() {
+                Ok(percent) if percent <= MAX_SWING_PERCENT => swing = percent.to_string(),
+                _ => {
+                    return Err(format!(
+                        "Invalid Swing in Song ID: '{}' should be '{}' followed by a percentage from 0 to {} (e.g. {}66). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, SWING_ID_PREFIX, MAX_SWING_PERCENT, SWING_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(flag) = optional_part.strip_prefix(MODULATION_ID_PREFIX) {
+            match flag {
+                "1" => modulation = "On".to_string(),
+                "0" => modulation = "Off".to_string(),
+                _ => {
+                    return Err(format!(
+                        "Invalid Key Change in Song ID: '{}' should be {}1 (on) or {}0 (off). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, MODULATION_ID_PREFIX, MODULATION_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(chords) = optional_part.strip_prefix(PROGRESSION_ID_PREFIX) {
+            match progs::parse_roman_progression(&chords.replace(PROGRESSION_ID_SEPARATOR, " ")) {
+                Ok(chords) if !chords.is_empty() => progression = progs::roman_progression_text(&chords),
+                Ok(_) => {
+                    return Err(format!(
+                        "Invalid Progression in Song ID: '{}' has no chords after '{}'. Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, PROGRESSION_ID_PREFIX
+                    ));
+                }
+                Err(error_message) => {
+                    return Err(format!(
+                        "Invalid Progression in Song ID: {}. Chords are separated by '{}' (e.g. {}I.V.vi.IV).",
+                        error_message, PROGRESSION_ID_SEPARATOR, PROGRESSION_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(level) = optional_part.strip_prefix(LOFI_ID_PREFIX) {
+            match level.parse::<usize>().ok().and_then(|level| LOFI_SETTINGS.get(level + 1)) {
+                Some(setting) if level.len() == 1 => lofi = setting.to_string(),
+                _ => {
+                    return Err(format!(
+                        "Invalid Lo-fi in Song ID: '{}' should be {}0 (off), {}1 (light) or {}2 (heavy). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, LOFI_ID_PREFIX, LOFI_ID_PREFIX, LOFI_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(flag) = optional_part.strip_prefix(NES_ID_PREFIX) {
+            match flag {
+                "1" => nes_mode = true,
+                "0" => nes_mode = false,
+                _ => {
+                    return Err(format!(
+                        "Invalid NES Mode in Song ID: '{}' should be {}1 (on) or {}0 (off). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, NES_ID_PREFIX, NES_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(flag) = optional_part.strip_prefix(ECHO_ID_PREFIX) {
+            match flag {
+                "1" => echo = AUTO_ECHO.to_string(),
+                "0" => echo = "Off".to_string(),
+                _ => {
+                    return Err(format!(
+                        "Invalid Echo in Song ID: '{}' should be {}1 (the style's) or {}0 (off). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, ECHO_ID_PREFIX, ECHO_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(flag) = optional_part.strip_prefix(DRUMS_ID_PREFIX) {
+            match flag {
+                "1" => drums = true,
+                "0" => drums = false,
+                _ => {
+                    return Err(format!(
+                        "Invalid Drums in Song ID: '{}' should be {}1 (on) or {}0 (off). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, DRUMS_ID_PREFIX, DRUMS_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(seconds) = optional_part.strip_prefix(FADE_OUT_ID_PREFIX) {
+            match seconds.parse::<u32>() {
+                Ok(seconds) if seconds <= MAX_FADE_OUT_SECS => fade_out_secs = Some(seconds),
+                _ => {
+                    return Err(format!(
+                        "Invalid Fade-out in Song ID: '{}' should be '{}' followed by seconds from 0 to {} (e.g. {}8). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, FADE_OUT_ID_PREFIX, MAX_FADE_OUT_SECS, FADE_OUT_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(levels) = optional_part.strip_prefix(GAINS_ID_PREFIX) {
+            let levels: Option<Vec<String>> = levels
+                .split(PROGRESSION_ID_SEPARATOR)
+                .map(|level| level.parse::<u32>().ok().filter(|&percent| percent <= 100).map(|percent| percent.to_string()))
+                .collect();
+            match levels.and_then(|levels| <[String; 3]>::try_from(levels).ok()) {
+                Some(levels) => gains = Some(levels),
+                None => {
+                    return Err(format!(
+                        "Invalid Mix in Song ID: '{}' should be '{}' followed by the melody, chord and bass levels from 0 to 100, separated by '{}' (e.g. {}70{}50{}40). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, GAINS_ID_PREFIX, PROGRESSION_ID_SEPARATOR, GAINS_ID_PREFIX, PROGRESSION_ID_SEPARATOR, PROGRESSION_ID_SEPARATOR
+                    ));
+                }
+            }
+        } else if let Some(part) = SETTING_ID_PARTS.iter().find(|part| optional_part.starts_with(part.prefix)) {
+            match optional_part[part.prefix.len()..].parse::<usize>() {
+                Ok(index) if index < part.settings.len() => settings.push((part, index)),
+                _ => {
+                    let choices: Vec<String> = part
+                        .settings
+                        .iter()
+                        .enumerate()
+                        .map(|(index, setting)| format!("{}{} ({})", part.prefix, index, setting))
+                        .collect();
+                    return Err(format!(
+                        "Invalid {} in Song ID: '{}' should be one of {}. Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        part.name,
+                        optional_part,
+                        choices.join(", ")
+                    ));
+                }
+            }
+        } else if let Some((prefix, sub_seed)) = [(MELODY_SEED_ID_PREFIX, &mut melody_seed), (BASS_SEED_ID_PREFIX, &mut bass_seed)]
+            .into_iter()
+            .find(|(prefix, _)| optional_part.starts_with(prefix))
+        {
+            match optional_part[prefix.len()..].parse::<u64>() {
+                Ok(seed) => *sub_seed = Some(seed),
+                Err(_) => {
+                    return Err(format!(
+                        "Invalid Melody or Bass Seed in Song ID: '{}' should be {} (melody) or {} (bass) followed by a number (e.g. {}123). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part, MELODY_SEED_ID_PREFIX, BASS_SEED_ID_PREFIX, MELODY_SEED_ID_PREFIX
+                    ));
+                }
+            }
+        } else if let Some(letters) = optional_part.strip_prefix(WAVEFORM_ID_PREFIX) {
+            match parse_waveforms_id_part(letters) {
+                Some(settings) => waveforms = settings,
+                None => {
+                    return Err(format!(
+                        "Invalid Waveforms in Song ID: '{}' should be '{}' followed by a letter for the lead, chords and bass: {} for auto, or {}. Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part,
+                        WAVEFORM_ID_PREFIX,
+                        AUTO_WAVEFORM_ID_CHAR,
+                        Waveform::ALL
+                            .iter()
+                            .map(|waveform| format!("{} ({})", waveform.id_char(), waveform.label()))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+            }
+        } else {
+            match progs::TimeSignature::ALL
+                .into_iter()
+                .find(|&signature| time_signature_id_part(signature) == optional_part)
+            {
+                Some(signature) => time_signature = signature.label().to_string(),
+                None => {
+                    let examples: Vec<String> = progs::TimeSignature::ALL.into_iter().map(time_signature_id_part).collect();
+                    return Err(format!(
+                        "Invalid part in Song ID: '{}' should be a swing (e.g. {}66), a time signature ({}), a key change ({}1 or {}0), a progression ({}I.V.vi.IV), waveforms ({}aat), an echo ({}0), a lo-fi setting ({}1), NES mode ({}1), drums off ({}0), a fade-out ({}8), a mix ({}70.50.40), {} or a melody or bass seed ({}123 or {}123). Format: Scale-Style-BPM-LengthInMinutes[-sSwing][-tTime][-kKeyChange][-pChords][-wWaves][-eEcho][-lLoFi][-nNes][-dDrums][-hHarmony][-cCounter][-aArp][-rComping][-uPulse][-fTone][-oFadeOut][-gMix][-mMelody][-bBass]-Seed",
+                        optional_part,
+                        SWING_ID_PREFIX,
+                        examples.join(", "),
+                        MODULATION_ID_PREFIX,
+                        MODULATION_ID_PREFIX,
+                        PROGRESSION_ID_PREFIX,
+                        WAVEFORM_ID_PREFIX,
+                        ECHO_ID_PREFIX,
+                        LOFI_ID_PREFIX,
+                        NES_ID_PREFIX,
+                        DRUMS_ID_PREFIX,
+                        FADE_OUT_ID_PREFIX,
+                        GAINS_ID_PREFIX,
+                        SETTING_ID_PARTS
+                            .iter()
+                            .map(|part| format!("{} ({}1)", part.name.to_lowercase(), part.prefix))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        MELODY_SEED_ID_PREFIX,
+                        BASS_SEED_ID_PREFIX
+                    ));
+                }
+            }
+        }
+    }
+
+    // The root is a letter and an optional sharp; anything after it is the key quality
+    let key = parts[0];
+    let root_len = match key.char_indices().nth(1) {
+        Some((index, '#')) => index + 1,
+        Some((index, _)) => index,
+        None => key.len(),
+    };
+    let (scale, quality_suffix) = key.split_at(root_len);
+    let quality = match quality_suffix {
+        "" => "Major",
+        suffix if suffix.eq_ignore_ascii_case(MINOR_KEY_SUFFIX) => "Minor",
+        _ => {
+            return Err(format!(
+                "Invalid Scale in Song ID: '{}' should be a root note, followed by '{}' for a minor key (e.g. C, C#, Cm or C#m). Format: Scale-Style-BPM-LengthInMinutes-Seed",
+                key, MINOR_KEY_SUFFIX
+            ));
+        }
+    };
+    // Names match in any case, and load as the UI spells them
+    let Some(scale) = SCALE_NAMES.iter().find(|name| name.eq_ignore_ascii_case(scale)) else {
+        return Err(format!(
+            "Invalid Scale in Song ID: '{}' is not a root note ({}).{} Format: Scale-Style-BPM-LengthInMinutes-Seed",
+            scale,
+            SCALE_NAMES.join(", "),
+            did_you_mean(scale, &SCALE_NAMES)
+        ));
+    };
+    let Some(style) = STYLE_NAMES.iter().find(|name| name.eq_ignore_ascii_case(parts[1])) else {
+        return Err(format!(
+            "Invalid Style in Song ID: '{}' is not a style ({}).{} Format: Scale-Style-BPM-LengthInMinutes-Seed",
+            parts[1],
+            STYLE_NAMES.join(", "),
+            did_you_mean(parts[1], &STYLE_NAMES)
+        ));
+    };
+    let scale = scale.to_string();
+    let style = style.to_string();
+    let seed_str = parts[4].to_string();
+
+    let bpm_str = match parse_bpm(parts[2]) {
+        Ok(tempo) => tempo.map(|t| t.to_string()).unwrap_or_default(),
+        Err(error_message) => {
+            return Err(format!(
+                "Invalid BPM in Song ID: {} Format: Scale-Style-BPM-LengthInMinutes-Seed",
+                error_message
+            ));
+        }
+    };
+
+    let length_secs = match parse_song_length(parts[3]) {
+        Ok(secs) => secs,
+        Err(error_message) => {
+            return Err(format!(
+                "Invalid Length in Song ID: {} Format: Scale-Style-BPM-LengthInMinutes-Seed",
+                error_message
+            ));
+        }
+    };
+
+    if seed_str.parse::<u64>().is_err() && !seed_str.is_empty() {
+        return Err(format!(
+           "Invalid Seed in Song ID: '{}' is not a valid number. Format: Scale-Style-BPM-LengthInMinutes-Seed", 
+           seed_str
+        ));
+    }
+
+    let [melody_gain, chord_gain, bass_gain] = gains.unwrap_or_else(|| [(); 3].map(|_| DEFAULT_TRACK_GAIN_PERCENT.to_string()));
+    let mut app_state = AppState {
+        scale,
+        quality: quality.to_string(),
+        style,
+        bpm: bpm_str,
+        length_secs,
+        seed: seed_str,
+        swing,
+        time_signature,
+        modulation,
+        progression,
+        waveforms,
+        echo,
+        lofi,
+        nes_mode,
+        drums,
+        fade_out_secs,
+        melody_gain,
+        chord_gain,
+        bass_gain,
+        melody_seed,
+        bass_seed,
+        id_version,
+        ..Default::default()
+    };
+    for (part, index) in settings {
+        *(part.get_mut)(&mut app_state) = part.settings[index].to_string();
+    }
+    Ok(app_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use progs::TimeSignature;
+    use quickcheck::{Arbitrary, Gen, QuickCheck, TestResult, Testable};
+    use std::collections::HashSet;
+    use std::thread;
+
+    // WAIT_LIMIT: How long a test waits on a music service before failing.
+    const WAIT_LIMIT: Duration = Duration::from_secs(60);
+
+    fn endless_song() -> AppState {
+        AppState {
+            length_secs: ENDLESS_SONG_SECS,
+            seed: "42".to_string(),
+            drums: false,
+            ..AppState::default()
+        }
+    }
+
+    #[test]
+    fn a_service_asked_to_stop_never_starts_playing() {
+        let (sink_sender, sinks) = crossbeam_channel::unbounded();
+        let (control_sender, control_receiver) = crossbeam_channel::unbounded();
+        let (progress_sender, progress_receiver) = crossbeam_channel::unbounded();
+        // As if the controller gave up waiting while the opening was still rendering
+        let stop_requested = Arc::new(AtomicBool::new(true));
+        let service = thread::spawn(move || {
+            run_music_service(
+                endless_song(),
+                None,
+                Duration::ZERO,
+                1,
+                AudioOutput::Fake(Some(sink_sender)),
+                stop_requested,
+                control_receiver,
+                progress_sender,
+            )
+        });
+        let mut sink = sinks.recv_timeout(WAIT_LIMIT).expect("the service opened no sink");
+        let started = Instant::now();
+        while !progress_receiver
+            .recv_timeout(WAIT_LIMIT)
+            .is_ok_and(|progress| progress.app_state.is_some())
+        {
+            assert!(started.elapsed() < WAIT_LIMIT, "the song never started");
+        }
+        // The opening is queued, yet the sink stays paused
+        assert!(sink.by_ref().take(SAMPLE_RATE as usize).all(|sample| sample == 0.0));
+
+        control_sender.send(MusicControl::Terminate).unwrap();
+        service.join().unwrap();
+        assert!(sink.by_ref().take(SAMPLE_RATE as usize).count() < SAMPLE_RATE as usize);
+    }
+
+    #[test]
+    fn a_missing_audio_device_is_reported_and_ends_the_service() {
+        let (_control_sender, control_receiver) = crossbeam_channel::unbounded();
+        let (progress_sender, progress_receiver) = crossbeam_channel::unbounded();
+        run_music_service(
+            endless_song(),
+            None,
+            Duration::ZERO,
+            7,
+            AudioOutput::Fake(None),
+            Arc::new(AtomicBool::new(false)),
+            control_receiver,
+            progress_sender,
+        );
+        let reports: Vec<MusicProgress> = progress_receiver.try_iter().collect();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].error.is_some());
+        assert_eq!(reports[0].generation, 7);
+        assert_eq!(reports[0].total_samples, 0);
+    }
+
+    #[test]
+    fn a_streamed_song_and_its_preview_match_the_whole_render() {
+        let app_state = AppState {
+            length_secs: MIN_SONG_SECS,
+            seed: "42".to_string(),
+            ..AppState::default()
+        };
+        let whole = generate_audio_from_state(&app_state, 42, None);
+        assert_eq!(whole.samples.len(), whole.song_len);
+
+        // Section by section, as `start_stream` and `stream_sections` play it
+        let mut generator = SongGenerator::new(&app_state, 42, None, false);
+        let mut streamed = Vec::new();
+        let mut sections = 0;
+        while let Some(section) = generator.next_section() {
+            streamed.extend(section);
+            sections += 1;
+        }
+        assert!(sections > 1);
+        assert!(streamed == *whole.samples, "the streamed sections differ from the whole render");
+
+        let preview = generate_audio_from_state(&app_state, 42, Some(10.0));
+        assert!(preview.samples.len() >= 10 * SAMPLE_RATE as usize);
+        assert!(preview.samples.len() < whole.samples.len());
+        assert_eq!(preview.song_len, whole.song_len);
+        assert!(
+            preview.samples[..] == whole.samples[..preview.samples.len()],
+            "the preview is not the opening of the whole render"
+        );
+    }
+
+    #[test]
+    fn song_audio_plays_and_windows_its_chunks_without_copying_them() {
+        let song: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let chunks: Vec<Arc<Vec<f32>>> = [0..30, 30..70, 70..100]
+            .into_iter()
+            .map(|range| Arc::new(song[range].to_vec()))
+            .collect();
+        let mut audio = SongAudio::default();
+        for chunk in &chunks {
+            audio.push(Arc::clone(chunk));
+        }
+        audio.push(Arc::new(Vec::new()));
+        assert_eq!((audio.start(), audio.end()), (0, 100));
+        assert!(audio.samples().eq(song.iter().copied()));
+
+        for position in [0, 29, 30, 55, 99, 100] {
+            let sources = audio.sources_from(position);
+            assert!(sources.iter().all(|source| chunks.iter().any(|chunk| Arc::ptr_eq(chunk, &source.samples))));
+            let played: Vec<f32> = sources.into_iter().flatten().collect();
+            assert_eq!(played, song[position as usize..]);
+        }
+        // Across a chunk edge, and clamped to the ends of the song
+        assert_eq!(audio.window(30, 5), (song[25..35].to_vec(), 5));
+        assert_eq!(audio.window(2, 5), (song[0..7].to_vec(), 2));
+        assert_eq!(audio.window(150, 5), (song[95..100].to_vec(), 5));
+
+        // Only whole chunks that ended before the position are let go of
+        audio.forget_before(50);
+        assert_eq!((audio.start(), audio.end()), (30, 100));
+        assert_eq!(audio.window(10, 5), (song[30..35].to_vec(), 0));
+        let played: Vec<f32> = audio.sources_from(0).into_iter().flatten().collect();
+        assert_eq!(played, song[30..]);
+    }
+
+    #[test]
+    fn existing_song_ids_keep_their_bass() {
+        // The bass lines of a few styles and waveforms, as v2 songs played them before the
+        // melody and chords moved onto `synth::Oscillator` (see `bass_wave`)
+        const V2_BASS_HASH: u64 = 0x1eeb_5d28_0b60_8f14;
+        let samples_per_chord = SAMPLE_RATE as usize;
+        let mut hash: u64 = 0xcbf29ce484222325; // FNV-1a
+        for style in ["pop", "electronic", "ambient"] {
+            for waveform in [Waveform::Square, Waveform::Sawtooth, Waveform::Noise] {
+                let voice = BassVoice { waveform, envelope: true };
+                let bass_line = get_bass_line(
+                    style,
+                    &[0, 9, 5, 7],
+                    samples_per_chord,
+                    samples_per_chord * 4,
+                    120,
+                    0.0,
+                    progs::TimeSignature::default(),
+                    42,
+                    voice,
+                );
+                for sample in bass_line {
+                    hash = (hash ^ sample.to_bits() as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+        }
+        assert_eq!(hash, V2_BASS_HASH, "the bass of existing song IDs changed: {:#018x}", hash);
+    }
+
+    #[test]
+    fn the_bass_sine_and_triangle_match_the_oscillator() {
+        for waveform in [Waveform::Sine, Waveform::Triangle] {
+            for note in [28, 40, 52] {
+                let frequency = note_to_freq(note);
+                let mut oscillator = synth::Oscillator::new(waveform, frequency as f64, SAMPLE_RATE as f64);
+                for i in 0..SAMPLE_RATE as usize * 4 {
+                    let (bass, expected) = (bass_wave(waveform, frequency, i), oscillator.next_sample());
+                    assert!((bass - expected).abs() < 1e-6, "{:?} note {} at sample {}: {} vs {}", waveform, note, i, bass, expected);
+                }
+            }
+        }
+    }
+
+    // PROPERTY_TESTS: Random cases each song ID property is checked against.
+    const PROPERTY_TESTS: u64 = 300;
+    // CHORD_TOKENS: Custom progression chords the generated IDs pick from, spelled the way the
+    // chords field stores them (see `progs::roman_progression_text`).
+    const CHORD_TOKENS: [&str; 14] =
+        ["I", "ii", "iii", "IV", "V", "vi", "viio", "bVII", "V7", "ii7", "Imaj7", "IVaug", "viim7b5", "viio7"];
+
+    /* SongParams - The parameters of a well-formed song ID, picked across their whole range.
+     */
+    #[derive(Clone, Debug)]
+    struct SongParams {
+        app_state: AppState,
+        seed: u64,
+    }
+
+    impl Arbitrary for SongParams {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let pick = |g: &mut Gen, options: &[&str]| g.choose(options).unwrap().to_string();
+            let in_range = |g: &mut Gen, low: u32, high: u32| low + u32::arbitrary(g) % (high - low + 1);
+            let maybe = |g: &mut Gen, value: String| if bool::arbitrary(g) { value } else { String::new() };
+            let time_signatures: Vec<&str> = TimeSignature::ALL.iter().map(|signature| signature.label()).collect();
+            let waveforms: Vec<&str> = Waveform::ALL.iter().map(|waveform| waveform.label()).collect();
+            let chord_count = 1 + usize::arbitrary(g) % progs::MAX_CUSTOM_CHORDS;
+            let chords: Vec<String> = (0..chord_count).map(|_| pick(g, &CHORD_TOKENS)).collect();
+            let bpm = in_range(g, MIN_BPM, MAX_BPM).to_string();
+            let swing = in_range(g, 0, MAX_SWING_PERCENT).to_string();
+            let fade_out_secs = in_range(g, 0, MAX_FADE_OUT_SECS);
+            let gain = |g: &mut Gen| {
+                if bool::arbitrary(g) {
+                    DEFAULT_TRACK_GAIN_PERCENT.to_string()
+                } else {
+                    in_range(g, 0, 100).to_string()
+                }
+            };
+            let mut app_state = AppState {
+                scale: pick(g, &SCALE_NAMES),
+                quality: pick(g, &["Major", "Minor"]),
+                style: pick(g, &STYLE_NAMES),
+                bpm: maybe(g, bpm),
+                length_secs: if bool::arbitrary(g) { ENDLESS_SONG_SECS } else { in_range(g, MIN_SONG_SECS, MAX_SONG_SECS) },
+                swing: maybe(g, swing),
+                time_signature: pick(g, &[&[AUTO_TIME_SIGNATURE], &time_signatures[..]].concat()),
+                modulation: pick(g, &[AUTO_MODULATION, "On", "Off"]),
+                progression: maybe(g, chords.join(" ")),
+                waveforms: [(); 3].map(|_| pick(g, &[&[AUTO_WAVEFORM], &waveforms[..]].concat())),
+                echo: pick(g, &[AUTO_ECHO, "Off"]),
+                lofi: pick(g, &LOFI_SETTINGS),
+                nes_mode: bool::arbitrary(g),
+                drums: bool::arbitrary(g),
+                fade_out_secs: if bool::arbitrary(g) { Some(fade_out_secs) } else { None },
+                melody_gain: gain(g),
+                chord_gain: gain(g),
+                bass_gain: gain(g),
+                melody_seed: Option::arbitrary(g),
+                bass_seed: Option::arbitrary(g),
+                ..AppState::default()
+            };
+            for part in &SETTING_ID_PARTS {
+                *(part.get_mut)(&mut app_state) = pick(g, part.settings);
+            }
+            SongParams { app_state, seed: u64::arbitrary(g) }
+        }
+    }
+
+    fn check(property: impl Testable) {
+        QuickCheck::new().tests(PROPERTY_TESTS).quickcheck(property);
+    }
+
+    #[test]
+    fn parsing_any_text_never_panics() {
+        fn property(text: String) -> bool {
+            for id in [text.clone(), format!("v2:{}", text), format!("C-Pop-120-5-{}", text)] {
+                let _ = parse_song_id_to_app_state(&id);
+            }
+            true
+        }
+        check(property as fn(String) -> bool);
+    }
+
+    #[test]
+    fn parsing_a_damaged_song_id_never_panics() {
+        fn property(params: SongParams, at: usize, cut: usize, text: String) -> bool {
+            let id: Vec<char> = controller::song_id(&params.app_state, params.seed).chars().collect();
+            let at = at % (id.len() + 1);
+            let cut = at + cut % (id.len() - at + 1);
+            let damaged: String = id[..at].iter().chain(text.chars().collect::<Vec<_>>().iter()).chain(&id[cut..]).collect();
+            let _ = parse_song_id_to_app_state(&damaged);
+            true
+        }
+        check(property as fn(SongParams, usize, usize, String) -> bool);
+    }
+
+    #[test]
+    fn every_generated_song_id_round_trips() {
+        fn property(params: SongParams) -> TestResult {
+            let id = controller::song_id(&params.app_state, params.seed);
+            match parse_song_id_to_app_state(&id) {
+                Ok(parsed) => {
+                    let seed = parsed.seed.parse::<u64>().ok();
+                    let again = controller::song_id(&parsed, params.seed);
+                    // Everything that changes the audio comes back, not just the same ID
+                    let heard = |state: &AppState| {
+                        let settings: Vec<&str> = SETTING_ID_PARTS.iter().map(|part| (part.get)(state)).collect();
+                        let gains = [&state.melody_gain, &state.chord_gain, &state.bass_gain].map(|gain| gain.clone());
+                        format!("{:?} {} {:?} {:?}", settings, state.drums, state.fade_out_secs, gains)
+                    };
+                    if seed == Some(params.seed) && again == id && heard(&parsed) == heard(&params.app_state) {
+                        TestResult::passed()
+                    } else {
+                        TestResult::error(format!("{} came back as {}", id, again))
+                    }
+                }
+                Err(error) => TestResult::error(format!("{}: {}", id, error)),
+            }
+        }
+        check(property as fn(SongParams) -> TestResult);
+    }
+
+    // LENGTH_CHECK_SECS: How much of a song the length checks render; the rest of a long or
+    // endless song is planned but never rendered.
+    const LENGTH_CHECK_SECS: f32 = 8.0;
+
+    /* song_length_error - Plays a song ID's opening and checks the song against its length.
+     *
+     * A song of set length is rendered as a preview, which plans the whole song, and its length
+     * is checked to be within a bar of the request; an endless song is streamed section by
+     * section instead.
+     *
+     * inputs:
+     *     - id (&str): The song ID, with a numeric seed.
+     *
+     * outputs:
+     *     - Option<String>: What's wrong with the song, or None if it plays as asked.
+     */
+    fn song_length_error(id: &str) -> Option<String> {
+        let app_state = match parse_song_id_to_app_state(id) {
+            Ok(app_state) => app_state,
+            Err(error) => return Some(format!("{}: {}", id, error)),
+        };
+        let Ok(seed) = app_state.seed.parse::<u64>() else {
+            return Some(format!("{} has no numeric seed", id));
+        };
+        let wanted = (LENGTH_CHECK_SECS * SAMPLE_RATE as f32) as usize;
+        let (samples, song_len) = if app_state.is_endless() {
+            let mut generator = SongGenerator::new(&app_state, seed, None, true);
+            let mut samples = Vec::new();
+            while samples.len() < wanted {
+                match generator.next_section() {
+                    Some(section) if !section.is_empty() => samples.extend(section),
+                    _ => return Some(format!("{} stopped after {} samples", id, samples.len())),
+                }
+            }
+            (samples, None)
+        } else {
+            let render = generate_audio_from_state(&app_state, seed, Some(LENGTH_CHECK_SECS));
+            let requested = app_state.length_secs as f32 * SAMPLE_RATE as f32;
+            let bar = render.beats_per_bar as f32 * 60.0 / render.bpm as f32 * SAMPLE_RATE as f32;
+            if (render.song_len as f32 - requested).abs() > bar {
+                return Some(format!("{} is {} samples long, not about {}", id, render.song_len, requested));
+            }
+            (render.samples.to_vec(), Some(render.song_len))
+        };
+        let expected = song_len.map_or(wanted, |song_len| song_len.min(wanted));
+        if samples.len() < expected || song_len.is_some_and(|song_len| samples.len() > song_len) {
+            Some(format!("{} rendered {} samples of {:?}", id, samples.len(), song_len))
+        } else if !samples.iter().all(|sample| sample.is_finite()) {
+            Some(format!("{} rendered a sample that isn't a number", id))
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn every_generated_song_id_renders_a_song_of_about_its_length() {
+        fn property(params: SongParams) -> TestResult {
+            let id = controller::song_id(&params.app_state, params.seed);
+            match song_length_error(&id) {
+                None => TestResult::passed(),
+                Some(error) => TestResult::error(error),
+            }
+        }
+        // Each case renders a few seconds of audio, so fewer are run
+        QuickCheck::new().tests(20).quickcheck(property as fn(SongParams) -> TestResult);
+    }
+
+    #[test]
+    fn the_shortest_songs_at_the_slowest_tempos_keep_their_length() {
+        // A minute at 40 BPM or slower, where one stretched chord used to outlast the song
+        let ids = [
+            "v2:C-Ambient-40-1-0",
+            "v2:A#m-Ambient-21-1-t68-7",
+            "v2:Am-Ambient-20-1-t68-3",
+            "v2:B-Blues-20-1-0",
+            "v2:C-Metal-400-1-18446744073709551615",
+        ];
+        for id in ids {
+            assert_eq!(song_length_error(id), None);
+        }
+    }
+
+    #[test]
+    fn the_longest_and_endless_songs_render_their_opening() {
+        for id in ["v2:Em-Ambient-20-60-12345", "v2:C-Pop-400-60-0", "v2:C-Ambient-20-inf-0", "v2:C-Pop-400-inf-99"] {
+            assert_eq!(song_length_error(id), None);
+        }
+        for id in ["v2:C-Pop-999-1-0", "v2:C-Pop-0-1-0", "v2:C-Pop-120-0-0", "v2:C-Pop-120-61-0"] {
+            assert!(parse_song_id_to_app_state(id).is_err(), "{} parsed", id);
+        }
+    }
+
+    // SINE_BASS: A bass voice whose notes can be told apart sample by sample.
+    const SINE_BASS: BassVoice = BassVoice {
+        waveform: Waveform::Sine,
+        envelope: true,
+    };
+
+    // The bass note sounding over `from..to`, for a sine note that started at `note_start`
+    fn sine_bass_note_at(bass_line: &[f32], note_start: usize, from: usize, to: usize) -> Option<i32> {
+        (12..=84).find(|&note| {
+            let frequency = note_to_freq(note as u8);
+            (from..to)
+                .step_by(7)
+                .all(|index| (bass_line[index] - 0.6 * bass_wave(Waveform::Sine, frequency, index - note_start)).abs() < 1e-4)
+        })
+    }
+
+    #[test]
+    fn bass_fills_lead_into_the_next_root_which_always_sounds_on_the_change() {
+        let roots = [48u8, 53, 48, 55, 53, 50];
+        let beat = SAMPLE_RATE as usize / 2; // 120 BPM
+        let chord = 4 * beat;
+        let (mut fills, mut held) = (0, 0);
+        for seed in 0..8 {
+            let bass_line = get_bass_line(
+                "Blues",
+                &roots,
+                chord,
+                2 * roots.len() * chord,
+                120,
+                0.0,
+                TimeSignature::default(),
+                seed,
+                SINE_BASS,
+            );
+            for index in 0..2 * roots.len() {
+                let start = index * chord;
+                let root = roots[index % roots.len()] as i32 - 12;
+                let next_index = (index + 1) % roots.len();
+                let next_root = roots[next_index] as i32 - 12;
+                assert_eq!(sine_bass_note_at(&bass_line, start, start + beat / 2, start + 2 * beat), Some(root));
+
+                let last_beat = start + chord - beat;
+                let second = last_beat + beat / 2;
+                if sine_bass_note_at(&bass_line, start, last_beat + beat / 8, last_beat + 3 * beat / 8) == Some(root) {
+                    held += 1;
+                    continue;
+                }
+                assert_ne!(next_index, 0, "a fill ran into the start of the progression");
+                fills += 1;
+                let figure = (
+                    sine_bass_note_at(&bass_line, last_beat, last_beat + beat / 8, last_beat + 3 * beat / 8),
+                    sine_bass_note_at(&bass_line, second, second + beat / 8, second + 3 * beat / 8),
+                );
+                // Chromatic from below, or a whole tone above stepping down
+                let below = (Some(next_root - 2), Some(next_root - 1));
+                let above = (Some(next_root + 4), Some(next_root + 2));
+                assert!(figure == below || figure == above, "{:?} into {}", figure, next_root);
+            }
+        }
+        assert!(fills > 0 && held > 0, "{} fills, {} held roots", fills, held);
+    }
+
+    #[test]
+    fn walking_bass_starts_on_the_root_and_steps_toward_the_next_one_in_register() {
+        let register = WALKING_BASS_LOWEST..=WALKING_BASS_HIGHEST;
+        let mut fifths = 0;
+        for root in 24..36 {
+            for next_root in 24..36 {
+                for beats in 2..=6 {
+                    for seed in 0..4 {
+                        let mut rng = StdRng::seed_from_u64(seed);
+                        let notes = walking_bass_notes(root, next_root, beats, &mut rng);
+                        assert_eq!(notes.len(), beats);
+                        assert_eq!(notes[0], root);
+                        assert!(notes.iter().all(|note| register.contains(note)), "{:?} leaves the register", notes);
+                        assert!(notes.windows(2).all(|pair| pair[0] != pair[1]), "{:?} repeats a note", notes);
+                        // The beats between step by half or whole tones, the second maybe leaping to the fifth
+                        for beat in 1..beats - 1 {
+                            let leap = (notes[beat] - notes[beat - 1]).abs();
+                            if beat == 1 && (notes[1] == root + 7 || notes[1] == root - 5) {
+                                fifths += 1;
+                            } else {
+                                assert!(leap <= 2, "{:?} leaps on beat {}", notes, beat);
+                            }
+                        }
+                        let approach = (notes[beats - 1] - next_root).rem_euclid(12);
+                        assert!([11, 1, 2].contains(&approach), "{:?} doesn't approach {}", notes, next_root);
+                    }
+                }
+            }
+        }
+        assert!(fifths > 0);
+    }
+
+    #[test]
+    fn walking_bass_walks_the_same_path_for_the_same_seed() {
+        let walk = |seed: u64| -> Vec<Vec<i32>> {
+            let mut rng = StdRng::seed_from_u64(seed);
+            [(26, 31), (31, 24), (24, 33), (33, 26)].iter().map(|&(root, next_root)| walking_bass_notes(root, next_root, 4, &mut rng)).collect()
+        };
+        assert_eq!(walk(9), walk(9));
+        assert_ne!(walk(9), walk(10));
+    }
+
+    #[test]
+    fn jazz_bass_plays_each_chords_root_on_its_first_beat() {
+        let roots = [38u8, 43, 36, 45]; // ii-V-I-vi in C
+        let beat = SAMPLE_RATE as usize / 2; // 120 BPM
+        let chord = 4 * beat;
+        for seed in 0..4 {
+            let bass_line = get_bass_line("Jazz", &roots, chord, 2 * roots.len() * chord, 120, 0.0, TimeSignature::default(), seed, SINE_BASS);
+            for index in 0..2 * roots.len() {
+                let chord_start = index * chord;
+                let root = roots[index % roots.len()] as i32 - 12;
+                for step in 0..4 {
+                    let start = chord_start + step * beat;
+                    let note = sine_bass_note_at(&bass_line, start, start + beat / 8, start + beat / 2);
+                    let Some(note) = note else { panic!("no single note on beat {} of chord {}", step, index) };
+                    if step == 0 {
+                        assert_eq!(note, root, "chord {} doesn't start on its root", index);
+                    }
+                    assert!((WALKING_BASS_LOWEST..=WALKING_BASS_HIGHEST).contains(&note), "{} leaves the register", note);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_seed_picks_among_every_progression_the_style_offers() {
+        for (prog_name, minor) in [("blues", false), ("pop", true), ("jazz", true), ("folk", false), ("default", true)] {
+            let offered = progs::style_progressions(prog_name, minor);
+            let picks: Vec<(&str, &str)> = (0..64).map(|seed| pick_progressions(prog_name, minor, seed)).collect();
+            let picked: HashSet<&str> = picks.iter().map(|&(a, _)| a).collect();
+            assert_eq!(picked, offered.iter().copied().collect(), "{} (minor {})", prog_name, minor);
+            for (seed, &(a, b)) in picks.iter().enumerate() {
+                assert_ne!(a, b, "{} (minor {}) has another progression for B", prog_name, minor);
+                assert!(offered.contains(&b));
+                assert_eq!(pick_progressions(prog_name, minor, seed as u64), (a, b));
+            }
+        }
+        assert_eq!(pick_progressions("rock", true, 5), ("rock", "rock"));
+    }
+
+    #[test]
+    fn bass_is_near_silent_at_every_chord_boundary_and_the_end() {
+        let styles = ["Pop", "Rock", "Jazz", "Blues", "Electronic", "Ambient", "Classical", "Folk", "Metal", "Reggae"];
+        let voices = [
+            BassVoice { waveform: Waveform::Square, envelope: true },
+            BassVoice { waveform: Waveform::Triangle, envelope: false },
+        ];
+        let roots = [48u8, 53, 55, 50];
+        let chord = 2 * SAMPLE_RATE as usize; // A 4/4 bar at 120 BPM
+        // The song ends partway through a chord, whose bass note must still fade out
+        let total_samples = 5 * chord + chord / 3;
+        for style in styles {
+            for voice in voices {
+                for seed in 0..3 {
+                    let bass_line = get_bass_line(style, &roots, chord, total_samples, 120, 0.0, TimeSignature::default(), seed, voice);
+                    assert_eq!(bass_line.len(), total_samples);
+                    assert!(bass_line.iter().any(|sample| sample.abs() > 0.3), "{} plays no bass", style);
+                    let boundaries = (chord..total_samples).step_by(chord).chain([total_samples]);
+                    for boundary in boundaries {
+                        let edges = [Some(boundary - 1), (boundary < total_samples).then_some(boundary)];
+                        for index in edges.into_iter().flatten() {
+                            assert!(
+                                bass_line[index].abs() < 0.01,
+                                "{} {:?} seed {}: {} at sample {} by the chord boundary {}",
+                                style,
+                                voice,
+                                seed,
+                                bass_line[index],
+                                index,
+                                boundary
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether every sample is a whole number of the lo-fi stage's steps
+    fn on_lofi_levels(samples: &[f32], steps: f32) -> bool {
+        samples.iter().all(|&sample| {
+            let level = sample * steps;
+            (level - level.round()).abs() < 1e-3 && level.abs() <= steps
+        })
+    }
+
+    #[test]
+    fn lofi_quantizes_to_its_bit_depth_and_holds_each_level() {
+        for bits in LOFI_BITS {
+            for sample_rate in [44100, 22050, 11025] {
+                let mut lofi = LoFi::new(bits, sample_rate);
+                let input: Vec<f32> = (0..4000).map(|i| 1.2 * (i as f32 * 0.0031).sin()).collect();
+                let output: Vec<f32> = input.iter().map(|&sample| lofi.process(sample)).collect();
+                let steps = (1u32 << (bits - 1)) as f32;
+                assert!(on_lofi_levels(&output, steps), "{} bits leave a sample off its levels", bits);
+                let mut levels: Vec<i32> = output.iter().map(|&sample| (sample * steps).round() as i32).collect();
+                levels.sort_unstable();
+                levels.dedup();
+                assert!(levels.len() <= (1 << bits) + 1, "{} bits give {} levels", bits, levels.len());
+                // Each level is the input's nearest, at the start of its hold
+                let hold = 44100 / sample_rate as usize;
+                for (index, chunk) in output.chunks(hold).enumerate() {
+                    let held = (input[index * hold].clamp(-1.0, 1.0) * steps).round() / steps;
+                    assert!(chunk.iter().all(|&sample| sample == held));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_lofi_song_holds_only_its_quantized_levels() {
+        // (setting, style, quantization steps, or None for a clean mix)
+        let cases = [
+            ("Heavy", "Pop", Some((1u32 << (HEAVY_LOFI.0 - 1)) as f32)),
+            ("Light", "Jazz", Some((1u32 << (LIGHT_LOFI.0 - 1)) as f32)),
+            (AUTO_LOFI, "Electronic", Some((1u32 << (LIGHT_LOFI.0 - 1)) as f32)),
+            (AUTO_LOFI, "Pop", None),
+            ("Off", "Metal", None),
+        ];
+        for (setting, style, steps) in cases {
+            let app_state = AppState {
+                style: style.to_string(),
+                lofi: setting.to_string(),
+                length_secs: MIN_SONG_SECS,
+                seed: "7".to_string(),
+                ..AppState::default()
+            };
+            let song = generate_audio_from_state(&app_state, 7, Some(4.0));
+            assert!(song.samples.iter().any(|&sample| sample.abs() > 0.05), "{} {} is silent", setting, style);
+            match steps {
+                Some(steps) => assert!(on_lofi_levels(&song.samples, steps), "{} {} leaves its levels", setting, style),
+                None => assert!(!on_lofi_levels(&song.samples, 256.0), "{} {} was crushed", setting, style),
+            }
+        }
+        // Heavy also holds every level for a quarter of the samples
+        let app_state = AppState {
+            lofi: "Heavy".to_string(),
+            length_secs: MIN_SONG_SECS,
+            ..AppState::default()
+        };
+        let song = generate_audio_from_state(&app_state, 3, Some(2.0));
+        let hold = (SAMPLE_RATE / HEAVY_LOFI.1) as usize;
+        assert!(song.samples.chunks_exact(hold).all(|chunk| chunk.iter().all(|&sample| sample == chunk[0])));
+    }
+
+    #[test]
+    fn songs_end_on_a_bar_line_with_the_last_chord_whole() {
+        // (style, BPM, requested seconds, time signature)
+        let cases = [
+            ("Pop", "97", 60, "4/4"),
+            ("Folk", "133", 75, AUTO_TIME_SIGNATURE),
+            ("Classical", "71", 61, "6/8"),
+            ("Jazz", "187", 90, "4/4"),
+            ("Ambient", "64", 60, AUTO_TIME_SIGNATURE),
+            ("Rock", "150", 100, "3/4"),
+            ("Reggae", "89", 127, "4/4"),
+        ];
+        for (style, bpm, secs, time_signature) in cases {
+            for seed in [1, 2] {
+                let app_state = AppState {
+                    style: style.to_string(),
+                    bpm: bpm.to_string(),
+                    length_secs: secs,
+                    time_signature: time_signature.to_string(),
+                    ..AppState::default()
+                };
+                // The chord timeline and length are the whole song's, even for the opening alone
+                let render = generate_audio_from_state(&app_state, seed, Some(1.0));
+                let sec_per_beat = 60.0 / render.bpm as f32;
+                let bar_len = (render.beats_per_bar as f32 * sec_per_beat * SAMPLE_RATE as f32) as usize;
+                assert_eq!(render.song_len % bar_len, 0, "{} at {} BPM ends mid-bar", style, bpm);
+                let requested = secs as usize * SAMPLE_RATE as usize;
+                assert!(render.song_len.abs_diff(requested) <= bar_len / 2 + 1, "{} rounds {} s to {} samples", style, secs, render.song_len);
+
+                let starts: Vec<usize> = render.chord_timeline.iter().map(|&(start, _)| start as usize).collect();
+                let chord_len = starts[1] - starts[0];
+                assert!(starts.windows(2).all(|pair| pair[1] - pair[0] == chord_len), "{} changes chords off the grid", style);
+                let last_chord = render.song_len - starts[starts.len() - 1];
+                assert!(
+                    (chord_len..2 * chord_len).contains(&last_chord),
+                    "{} at {} BPM, {} s: the last chord lasts {} samples of {}",
+                    style,
+                    bpm,
+                    secs,
+                    last_chord,
+                    chord_len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mistyped_names_get_the_closest_suggestion() {
+        // (typed, the names it should be one of, suggestion or None)
+        let cases: [(&str, &[&str], Option<&str>); 16] = [
+            ("Jaz", &STYLE_NAMES, Some("Jazz")),
+            ("jazz ", &STYLE_NAMES, Some("Jazz")),
+            ("Electro", &STYLE_NAMES, Some("Electronic")),
+            ("ELEC", &STYLE_NAMES, Some("Electronic")),
+            ("Rokc", &STYLE_NAMES, Some("Rock")),
+            ("Clasical", &STYLE_NAMES, Some("Classical")),
+            ("Ambiant", &STYLE_NAMES, Some("Ambient")),
+            ("Reagge", &STYLE_NAMES, Some("Reggae")),
+            ("Metl", &STYLE_NAMES, Some("Metal")),
+            ("Techno", &STYLE_NAMES, None),
+            ("", &STYLE_NAMES, None),
+            ("C##", &SCALE_NAMES, Some("C#")),
+            ("G#5", &SCALE_NAMES, Some("G#")),
+            ("E#", &SCALE_NAMES, Some("E")),
+            ("H", &SCALE_NAMES, None),
+            ("Hello", &SCALE_NAMES, None),
+        ];
+        for (typed, names, suggestion) in cases {
+            let expected = suggestion.map_or(String::new(), |name| format!(" Did you mean '{}'?", name));
+            assert_eq!(did_you_mean(typed, names), expected, "for '{}'", typed);
+        }
+    }
+
+    #[test]
+    fn song_id_errors_suggest_the_closest_scale_or_style() {
+        let cases = [
+            ("C-Jaz-120-1-42", "Invalid Style", " Did you mean 'Jazz'?"),
+            ("Am-Electro-120-1-42", "Invalid Style", " Did you mean 'Electronic'?"),
+            ("B#-Pop-120-1-42", "Invalid Scale", " Did you mean 'B'?"),
+        ];
+        for (id, kind, suggestion) in cases {
+            let error = parse_song_id_to_app_state(id).unwrap_err();
+            assert!(error.starts_with(kind) && error.contains(suggestion), "{}: {}", id, error);
+        }
+        for id in ["C-Techno-120-1-42", "H-Pop-120-1-42"] {
+            let error = parse_song_id_to_app_state(id).unwrap_err();
+            assert!(!error.contains("Did you mean"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn bpms_are_checked_at_the_edges_of_their_range() {
+        // (typed, accepted tempo, or None when it is rejected)
+        let cases = [
+            ("20", Some(20)),
+            ("19", None),
+            ("400", Some(400)),
+            ("401", None),
+            ("0", None),
+            ("007", None),
+            ("040", Some(40)),
+            ("999", None),
+            ("-120", None),
+            ("12a", None),
+        ];
+        for (typed, tempo) in cases {
+            match (parse_bpm(typed), tempo) {
+                (Ok(parsed), Some(tempo)) => assert_eq!(parsed, Some(tempo), "for '{}'", typed),
+                (Err(error), None) => assert!(error.contains("use 20 to 400"), "for '{}': {}", typed, error),
+                (parsed, _) => panic!("'{}' gave {:?}", typed, parsed),
+            }
+        }
+        // An empty BPM is picked from the seed
+        assert_eq!(parse_bpm(""), Ok(None));
+    }
+
+    #[test]
+    fn songs_play_at_the_edges_of_the_bpm_range_as_given() {
+        for (bpm, tempo) in [("20", Some(MIN_BPM)), ("400", Some(MAX_BPM)), ("040", Some(40)), ("", None)] {
+            assert_eq!(song_bpm(bpm), tempo, "for '{}'", bpm);
+            let app_state = AppState {
+                bpm: bpm.to_string(),
+                seed: "7".to_string(),
+                ..AppState::default()
+            };
+            let song = generate_audio_from_state(&app_state, 7, Some(1.0));
+            match tempo {
+                Some(tempo) => assert_eq!(song.bpm, tempo),
+                None => assert!((MIN_BPM..=MAX_BPM).contains(&song.bpm), "picked {}", song.bpm),
+            }
+            assert_eq!(effective_app_state(&app_state).bpm, tempo.map_or(String::new(), |t| t.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_song_with_a_bpm_out_of_range_is_turned_down_rather_than_played() {
+        for bpm in ["19", "401", "0"] {
+            let (_control_sender, control_receiver) = crossbeam_channel::unbounded();
+            let (progress_sender, progress_receiver) = crossbeam_channel::unbounded();
+            let app_state = AppState {
+                bpm: bpm.to_string(),
+                ..endless_song()
+            };
+            // Checked before the audio output is opened, so the missing device isn't what's reported
+            run_music_service(
+                app_state,
+                None,
+                Duration::ZERO,
+                7,
+                AudioOutput::Fake(None),
+                Arc::new(AtomicBool::new(false)),
+                control_receiver,
+                progress_sender,
+            );
+            let reports: Vec<MusicProgress> = progress_receiver.try_iter().collect();
+            assert_eq!(reports.len(), 1, "for '{}'", bpm);
+            assert!(reports[0].error.as_deref().is_some_and(|error| error.contains("use 20 to 400")), "for '{}'", bpm);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "parse_bpm")]
+    fn generation_never_quietly_changes_a_bpm_out_of_range() {
+        song_bpm("401");
+    }
+
+    #[test]
+    fn song_ids_take_bpms_at_the_edges_of_the_range_and_reject_those_past_them() {
+        for (id, bpm) in [("C-Pop-20-1-42", "20"), ("C-Pop-400-1-42", "400"), ("C-Pop--1-42", "")] {
+            assert_eq!(parse_song_id_to_app_state(id).unwrap().bpm, bpm, "{}", id);
+        }
+        for id in ["C-Pop-19-1-42", "C-Pop-401-1-42", "C-Pop-0-1-42", "C-Pop-007-1-42"] {
+            let error = parse_song_id_to_app_state(id).unwrap_err();
+            assert!(error.starts_with("Invalid BPM in Song ID") && error.contains("use 20 to 400"), "{}: {}", id, error);
+        }
+    }
+
+    // MessyIdCase: A song ID as pasted, and the scale, quality, style, BPM, length in seconds
+    // and seed it loads as.
+    type MessyIdCase = (&'static str, &'static str, &'static str, &'static str, &'static str, u32, &'static str);
+
+    #[test]
+    fn messy_song_ids_load_as_their_tidy_form() {
+        let cases: [MessyIdCase; 14] = [
+            ("  C-Pop-120-5-42  ", "C", "Major", "Pop", "120", 300, "42"),
+            ("C-Pop-120-5-42\n", "C", "Major", "Pop", "120", 300, "42"),
+            ("\tC-Pop-120-5-42\r\n", "C", "Major", "Pop", "120", 300, "42"),
+            ("c-pop-120-5-42", "C", "Major", "Pop", "120", 300, "42"),
+            ("C - Pop - 120 - 5 - 42", "C", "Major", "Pop", "120", 300, "42"),
+            ("f#m-ELECTRONIC-140-3-7", "F#", "Minor", "Electronic", "140", 180, "7"),
+            ("a#M-jAzZ-96-2-1", "A#", "Minor", "Jazz", "96", 120, "1"),
+            ("C-Pop-120-5min-42", "C", "Major", "Pop", "120", 300, "42"),
+            ("C-Pop-120-5 min-42", "C", "Major", "Pop", "120", 300, "42"),
+            ("C-Pop-120-5MIN-42", "C", "Major", "Pop", "120", 300, "42"),
+            ("D-Rock-100-2.5-9", "D", "Major", "Rock", "100", 150, "9"),
+            ("D-Rock-100-150s-9", "D", "Major", "Rock", "100", 150, "9"),
+            ("D-Rock-100-2:30-9", "D", "Major", "Rock", "100", 150, "9"),
+            (" g-reggae-080-1-", "G", "Major", "Reggae", "80", 60, ""),
+        ];
+        for (id, scale, quality, style, bpm, length_secs, seed) in cases {
+            let state = parse_song_id_to_app_state(id).unwrap_or_else(|error| panic!("{:?}: {}", id, error));
+            assert_eq!(
+                (state.scale.as_str(), state.quality.as_str(), state.style.as_str(), state.bpm.as_str()),
+                (scale, quality, style, bpm),
+                "{:?}",
+                id
+            );
+            assert_eq!((state.length_secs, state.seed.as_str()), (length_secs, seed), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn song_ids_past_tidying_up_are_still_rejected() {
+        // (song ID, the part the error names)
+        let cases = [
+            ("", "Invalid Song ID"),
+            ("C Pop 120 5 42", "Invalid Song ID"),
+            ("C-Pop-120-5", "Invalid Song ID"),
+            ("C-Pop-120-5-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-n1-42", "Invalid Song ID"),
+            ("H-Pop-120-5-42", "Invalid Scale"),
+            ("Cmaj-Pop-120-5-42", "Invalid Scale"),
+            ("C-Polka-120-5-42", "Invalid Style"),
+            ("C-Pop-fast-5-42", "Invalid BPM"),
+            ("C-Pop-120-5 hours-42", "Invalid Length"),
+            ("C-Pop-120-0min-42", "Invalid Length"),
+            ("C-Pop-120-5-4 2", "Invalid Seed"),
+            ("C-Pop-120-5-forty", "Invalid Seed"),
+        ];
+        for (id, kind) in cases {
+            let error = parse_song_id_to_app_state(id).unwrap_err();
+            assert!(error.starts_with(kind), "{:?}: {}", id, error);
+        }
+    }
+
+    #[test]
+    fn versioned_and_unversioned_song_ids_load_the_same_song() {
+        let unversioned = parse_song_id_to_app_state("F#m-Jazz-96-2:30-s40-7").unwrap();
+        assert_eq!(unversioned.id_version, 1);
+        // (song ID, the version it is read as)
+        let cases = [
+            ("v1:F#m-Jazz-96-2:30-s40-7", 1),
+            ("v2:F#m-Jazz-96-2:30-s40-7", SONG_ID_VERSION),
+            ("V2:F#m-Jazz-96-2:30-s40-7", SONG_ID_VERSION),
+            (" v2 : F#m-Jazz-96-2:30-s40-7 ", SONG_ID_VERSION),
+        ];
+        for (id, version) in cases {
+            let versioned = parse_song_id_to_app_state(id).unwrap_or_else(|error| panic!("{:?}: {}", id, error));
+            assert_eq!(versioned.id_version, version, "{:?}", id);
+            // Only the version differs: both share as the same song
+            assert_eq!(controller::song_id(&versioned, 7), controller::song_id(&unversioned, 7), "{:?}", id);
+            assert_eq!((versioned.length_secs, versioned.swing.as_str()), (150, "40"), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn unknown_song_id_versions_are_rejected() {
+        // (song ID, what the error says)
+        let cases = [
+            ("v3:C-Pop-120-5-42", "Update 8BitBeats"),
+            ("v99:C-Pop-120-5-42", "Update 8BitBeats"),
+            ("v0:C-Pop-120-5-42", "Invalid Version"),
+            ("v:C-Pop-120-5-42", "Invalid Version"),
+            ("vtwo:C-Pop-120-5-42", "Invalid Version"),
+            ("x2:C-Pop-120-5-42", "Invalid Version"),
+            ("2:C-Pop-120-5-42", "Invalid Version"),
+            // A ':' after a '-' is a length, so this is read as an unversioned ID
+            ("v-2:C-Pop-120-5-42", "Invalid part in Song ID"),
+        ];
+        for (id, kind) in cases {
+            let error = parse_song_id_to_app_state(id).unwrap_err();
+            assert!(error.contains(kind), "{:?}: {}", id, error);
+        }
+    }
+
+    #[test]
+    fn song_ids_carry_every_setting_that_changes_the_audio() {
+        let state = parse_song_id_to_app_state("v2:C-Pop-120-5-d0-h2-c2-a1-r1-u4-f1-o12-g70.0.100-42").unwrap();
+        assert!(!state.drums);
+        assert_eq!(
+            [&state.harmony, &state.counter_melody, &state.arp_chords, &state.comping, &state.pulse_width, &state.brightness],
+            ["Song", "Off", "On", "Sustain", "PWM", "Dark"]
+        );
+        assert_eq!(state.fade_out_secs, Some(12));
+        assert_eq!([&state.melody_gain, &state.chord_gain, &state.bass_gain], ["70", "0", "100"]);
+        // The defaults are left out, and read back as the defaults
+        let plain = parse_song_id_to_app_state("v2:C-Pop-120-5-d1-h0-42").unwrap();
+        assert_eq!(controller::song_id(&plain, 42), "v2:C-Pop-120-5-42");
+        assert!(plain.drums && plain.fade_out_secs.is_none());
+        assert_eq!(plain.melody_gain, DEFAULT_TRACK_GAIN_PERCENT);
+        // A gain that isn't a number plays, and is shared, as the default
+        let unreadable_gain = AppState { bass_gain: "loud".to_string(), chord_gain: "150".to_string(), ..AppState::default() };
+        assert_eq!(gains_id_part(&unreadable_gain).as_deref(), Some("g50.100.50"));
+
+        // (song ID, the part the error names)
+        let cases = [
+            ("C-Pop-120-5-d2-42", "Invalid Drums"),
+            ("C-Pop-120-5-h3-42", "Invalid Harmony"),
+            ("C-Pop-120-5-rx-42", "Invalid Comping"),
+            ("C-Pop-120-5-f-42", "Invalid Brightness"),
+            ("C-Pop-120-5-o21-42", "Invalid Fade-out"),
+            ("C-Pop-120-5-g101.50.50-42", "Invalid Mix"),
+            ("C-Pop-120-5-g50.50-42", "Invalid Mix"),
+        ];
+        for (id, kind) in cases {
+            let error = parse_song_id_to_app_state(id).unwrap_err();
+            assert!(error.starts_with(kind), "{}: {}", id, error);
+        }
+    }
 }
@@ -0,0 +1,194 @@
+use crate::session;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// LIBRARY_FILE_NAME: Name of the saved-songs file in the data directory, one "timestamp song-id" per line.
+const LIBRARY_FILE_NAME: &str = "library";
+
+/* LibraryEntry - A song saved to the library.
+ *
+ * fields:
+ *     - song_id (String): The saved song ID.
+ *     - saved_at (u64): When it was saved, in seconds since the Unix epoch.
+ */
+#[derive(Debug, Clone)]
+pub struct LibraryEntry {
+    pub song_id: String,
+    pub saved_at: u64,
+}
+
+/* Library - The saved songs, kept in sync with the library file.
+ *
+ * Every change rewrites the whole file atomically, so a crash mid-write keeps the previous
+ * library instead of corrupting it.
+ *
+ * fields:
+ *     - entries (Vec<LibraryEntry>): Saved songs, oldest first.
+ *     - path (Option<PathBuf>): The library file, or None if there is no home directory.
+ */
+pub struct Library {
+    entries: Vec<LibraryEntry>,
+    path: Option<PathBuf>,
+}
+
+/* format_saved_at - Formats a save timestamp as a UTC date and time.
+ *
+ * inputs:
+ *     - saved_at (u64): Seconds since the Unix epoch.
+ *
+ * outputs:
+ *     - String: e.g. "2024-05-01 18:30".
+ */
+pub fn format_saved_at(saved_at: u64) -> String {
+    let days = (saved_at / 86_400) as i64;
+    let minutes_of_day = saved_at % 86_400 / 60;
+    // Civil date from days since 1970-01-01 (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes_of_day / 60,
+        minutes_of_day % 60
+    )
+}
+
+impl Library {
+    /* load - Reads the library file.
+     *
+     * A missing file is an empty library, and unreadable lines are skipped.
+     *
+     * inputs:
+     *     - None
+     *
+     * outputs:
+     *     - Self: The saved songs.
+     */
+    pub fn load() -> Self {
+        let path = session::data_dir().map(|dir| dir.join(LIBRARY_FILE_NAME));
+        let contents = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let entries = contents
+            .lines()
+            .filter_map(|line| {
+                let (saved_at, song_id) = line.trim().split_once(' ')?;
+                Some(LibraryEntry {
+                    song_id: song_id.trim().to_string(),
+                    saved_at: saved_at.parse().ok()?,
+                })
+            })
+            .filter(|entry| !entry.song_id.is_empty())
+            .collect();
+        Self { entries, path }
+    }
+
+    /* save - Writes the library file.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - io::Result<()>: Ok on success, or an error if the file could not be written.
+     */
+    fn save(&self) -> io::Result<()> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|entry| format!("{} {}\n", entry.saved_at, entry.song_id))
+            .collect();
+        session::write_atomically(path, &contents)
+    }
+
+    /* entries - Returns the saved songs, oldest first.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - &[LibraryEntry]: The saved songs.
+     */
+    pub fn entries(&self) -> &[LibraryEntry] {
+        &self.entries
+    }
+
+    /* contains - Checks whether a song is saved.
+     *
+     * inputs:
+     *     - &self
+     *     - song_id (&str): The song ID.
+     *
+     * outputs:
+     *     - bool: True if the song is in the library.
+     */
+    pub fn contains(&self, song_id: &str) -> bool {
+        self.entries.iter().any(|entry| entry.song_id == song_id)
+    }
+
+    /* add - Saves a song with the current time, unless it is already saved.
+     *
+     * inputs:
+     *     - &mut self
+     *     - song_id (&str): The song ID.
+     *
+     * outputs:
+     *     - io::Result<bool>: True if the song was added, false if it was already saved.
+     */
+    pub fn add(&mut self, song_id: &str) -> io::Result<bool> {
+        if self.contains(song_id) {
+            return Ok(false);
+        }
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.entries.push(LibraryEntry {
+            song_id: song_id.to_string(),
+            saved_at,
+        });
+        if let Err(error) = self.save() {
+            // Keep memory and file in agreement
+            self.entries.pop();
+            return Err(error);
+        }
+        Ok(true)
+    }
+
+    /* remove - Removes a song from the library.
+     *
+     * inputs:
+     *     - &mut self
+     *     - song_id (&str): The song ID.
+     *
+     * outputs:
+     *     - io::Result<bool>: True if the song was removed, false if it wasn't saved.
+     */
+    pub fn remove(&mut self, song_id: &str) -> io::Result<bool> {
+        let previous = self.entries.clone();
+        self.entries.retain(|entry| entry.song_id != song_id);
+        if self.entries.len() == previous.len() {
+            return Ok(false);
+        }
+        if let Err(error) = self.save() {
+            // Keep memory and file in agreement
+            self.entries = previous;
+            return Err(error);
+        }
+        Ok(true)
+    }
+}
@@ -1,17 +1,92 @@
+mod a11y;
+mod changelog;
+mod clipboard;
+mod config;
+mod controller;
+mod doctor;
+mod dsp;
+mod drums;
 mod gen;
+mod library;
 mod melodies;
 mod progs;
+mod session;
+mod share;
+mod synth;
+mod terminal;
+mod theme;
 mod tui;
+mod workers;
 
-use crate::gen::parse_song_id_to_app_state;
+use crate::controller::{PlaybackController, RewindTarget, VariedTrack};
+use crate::gen::{parse_song_id_to_app_state, SONG_ID_VERSION};
 use crate::gen::MusicControl;
-use crate::tui::UserAction;
-use crossbeam_channel::Sender as CrossbeamSender;
-use rand::{seq::SliceRandom, Rng};
-use ratatui::prelude::CrosstermBackend;
+use crate::gen::WAVEFORM_TRACKS;
+use crate::tui::{AppState, UserAction};
+use ratatui::prelude::{Backend, CrosstermBackend};
 use std::error::Error;
-use std::thread;
-use std::thread::JoinHandle;
+use std::io::IsTerminal;
+
+// HISTORY_LIMIT: Songs remembered for Previous; the oldest is dropped beyond this.
+const HISTORY_LIMIT: usize = 100;
+
+/* remember_song - Pushes the song shown in the TUI onto the history, if it has an ID.
+ *
+ * A song that is already the newest entry is not added twice.
+ *
+ * inputs:
+ *     - history (&mut Vec<String>): Song IDs of earlier songs, oldest first; each ID carries
+ *       everything needed to play its song again.
+ *     - app_state (&AppState): The state of the outgoing song.
+ *
+ * outputs:
+ *     - None
+ */
+fn remember_song(history: &mut Vec<String>, app_state: &AppState) {
+    let Some(song_id) = &app_state.current_song_id_display else {
+        return;
+    };
+    if history.last() == Some(song_id) {
+        return;
+    }
+    history.push(song_id.clone());
+    if history.len() > HISTORY_LIMIT {
+        history.remove(0);
+    }
+}
+
+/* play_loaded_song - Replaces the current song with one loaded from its ID.
+ *
+ * inputs:
+ *     - tui (&mut tui::Tui<B>): The TUI to update.
+ *     - controller (&mut PlaybackController): Owns the music service.
+ *     - song_id (String): The ID being loaded, shown while the song generates.
+ *     - loaded_app_state (AppState): The parameters parsed from the ID.
+ *
+ * outputs:
+ *     - None
+ */
+fn play_loaded_song<B: Backend>(
+    tui: &mut tui::Tui<B>,
+    controller: &mut PlaybackController,
+    song_id: String,
+    loaded_app_state: AppState,
+) {
+    // Terminate existing music service if any
+    controller.stop();
+
+    tui.reset_progress_for_new_song(); // Reset visual progress
+    tui.set_app_state(loaded_app_state.clone()); // Directly set TUI state
+    tui.set_volume(controller.volume());
+    tui.set_muted(controller.is_muted());
+    tui.set_current_song_id_display(Some(song_id)); // Show the ID being loaded
+
+    // Spawn new music service with the loaded state, then make sure it plays
+    controller.start_song(loaded_app_state);
+    controller.send(MusicControl::Resume);
+    tui.set_playing_state(true);
+    tui.focus_on_play_pause();
+}
 
 /* main - Initializes the TUI and music service, then enters the main event loop.
  *
@@ -28,62 +103,141 @@ use std::thread::JoinHandle;
  *                                   or an error if an unrecoverable issue occurs.
  */
 fn main() -> Result<(), Box<dyn Error>> {
-    let (music_control_sender, _music_control_receiver) =
-        crossbeam_channel::unbounded::<MusicControl>();
-    let (progress_sender, progress_receiver) = crossbeam_channel::unbounded::<gen::MusicProgress>();
+    if std::env::args().nth(1).as_deref() == Some(doctor::DOCTOR_COMMAND) {
+        std::process::exit(doctor::run());
+    }
+    // A broken config stops startup with a message rather than silently using other settings
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (config, config_warnings) =
+        match config::config_path_from_args(&args).and_then(config::load) {
+            Ok(loaded) => loaded,
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        };
+
+    if args.iter().any(|arg| arg == "--a11y") {
+        for warning in &config_warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        return a11y::run(&config);
+    }
+
+    // Exit with a pointer to --a11y rather than garbling a terminal that can't show the TUI
+    let capabilities = match terminal::detect_support(std::io::stdout().is_terminal()) {
+        terminal::Support::Tui(capabilities) => capabilities,
+        terminal::Support::Unsupported(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    let backend = terminal::CapabilityBackend::new(CrosstermBackend::new(std::io::stdout()), capabilities);
+    let mut tui = tui::Tui::new(backend)?;
+    tui.setup(capabilities.alternate_screen)?;
+
+    // Show the "What's new" popup once after an upgrade. Fresh installs and downgrades
+    // just record the running version so the next upgrade is detected.
+    let current_version = env!("CARGO_PKG_VERSION");
+    let last_seen_version = session::load_last_seen_version();
+    if changelog::should_show_whats_new(last_seen_version.as_deref(), current_version) {
+        let last_seen = last_seen_version.as_deref().unwrap_or_default();
+        tui.show_whats_new(changelog::whats_new_lines(last_seen, current_version));
+    } else if last_seen_version.as_deref() != Some(current_version) {
+        let _ = session::save_last_seen_version(current_version);
+    }
+
+    let mut initial_app_state = AppState::default();
+    config.apply(&mut initial_app_state);
+    tui.set_app_state(initial_app_state);
+    tui.set_theme(theme::Theme::select(config.theme.as_deref(), capabilities.colors));
+    if !config_warnings.is_empty() {
+        tui.show_notice("Config has unknown keys (listed on exit)");
+    }
 
-    let mut tui = tui::Tui::new(CrosstermBackend::new(std::io::stdout()))?;
-    tui.setup()?;
+    let mut library = library::Library::load();
+    tui.set_library(library.entries().to_vec());
 
-    let mut music_service_handle: Option<JoinHandle<()>> = None;
-    let mut music_sender_option: Option<CrossbeamSender<MusicControl>> =
-        Some(music_control_sender.clone());
+    // Volume and mute are remembered across songs, since every new music service starts with a fresh sink
+    let mut controller = PlaybackController::new(tui.get_current_app_state().volume);
+    controller.set_rewind_to_section(
+        !std::env::args().any(|arg| arg == controller::REWIND_TO_START_FLAG),
+    );
 
     use crossterm::event;
     use std::time::{Duration, Instant};
 
     let frame_duration = Duration::from_millis(16);
     let mut last_frame = Instant::now();
+    // Earlier songs for Previous, oldest first
+    let mut history: Vec<String> = Vec::new();
+    // Set when a song change started here, since the outgoing song is then already in the history
+    let mut switch_requested = false;
+
+    if config.autoplay {
+        let mut app_state = controller::fresh_song_state(&tui.get_current_app_state());
+        app_state.is_random = false;
+        controller.start_song(app_state);
+        tui.set_playing_state(true);
+        tui.focus_on_play_pause();
+    }
 
     'main: loop {
         // Process all pending progress updates
-        while let Ok(progress) = progress_receiver.try_recv() {
-            tui.update_progress(progress.current_samples, progress.total_samples);
+        while let Some(progress) = controller.try_recv_progress() {
+            tui.set_generating(progress.generating);
+            if progress.generating {
+                continue;
+            }
+            if let Some(timeline) = progress.chord_timeline {
+                tui.set_chord_timeline(timeline);
+            }
+            if let Some(timeline) = progress.section_timeline {
+                tui.set_section_timeline(timeline);
+            }
+            tui.update_progress(progress.current_samples, progress.total_samples, progress.endless);
+            tui.update_level(progress.level);
+            tui.update_scope(progress.scope);
+            tui.update_spectrum(progress.spectrum);
+            tui.update_beat(progress.current_beat, progress.beat_phase, progress.beats_per_bar, progress.bpm);
+            tui.update_loudness(progress.loudness_db);
 
             // If we received a new app state (happens when a new song is generated)
             if let Some(new_app_state) = progress.app_state {
+                // A song the service moved on from by itself can be returned to with Previous
+                if !std::mem::take(&mut switch_requested) {
+                    remember_song(&mut history, &tui.get_current_app_state());
+                }
+                // Built from the parameters the service reports, not the fields (which may be mid-edit),
+                // so the ID always reproduces what is playing
+                let playing_song_id = controller::song_id(&new_app_state, progress.actual_seed);
                 tui.set_app_state(new_app_state);
+                tui.set_current_song_id_display(Some(playing_song_id));
+                // The volume may have changed during generation
+                tui.set_volume(controller.volume());
+                tui.set_muted(controller.is_muted());
             }
 
-            // If a song was just generated, its ID display might not be set yet.
-            if tui
-                .get_current_app_state()
-                .current_song_id_display
-                .is_none()
-                && progress.total_samples > 0
-            {
-                let current_app_params = tui.get_current_app_state();
-                let length_part = current_app_params
-                    .length
-                    .split_whitespace()
-                    .next()
-                    .unwrap_or("?");
-                let generated_id_str = format!(
-                    "{}-{}-{}-{}-{}",
-                    current_app_params.scale,
-                    current_app_params.style,
-                    current_app_params.bpm,
-                    length_part,
-                    progress.actual_seed
-                );
-                tui.set_current_song_id_display(Some(generated_id_str));
-            } else if progress.total_samples == 0 {
+            if let Some(error) = &progress.error {
+                // The service could not play (e.g. no audio device) and has stopped
+                tui.set_playing_state(false);
+                tui.show_notice(error);
+            }
+            if progress.total_samples == 0 {
                 // Song ended or was terminated
                 tui.set_current_song_id_display(None);
+                if controller.stop_after_current() {
+                    // The last song finished: end the session as if Stop was pressed
+                    controller.stop();
+                    tui.set_playing_state(false);
+                    tui.show_notice("Finished");
+                }
             }
         }
 
         // Redraw the UI
+        tui.set_stop_after_current(controller.stop_after_current());
+        tui.update_worker_stats(workers::global().stats());
         tui.draw()?;
 
         // Process input with timeout to maintain frame rate
@@ -95,173 +249,243 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         if event::poll(input_timeout)? {
             // We have input to process
-            match tui.handle_input()? {
+            let action = tui.handle_input()?;
+            match action {
                 UserAction::Quit => break 'main,
                 UserAction::RewindSong => {
-                    if let Some(sender) = &music_sender_option {
-                        let _ = sender.send(MusicControl::Rewind);
-                        // After sending Rewind, TUI needs to be updated to reflect the song at the beginning
-                        tui.reset_current_song_progress(); // Visually reset progress in TUI
+                    if let Some(target) = controller.rewind(Instant::now()) {
+                        if target == RewindTarget::Start {
+                            // After a full rewind, TUI needs to be updated to reflect the song at the beginning
+                            tui.reset_current_song_progress(); // Visually reset progress in TUI
+                        }
+                        tui.show_rewind(target);
                         tui.set_playing_state(true); // Ensure TUI shows as playing
                         tui.focus_on_play_pause(); // Set focus back to play/pause
                     }
                 }
-                UserAction::FastForwardSong => {
-                    if let Some(sender) = music_sender_option.take() {
-                        let _ = sender.send(MusicControl::Terminate);
-                        if let Some(handle) = music_service_handle.take() {
-                            handle
-                                .join()
-                                .expect("Failed to join music thread for fast-forward");
-                        }
-                    }
-                    // Drain any lingering progress messages from the old song
-                    while progress_receiver.try_recv().is_ok() {}
-
+                UserAction::Seek(fraction) => {
+                    // The TUI already shows the predicted position; the service confirms it
+                    controller.seek(fraction);
+                }
+                UserAction::GenerateMusic | UserAction::GenerateRandomMusic if tui.is_generating() => {
+                    // Busy: the song already asked for is still being generated
+                }
+                UserAction::FastForwardSong
+                | UserAction::GenerateMusic
+                | UserAction::GenerateRandomMusic => {
+                    let current_app_state = tui.get_current_app_state();
+                    remember_song(&mut history, &current_app_state);
+                    switch_requested = true;
                     tui.reset_progress_for_new_song();
                     tui.set_current_song_id_display(None); // Clear old song ID immediately
-                    let mut app_state_clone = tui.get_current_app_state();
-                    app_state_clone.seed = "".to_string(); // Ensure a new random seed is used
-                                                           // Clear progress fields in the clone to ensure gen_music_service starts fresh
-                    app_state_clone.current_song_progress = 0.0;
-                    app_state_clone.current_song_elapsed_secs = 0.0;
-                    app_state_clone.current_song_duration_secs = 0.0;
-                    app_state_clone.is_playing = true; // Ensure we start in playing state
-
-                    let (new_music_sender, new_music_receiver) =
-                        crossbeam_channel::unbounded::<MusicControl>();
-                    let new_progress_sender_clone = progress_sender.clone();
-
-                    music_sender_option = Some(new_music_sender.clone());
-                    music_service_handle = Some(thread::spawn(move || {
-                        gen::run_music_service(
-                            app_state_clone,
-                            new_music_receiver,
-                            new_progress_sender_clone,
-                        );
-                    }));
+                    match action {
+                        UserAction::FastForwardSong => {
+                            // Plays the pre-generated next song when it's ready
+                            controller.skip(&current_app_state);
+                        }
+                        UserAction::GenerateMusic => {
+                            let mut app_state = controller::fresh_song_state(&current_app_state);
+                            app_state.is_random = false;
+                            controller.start_song(app_state);
+                            tui.set_generating(true);
+                        }
+                        _ => {
+                            let app_state = controller::random_song_state(&current_app_state);
+                            tui.set_app_state(app_state.clone()); // Show the randomly picked parameters
+                            controller.start_song(app_state);
+                            tui.set_generating(true);
+                        }
+                    }
                     tui.set_playing_state(true); // Set TUI to playing
                     tui.focus_on_play_pause();
                 }
-                UserAction::GenerateMusic => {
-                    if let Some(sender) = music_sender_option.take() {
-                        let _ = sender.send(MusicControl::Terminate);
-                        if let Some(handle) = music_service_handle.take() {
-                            handle.join().expect("Failed to join music thread");
+                UserAction::VarySong(track) => match controller.current_seed() {
+                    Some(seed) => {
+                        // The song being varied can be returned to with Previous
+                        let current_app_state = tui.get_current_app_state();
+                        remember_song(&mut history, &current_app_state);
+                        switch_requested = true;
+                        tui.reset_progress_for_new_song();
+                        tui.set_current_song_id_display(None);
+                        controller.start_song(controller::varied_song_state(&current_app_state, seed, track));
+                        tui.set_playing_state(true);
+                        tui.show_notice(match track {
+                            VariedTrack::Melody => "Same song, new melody",
+                            VariedTrack::Bass => "Same song, new bass line",
+                        });
+                    }
+                    None => tui.show_notice("No song to vary yet"),
+                },
+                UserAction::PreviousSong => {
+                    // Regenerated from its ID; the song being left is not added, so Previous keeps going back
+                    if let Some(song_id) = history.pop() {
+                        if let Ok(mut previous_app_state) = parse_song_id_to_app_state(&song_id) {
+                            previous_app_state.carry_playback_settings(&tui.get_current_app_state());
+                            switch_requested = true;
+                            play_loaded_song(&mut tui, &mut controller, song_id, previous_app_state);
                         }
                     }
-                    // Drain any lingering progress messages from the old song
-                    while progress_receiver.try_recv().is_ok() {}
-
-                    tui.reset_progress_for_new_song();
-                    tui.set_current_song_id_display(None); // Clear old song ID immediately
-                    let mut app_state_clone = tui.get_current_app_state(); // Make mutable
-                                                                           // Clear progress fields in the clone to ensure gen_music_service starts fresh
-                    app_state_clone.current_song_progress = 0.0;
-                    app_state_clone.current_song_elapsed_secs = 0.0;
-                    app_state_clone.current_song_duration_secs = 0.0;
-                    app_state_clone.is_random = false;
-                    app_state_clone.is_playing = true; // Ensure we start in playing state
-
-                    let (new_music_sender, new_music_receiver) =
-                        crossbeam_channel::unbounded::<MusicControl>();
-                    let new_progress_sender_clone = progress_sender.clone();
-
-                    music_sender_option = Some(new_music_sender.clone());
-                    music_service_handle = Some(thread::spawn(move || {
-                        gen::run_music_service(
-                            app_state_clone,
-                            new_music_receiver,
-                            new_progress_sender_clone,
-                        );
-                    }));
-                    tui.set_playing_state(true);
-                    tui.focus_on_play_pause();
                 }
-                UserAction::GenerateRandomMusic => {
-                    if let Some(sender) = music_sender_option.take() {
-                        let _ = sender.send(MusicControl::Terminate);
-                        if let Some(handle) = music_service_handle.take() {
-                            handle.join().expect("Failed to join music thread");
+                UserAction::ToggleSaveSong => {
+                    match tui.get_current_app_state().current_song_id_display {
+                        Some(song_id) => {
+                            // Saving a song that is already saved removes it instead
+                            let result = if library.contains(&song_id) {
+                                library.remove(&song_id).map(|_| "Removed from library")
+                            } else {
+                                library.add(&song_id).map(|_| "★ Saved to library")
+                            };
+                            match result {
+                                Ok(notice) => tui.show_notice(notice),
+                                Err(_) => tui.show_notice("Could not write the library file"),
+                            }
+                            tui.set_library(library.entries().to_vec());
                         }
+                        None => tui.show_notice("No song to save yet"),
+                    }
+                }
+                UserAction::CopySongId => {
+                    let song_id = tui.get_current_app_state().current_song_id_display;
+                    // Share the seed the song was really generated with, not a possibly empty seed field
+                    match (song_id, controller.current_seed()) {
+                        (Some(song_id), Some(seed)) => {
+                            match clipboard::copy(&controller::with_seed(&song_id, seed)) {
+                                Ok(()) => tui.show_notice("Copied!"),
+                                Err(_) => tui.show_notice("Could not copy the song ID"),
+                            }
+                        }
+                        (Some(_), None) => tui.show_notice("Song is still generating"),
+                        (None, _) => tui.show_notice("No song playing"),
+                    }
+                }
+                UserAction::ShareSong => {
+                    let song_id = tui.get_current_app_state().current_song_id_display;
+                    match (song_id, controller.current_seed()) {
+                        (Some(song_id), Some(seed)) => {
+                            let song_id = controller::with_seed(&song_id, seed);
+                            match share::share_code(&song_id) {
+                                Ok(code) => tui.show_share(song_id, code),
+                                Err(_) => tui.show_notice("Could not make a share code"),
+                            }
+                        }
+                        (Some(_), None) => tui.show_notice("Song is still generating"),
+                        (None, _) => tui.show_notice("No song playing"),
+                    }
+                }
+                UserAction::CopyShareCode => {
+                    if let Some(code) = tui.take_share_code() {
+                        match clipboard::copy(&code) {
+                            Ok(()) => tui.show_notice("Copied!"),
+                            Err(_) => tui.show_notice("Could not copy the share code"),
+                        }
+                    }
+                }
+                UserAction::RemoveLibraryEntry => {
+                    if let Some(song_id) = tui.selected_library_song() {
+                        if library.remove(&song_id).is_err() {
+                            tui.show_notice("Could not write the library file");
+                        }
+                        tui.set_library(library.entries().to_vec());
                     }
-                    // Drain any lingering progress messages from the old song
-                    while progress_receiver.try_recv().is_ok() {}
-
-                    tui.reset_progress_for_new_song();
-                    tui.set_current_song_id_display(None); // Clear old song ID immediately
-
-                    let mut rng = rand::thread_rng();
-                    let mut app_state_clone = tui.get_current_app_state();
-
-                    // Clear progress fields in the clone to ensure gen_music_service starts fresh
-                    app_state_clone.current_song_progress = 0.0;
-                    app_state_clone.current_song_elapsed_secs = 0.0;
-                    app_state_clone.current_song_duration_secs = 0.0;
-                    app_state_clone.is_random = true;
-                    app_state_clone.is_playing = true; // Ensure we start in playing state
-                    app_state_clone.scale = [
-                        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
-                    ]
-                    .choose(&mut rng)
-                    .unwrap()
-                    .to_string();
-
-                    app_state_clone.style = [
-                        "Pop",
-                        "Rock",
-                        "Jazz",
-                        "Blues",
-                        "Electronic",
-                        "Ambient",
-                        "Classical",
-                        "Folk",
-                        "Metal",
-                        "Reggae",
-                    ]
-                    .choose(&mut rng)
-                    .unwrap()
-                    .to_string();
-
-                    app_state_clone.length = ["1 min", "2 min", "3 min", "5 min", "10 min"]
-                        .choose(&mut rng)
-                        .unwrap()
-                        .to_string();
-                    app_state_clone.bpm = rng.gen_range(60..180).to_string();
-                    app_state_clone.seed = rand::random::<u64>().to_string();
-                    tui.set_app_state(app_state_clone.clone());
-
-                    let (new_music_sender, new_music_receiver) =
-                        crossbeam_channel::unbounded::<MusicControl>();
-                    let new_progress_sender_clone = progress_sender.clone();
-
-                    music_sender_option = Some(new_music_sender.clone());
-                    music_service_handle = Some(thread::spawn(move || {
-                        gen::run_music_service(
-                            app_state_clone,
-                            new_music_receiver,
-                            new_progress_sender_clone,
-                        );
-                    }));
-                    tui.set_playing_state(true);
-                    tui.focus_on_play_pause();
                 }
                 UserAction::TogglePlayback => {
-                    if let Some(sender) = &music_sender_option {
-                        if tui.is_paused() {
-                            // If TUI thinks it's paused, we want to play
-                            let _ = sender.send(MusicControl::Resume);
+                    if tui.is_paused() {
+                        // If TUI thinks it's paused, we want to play
+                        if controller.send(MusicControl::Resume) {
                             tui.set_playing_state(true); // Update TUI state
-                        } else {
-                            // If TUI thinks it's playing, we want to pause
-                            let _ = sender.send(MusicControl::Pause);
-                            tui.set_playing_state(false); // Update TUI state
                         }
+                    } else if controller.send(MusicControl::Pause) {
+                        // If TUI thinks it's playing, we want to pause
+                        tui.set_playing_state(false); // Update TUI state
                     }
                 }
                 UserAction::ToggleHelp => {
                     tui.toggle_help();
                 }
+                UserAction::ToggleScope => {
+                    tui.toggle_scope();
+                }
+                UserAction::ToggleSpectrum => {
+                    tui.toggle_spectrum();
+                }
+                UserAction::ToggleDebug => {
+                    tui.toggle_debug();
+                }
+                UserAction::ToggleLoop => {
+                    let is_looping = tui.toggle_loop();
+                    controller.send(MusicControl::SetLoop(is_looping));
+                }
+                UserAction::ToggleDrums => {
+                    if tui.toggle_drums() {
+                        tui.show_notice("Drums on from the next song");
+                    } else {
+                        tui.show_notice("Drums off from the next song");
+                    }
+                }
+                UserAction::ToggleStopAfterCurrent => match controller.toggle_stop_after_current() {
+                    Some(true) => tui.show_notice("Stopping after this track"),
+                    Some(false) => tui.show_notice("Playing on after this track"),
+                    None => tui.show_notice("No song playing"),
+                },
+                UserAction::CycleCrossfade => {
+                    let crossfade_secs = tui.cycle_crossfade();
+                    controller.send(MusicControl::SetCrossfade(crossfade_secs));
+                }
+                UserAction::CycleHarmony => {
+                    let harmony = tui.cycle_harmony();
+                    tui.show_notice(&format!("Harmony {} from the next song", harmony));
+                }
+                UserAction::CycleCounterMelody => {
+                    let counter_melody = tui.cycle_counter_melody();
+                    tui.show_notice(&format!("Counter-melody {} from the next song", counter_melody));
+                }
+                UserAction::CycleArpChords => {
+                    let arp_chords = tui.cycle_arp_chords();
+                    tui.show_notice(&format!("Arp chords {} from the next song", arp_chords));
+                }
+                UserAction::CycleComping => {
+                    let comping = tui.cycle_comping();
+                    tui.show_notice(&format!("Comping {} from the next song", comping));
+                }
+                UserAction::CyclePulseWidth => {
+                    let pulse_width = tui.cycle_pulse_width();
+                    tui.show_notice(&format!("Pulse width {} from the next song", pulse_width));
+                }
+                UserAction::CycleBrightness => {
+                    let brightness = tui.cycle_brightness();
+                    tui.show_notice(&format!("Brightness {} from the next song", brightness));
+                }
+                UserAction::CycleEcho => {
+                    let echo = tui.cycle_echo();
+                    tui.show_notice(&format!("Echo {} from the next song", echo));
+                }
+                UserAction::CycleLoFi => {
+                    let lofi = tui.cycle_lofi();
+                    tui.show_notice(&format!("Lo-fi {} from the next song", lofi));
+                }
+                UserAction::ToggleNesMode => {
+                    if tui.toggle_nes_mode() {
+                        tui.show_notice("NES mode on from the next song");
+                    } else {
+                        tui.show_notice("NES mode off from the next song");
+                    }
+                }
+                UserAction::CycleWaveform(track) => {
+                    let waveform = tui.cycle_waveform(track);
+                    tui.show_notice(&format!("{} waveform {} from the next song", WAVEFORM_TRACKS[track], waveform));
+                }
+                UserAction::CycleModulation => {
+                    let modulation = tui.cycle_modulation();
+                    tui.show_notice(&format!("Key change {} from the next song", modulation));
+                }
+                UserAction::VolumeUp | UserAction::VolumeDown => {
+                    let volume = controller.step_volume(matches!(action, UserAction::VolumeUp));
+                    tui.set_volume(volume);
+                }
+                UserAction::ToggleMute => {
+                    let is_muted = controller.toggle_mute();
+                    tui.set_muted(is_muted);
+                }
                 UserAction::AttemptLoadSong => {
                     let song_name_to_load = tui
                         .get_current_app_state()
@@ -270,48 +494,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .to_string();
                     if !song_name_to_load.is_empty() {
                         match parse_song_id_to_app_state(&song_name_to_load) {
-                            Ok(loaded_app_state) => {
-                                // Terminate existing music service if any
-                                if let Some(sender) = music_sender_option.take() {
-                                    let _ = sender.send(MusicControl::Terminate);
-                                    if let Some(handle) = music_service_handle.take() {
-                                        handle
-                                            .join()
-                                            .expect("Failed to join music thread for song load");
-                                    }
-                                }
-                                // Drain any lingering progress messages
-                                while progress_receiver.try_recv().is_ok() {}
-
-                                tui.reset_progress_for_new_song(); // Reset visual progress
-                                                                   // Update TUI with loaded state, but preserve some dynamic states like is_playing
-                                                                   // parse_song_id_to_app_state returns a full AppState, so we use it directly
-                                                                   // For now, we will directly use the loaded state, this implies song starts paused
-                                                                   // and user has to press play.
-                                tui.set_app_state(loaded_app_state.clone()); // Directly set TUI state
-                                tui.set_current_song_id_display(Some(song_name_to_load.clone())); // Show the ID being loaded
-
-                                let (new_music_sender, new_music_receiver) =
-                                    crossbeam_channel::unbounded::<MusicControl>();
-                                let new_progress_sender_clone = progress_sender.clone();
-                                music_sender_option = Some(new_music_sender.clone());
-
-                                // Spawn new music service with the loaded state
-                                music_service_handle = Some(thread::spawn(move || {
-                                    gen::run_music_service(
-                                        loaded_app_state,
-                                        new_music_receiver,
-                                        new_progress_sender_clone,
-                                    );
-                                }));
-
-                                // After successfully setting up the new song, send a Resume command to start it.
-                                if let Some(sender) = &music_sender_option {
-                                    let _ = sender.send(MusicControl::Resume);
-                                }
-                                tui.set_playing_state(true);
-                                tui.focus_on_play_pause();
+                            Ok(mut loaded_app_state) => {
+                                let current_app_state = tui.get_current_app_state();
+                                loaded_app_state.carry_playback_settings(&current_app_state);
+
+                                remember_song(&mut history, &current_app_state);
+                                switch_requested = true;
+                                let id_version = loaded_app_state.id_version;
+                                play_loaded_song(&mut tui, &mut controller, song_name_to_load, loaded_app_state);
                                 tui.clear_song_loader_input();
+                                if id_version < SONG_ID_VERSION {
+                                    tui.show_notice(&format!("Song ID v{} may sound different now", id_version));
+                                }
                             }
                             Err(error_message) => {
                                 tui.show_song_id_error(error_message);
@@ -320,6 +514,9 @@ fn main() -> Result<(), Box<dyn Error>> {
                         }
                     }
                 }
+                UserAction::DismissWhatsNew => {
+                    let _ = session::save_last_seen_version(current_version);
+                }
                 UserAction::NoOp => {}
                 // UserActions handled by TUI state changes or that trigger TUI updates,
                 // allowing the main loop to continue.
@@ -330,6 +527,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 | UserAction::OpenPopup
                 | UserAction::CyclePopupOption
                 | UserAction::CloseSongIdErrorPopup
+                | UserAction::ScrollPopup
                 | UserAction::SelectPopupItem => { /* These are handled by TUI state changes or main initiates TUI change, main loop continues */
                 }
             }
@@ -337,6 +535,17 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    // The audio stops before the terminal is restored, so nothing plays after the app is gone
+    let music_stopped = controller.shutdown(controller::QUIT_SHUTDOWN_DEADLINE);
+    // Queued renders are cancelled; a render already running gets a short grace period
+    workers::global().shutdown(workers::QUIT_SHUTDOWN_DEADLINE);
     tui.teardown()?;
+    // Printed after the TUI is gone so they stay readable
+    for warning in &config_warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if !music_stopped {
+        eprintln!("Warning: The music service did not stop in time and was left to exit with the app.");
+    }
     Ok(())
 }
@@ -1,4 +1,5 @@
-use dasp_signal::Signal;
+use crate::progs::ChordSpan;
+use crate::synth::{Oscillator, PulseWidth, Vibrato, Waveform};
 use rand::prelude::*;
 use rand::rngs::StdRng;
 use rust_music_theory::note::{Note, Notes, PitchClass};
@@ -78,99 +79,660 @@ fn note_to_frequency(note: &Note) -> f32 {
     440.0 * 2f32.powf((midi_number as f32 - 69.0) / 12.0)
 }
 
+/* note_to_midi_number - Converts a `Note` to its MIDI number (C4 = 60).
+ *
+ * inputs:
+ *     - note (&Note): The note to convert.
+ *
+ * outputs:
+ *     - i32: The MIDI number of the note.
+ */
+fn note_to_midi_number(note: &Note) -> i32 {
+    (note.octave as i32 + 1) * 12 + pitch_to_semitone(&note.pitch_class) as i32
+}
+
+/* midi_number_to_note - Converts a MIDI number (C4 = 60) back to a `Note`.
+ *
+ * inputs:
+ *     - midi_number (i32): The MIDI number to convert (must be at least 12, i.e. C0).
+ *
+ * outputs:
+ *     - Note: The corresponding note.
+ */
+fn midi_number_to_note(midi_number: i32) -> Note {
+    Note::new(
+        semitone_to_pitch(midi_number.rem_euclid(12) as u8),
+        (midi_number / 12 - 1).max(0) as u8,
+    )
+}
+
+// BLUES_SCALE_INTERVALS: Semitones above the root of the six-note blues scale: root, b3, 4,
+// b5 (the blue note), 5 and b7.
+const BLUES_SCALE_INTERVALS: [u8; 6] = [0, 3, 5, 6, 7, 10];
+
+// ARPEGGIO_PATTERNS: Orders in which an arpeggio visits the chord's root (0), third (1),
+// fifth (2) and the root an octave up (3), one tone per sixteenth note.
+const ARPEGGIO_PATTERNS: [[usize; 4]; 3] = [[0, 1, 2, 3], [3, 2, 1, 0], [0, 2, 1, 2]];
+
+// CONSONANT_DYAD_INTERVALS: Semitone distances allowed between a melody note and its
+// double-stop partner below: minor/major thirds and minor/major sixths.
+const CONSONANT_DYAD_INTERVALS: [i32; 4] = [3, 4, 8, 9];
+
+// DYAD_SEED_SALT: Mixed into the melody seed so dyad choices use their own random stream
+// and leave the note and rhythm choices of existing songs untouched.
+const DYAD_SEED_SALT: u64 = 0x6479_6164_5f68_6974;
+
+// REST_SEED_SALT: Mixed into the melody seed so rest choices use their own random stream.
+const REST_SEED_SALT: u64 = 0x7265_7374_5f72_6f6c;
+
+// REST_PHRASE_NOTES: Notes played in a row after which the chance of a rest has doubled, so
+// long phrases tend to end in a breath.
+const REST_PHRASE_NOTES: f32 = 8.0;
+
+// AMBIENT_REST_PROBABILITY / REGGAE_REST_PROBABILITY: Chance that a time slot of the style's
+// melody rests (see `plan_rests`).
+const AMBIENT_REST_PROBABILITY: f32 = 0.4;
+const REGGAE_REST_PROBABILITY: f32 = 0.35;
+
+// ACCENT_PERIOD_BARS: Phrase accents fall on bar 1 of every 4-bar group.
+const ACCENT_PERIOD_BARS: f32 = 4.0;
+
+// MOTIF_MIN_BARS / MOTIF_MAX_BARS: Range of a melody's motif length, in bars.
+const MOTIF_MIN_BARS: usize = 2;
+const MOTIF_MAX_BARS: usize = 4;
+
+// FINAL_NOTE_MIN_BEATS: The shortest the root ending a song is held, in beats.
+const FINAL_NOTE_MIN_BEATS: f32 = 1.0;
+
+// MELODY_FORMS: Plans a melody cycles through, one letter per phrase: A is the motif and B the
+// phrase answering it.
+const MELODY_FORMS: [&str; 2] = ["AABA", "ABAB"];
+
+// TRIPLET_GROUPS: Triplet figures a melody may play over one beat, as fractions of the beat: three
+// triplet eighths, or a quarter-eighth triplet. Each fills the whole beat, so the next beat stays put.
+const TRIPLET_GROUPS: [&[f32]; 2] = [&[1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0], &[2.0 / 3.0, 1.0 / 3.0]];
+// TRIPLET_CHANCE: Chance that a Complex or Syncopated melody plays a triplet figure on a beat.
+const TRIPLET_CHANCE: f32 = 0.15;
+// TRIPLET_PATTERN_CHANCE: Chance that a Triplet melody plays a triplet figure on a beat.
+const TRIPLET_PATTERN_CHANCE: f32 = 0.5;
+
+// HARMONY_THIRD_STEPS / HARMONY_SIXTH_STEPS: Scale degrees a harmony voice sings above the
+// melody: a third, or a sixth for folk and classical.
+const HARMONY_THIRD_STEPS: usize = 2;
+const HARMONY_SIXTH_STEPS: usize = 5;
+// COUNTER_SEED_SALT: Mixed into the seed so the counter-melody uses its own random stream.
+const COUNTER_SEED_SALT: u64 = 0x636f_756e_7465_7272;
+// ARTICULATION_SEED_SALT: Mixed into the seed so each note's articulation varies on its own random stream.
+const ARTICULATION_SEED_SALT: u64 = 0x6172_7469_635f_6875;
+// ARTICULATION_VARIATION: How far each note's articulation may stray from the note shape's, up
+// or down, so detached lines sound played rather than sequenced.
+const ARTICULATION_VARIATION: f32 = 0.04;
+// COUNTER_NOTE_BEATS: The counter-melody's note lengths in beats: half and whole notes.
+const COUNTER_NOTE_BEATS: [f32; 2] = [2.0, 4.0];
+// COUNTER_CONTRARY_CHANCE: How often the counter-melody moves against the melody when the
+// melody moves; otherwise it takes the nearest chord tone.
+const COUNTER_CONTRARY_CHANCE: f64 = 0.8;
+// COUNTER_OCTAVE / COUNTER_LOWEST_OFFSET / COUNTER_RANGE_SEMITONES: The counter-melody's range
+// starts a fifth below the key's root in octave 3, at the bottom of the melody's, and spans
+// an octave and a third.
+const COUNTER_OCTAVE: i32 = 3;
+const COUNTER_LOWEST_OFFSET: i32 = -7;
+const COUNTER_RANGE_SEMITONES: i32 = 16;
+// COUNTER_NOTE_SHAPE: The counter-melody plays a soft, slightly detached sine, apart from the square lead.
+const COUNTER_NOTE_SHAPE: NoteShape = NoteShape {
+    articulation: 0.95,
+    attack_seconds: 0.02,
+    decay_seconds: 0.0,
+    sustain_level: 1.0,
+    release_seconds: 0.08,
+    waveform: Waveform::Sine,
+    pulse_width: PulseWidth::EVEN,
+    vibrato: Vibrato::NONE,
+    vibrato_min_seconds: 0.0,
+};
+
+// Contour: The start in seconds and MIDI number of every sounding note of a melody, in order.
+pub type Contour = Vec<(f32, i32)>;
+
+/* MelodyEvent - A single planned melody note, prior to synthesis.
+ *
+ * fields:
+ *     - note (Note): The main pitch of the event.
+ *     - duration (f32): The length of the event's time slot in seconds.
+ *     - dyad (Option<Note>): An optional second pitch below `note`, sounded together as a double-stop.
+ *     - rest (bool): True if the time slot stays silent.
+ */
+struct MelodyEvent {
+    note: Note,
+    duration: f32,
+    dyad: Option<Note>,
+    rest: bool,
+}
+
+/* diatonic_scale_notes - Builds a diatonic scale in one of its modes.
+ *
+ * inputs:
+ *     - root (PitchClass): The tonic of the scale.
+ *     - mode (Mode): The mode (e.g., Ionian for major, Aeolian for natural minor).
+ *
+ * outputs:
+ *     - Vec<Note>: The seven scale notes from the tonic upwards, followed by the tonic an octave up.
+ */
+fn diatonic_scale_notes(root: PitchClass, mode: Mode) -> Vec<Note> {
+    Scale::new(ScaleType::Diatonic, root, 4, Some(mode), Direction::Ascending)
+        .unwrap()
+        .notes()
+}
+
+/* blues_scale_notes - Builds the blues scale (minor pentatonic plus the flat fifth).
+ *
+ * rust_music_theory has no blues scale, so the notes are built from `BLUES_SCALE_INTERVALS`.
+ *
+ * inputs:
+ *     - root (PitchClass): The tonic of the scale.
+ *
+ * outputs:
+ *     - Vec<Note>: The six scale notes from the tonic upwards, followed by the tonic an octave
+ *       up, laid out like `diatonic_scale_notes`.
+ */
+fn blues_scale_notes(root: PitchClass) -> Vec<Note> {
+    let root_semitone = pitch_to_semitone(&root);
+    let mut notes: Vec<Note> = BLUES_SCALE_INTERVALS
+        .iter()
+        .map(|&interval| Note::new(semitone_to_pitch(root_semitone + interval), 4))
+        .collect();
+    notes.push(Note::new(root, 5));
+    notes
+}
+
+/* dyad_below - Finds a consonant in-scale partner note below a melody note.
+ *
+ * Steps down the scale by `scale_steps` degrees (2 for a third, 5 for a sixth in a
+ * seven-note scale) and checks the resulting distance against `CONSONANT_DYAD_INTERVALS`.
+ *
+ * inputs:
+ *     - note (&Note): The melody note to harmonize.
+ *     - scale_notes (&[Note]): The scale the melody is drawn from (tonic first).
+ *     - scale_steps (usize): How many scale degrees below the note the partner lies.
+ *
+ * outputs:
+ *     - Option<Note>: The partner note, or None if the note is not in the scale or the interval is not consonant.
+ */
+fn dyad_below(note: &Note, scale_notes: &[Note], scale_steps: usize) -> Option<Note> {
+    // One entry per scale degree; the closing octave repeats the tonic
+    let mut degrees: Vec<i32> = Vec::new();
+    for semitone in scale_notes.iter().map(|n| pitch_to_semitone(&n.pitch_class) as i32) {
+        if degrees.contains(&semitone) {
+            break;
+        }
+        degrees.push(semitone);
+    }
+    let degree_count = degrees.len();
+    if degree_count < 5 {
+        return None;
+    }
+    let note_semitone = pitch_to_semitone(&note.pitch_class) as i32;
+    let degree = degrees.iter().position(|&d| d == note_semitone)?;
+    let partner_degree = (degree + degree_count - scale_steps % degree_count) % degree_count;
+    let interval = (note_semitone - degrees[partner_degree]).rem_euclid(12);
+    if !CONSONANT_DYAD_INTERVALS.contains(&interval) {
+        return None;
+    }
+    let partner_midi = note_to_midi_number(note) - interval;
+    (partner_midi >= 12).then(|| midi_number_to_note(partner_midi))
+}
+
+/* swing_position - Moves a point in time onto the swung grid.
+ *
+ * Swing delays the off-beat eighth of every beat, up to a triplet feel at full swing
+ * (two thirds of the way through the beat), and stretches or squeezes everything between
+ * the beat and the off-beat to match. Beats themselves never move, so swung parts stay
+ * in step with straight ones on every beat.
+ *
+ * inputs:
+ *     - beats (f64): The straight position, in beats from the start of the song.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *
+ * outputs:
+ *     - f64: The swung position, in beats.
+ */
+pub fn swing_position(beats: f64, swing: f32) -> f64 {
+    let off_beat = 0.5 + swing.clamp(0.0, 1.0) as f64 / 6.0; // Where the off-beat eighth lands
+    let beat = beats.floor();
+    let within = beats - beat;
+    let swung = if within < 0.5 {
+        within * 2.0 * off_beat
+    } else {
+        off_beat + (within - 0.5) * 2.0 * (1.0 - off_beat)
+    };
+    beat + swung
+}
+
+/* swing_events - Re-times planned events onto the swung grid.
+ *
+ * Consecutive eighths become long-short pairs; quarter notes and longer keep their length.
+ * Triplet figures already have their own lilt and fill whole beats, so they stay as planned.
+ *
+ * inputs:
+ *     - events (&mut [MelodyEvent]): The events, in order from the start of the song.
+ *     - seconds_per_quarter_note (f32): Length of a beat.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *
+ * outputs:
+ *     - None
+ */
+fn swing_events(events: &mut [MelodyEvent], seconds_per_quarter_note: f32, swing: f32) {
+    if swing <= 0.0 || seconds_per_quarter_note <= 0.0 {
+        return; // Straight timing stays untouched, sample for sample
+    }
+    let beat_seconds = seconds_per_quarter_note as f64;
+    let mut start_beat = 0.0f64;
+    for event in events.iter_mut() {
+        let end_beat = start_beat + event.duration as f64 / beat_seconds;
+        if !is_triplet_duration(end_beat - start_beat) {
+            let swung_beats = swing_position(end_beat, swing) - swing_position(start_beat, swing);
+            event.duration = (swung_beats * beat_seconds) as f32;
+        }
+        start_beat = end_beat;
+    }
+}
+
+/* is_triplet_duration - Tells whether a note length belongs to a triplet figure.
+ *
+ * Every other rhythm a melody plays lands on the sixteenth-note grid.
+ *
+ * inputs:
+ *     - beats (f64): The note's length in beats.
+ *
+ * outputs:
+ *     - bool: True if the length is a third or two thirds of a beat (off the sixteenth grid).
+ */
+fn is_triplet_duration(beats: f64) -> bool {
+    let sixteenths = beats * 4.0;
+    (sixteenths - sixteenths.round()).abs() > 0.01
+}
+
+/* add_accent_dyads - Turns some phrase-accent notes into double-stops.
+ *
+ * An accent is the first event starting at or after each `ACCENT_PERIOD_BARS` boundary
+ * (the start of every 4-bar group). Each accent receives a third or sixth below with
+ * probability `dyad_probability`, using a random stream derived from `seed`.
+ *
+ * inputs:
+ *     - events (&mut [MelodyEvent]): The planned melody events.
+ *     - scale_notes (&[Note]): The scale the melody is drawn from.
+ *     - seconds_per_quarter_note (f32): Duration of one beat, used to locate bar boundaries.
+ *     - beats_per_bar (usize): Beats in a bar of the song's time signature.
+ *     - dyad_probability (f32): Chance (0.0-1.0) that an accent becomes a double-stop.
+ *     - seed (u64): The melody seed.
+ *
+ * outputs:
+ *     - None
+ */
+fn add_accent_dyads(
+    events: &mut [MelodyEvent],
+    scale_notes: &[Note],
+    seconds_per_quarter_note: f32,
+    beats_per_bar: usize,
+    dyad_probability: f32,
+    seed: u64,
+) {
+    if dyad_probability <= 0.0 || seconds_per_quarter_note <= 0.0 {
+        return;
+    }
+    let mut rng = StdRng::seed_from_u64(seed ^ DYAD_SEED_SALT);
+    let accent_period_beats = ACCENT_PERIOD_BARS * beats_per_bar.max(1) as f32;
+    let mut start_beat = 0.0f32;
+    let mut next_accent_beat = 0.0f32;
+    for event in events.iter_mut() {
+        // Small tolerance so float rounding doesn't push an on-the-bar note past its boundary
+        if start_beat + 1e-3 >= next_accent_beat {
+            while next_accent_beat <= start_beat + 1e-3 {
+                next_accent_beat += accent_period_beats;
+            }
+            if rng.gen::<f32>() < dyad_probability {
+                let scale_steps = if rng.gen::<bool>() { 2 } else { 5 }; // Third or sixth below
+                event.dyad = dyad_below(&event.note, scale_notes, scale_steps);
+            }
+        }
+        start_beat += event.duration / seconds_per_quarter_note;
+    }
+}
+
+/* NoteShape - Per-note articulation settings and ADSR envelope for the melody synthesizer.
+ *
+ * fields:
+ *     - articulation (f32): Fraction of each note's time slot before its release (1.0 = legato).
+ *     - attack_seconds (f32): Length of the linear rise from silence at the start of each note.
+ *     - decay_seconds (f32): Length of the linear fall from the peak to the sustain level.
+ *     - sustain_level (f32): Level (0.0-1.0) the note holds after its decay.
+ *     - release_seconds (f32): Length of the linear fade to silence at the end of each note. It
+ *       rings on into the articulation gap, starting early when the gap is shorter than it, so
+ *       every note ends silent before the next begins.
+ *     - waveform (Waveform): The tone of each note.
+ *     - pulse_width (PulseWidth): The duty of a square tone, and how it sweeps over each note.
+ *     - vibrato (Vibrato): The wobble in pitch of long notes, before the per-note variation.
+ *     - vibrato_min_seconds (f32): Shortest time slot that gets vibrato, so fast passages stay clean.
+ */
+pub struct NoteShape {
+    pub articulation: f32,
+    pub attack_seconds: f32,
+    pub decay_seconds: f32,
+    pub sustain_level: f32,
+    pub release_seconds: f32,
+    pub waveform: Waveform,
+    pub pulse_width: PulseWidth,
+    pub vibrato: Vibrato,
+    pub vibrato_min_seconds: f32,
+}
+
+impl Default for NoteShape {
+    fn default() -> Self {
+        Self {
+            articulation: 1.0,
+            attack_seconds: 0.005,
+            decay_seconds: 0.05,
+            sustain_level: 0.85,
+            release_seconds: 0.02,
+            waveform: Waveform::Square,
+            pulse_width: PulseWidth::EVEN,
+            vibrato: Vibrato::NONE,
+            vibrato_min_seconds: 0.0,
+        }
+    }
+}
+
+/* envelope_level - Returns the attack-decay-sustain level of a note at a sample.
+ *
+ * inputs:
+ *     - sample (usize): Samples since the note began.
+ *     - attack_samples (usize): Length of the attack in samples.
+ *     - decay_samples (usize): Length of the decay in samples.
+ *     - sustain_level (f32): The level after the decay.
+ *
+ * outputs:
+ *     - f32: The level, from 0.0 at the note's first sample up to 1.0 at the end of the attack.
+ */
+fn envelope_level(sample: usize, attack_samples: usize, decay_samples: usize, sustain_level: f32) -> f32 {
+    if sample < attack_samples {
+        sample as f32 / attack_samples as f32
+    } else if sample < attack_samples + decay_samples {
+        let progress = (sample - attack_samples) as f32 / decay_samples as f32;
+        1.0 - (1.0 - sustain_level) * progress
+    } else if decay_samples > 0 {
+        sustain_level
+    } else {
+        1.0
+    }
+}
+
+// OSCILLATOR_LEVEL: Peak of each melody voice; half amplitude to prevent distortion.
+const OSCILLATOR_LEVEL: f32 = 0.5;
+
+/* oscillator - Builds an oscillator for one melody pitch.
+ *
+ * inputs:
+ *     - note (&Note): The pitch to play.
+ *     - note_shape (&NoteShape): The shape whose waveform and pulse width to play it with.
+ *     - vibrato (Vibrato): The note's vibrato.
+ *
+ * outputs:
+ *     - Oscillator: The oscillator, at 44100 Hz.
+ */
+fn oscillator(note: &Note, note_shape: &NoteShape, vibrato: Vibrato) -> Oscillator {
+    Oscillator::new(note_shape.waveform, note_to_frequency(note) as f64, 44100.0)
+        .with_pulse_width(note_shape.pulse_width)
+        .with_vibrato(vibrato)
+}
+
+// VIBRATO_DELAY_SECONDS: How long a long note holds its pitch before its vibrato ramps in.
+const VIBRATO_DELAY_SECONDS: f32 = 0.15;
+// VIBRATO_MIN_BEATS: Shortest note that gets vibrato, in beats: a dotted quarter.
+const VIBRATO_MIN_BEATS: f32 = 1.5;
+// VIBRATO_SEED_SALT: Mixed into the seed so each note's vibrato varies on its own random stream.
+const VIBRATO_SEED_SALT: u64 = 0x7669_6272_6174_6f21;
+// VIBRATO_DEPTH_VARIATION / VIBRATO_RATE_VARIATION_HZ: How far each note's vibrato depth (as a
+// fraction of the style's) and rate may stray from the style's.
+const VIBRATO_DEPTH_VARIATION: f32 = 0.25;
+const VIBRATO_RATE_VARIATION_HZ: f32 = 0.4;
+
+/* style_vibrato - Returns the vibrato a style sings its long notes with.
+ *
+ * inputs:
+ *     - style (&str): Musical style string (lowercase).
+ *
+ * outputs:
+ *     - Vibrato: Wide and quick for metal and rock, a wide blues bend, a singer's vibrato for
+ *       classical and jazz, a faint, slow one for ambient, a light one otherwise, and none
+ *       for the electronic arpeggio.
+ */
+fn style_vibrato(style: &str) -> Vibrato {
+    let (depth_cents, rate_hz) = match style {
+        "metal" => (60.0, 6.0),
+        "rock" => (35.0, 6.0),
+        "blues" => (40.0, 5.0),
+        "classical" => (25.0, 5.5),
+        "jazz" => (20.0, 5.0),
+        "ambient" => (8.0, 4.5),
+        "electronic" => (0.0, 0.0),
+        _ => (12.0, 5.5),
+    };
+    Vibrato {
+        depth_cents,
+        rate_hz,
+        delay_seconds: VIBRATO_DELAY_SECONDS,
+    }
+}
+
+// AMBIENT_NOTE_SHAPE: Ambient notes swell in and fade out slowly, so the line floats over the pads.
+const AMBIENT_NOTE_SHAPE: NoteShape = NoteShape {
+    articulation: 1.0,
+    attack_seconds: 0.3,
+    decay_seconds: 0.0,
+    sustain_level: 1.0,
+    release_seconds: 1.0,
+    waveform: Waveform::Square,
+    pulse_width: PulseWidth::EVEN,
+    vibrato: Vibrato::NONE,
+    vibrato_min_seconds: 0.0,
+};
+// POP_NOTE_SHAPE: Pop notes snap in and fall away quickly to a lower level, like a plucked
+// string, slightly detached.
+const POP_NOTE_SHAPE: NoteShape = NoteShape {
+    articulation: 0.85,
+    attack_seconds: 0.002,
+    decay_seconds: 0.1,
+    sustain_level: 0.55,
+    release_seconds: 0.04,
+    waveform: Waveform::Square,
+    pulse_width: PulseWidth::EVEN,
+    vibrato: Vibrato::NONE,
+    vibrato_min_seconds: 0.0,
+};
+
+// STACCATO_ARTICULATION: Fraction of the slot that sounds in the staccato electronic and classical runs.
+const STACCATO_ARTICULATION: f32 = 0.6;
+
+/* style_note_shape - Returns the note shape a style's melody plays with at a moderate tempo.
+ *
+ * inputs:
+ *     - style (&str): Musical style string (lowercase).
+ *
+ * outputs:
+ *     - NoteShape: Plucky and slightly detached for pop, plucky and staccato for electronic,
+ *       staccato for classical, legato slow swells for ambient, and the default short attack
+ *       and release, legato, otherwise.
+ */
+fn style_note_shape(style: &str) -> NoteShape {
+    match style {
+        "pop" => POP_NOTE_SHAPE,
+        "electronic" => NoteShape {
+            articulation: STACCATO_ARTICULATION,
+            ..POP_NOTE_SHAPE
+        },
+        "classical" => NoteShape {
+            articulation: STACCATO_ARTICULATION,
+            ..NoteShape::default()
+        },
+        "ambient" => AMBIENT_NOTE_SHAPE,
+        _ => NoteShape::default(),
+    }
+}
+
+// SLOW_TEMPO_RELEASE_SECONDS: Release tail used on slow songs so long notes fade rather than cut.
+const SLOW_TEMPO_RELEASE_SECONDS: f32 = 0.12;
+// FAST_TEMPO_ARTICULATION: Largest fraction of the slot that sounds on fast songs, leaving at least a short gap.
+const FAST_TEMPO_ARTICULATION: f32 = 0.8;
+
+/* tempo_bpm_thresholds - Returns the BPM range in which a style's melody is left untouched.
+ *
+ * inputs:
+ *     - style (&str): Musical style string (lowercase).
+ *
+ * outputs:
+ *     - (u32, u32): (slow threshold, fast threshold). Below the first the melody drops an octave,
+ *       above the second it is played more detached.
+ */
+fn tempo_bpm_thresholds(style: &str) -> (u32, u32) {
+    match style {
+        "blues" => (70, 150),
+        "pop" => (80, 150),
+        "jazz" => (72, 180),
+        "rock" => (80, 140), // Straight eighths blur together sooner than quarter notes
+        "metal" => (80, 140), // Sixteenth-note runs likewise
+        "electronic" => (90, 140), // Sixteenth-note arpeggios even more so
+        _ => (76, 150),
+    }
+}
+
+/* tempo_adjustment - Derives the melody octave shift and note shape from the resolved BPM.
+ *
+ * A square lead in octave 3 with long note values sounds shrill and exposed at very slow
+ * tempos, so slow songs drop the melody an octave and soften note ends with a longer release.
+ * Very fast songs keep the octave but shorten each note for a crisper, more detached line.
+ * Both start from the style's own note shape (see `style_note_shape`) and vibrato (see
+ * `style_vibrato`), which only notes of at least `VIBRATO_MIN_BEATS` at this tempo get.
+ *
+ * inputs:
+ *     - style (&str): Musical style string (lowercase).
+ *     - bpm (u32): The resolved beats per minute of the song.
+ *
+ * outputs:
+ *     - (i8, NoteShape): The octave shift to apply and the note shape to synthesize with.
+ */
+fn tempo_adjustment(style: &str, bpm: u32) -> (i8, NoteShape) {
+    let (slow_bpm, fast_bpm) = tempo_bpm_thresholds(style);
+    let shape = NoteShape {
+        vibrato: style_vibrato(style),
+        vibrato_min_seconds: VIBRATO_MIN_BEATS * 60.0 / bpm.max(1) as f32,
+        ..style_note_shape(style)
+    };
+    if bpm < slow_bpm {
+        (
+            -1,
+            NoteShape {
+                release_seconds: shape.release_seconds.max(SLOW_TEMPO_RELEASE_SECONDS),
+                ..shape
+            },
+        )
+    } else if bpm > fast_bpm {
+        (
+            0,
+            NoteShape {
+                articulation: shape.articulation.min(FAST_TEMPO_ARTICULATION),
+                ..shape
+            },
+        )
+    } else {
+        (0, shape)
+    }
+}
+
 /* RhythmPattern - Defines different rhythmic feels for melody generation.
  *
  * Each variant implies a different distribution of note durations.
  */
+#[derive(Debug)]
 pub enum RhythmPattern {
     Simple,     // Primarily quarter notes (1 note per beat).
     Medium,     // Mix of quarter and eighth notes (1-2 notes per beat).
-    Complex,    // Mix of eighth and sixteenth notes, allowing for faster passages.
-    Syncopated, // Emphasizes off-beat notes for a syncopated feel.
+    Complex,    // Mix of eighth and sixteenth notes, allowing for faster passages, with occasional triplets.
+    Syncopated, // Emphasizes off-beat notes for a syncopated feel, with occasional triplets.
+    Driving,    // Straight eighth notes throughout.
+    Sparse,     // Half and whole notes.
+    Triplet,    // Triplet figures mixed with quarter notes and pairs of eighths, a beat at a time.
 }
 
-/* generate_melody_samples - Generates a sequence of audio samples for a melody.
+/* PhraseNote - One time slot of a planned melody phrase.
  *
- * This function constructs a melody based on musical scale, rhythm, and duration.
- * It involves several steps:
- * 1. Defining note durations based on the `rhythm_pattern`.
- * 2. Selecting a sequence of notes from the specified `scale` with probabilistic transitions.
- * 3. Synthesizing audio samples for each note using a simple sine wave and an ADSR envelope.
- * 4. Applying articulation (small gaps) between notes.
+ * fields:
+ *     - degree (usize): Index of the note in the melody's scale.
+ *     - octave (i8): The octave the note is played in.
+ *     - beats (f32): Length of the slot in beats.
+ *     - rest (bool): True if the slot stays silent.
+ */
+#[derive(Debug, Clone, Copy)]
+struct PhraseNote {
+    degree: usize,
+    octave: i8,
+    beats: f32,
+    rest: bool,
+}
+
+/* Variation - How a repeat of a phrase differs from its first statement.
+ */
+#[derive(Debug, Clone, Copy)]
+enum Variation {
+    Exact,      // The phrase as first played.
+    StepUp,     // Every note a scale step higher.
+    Diminution, // Twice as fast, played twice to fill the same bars.
+}
+
+// VARIATIONS: The variations a repeated phrase is chosen from, equally likely.
+const VARIATIONS: [Variation; 3] = [Variation::Exact, Variation::StepUp, Variation::Diminution];
+
+/* plan_rhythm - Plans the note lengths of one phrase in the given rhythmic feel.
+ *
+ * Triplet figures always start on a beat and are added whole (see `push_triplet_group`), and
+ * a last note running past the phrase is shortened, so the phrase fills exactly `beats` beats.
  *
  * inputs:
- *     - root_note (PitchClass): The tonic of the scale for the melody.
- *     - scale_type (ScaleType): The type of scale (e.g., Major, Minor).
- *     - mode (Mode): The mode of the scale (e.g., Ionian, Dorian).
- *     - octave (i8): The base octave for the melody notes.
- *     - rhythm_pattern (RhythmPattern): The rhythmic feel to apply.
- *     - duration_seconds (u32): Total desired duration of the melody in seconds.
- *     - seconds_per_quarter_note (f32): Duration of a single quarter note, derived from BPM.
- *     - seed (u64): Seed for the random number generator to ensure reproducibility.
+ *     - rhythm_pattern (&RhythmPattern): The rhythmic feel to apply.
+ *     - beats (usize): Length of the phrase in beats.
+ *     - rng (&mut StdRng): The melody's random number generator.
  *
  * outputs:
- *     - Vec<f32>: A vector of f32 audio samples representing the generated melody at SAMPLE_RATE.
+ *     - Vec<f32>: The note lengths in beats, in order.
  */
-pub fn generate_melody_samples(
-    root_note: PitchClass,
-    scale_type: ScaleType,
-    mode: Mode,
-    octave: i8,
-    rhythm_pattern: RhythmPattern,
-    duration_seconds: u32,
-    seconds_per_quarter_note: f32,
-    seed: u64,
-) -> Vec<f32> {
-    let mut rng = StdRng::seed_from_u64(seed);
-    const SAMPLE_RATE: f32 = 44100.0;
-    // Create scale
-    let scale = Scale::new(
-        scale_type, // scale type
-        root_note,  // tonic
-        4,          // octave
-        Some(mode), // scale mode
-        Direction::Ascending,
-    )
-    .unwrap();
-
-    let scale_notes = scale.notes();
+fn plan_rhythm(rhythm_pattern: &RhythmPattern, beats: usize, rng: &mut StdRng) -> Vec<f32> {
+    let target = beats as f32;
     let mut durations: Vec<f32> = vec![];
-    let mut dur_sum = 0.0;
-    // let quarter_note_duration = 60.0 / bpm as f32; // Removed, using seconds_per_quarter_note directly
-                                                   // Apply rhythm pattern
-    let durations = match rhythm_pattern {
+    let mut beat_sum: f32 = 0.0;
+    match rhythm_pattern {
         RhythmPattern::Simple => {
             // All quarter notes
-
-            // Calculate how many quarter notes fit in the total duration
-            let num_quarter_notes =
-                (duration_seconds as f32 / seconds_per_quarter_note).floor() as usize; // Number of full quarter notes
-
-            // Create a vector filled with quarter note durations
-            let durations: Vec<f32> = vec![seconds_per_quarter_note; num_quarter_notes];
-            durations
+            durations = vec![1.0; beats];
+            beat_sum = target;
         }
         RhythmPattern::Medium => {
             // Mix of quarter and eighth notes
-
-            while dur_sum < duration_seconds as f32 {
+            while beat_sum < target {
                 // 50% chance of quarter note, 50% chance of eighth note
-                let actual_duration = if rng.gen::<bool>() { // CORRECTED
-                    1.0 * seconds_per_quarter_note
-                } else {
-                    0.5 * seconds_per_quarter_note
-                };
-                durations.push(actual_duration);
-                dur_sum += actual_duration;
+                let beat_multiplier = if rng.gen::<bool>() { 1.0 } else { 0.5 };
+                durations.push(beat_multiplier);
+                beat_sum += beat_multiplier;
             }
-
-            durations
         }
         RhythmPattern::Complex => {
-            // Mix of quarter, eighth, and sixteenth notes
-            while dur_sum < duration_seconds as f32 {
+            // Mix of quarter, eighth, and sixteenth notes, with the odd triplet on a beat
+            while beat_sum < target {
+                if beat_sum.fract() == 0.0 && rng.gen::<f32>() < TRIPLET_CHANCE {
+                    push_triplet_group(&mut durations, rng);
+                    beat_sum += 1.0;
+                    continue;
+                }
                 // 25% quarter, 50% eighth, 25% sixteenth
-                let roll = rng.gen::<f32>(); // CORRECTED
+                let roll = rng.gen::<f32>();
                 let beat_multiplier = if roll < 0.25 {
                     1.0 // quarter
                 } else if roll < 0.75 {
@@ -178,63 +740,166 @@ pub fn generate_melody_samples(
                 } else {
                     0.25 // sixteenth
                 };
-                let actual_duration = beat_multiplier * seconds_per_quarter_note;
-                dur_sum += actual_duration;
-                durations.push(actual_duration); // Push actual duration in seconds
+                durations.push(beat_multiplier);
+                beat_sum += beat_multiplier;
+            }
+        }
+        RhythmPattern::Driving => {
+            // All eighth notes
+            durations = vec![0.5; beats * 2];
+            beat_sum = target;
+        }
+        RhythmPattern::Sparse => {
+            // Half and whole notes
+            while beat_sum < target {
+                let beat_multiplier = if rng.gen::<bool>() { 2.0 } else { 4.0 };
+                durations.push(beat_multiplier);
+                beat_sum += beat_multiplier;
             }
-
-            durations
         }
         RhythmPattern::Syncopated => {
-            // Syncopated rhythm with some off-beat notes
-            // let mut durations = vec![]; // durations is already mutably borrowed
-
-            // counts if beat is on beat or off beat
-            let mut i = 0;
-            dur_sum = 0.0; // Reset dur_sum for this pattern
-            while dur_sum < duration_seconds as f32 {
+            // Syncopated rhythm with some off-beat notes, and the odd triplet on a beat
+            let mut i = 0; // Counts whether the next note is on or off the beat
+            while beat_sum < target {
+                if beat_sum.fract() == 0.0 && rng.gen::<f32>() < TRIPLET_CHANCE {
+                    push_triplet_group(&mut durations, rng);
+                    beat_sum += 1.0;
+                    continue;
+                }
                 let beat_multiplier = if i % 2 == 0 {
                     // On-beat notes are usually shorter
-                    if rng.gen::<bool>() { // CORRECTED
-                        0.5
-                    } else {
-                        0.25
-                    }
+                    if rng.gen::<bool>() { 0.5 } else { 0.25 }
                 } else {
                     // Off-beat notes are usually longer
-                    if rng.gen::<bool>() { // CORRECTED
-                        1.0
-                    } else {
-                        0.75
-                    }
+                    if rng.gen::<bool>() { 1.0 } else { 0.75 }
                 };
-                let actual_duration = beat_multiplier * seconds_per_quarter_note;
-                
-                if dur_sum + actual_duration > duration_seconds as f32 && !durations.is_empty() {
-                    // Avoid adding a note that grossly exceeds total duration, unless it's the first note
-                    break;
-                }
-                dur_sum += actual_duration;
+                durations.push(beat_multiplier);
+                beat_sum += beat_multiplier;
                 i += 1;
-                durations.push(actual_duration); // Push actual duration in seconds
             }
+        }
+        RhythmPattern::Triplet => {
+            // One beat at a time: a triplet figure, a quarter note, or two eighths
+            while beat_sum < target {
+                if rng.gen::<f32>() < TRIPLET_PATTERN_CHANCE {
+                    push_triplet_group(&mut durations, rng);
+                } else if rng.gen::<bool>() {
+                    durations.push(1.0);
+                } else {
+                    durations.extend([0.5; 2]);
+                }
+                beat_sum += 1.0;
+            }
+        }
+    }
+    // Triplets never cross the end (it falls on a beat), so only a plain note can overrun it
+    if beat_sum > target {
+        if let Some(last) = durations.last_mut() {
+            *last -= beat_sum - target;
+        }
+    }
+    durations
+}
 
-            durations
+/* push_triplet_group - Appends a randomly chosen triplet figure (see `TRIPLET_GROUPS`).
+ *
+ * The whole figure is added at once, so a triplet is never left incomplete. It fills
+ * exactly one beat.
+ *
+ * inputs:
+ *     - durations (&mut Vec<f32>): The note lengths planned so far, in beats.
+ *     - rng (&mut StdRng): The melody's random number generator.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_triplet_group(durations: &mut Vec<f32>, rng: &mut StdRng) {
+    let group = TRIPLET_GROUPS[rng.gen_range(0..TRIPLET_GROUPS.len())];
+    durations.extend_from_slice(group);
+}
+
+/* plan_rests - Lays out a phrase's time slots, choosing which of them are left silent.
+ *
+ * Each slot rests with `rest_probability`, rising the longer the phrase since the last rest
+ * (see `REST_PHRASE_NOTES`). The first and last slots always sound, so a phrase never starts
+ * or ends on silence. Degrees and octaves are filled in later by `walk_phrase`.
+ *
+ * inputs:
+ *     - rhythm (&[f32]): The phrase's note lengths in beats (see `plan_rhythm`).
+ *     - rest_probability (f32): Chance (0.0-1.0) of a rest right after another rest.
+ *     - rest_rng (&mut StdRng): The melody's random stream for rests, apart from the notes'.
+ *
+ * outputs:
+ *     - Vec<PhraseNote>: One slot per note length, in order.
+ */
+fn plan_rests(rhythm: &[f32], rest_probability: f32, rest_rng: &mut StdRng) -> Vec<PhraseNote> {
+    let mut phrase: Vec<PhraseNote> = rhythm
+        .iter()
+        .map(|&beats| PhraseNote {
+            degree: 0,
+            octave: 0,
+            beats,
+            rest: false,
+        })
+        .collect();
+    if rest_probability <= 0.0 || phrase.len() < 3 {
+        return phrase;
+    }
+    let slots = phrase.len();
+    let mut phrase_notes = 1; // The first slot always sounds
+    for slot in phrase.iter_mut().take(slots - 1).skip(1) {
+        let chance = rest_probability * (1.0 + phrase_notes as f32 / REST_PHRASE_NOTES);
+        if rest_rng.gen::<f32>() < chance {
+            slot.rest = true;
+            phrase_notes = 0;
+        } else {
+            phrase_notes += 1;
         }
-    };
+    }
+    phrase
+}
 
-    // Create note sequence
-    let mut prev_note_idx = 0;
-    let mut melody_notes: Vec<Note> = vec![];
-    let total_beats: u32 = durations.len() as u32;
-    for i in 0..total_beats {
-        // For first note, start with the root note or fifth
-        if i == 0 {
+/* walk_phrase - Picks the notes of a phrase as a walk through the scale.
+ *
+ * The walk favors steps of one or two scale degrees, with the odd leap and octave jump when
+ * `leaps` is set. A rest keeps the last note's place, so the line resumes smoothly after the gap.
+ *
+ * inputs:
+ *     - phrase (&mut [PhraseNote]): The phrase's slots (see `plan_rests`); their degrees and
+ *       octaves are filled in.
+ *     - scale_len (usize): Number of notes in the melody's scale.
+ *     - octave (i8): The base octave for the notes.
+ *     - previous_degree (Option<usize>): The note the walk continues from, or None to start on
+ *       the root or fifth.
+ *     - resolve (bool): True to lean the last note toward the root or fifth.
+ *     - leaps (bool): True to allow occasional leaps and octave jumps.
+ *     - rng (&mut StdRng): The melody's random number generator.
+ *
+ * outputs:
+ *     - None
+ */
+fn walk_phrase(
+    phrase: &mut [PhraseNote],
+    scale_len: usize,
+    octave: i8,
+    previous_degree: Option<usize>,
+    resolve: bool,
+    leaps: bool,
+    rng: &mut StdRng,
+) {
+    let last_slot = phrase.len().saturating_sub(1);
+    let mut previous_degree = previous_degree;
+    for (i, slot) in phrase.iter_mut().enumerate() {
+        slot.octave = octave;
+        // For the melody's first note, start with the root note or fifth
+        let Some(prev_note_idx) = previous_degree else {
             let first_note_options = [0, 4]; // Root or fifth
-            prev_note_idx = *first_note_options.choose(&mut rng).unwrap();
-            let note = scale_notes[prev_note_idx].clone();
-            let note_with_octave = Note::new(note.pitch_class, octave as u8);
-            melody_notes.push(note_with_octave);
+            slot.degree = *first_note_options.choose(rng).unwrap();
+            previous_degree = Some(slot.degree);
+            continue;
+        };
+        if slot.rest {
+            slot.degree = prev_note_idx;
             continue;
         }
 
@@ -244,7 +909,7 @@ pub fn generate_melody_samples(
         // Favor steps (1 or 2 indices away) over leaps
         for jump in [-2, -1, 1, 2].iter() {
             let new_idx = (prev_note_idx as i32 + jump) as usize;
-            if new_idx < scale_notes.len() {
+            if new_idx < scale_len {
                 // Add step moves multiple times to increase their probability
                 possible_jumps.push(new_idx);
                 possible_jumps.push(new_idx); // Duplicate to increase probability
@@ -252,148 +917,1200 @@ pub fn generate_melody_samples(
         }
 
         // Add occasional larger jumps for variety
-        for jump in [-4, -3, 3, 4].iter() {
+        for jump in [-4, -3, 3, 4].iter().filter(|_| leaps) {
             let new_idx_signed = prev_note_idx as i32 + jump;
-            if new_idx_signed >= 0 && new_idx_signed < scale_notes.len() as i32 {
+            if new_idx_signed >= 0 && new_idx_signed < scale_len as i32 {
                 possible_jumps.push(new_idx_signed as usize);
             }
         }
 
-        // For the last note, prefer ending on the root or fifth
-        if i == total_beats - 1 {
-            // Higher probability to end on root or fifth
+        // For a resolving phrase's last note, prefer ending on the root or fifth
+        if resolve && i == last_slot {
             possible_jumps.extend(vec![0; 5]); // Root
             possible_jumps.push(4); // Fifth
         }
 
         // Choose the next note
-        prev_note_idx = *possible_jumps.choose(&mut rng).unwrap_or(&0);
-        let note = scale_notes[prev_note_idx].clone();
+        slot.degree = *possible_jumps.choose(rng).unwrap_or(&0);
+        previous_degree = Some(slot.degree);
 
         // Determine octave (occasionally jump octaves for variety)
-        let note_octave = if rng.gen::<f32>() < 0.05 { // CORRECTED
-            // 10% chance to jump octave, corrected to 5%
-            if rng.gen::<bool>() { // CORRECTED
-                octave + 1
-            } else {
-                octave - 1
-            }
+        if leaps && rng.gen::<f32>() < 0.05 {
+            slot.octave = if rng.gen::<bool>() { octave + 1 } else { octave - 1 };
+        }
+    }
+}
+
+/* vary_phrase - Builds a repeat of a phrase with the given variation.
+ *
+ * Diminution only applies to phrases whose notes are all whole eighths, so the halved notes
+ * stay on the sixteenth-note grid; other phrases repeat exactly instead.
+ *
+ * inputs:
+ *     - phrase (&[PhraseNote]): The phrase as first played.
+ *     - variation (Variation): How the repeat differs.
+ *     - scale_len (usize): Number of notes in the melody's scale, which ends with the tonic an
+ *       octave up.
+ *
+ * outputs:
+ *     - Vec<PhraseNote>: The varied phrase, as long in beats as the original.
+ */
+fn vary_phrase(phrase: &[PhraseNote], variation: Variation, scale_len: usize) -> Vec<PhraseNote> {
+    match variation {
+        Variation::StepUp => phrase
+            .iter()
+            .map(|note| PhraseNote {
+                // Wraps at the octave, so the top tonic steps up to the second degree
+                degree: (note.degree + 1) % (scale_len - 1).max(1),
+                ..*note
+            })
+            .collect(),
+        Variation::Diminution if phrase.iter().all(|note| (note.beats * 2.0).fract() == 0.0) => phrase
+            .iter()
+            .chain(phrase.iter())
+            .map(|note| PhraseNote {
+                beats: note.beats / 2.0,
+                ..*note
+            })
+            .collect(),
+        _ => phrase.to_vec(),
+    }
+}
+
+/* generate_melody_samples - Generates a sequence of audio samples for a melody.
+ *
+ * This function constructs a melody based on musical scale, rhythm, and duration.
+ * It involves several steps:
+ * 1. Planning a motif of 2-4 bars: note lengths in the `rhythm_pattern` (see `plan_rhythm`),
+ *    rests (see `plan_rests`) and a walk through `scale_notes` (see `walk_phrase`), plus an
+ *    answering phrase on the same rhythm that heads home to the root or fifth.
+ * 2. Laying the two phrases out in an AABA or ABAB plan (see `MELODY_FORMS`) until the song is
+ *    full, each repeat played exactly, a scale step higher, or twice as fast (see `vary_phrase`),
+ *    and occasionally doubling phrase-accent notes with a third or sixth below (a double-stop).
+ *    A harmony voice, when asked for, shadows every note a few scale degrees higher
+ *    (see `harmony_note`) and rests with the melody.
+ * 3. Synthesizing audio samples for each note in the note shape's waveform, under its ADSR
+ *    envelope, with the release ringing into the articulation gap (see `synthesize_melody`).
+ *
+ * Every choice comes from `seed`, so the same seed always yields the same structure.
+ *
+ * inputs:
+ *     - scale_notes (&[Note]): The scale to draw notes from, tonic first and ending with the
+ *       tonic an octave up (see `diatonic_scale_notes` and `blues_scale_notes`). The fifth
+ *       degree must be at index 4.
+ *     - octave (i8): The base octave for the melody notes.
+ *     - rhythm_pattern (RhythmPattern): The rhythmic feel to apply.
+ *     - duration_seconds (f32): Total desired duration of the melody in seconds, rounded to whole beats.
+ *     - seconds_per_quarter_note (f32): Duration of a single quarter note, derived from BPM.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel); see `swing_position`.
+ *     - beats_per_bar (usize): Beats in a bar of the song's time signature, which sets the phrase length.
+ *     - dyad_probability (f32): Chance (0.0-1.0) that a phrase-accent note becomes a double-stop.
+ *     - leaps (bool): True to allow occasional leaps and octave jumps; false keeps the line to
+ *       steps of one or two scale degrees.
+ *     - rest_probability (f32): Chance (0.0-1.0) that a time slot is left silent, before the
+ *       bias toward resting after long phrases.
+ *     - note_shape (&NoteShape): Articulation and release applied to every note.
+ *     - final_tonic (bool): True to end on the root, held for at least a beat, as the song's last
+ *       note; otherwise the melody ends on the root or the fifth.
+ *     - harmony_steps (Option<usize>): Scale degrees above the melody the harmony voice sings
+ *       (`HARMONY_THIRD_STEPS` or `HARMONY_SIXTH_STEPS`), or None for no harmony.
+ *     - sample_limit (usize): Stop synthesizing after this many samples (usize::MAX renders everything).
+ *       All notes are still planned, so a limited render is an exact prefix of the full one.
+ *     - seed (u64): Seed for the random number generator to ensure reproducibility.
+ *
+ * A melody always has at least one note when `duration_seconds` is nonzero, even if a single
+ * beat is longer than the whole song. A zero duration or an invalid beat length yields no samples.
+ *
+ * outputs:
+ *     - (Vec<f32>, Vec<f32>, Contour, usize): The synthesized melody and harmony (empty
+ *       without one) at SAMPLE_RATE (at most `sample_limit` each), the melody's contour (the start
+ *       in seconds and MIDI number of every sounding note, in order) and the length of the full
+ *       melody in samples.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn generate_melody_samples(
+    scale_notes: &[Note],
+    octave: i8,
+    rhythm_pattern: RhythmPattern,
+    duration_seconds: f32,
+    seconds_per_quarter_note: f32,
+    swing: f32,
+    beats_per_bar: usize,
+    dyad_probability: f32,
+    leaps: bool,
+    rest_probability: f32,
+    note_shape: &NoteShape,
+    final_tonic: bool,
+    harmony_steps: Option<usize>,
+    sample_limit: usize,
+    seed: u64,
+) -> (Vec<f32>, Vec<f32>, Contour, usize) {
+    if !duration_seconds.is_finite()
+        || duration_seconds <= 0.0
+        || !seconds_per_quarter_note.is_finite()
+        || seconds_per_quarter_note <= 0.0
+    {
+        return (Vec::new(), Vec::new(), Vec::new(), 0);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    // Own random stream, so the notes and rhythm match a melody without rests
+    let mut rest_rng = StdRng::seed_from_u64(seed ^ REST_SEED_SALT);
+    let scale_len = scale_notes.len();
+    let total_beats = ((duration_seconds / seconds_per_quarter_note).round() as usize).max(1);
+
+    // The motif, and an answer to it on the same rhythm that heads home to the root or fifth
+    let motif_beats = rng.gen_range(MOTIF_MIN_BARS..=MOTIF_MAX_BARS) * beats_per_bar.max(1);
+    let form = MELODY_FORMS[rng.gen_range(0..MELODY_FORMS.len())];
+    let motif_rhythm = plan_rhythm(&rhythm_pattern, motif_beats, &mut rng);
+    let mut motif = plan_rests(&motif_rhythm, rest_probability, &mut rest_rng);
+    walk_phrase(&mut motif, scale_len, octave, None, false, leaps, &mut rng);
+    let mut answer = plan_rests(&motif_rhythm, rest_probability, &mut rest_rng);
+    let motif_end = motif.last().map(|note| note.degree);
+    walk_phrase(&mut answer, scale_len, octave, motif_end, true, leaps, &mut rng);
+
+    // Play the form over and over until the song is full, varying every repeat of a phrase
+    let mut slots: Vec<PhraseNote> = vec![];
+    let mut beat_sum: f32 = 0.0;
+    for (section_index, section) in form.chars().cycle().enumerate() {
+        if beat_sum >= total_beats as f32 {
+            break;
+        }
+        let phrase = if section == 'A' { &motif } else { &answer };
+        let first_statement = form.find(section) == Some(section_index);
+        let section_notes = if first_statement {
+            phrase.clone()
         } else {
-            octave
+            vary_phrase(phrase, VARIATIONS[rng.gen_range(0..VARIATIONS.len())], scale_len)
         };
+        for note in section_notes {
+            if beat_sum >= total_beats as f32 {
+                break;
+            }
+            beat_sum += note.beats;
+            slots.push(note);
+        }
+    }
+    // Phrases and triplets start on beats, so only a plain note can overrun the last beat
+    if let Some(last) = slots.last_mut() {
+        last.beats -= (beat_sum - total_beats as f32).max(0.0);
+    }
+    if final_tonic && !slots.is_empty() {
+        // The song's last note: fold the closing notes into one root lasting at least a beat.
+        // Phrases and triplets start on beats, so this stops on a beat
+        let mut held_beats = 0.0;
+        while let Some(note) = slots.pop() {
+            held_beats += note.beats;
+            if held_beats + 1e-3 >= FINAL_NOTE_MIN_BEATS {
+                break;
+            }
+        }
+        slots.push(PhraseNote {
+            degree: 0,
+            octave,
+            beats: held_beats,
+            rest: false,
+        });
+    } else if let Some(last) = slots.last_mut() {
+        // End on a sounding root or fifth
+        last.rest = false;
+        last.degree = *[0, 4].choose(&mut rng).unwrap();
+        last.octave = octave;
+    }
 
-        let note_with_octave = Note::new(note.pitch_class, note_octave as u8);
-        melody_notes.push(note_with_octave);
+    let mut events: Vec<MelodyEvent> = slots
+        .iter()
+        .map(|slot| MelodyEvent {
+            note: Note::new(scale_notes[slot.degree].pitch_class, slot.octave as u8),
+            duration: slot.beats * seconds_per_quarter_note,
+            dyad: None,
+            rest: slot.rest,
+        })
+        .collect();
+    add_accent_dyads(
+        &mut events,
+        scale_notes,
+        seconds_per_quarter_note,
+        beats_per_bar,
+        dyad_probability,
+        seed,
+    );
+    // Planned from the slots rather than the events, so accent double-stops aren't harmonized
+    let mut harmony_events: Vec<MelodyEvent> = match harmony_steps {
+        Some(steps) => slots
+            .iter()
+            .zip(&events)
+            .map(|(slot, event)| MelodyEvent {
+                note: harmony_note(&event.note, slot.degree, steps, scale_notes),
+                duration: event.duration,
+                dyad: None,
+                rest: slot.rest,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    swing_events(&mut events, seconds_per_quarter_note, swing);
+    swing_events(&mut harmony_events, seconds_per_quarter_note, swing);
+    let mut contour = Vec::with_capacity(events.len());
+    let mut start_seconds = 0.0;
+    for event in &events {
+        if !event.rest {
+            contour.push((start_seconds, note_to_midi_number(&event.note)));
+        }
+        start_seconds += event.duration;
     }
+    let (melody, melody_len) = synthesize_melody(&events, note_shape, sample_limit, seed);
+    let (harmony, _) = synthesize_melody(&harmony_events, note_shape, sample_limit, seed);
+    (melody, harmony, contour, melody_len)
+}
 
-    // Generate the audio samples
+/* harmony_note - Returns the note a harmony voice sings over a melody note.
+ *
+ * The harmony stays in the melody's scale, so its thirds and sixths are major or minor as the
+ * key calls for, and it always sits above the melody note.
+ *
+ * inputs:
+ *     - melody (&Note): The melody note.
+ *     - degree (usize): Index of the melody note in `scale_notes`.
+ *     - steps (usize): Scale degrees above the melody (2 for a third, 5 for a sixth).
+ *     - scale_notes (&[Note]): The melody's scale, ending with the tonic an octave up.
+ *
+ * outputs:
+ *     - Note: The nearest note above the melody note on the harmony's scale degree.
+ */
+fn harmony_note(melody: &Note, degree: usize, steps: usize, scale_notes: &[Note]) -> Note {
+    let degrees = (scale_notes.len() - 1).max(1); // The last note repeats the tonic
+    let pitch = pitch_to_semitone(&scale_notes[(degree + steps) % degrees].pitch_class) as i32;
+    let melody_midi = note_to_midi_number(melody);
+    let mut midi = melody_midi - melody_midi.rem_euclid(12) + pitch;
+    while midi <= melody_midi {
+        midi += 12;
+    }
+    midi_number_to_note(midi)
+}
+
+/* synthesize_melody - Renders planned melody events as audio, in the note shape's waveform.
+ *
+ * Each note rises from silence over the attack, decays to the sustain level and fades back
+ * to silence over the release, which starts where the articulation gap begins or early enough
+ * to end with the note's slot, and never takes more than half of it. So notes never start or
+ * stop on a jump, however short they are. Each note's articulation strays from the shape's by
+ * up to `ARTICULATION_VARIATION`; the gaps fall inside the notes' slots, so the length is kept.
+ * Notes whose slot lasts at least the shape's `vibrato_min_seconds` get its vibrato, its depth
+ * and rate varied a little per note; shorter notes hold their pitch.
+ *
+ * inputs:
+ *     - events (&[MelodyEvent]): The notes to play, in order.
+ *     - note_shape (&NoteShape): Articulation and envelope applied to every note.
+ *     - sample_limit (usize): Stop synthesizing after this many samples (usize::MAX renders everything).
+ *     - seed (u64): Seed for the variation in articulation and vibrato.
+ *
+ * outputs:
+ *     - (Vec<f32>, usize): The samples at SAMPLE_RATE (at most `sample_limit`), and the
+ *       length of the full melody in samples.
+ */
+fn synthesize_melody(
+    events: &[MelodyEvent],
+    note_shape: &NoteShape,
+    sample_limit: usize,
+    seed: u64,
+) -> (Vec<f32>, usize) {
+    const SAMPLE_RATE: f32 = 44100.0;
+    let mut rng = StdRng::seed_from_u64(seed ^ ARTICULATION_SEED_SALT);
+    let mut vibrato_rng = StdRng::seed_from_u64(seed ^ VIBRATO_SEED_SALT);
     let mut all_samples = Vec::new();
+    let total_len: usize = events
+        .iter()
+        .map(|event| (SAMPLE_RATE * event.duration) as usize)
+        .sum();
+
+    for event in events {
+        if all_samples.len() >= sample_limit {
+            break;
+        }
+        let samples_for_note = (SAMPLE_RATE * event.duration) as usize;
+        let articulation = note_shape.articulation + rng.gen_range(-ARTICULATION_VARIATION..=ARTICULATION_VARIATION);
+        let vibrato = Vibrato {
+            depth_cents: note_shape.vibrato.depth_cents
+                * (1.0 + vibrato_rng.gen_range(-VIBRATO_DEPTH_VARIATION..=VIBRATO_DEPTH_VARIATION)),
+            rate_hz: note_shape.vibrato.rate_hz
+                + vibrato_rng.gen_range(-VIBRATO_RATE_VARIATION_HZ..=VIBRATO_RATE_VARIATION_HZ),
+            ..note_shape.vibrato
+        };
+        let vibrato = if event.duration >= note_shape.vibrato_min_seconds { vibrato } else { Vibrato::NONE };
 
-    for (note, duration) in melody_notes.iter().zip(durations.iter()) {
-        let frequency = note_to_frequency(note);
-        let samples_for_note = (SAMPLE_RATE * duration) as usize;
+        // The release begins at the articulation gap, or earlier so it ends with the slot
+        let sound_samples = (samples_for_note as f32 * articulation.clamp(0.0, 1.0)) as usize;
+        let release_samples = ((SAMPLE_RATE * note_shape.release_seconds) as usize).min(samples_for_note / 2);
+        let release_start = sound_samples.min(samples_for_note - release_samples);
+        let note_samples = release_start + release_samples;
+        let gap_samples = samples_for_note - note_samples;
+        let attack_samples = ((SAMPLE_RATE * note_shape.attack_seconds) as usize).min(release_start);
+        let decay_samples = ((SAMPLE_RATE * note_shape.decay_seconds) as usize).min(release_start - attack_samples);
+        let sustain_level = note_shape.sustain_level.clamp(0.0, 1.0);
+        let note_start = all_samples.len();
 
-        // Add a small gap between notes (articulation)
-        let articulation = 1.0; // 85% of the note duration is played
-        let sound_samples = (samples_for_note as f32 * articulation) as usize;
-        let gap_samples = samples_for_note - sound_samples;
+        if event.rest {
+            all_samples.extend(vec![0.0; samples_for_note]);
+            continue;
+        }
 
-        // Generate the sine wave for this note
-        let mut note_signal = dasp_signal::rate(SAMPLE_RATE as f64)
-            .const_hz(frequency as f64)
-            .square()
-            .map(|x| (x * 0.5) as f32); // Half amplitude to prevent distortion
+        // Generate the wave for this note
+        let mut note_signal = oscillator(&event.note, note_shape, vibrato);
 
-        // Add the sound part
-        for _ in 0..sound_samples {
-            all_samples.push(note_signal.next());
+        // Add the sound part, summing in the double-stop partner (at reduced gain) if present
+        match &event.dyad {
+            Some(dyad_note) => {
+                let mut dyad_signal = oscillator(dyad_note, note_shape, vibrato);
+                for _ in 0..note_samples {
+                    let sum = note_signal.next_sample() + dyad_signal.next_sample();
+                    all_samples.push(sum * OSCILLATOR_LEVEL * 0.6);
+                }
+            }
+            None => {
+                for _ in 0..note_samples {
+                    all_samples.push(note_signal.next_sample() * OSCILLATOR_LEVEL);
+                }
+            }
+        }
+
+        // Attack, decay and sustain, then the release from wherever that left the note
+        for (i, sample) in all_samples[note_start..note_start + release_start].iter_mut().enumerate() {
+            *sample *= envelope_level(i, attack_samples, decay_samples, sustain_level);
+        }
+        if release_samples > 0 {
+            let release_level = envelope_level(release_start, attack_samples, decay_samples, sustain_level);
+            for (i, sample) in all_samples[note_start + release_start..].iter_mut().enumerate() {
+                *sample *= release_level * (1.0 - (i + 1) as f32 / release_samples as f32);
+            }
         }
 
         // Add the gap (silence) between notes
         all_samples.extend(vec![0.0; gap_samples]);
     }
 
-    all_samples
+    all_samples.truncate(sample_limit);
+    (all_samples, total_len)
 }
 
 /* get_melody - Generates melody audio samples based on style, root note, and duration.
  *
  * This function acts as a high-level selector for melody generation. It interprets the
- * `style` string to choose appropriate scale, mode, rhythm, and octave parameters,
+ * `style` string to choose appropriate scale, mode, rhythm, and octave parameters (minor keys
+ * use Aeolian where major keys use Ionian, and Dorian for jazz; blues uses the blues scale in
+ * either; ambient plays sparse long notes with slow fades, classical sings legato, folk
+ * moves by step in Mixolydian or Ionian, metal runs fast in natural minor or Phrygian, and reggae
+ * plays a syncopated line with rests). Each style also sets how often its melody rests, from
+ * plenty of space in ambient to barely any in the default line (electronic plays an unbroken
+ * arpeggio instead, see `get_arpeggio`),
+ * adjusts the octave and note shape for very slow or very fast tempos,
  * then calls `generate_melody_samples` to create the audio.
  *
  * inputs:
  *     - style (&str): Musical style string (e.g., "pop", "rock", "jazz", "blues").
  *     - root (u8): MIDI root note of the scale (0-11).
- *     - duration (u32): Total desired duration of the melody in seconds.
- *     - seconds_per_quarter_note (f32): Duration of a single quarter note, derived from BPM.
+ *     - minor (bool): True for a minor key, false for a major one.
+ *     - duration (f32): Total desired duration of the melody in seconds.
+ *     - bpm (u32): The resolved beats per minute of the song.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - beats_per_bar (usize): Beats in a bar of the song's time signature.
+ *     - octave_lift (i8): Octaves to raise the melody by, on top of the style's own octave.
+ *     - final_tonic (bool): True if the melody ends the song, so it closes on a held root.
+ *     - harmony (bool): True to add a harmony voice a third above the melody (a sixth for folk
+ *       and classical).
+ *     - double_stops (bool): False to keep the melody to one pitch at a time, with none of the
+ *       style's accent double-stops (as NES mode's pulse channel plays it).
+ *     - waveform (Waveform): The tone of the melody and its harmony.
+ *     - pulse_width (PulseWidth): The duty of a square melody and harmony.
+ *     - sample_limit (usize): Maximum number of samples to synthesize (usize::MAX for the whole melody).
  *     - seed (u64): Seed for random number generation.
  *
  * outputs:
- *     - Vec<f32>: A vector of f32 audio samples representing the generated melody.
+ *     - (Vec<f32>, Vec<f32>, Contour, usize): The melody and harmony samples (at most
+ *       `sample_limit` each; the harmony is empty unless asked for), the melody's contour (see
+ *       `generate_melody_samples`) and the full melody length in samples.
  */
-pub fn get_melody(style: &str, root: u8, duration: u32, seconds_per_quarter_note: f32, seed: u64) -> Vec<f32> {
+#[allow(clippy::too_many_arguments)]
+pub fn get_melody(
+    style: &str,
+    root: u8,
+    minor: bool,
+    duration: f32,
+    bpm: u32,
+    swing: f32,
+    beats_per_bar: usize,
+    octave_lift: i8,
+    final_tonic: bool,
+    harmony: bool,
+    double_stops: bool,
+    waveform: Waveform,
+    pulse_width: PulseWidth,
+    sample_limit: usize,
+    seed: u64,
+) -> (Vec<f32>, Vec<f32>, Contour, usize) {
     let root_pitch = semitone_to_pitch(root);
     let mut rng = StdRng::seed_from_u64(seed); // Changed from ChaCha8Rng. Initialize RNG here for consistent choices
+    let seconds_per_quarter_note = 60.0 / bpm.max(1) as f32; // Guard against dividing by a zero BPM
+    let style = style.to_lowercase(); // Added to_lowercase for consistency with gen.rs
+    let (octave_shift, note_shape) = tempo_adjustment(&style, bpm);
+    let note_shape = NoteShape { waveform, pulse_width, ..note_shape };
+    let octave = 3 + octave_shift + octave_lift; // Middle octave unless the tempo or caller calls for a shift
+    let diatonic_mode = if minor { Mode::Aeolian } else { Mode::Ionian }; // Natural minor or major
+    let harmony_steps = harmony.then_some(match style.as_str() {
+        "folk" | "classical" => HARMONY_SIXTH_STEPS,
+        _ => HARMONY_THIRD_STEPS,
+    });
+    let dyads = |chance: f32| if double_stops { chance } else { 0.0 }; // The style's double-stop chance
 
-    match style.to_lowercase().as_str() { // Added to_lowercase for consistency with gen.rs
+    match style.as_str() {
         "blues" => {
-            // Blues uses pentatonic minor scale typically
+            // Blues uses the blues scale, with its flat third, fifth and seventh, in any key
+            let rhythm = if rng.gen::<bool>() {
+                RhythmPattern::Syncopated
+            } else {
+                RhythmPattern::Triplet
+            };
+
             generate_melody_samples(
-                root_pitch,
-                ScaleType::Diatonic,
-                Mode::Ionian,
-                3,                         // Middle octave
-                RhythmPattern::Syncopated, // Blues has syncopated rhythm
+                &blues_scale_notes(root_pitch),
+                octave, // Middle octave, tempo-adjusted
+                rhythm, // Syncopated phrasing, or triplet figures
                 duration,
                 seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.5),               // Blues leans hard on double-stops
+                true,                     // Occasional leaps for variety
+                0.25,                     // Room between the licks
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
                 seed,
             )
         }
         "pop" => {
-            // Pop often uses major scale
+            // Pop often uses major scale (natural minor in minor keys)
             generate_melody_samples(
-                root_pitch,
-                ScaleType::Diatonic,
-                Mode::Ionian,          // Major scale
-                3,                     // Middle octave
+                &diatonic_scale_notes(root_pitch, diatonic_mode),
+                octave,                // Middle octave, tempo-adjusted
                 RhythmPattern::Medium, // Pop usually has straightforward rhythm
                 duration,
                 seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.35),              // Occasional punch on pop phrase starts
+                true,                     // Occasional leaps for variety
+                0.15,                     // A breath between phrases
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
+                seed,
+            )
+        }
+        "rock" => {
+            // Rock drives a major (or natural minor) melody in straight eighths
+            generate_melody_samples(
+                &diatonic_scale_notes(root_pitch, diatonic_mode),
+                octave,                 // Middle octave, tempo-adjusted
+                RhythmPattern::Driving, // Rock pushes steady eighth notes
+                duration,
+                seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.3),               // Power-chord style punch on phrase starts
+                true,                     // Occasional leaps for variety
+                0.1,                      // Brief gaps in the drive
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
+                seed,
+            )
+        }
+        "metal" => {
+            // Metal shreds in natural minor or Phrygian, whatever the key
+            let metal_mode = if rng.gen::<bool>() { Mode::Phrygian } else { Mode::Aeolian };
+            let rhythm = if rng.gen::<bool>() {
+                RhythmPattern::Complex
+            } else {
+                RhythmPattern::Syncopated
+            };
+
+            generate_melody_samples(
+                &diatonic_scale_notes(root_pitch, metal_mode),
+                octave, // Middle octave, tempo-adjusted
+                rhythm, // Fast runs or off-beat riffs
+                duration,
+                seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.3),               // Power-chord style punch on phrase starts
+                true,                     // Occasional leaps for variety
+                0.1,                      // Brief gaps between the runs
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
+                seed,
+            )
+        }
+        "folk" => {
+            // Folk sings stepwise in Mixolydian or Ionian (Dorian or Aeolian in minor keys)
+            let modal = rng.gen::<bool>();
+            let folk_mode = match (minor, modal) {
+                (false, true) => Mode::Mixolydian,
+                (false, false) => Mode::Ionian,
+                (true, true) => Mode::Dorian,
+                (true, false) => Mode::Aeolian,
+            };
+            let rhythm = if rng.gen::<bool>() {
+                RhythmPattern::Simple
+            } else {
+                RhythmPattern::Medium
+            };
+
+            generate_melody_samples(
+                &diatonic_scale_notes(root_pitch, folk_mode),
+                octave, // Middle octave, tempo-adjusted
+                rhythm, // Plain quarters, or quarters and eighths
+                duration,
+                seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.2),               // Occasional thirds and sixths on phrase starts
+                false,                    // A singable line moves by step
+                0.15,                     // Breaths, as a singer would take
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
+                seed,
+            )
+        }
+        "classical" => {
+            // Classical sings a major (or natural minor) line
+            generate_melody_samples(
+                &diatonic_scale_notes(root_pitch, diatonic_mode),
+                octave,                // Middle octave, tempo-adjusted
+                RhythmPattern::Medium, // Even quarters and eighths over the broken chords
+                duration,
+                seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.2),               // Occasional thirds and sixths on phrase starts
+                true,                     // Occasional leaps for variety
+                0.15,                     // Breaths between phrases
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
+                seed,
+            )
+        }
+        "reggae" => {
+            // Reggae leaves plenty of space around a syncopated major (or natural minor) line
+            generate_melody_samples(
+                &diatonic_scale_notes(root_pitch, diatonic_mode),
+                octave,                    // Middle octave, tempo-adjusted
+                RhythmPattern::Syncopated, // Off-beat phrasing over the skank
+                duration,
+                seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.2),               // Occasional thirds and sixths on phrase starts
+                true,                     // Occasional leaps for variety
+                REGGAE_REST_PROBABILITY,  // Space between the phrases
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
+                seed,
+            )
+        }
+        "ambient" => {
+            // Ambient floats a few long major (or natural minor) notes over the pads
+            generate_melody_samples(
+                &diatonic_scale_notes(root_pitch, diatonic_mode),
+                octave,                // Middle octave, tempo-adjusted
+                RhythmPattern::Sparse, // Long notes with plenty of space
+                duration,
+                seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                0.0,                      // No double-stops, to keep the line soft
+                true,                     // Occasional leaps for variety
+                AMBIENT_REST_PROBABILITY, // Plenty of silence between notes
+                &note_shape,              // Slow swells (see `style_note_shape`)
+                final_tonic,
+                harmony_steps,
+                sample_limit,
                 seed,
             )
         }
         "jazz" => {
-            // Jazz often uses Dorian or Mixolydian scales
-            let jazz_mode = if rng.gen::<bool>() { // Use the seeded rng
+            // Jazz often uses Dorian or Mixolydian scales; minor keys stay Dorian
+            let dorian = rng.gen::<bool>(); // Use the seeded rng (drawn either way, so major songs are unchanged)
+            let jazz_mode = if dorian || minor {
                 Mode::Dorian
             } else {
                 Mode::Mixolydian
             };
 
+            let rhythm = if rng.gen::<bool>() {
+                RhythmPattern::Complex
+            } else {
+                RhythmPattern::Triplet
+            };
+
             generate_melody_samples(
-                root_pitch,
-                ScaleType::Diatonic,
-                jazz_mode,
-                3,                      // Middle octave
-                RhythmPattern::Complex, // Jazz has complex rhythms
+                &diatonic_scale_notes(root_pitch, jazz_mode),
+                octave, // Middle octave, tempo-adjusted
+                rhythm, // Jazz has complex rhythms, or triplet figures
                 duration,
                 seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.25),              // Sparse dyads keep jazz lines light
+                true,                     // Occasional leaps for variety
+                0.2,                      // Room to breathe between the lines
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
                 seed,
             )
         }
         _ => {
-            // Default to major scale
+            // Default to major scale (natural minor in minor keys)
             generate_melody_samples(
-                root_pitch,
-                ScaleType::Diatonic,
-                Mode::Ionian, // Major scale
-                3,            // Middle octave
+                &diatonic_scale_notes(root_pitch, diatonic_mode),
+                octave, // Middle octave, tempo-adjusted
                 RhythmPattern::Simple,
                 duration,
                 seconds_per_quarter_note, // Pass seconds_per_quarter_note
+                swing,                    // Long-short eighths when swung
+                beats_per_bar,            // Phrase accents every 4 bars
+                dyads(0.2),               // Default accent dyad chance
+                true,                     // Occasional leaps for variety
+                0.02,                     // Barely a pause
+                &note_shape,
+                final_tonic,
+                harmony_steps,
+                sample_limit,
                 seed,
             )
         }
     }
 }
+
+/* get_arpeggio - Generates a sixteenth-note arpeggio that follows a chord progression.
+ *
+ * Used in place of a melody by the electronic style. Each chord is spelled by stacking
+ * thirds on its root within the key's natural minor scale, which gives the chords of the
+ * electronic progression, and every chord picks one of `ARPEGGIO_PATTERNS` at random.
+ *
+ * inputs:
+ *     - root (u8): The key's root note (0-11).
+ *     - chord_roots (&[u8]): MIDI-like root of each chord, in order; they repeat if the arpeggio outlasts them.
+ *     - chord_duration (f32): Length of each chord in seconds.
+ *     - duration (f32): Total desired duration in seconds.
+ *     - bpm (u32): The resolved beats per minute of the song.
+ *     - swing (f32): Swing amount, from 0.0 (straight) to 1.0 (triplet feel).
+ *     - octave_lift (i8): Octaves to raise the arpeggio by.
+ *     - final_tonic (bool): True if the arpeggio ends the song, so its last beat holds the root.
+ *     - waveform (Waveform): The tone of the arpeggio.
+ *     - pulse_width (PulseWidth): The duty of a square arpeggio.
+ *     - sample_limit (usize): Maximum number of samples to synthesize (usize::MAX for the whole arpeggio).
+ *     - seed (u64): Seed for random number generation.
+ *
+ * outputs:
+ *     - (Vec<f32>, usize): The samples (at most `sample_limit`) and the full length in samples.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn get_arpeggio(
+    root: u8,
+    chord_roots: &[u8],
+    chord_duration: f32,
+    duration: f32,
+    bpm: u32,
+    swing: f32,
+    octave_lift: i8,
+    final_tonic: bool,
+    waveform: Waveform,
+    pulse_width: PulseWidth,
+    sample_limit: usize,
+    seed: u64,
+) -> (Vec<f32>, usize) {
+    let sixteenth_note = 15.0 / bpm.max(1) as f32;
+    if chord_roots.is_empty() || !duration.is_finite() || duration <= 0.0 || !chord_duration.is_finite() || chord_duration <= 0.0 {
+        return (Vec::new(), 0);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (octave_shift, note_shape) = tempo_adjustment("electronic", bpm);
+    let note_shape = NoteShape { waveform, pulse_width, ..note_shape };
+    let octave = 4 + octave_shift + octave_lift; // An octave above the melody, to sparkle over the chords
+
+    let degrees: Vec<u8> = diatonic_scale_notes(semitone_to_pitch(root), Mode::Aeolian)
+        .iter()
+        .take(7)
+        .map(|note| pitch_to_semitone(&note.pitch_class))
+        .collect();
+    // Root, third and fifth of each chord, as semitones above C
+    let chord_tones: Vec<[u8; 3]> = chord_roots
+        .iter()
+        .map(|&chord_root| match degrees.iter().position(|&d| d == chord_root % 12) {
+            Some(degree) => [0, 2, 4].map(|step| degrees[(degree + step) % 7]),
+            None => [0, 4, 7].map(|interval| (chord_root + interval) % 12), // Major triad
+        })
+        .collect();
+
+    let slots_per_chord = ((chord_duration / sixteenth_note).round() as usize).max(1);
+    let total_slots = ((duration / sixteenth_note).round() as usize).max(1);
+    let mut events = Vec::with_capacity(total_slots);
+    let mut pattern = &ARPEGGIO_PATTERNS[0];
+    for slot in 0..total_slots {
+        let chord_index = slot / slots_per_chord;
+        let step = slot % slots_per_chord;
+        if step == 0 {
+            pattern = ARPEGGIO_PATTERNS.choose(&mut rng).unwrap();
+        }
+        let tones = &chord_tones[chord_index % chord_tones.len()];
+        let tone = pattern[step % pattern.len()];
+        // Tones that wrap past B belong to the next octave
+        let base_midi = (octave as i32 + 1) * 12 + tones[0] as i32;
+        let midi = match tone {
+            3 => base_midi + 12,
+            _ => base_midi + (tones[tone] as i32 - tones[0] as i32).rem_euclid(12),
+        };
+        events.push(MelodyEvent {
+            note: midi_number_to_note(midi),
+            duration: sixteenth_note,
+            dyad: None,
+            rest: false,
+        });
+    }
+    if final_tonic {
+        // The song's last beat holds the root instead of arpeggiating
+        let held_slots = events.len().min(4);
+        events.truncate(events.len() - held_slots);
+        events.push(MelodyEvent {
+            note: midi_number_to_note((octave as i32 + 1) * 12 + (root % 12) as i32),
+            duration: sixteenth_note * held_slots as f32,
+            dyad: None,
+            rest: false,
+        });
+    }
+    swing_events(&mut events, sixteenth_note * 4.0, swing);
+    synthesize_melody(&events, &note_shape, sample_limit, seed)
+}
+
+/* get_counter_melody - Generates a slow second voice that moves against the melody.
+ *
+ * The counter-melody plays half and whole notes, never across a chord change, each on a tone
+ * of the chord sounding under it. When the melody has moved since the counter-melody's last
+ * note, the next note usually (`COUNTER_CONTRARY_CHANCE`) goes the other way, to the nearest
+ * chord tone in that direction; otherwise it takes the nearest chord tone, often holding a
+ * tone the two chords share.
+ *
+ * inputs:
+ *     - root (u8): The key's root note (0-11).
+ *     - chords (&[ChordSpan]): The chords to follow, timed from the start of the melody.
+ *     - lead (&[(f32, i32)]): The melody's contour, as `get_melody` returns it (empty for none).
+ *     - bpm (u32): The resolved beats per minute of the song.
+ *     - octave_lift (i8): Octaves to raise the counter-melody by.
+ *     - final_tonic (bool): True if the counter-melody ends the song, so its last note is the root.
+ *     - sample_limit (usize): Maximum number of samples to synthesize (usize::MAX for the whole counter-melody).
+ *     - seed (u64): Seed for random number generation.
+ *
+ * outputs:
+ *     - Vec<f32>: The samples, at most `sample_limit`.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn get_counter_melody(
+    root: u8,
+    chords: &[ChordSpan],
+    lead: &[(f32, i32)],
+    bpm: u32,
+    octave_lift: i8,
+    final_tonic: bool,
+    sample_limit: usize,
+    seed: u64,
+) -> Vec<f32> {
+    let beat_seconds = 60.0 / bpm.max(1) as f32;
+    let mut rng = StdRng::seed_from_u64(seed ^ COUNTER_SEED_SALT);
+    let tonic = (COUNTER_OCTAVE + octave_lift as i32 + 1) * 12 + (root % 12) as i32;
+    let lowest = tonic + COUNTER_LOWEST_OFFSET;
+    let range = lowest..=lowest + COUNTER_RANGE_SEMITONES;
+    // The melody's pitch at a point in time: its last note starting by then
+    let lead_at = |seconds: f32| {
+        let index = lead.partition_point(|&(start, _)| start <= seconds + 1e-3);
+        index.checked_sub(1).map(|index| lead[index].1)
+    };
+    let nearest = |candidates: &[i32], target: i32| {
+        candidates.iter().copied().min_by_key(|&midi| (midi - target).abs()).unwrap_or(target)
+    };
+
+    let mut events: Vec<MelodyEvent> = Vec::new();
+    let mut previous: Option<(f32, i32)> = None; // Start and pitch of the last note
+    for chord in chords {
+        let candidates: Vec<i32> = range
+            .clone()
+            .filter(|midi| chord.tones.contains(&(midi.rem_euclid(12) as u8)))
+            .collect();
+        let end = chord.start_seconds + chord.duration_seconds;
+        let mut start = chord.start_seconds;
+        while end - start > 1e-3 {
+            // A chord too short for two notes is one note, so nothing is left shorter than a beat
+            let remaining = end - start;
+            let duration = if remaining < 1.5 * COUNTER_NOTE_BEATS[0] * beat_seconds {
+                remaining
+            } else {
+                (COUNTER_NOTE_BEATS.choose(&mut rng).unwrap() * beat_seconds).min(remaining)
+            };
+            let midi = match previous {
+                None => nearest(&candidates, tonic),
+                Some((previous_start, previous_midi)) => {
+                    let lead_motion = match (lead_at(previous_start), lead_at(start)) {
+                        (Some(from), Some(to)) => to - from,
+                        _ => 0,
+                    };
+                    let contrary = match lead_motion.signum() {
+                        1 => candidates.iter().copied().filter(|&midi| midi < previous_midi).max(),
+                        -1 => candidates.iter().copied().filter(|&midi| midi > previous_midi).min(),
+                        _ => None,
+                    };
+                    contrary
+                        .filter(|_| rng.gen_bool(COUNTER_CONTRARY_CHANCE))
+                        .unwrap_or_else(|| nearest(&candidates, previous_midi))
+                }
+            };
+            events.push(MelodyEvent {
+                note: midi_number_to_note(midi),
+                duration,
+                dyad: None,
+                rest: false,
+            });
+            previous = Some((start, midi));
+            start += duration;
+        }
+    }
+    if let (true, Some(last)) = (final_tonic, events.last_mut()) {
+        // The song's last note settles on the nearest root
+        let roots: Vec<i32> = range.filter(|midi| midi.rem_euclid(12) == (root % 12) as i32).collect();
+        last.note = midi_number_to_note(nearest(&roots, note_to_midi_number(&last.note)));
+    }
+    synthesize_melody(&events, &COUNTER_NOTE_SHAPE, sample_limit, seed).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SECONDS_PER_BEAT: Beat length of the test melodies (120 BPM).
+    const SECONDS_PER_BEAT: f32 = 0.5;
+
+    fn test_scales() -> Vec<Vec<Note>> {
+        vec![
+            diatonic_scale_notes(PitchClass::C, Mode::Ionian),
+            diatonic_scale_notes(PitchClass::A, Mode::Aeolian),
+            diatonic_scale_notes(PitchClass::Fs, Mode::Dorian),
+            blues_scale_notes(PitchClass::E),
+        ]
+    }
+
+    // Seeded melody events over random scale degrees, on a rhythm planned like a real melody's
+    fn seeded_events(pattern: &RhythmPattern, beats: usize, scale_notes: &[Note], seed: u64) -> Vec<MelodyEvent> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        plan_rhythm(pattern, beats, &mut rng)
+            .into_iter()
+            .map(|beats| MelodyEvent {
+                note: Note::new(scale_notes[rng.gen_range(0..scale_notes.len())].pitch_class, rng.gen_range(3..6)),
+                duration: beats * SECONDS_PER_BEAT,
+                dyad: None,
+                rest: false,
+            })
+            .collect()
+    }
+
+    // Indexes of the events a phrase accent falls on: the first at or after every 4-bar boundary
+    fn accent_indexes(events: &[MelodyEvent], beats_per_bar: usize) -> Vec<usize> {
+        let period = ACCENT_PERIOD_BARS * beats_per_bar as f32;
+        let mut accents = Vec::new();
+        let mut start = 0.0f32;
+        let mut next_accent = 0.0f32;
+        for (index, event) in events.iter().enumerate() {
+            if start + 1e-3 >= next_accent {
+                accents.push(index);
+                while next_accent <= start + 1e-3 {
+                    next_accent += period;
+                }
+            }
+            start += event.duration / SECONDS_PER_BEAT;
+        }
+        accents
+    }
+
+    #[test]
+    fn dyads_fall_only_on_accents_and_are_consonant_and_in_scale() {
+        let patterns = [RhythmPattern::Medium, RhythmPattern::Complex, RhythmPattern::Syncopated, RhythmPattern::Triplet];
+        let mut dyads = 0;
+        for scale_notes in test_scales() {
+            let in_scale: Vec<u8> = scale_notes.iter().map(|note| pitch_to_semitone(&note.pitch_class)).collect();
+            for pattern in &patterns {
+                for beats_per_bar in [3, 4] {
+                    for seed in 0..20 {
+                        let mut events = seeded_events(pattern, 32 * beats_per_bar, &scale_notes, seed);
+                        add_accent_dyads(&mut events, &scale_notes, SECONDS_PER_BEAT, beats_per_bar, 1.0, seed);
+                        let accents = accent_indexes(&events, beats_per_bar);
+                        for (index, event) in events.iter().enumerate() {
+                            let Some(dyad) = &event.dyad else { continue };
+                            dyads += 1;
+                            assert!(accents.contains(&index), "a dyad off the accents at event {}", index);
+                            let interval = note_to_midi_number(&event.note) - note_to_midi_number(dyad);
+                            assert!(CONSONANT_DYAD_INTERVALS.contains(&interval), "interval {}", interval);
+                            assert!(in_scale.contains(&pitch_to_semitone(&dyad.pitch_class)));
+                            assert!(in_scale.contains(&pitch_to_semitone(&event.note.pitch_class)));
+                        }
+                    }
+                }
+            }
+        }
+        assert!(dyads > 100, "only {} dyads were added", dyads);
+    }
+
+    #[test]
+    fn dyads_depend_only_on_the_seed_and_probability() {
+        let scale_notes = diatonic_scale_notes(PitchClass::G, Mode::Ionian);
+        let dyads_of = |seed: u64, probability: f32| -> Vec<Option<i32>> {
+            let mut events = seeded_events(&RhythmPattern::Medium, 64, &scale_notes, 3);
+            add_accent_dyads(&mut events, &scale_notes, SECONDS_PER_BEAT, 4, probability, seed);
+            events.iter().map(|event| event.dyad.as_ref().map(note_to_midi_number)).collect()
+        };
+        assert_eq!(dyads_of(11, 0.5), dyads_of(11, 0.5));
+        // The seed picks which accents get one
+        assert_ne!(dyads_of(11, 0.5), dyads_of(12, 0.5));
+        assert!(dyads_of(11, 1.0).iter().filter(|dyad| dyad.is_some()).count() > dyads_of(11, 0.5).iter().filter(|dyad| dyad.is_some()).count());
+        assert!(dyads_of(11, 0.0).iter().all(Option::is_none));
+    }
+
+    // TEMPO_STYLES: Styles whose melodies move with the tempo (electronic plays an arpeggio instead).
+    const TEMPO_STYLES: [&str; 8] = ["pop", "rock", "jazz", "blues", "classical", "folk", "metal", "reggae"];
+
+    fn median_midi(style: &str, bpm: u32, seed: u64) -> i32 {
+        // The same number of beats at either tempo
+        let duration = 64.0 * 60.0 / bpm as f32;
+        let (_, _, contour, _) = get_melody(
+            style,
+            0,
+            false,
+            duration,
+            bpm,
+            0.0,
+            4,
+            0,
+            false,
+            false,
+            true,
+            Waveform::Square,
+            PulseWidth::EVEN,
+            0, // Only the contour is needed
+            seed,
+        );
+        let mut notes: Vec<i32> = contour.into_iter().map(|(_, note)| note).collect();
+        notes.sort_unstable();
+        notes[notes.len() / 2]
+    }
+
+    #[test]
+    fn a_slow_melody_sits_about_an_octave_below_a_fast_one() {
+        for style in TEMPO_STYLES {
+            for seed in 0..5 {
+                let drop = median_midi(style, 170, seed) - median_midi(style, 60, seed);
+                assert!((9..=15).contains(&drop), "{} seed {} dropped {} semitones", style, seed, drop);
+            }
+        }
+    }
+
+    #[test]
+    fn tempo_adjustment_only_changes_very_slow_and_very_fast_songs() {
+        for style in TEMPO_STYLES {
+            let (slow_bpm, fast_bpm) = tempo_bpm_thresholds(style);
+            let base = style_note_shape(style);
+
+            let (shift, shape) = tempo_adjustment(style, slow_bpm - 1);
+            assert_eq!(shift, -1, "{}", style);
+            assert!(shape.release_seconds >= SLOW_TEMPO_RELEASE_SECONDS);
+            assert_eq!(shape.articulation, base.articulation);
+
+            for bpm in [slow_bpm, fast_bpm] {
+                let (shift, shape) = tempo_adjustment(style, bpm);
+                assert_eq!(shift, 0, "{} at {}", style, bpm);
+                assert_eq!((shape.articulation, shape.release_seconds), (base.articulation, base.release_seconds));
+            }
+
+            let (shift, shape) = tempo_adjustment(style, fast_bpm + 1);
+            assert_eq!(shift, 0, "{}", style);
+            assert!(shape.articulation <= FAST_TEMPO_ARTICULATION);
+            assert_eq!(shape.release_seconds, base.release_seconds);
+        }
+    }
+
+    // TRIPLET_RHYTHMS: The rhythmic feels that play triplet figures.
+    const TRIPLET_RHYTHMS: [RhythmPattern; 3] = [RhythmPattern::Complex, RhythmPattern::Syncopated, RhythmPattern::Triplet];
+
+    // Splits a rhythm into its triplet figures, checking each starts on a beat and is whole
+    fn triplet_figures(rhythm: &[f32]) -> Vec<&'static [f32]> {
+        let mut figures = Vec::new();
+        let mut position = 0.0f64;
+        let mut index = 0;
+        while index < rhythm.len() {
+            if !is_triplet_duration(rhythm[index] as f64) {
+                position += rhythm[index] as f64;
+                index += 1;
+                continue;
+            }
+            assert!((position - position.round()).abs() < 1e-4, "a triplet starts off the beat, at beat {}", position);
+            let figure = TRIPLET_GROUPS
+                .into_iter()
+                .find(|group| group.first() == rhythm.get(index))
+                .unwrap_or_else(|| panic!("{} beats doesn't start a triplet figure", rhythm[index]));
+            assert_eq!(rhythm.get(index..index + figure.len()), Some(figure), "a broken triplet at note {}", index);
+            figures.push(figure);
+            position += 1.0;
+            index += figure.len();
+        }
+        figures
+    }
+
+    #[test]
+    fn every_rhythm_fills_its_phrase_exactly() {
+        let patterns = [
+            RhythmPattern::Simple,
+            RhythmPattern::Medium,
+            RhythmPattern::Complex,
+            RhythmPattern::Syncopated,
+            RhythmPattern::Driving,
+            RhythmPattern::Sparse,
+            RhythmPattern::Triplet,
+        ];
+        for pattern in &patterns {
+            for beats in [3, 4, 6, 16, 96] {
+                for seed in 0..50 {
+                    let rhythm = plan_rhythm(pattern, beats, &mut StdRng::seed_from_u64(seed));
+                    let total: f64 = rhythm.iter().map(|&note| note as f64).sum();
+                    assert!((total - beats as f64).abs() < 1e-4, "{:?} over {} beats lasts {}", pattern, beats, total);
+                    assert!(rhythm.iter().all(|&note| note > 0.0), "{:?} has an empty note", pattern);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn triplets_come_in_whole_figures_that_start_on_the_beat() {
+        for pattern in &TRIPLET_RHYTHMS {
+            let mut figures = 0;
+            for seed in 0..50 {
+                let rhythm = plan_rhythm(pattern, 32, &mut StdRng::seed_from_u64(seed));
+                figures += triplet_figures(&rhythm).len();
+            }
+            assert!(figures > 20, "{:?} played only {} triplet figures", pattern, figures);
+        }
+        for pattern in [RhythmPattern::Simple, RhythmPattern::Medium, RhythmPattern::Driving, RhythmPattern::Sparse] {
+            let rhythm = plan_rhythm(&pattern, 32, &mut StdRng::seed_from_u64(1));
+            assert!(triplet_figures(&rhythm).is_empty(), "{:?} played a triplet", pattern);
+        }
+    }
+
+    #[test]
+    fn swing_leaves_triplets_and_the_song_length_alone() {
+        let scale_notes = diatonic_scale_notes(PitchClass::D, Mode::Dorian);
+        for pattern in &TRIPLET_RHYTHMS {
+            for seed in 0..20 {
+                let mut swung = seeded_events(pattern, 32, &scale_notes, seed);
+                let straight: Vec<f32> = swung.iter().map(|event| event.duration).collect();
+                swing_events(&mut swung, SECONDS_PER_BEAT, 0.6);
+                for (before, after) in straight.iter().zip(&swung) {
+                    if is_triplet_duration((before / SECONDS_PER_BEAT) as f64) {
+                        assert!((before - after.duration).abs() < 1e-5, "a triplet swung from {} to {}", before, after.duration);
+                    }
+                }
+                let total: f32 = swung.iter().map(|event| event.duration).sum();
+                assert!((total - 32.0 * SECONDS_PER_BEAT).abs() < 1e-3, "{:?} swung lasts {}", pattern, total);
+            }
+        }
+    }
+
+    // The largest jump between neighbouring samples of a melody
+    fn max_delta(samples: &[f32]) -> f32 {
+        samples.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f32::max)
+    }
+
+    // The sample every note of the events starts on, and the end of the last
+    fn note_boundaries(events: &[MelodyEvent]) -> Vec<usize> {
+        let mut boundaries = vec![0];
+        for event in events {
+            boundaries.push(boundaries[boundaries.len() - 1] + (44100.0 * event.duration) as usize);
+        }
+        boundaries
+    }
+
+    #[test]
+    fn every_style_starts_and_ends_its_notes_in_silence() {
+        let scale_notes = diatonic_scale_notes(PitchClass::C, Mode::Ionian);
+        for style in TEMPO_STYLES.iter().chain(&["ambient", "electronic"]) {
+            for bpm in [50, 120, 200] {
+                let (_, note_shape) = tempo_adjustment(style, bpm);
+                for seed in 0..4 {
+                    let events = seeded_events(&RhythmPattern::Complex, 16, &scale_notes, seed);
+                    let (samples, _) = synthesize_melody(&events, &note_shape, usize::MAX, seed);
+                    for &boundary in note_boundaries(&events).iter().skip(1) {
+                        let before = samples[boundary - 1];
+                        let after = samples.get(boundary).copied().unwrap_or(0.0);
+                        assert!(
+                            before.abs() < 0.01 && after.abs() < 0.01,
+                            "{} at {} BPM jumps from {} to {} at sample {}",
+                            style,
+                            bpm,
+                            before,
+                            after,
+                            boundary
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn enveloped_notes_change_no_faster_than_their_wave() {
+        let scale_notes = diatonic_scale_notes(PitchClass::A, Mode::Aeolian);
+        // A triangle's steepest slope at the highest test note (B5), with room for vibrato, plus
+        // the steepest attack of any style
+        let highest = note_to_frequency(&Note::new(PitchClass::B, 5));
+        let wave_delta = 4.0 * OSCILLATOR_LEVEL * highest * 1.05 / 44100.0;
+        let attack_delta = OSCILLATOR_LEVEL / (44100.0 * POP_NOTE_SHAPE.attack_seconds);
+        let limit = wave_delta + attack_delta;
+        for style in TEMPO_STYLES.iter().chain(&["ambient", "electronic"]) {
+            let (_, note_shape) = tempo_adjustment(style, 120);
+            let note_shape = NoteShape { waveform: Waveform::Triangle, ..note_shape };
+            for seed in 0..4 {
+                let events = seeded_events(&RhythmPattern::Syncopated, 16, &scale_notes, seed);
+                let (samples, _) = synthesize_melody(&events, &note_shape, usize::MAX, seed);
+                let delta = max_delta(&samples);
+                assert!(delta < limit, "{} jumps by {} (limit {})", style, delta, limit);
+            }
+        }
+        // Chopped without an envelope, the same notes click well past the limit
+        let chopped = NoteShape {
+            attack_seconds: 0.0,
+            decay_seconds: 0.0,
+            release_seconds: 0.0,
+            waveform: Waveform::Triangle,
+            ..NoteShape::default()
+        };
+        let events = seeded_events(&RhythmPattern::Syncopated, 16, &scale_notes, 0);
+        let (samples, _) = synthesize_melody(&events, &chopped, usize::MAX, 0);
+        assert!(max_delta(&samples) > 2.0 * limit, "the unshaped notes jump by only {}", max_delta(&samples));
+    }
+}
@@ -1,6 +1,7 @@
+use crate::synth::{self, Oscillator, PulseWidth, Waveform};
+use crate::workers;
 use rust_music_theory::note::{Note, Notes, PitchClass};
 
-use dasp_signal::Signal;
 use rust_music_theory::chord::{Chord, Number as ChordNumber, Quality as ChordQuality};
 
 /* PitchClassExt - Extension trait for `rust_music_theory::note::PitchClass`.
@@ -118,9 +119,10 @@ fn note_to_frequency(note: &Note) -> f32 {
 
 /* generate_chord_samples - Generates audio samples for a given chord.
  *
- * This function creates the sound data for a chord by synthesizing sine waves for each note
- * in the chord and summing them. The amplitude of each sine wave is initially set to 0.4
- * before summing and averaging to prevent clipping.
+ * This function creates the sound data for a chord by synthesizing a wave for each note
+ * in the chord and summing them. The amplitude of each wave is initially set to 0.4
+ * before summing and averaging to prevent clipping. With an Alberti pattern the notes are
+ * played one after another instead (see `broken_chord_tones`).
  *
  * inputs:
  *     - root_note (PitchClass): The root pitch class of the chord (e.g., C, G#, Bb).
@@ -128,16 +130,23 @@ fn note_to_frequency(note: &Note) -> f32 {
  *     - chord_type (ChordNumber): The type of chord (e.g., Triad, Seventh, Ninth).
  *     - duration_seconds (f32): The desired duration of the chord in seconds.
  *     - sample_rate (u32): The audio sample rate in samples per second (e.g., 44100 Hz).
+ *     - envelope (ChordEnvelope): Fade-in and fade-out applied to the chord.
+ *     - pattern (ChordPattern): Whether the notes sound together or are broken up.
+ *     - waveform (Waveform): The tone of every note.
  *
  * outputs:
  *     - Vec<f32>: A vector of f32 audio samples representing the chord.
  */
+#[allow(clippy::too_many_arguments)]
 pub fn generate_chord_samples(
     root_note: PitchClass,
     chord_quality: ChordQuality,
     chord_type: ChordNumber,
     duration_seconds: f32,
     sample_rate: u32,
+    envelope: ChordEnvelope,
+    pattern: ChordPattern,
+    waveform: Waveform,
 ) -> Vec<f32> {
     // Create a chord object using the music theory library
     let chord = Chord::new(root_note, chord_quality, chord_type);
@@ -148,15 +157,269 @@ pub fn generate_chord_samples(
     // Calculate the frequency for each note in the chord
     let note_frequencies: Vec<f32> = chord_notes.iter().map(note_to_frequency).collect();
 
-    // Generate sine wave signals for each frequency
-    let mut note_generators: Vec<_> = note_frequencies
-        .iter()
-        .map(|&freq| {
-            dasp_signal::rate(sample_rate as f64)
-                .const_hz(freq as f64)
-                .sine()
-                .map(|x| (x * 0.4) as f32) // Increased initial amplitude to 0.4
+    render_chord(&note_frequencies, duration_seconds, sample_rate, envelope, pattern, waveform)
+}
+
+/* generate_power_chord_samples - Generates audio samples for a power chord (root and fifth, no third).
+ *
+ * inputs:
+ *     - root_note (PitchClass): The root pitch class of the chord.
+ *     - octave (u8): The octave of the root (4 matches `Chord::notes`).
+ *     - duration_seconds (f32): The desired duration of the chord in seconds.
+ *     - sample_rate (u32): The audio sample rate in samples per second.
+ *     - envelope (ChordEnvelope): Fade-in and fade-out applied to the chord.
+ *     - pattern (ChordPattern): Whether the notes sound together or are broken up.
+ *     - waveform (Waveform): The tone of every note.
+ *
+ * outputs:
+ *     - Vec<f32>: A vector of f32 audio samples representing the chord.
+ */
+fn generate_power_chord_samples(
+    root_note: PitchClass,
+    octave: u8,
+    duration_seconds: f32,
+    sample_rate: u32,
+    envelope: ChordEnvelope,
+    pattern: ChordPattern,
+    waveform: Waveform,
+) -> Vec<f32> {
+    // The fifth sits above the root
+    let root = Note::new(root_note, octave);
+    let root_frequency = note_to_frequency(&root);
+    let fifth_frequency = root_frequency * 2f32.powf(7.0 / 12.0);
+    render_chord(&[root_frequency, fifth_frequency], duration_seconds, sample_rate, envelope, pattern, waveform)
+}
+
+/* render_chord - Synthesizes a chord in the given pattern and applies its envelope.
+ *
+ * The envelope's fades are linear and each is cut to `MAX_CHORD_FADE_FRACTION` of the chord.
+ *
+ * inputs:
+ *     - note_frequencies (&[f32]): The frequency of each chord note, in Hz, root first.
+ *     - duration_seconds (f32): The desired duration of the chord in seconds.
+ *     - sample_rate (u32): The audio sample rate in samples per second.
+ *     - envelope (ChordEnvelope): Fade-in and fade-out applied to the chord.
+ *     - pattern (ChordPattern): Whether the notes sound together or are broken up.
+ *     - waveform (Waveform): The tone of every note.
+ *
+ * outputs:
+ *     - Vec<f32>: The chord samples.
+ */
+fn render_chord(
+    note_frequencies: &[f32],
+    duration_seconds: f32,
+    sample_rate: u32,
+    envelope: ChordEnvelope,
+    pattern: ChordPattern,
+    waveform: Waveform,
+) -> Vec<f32> {
+    let mut chord_samples = match pattern {
+        ChordPattern::Block => sum_chord_tones(note_frequencies, duration_seconds, waveform, sample_rate),
+        ChordPattern::Alberti { step_seconds } => broken_chord_tones(
+            note_frequencies,
+            &ALBERTI_ORDER,
+            duration_seconds,
+            step_seconds,
+            waveform,
+            sample_rate,
+        ),
+        ChordPattern::Waltz {
+            beat_seconds,
+            time_signature,
+        } => {
+            // Every beat but the first of each group: "oom-pah-pah"
+            let group = time_signature.beats_per_group();
+            let hit_steps: Vec<usize> = (1..group).collect();
+            let mut samples = sum_chord_tones(note_frequencies, duration_seconds, waveform, sample_rate);
+            gate_chord_stabs(&mut samples, beat_seconds, group, &hit_steps, sample_rate);
+            samples
+        }
+        ChordPattern::Skank { beat_seconds } => {
+            // On an eighth-note grid, only the second eighth of each beat is played
+            let mut samples = sum_chord_tones(note_frequencies, duration_seconds, waveform, sample_rate);
+            gate_chord_stabs(&mut samples, beat_seconds / 2.0, 2, &[1], sample_rate);
+            samples
+        }
+        ChordPattern::ChipArp { rate_hz, pulse_width } => {
+            chip_arp_tones(note_frequencies, duration_seconds, rate_hz, waveform, pulse_width, sample_rate)
+        }
+    };
+
+    let total_samples = chord_samples.len();
+    let max_fade_samples = (total_samples as f32 * MAX_CHORD_FADE_FRACTION) as usize;
+    let fade_samples = |seconds: f32| ((sample_rate as f32 * seconds.max(0.0)) as usize).min(max_fade_samples);
+    let attack_samples = fade_samples(envelope.attack_seconds);
+    let release_samples = fade_samples(envelope.release_seconds);
+    for (i, sample) in chord_samples.iter_mut().enumerate() {
+        let mut level = 1.0;
+        if i < attack_samples {
+            level = i as f32 / attack_samples as f32;
+        }
+        if total_samples - i <= release_samples {
+            level = level.min((total_samples - i - 1) as f32 / release_samples as f32);
+        }
+        *sample *= level;
+    }
+
+    chord_samples
+}
+
+/* broken_chord_tones - Plays the notes of a chord one at a time, repeating a pattern.
+ *
+ * Each step is a wave at 0.4, the same peak as a block chord, with short fades at both ends.
+ *
+ * inputs:
+ *     - note_frequencies (&[f32]): The frequency of each chord note, in Hz, root first.
+ *     - order (&[usize]): Indices into `note_frequencies` to cycle through; indices past the
+ *       last note play the last note.
+ *     - duration_seconds (f32): The desired duration of the chord in seconds.
+ *     - step_seconds (f32): The length of each note of the pattern.
+ *     - waveform (Waveform): The tone of every note.
+ *     - sample_rate (u32): The audio sample rate in samples per second.
+ *
+ * outputs:
+ *     - Vec<f32>: The chord samples.
+ */
+fn broken_chord_tones(
+    note_frequencies: &[f32],
+    order: &[usize],
+    duration_seconds: f32,
+    step_seconds: f32,
+    waveform: Waveform,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let total_samples = (sample_rate as f32 * duration_seconds) as usize;
+    if note_frequencies.is_empty() || order.is_empty() {
+        return vec![0.0; total_samples];
+    }
+    let step_samples = ((sample_rate as f32 * step_seconds) as usize).max(1);
+    let fade_samples = ((sample_rate as f32 * BROKEN_CHORD_FADE_SECONDS) as usize).min(step_samples / 2);
+    let mut chord_samples = Vec::with_capacity(total_samples);
+
+    for &index in order.iter().cycle() {
+        if chord_samples.len() >= total_samples {
+            break;
+        }
+        let frequency = note_frequencies[index.min(note_frequencies.len() - 1)];
+        let mut oscillator = Oscillator::new(waveform, frequency as f64, sample_rate as f64);
+        let length = step_samples.min(total_samples - chord_samples.len());
+        for i in 0..length {
+            let level = if fade_samples > 0 {
+                let attack = i as f32 / fade_samples as f32;
+                let release = (length - i - 1) as f32 / fade_samples as f32;
+                attack.min(release).min(1.0)
+            } else {
+                1.0
+            };
+            chord_samples.push(oscillator.next_sample() * 0.4 * level);
+        }
+    }
+
+    chord_samples
+}
+
+/* chip_arp_tones - Fakes a chord on a single voice by cycling through its notes very fast.
+ *
+ * The notes follow each other from the root up, `rate_hz` of them a second, on one oscillator
+ * whose phase carries over from note to note, as on a single channel of a sound chip.
+ *
+ * inputs:
+ *     - note_frequencies (&[f32]): The frequency of each chord note, in Hz, root first.
+ *     - duration_seconds (f32): The desired duration of the chord in seconds.
+ *     - rate_hz (f32): How many notes are played per second.
+ *     - waveform (Waveform): The tone of the voice.
+ *     - pulse_width (PulseWidth): The duty of a square voice; a sweep runs through the whole chord.
+ *     - sample_rate (u32): The audio sample rate in samples per second.
+ *
+ * outputs:
+ *     - Vec<f32>: The chord samples.
+ */
+fn chip_arp_tones(
+    note_frequencies: &[f32],
+    duration_seconds: f32,
+    rate_hz: f32,
+    waveform: Waveform,
+    pulse_width: PulseWidth,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let total_samples = (sample_rate as f32 * duration_seconds) as usize;
+    if note_frequencies.is_empty() {
+        return vec![0.0; total_samples];
+    }
+    let step_samples = ((sample_rate as f32 / rate_hz.max(1.0)) as usize).max(1);
+    let level = match waveform {
+        Waveform::Square => CHIP_ARP_SQUARE_LEVEL,
+        _ => 0.4, // The same peak as a block chord
+    };
+    let mut phase = 0.0f64;
+    (0..total_samples)
+        .map(|i| {
+            let frequency = note_frequencies[(i / step_samples) % note_frequencies.len()];
+            phase += frequency as f64 / sample_rate as f64;
+            let duty = pulse_width.duty_at(i as f64 / sample_rate as f64);
+            synth::pulse_wave(waveform, phase, duty) * level
         })
+        .collect()
+}
+
+/* gate_chord_stabs - Cuts a sustained chord down to short stabs on a rhythmic grid.
+ *
+ * The chord is split into grid steps, counted in cycles of `steps_per_cycle` from the start of
+ * the chord. Steps listed in `hit_steps` keep a short stab (`CHORD_STAB_FRACTION` of the step)
+ * with clickless edges; all other steps are silenced.
+ *
+ * inputs:
+ *     - chord_samples (&mut [f32]): The sustained chord, starting on a downbeat.
+ *     - step_seconds (f32): The length of a grid step.
+ *     - steps_per_cycle (usize): The number of steps before the pattern repeats.
+ *     - hit_steps (&[usize]): The steps of each cycle that get a stab (0 = the first).
+ *     - sample_rate (u32): The audio sample rate in samples per second.
+ *
+ * outputs:
+ *     - None
+ */
+fn gate_chord_stabs(
+    chord_samples: &mut [f32],
+    step_seconds: f32,
+    steps_per_cycle: usize,
+    hit_steps: &[usize],
+    sample_rate: u32,
+) {
+    let step_samples = ((sample_rate as f32 * step_seconds) as usize).max(1);
+    let stab_samples = ((step_samples as f32 * CHORD_STAB_FRACTION) as usize).max(1);
+    let fade_samples = ((sample_rate as f32 * BROKEN_CHORD_FADE_SECONDS) as usize).min(stab_samples / 2);
+    for (i, sample) in chord_samples.iter_mut().enumerate() {
+        let step = i / step_samples;
+        let position = i % step_samples;
+        let level = if !hit_steps.contains(&(step % steps_per_cycle.max(1))) || position >= stab_samples {
+            0.0
+        } else if fade_samples > 0 {
+            let attack = position as f32 / fade_samples as f32;
+            let release = (stab_samples - position - 1) as f32 / fade_samples as f32;
+            attack.min(release).min(1.0)
+        } else {
+            1.0
+        };
+        *sample *= level;
+    }
+}
+
+/* sum_chord_tones - Mixes waves for the notes of a chord.
+ *
+ * inputs:
+ *     - note_frequencies (&[f32]): The frequency of each chord note, in Hz.
+ *     - duration_seconds (f32): The desired duration of the chord in seconds.
+ *     - waveform (Waveform): The tone of every note.
+ *     - sample_rate (u32): The audio sample rate in samples per second.
+ *
+ * outputs:
+ *     - Vec<f32>: The averaged samples.
+ */
+fn sum_chord_tones(note_frequencies: &[f32], duration_seconds: f32, waveform: Waveform, sample_rate: u32) -> Vec<f32> {
+    // An oscillator for each frequency
+    let mut note_generators: Vec<Oscillator> = note_frequencies
+        .iter()
+        .map(|&freq| Oscillator::new(waveform, freq as f64, sample_rate as f64))
         .collect();
 
     // Calculate the total number of samples needed
@@ -165,8 +428,8 @@ pub fn generate_chord_samples(
 
     // Combine samples from all notes to create the chord sound
     for _ in 0..total_samples {
-        // Sum all the sine waves together
-        let sample_sum: f32 = note_generators.iter_mut().map(|sine| sine.next()).sum();
+        // Sum all the waves together, each at 0.4
+        let sample_sum: f32 = note_generators.iter_mut().map(|oscillator| oscillator.next_sample() * 0.4).sum();
 
         // Average the samples to avoid clipping
         chord_samples.push(sample_sum / note_frequencies.len() as f32);
@@ -189,10 +452,278 @@ pub fn get_pitch(root: u8) -> PitchClass {
     PitchClass::from_numeric(root)
 }
 
+// MAX_CHORD_FADE_FRACTION: Longest fade-in or fade-out, as a fraction of the chord, so every chord
+// still reaches full level between its fades.
+const MAX_CHORD_FADE_FRACTION: f32 = 0.4;
+
+/* ChordEnvelope - Fade-in and fade-out applied to every chord of a progression.
+ *
+ * The default has no fades, so chords start and stop at full level.
+ *
+ * fields:
+ *     - attack_seconds (f32): Length of the fade-in at the start of each chord.
+ *     - release_seconds (f32): Length of the fade-out at the end of each chord.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChordEnvelope {
+    pub attack_seconds: f32,
+    pub release_seconds: f32,
+}
+
+// ALBERTI_ORDER: The Alberti pattern as indices into the chord's notes: root, fifth, third, fifth.
+const ALBERTI_ORDER: [usize; 4] = [0, 2, 1, 2];
+
+// CHIP_ARP_SQUARE_LEVEL: Peak of a square-wave chip arpeggio, below a block chord's 0.4 as a
+// square sounds much louder than a sine at the same peak.
+const CHIP_ARP_SQUARE_LEVEL: f32 = 0.25;
+
+// BROKEN_CHORD_FADE_SECONDS: Fade at both ends of each broken-chord note, so the steps don't click.
+const BROKEN_CHORD_FADE_SECONDS: f32 = 0.005;
+
+/* TimeSignature - How the beats of a song are grouped into bars.
+ *
+ * The BPM always counts the beats of the bar, so in 6/8 it counts eighth notes, which are
+ * grouped in two threes.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeSignature {
+    #[default]
+    FourFour,  // Four beats to the bar.
+    ThreeFour, // Three beats to the bar, as in a waltz.
+    SixEight,  // Six eighth-note beats to the bar, felt as two groups of three.
+}
+
+impl TimeSignature {
+    // ALL: Every time signature, in the order the TUI offers them.
+    pub const ALL: [TimeSignature; 3] = [TimeSignature::FourFour, TimeSignature::ThreeFour, TimeSignature::SixEight];
+
+    /* beats_per_bar - Returns the number of beats in a bar.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - usize: 4, 3 or 6.
+     */
+    pub fn beats_per_bar(self) -> usize {
+        match self {
+            TimeSignature::FourFour => 4,
+            TimeSignature::ThreeFour => 3,
+            TimeSignature::SixEight => 6,
+        }
+    }
+
+    /* beats_per_group - Returns the number of beats between strong beats.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - usize: The whole bar, except in 6/8, where each half of the bar is a group.
+     */
+    pub fn beats_per_group(self) -> usize {
+        match self {
+            TimeSignature::SixEight => 3,
+            _ => self.beats_per_bar(),
+        }
+    }
+
+    /* label - Returns the time signature as it is written, e.g. "3/4".
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - &'static str: The label.
+     */
+    pub fn label(self) -> &'static str {
+        match self {
+            TimeSignature::FourFour => "4/4",
+            TimeSignature::ThreeFour => "3/4",
+            TimeSignature::SixEight => "6/8",
+        }
+    }
+
+    /* from_label - Parses a label written by `label`.
+     *
+     * inputs:
+     *     - label (&str): The label, e.g. "6/8".
+     *
+     * outputs:
+     *     - Option<TimeSignature>: The time signature, or None if the label is unknown.
+     */
+    pub fn from_label(label: &str) -> Option<TimeSignature> {
+        TimeSignature::ALL.into_iter().find(|signature| signature.label() == label.trim())
+    }
+}
+
+// CHORD_STAB_FRACTION: Part of its grid step that a chord stab sounds for (see `gate_chord_stabs`).
+const CHORD_STAB_FRACTION: f32 = 0.5;
+
+/* ChordPattern - How the notes of each chord are played.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ChordPattern {
+    #[default]
+    Block,                         // All notes sound together for the whole chord.
+    Alberti { step_seconds: f32 }, // One note at a time (see `ALBERTI_ORDER`), each step this long.
+    // Short stabs on every beat but the first of each group (beats 2 and 3 in 3/4).
+    Waltz {
+        beat_seconds: f32,
+        time_signature: TimeSignature,
+    },
+    Skank { beat_seconds: f32 },   // Short stabs on the "and" of every beat, downbeats left out.
+    // One voice cycling through the notes, `rate_hz` a second, like a sound chip (see `chip_arp_tones`).
+    ChipArp { rate_hz: f32, pulse_width: PulseWidth },
+}
+
+// ChordTones: A chord's pitch classes as semitones above C (0-11), root first.
+pub type ChordTones = Vec<u8>;
+
+/* ChordVoicing - How a chord's notes are picked.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChordVoicing {
+    // The notes of a chord of this quality and number.
+    Full(ChordQuality, ChordNumber),
+    // Root and fifth only, in this octave.
+    Power { octave: u8 },
+}
+
+/* ChordKey - Everything that tells one chord's samples apart from another's in the same
+ * progression, whose chords share their envelope, pattern and waveform.
+ *
+ * fields:
+ *     - root (u8): The chord root as a pitch class (0-11).
+ *     - voicing (ChordVoicing): Which notes of the root are played.
+ *     - duration (f32): The chord's length in seconds.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChordKey {
+    root: u8,
+    voicing: ChordVoicing,
+    duration: f32,
+}
+
+// ChordRender: Renders one chord's samples, keyed by what the samples depend on; see `render_chords`.
+type ChordRender = (ChordKey, Box<dyn FnOnce() -> Vec<f32> + Send>);
+
+/* render_chords - Renders the chords of a progression, forked across the worker pool.
+ *
+ * A chord that repeats (IV comes round twice in the blues) is only rendered the first time,
+ * and copied wherever it comes back. The chords don't depend on each other (nothing random
+ * goes into them), so rendering them at once gives the same samples as one after another.
+ * Chords no free worker picks up are rendered on the calling thread (see `WorkerPool::fork`).
+ *
+ * inputs:
+ *     - renders (Vec<ChordRender>): One render per chord, in order.
+ *
+ * outputs:
+ *     - Vec<Vec<f32>>: The samples of every chord, in the same order.
+ */
+fn render_chords(renders: Vec<ChordRender>) -> Vec<Vec<f32>> {
+    let mut keys: Vec<ChordKey> = Vec::with_capacity(renders.len());
+    let mut unique_renders = Vec::with_capacity(renders.len());
+    let order: Vec<usize> = renders
+        .into_iter()
+        .map(|(key, render)| {
+            keys.iter().position(|&rendered| rendered == key).unwrap_or_else(|| {
+                keys.push(key);
+                unique_renders.push(render);
+                keys.len() - 1
+            })
+        })
+        .collect();
+    let pool = workers::global();
+    let forks: Vec<_> = unique_renders.into_iter().map(|render| pool.fork(render)).collect();
+    let rendered: Vec<Vec<f32>> = forks.into_iter().map(|fork| fork.join()).collect();
+    order.into_iter().map(|index| rendered[index].clone()).collect()
+}
+
+/* ChordSpan - One chord of a chord timeline, for parts that follow the harmony.
+ *
+ * fields:
+ *     - start_seconds (f32): When the chord starts, in seconds from the start of the timeline.
+ *     - duration_seconds (f32): How long the chord lasts, in seconds.
+ *     - tones (Vec<u8>): The chord's pitch classes as semitones above C (0-11), root first.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordSpan {
+    pub start_seconds: f32,
+    pub duration_seconds: f32,
+    pub tones: ChordTones,
+}
+
+/* chord_tones - Spells a chord as pitch classes.
+ *
+ * inputs:
+ *     - root (u8): The chord root as a semitone offset from C (wraps around 12).
+ *     - quality (ChordQuality): The quality of the chord.
+ *     - number (ChordNumber): The type of chord (triad, seventh, ...).
+ *
+ * outputs:
+ *     - Vec<u8>: The chord's pitch classes (0-11), root first.
+ */
+fn chord_tones(root: u8, quality: ChordQuality, number: ChordNumber) -> ChordTones {
+    Chord::new(get_pitch(root), quality, number)
+        .notes()
+        .iter()
+        .map(|note| note.pitch_class.to_semitone() as u8)
+        .collect()
+}
+
+/* power_chord_tones - Spells a power chord (root and fifth) as pitch classes.
+ *
+ * inputs:
+ *     - root (u8): The chord root as a semitone offset from C (wraps around 12).
+ *
+ * outputs:
+ *     - Vec<u8>: The root and fifth (0-11).
+ */
+fn power_chord_tones(root: u8) -> ChordTones {
+    vec![root % 12, (root + 7) % 12]
+}
+
+// PITCH_NAMES: Display names of the pitch classes, indexed by semitones above C.
+const PITCH_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/* chord_label - Builds a short display name for a chord, e.g. "G maj", "A min", "Dm7" or "G7".
+ *
+ * inputs:
+ *     - root (u8): The chord root as a semitone offset from C (wraps around 12).
+ *     - quality (&ChordQuality): The quality of the chord.
+ *     - number (&ChordNumber): The type of chord (triad, seventh, ...).
+ *
+ * outputs:
+ *     - String: The chord name.
+ */
+fn chord_label(root: u8, quality: &ChordQuality, number: &ChordNumber) -> String {
+    let name = PITCH_NAMES[(root % 12) as usize];
+    match (number, quality) {
+        (ChordNumber::Triad, ChordQuality::Major) => format!("{} maj", name),
+        (ChordNumber::Triad, ChordQuality::Minor) => format!("{} min", name),
+        (ChordNumber::Triad, ChordQuality::Diminished) => format!("{} dim", name),
+        (ChordNumber::Triad, ChordQuality::Augmented) => format!("{} aug", name),
+        (ChordNumber::Seventh, ChordQuality::Major) => format!("{}maj7", name),
+        (ChordNumber::Seventh, ChordQuality::Minor) => format!("{}m7", name),
+        (ChordNumber::Seventh, ChordQuality::Dominant) => format!("{}7", name),
+        (ChordNumber::Seventh, ChordQuality::Diminished) => format!("{}dim7", name),
+        (ChordNumber::Seventh, ChordQuality::HalfDiminished) => format!("{}m7b5", name),
+        _ => format!("{} {:?} {:?}", name, quality, number),
+    }
+}
+
 /* get_progression - Retrieves a predefined chord progression and its corresponding root notes.
  *
- * This function generates a sequence of chords based on a progression name (e.g., "blues", "pop", "jazz")
- * and a root note. It returns both the audio samples for each chord and a list of the MIDI
+ * This function generates a sequence of chords based on a progression name (e.g., "blues", "pop", "jazz"),
+ * a root note and the key quality; minor keys get the minor counterpart of each progression
+ * (e.g. i-VI-III-VII instead of I-V-vi-IV for pop). Rock is played as power chords (root and
+ * fifth, labelled e.g. "C5"), metal as low power chords on i-bVI-bVII-i, electronic always loops a minor progression, classical
+ * cadences back home through the dominant (I-IV-V-I), and folk sways between the tonic and
+ * the other primary chords (I-IV-I-V). The bank also holds the full twelve-bar blues, the
+ * doo-wop I-vi-IV-V, the Andalusian cadence (always i-bVII-bVI-V), a ii-V-I with a vi
+ * turnaround and the minor i-iv-v; `style_progressions` lists which suit each style. A custom
+ * progression (see `parse_roman_progression`) is played instead of the named one when given. It returns both the audio samples for each chord and a list of the MIDI
  * note numbers for the root of each chord in the progression.
  *
  * The root notes are calculated assuming the input `root` (0-11) is for a specific octave (e.g. C4).
@@ -203,18 +734,39 @@ pub fn get_pitch(root: u8) -> PitchClass {
  *
  * inputs:
  *     - prog_name (String): The name of the desired progression (case-insensitive).
- *     - root (u8): The root note (0-11, e.g., 0 for C, 1 for C#) for the entire progression.
- *     - chord_duration (f32): The duration of each chord in seconds.
+ *     - root (u8): The root note (0-11, e.g., 0 for C, 1 for C#) for the entire progression. Larger values wrap around 12.
+ *     - minor (bool): True for a minor key, false for a major one.
+ *     - chord_duration (f32): The duration of each chord in seconds. Negative or non-finite durations give silent (empty) chords.
+ *     - envelope (ChordEnvelope): Fade-in and fade-out applied to every chord.
+ *     - pattern (ChordPattern): Whether each chord's notes sound together or are broken up.
+ *     - waveform (Waveform): The tone of every chord note.
+ *     - custom (&[RomanChord]): The chords of a custom progression; empty to play `prog_name`.
  *
  * outputs:
- *     - (Vec<Vec<f32>>, Vec<u8>): A tuple containing:
+ *     - (Vec<Vec<f32>>, Vec<u8>, Vec<String>, Vec<ChordTones>): A tuple containing:
  *         - A vector where each inner vector contains the audio samples for a chord in the progression.
  *         - A vector of u8 MIDI note numbers for the root of each chord in the progression.
+ *         - A vector of display names for each chord in the progression (see `chord_label`).
+ *         - A vector of the pitch classes (0-11, root first) of each chord in the progression.
  */
-pub fn get_progression(prog_name: String, root: u8, chord_duration: f32) -> (Vec<Vec<f32>>, Vec<u8>) {
+#[allow(clippy::too_many_arguments)]
+pub fn get_progression(
+    prog_name: String,
+    root: u8,
+    minor: bool,
+    chord_duration: f32,
+    envelope: ChordEnvelope,
+    pattern: ChordPattern,
+    waveform: Waveform,
+    custom: &[RomanChord],
+) -> (Vec<Vec<f32>>, Vec<u8>, Vec<String>, Vec<ChordTones>) {
     let sample_rate = 44100; // Standard CD-quality audio
-    let mut chord_samples_list = Vec::new();
+    let root = root % 12; // Keeps the MIDI root arithmetic below from overflowing a u8
+    let chord_duration = if chord_duration.is_finite() { chord_duration.max(0.0) } else { 0.0 };
+    let mut chord_renders: Vec<ChordRender> = Vec::new();
     let mut root_notes_list = Vec::new();
+    let mut chord_labels_list = Vec::new();
+    let mut chord_tones_list = Vec::new();
 
     // Define a helper closure to generate chord and collect root note
     let mut add_chord = |current_root_offset: u8, quality: ChordQuality, number: ChordNumber| {
@@ -233,37 +785,610 @@ pub fn get_progression(prog_name: String, root: u8, chord_duration: f32) -> (Vec
         // The `root` (0-11) from UI + `current_root_offset`. Bass will be octave 2.
         let chord_root_midi = root + current_root_offset + 12 * 3; // Assuming octave 3 for chord root
         root_notes_list.push(chord_root_midi);
-        chord_samples_list.push(generate_chord_samples(
-            get_pitch(absolute_root), // This is fine, uses the 0-11 pitch class
-            quality,
-            number,
-            chord_duration,
-            sample_rate,
+        chord_labels_list.push(chord_label(absolute_root, &quality, &number));
+        chord_tones_list.push(chord_tones(absolute_root, quality, number));
+        chord_renders.push((
+            ChordKey { root: absolute_root % 12, voicing: ChordVoicing::Full(quality, number), duration: chord_duration },
+            Box::new(move || generate_chord_samples(
+                get_pitch(absolute_root), // This is fine, uses the 0-11 pitch class
+                quality,
+                number,
+                chord_duration,
+                sample_rate,
+                envelope,
+                pattern,
+                waveform,
+            )),
         ));
     };
 
-    match prog_name.to_lowercase().as_str() {
-        "blues" => {
+    if !custom.is_empty() {
+        for &(offset, quality, number) in custom {
+            add_chord(offset, quality, number);
+        }
+        return (render_chords(chord_renders), root_notes_list, chord_labels_list, chord_tones_list);
+    }
+
+    match (prog_name.to_lowercase().as_str(), minor) {
+        (name @ ("rock" | "metal"), _) => {
+            // Rock plays I-bVII-IV-I (i-bVI-bVII-i in minor keys); power chords have no third,
+            // so the key only changes which roots are played. Metal always takes the minor
+            // roots, an octave lower.
+            let (offsets, octave): ([u8; 4], u8) = match (name, minor) {
+                ("metal", _) => ([0, 8, 10, 0], 3),
+                (_, true) => ([0, 8, 10, 0], 4),
+                (_, false) => ([0, 10, 5, 0], 4),
+            };
+            for offset in offsets {
+                let absolute_root = root + offset;
+                root_notes_list.push(absolute_root + 12 * 3); // Octave 3, as in `add_chord`
+                chord_labels_list.push(format!("{}5", PITCH_NAMES[(absolute_root % 12) as usize]));
+                chord_tones_list.push(power_chord_tones(absolute_root));
+                chord_renders.push((
+                    ChordKey { root: absolute_root % 12, voicing: ChordVoicing::Power { octave }, duration: chord_duration },
+                    Box::new(move || generate_power_chord_samples(
+                        get_pitch(absolute_root),
+                        octave,
+                        chord_duration,
+                        sample_rate,
+                        envelope,
+                        pattern,
+                        waveform,
+                    )),
+                ));
+            }
+        }
+        ("electronic", _) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+            add_chord(8, ChordQuality::Major, ChordNumber::Triad);    // VI
+            add_chord(10, ChordQuality::Major, ChordNumber::Triad);   // VII
+        }
+        ("twelve-bar blues", false) => {
+            // I I I I, IV IV I I, V IV I V
+            for offset in [0, 0, 0, 0, 5, 5, 0, 0, 7, 5, 0, 7] {
+                add_chord(offset, ChordQuality::Dominant, ChordNumber::Seventh);
+            }
+        }
+        ("twelve-bar blues", true) => {
+            // i i i i, iv iv i i, v iv i v
+            for offset in [0, 0, 0, 0, 5, 5, 0, 0, 7, 5, 0, 7] {
+                add_chord(offset, ChordQuality::Minor, ChordNumber::Triad);
+            }
+        }
+        ("doo-wop", false) => {
             add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
+            add_chord(9, ChordQuality::Minor, ChordNumber::Triad);    // vi
             add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
             add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
+        }
+        ("doo-wop", true) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(8, ChordQuality::Major, ChordNumber::Triad);    // VI
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+            add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
+        }
+        ("andalusian", _) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(10, ChordQuality::Major, ChordNumber::Triad);   // bVII
+            add_chord(8, ChordQuality::Major, ChordNumber::Triad);    // bVI
+            add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
+        }
+        ("jazz turnaround", false) => {
+            add_chord(2, ChordQuality::Minor, ChordNumber::Seventh);  // ii
+            add_chord(7, ChordQuality::Dominant, ChordNumber::Seventh);// V
+            add_chord(0, ChordQuality::Major, ChordNumber::Seventh);  // I
+            add_chord(9, ChordQuality::Minor, ChordNumber::Seventh);  // vi, turning back to ii
+        }
+        ("jazz turnaround", true) => {
+            add_chord(2, ChordQuality::HalfDiminished, ChordNumber::Seventh); // ii half-diminished
+            add_chord(7, ChordQuality::Dominant, ChordNumber::Seventh);// V
+            add_chord(0, ChordQuality::Minor, ChordNumber::Seventh);  // i
+            add_chord(8, ChordQuality::Major, ChordNumber::Seventh);  // VI, turning back to ii
+        }
+        ("minor i-iv-v", _) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+            add_chord(7, ChordQuality::Minor, ChordNumber::Triad);    // v
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+        }
+        ("folk", true) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(7, ChordQuality::Minor, ChordNumber::Triad);    // v
+        }
+        ("classical", true) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+            add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V, from the harmonic minor
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+        }
+        ("blues", true) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+            add_chord(7, ChordQuality::Minor, ChordNumber::Triad);    // v
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+        }
+        ("pop", true) => {
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(8, ChordQuality::Major, ChordNumber::Triad);    // VI
+            add_chord(3, ChordQuality::Major, ChordNumber::Triad);    // III
+            add_chord(10, ChordQuality::Major, ChordNumber::Triad);   // VII
+        }
+        ("jazz", true) => {
+            add_chord(2, ChordQuality::HalfDiminished, ChordNumber::Seventh); // ii half-diminished
+            add_chord(7, ChordQuality::Dominant, ChordNumber::Seventh);// V
+            add_chord(0, ChordQuality::Minor, ChordNumber::Seventh);  // i
+        }
+        (_, true) => { // Default to a simple i-iv progression
+            add_chord(0, ChordQuality::Minor, ChordNumber::Triad);    // i
+            add_chord(5, ChordQuality::Minor, ChordNumber::Triad);    // iv
+        }
+        ("folk", false) => {
+            add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
             add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
+            add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
+            add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
         }
-        "pop" => {
+        ("classical", false) => {
+            add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
+            add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
+            add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
+            add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
+        }
+        ("blues", false) => {
+            add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
+            add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
+            add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
+            add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
+        }
+        ("pop", false) => {
             add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
             add_chord(7, ChordQuality::Major, ChordNumber::Triad);    // V
             add_chord(9, ChordQuality::Minor, ChordNumber::Triad);    // vi
             add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
         }
-        "jazz" => {
+        ("jazz", false) => {
             add_chord(2, ChordQuality::Minor, ChordNumber::Seventh);  // ii
             add_chord(7, ChordQuality::Dominant, ChordNumber::Seventh);// V
             add_chord(0, ChordQuality::Major, ChordNumber::Seventh);  // I
         }
-        _ => { // Default to a simple I-IV progression
+        (_, false) => { // Default to a simple I-IV progression
             add_chord(0, ChordQuality::Major, ChordNumber::Triad);    // I
             add_chord(5, ChordQuality::Major, ChordNumber::Triad);    // IV
         }
     }
-    (chord_samples_list, root_notes_list)
+    (render_chords(chord_renders), root_notes_list, chord_labels_list, chord_tones_list)
+}
+
+/* style_progressions - Lists the progressions a style can play in a key.
+ *
+ * The style's own progression always comes first. Progressions built on minor chords (the
+ * Andalusian cadence and the minor i-iv-v) are only offered in minor keys.
+ *
+ * inputs:
+ *     - prog_name (&str): The style's own progression, as `get_progression` names it (case-insensitive).
+ *     - minor (bool): True for a minor key, false for a major one.
+ *
+ * outputs:
+ *     - Vec<&'static str>: The progression names to choose from.
+ */
+pub fn style_progressions(prog_name: &str, minor: bool) -> Vec<&'static str> {
+    match (prog_name.to_lowercase().as_str(), minor) {
+        ("blues", _) => vec!["blues", "twelve-bar blues"],
+        ("pop", false) => vec!["pop", "doo-wop"],
+        ("pop", true) => vec!["pop", "doo-wop", "andalusian"],
+        ("jazz", _) => vec!["jazz", "jazz turnaround"],
+        ("classical", false) => vec!["classical"],
+        ("classical", true) => vec!["classical", "andalusian"],
+        ("folk", false) => vec!["folk", "doo-wop"],
+        ("folk", true) => vec!["folk", "minor i-iv-v"],
+        ("rock", _) => vec!["rock"],
+        ("metal", _) => vec!["metal"],
+        ("electronic", _) => vec!["electronic"],
+        (_, false) => vec!["default"],
+        (_, true) => vec!["default", "minor i-iv-v"],
+    }
+}
+
+// RomanChord: One chord of a custom progression: its root in semitones above the key's root
+// (0-11), its quality and its number.
+pub type RomanChord = (u8, ChordQuality, ChordNumber);
+
+// MAX_CUSTOM_CHORDS: Most chords a custom progression may have.
+pub const MAX_CUSTOM_CHORDS: usize = 12;
+
+// ROMAN_NUMERALS: The upper-case numeral of each scale degree and its semitones above the root
+// in a major scale.
+const ROMAN_NUMERALS: [(&str, u8); 7] = [("I", 0), ("II", 2), ("III", 4), ("IV", 5), ("V", 7), ("VI", 9), ("VII", 11)];
+
+/* parse_roman_chord - Parses one roman-numeral chord, e.g. "vi", "bVII", "V7" or "viio".
+ *
+ * An upper-case numeral is a major chord and a lower-case one a minor chord; a leading "b"
+ * lowers it a semitone. After the numeral may come "7" (dominant on an upper-case numeral,
+ * minor on a lower-case one), "maj7", "o" or "dim", "o7" or "dim7", "m7b5" or "ø7", and
+ * "+" or "aug".
+ *
+ * inputs:
+ *     - token (&str): The chord.
+ *
+ * outputs:
+ *     - Result<RomanChord, String>: The chord, or a message naming what isn't understood.
+ */
+fn parse_roman_chord(token: &str) -> Result<RomanChord, String> {
+    let (flat, rest) = match token.strip_prefix('b') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    // X is taken in too, so "IX" is an unknown numeral rather than an unknown extension
+    let numeral_len = rest.find(|c: char| !matches!(c, 'I' | 'V' | 'X' | 'i' | 'v' | 'x')).unwrap_or(rest.len());
+    let (numeral, extension) = rest.split_at(numeral_len);
+    let upper = numeral.chars().all(|c| c.is_ascii_uppercase());
+    let offset = ROMAN_NUMERALS
+        .iter()
+        .find(|(name, _)| *name == numeral.to_ascii_uppercase())
+        .filter(|_| upper || numeral.chars().all(|c| c.is_ascii_lowercase()))
+        .map(|&(_, offset)| if flat { (offset + 11) % 12 } else { offset })
+        .ok_or_else(|| format!("Unknown numeral '{}' (use I to VII, with b for a flat, e.g. I V vi IV or bVII)", token))?;
+    let (quality, number) = match (extension, upper) {
+        ("", true) => (ChordQuality::Major, ChordNumber::Triad),
+        ("", false) => (ChordQuality::Minor, ChordNumber::Triad),
+        ("7", true) => (ChordQuality::Dominant, ChordNumber::Seventh),
+        ("7", false) => (ChordQuality::Minor, ChordNumber::Seventh),
+        ("maj7", true) => (ChordQuality::Major, ChordNumber::Seventh),
+        ("o" | "dim", _) => (ChordQuality::Diminished, ChordNumber::Triad),
+        ("o7" | "dim7", _) => (ChordQuality::Diminished, ChordNumber::Seventh),
+        ("m7b5" | "ø7", _) => (ChordQuality::HalfDiminished, ChordNumber::Seventh),
+        ("+" | "aug", _) => (ChordQuality::Augmented, ChordNumber::Triad),
+        _ => {
+            return Err(format!(
+                "Unsupported extension '{}' in '{}' (use 7, maj7 on upper case, o, o7, m7b5 or aug)",
+                extension, token
+            ));
+        }
+    };
+    Ok((offset, quality, number))
+}
+
+/* parse_roman_progression - Parses a custom progression of space-separated roman numerals.
+ *
+ * inputs:
+ *     - text (&str): The progression, e.g. "I V vi IV" or "ii7 V7 Imaj7" (see `parse_roman_chord`).
+ *
+ * outputs:
+ *     - Result<Vec<RomanChord>, String>: The chords, empty for blank text, or a message for the
+ *       first chord that isn't understood.
+ */
+pub fn parse_roman_progression(text: &str) -> Result<Vec<RomanChord>, String> {
+    let chords = text.split_whitespace().map(parse_roman_chord).collect::<Result<Vec<_>, _>>()?;
+    if chords.len() > MAX_CUSTOM_CHORDS {
+        return Err(format!(
+            "A progression can have at most {} chords, this one has {}",
+            MAX_CUSTOM_CHORDS,
+            chords.len()
+        ));
+    }
+    Ok(chords)
+}
+
+/* roman_progression_text - Spells a custom progression the way `parse_roman_progression` reads it.
+ *
+ * Flats are used for the chromatic degrees and the extensions are written in plain letters
+ * ("o", "m7b5", "aug"), so the text can go into a song ID.
+ *
+ * inputs:
+ *     - chords (&[RomanChord]): The chords.
+ *
+ * outputs:
+ *     - String: The chords as space-separated roman numerals, e.g. "i bVII bVI V".
+ */
+pub fn roman_progression_text(chords: &[RomanChord]) -> String {
+    let spell = |&(offset, quality, number): &RomanChord| {
+        let (flat, numeral) = match ROMAN_NUMERALS.iter().find(|&&(_, degree)| degree == offset % 12) {
+            Some(&(numeral, _)) => ("", numeral),
+            None => ("b", ROMAN_NUMERALS.iter().find(|&&(_, degree)| degree == offset % 12 + 1).unwrap().0),
+        };
+        let numeral = match quality {
+            ChordQuality::Minor | ChordQuality::Diminished | ChordQuality::HalfDiminished => numeral.to_lowercase(),
+            _ => numeral.to_string(),
+        };
+        let extension = match (quality, number) {
+            (ChordQuality::Dominant | ChordQuality::Minor, ChordNumber::Seventh) => "7",
+            (ChordQuality::Major, ChordNumber::Seventh) => "maj7",
+            (ChordQuality::Diminished, ChordNumber::Triad) => "o",
+            (ChordQuality::Diminished, ChordNumber::Seventh) => "o7",
+            (ChordQuality::HalfDiminished, _) => "m7b5",
+            (ChordQuality::Augmented, _) => "aug",
+            _ => "",
+        };
+        format!("{}{}{}", flat, numeral, extension)
+    };
+    chords.iter().map(spell).collect::<Vec<_>>().join(" ")
+}
+
+/* get_cadence - Generates the two chords that close a song in the progression's style.
+ *
+ * Most progressions close V-I, borrowing the major V from the harmonic minor in minor keys.
+ * Jazz closes V7-Imaj7 (V7-im7 in minor), blues V7-I7 (V7-i in minor), rock and metal
+ * bVII-I on their power chords, electronic bVII-i, and the default progression plagally, IV-I.
+ *
+ * inputs:
+ *     - prog_name (&str): The name of the progression the song plays (case-insensitive).
+ *     - root (u8): The root note (0-11) of the key. Larger values wrap around 12.
+ *     - minor (bool): True for a minor key, false for a major one.
+ *     - chord_duration (f32): The duration of the first chord in seconds.
+ *     - final_duration (f32): The duration of the closing tonic in seconds.
+ *     - envelope (ChordEnvelope): Fade-in and fade-out applied to both chords.
+ *     - pattern (ChordPattern): Whether each chord's notes sound together or are broken up.
+ *     - waveform (Waveform): The tone of every chord note.
+ *
+ * outputs:
+ *     - (Vec<Vec<f32>>, Vec<u8>, Vec<String>, Vec<ChordTones>): The two chords, in the form `get_progression` returns.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn get_cadence(
+    prog_name: &str,
+    root: u8,
+    minor: bool,
+    chord_duration: f32,
+    final_duration: f32,
+    envelope: ChordEnvelope,
+    pattern: ChordPattern,
+    waveform: Waveform,
+) -> (Vec<Vec<f32>>, Vec<u8>, Vec<String>, Vec<ChordTones>) {
+    let sample_rate = 44100;
+    let root = root % 12;
+    let durations = [chord_duration, final_duration].map(|duration| if duration.is_finite() { duration.max(0.0) } else { 0.0 });
+    let mut chord_renders: Vec<ChordRender> = Vec::with_capacity(2);
+    let mut root_notes_list = Vec::with_capacity(2);
+    let mut chord_labels_list = Vec::with_capacity(2);
+    let mut chord_tones_list = Vec::with_capacity(2);
+
+    let name = prog_name.to_lowercase();
+    if name == "rock" || name == "metal" {
+        let octave = if name == "metal" { 3 } else { 4 }; // As in `get_progression`
+        for (offset, duration) in [10, 0].into_iter().zip(durations) {
+            let absolute_root = root + offset;
+            root_notes_list.push(absolute_root + 12 * 3);
+            chord_labels_list.push(format!("{}5", PITCH_NAMES[(absolute_root % 12) as usize]));
+            chord_tones_list.push(power_chord_tones(absolute_root));
+            chord_renders.push((
+                ChordKey { root: absolute_root % 12, voicing: ChordVoicing::Power { octave }, duration },
+                Box::new(move || generate_power_chord_samples(
+                    get_pitch(absolute_root),
+                    octave,
+                    duration,
+                    sample_rate,
+                    envelope,
+                    pattern,
+                    waveform,
+                )),
+            ));
+        }
+        return (render_chords(chord_renders), root_notes_list, chord_labels_list, chord_tones_list);
+    }
+
+    let chords = match (name.as_str(), minor) {
+        ("electronic", _) => [
+            (10, ChordQuality::Major, ChordNumber::Triad), // bVII
+            (0, ChordQuality::Minor, ChordNumber::Triad),  // i
+        ],
+        ("jazz", _) => [
+            (7, ChordQuality::Dominant, ChordNumber::Seventh), // V7
+            (0, if minor { ChordQuality::Minor } else { ChordQuality::Major }, ChordNumber::Seventh),
+        ],
+        ("blues", false) => [
+            (7, ChordQuality::Dominant, ChordNumber::Seventh), // V7
+            (0, ChordQuality::Dominant, ChordNumber::Seventh), // I7
+        ],
+        ("blues", true) => [
+            (7, ChordQuality::Dominant, ChordNumber::Seventh), // V7
+            (0, ChordQuality::Minor, ChordNumber::Triad),      // i
+        ],
+        ("default", _) => [
+            (5, if minor { ChordQuality::Minor } else { ChordQuality::Major }, ChordNumber::Triad), // IV
+            (0, if minor { ChordQuality::Minor } else { ChordQuality::Major }, ChordNumber::Triad), // I
+        ],
+        (_, _) => [
+            (7, ChordQuality::Major, ChordNumber::Triad), // V
+            (0, if minor { ChordQuality::Minor } else { ChordQuality::Major }, ChordNumber::Triad), // I
+        ],
+    };
+    for ((offset, quality, number), duration) in chords.into_iter().zip(durations) {
+        let absolute_root = root + offset;
+        root_notes_list.push(absolute_root + 12 * 3); // Octave 3, as in `get_progression`
+        chord_labels_list.push(chord_label(absolute_root, &quality, &number));
+        chord_tones_list.push(chord_tones(absolute_root, quality, number));
+        chord_renders.push((
+            ChordKey { root: absolute_root % 12, voicing: ChordVoicing::Full(quality, number), duration },
+            Box::new(move || generate_chord_samples(
+                get_pitch(absolute_root),
+                quality,
+                number,
+                duration,
+                sample_rate,
+                envelope,
+                pattern,
+                waveform,
+            )),
+        ));
+    }
+    (render_chords(chord_renders), root_notes_list, chord_labels_list, chord_tones_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // TWELVE_BAR_BLUES: A progression that repeats chords, as (root, voicing, duration) of each.
+    const TWELVE_BAR_BLUES: [(u8, ChordVoicing, f32); 12] = [
+        (0, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (5, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (0, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (0, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (5, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (5, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (0, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (0, ChordVoicing::Power { octave: 3 }, 2.0),
+        (7, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (5, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+        (0, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 1.0),
+        (7, ChordVoicing::Full(ChordQuality::Major, ChordNumber::Seventh), 2.0),
+    ];
+
+    fn render_uncached(key: ChordKey, pattern: ChordPattern, waveform: Waveform) -> Vec<f32> {
+        let envelope = ChordEnvelope { attack_seconds: 0.01, release_seconds: 0.05 };
+        match key.voicing {
+            ChordVoicing::Full(quality, number) => generate_chord_samples(
+                get_pitch(key.root),
+                quality,
+                number,
+                key.duration,
+                44100,
+                envelope,
+                pattern,
+                waveform,
+            ),
+            ChordVoicing::Power { octave } => generate_power_chord_samples(
+                get_pitch(key.root),
+                octave,
+                key.duration,
+                44100,
+                envelope,
+                pattern,
+                waveform,
+            ),
+        }
+    }
+
+    fn blues_renders(pattern: ChordPattern, waveform: Waveform, count: &Arc<AtomicUsize>) -> Vec<ChordRender> {
+        TWELVE_BAR_BLUES
+            .iter()
+            .map(|&(root, voicing, duration)| {
+                let key = ChordKey { root, voicing, duration };
+                let count = Arc::clone(count);
+                let render: Box<dyn FnOnce() -> Vec<f32> + Send> = Box::new(move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    render_uncached(key, pattern, waveform)
+                });
+                (key, render)
+            })
+            .collect()
+    }
+
+    fn bits(samples: &[f32]) -> Vec<u32> {
+        samples.iter().map(|sample| sample.to_bits()).collect()
+    }
+
+    #[test]
+    fn cached_chord_renders_match_uncached_ones_bit_for_bit() {
+        let patterns = [
+            ChordPattern::Block,
+            ChordPattern::Alberti { step_seconds: 0.25 },
+            ChordPattern::Skank { beat_seconds: 0.5 },
+            ChordPattern::ChipArp { rate_hz: 30.0, pulse_width: PulseWidth::EVEN },
+        ];
+        for pattern in patterns {
+            for waveform in [Waveform::Sine, Waveform::Square, Waveform::Noise] {
+                let cached_count = Arc::new(AtomicUsize::new(0));
+                let cached = render_chords(blues_renders(pattern, waveform, &cached_count));
+
+                let uncached_count = Arc::new(AtomicUsize::new(0));
+                let uncached: Vec<Vec<f32>> = blues_renders(pattern, waveform, &uncached_count)
+                    .into_iter()
+                    .map(|(_, render)| render())
+                    .collect();
+
+                assert_eq!(cached.len(), uncached.len());
+                for (index, (cached, uncached)) in cached.iter().zip(&uncached).enumerate() {
+                    assert_eq!(
+                        bits(cached),
+                        bits(uncached),
+                        "chord {index} differs with {pattern:?} and {waveform:?}"
+                    );
+                }
+                // C7, F7, C5, G7 and the short C7: each rendered once however often it repeats
+                assert_eq!(cached_count.load(Ordering::SeqCst), 5);
+                assert_eq!(uncached_count.load(Ordering::SeqCst), TWELVE_BAR_BLUES.len());
+            }
+        }
+    }
+
+    // ProgressionCase: (progression, minor key, roots in C, chord labels in C)
+    type ProgressionCase = (&'static str, bool, &'static [u8], &'static [&'static str]);
+
+    // NAMED_PROGRESSIONS: Every progression in the bank, as played in C major and C minor.
+    const NAMED_PROGRESSIONS: &[ProgressionCase] = &[
+        (
+            "twelve-bar blues",
+            false,
+            &[36, 36, 36, 36, 41, 41, 36, 36, 43, 41, 36, 43],
+            &["C7", "C7", "C7", "C7", "F7", "F7", "C7", "C7", "G7", "F7", "C7", "G7"],
+        ),
+        (
+            "twelve-bar blues",
+            true,
+            &[36, 36, 36, 36, 41, 41, 36, 36, 43, 41, 36, 43],
+            &["C min", "C min", "C min", "C min", "F min", "F min", "C min", "C min", "G min", "F min", "C min", "G min"],
+        ),
+        ("doo-wop", false, &[36, 45, 41, 43], &["C maj", "A min", "F maj", "G maj"]),
+        ("doo-wop", true, &[36, 44, 41, 43], &["C min", "G# maj", "F min", "G maj"]),
+        ("andalusian", false, &[36, 46, 44, 43], &["C min", "A# maj", "G# maj", "G maj"]),
+        ("andalusian", true, &[36, 46, 44, 43], &["C min", "A# maj", "G# maj", "G maj"]),
+        ("jazz turnaround", false, &[38, 43, 36, 45], &["Dm7", "G7", "Cmaj7", "Am7"]),
+        ("jazz turnaround", true, &[38, 43, 36, 44], &["Dm7b5", "G7", "Cm7", "G#maj7"]),
+        ("minor i-iv-v", true, &[36, 41, 43, 36], &["C min", "F min", "G min", "C min"]),
+        ("blues", false, &[36, 41, 43, 41], &["C maj", "F maj", "G maj", "F maj"]),
+        ("pop", false, &[36, 43, 45, 41], &["C maj", "G maj", "A min", "F maj"]),
+        ("pop", true, &[36, 44, 39, 46], &["C min", "G# maj", "D# maj", "A# maj"]),
+        ("jazz", false, &[38, 43, 36], &["Dm7", "G7", "Cmaj7"]),
+        ("classical", false, &[36, 41, 43, 36], &["C maj", "F maj", "G maj", "C maj"]),
+        ("folk", false, &[36, 41, 36, 43], &["C maj", "F maj", "C maj", "G maj"]),
+        ("rock", false, &[36, 46, 41, 36], &["C5", "A#5", "F5", "C5"]),
+        ("metal", false, &[36, 44, 46, 36], &["C5", "G#5", "A#5", "C5"]),
+        ("electronic", false, &[36, 41, 44, 46], &["C min", "F min", "G# maj", "A# maj"]),
+        ("default", false, &[36, 41], &["C maj", "F maj"]),
+    ];
+
+    #[test]
+    fn each_named_progression_plays_its_roots_in_c() {
+        let envelope = ChordEnvelope { attack_seconds: 0.01, release_seconds: 0.05 };
+        for &(name, minor, roots, labels) in NAMED_PROGRESSIONS {
+            let (chords, root_notes, chord_labels, chord_tones) =
+                get_progression(String::from(name), 0, minor, 0.1, envelope, ChordPattern::Block, Waveform::Square, &[]);
+            assert_eq!(root_notes, roots, "roots of {} (minor {})", name, minor);
+            assert_eq!(chord_labels, labels, "labels of {} (minor {})", name, minor);
+            assert_eq!(chords.len(), roots.len());
+            for (tones, root) in chord_tones.iter().zip(roots) {
+                assert_eq!(tones.first(), Some(&(root % 12)), "{} puts the root first", name);
+            }
+        }
+    }
+
+    #[test]
+    fn every_progression_a_style_offers_is_in_the_bank() {
+        let styles = ["blues", "pop", "jazz", "classical", "folk", "rock", "metal", "electronic", "default"];
+        for style in styles {
+            for minor in [false, true] {
+                let offered = style_progressions(style, minor);
+                assert_eq!(offered.first().copied(), Some(style), "{} comes first for itself", style);
+                for name in offered {
+                    assert!(
+                        NAMED_PROGRESSIONS.iter().any(|&(case, _, _, _)| case == name),
+                        "{} offers {}, which no test plays",
+                        style,
+                        name
+                    );
+                }
+            }
+        }
+        // The progressions built on minor chords stay out of major keys
+        for style in styles {
+            let major = style_progressions(style, false);
+            assert!(!major.contains(&"andalusian") && !major.contains(&"minor i-iv-v"), "{}: {:?}", style, major);
+        }
+    }
 }
@@ -0,0 +1,93 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// SESSION_FILE_NAME: Name of the small key=value file holding per-user session data.
+const SESSION_FILE_NAME: &str = "session";
+
+/* data_dir - Returns the directory used for 8BitBeats' persistent data.
+ *
+ * Follows the XDG base directory convention: $XDG_DATA_HOME/8bitbeats, falling back
+ * to ~/.local/share/8bitbeats when XDG_DATA_HOME is unset.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - Option<PathBuf>: The data directory, or None if no home directory can be determined.
+ */
+pub fn data_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+    };
+    Some(base.join("8bitbeats"))
+}
+
+/* write_atomically - Writes a file by writing a temporary sibling and renaming it into place.
+ *
+ * A crash mid-write leaves the previous file intact instead of a truncated one.
+ *
+ * inputs:
+ *     - path (&PathBuf): The destination file.
+ *     - contents (&str): The full contents to write.
+ *
+ * outputs:
+ *     - io::Result<()>: Ok on success, or the underlying I/O error.
+ */
+pub fn write_atomically(path: &PathBuf, contents: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)
+}
+
+/* probe_writable - Checks that a directory can be created and written to.
+ *
+ * Writes and removes a small probe file, so nothing is left behind on success.
+ *
+ * inputs:
+ *     - dir (&Path): The directory to check.
+ *
+ * outputs:
+ *     - io::Result<()>: Ok if the directory is writable, or the error that prevented it.
+ */
+pub fn probe_writable(dir: &Path) -> io::Result<()> {
+    let probe_path = dir.join(".write-probe");
+    write_atomically(&probe_path, "")?;
+    fs::remove_file(&probe_path)
+}
+
+/* load_last_seen_version - Reads the last acknowledged app version from the session file.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - Option<String>: The recorded version, or None on a fresh install or unreadable file.
+ */
+pub fn load_last_seen_version() -> Option<String> {
+    let contents = fs::read_to_string(data_dir()?.join(SESSION_FILE_NAME)).ok()?;
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "last_seen_version").then(|| value.trim().to_string())
+    })
+}
+
+/* save_last_seen_version - Records the acknowledged app version in the session file.
+ *
+ * inputs:
+ *     - version (&str): The version to record.
+ *
+ * outputs:
+ *     - io::Result<()>: Ok on success, or an error if the file could not be written.
+ */
+pub fn save_last_seen_version(version: &str) -> io::Result<()> {
+    let dir = data_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    write_atomically(
+        &dir.join(SESSION_FILE_NAME),
+        &format!("last_seen_version={}\n", version),
+    )
+}
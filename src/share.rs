@@ -0,0 +1,481 @@
+use crate::controller;
+use crate::gen::{
+    self, LOFI_SETTINGS, SCALE_NAMES, SONG_ID_VERSION_PREFIX, SONG_ID_VERSION_SEPARATOR, STYLE_NAMES,
+};
+use crate::progs::TimeSignature;
+use crate::synth::Waveform;
+use crate::tui::AppState;
+
+// SHARE_CODE_PREFIX: Starts a share code ("8BB-3yQp9TfW2k..."), telling it apart from a song ID.
+pub const SHARE_CODE_PREFIX: &str = "8BB-";
+
+// BASE58_ALPHABET: Digits of a share code; 0, O, I and l are left out so it can be read aloud or retyped.
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+// Flags marking which of the optional song ID parts a share code carries, in the order they follow.
+const HAS_SEED: u64 = 1;
+const HAS_SWING: u64 = 1 << 1;
+const HAS_TIME_SIGNATURE: u64 = 1 << 2;
+const HAS_MODULATION: u64 = 1 << 3;
+const HAS_PROGRESSION: u64 = 1 << 4;
+const HAS_WAVEFORMS: u64 = 1 << 5;
+const HAS_ECHO_OFF: u64 = 1 << 6;
+const HAS_LOFI: u64 = 1 << 7;
+const HAS_NES_MODE: u64 = 1 << 8;
+const HAS_MELODY_SEED: u64 = 1 << 9;
+const HAS_BASS_SEED: u64 = 1 << 10;
+const HAS_DRUMS_OFF: u64 = 1 << 11;
+const HAS_FADE_OUT: u64 = 1 << 12;
+const HAS_GAINS: u64 = 1 << 13;
+// FIRST_SETTING_FLAG_BIT: The flag of `gen::SETTING_ID_PARTS[0]`; each later part has the next bit.
+const FIRST_SETTING_FLAG_BIT: usize = 14;
+
+/* push_varint - Appends a number in as few bytes as it needs, 7 bits per byte.
+ *
+ * inputs:
+ *     - bytes (&mut Vec<u8>): The code being built.
+ *     - value (u64): The number to append.
+ *
+ * outputs:
+ *     - None
+ */
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let low_bits = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(low_bits);
+            return;
+        }
+        bytes.push(low_bits | 0x80);
+    }
+}
+
+/* CodeReader - Reads the numbers and text of a decoded share code back in order.
+ *
+ * fields:
+ *     - bytes (&[u8]): The decoded code.
+ *     - position (usize): Index of the next unread byte.
+ */
+struct CodeReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl CodeReader<'_> {
+    /* varint - Reads a number written by `push_varint`.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<u64>: The number, or None if the code ends early or it doesn't fit in a u64.
+     */
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes.get(self.position)?;
+            self.position += 1;
+            let low_bits = (byte & 0x7f) as u64;
+            if low_bits << shift >> shift != low_bits {
+                return None;
+            }
+            value |= low_bits << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /* index - Reads a number that picks one of `count` choices.
+     *
+     * inputs:
+     *     - &mut self
+     *     - count (usize): How many choices there are.
+     *
+     * outputs:
+     *     - Option<usize>: The index, or None if it is out of range.
+     */
+    fn index(&mut self, count: usize) -> Option<usize> {
+        self.varint().map(|value| value as usize).filter(|&index| index < count)
+    }
+
+    /* text - Reads text written as its length in bytes followed by the UTF-8 bytes.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<String>: The text, or None if the code ends early or it isn't UTF-8.
+     */
+    fn text(&mut self) -> Option<String> {
+        let len = self.varint()? as usize;
+        let end = self.position.checked_add(len).filter(|&end| end <= self.bytes.len())?;
+        let text = std::str::from_utf8(&self.bytes[self.position..end]).ok()?;
+        self.position = end;
+        Some(text.to_string())
+    }
+}
+
+/* base58_encode - Writes bytes as base58 digits.
+ *
+ * inputs:
+ *     - bytes (&[u8]): The data to encode.
+ *
+ * outputs:
+ *     - String: The digits from `BASE58_ALPHABET`, with a '1' for each leading zero byte.
+ */
+fn base58_encode(bytes: &[u8]) -> String {
+    // The number's digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let leading_zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n('1', leading_zeros)
+        .chain(digits.iter().rev().map(|&digit| BASE58_ALPHABET[digit as usize] as char))
+        .collect()
+}
+
+/* base58_decode - Reads base58 digits back into bytes.
+ *
+ * inputs:
+ *     - text (&str): The digits.
+ *
+ * outputs:
+ *     - Result<Vec<u8>, char>: The bytes, or the first character that isn't a base58 digit.
+ */
+fn base58_decode(text: &str) -> Result<Vec<u8>, char> {
+    // The number's bytes, least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for character in text.chars() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&digit| digit as char == character)
+            .ok_or(character)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    let leading_zeros = text.chars().take_while(|&character| character == '1').count();
+    Ok(std::iter::repeat_n(0, leading_zeros).chain(bytes.into_iter().rev()).collect())
+}
+
+/* strip_share_code_prefix - Recognizes a share code by its prefix, in any case.
+ *
+ * inputs:
+ *     - text (&str): A song ID or share code, already trimmed.
+ *
+ * outputs:
+ *     - Option<&str>: The code after `SHARE_CODE_PREFIX`, or None if the text is not a share code.
+ */
+pub fn strip_share_code_prefix(text: &str) -> Option<&str> {
+    text.get(..SHARE_CODE_PREFIX.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(SHARE_CODE_PREFIX))
+        .map(|_| &text[SHARE_CODE_PREFIX.len()..])
+}
+
+/* share_code - Packs a song ID into a short code for sharing.
+ *
+ * The scale, style, BPM, length and seeds are stored as numbers and the optional parts only
+ * when the ID has them, so a plain ID like "v2:C#-Electronic-145-10-18446744073709551615"
+ * becomes about 25 characters. `song_id_from_share_code` gives back the same ID.
+ *
+ * inputs:
+ *     - song_id (&str): The song ID to pack.
+ *
+ * outputs:
+ *     - Result<String, String>: The code starting with `SHARE_CODE_PREFIX`, or the error from
+ *       parsing the ID.
+ */
+pub fn share_code(song_id: &str) -> Result<String, String> {
+    let app_state = gen::parse_song_id_to_app_state(song_id)?;
+    let seed = app_state.seed.parse::<u64>().ok();
+    let swing = app_state.swing.parse::<u64>().ok();
+    let time_signature = TimeSignature::from_label(&app_state.time_signature)
+        .and_then(|signature| TimeSignature::ALL.iter().position(|&other| other == signature));
+    let modulation = match app_state.modulation.as_str() {
+        "On" => Some(1),
+        "Off" => Some(0),
+        _ => None,
+    };
+    let waveforms_set = gen::waveforms_id_part(&app_state.waveforms).is_some();
+    let lofi = LOFI_SETTINGS
+        .iter()
+        .position(|&setting| setting == app_state.lofi)
+        .filter(|_| gen::lofi_id_part(&app_state.lofi).is_some());
+    let gains = gen::gains_id_part(&app_state).map(|_| {
+        [&app_state.melody_gain, &app_state.chord_gain, &app_state.bass_gain].map(|gain| gain.parse::<u64>().unwrap_or_default())
+    });
+    let settings: Vec<Option<usize>> = gen::SETTING_ID_PARTS.iter().map(|part| part.index(&app_state)).collect();
+    let setting_flags = settings
+        .iter()
+        .enumerate()
+        .filter(|(_, index)| index.is_some())
+        .fold(0, |flags, (part, _)| flags | 1 << (FIRST_SETTING_FLAG_BIT + part));
+    let flags = [
+        (HAS_SEED, seed.is_some()),
+        (HAS_SWING, swing.is_some()),
+        (HAS_TIME_SIGNATURE, time_signature.is_some()),
+        (HAS_MODULATION, modulation.is_some()),
+        (HAS_PROGRESSION, !app_state.progression.is_empty()),
+        (HAS_WAVEFORMS, waveforms_set),
+        (HAS_ECHO_OFF, app_state.echo == "Off"),
+        (HAS_LOFI, lofi.is_some()),
+        (HAS_NES_MODE, app_state.nes_mode),
+        (HAS_MELODY_SEED, app_state.melody_seed.is_some()),
+        (HAS_BASS_SEED, app_state.bass_seed.is_some()),
+        (HAS_DRUMS_OFF, !app_state.drums),
+        (HAS_FADE_OUT, app_state.fade_out_secs.is_some()),
+        (HAS_GAINS, gains.is_some()),
+    ]
+    .into_iter()
+    .filter(|&(_, is_set)| is_set)
+    .fold(setting_flags, |flags, (flag, _)| flags | flag);
+
+    let scale_index = SCALE_NAMES.iter().position(|&name| name == app_state.scale).unwrap_or_default();
+    let style_index = STYLE_NAMES.iter().position(|&name| name == app_state.style).unwrap_or_default();
+
+    let mut bytes = Vec::new();
+    push_varint(&mut bytes, app_state.id_version as u64);
+    push_varint(&mut bytes, (scale_index * 2 + app_state.is_minor() as usize) as u64);
+    push_varint(&mut bytes, style_index as u64);
+    push_varint(&mut bytes, app_state.bpm.parse::<u64>().unwrap_or_default()); // 0 for a random BPM
+    push_varint(&mut bytes, app_state.length_secs as u64);
+    push_varint(&mut bytes, flags);
+    for value in [seed, swing, time_signature.map(|index| index as u64), modulation]
+        .into_iter()
+        .flatten()
+    {
+        push_varint(&mut bytes, value);
+    }
+    if !app_state.progression.is_empty() {
+        push_varint(&mut bytes, app_state.progression.len() as u64);
+        bytes.extend_from_slice(app_state.progression.as_bytes());
+    }
+    if waveforms_set {
+        for setting in &app_state.waveforms {
+            // 0 lets the style pick, as in the Auto setting
+            let waveform = Waveform::ALL.iter().position(|waveform| waveform.label() == setting);
+            push_varint(&mut bytes, waveform.map_or(0, |index| index as u64 + 1));
+        }
+    }
+    let fade_out = app_state.fade_out_secs.map(u64::from);
+    let settings = settings.into_iter().flatten().map(|index| index as u64);
+    for value in [lofi.map(|index| index as u64), app_state.melody_seed, app_state.bass_seed, fade_out]
+        .into_iter()
+        .flatten()
+        .chain(gains.into_iter().flatten())
+        .chain(settings)
+    {
+        push_varint(&mut bytes, value);
+    }
+    Ok(format!("{}{}", SHARE_CODE_PREFIX, base58_encode(&bytes)))
+}
+
+/* song_id_from_share_code - Unpacks a share code made by `share_code` into its song ID.
+ *
+ * inputs:
+ *     - code (&str): The code after `SHARE_CODE_PREFIX`.
+ *
+ * outputs:
+ *     - Result<String, String>: The song ID, or an error if the code is mistyped or cut short.
+ */
+pub fn song_id_from_share_code(code: &str) -> Result<String, String> {
+    let bytes = base58_decode(code).map_err(|character| {
+        format!(
+            "Invalid Share Code: '{}' can't be part of a share code (they use letters and digits other than 0, O, I and l).",
+            character
+        )
+    })?;
+    let invalid = || {
+        format!(
+            "Invalid Share Code: '{}{}' is cut short or mistyped. Check it against the code that was shared.",
+            SHARE_CODE_PREFIX, code
+        )
+    };
+    let mut reader = CodeReader { bytes: &bytes, position: 0 };
+    let mut read = || -> Option<(u64, AppState)> {
+        let id_version = reader.varint().and_then(|version| u32::try_from(version).ok())?;
+        let key = reader.index(SCALE_NAMES.len() * 2)?;
+        let style = reader.index(STYLE_NAMES.len())?;
+        let bpm = reader.varint()?;
+        let length_secs = reader.varint().and_then(|secs| u32::try_from(secs).ok())?;
+        let flags = reader.varint()?;
+        let mut app_state = AppState {
+            scale: SCALE_NAMES[key / 2].to_string(),
+            quality: if key % 2 == 1 { "Minor" } else { "Major" }.to_string(),
+            style: STYLE_NAMES[style].to_string(),
+            bpm: if bpm == 0 { String::new() } else { bpm.to_string() },
+            length_secs,
+            id_version,
+            ..AppState::default()
+        };
+        let has = |flag: u64| flags & flag != 0;
+        let seed = if has(HAS_SEED) { Some(reader.varint()?) } else { None };
+        if has(HAS_SWING) {
+            app_state.swing = reader.varint()?.to_string();
+        }
+        if has(HAS_TIME_SIGNATURE) {
+            app_state.time_signature = TimeSignature::ALL[reader.index(TimeSignature::ALL.len())?].label().to_string();
+        }
+        if has(HAS_MODULATION) {
+            app_state.modulation = if reader.index(2)? == 1 { "On" } else { "Off" }.to_string();
+        }
+        if has(HAS_PROGRESSION) {
+            app_state.progression = reader.text()?;
+        }
+        if has(HAS_WAVEFORMS) {
+            for setting in app_state.waveforms.iter_mut() {
+                *setting = match reader.index(Waveform::ALL.len() + 1)? {
+                    0 => gen::AUTO_WAVEFORM.to_string(),
+                    index => Waveform::ALL[index - 1].label().to_string(),
+                };
+            }
+        }
+        if has(HAS_ECHO_OFF) {
+            app_state.echo = "Off".to_string();
+        }
+        if has(HAS_LOFI) {
+            app_state.lofi = LOFI_SETTINGS[reader.index(LOFI_SETTINGS.len())?].to_string();
+        }
+        app_state.nes_mode = has(HAS_NES_MODE);
+        if has(HAS_MELODY_SEED) {
+            app_state.melody_seed = Some(reader.varint()?);
+        }
+        if has(HAS_BASS_SEED) {
+            app_state.bass_seed = Some(reader.varint()?);
+        }
+        app_state.drums = !has(HAS_DRUMS_OFF);
+        if has(HAS_FADE_OUT) {
+            app_state.fade_out_secs = Some(reader.index(gen::MAX_FADE_OUT_SECS as usize + 1)? as u32);
+        }
+        if has(HAS_GAINS) {
+            for gain in [&mut app_state.melody_gain, &mut app_state.chord_gain, &mut app_state.bass_gain] {
+                *gain = reader.index(101)?.to_string();
+            }
+        }
+        for (part_index, part) in gen::SETTING_ID_PARTS.iter().enumerate() {
+            if has(1 << (FIRST_SETTING_FLAG_BIT + part_index)) {
+                *(part.get_mut)(&mut app_state) = part.settings[reader.index(part.settings.len())?].to_string();
+            }
+        }
+        app_state.seed = seed.map(|seed| seed.to_string()).unwrap_or_default();
+        Some((seed.unwrap_or_default(), app_state))
+    };
+    let (seed, app_state) = read().ok_or_else(invalid)?;
+    if reader.position != bytes.len() {
+        return Err(invalid());
+    }
+
+    // Stamped with the code's own version, and the seed left empty if the code has none
+    let song_id = controller::song_id(&app_state, seed);
+    let body = song_id.split_once(SONG_ID_VERSION_SEPARATOR).map_or(song_id.as_str(), |(_, body)| body);
+    let body = if app_state.seed.is_empty() {
+        body.rsplit_once('-').map_or(body, |(parts, _)| parts).to_string() + "-"
+    } else {
+        body.to_string()
+    };
+    Ok(format!("{}{}{}{}", SONG_ID_VERSION_PREFIX, app_state.id_version, SONG_ID_VERSION_SEPARATOR, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{ENDLESS_SONG_SECS, MAX_BPM, MIN_BPM, MIN_SONG_SECS};
+
+    fn assert_round_trip(song_id: &str) {
+        let code = share_code(song_id).unwrap_or_else(|error| panic!("{}: {}", song_id, error));
+        let code_body = strip_share_code_prefix(&code).expect("share codes start with the prefix");
+        assert_eq!(song_id_from_share_code(code_body).as_deref(), Ok(song_id), "via {}", code);
+    }
+
+    #[test]
+    fn every_key_style_tempo_length_and_seed_round_trips() {
+        for scale in SCALE_NAMES {
+            for quality in ["Major", "Minor"] {
+                for style in STYLE_NAMES {
+                    for bpm in [String::new(), MIN_BPM.to_string(), "120".to_string(), MAX_BPM.to_string()] {
+                        for length_secs in [ENDLESS_SONG_SECS, MIN_SONG_SECS, 300, 3600] {
+                            for seed in [0, 42, u64::MAX] {
+                                let app_state = AppState {
+                                    scale: scale.to_string(),
+                                    quality: quality.to_string(),
+                                    style: style.to_string(),
+                                    bpm: bpm.clone(),
+                                    length_secs,
+                                    ..AppState::default()
+                                };
+                                assert_round_trip(&controller::song_id(&app_state, seed));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_optional_part_round_trips() {
+        let base = AppState::default();
+        let mut variants = vec![
+            AppState { swing: "66".to_string(), ..base.clone() },
+            AppState { modulation: "On".to_string(), ..base.clone() },
+            AppState { modulation: "Off".to_string(), ..base.clone() },
+            AppState { progression: "I vi IV V".to_string(), ..base.clone() },
+            AppState { echo: "Off".to_string(), ..base.clone() },
+            AppState { nes_mode: true, ..base.clone() },
+            AppState { melody_seed: Some(7), bass_seed: Some(u64::MAX), ..base.clone() },
+            AppState { drums: false, ..base.clone() },
+            AppState { fade_out_secs: Some(0), ..base.clone() },
+            AppState { fade_out_secs: Some(gen::MAX_FADE_OUT_SECS), ..base.clone() },
+            AppState { melody_gain: "100".to_string(), chord_gain: "0".to_string(), ..base.clone() },
+        ];
+        for part in &gen::SETTING_ID_PARTS {
+            variants.extend(part.settings.iter().map(|setting| {
+                let mut app_state = base.clone();
+                *(part.get_mut)(&mut app_state) = setting.to_string();
+                app_state
+            }));
+        }
+        variants.extend(TimeSignature::ALL.iter().map(|signature| AppState {
+            time_signature: signature.label().to_string(),
+            ..base.clone()
+        }));
+        variants.extend(LOFI_SETTINGS.iter().map(|setting| AppState {
+            lofi: setting.to_string(),
+            ..base.clone()
+        }));
+        variants.extend(Waveform::ALL.iter().map(|waveform| AppState {
+            waveforms: [waveform.label().to_string(), gen::AUTO_WAVEFORM.to_string(), waveform.label().to_string()],
+            ..base.clone()
+        }));
+        for app_state in variants {
+            assert_round_trip(&controller::song_id(&app_state, 12345));
+        }
+    }
+
+    #[test]
+    fn a_mistyped_code_is_rejected() {
+        let code = share_code("v2:C-Pop-120-5-42").unwrap();
+        let body = strip_share_code_prefix(&code).unwrap();
+        assert!(song_id_from_share_code(&body[..body.len() - 2]).is_err());
+        assert!(song_id_from_share_code("0Il").is_err());
+    }
+}
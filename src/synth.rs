@@ -0,0 +1,377 @@
+/* Waveform - The tone an oscillator plays.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,   // The bright chiptune pulse, at half duty.
+    Sine,     // A soft, pure tone.
+    Triangle, // The hollow tone of the NES bass channel.
+    Sawtooth, // A buzzy tone, rich in harmonics.
+    Noise,    // Pitched noise, changing value `NOISE_STEPS_PER_CYCLE` times a cycle.
+}
+
+// NOISE_STEPS_PER_CYCLE: How many random values the noise waveform holds per cycle, so a
+// higher note sounds like brighter noise.
+const NOISE_STEPS_PER_CYCLE: f64 = 8.0;
+
+// PWM_RATE_HZ: How many times a second a sweeping pulse width swings back and forth.
+const PWM_RATE_HZ: f64 = 2.0;
+// MIN_DUTY: The thinnest pulse a sweep can reach, so the square never falls silent.
+const MIN_DUTY: f64 = 0.05;
+
+/* PulseWidth - How much of each cycle a square wave spends high.
+ *
+ * fields:
+ *     - duty (f32): The fraction of the cycle, e.g. 0.125 for a thin, nasal pulse or 0.5 for
+ *       an even square.
+ *     - sweep (f32): How far the duty swings either side of `duty`, `PWM_RATE_HZ` times a
+ *       second from the start of each note; 0.0 holds it still.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PulseWidth {
+    pub duty: f32,
+    pub sweep: f32,
+}
+
+impl PulseWidth {
+    // EVEN: The even square wave, held still.
+    pub const EVEN: PulseWidth = PulseWidth { duty: 0.5, sweep: 0.0 };
+
+    /* duty_at - Returns the duty a time into a note.
+     *
+     * inputs:
+     *     - self
+     *     - seconds (f64): Time since the note started.
+     *
+     * outputs:
+     *     - f64: The duty, kept between `MIN_DUTY` and 1.0 - `MIN_DUTY`.
+     */
+    pub fn duty_at(self, seconds: f64) -> f64 {
+        if self.sweep == 0.0 {
+            // Held still, so there is no sweep to work out
+            return (self.duty as f64).clamp(MIN_DUTY, 1.0 - MIN_DUTY);
+        }
+        let swing = self.sweep as f64 * (seconds * PWM_RATE_HZ * std::f64::consts::TAU).sin();
+        (self.duty as f64 + swing).clamp(MIN_DUTY, 1.0 - MIN_DUTY)
+    }
+}
+
+impl Waveform {
+    // ALL: Every waveform, in the order the waveform settings cycle through.
+    pub const ALL: [Waveform; 5] = [
+        Waveform::Square,
+        Waveform::Sine,
+        Waveform::Triangle,
+        Waveform::Sawtooth,
+        Waveform::Noise,
+    ];
+
+    /* label - Returns the waveform's display name, e.g. "Triangle".
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - &'static str: The label.
+     */
+    pub fn label(self) -> &'static str {
+        match self {
+            Waveform::Square => "Square",
+            Waveform::Sine => "Sine",
+            Waveform::Triangle => "Triangle",
+            Waveform::Sawtooth => "Saw",
+            Waveform::Noise => "Noise",
+        }
+    }
+
+    /* from_label - Finds the waveform with a display name.
+     *
+     * inputs:
+     *     - label (&str): The label, as returned by `label`.
+     *
+     * outputs:
+     *     - Option<Waveform>: The waveform, or None for any other text (such as "Auto").
+     */
+    pub fn from_label(label: &str) -> Option<Waveform> {
+        Waveform::ALL.into_iter().find(|waveform| waveform.label() == label.trim())
+    }
+
+    /* id_char - Returns the letter that stands for the waveform in a song ID.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - char: 'q' (square), 's' (sine), 't' (triangle), 'w' (saw) or 'n' (noise).
+     */
+    pub fn id_char(self) -> char {
+        match self {
+            Waveform::Square => 'q',
+            Waveform::Sine => 's',
+            Waveform::Triangle => 't',
+            Waveform::Sawtooth => 'w',
+            Waveform::Noise => 'n',
+        }
+    }
+
+    /* from_id_char - Finds the waveform a song ID letter stands for.
+     *
+     * inputs:
+     *     - letter (char): The letter, as returned by `id_char`.
+     *
+     * outputs:
+     *     - Option<Waveform>: The waveform, or None for any other letter.
+     */
+    pub fn from_id_char(letter: char) -> Option<Waveform> {
+        Waveform::ALL.into_iter().find(|waveform| waveform.id_char() == letter)
+    }
+}
+
+// VIBRATO_RAMP_SECONDS: How long vibrato takes to reach its full depth once it sets in.
+const VIBRATO_RAMP_SECONDS: f64 = 0.2;
+
+/* Vibrato - A slow wobble in an oscillator's pitch that sets in after a delay.
+ *
+ * fields:
+ *     - depth_cents (f32): How far the pitch swings either way at full depth, in cents
+ *       (hundredths of a semitone); 0.0 for none.
+ *     - rate_hz (f32): Swings per second.
+ *     - delay_seconds (f32): How long the pitch holds still before the vibrato ramps in over
+ *       `VIBRATO_RAMP_SECONDS`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vibrato {
+    pub depth_cents: f32,
+    pub rate_hz: f32,
+    pub delay_seconds: f32,
+}
+
+impl Vibrato {
+    // NONE: A steady pitch.
+    pub const NONE: Vibrato = Vibrato {
+        depth_cents: 0.0,
+        rate_hz: 0.0,
+        delay_seconds: 0.0,
+    };
+
+    /* pitch_ratio - Returns how far the vibrato has bent the pitch a time into a note.
+     *
+     * inputs:
+     *     - self
+     *     - seconds (f64): Time since the note started.
+     *
+     * outputs:
+     *     - f64: The frequency multiplier, 1.0 before the vibrato sets in.
+     */
+    fn pitch_ratio(self, seconds: f64) -> f64 {
+        let since_onset = seconds - self.delay_seconds as f64;
+        if self.depth_cents == 0.0 || since_onset <= 0.0 {
+            return 1.0;
+        }
+        let depth = self.depth_cents as f64 * (since_onset / VIBRATO_RAMP_SECONDS).min(1.0);
+        let cents = depth * (since_onset * self.rate_hz as f64 * std::f64::consts::TAU).sin();
+        (cents / 1200.0).exp2()
+    }
+}
+
+/* noise_value - Returns a repeatable pseudo-random value for a step of the noise waveform.
+ *
+ * inputs:
+ *     - step (u64): The step, counted from the start of the note.
+ *
+ * outputs:
+ *     - f32: A value from -1.0 to 1.0, always the same for the same step.
+ */
+fn noise_value(step: u64) -> f32 {
+    // SplitMix64's finalizer, which scatters neighbouring steps far apart
+    let mut z = step.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+/* wave - Returns the value of a waveform at a point of its cycle, with an even square.
+ *
+ * inputs:
+ *     - waveform (Waveform): The waveform.
+ *     - phase (f64): Cycles since the oscillator started; only the noise uses the whole part.
+ *
+ * outputs:
+ *     - f32: The value, from -1.0 to 1.0 (see `pulse_wave`).
+ */
+pub fn wave(waveform: Waveform, phase: f64) -> f32 {
+    pulse_wave(waveform, phase, 0.5)
+}
+
+/* pulse_wave - Returns the value of a waveform at a point of its cycle.
+ *
+ * A square is high for the first `duty` of each cycle. It is centred so it averages zero,
+ * as a sound chip's output does once it is AC-coupled, and scaled so its taller side peaks
+ * at 1.0: a 12.5% pulse swings from 1.0 down to about -0.14.
+ *
+ * inputs:
+ *     - waveform (Waveform): The waveform.
+ *     - phase (f64): Cycles since the oscillator started; only the noise uses the whole part.
+ *     - duty (f64): The fraction of each cycle a square spends high; other waveforms ignore it.
+ *
+ * outputs:
+ *     - f32: The value, from -1.0 to 1.0. Every waveform but the noise starts at its cycle's
+ *       start (0.0, or the high side for the square) and a sine, triangle and saw rise from there.
+ */
+pub fn pulse_wave(waveform: Waveform, phase: f64, duty: f64) -> f32 {
+    let cycle = phase.rem_euclid(1.0);
+    let value = match waveform {
+        Waveform::Square => {
+            let duty = duty.clamp(MIN_DUTY, 1.0 - MIN_DUTY);
+            let high = if cycle < duty { 1.0 - duty } else { -duty };
+            high / duty.max(1.0 - duty)
+        }
+        Waveform::Sine => (cycle * std::f64::consts::TAU).sin(),
+        // Rises from 0 to 1 over the first quarter, down to -1 and back up to 0
+        Waveform::Triangle => 1.0 - 4.0 * (cycle - 0.25).abs().min((cycle - 1.25).abs()),
+        Waveform::Sawtooth => if cycle < 0.5 { 2.0 * cycle } else { 2.0 * cycle - 2.0 },
+        Waveform::Noise => return noise_value((phase.max(0.0) * NOISE_STEPS_PER_CYCLE) as u64),
+    };
+    value as f32
+}
+
+/* Oscillator - A waveform playing at a steady pitch, one sample at a time.
+ *
+ * fields:
+ *     - waveform (Waveform): The tone.
+ *     - pulse_width (PulseWidth): The duty of a square wave.
+ *     - vibrato (Vibrato): The wobble in pitch.
+ *     - step (f64): Cycles per sample, before vibrato.
+ *     - phase (f64): Cycles played so far.
+ *     - seconds_per_sample (f64): The length of a sample, for the pulse width sweep and vibrato.
+ *     - samples (u64): Samples played so far.
+ */
+pub struct Oscillator {
+    waveform: Waveform,
+    pulse_width: PulseWidth,
+    vibrato: Vibrato,
+    step: f64,
+    phase: f64,
+    seconds_per_sample: f64,
+    samples: u64,
+}
+
+impl Oscillator {
+    /* new - Starts an oscillator at the start of its cycle.
+     *
+     * inputs:
+     *     - waveform (Waveform): The tone.
+     *     - frequency (f64): The pitch, in Hz.
+     *     - sample_rate (f64): Samples per second.
+     *
+     * outputs:
+     *     - Oscillator: The oscillator, its first sample at phase 0, with an even square and
+     *       no vibrato.
+     */
+    pub fn new(waveform: Waveform, frequency: f64, sample_rate: f64) -> Oscillator {
+        Oscillator {
+            waveform,
+            pulse_width: PulseWidth::EVEN,
+            vibrato: Vibrato::NONE,
+            step: frequency / sample_rate,
+            phase: 0.0,
+            seconds_per_sample: 1.0 / sample_rate,
+            samples: 0,
+        }
+    }
+
+    /* with_pulse_width - Sets the duty the oscillator's square wave plays at.
+     *
+     * inputs:
+     *     - self
+     *     - pulse_width (PulseWidth): The duty, and how far it sweeps from the first sample on.
+     *
+     * outputs:
+     *     - Oscillator: The oscillator, otherwise unchanged.
+     */
+    pub fn with_pulse_width(self, pulse_width: PulseWidth) -> Oscillator {
+        Oscillator { pulse_width, ..self }
+    }
+
+    /* with_vibrato - Sets the wobble in the oscillator's pitch.
+     *
+     * inputs:
+     *     - self
+     *     - vibrato (Vibrato): The vibrato, timed from the first sample.
+     *
+     * outputs:
+     *     - Oscillator: The oscillator, otherwise unchanged.
+     */
+    pub fn with_vibrato(self, vibrato: Vibrato) -> Oscillator {
+        Oscillator { vibrato, ..self }
+    }
+
+    /* next_sample - Returns the oscillator's next sample.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - f32: The sample, from -1.0 to 1.0.
+     */
+    pub fn next_sample(&mut self) -> f32 {
+        let seconds = self.samples as f64 * self.seconds_per_sample;
+        // Only a square has a duty
+        let duty = match self.waveform {
+            Waveform::Square => self.pulse_width.duty_at(seconds),
+            _ => PulseWidth::EVEN.duty as f64,
+        };
+        let value = pulse_wave(self.waveform, self.phase, duty);
+        // The phase advances by the bent pitch, so the wave stays continuous as it wobbles
+        self.phase += self.step * self.vibrato.pitch_ratio(seconds);
+        self.samples += 1;
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SAMPLE_RATE: Sample rate of the test oscillators.
+    const SAMPLE_RATE: f64 = 44100.0;
+    // FREQUENCIES: Pitches from a low bass note to a high lead, with steps that don't divide a cycle evenly.
+    const FREQUENCIES: [f64; 4] = [41.2, 110.0, 440.0, 2637.02];
+    // SINE_TOLERANCE: How far a sine may drift from the analytic one over ten seconds, as the
+    // running phase gathers rounding.
+    const SINE_TOLERANCE: f64 = 1e-5;
+
+    // The oscillator's first `seconds` of output
+    fn play(mut oscillator: Oscillator, seconds: f64) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE) as usize).map(|_| oscillator.next_sample()).collect()
+    }
+
+    #[test]
+    fn a_sine_follows_the_analytic_sine() {
+        for frequency in FREQUENCIES {
+            let samples = play(Oscillator::new(Waveform::Sine, frequency, SAMPLE_RATE), 10.0);
+            for (i, sample) in samples.into_iter().enumerate() {
+                let t = i as f64 / SAMPLE_RATE;
+                let expected = (std::f64::consts::TAU * frequency * t).sin();
+                assert!((sample as f64 - expected).abs() < SINE_TOLERANCE, "{} Hz at sample {}: {} vs {}", frequency, i, sample, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn an_even_square_is_the_sign_of_the_sine() {
+        for frequency in FREQUENCIES {
+            let samples = play(Oscillator::new(Waveform::Square, frequency, SAMPLE_RATE), 10.0);
+            for (i, sample) in samples.into_iter().enumerate() {
+                let cycles = frequency * i as f64 / SAMPLE_RATE;
+                // A sample landing right on an edge may fall either side of it
+                let from_edge = (cycles * 2.0 - (cycles * 2.0).round()).abs();
+                if from_edge < 1e-6 {
+                    continue;
+                }
+                let expected = (std::f64::consts::TAU * cycles).sin().signum() as f32;
+                assert_eq!(sample, expected, "{} Hz at sample {}", frequency, i);
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use ratatui::backend::{Backend, ClearType, WindowSize};
+use ratatui::buffer::Cell;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier};
+use std::io;
+
+// NO_CURSOR_TERMS: Terminal types that can't position the cursor, so the TUI can't be drawn at all.
+const NO_CURSOR_TERMS: &[&str] = &["dumb", "unknown", "cons25"];
+
+// NO_ALTERNATE_SCREEN_TERMS: Terminal types without an alternate screen; the TUI draws over the
+// normal screen instead and clears it on exit.
+const NO_ALTERNATE_SCREEN_TERMS: &[&str] = &["linux", "vt52", "vt100", "vt102", "vt220", "ansi"];
+
+// MONOCHROME_TERMS: Terminal types known to have no colors.
+const MONOCHROME_TERMS: &[&str] = &["vt52", "vt100", "vt102", "vt220"];
+
+// ASCII_ONLY_TERMS: Terminal types whose fonts lack the block, braille and symbol glyphs the TUI uses.
+const ASCII_ONLY_TERMS: &[&str] = &["linux", "vt52", "vt100", "vt102", "vt220", "ansi"];
+
+/* Capabilities - What the terminal can display, decided once at startup.
+ *
+ * fields:
+ *     - alternate_screen (bool): True if the TUI can switch to the alternate screen.
+ *     - colors (bool): True if colors are shown; otherwise highlights use reverse video.
+ *     - unicode (bool): True if non-ASCII glyphs are shown; otherwise ASCII stand-ins are drawn.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub alternate_screen: bool,
+    pub colors: bool,
+    pub unicode: bool,
+}
+
+/* Support - The outcome of the startup capability check.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Support {
+    Tui(Capabilities),   // The TUI can run, possibly degraded
+    Unsupported(String), // The TUI can't run; the message explains why and what to use instead
+}
+
+/* decide_support - Decides how (or whether) the TUI can run on a terminal.
+ *
+ * inputs:
+ *     - term (Option<&str>): The TERM environment variable, if set.
+ *     - stdout_is_tty (bool): True if stdout is an interactive terminal.
+ *     - locale (Option<&str>): The effective locale (LC_ALL, LC_CTYPE or LANG), if set.
+ *
+ * outputs:
+ *     - Support: The capabilities to run with, or the reason the TUI can't run.
+ */
+pub fn decide_support(term: Option<&str>, stdout_is_tty: bool, locale: Option<&str>) -> Support {
+    if !stdout_is_tty {
+        return Support::Unsupported(
+            "8BitBeats needs an interactive terminal, but its output is not a terminal. \
+             Run it with --a11y for a plain line-based interface."
+                .to_string(),
+        );
+    }
+    let term = term.unwrap_or("").trim().to_ascii_lowercase();
+    if term.is_empty() || NO_CURSOR_TERMS.contains(&term.as_str()) {
+        let shown = if term.is_empty() { "unset" } else { term.as_str() };
+        return Support::Unsupported(format!(
+            "8BitBeats needs a terminal that can position the cursor (TERM is {}). \
+             Run it with --a11y for a plain line-based interface.",
+            shown
+        ));
+    }
+    // Only an explicit non-UTF-8 charset (e.g. "en_US.ISO-8859-1") rules out Unicode; plain
+    // "C" or "POSIX" locales are common in terminals that render UTF-8 fine
+    let non_utf8_locale = locale
+        .and_then(|locale| locale.split('@').next())
+        .and_then(|locale| locale.split_once('.'))
+        .is_some_and(|(_, charset)| {
+            let charset = charset.to_ascii_lowercase();
+            charset != "utf-8" && charset != "utf8"
+        });
+    Support::Tui(Capabilities {
+        alternate_screen: !NO_ALTERNATE_SCREEN_TERMS.contains(&term.as_str()),
+        colors: !MONOCHROME_TERMS.contains(&term.as_str())
+            && !term.ends_with("-mono")
+            && !term.ends_with("-m"),
+        unicode: !ASCII_ONLY_TERMS.contains(&term.as_str()) && !non_utf8_locale,
+    })
+}
+
+/* detect_support - Runs `decide_support` on the real environment.
+ *
+ * inputs:
+ *     - stdout_is_tty (bool): True if the output the TUI would draw to is an interactive terminal.
+ *
+ * outputs:
+ *     - Support: The capabilities to run with, or the reason the TUI can't run.
+ */
+pub fn detect_support(stdout_is_tty: bool) -> Support {
+    let term = std::env::var("TERM").ok();
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+    decide_support(term.as_deref(), stdout_is_tty, locale.as_deref())
+}
+
+/* ascii_symbol - Returns an ASCII stand-in for a glyph the terminal can't show.
+ *
+ * inputs:
+ *     - symbol (&str): The cell's symbol.
+ *
+ * outputs:
+ *     - Option<&'static str>: The replacement, or None if the symbol is already ASCII.
+ */
+fn ascii_symbol(symbol: &str) -> Option<&'static str> {
+    let ch = symbol.chars().next()?;
+    if symbol.is_ascii() {
+        return None;
+    }
+    let replacement = match ch {
+        '▼' => "v",
+        '▲' => "^",
+        '▷' | '▶' => ">",
+        '⏮' => "<",
+        '⟳' => "@",
+        '■' => "#",
+        '♪' => "*",
+        '…' => ".",
+        '─' | '━' | '═' => "-",
+        '│' | '┃' | '║' => "|",
+        '\u{2500}'..='\u{257f}' => "+", // Other box drawing (corners, joins)
+        '\u{2580}'..='\u{259f}' => "#", // Block elements (meters, gauges, bars)
+        '\u{2800}'..='\u{28ff}' => ".", // Braille (chart markers)
+        _ => "?",
+    };
+    Some(replacement)
+}
+
+/* CapabilityBackend - Wraps a backend and adapts every drawn cell to the terminal's capabilities.
+ *
+ * Drawing code stays the same everywhere: colors become reverse video (for highlights) or
+ * plain text on monochrome terminals, and non-ASCII glyphs become ASCII stand-ins.
+ *
+ * fields:
+ *     - inner (B): The real backend.
+ *     - capabilities (Capabilities): What the terminal can display.
+ */
+pub struct CapabilityBackend<B: Backend> {
+    inner: B,
+    capabilities: Capabilities,
+}
+
+impl<B: Backend> CapabilityBackend<B> {
+    /* new - Wraps a backend.
+     *
+     * inputs:
+     *     - inner (B): The real backend.
+     *     - capabilities (Capabilities): What the terminal can display.
+     *
+     * outputs:
+     *     - Self: The wrapping backend.
+     */
+    pub fn new(inner: B, capabilities: Capabilities) -> Self {
+        Self {
+            inner,
+            capabilities,
+        }
+    }
+
+    /* adapt_cell - Returns a copy of a cell that the terminal can display.
+     *
+     * inputs:
+     *     - &self
+     *     - cell (&Cell): The cell as drawn.
+     *
+     * outputs:
+     *     - Cell: The adapted cell.
+     */
+    fn adapt_cell(&self, cell: &Cell) -> Cell {
+        let mut cell = cell.clone();
+        if !self.capabilities.colors {
+            // Yellow marks focus and highlights throughout the UI, so it keeps a visible form
+            if cell.fg == Color::Yellow || cell.bg != Color::Reset {
+                cell.modifier |= Modifier::REVERSED;
+            }
+            cell.fg = Color::Reset;
+            cell.bg = Color::Reset;
+            cell.underline_color = Color::Reset;
+        }
+        if !self.capabilities.unicode {
+            if let Some(replacement) = ascii_symbol(cell.symbol()) {
+                cell.set_symbol(replacement);
+            }
+        }
+        cell
+    }
+}
+
+impl<B: Backend> Backend for CapabilityBackend<B> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        let adapted: Vec<(u16, u16, Cell)> = content
+            .map(|(x, y, cell)| (x, y, self.adapt_cell(cell)))
+            .collect();
+        self.inner
+            .draw(adapted.iter().map(|(x, y, cell)| (*x, *y, cell)))
+    }
+
+    fn append_lines(&mut self, n: u16) -> io::Result<()> {
+        self.inner.append_lines(n)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.inner.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.inner.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        self.inner.get_cursor()
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.inner.set_cursor(x, y)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        self.inner.clear_region(clear_type)
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        self.inner.size()
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        self.inner.window_size()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tui(alternate_screen: bool, colors: bool, unicode: bool) -> Support {
+        Support::Tui(Capabilities {
+            alternate_screen,
+            colors,
+            unicode,
+        })
+    }
+
+    #[test]
+    fn terminals_get_the_capabilities_their_type_and_locale_allow() {
+        // (TERM, locale, expected)
+        let cases = [
+            (Some("xterm-256color"), Some("en_US.UTF-8"), tui(true, true, true)),
+            (Some("XTERM-256COLOR "), None, tui(true, true, true)),
+            (Some("screen"), Some("C"), tui(true, true, true)),
+            (Some("tmux-256color"), Some("POSIX"), tui(true, true, true)),
+            (Some("xterm"), Some("de_DE.utf8@euro"), tui(true, true, true)),
+            (Some("xterm"), Some("en_US.ISO-8859-1"), tui(true, true, false)),
+            (Some("xterm"), Some("ja_JP.eucJP@cjk"), tui(true, true, false)),
+            (Some("linux"), Some("en_US.UTF-8"), tui(false, true, false)),
+            (Some("ansi"), None, tui(false, true, false)),
+            (Some("vt100"), None, tui(false, false, false)),
+            (Some("vt220"), Some("en_US.UTF-8"), tui(false, false, false)),
+            (Some("xterm-mono"), None, tui(true, false, true)),
+            (Some("aterm-m"), None, tui(true, false, true)),
+        ];
+        for (term, locale, expected) in cases {
+            assert_eq!(decide_support(term, true, locale), expected, "TERM={:?} locale={:?}", term, locale);
+        }
+    }
+
+    #[test]
+    fn terminals_that_cant_position_the_cursor_are_unsupported() {
+        for term in [None, Some(""), Some("  "), Some("dumb"), Some("DUMB"), Some("unknown"), Some("cons25")] {
+            let support = decide_support(term, true, Some("en_US.UTF-8"));
+            let Support::Unsupported(message) = support else {
+                panic!("TERM={:?} was supported", term);
+            };
+            assert!(message.contains("--a11y"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn output_that_is_not_a_terminal_is_unsupported() {
+        let support = decide_support(Some("xterm-256color"), false, Some("en_US.UTF-8"));
+        assert!(matches!(support, Support::Unsupported(message) if message.contains("not a terminal")));
+    }
+}
@@ -0,0 +1,318 @@
+use ratatui::style::{Color, Modifier, Style};
+
+// THEME_NAMES: Built-in themes, selectable with `theme = "..."` in the config file.
+pub const THEME_NAMES: &[&str] = &["default", "gruvbox", "monochrome"];
+
+/* Palette - The colors a theme draws with.
+ *
+ * fields:
+ *     - accent (Color): Title art and the progress gauge.
+ *     - focus (Color): The focused control, notices and other highlights.
+ *     - edit (Color): A control being edited or with its popup open.
+ *     - error (Color): Errors and warnings.
+ *     - dim (Color): Secondary text and empty meter cells.
+ *     - info (Color): The current chord.
+ *     - active (Color): The oscilloscope trace and the loop badge.
+ *     - visual (Color): The spectrum analyzer and the debug overlay.
+ *     - surface (Color): Popup backgrounds.
+ *     - text (Color): Text on popup backgrounds.
+ *     - on_focus (Color): Text on a `focus` background (selected list items).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Palette {
+    accent: Color,
+    focus: Color,
+    edit: Color,
+    error: Color,
+    dim: Color,
+    info: Color,
+    active: Color,
+    visual: Color,
+    surface: Color,
+    text: Color,
+    on_focus: Color,
+}
+
+// DEFAULT_PALETTE: The original 8BitBeats colors.
+const DEFAULT_PALETTE: Palette = Palette {
+    accent: Color::Blue,
+    focus: Color::Yellow,
+    edit: Color::Green,
+    error: Color::Red,
+    dim: Color::DarkGray,
+    info: Color::Cyan,
+    active: Color::Green,
+    visual: Color::Magenta,
+    surface: Color::DarkGray,
+    text: Color::White,
+    on_focus: Color::Black,
+};
+
+// GRUVBOX_PALETTE: Warm, muted colors after the gruvbox scheme, from the 256-color palette.
+const GRUVBOX_PALETTE: Palette = Palette {
+    accent: Color::Indexed(109),
+    focus: Color::Indexed(214),
+    edit: Color::Indexed(142),
+    error: Color::Indexed(167),
+    dim: Color::Indexed(243),
+    info: Color::Indexed(108),
+    active: Color::Indexed(142),
+    visual: Color::Indexed(175),
+    surface: Color::Indexed(237),
+    text: Color::Indexed(223),
+    on_focus: Color::Indexed(235),
+};
+
+/* Theme - How the TUI colors (or, without colors, emphasizes) each kind of element.
+ *
+ * Draw code asks for a role (`focus`, `edit`, ...) rather than a color, so a theme without
+ * colors can fall back to bold, underline and reverse video.
+ *
+ * fields:
+ *     - palette (Option<Palette>): The colors, or None for the style-free theme.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    palette: Option<Palette>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            palette: Some(DEFAULT_PALETTE),
+        }
+    }
+}
+
+impl Theme {
+    /* named - Looks up a built-in theme.
+     *
+     * inputs:
+     *     - name (&str): One of `THEME_NAMES`.
+     *
+     * outputs:
+     *     - Option<Self>: The theme, or None for an unknown name.
+     */
+    pub fn named(name: &str) -> Option<Self> {
+        let palette = match name {
+            "default" => Some(DEFAULT_PALETTE),
+            "gruvbox" => Some(GRUVBOX_PALETTE),
+            "monochrome" => None,
+            _ => return None,
+        };
+        Some(Self { palette })
+    }
+
+    /* select - Picks the theme to draw with.
+     *
+     * NO_COLOR (https://no-color.org) and terminals without colors always get the style-free
+     * theme, whatever the config file asks for.
+     *
+     * inputs:
+     *     - name (Option<&str>): The configured theme, if any.
+     *     - colors (bool): False if the terminal can't show colors.
+     *
+     * outputs:
+     *     - Self: The theme.
+     */
+    pub fn select(name: Option<&str>, colors: bool) -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        if no_color || !colors {
+            return Self { palette: None };
+        }
+        name.and_then(Self::named).unwrap_or_default()
+    }
+
+    /* role - Builds the style for one role.
+     *
+     * inputs:
+     *     - &self
+     *     - color (fn(&Palette) -> Color): Picks the role's color from the palette.
+     *     - fallback (Modifier): Emphasis used instead when there are no colors.
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    fn role(&self, color: fn(&Palette) -> Color, fallback: Modifier) -> Style {
+        match &self.palette {
+            Some(palette) => Style::default().fg(color(palette)),
+            None => Style::default().add_modifier(fallback),
+        }
+    }
+
+    /* accent - Style for title art.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn accent(&self) -> Style {
+        self.role(|palette| palette.accent, Modifier::empty())
+    }
+
+    /* focus - Style for the focused control and highlights.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn focus(&self) -> Style {
+        self.role(|palette| palette.focus, Modifier::BOLD | Modifier::UNDERLINED)
+    }
+
+    /* edit - Style for a control being edited or with its popup open.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn edit(&self) -> Style {
+        self.role(|palette| palette.edit, Modifier::BOLD | Modifier::REVERSED)
+    }
+
+    /* error - Style for errors and warnings.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn error(&self) -> Style {
+        self.role(|palette| palette.error, Modifier::BOLD)
+    }
+
+    /* dim - Style for secondary text.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn dim(&self) -> Style {
+        self.role(|palette| palette.dim, Modifier::DIM)
+    }
+
+    /* info - Style for the current chord.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn info(&self) -> Style {
+        self.role(|palette| palette.info, Modifier::empty())
+    }
+
+    /* active - Style for the oscilloscope trace and the loop badge.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn active(&self) -> Style {
+        self.role(|palette| palette.active, Modifier::empty())
+    }
+
+    /* visual - Style for the spectrum analyzer and the debug overlay.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn visual(&self) -> Style {
+        self.role(|palette| palette.visual, Modifier::empty())
+    }
+
+    /* surface - Background style for popups.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn surface(&self) -> Style {
+        match &self.palette {
+            Some(palette) => Style::default().bg(palette.surface),
+            None => Style::default(),
+        }
+    }
+
+    /* text - Style for plain text on a popup background.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn text(&self) -> Style {
+        self.role(|palette| palette.text, Modifier::empty())
+    }
+
+    /* selection - Style for the selected item of a list.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn selection(&self) -> Style {
+        match &self.palette {
+            Some(palette) => Style::default().bg(palette.focus).fg(palette.on_focus),
+            None => Style::default().add_modifier(Modifier::REVERSED),
+        }
+    }
+
+    /* gauge - Style for the progress gauge: the filled part in the accent color over a dim track.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Style: The style.
+     */
+    pub fn gauge(&self) -> Style {
+        match &self.palette {
+            Some(palette) => Style::default().fg(palette.accent).bg(palette.dim),
+            None => Style::default(),
+        }
+    }
+
+    /* meter - Style for one lit cell of the level meter.
+     *
+     * inputs:
+     *     - &self
+     *     - position (f32): The cell's position along the meter (0.0 to 1.0).
+     *
+     * outputs:
+     *     - Style: Edit color when quiet, focus color when loud, error color near clipping.
+     */
+    pub fn meter(&self, position: f32) -> Style {
+        let Some(palette) = &self.palette else {
+            return Style::default();
+        };
+        let color = if position > 0.9 {
+            palette.error
+        } else if position > 0.7 {
+            palette.focus
+        } else {
+            palette.edit
+        };
+        Style::default().fg(color)
+    }
+}
@@ -1,18 +1,48 @@
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    cursor::MoveTo,
+    terminal::{
+        self, disable_raw_mode, enable_raw_mode, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use ratatui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction as LayoutDirection, Layout, Rect},
-    style::{Color, Modifier, Style, Stylize},
+    layout::{Alignment, Constraint, Direction as LayoutDirection, Layout, Margin, Rect},
+    style::{Modifier, Style, Stylize},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph},
-    Terminal,
+    symbols,
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Clear, Dataset, Gauge, GraphType,
+        List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState,
+    },
+    Frame, Terminal,
 };
 
-use std::{collections::HashMap, io, sync::OnceLock};
+use std::{
+    collections::HashMap,
+    io,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::clipboard;
+use crate::controller::{RewindTarget, VariedTrack};
+use crate::gen::{
+    next_arp_chords, next_brightness, next_comping, next_counter_melody, next_echo, next_harmony, next_lofi, next_modulation, next_pulse_width,
+    format_song_length, next_waveform, parse_bpm, parse_song_length, SongSection, AUTO_ARP_CHORDS, AUTO_BRIGHTNESS, AUTO_COMPING, AUTO_COUNTER_MELODY, AUTO_ECHO, AUTO_LOFI, AUTO_MODULATION, AUTO_PULSE_WIDTH, AUTO_TIME_SIGNATURE,
+    AUTO_WAVEFORM, DEFAULT_SONG_SECS, ENDLESS_LENGTH_LABEL, ENDLESS_SONG_SECS, HARMONY_OFF,
+    MAX_CROSSFADE_SECS, MAX_SWING_PERCENT, PROGRESSION_ID_SEPARATOR, SCALE_NAMES, SONG_ID_VERSION, SONG_ID_VERSION_SEPARATOR, STYLE_NAMES,
+    WAVEFORM_TRACKS,
+};
+use crate::library::{self, LibraryEntry};
+use crate::progs::{self, TimeSignature};
+use crate::theme::Theme;
+use crate::workers::WorkerStats;
 
 
 /* UserAction - Represents all possible actions a user can trigger in the TUI.
@@ -20,6 +50,7 @@ use std::{collections::HashMap, io, sync::OnceLock};
  * This enum is used to communicate user intentions from the input handling logic
  * to the main application loop, which then acts upon these actions.
  */
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UserAction {
     Quit,
     TogglePlayback,
@@ -36,8 +67,39 @@ pub enum UserAction {
     AttemptLoadSong,
     CloseSongIdErrorPopup,
     RewindSong,
+    Seek(f32), // Continue the current song from a fraction (0.0-1.0) of its length.
     FastForwardSong,
+    PreviousSong,
+    ToggleSaveSong,
+    RemoveLibraryEntry,
+    CopySongId,
+    ShareSong,
+    CopyShareCode,
+    ToggleStopAfterCurrent,
     ToggleHelp,
+    VolumeUp,
+    VolumeDown,
+    ScrollPopup,
+    DismissWhatsNew,
+    ToggleMute,
+    ToggleScope,
+    ToggleSpectrum,
+    ToggleLoop,
+    ToggleDrums,
+    CycleCrossfade,
+    CycleModulation,
+    CycleHarmony,
+    CycleCounterMelody,
+    CycleArpChords,
+    CycleComping,
+    CyclePulseWidth,
+    CycleBrightness,
+    CycleEcho,
+    CycleLoFi,
+    ToggleNesMode,
+    VarySong(VariedTrack), // Replays the current song with a new melody or bass.
+    CycleWaveform(usize), // Steps the waveform of a track (an index into `WAVEFORM_TRACKS`).
+    ToggleDebug,
 }
 
 /* Direction - Represents navigational directions within the TUI.
@@ -59,14 +121,22 @@ pub enum Direction {
  */
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum InputId {
+    Previous,
     Rewind,
     PlayPause,
     Skip,
     Scale,
+    Quality,
     Style,
     Bpm,
+    Swing,
+    TimeSignature,
     Length,
     Seed,
+    Progression,
+    MelodyGain,
+    ChordGain,
+    BassGain,
     Generate,
     GenerateRandom,
     SongLoader,
@@ -87,6 +157,7 @@ struct InputNode {
 
 // INPUT_GRAPH defines the static navigation map between UI elements.
 // It uses InputId as keys and InputNode to define reachable neighbors.
+// The `/` shortcut jumps straight to SongLoader from anywhere, outside of this graph.
 static INPUT_GRAPH: OnceLock<HashMap<InputId, InputNode>> = OnceLock::new();
 
 /* get_input_graph - Retrieves or initializes the TUI navigation graph.
@@ -106,16 +177,27 @@ fn get_input_graph() -> &'static HashMap<InputId, InputNode> {
     INPUT_GRAPH.get_or_init(|| {
         let mut graph = HashMap::new();
         graph.insert(
-            InputId::Rewind,
+            InputId::Previous,
             InputNode {
                 neighbors: HashMap::from([
-                    (Direction::Right, InputId::PlayPause),
+                    (Direction::Right, InputId::Rewind),
                     (Direction::Left, InputId::Skip),
                     (Direction::Down, InputId::Scale),
                 ]),
             },
         );
 
+        graph.insert(
+            InputId::Rewind,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Right, InputId::PlayPause),
+                    (Direction::Left, InputId::Previous),
+                    (Direction::Down, InputId::Quality),
+                ]),
+            },
+        );
+
         graph.insert(
             InputId::PlayPause,
             InputNode {
@@ -131,7 +213,7 @@ fn get_input_graph() -> &'static HashMap<InputId, InputNode> {
             InputId::Skip,
             InputNode {
                 neighbors: HashMap::from([
-                    (Direction::Right, InputId::Rewind),
+                    (Direction::Right, InputId::Previous),
                     (Direction::Left, InputId::PlayPause),
                     (Direction::Down, InputId::Style),
                 ]),
@@ -143,20 +225,32 @@ fn get_input_graph() -> &'static HashMap<InputId, InputNode> {
             InputNode {
                 neighbors: HashMap::from([
                     (Direction::Up, InputId::Rewind),
-                    (Direction::Right, InputId::Style),
+                    (Direction::Right, InputId::Quality),
                     (Direction::Left, InputId::Style),
                     (Direction::Down, InputId::Bpm),
                 ]),
             },
         );
 
+        graph.insert(
+            InputId::Quality,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::Rewind),
+                    (Direction::Right, InputId::Style),
+                    (Direction::Left, InputId::Scale),
+                    (Direction::Down, InputId::Swing),
+                ]),
+            },
+        );
+
         graph.insert(
             InputId::Style,
             InputNode {
                 neighbors: HashMap::from([
                     (Direction::Up, InputId::Skip),
                     (Direction::Right, InputId::Scale),
-                    (Direction::Left, InputId::Scale),
+                    (Direction::Left, InputId::Quality),
                     (Direction::Down, InputId::Length),
                 ]),
             },
@@ -167,21 +261,45 @@ fn get_input_graph() -> &'static HashMap<InputId, InputNode> {
             InputNode {
                 neighbors: HashMap::from([
                     (Direction::Up, InputId::Scale),
-                    (Direction::Right, InputId::Length),
+                    (Direction::Right, InputId::Swing),
                     (Direction::Left, InputId::Length),
                     (Direction::Down, InputId::Seed),
                 ]),
             },
         );
 
+        graph.insert(
+            InputId::Swing,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::Quality),
+                    (Direction::Right, InputId::TimeSignature),
+                    (Direction::Left, InputId::Bpm),
+                    (Direction::Down, InputId::Seed),
+                ]),
+            },
+        );
+
+        graph.insert(
+            InputId::TimeSignature,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::Style),
+                    (Direction::Right, InputId::Length),
+                    (Direction::Left, InputId::Swing),
+                    (Direction::Down, InputId::Progression),
+                ]),
+            },
+        );
+
         graph.insert(
             InputId::Length,
             InputNode {
                 neighbors: HashMap::from([
                     (Direction::Up, InputId::Style),
                     (Direction::Right, InputId::Bpm),
-                    (Direction::Left, InputId::Bpm),
-                    (Direction::Down, InputId::Seed),
+                    (Direction::Left, InputId::TimeSignature),
+                    (Direction::Down, InputId::Progression),
                 ]),
             },
         );
@@ -191,18 +309,66 @@ fn get_input_graph() -> &'static HashMap<InputId, InputNode> {
             InputNode {
                 neighbors: HashMap::from([
                     (Direction::Up, InputId::Bpm),
-                    (Direction::Down, InputId::Generate),
+                    (Direction::Down, InputId::MelodyGain),
                     (Direction::Left, InputId::Bpm),
+                    (Direction::Right, InputId::Progression),
+                ]),
+            },
+        );
+
+        graph.insert(
+            InputId::Progression,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::Length),
+                    (Direction::Down, InputId::BassGain),
+                    (Direction::Left, InputId::Seed),
                     (Direction::Right, InputId::Length),
                 ]),
             },
         );
 
         graph.insert(
-            InputId::Generate,
+            InputId::MelodyGain,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::Seed),
+                    (Direction::Down, InputId::Generate),
+                    (Direction::Left, InputId::BassGain),
+                    (Direction::Right, InputId::ChordGain),
+                ]),
+            },
+        );
+
+        graph.insert(
+            InputId::ChordGain,
             InputNode {
                 neighbors: HashMap::from([
                     (Direction::Up, InputId::Seed),
+                    (Direction::Down, InputId::Generate),
+                    (Direction::Left, InputId::MelodyGain),
+                    (Direction::Right, InputId::BassGain),
+                ]),
+            },
+        );
+
+        graph.insert(
+            InputId::BassGain,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::Progression),
+                    (Direction::Down, InputId::Generate),
+                    (Direction::Left, InputId::ChordGain),
+                    (Direction::Right, InputId::MelodyGain),
+                ]),
+            },
+        );
+
+        graph.insert(
+            InputId::Generate,
+            InputNode {
+                neighbors: HashMap::from([
+                    (Direction::Up, InputId::MelodyGain),
                     (Direction::Down, InputId::GenerateRandom),
                     (Direction::Left, InputId::Generate),
                     (Direction::Right, InputId::Generate),
@@ -248,12 +414,21 @@ pub enum InputMode {
     Navigation,
     Editing,
     ScalePopup,
+    QualityPopup,
     StylePopup,
     LengthPopup,
+    TimeSignaturePopup,
     SongLoaderEditing,
     SongIdErrorPopup,
+    WhatsNewPopup,
+    LibraryPopup,
+    WaveformPopup,
+    SharePopup,
 }
 
+// DEFAULT_TRACK_GAIN_PERCENT: Mixer level that reproduces the original fixed track balance.
+pub const DEFAULT_TRACK_GAIN_PERCENT: &str = "50";
+
 /* AppState - Holds the overall state of the TUI application.
  *
  * This struct centralizes all data that the TUI needs to render itself
@@ -261,16 +436,54 @@ pub enum InputMode {
  * progress information, and input field values.
  *
  * fields:
- *     - scale (String): The selected musical scale for generation.
+ *     - scale (String): The selected musical scale for generation (its root note).
+ *     - quality (String): The selected key quality for generation, "Major" or "Minor".
  *     - style (String): The selected musical style for generation.
  *     - bpm (String): The selected beats per minute for generation.
- *     - length (String): The selected length for music generation.
+ *     - length_secs (u32): The selected song length in seconds (see `parse_song_length`).
  *     - seed (String): The seed for random number generation, affecting music output.
+ *     - melody_gain (String): Melody track level in percent (0-100, 50 = default mix).
+ *     - chord_gain (String): Chord track level in percent (0-100, 50 = default mix).
+ *     - bass_gain (String): Bass track level in percent (0-100, 50 = default mix).
+ *     - drums (bool): True if generated songs include the drum track.
+ *     - harmony (String): Where a harmony voice shadows the melody: "Off", "Chorus" or "Song".
+ *     - counter_melody (String): Whether a slow counter-melody plays under the melody: "Auto"
+ *       (the style decides), "On" or "Off".
+ *     - arp_chords (String): Whether chords are played as fast chip arpeggios: "Auto" (the
+ *       style decides), "On" or "Off".
+ *     - comping (String): Whether block chords are comped in a rhythm: "Auto" (the style picks
+ *       one) or "Sustain".
+ *     - pulse_width (String): The duty of square leads and arp chords: "12.5%", "25%", "50%",
+ *       "PWM" (sweeping over each note), or "Auto" to let the style pick.
+ *     - swing (String): Swing in percent (0-100); empty uses the style's default.
+ *     - time_signature (String): "4/4", "3/4", "6/8", or "Auto" to use the style's default.
+ *     - modulation (String): Whether the last section changes key: "On", "Off", or "Auto" to let
+ *       the song's length and seed decide.
+ *     - progression (String): A custom chord progression in roman numerals (e.g. "I V vi IV");
+ *       empty plays the style's progressions.
+ *     - waveforms ([String; 3]): The waveform of the lead, chord and bass tracks (see
+ *       `WAVEFORM_TRACKS`), each a waveform label or "Auto" to use the style's.
+ *     - echo (String): Whether the melody echoes: "Auto" (as much as the style does) or "Off".
+ *     - lofi (String): How hard the mix is bit-crushed and downsampled: "Off", "Light",
+ *       "Heavy", or "Auto" to let the style decide.
+ *     - brightness (String): How much the melody, chords and bass are low-pass filtered: "Dark",
+ *       "Warm", "Bright", "Open" (unfiltered), or "Auto" to use the style's tone.
+ *     - nes_mode (bool): True if generated songs keep to the NES sound chip's channels: two
+ *       pulses, a triangle and noise.
+ *     - melody_seed (Option<u64>): Seed of a varied melody, or None to use the song's seed.
+ *     - bass_seed (Option<u64>): Seed of a varied bass line, or None to use the song's seed.
+ *     - id_version (u32): The song ID version the settings were loaded from (see
+ *       `SONG_ID_VERSION`). Generation doesn't depend on it yet: every song plays, and is shared,
+ *       the way the current version makes it.
  *     - input_mode (InputMode): The current input mode of the TUI.
  *     - popup_list_state (ListState): State for managing selection in pop-up lists.
+ *     - popup_filter (String): Text typed into an open Scale/Key/Style/Length/Time popup to narrow its
+ *       options; the popup's selection indexes the filtered options.
  *     - scales (Vec<String>): List of available musical scales.
+ *     - qualities (Vec<String>): List of available key qualities.
  *     - styles (Vec<String>): List of available musical styles.
  *     - lengths (Vec<String>): List of available music lengths.
+ *     - time_signatures (Vec<String>): List of available time signatures, "Auto" first.
  *     - is_playing (bool): True if music is currently playing, false otherwise.
  *     - current_song_progress (f32): Playback progress of the current song (0.0 to 1.0).
  *     - current_song_elapsed_secs (f32): Elapsed playback time of the current song in seconds.
@@ -279,20 +492,54 @@ pub enum InputMode {
  *     - song_id_error (Option<String>): Stores an error message if song ID loading fails.
  *     - current_song_id_display (Option<String>): The ID of the currently playing/loaded song.
  *     - show_help (bool): True if the help menu should be displayed.
+ *     - volume (f32): Master playback volume (1.0 = 100%, range 0.0 to 2.0).
+ *     - is_muted (bool): True if output is muted while playback keeps advancing.
+ *     - is_looping (bool): True if the current song repeats instead of moving on to a new one.
+ *     - crossfade_secs (u32): Seconds the next song fades in over the current one on skip and
+ *       auto-advance (0 = change songs immediately).
+ *     - fade_out_secs (Option<u32>): Seconds every song fades out over at its end, from the
+ *       config (0 = none), or None for the style's.
+ *     - whats_new_lines (Vec<String>): Changelog lines shown in the "What's new" popup.
+ *     - whats_new_scroll (u16): First visible line of the "What's new" popup.
  */
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub scale: String,
+    pub quality: String,
     pub style: String,
     pub bpm: String,
-    pub length: String,
+    pub length_secs: u32,
     pub seed: String,
+    pub melody_gain: String,
+    pub chord_gain: String,
+    pub bass_gain: String,
+    pub drums: bool,
+    pub harmony: String,
+    pub counter_melody: String,
+    pub arp_chords: String,
+    pub comping: String,
+    pub pulse_width: String,
+    pub swing: String,
+    pub time_signature: String,
+    pub modulation: String,
+    pub progression: String,
+    pub waveforms: [String; 3],
+    pub echo: String,
+    pub lofi: String,
+    pub brightness: String,
+    pub nes_mode: bool,
+    pub melody_seed: Option<u64>,
+    pub bass_seed: Option<u64>,
+    pub id_version: u32,
     pub input_mode: InputMode,
     pub popup_list_state: ListState,
+    pub popup_filter: String,
     pub is_random: bool,
     pub scales: Vec<String>,
+    pub qualities: Vec<String>,
     pub styles: Vec<String>,
     pub lengths: Vec<String>,
+    pub time_signatures: Vec<String>,
     pub is_playing: bool,
     pub current_song_progress: f32,
     pub current_song_elapsed_secs: f32,
@@ -301,44 +548,60 @@ pub struct AppState {
     pub song_id_error: Option<String>,
     pub current_song_id_display: Option<String>,
     pub show_help: bool,
+    pub volume: f32,
+    pub is_muted: bool,
+    pub is_looping: bool,
+    pub crossfade_secs: u32,
+    pub fade_out_secs: Option<u32>,
+    pub whats_new_lines: Vec<String>,
+    pub whats_new_scroll: u16,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
             scale: "C".to_string(),
+            quality: "Major".to_string(),
             style: "Pop".to_string(),
             bpm: "120".to_string(),
-            length: "5 min".to_string(),
+            length_secs: DEFAULT_SONG_SECS,
             seed: "".to_string(),
+            melody_gain: DEFAULT_TRACK_GAIN_PERCENT.to_string(),
+            chord_gain: DEFAULT_TRACK_GAIN_PERCENT.to_string(),
+            bass_gain: DEFAULT_TRACK_GAIN_PERCENT.to_string(),
+            drums: true,
+            harmony: HARMONY_OFF.to_string(),
+            counter_melody: AUTO_COUNTER_MELODY.to_string(),
+            arp_chords: AUTO_ARP_CHORDS.to_string(),
+            comping: AUTO_COMPING.to_string(),
+            pulse_width: AUTO_PULSE_WIDTH.to_string(),
+            swing: String::new(),
+            time_signature: AUTO_TIME_SIGNATURE.to_string(),
+            modulation: AUTO_MODULATION.to_string(),
+            progression: String::new(),
+            waveforms: WAVEFORM_TRACKS.map(|_| AUTO_WAVEFORM.to_string()),
+            echo: AUTO_ECHO.to_string(),
+            lofi: AUTO_LOFI.to_string(),
+            brightness: AUTO_BRIGHTNESS.to_string(),
+            nes_mode: false,
+            melody_seed: None,
+            bass_seed: None,
+            id_version: SONG_ID_VERSION,
             input_mode: InputMode::Navigation,
             popup_list_state: ListState::default(),
+            popup_filter: String::new(),
             is_random: false,
-            scales: vec![
-                "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
-            ]
-            .into_iter()
-            .map(String::from)
-            .collect(),
-            styles: vec![
-                "Pop",
-                "Rock",
-                "Jazz",
-                "Blues",
-                "Electronic",
-                "Ambient",
-                "Classical",
-                "Folk",
-                "Metal",
-                "Reggae",
-            ]
-            .into_iter()
-            .map(String::from)
-            .collect(),
-            lengths: vec!["1 min", "2 min", "3 min", "5 min", "10 min"]
+            scales: SCALE_NAMES.into_iter().map(String::from).collect(),
+            qualities: vec!["Major".to_string(), "Minor".to_string()],
+            styles: STYLE_NAMES.into_iter().map(String::from).collect(),
+            lengths: vec!["1 min", "2 min", "3 min", "5 min", "10 min", ENDLESS_LENGTH_LABEL, CUSTOM_LENGTH_OPTION]
                 .into_iter()
                 .map(String::from)
                 .collect(),
+            time_signatures: std::iter::once(AUTO_TIME_SIGNATURE)
+                .chain(TimeSignature::ALL.iter().map(|time_signature| time_signature.label()))
+                .map(String::from)
+                .collect(),
             is_playing: false,
             current_song_progress: 0.0,
             current_song_elapsed_secs: 0.0,
@@ -347,7 +610,408 @@ impl Default for AppState {
             song_id_error: None,
             current_song_id_display: None,
             show_help: false,
+            volume: 1.0,
+            is_muted: false,
+            is_looping: false,
+            crossfade_secs: 0,
+            fade_out_secs: None,
+            whats_new_lines: Vec::new(),
+            whats_new_scroll: 0,
+        }
+    }
+}
+
+impl AppState {
+    /* is_minor - Checks whether songs are generated in a minor key.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool : True for the "Minor" key quality; anything else is major.
+     */
+    pub fn is_minor(&self) -> bool {
+        self.quality.eq_ignore_ascii_case("Minor")
+    }
+
+    /* is_endless - Checks whether songs play on until they are skipped or stopped.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool : True for the `ENDLESS_SONG_SECS` length.
+     */
+    pub fn is_endless(&self) -> bool {
+        self.length_secs == ENDLESS_SONG_SECS
+    }
+
+    /* carry_playback_settings - Keeps how playback goes on when a song is loaded from its ID.
+     *
+     * A song ID carries everything that is heard, so a loaded song plays as it was shared; what
+     * follows it (a random song, a repeat, a crossfade) and the volume stay as the listener set
+     * them. The destructuring is exhaustive on purpose, as in `merge_during_edit`: adding a
+     * field to `AppState` forces a decision here about whether loading a song keeps it.
+     *
+     * inputs:
+     *     - &mut self
+     *     - from (&AppState): The state before the song was loaded.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn carry_playback_settings(&mut self, from: &AppState) {
+        let AppState {
+            // Carried in the song ID, or resolved from it
+            scale: _,
+            quality: _,
+            style: _,
+            bpm: _,
+            length_secs: _,
+            seed: _,
+            melody_gain: _,
+            chord_gain: _,
+            bass_gain: _,
+            drums: _,
+            harmony: _,
+            counter_melody: _,
+            arp_chords: _,
+            comping: _,
+            pulse_width: _,
+            swing: _,
+            time_signature: _,
+            modulation: _,
+            progression: _,
+            waveforms: _,
+            echo: _,
+            lofi: _,
+            brightness: _,
+            nes_mode: _,
+            melody_seed: _,
+            bass_seed: _,
+            id_version: _,
+            fade_out_secs: _,
+            // Playback, kept from before the song was loaded
+            is_random,
+            is_looping,
+            crossfade_secs,
+            volume,
+            is_muted,
+            // Screen state, which the TUI keeps apart from a loaded song's state
+            input_mode: _,
+            popup_list_state: _,
+            popup_filter: _,
+            scales: _,
+            qualities: _,
+            styles: _,
+            lengths: _,
+            time_signatures: _,
+            is_playing: _,
+            current_song_progress: _,
+            current_song_elapsed_secs: _,
+            current_song_duration_secs: _,
+            song_loader_input: _,
+            song_id_error: _,
+            current_song_id_display: _,
+            show_help: _,
+            whats_new_lines: _,
+            whats_new_scroll: _,
+        } = from;
+        self.is_random = *is_random;
+        self.is_looping = *is_looping;
+        self.crossfade_secs = *crossfade_secs;
+        self.volume = *volume;
+        self.is_muted = *is_muted;
+    }
+
+    /* popup_options - Returns the options of the open Scale, Key, Style, Length or Time popup.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - &[String] : The options, or an empty slice if no such popup is open.
+     */
+    pub fn popup_options(&self) -> &[String] {
+        match self.input_mode {
+            InputMode::ScalePopup => &self.scales,
+            InputMode::QualityPopup => &self.qualities,
+            InputMode::StylePopup => &self.styles,
+            InputMode::LengthPopup => &self.lengths,
+            InputMode::TimeSignaturePopup => &self.time_signatures,
+            _ => &[],
+        }
+    }
+
+    /* visible_popup_options - Applies the popup filter to the open popup's options.
+     *
+     * Matching is case-insensitive; options starting with the filter come first, then
+     * options containing it elsewhere, each in their original order.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Vec<usize> : Indexes into `popup_options()` of the options to show.
+     */
+    pub fn visible_popup_options(&self) -> Vec<usize> {
+        let filter = self.popup_filter.to_lowercase();
+        let options: Vec<String> = self.popup_options().iter().map(|o| o.to_lowercase()).collect();
+        let prefixed = (0..options.len()).filter(|&i| options[i].starts_with(&filter));
+        let contained = (0..options.len())
+            .filter(|&i| !options[i].starts_with(&filter) && options[i].contains(&filter));
+        prefixed.chain(contained).collect()
+    }
+
+    /* merge_during_edit - Applies a background state update without disturbing an edit in progress.
+     *
+     * Every field is taken from `incoming` except the input mode and the field being edited,
+     * so a song that starts mid-edit updates the display while the half-typed value survives.
+     * The destructuring is exhaustive on purpose: adding a field to `AppState` forces a
+     * decision here about whether it belongs to an edit.
+     *
+     * inputs:
+     *     - &mut self
+     *     - incoming (AppState): The state sent by the background event.
+     *     - editing (InputId): The input currently being edited.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn merge_during_edit(&mut self, incoming: AppState, editing: InputId) {
+        let AppState {
+            scale,
+            quality,
+            style,
+            bpm,
+            length_secs,
+            seed,
+            melody_gain,
+            chord_gain,
+            bass_gain,
+            drums,
+            harmony,
+            counter_melody,
+            arp_chords,
+            comping,
+            pulse_width,
+            swing,
+            time_signature,
+            modulation,
+            progression,
+            waveforms,
+            echo,
+            lofi,
+            brightness,
+            nes_mode,
+            melody_seed,
+            bass_seed,
+            id_version,
+            input_mode: _, // The edit owns the input mode until it concludes
+            popup_list_state,
+            popup_filter: _, // Only typed while a popup is open, never during a text edit
+            is_random,
+            scales,
+            qualities,
+            styles,
+            lengths,
+            time_signatures,
+            is_playing,
+            current_song_progress,
+            current_song_elapsed_secs,
+            current_song_duration_secs,
+            song_loader_input,
+            song_id_error,
+            current_song_id_display,
+            show_help,
+            volume,
+            is_muted,
+            is_looping,
+            crossfade_secs,
+            fade_out_secs,
+            whats_new_lines,
+            whats_new_scroll,
+        } = incoming;
+
+        // Text fields: keep whichever one is being edited
+        let text_fields = [
+            (InputId::Bpm, &mut self.bpm, bpm),
+            (InputId::Seed, &mut self.seed, seed),
+            (InputId::MelodyGain, &mut self.melody_gain, melody_gain),
+            (InputId::ChordGain, &mut self.chord_gain, chord_gain),
+            (InputId::BassGain, &mut self.bass_gain, bass_gain),
+            (InputId::Swing, &mut self.swing, swing),
+            (InputId::Progression, &mut self.progression, progression),
+            (InputId::SongLoader, &mut self.song_loader_input, song_loader_input),
+        ];
+        for (id, field, value) in text_fields {
+            if id != editing {
+                *field = value;
+            }
+        }
+
+        self.scale = scale;
+        self.quality = quality;
+        self.style = style;
+        self.length_secs = length_secs;
+        self.time_signature = time_signature;
+        self.modulation = modulation;
+        self.drums = drums;
+        self.harmony = harmony;
+        self.counter_melody = counter_melody;
+        self.arp_chords = arp_chords;
+        self.comping = comping;
+        self.pulse_width = pulse_width;
+        self.waveforms = waveforms;
+        self.echo = echo;
+        self.lofi = lofi;
+        self.brightness = brightness;
+        self.nes_mode = nes_mode;
+        self.melody_seed = melody_seed;
+        self.bass_seed = bass_seed;
+        self.id_version = id_version;
+        self.popup_list_state = popup_list_state;
+        self.is_random = is_random;
+        self.scales = scales;
+        self.qualities = qualities;
+        self.styles = styles;
+        self.lengths = lengths;
+        self.time_signatures = time_signatures;
+        self.is_playing = is_playing;
+        self.current_song_progress = current_song_progress;
+        self.current_song_elapsed_secs = current_song_elapsed_secs;
+        self.current_song_duration_secs = current_song_duration_secs;
+        self.song_id_error = song_id_error;
+        self.current_song_id_display = current_song_id_display;
+        self.show_help = show_help;
+        self.volume = volume;
+        self.is_muted = is_muted;
+        self.is_looping = is_looping;
+        self.crossfade_secs = crossfade_secs;
+        self.fade_out_secs = fade_out_secs;
+        self.whats_new_lines = whats_new_lines;
+        self.whats_new_scroll = whats_new_scroll;
+    }
+}
+
+/* ProgressInterpolator - Moves the progress bar smoothly between progress messages.
+ *
+ * Progress arrives about every 100ms, which on a long song leaves the bar sticking and then
+ * jumping. Between messages the position is advanced with the frame clock, but never more
+ * than one reporting interval past the last reported position, so a late message can't let
+ * the bar run ahead of the music.
+ *
+ * fields:
+ *     - position (u64): The last reported (or frozen) position, in samples.
+ *     - total (u64): The song length, in samples.
+ *     - reported_at (Option<Instant>): When `position` was reported, or None before the first report.
+ */
+#[derive(Debug, Default, Clone, Copy)]
+struct ProgressInterpolator {
+    position: u64,
+    total: u64,
+    reported_at: Option<Instant>,
+}
+
+impl ProgressInterpolator {
+    /* report - Records an authoritative position from the music service.
+     *
+     * inputs:
+     *     - &mut self
+     *     - position (u64): The reported position, in samples.
+     *     - total (u64): The song length, in samples.
+     *     - now (Instant): When the report was received.
+     *
+     * outputs:
+     *     - None
+     */
+    fn report(&mut self, position: u64, total: u64, now: Instant) {
+        self.position = position;
+        self.total = total;
+        self.reported_at = Some(now);
+    }
+
+    /* position_at - Estimates the playback position at a moment.
+     *
+     * inputs:
+     *     - &self
+     *     - now (Instant): The moment to estimate for.
+     *     - is_playing (bool): False holds the position still.
+     *
+     * outputs:
+     *     - u64: The estimated position, in samples.
+     */
+    fn position_at(&self, now: Instant, is_playing: bool) -> u64 {
+        let Some(reported_at) = self.reported_at.filter(|_| is_playing) else {
+            return self.position;
+        };
+        let elapsed = now
+            .saturating_duration_since(reported_at)
+            .min(MAX_PROGRESS_EXTRAPOLATION);
+        let advanced = self.position + (elapsed.as_secs_f64() * TUI_SAMPLE_RATE as f64) as u64;
+        advanced.min(self.total)
+    }
+
+    /* freeze - Holds the bar at its current estimate, for a pause.
+     *
+     * inputs:
+     *     - &mut self
+     *     - now (Instant): When playback paused.
+     *
+     * outputs:
+     *     - None
+     */
+    fn freeze(&mut self, now: Instant) {
+        self.position = self.position_at(now, true);
+        self.reported_at = Some(now);
+    }
+
+    /* resume - Restarts interpolation from the held position, for a resume.
+     *
+     * inputs:
+     *     - &mut self
+     *     - now (Instant): When playback resumed.
+     *
+     * outputs:
+     *     - None
+     */
+    fn resume(&mut self, now: Instant) {
+        self.reported_at = Some(now);
+    }
+
+    /* ratio - Returns the estimated progress through the song.
+     *
+     * inputs:
+     *     - &self
+     *     - now (Instant): The moment to estimate for.
+     *     - is_playing (bool): False holds the position still.
+     *
+     * outputs:
+     *     - f64: Progress from 0.0 to 1.0.
+     */
+    fn ratio(&self, now: Instant, is_playing: bool) -> f64 {
+        if self.total == 0 {
+            return 0.0;
         }
+        (self.position_at(now, is_playing) as f64 / self.total as f64).clamp(0.0, 1.0)
+    }
+
+    /* pulse - Returns where the bar of an endless song stands, sweeping back and forth.
+     *
+     * Follows the playback position, so it rests while paused.
+     *
+     * inputs:
+     *     - &self
+     *     - now (Instant): The moment to estimate for.
+     *     - is_playing (bool): False holds the position still.
+     *
+     * outputs:
+     *     - f64: From 0.0 to 1.0 and back once every `ENDLESS_PULSE_SECONDS` of playback.
+     */
+    fn pulse(&self, now: Instant, is_playing: bool) -> f64 {
+        let seconds = self.position_at(now, is_playing) as f64 / TUI_SAMPLE_RATE as f64;
+        let phase = (seconds / ENDLESS_PULSE_SECONDS).fract();
+        1.0 - (2.0 * phase - 1.0).abs()
     }
 }
 
@@ -369,12 +1033,180 @@ pub struct Tui<B: Backend> {
     current_focus: InputId,
     state: AppState,
     editing_original_value: Option<String>,
+    output_level: f32,
+    displayed_level: f32,
+    show_scope: bool,
+    scope_samples: Vec<f32>,
+    show_spectrum: bool,
+    spectrum_bands: Vec<f32>,
+    current_beat: u32,
+    beats_per_bar: u32,
+    beat_phase: f32,
+    song_bpm: u32,
+    song_loader_selected: bool,
+    chord_timeline: Vec<(u64, String)>,
+    current_chord: Option<String>,
+    section_timeline: Vec<(u64, SongSection)>,
+    current_section: Option<SongSection>,
+    loudness_db: Option<f32>,
+    show_debug: bool,
+    worker_stats: WorkerStats,
+    notice: Option<(String, Instant)>,
+    seed_rejected_at: Option<Instant>,
+    stop_after_current: bool,
+    library: Vec<LibraryEntry>,
+    share: Option<(String, String)>,
+    length_input: String,
+    alternate_screen: bool,
+    progress: ProgressInterpolator,
+    endless_song: bool,
+    generating_since: Option<Instant>,
+    theme: Theme,
+    compact: bool,
+    widget_areas: HashMap<InputId, Rect>,
+    popup_area: Option<Rect>,
+    popup_list_area: Option<Rect>,
+    gauge_area: Option<Rect>,
+    seeking: bool,
+    seek_awaiting_progress: bool,
+    unsent_seek: Option<f32>,
 }
 
 // TUI_SAMPLE_RATE: Assumed audio sample rate, used for time calculations in the TUI.
 // This should ideally be consistent with the actual sample rate used in `gen.rs`.
 const TUI_SAMPLE_RATE: f32 = 44100.0;
 
+// MAX_LENGTH_INPUT_LEN: Longest custom length the Length field accepts, e.g. "7.5 min".
+const MAX_LENGTH_INPUT_LEN: usize = 8;
+// CUSTOM_LENGTH_OPTION: The Length popup entry that switches to typing a length.
+const CUSTOM_LENGTH_OPTION: &str = "Custom…";
+
+// MAX_SONG_ID_LEN: Longest text the Load Song field accepts from a paste; real IDs, even with a
+// custom progression of `progs::MAX_CUSTOM_CHORDS` chords, are well under it.
+const MAX_SONG_ID_LEN: usize = 192;
+// MAX_PROGRESSION_LEN: Longest text the Chords field accepts, enough for `progs::MAX_CUSTOM_CHORDS` chords.
+const MAX_PROGRESSION_LEN: usize = 64;
+// MAX_SEED_DIGITS: Longest seed the Seed field accepts (the digits of u64::MAX).
+const MAX_SEED_DIGITS: usize = 20;
+// SEED_COUNTER_FROM: Seed length from which a "18/20" style counter is shown while editing.
+const SEED_COUNTER_FROM: usize = 16;
+// SEED_REJECT_FLASH: How long the Seed field flashes red after a digit or value is rejected.
+const SEED_REJECT_FLASH: Duration = Duration::from_millis(400);
+// MAX_PROGRESS_EXTRAPOLATION: How far the progress bar may run ahead of the last progress
+// message; the music service reports about every 100ms.
+const MAX_PROGRESS_EXTRAPOLATION: Duration = Duration::from_millis(100);
+// ENDLESS_PULSE_SECONDS: How long the progress bar of an endless song takes to sweep across and back.
+const ENDLESS_PULSE_SECONDS: f64 = 4.0;
+
+// LEVEL_METER_WIDTH: Width of the output level meter next to the progress bar, in cells.
+const LEVEL_METER_WIDTH: u16 = 12;
+// LEVEL_METER_FLOOR_DB: Level (dBFS) shown as an empty meter.
+const LEVEL_METER_FLOOR_DB: f32 = -48.0;
+// LEVEL_DECAY_PER_FRAME: Fraction of the displayed level kept each frame when the level drops.
+const LEVEL_DECAY_PER_FRAME: f32 = 0.85;
+// GENERATING_SPINNER: Frames of the spinner shown while a song is being generated, one per
+// `GENERATING_SPINNER_FRAME`.
+const GENERATING_SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const GENERATING_SPINNER_FRAME: Duration = Duration::from_millis(100);
+// BEATS_PER_BAR: Beats grouped into one bar until the playing song reports its time signature (4/4).
+pub const BEATS_PER_BAR: u32 = 4;
+// BEAT_FLASH_PHASE: Portion of each beat during which the beat indicator is lit. Wide enough
+// that at least one ~100ms progress update lands inside it at fast tempos.
+const BEAT_FLASH_PHASE: f32 = 0.3;
+// NOTICE_DURATION: How long a short notice (where a Rewind jumped to, a library change)
+// replaces the chord name in the Now Playing panel.
+const NOTICE_DURATION: Duration = Duration::from_millis(1500);
+// OPTION_POPUP_WIDTH: Width of the Scale, Style and Length popups.
+const OPTION_POPUP_WIDTH: u16 = 25;
+// LIBRARY_POPUP_HEIGHT: Height of the library popup, taller than the option popups.
+const LIBRARY_POPUP_HEIGHT: u16 = 20;
+// LIBRARY_POPUP_WIDTH: Width of the library popup, enough for a song ID and its save date.
+const LIBRARY_POPUP_WIDTH: u16 = 60;
+// WAVEFORM_POPUP_WIDTH: Width of the waveform popup, enough for its title.
+const WAVEFORM_POPUP_WIDTH: u16 = 40;
+// MIN_WIDTH / MIN_HEIGHT: Smallest terminal size the TUI draws in (with the compact layout);
+// smaller terminals get a resize hint.
+pub const MIN_WIDTH: u16 = 60;
+pub const MIN_HEIGHT: u16 = 18;
+// FULL_LAYOUT_WIDTH / FULL_LAYOUT_HEIGHT: Smallest terminal size for the full layout; smaller
+// terminals get the compact one.
+pub const FULL_LAYOUT_WIDTH: u16 = 80;
+pub const FULL_LAYOUT_HEIGHT: u16 = 25;
+// LOADER_POPUP_WIDTH: Width of the popup holding the Load Song field in the compact layout.
+const LOADER_POPUP_WIDTH: u16 = 56;
+// VISUALIZER_PANEL_HEIGHT: Height of the oscilloscope/spectrum panels shown under the Load Song panel.
+const VISUALIZER_PANEL_HEIGHT: u16 = 9;
+
+/* level_fraction - Maps a linear level onto a 0.0-1.0 display scale in decibels.
+ *
+ * inputs:
+ *     - level (f32): Linear level (0.0 to 1.0).
+ *
+ * outputs:
+ *     - f32: 0.0 at `LEVEL_METER_FLOOR_DB` or below, 1.0 at 0 dBFS.
+ */
+fn level_fraction(level: f32) -> f32 {
+    let db = if level > 0.0 {
+        20.0 * level.log10()
+    } else {
+        LEVEL_METER_FLOOR_DB
+    };
+    ((db - LEVEL_METER_FLOOR_DB) / -LEVEL_METER_FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/* merge_bands - Reduces spectrum bands to fit a narrower display.
+ *
+ * Neighboring bands are grouped as evenly as possible and each group shows its loudest band.
+ *
+ * inputs:
+ *     - bands (&[f32]): Band magnitudes, lowest first.
+ *     - count (usize): Number of bands wanted.
+ *
+ * outputs:
+ *     - Vec<f32>: `count` bands (or fewer, if fewer were given).
+ */
+fn merge_bands(bands: &[f32], count: usize) -> Vec<f32> {
+    if count == 0 || bands.len() <= count {
+        return bands.to_vec();
+    }
+    (0..count)
+        .map(|i| {
+            let start = i * bands.len() / count;
+            let end = (i + 1) * bands.len() / count;
+            bands[start..end].iter().fold(0.0f32, |max, &b| max.max(b))
+        })
+        .collect()
+}
+
+/* level_meter_line - Renders an output level as a row of filled and empty meter cells.
+ *
+ * The level is mapped logarithmically between `LEVEL_METER_FLOOR_DB` and 0 dBFS, and
+ * cells are colored green, yellow and red from left to right (in the theme's colors).
+ *
+ * inputs:
+ *     - level (f32): Linear RMS level (0.0 to 1.0).
+ *     - width (u16): Number of cells in the meter.
+ *     - theme (&Theme): The colors to draw with.
+ *
+ * outputs:
+ *     - Line<'static>: The meter as styled spans.
+ */
+fn level_meter_line(level: f32, width: u16, theme: &Theme) -> Line<'static> {
+    let fraction = level_fraction(level);
+    let filled = (fraction * width as f32).round() as u16;
+    let spans: Vec<Span> = (0..width)
+        .map(|i| {
+            let position = (i + 1) as f32 / width as f32;
+            if i < filled {
+                Span::styled("\u{2588}", theme.meter(position))
+            } else {
+                Span::styled("\u{2591}", theme.dim())
+            }
+        })
+        .collect();
+    Line::from(spans)
+}
+
 /* format_duration - Formats a duration from total seconds into a MM:SS string.
  *
  * This is a helper function used to display time values in a user-friendly format.
@@ -385,7 +1217,7 @@ const TUI_SAMPLE_RATE: f32 = 44100.0;
  * outputs:
  *     - String : The duration formatted as "MM:SS".
  */
-fn format_duration(total_seconds: f32) -> String {
+pub fn format_duration(total_seconds: f32) -> String {
     let minutes = (total_seconds / 60.0).floor() as u32;
     let seconds = (total_seconds % 60.0).floor() as u32;
     format!("{:02}:{:02}", minutes, seconds)
@@ -410,23 +1242,69 @@ impl<B: Backend> Tui<B> {
             current_focus: InputId::PlayPause,
             state: AppState::default(),
             editing_original_value: None,
+            output_level: 0.0,
+            displayed_level: 0.0,
+            show_scope: false,
+            scope_samples: Vec::new(),
+            show_spectrum: false,
+            spectrum_bands: Vec::new(),
+            current_beat: 0,
+            beats_per_bar: BEATS_PER_BAR,
+            beat_phase: 0.0,
+            song_bpm: 0,
+            song_loader_selected: false,
+            chord_timeline: Vec::new(),
+            current_chord: None,
+            section_timeline: Vec::new(),
+            current_section: None,
+            loudness_db: None,
+            show_debug: false,
+            worker_stats: WorkerStats::default(),
+            notice: None,
+            seed_rejected_at: None,
+            stop_after_current: false,
+            library: Vec::new(),
+            share: None,
+            length_input: String::new(),
+            alternate_screen: true,
+            progress: ProgressInterpolator::default(),
+            endless_song: false,
+            generating_since: None,
+            theme: Theme::default(),
+            compact: false,
+            widget_areas: HashMap::new(),
+            popup_area: None,
+            popup_list_area: None,
+            gauge_area: None,
+            seeking: false,
+            seek_awaiting_progress: false,
+            unsent_seek: None,
         })
     }
 
     /* setup - Initializes the terminal for TUI interaction.
      *
-     * This method enables raw mode and switches to the alternate screen buffer.
+     * This method enables raw mode and switches to the alternate screen buffer. Terminals
+     * without one are cleared instead, and cleared again by `teardown`.
      *
      * inputs:
      *     - &mut self
+     *     - alternate_screen (bool): Whether the terminal supports the alternate screen.
      *
      * outputs:
      *     - Result<(), Box<dyn std::error::Error>> : Ok on success, or an error.
      */
-    pub fn setup(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn setup(&mut self, alternate_screen: bool) -> Result<(), Box<dyn std::error::Error>> {
         enable_raw_mode()?;
+        self.alternate_screen = alternate_screen;
         let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen)?;
+        if alternate_screen {
+            execute!(stdout, EnterAlternateScreen)?;
+        } else {
+            execute!(stdout, terminal::Clear(ClearType::All))?;
+        }
+        // Pasted text arrives as one event instead of keystrokes that would trigger shortcuts
+        execute!(stdout, EnableBracketedPaste, EnableMouseCapture)?;
         Ok(())
     }
 
@@ -444,7 +1322,12 @@ impl<B: Backend> Tui<B> {
     pub fn teardown(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         disable_raw_mode()?;
         let mut stdout = io::stdout();
-        execute!(stdout, LeaveAlternateScreen)?;
+        execute!(stdout, DisableBracketedPaste, DisableMouseCapture)?;
+        if self.alternate_screen {
+            execute!(stdout, LeaveAlternateScreen)?;
+        } else {
+            execute!(stdout, terminal::Clear(ClearType::All), MoveTo(0, 0))?;
+        }
         self.terminal.show_cursor()?;
         Ok(())
     }
@@ -459,31 +1342,55 @@ impl<B: Backend> Tui<B> {
      *     - &mut self
      *     - current_samples (u64): The number of samples played so far.
      *     - total_samples (u64): The total number of samples in the song.
+     *     - endless (bool): True for an endless song, which only shows its elapsed time.
      *
      * outputs:
      *     - None
      */
-    pub fn update_progress(&mut self, current_samples: u64, total_samples: u64) {
+    pub fn update_progress(&mut self, current_samples: u64, total_samples: u64, endless: bool) {
+        self.endless_song = endless;
+        // Resolved from the sample position, so rewinds and seeks land on the right chord
+        let chord_index = self
+            .chord_timeline
+            .partition_point(|(start, _)| *start <= current_samples);
+        self.current_chord = match chord_index.checked_sub(1) {
+            Some(index) if total_samples > 0 => Some(self.chord_timeline[index].1.clone()),
+            _ => None,
+        };
+        let section_index = self
+            .section_timeline
+            .partition_point(|(start, _)| *start <= current_samples);
+        self.current_section = match section_index.checked_sub(1) {
+            Some(index) if total_samples > 0 => Some(self.section_timeline[index].1),
+            _ => None,
+        };
+
         // Always update the duration if total_samples is valid and has changed
         if total_samples > 0 {
-            let new_duration = total_samples as f32 / TUI_SAMPLE_RATE;
+            // An endless song has no duration; its total only counts what has been rendered
+            let new_duration = if endless { 0.0 } else { total_samples as f32 / TUI_SAMPLE_RATE };
             if (self.state.current_song_duration_secs - new_duration).abs() > f32::EPSILON {
                 self.state.current_song_duration_secs = new_duration;
             }
             
-            // Calculate progress and update if changed significantly
-            let progress = current_samples as f32 / total_samples as f32;
-            if (progress - self.state.current_song_progress).abs() > 0.001 {
-                self.state.current_song_progress = progress;
-                
-                // Update elapsed time based on samples
-                let new_elapsed = current_samples as f32 / TUI_SAMPLE_RATE;
-                if (new_elapsed - self.state.current_song_elapsed_secs).abs() > 0.05 {
-                    self.state.current_song_elapsed_secs = new_elapsed;
-                }
+            // One seek per progress tick while scrubbing; the bar keeps the dragged position
+            self.seek_awaiting_progress = false;
+            if self.seeking {
+                return;
+            }
+
+            // Every report is kept, so long songs advance below a tenth of a percent
+            self.state.current_song_progress = if endless { 0.0 } else { current_samples as f32 / total_samples as f32 };
+            self.progress.report(current_samples, total_samples, Instant::now());
+
+            // Update elapsed time based on samples
+            let new_elapsed = current_samples as f32 / TUI_SAMPLE_RATE;
+            if (new_elapsed - self.state.current_song_elapsed_secs).abs() > 0.05 {
+                self.state.current_song_elapsed_secs = new_elapsed;
             }
         } else if total_samples == 0 && current_samples == 0 {
             // Reset progress if we get zeros (song ended or reset)
+            self.progress = ProgressInterpolator::default();
             self.state.current_song_progress = 0.0;
             self.state.current_song_elapsed_secs = 0.0;
             self.state.current_song_duration_secs = 0.0;
@@ -516,9 +1423,42 @@ impl<B: Backend> Tui<B> {
      *     - Result<(), Box<dyn std::error::Error>> : Ok on success, or an error if drawing fails.
      */
     pub fn draw(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // Rise to new peaks immediately, fall back smoothly
+        self.displayed_level = self
+            .output_level
+            .max(self.displayed_level * LEVEL_DECAY_PER_FRAME);
+        let displayed_level = self.displayed_level;
+        let current_song_saved = self.is_current_song_saved();
+        let endless_song = self.endless_song;
+        let progress_ratio = if endless_song {
+            self.progress.pulse(Instant::now(), self.state.is_playing)
+        } else {
+            self.progress.ratio(Instant::now(), self.state.is_playing)
+        };
+        let duration_str = if endless_song {
+            ENDLESS_LENGTH_LABEL.to_string()
+        } else {
+            format_duration(self.state.current_song_duration_secs)
+        };
+        let generating_spinner = self.generating_since.map(|since| {
+            let frame = since.elapsed().as_millis() / GENERATING_SPINNER_FRAME.as_millis();
+            GENERATING_SPINNER[frame as usize % GENERATING_SPINNER.len()]
+        });
+        let seed_rejected = self
+            .seed_rejected_at
+            .is_some_and(|rejected_at| rejected_at.elapsed() < SEED_REJECT_FLASH);
+        let theme = &self.theme;
+        // Below the full layout's size, controls are packed into fewer rows
+        let terminal_size = self.terminal.size()?;
+        self.compact =
+            terminal_size.width < FULL_LAYOUT_WIDTH || terminal_size.height < FULL_LAYOUT_HEIGHT;
+        let compact = self.compact;
+        // Where each control and popup was drawn, for mouse clicks
+        let mut widget_areas = HashMap::new();
+        let mut popup_area_drawn = None;
+        let mut popup_list_area = None;
+        let mut gauge_area = None;
         self.terminal.draw(|f| {
-            static MIN_WIDTH: u16 = 80;
-            static MIN_HEIGHT: u16 = 25;
 
             let size = f.size();
             let terminal_width = size.width;
@@ -530,15 +1470,25 @@ impl<B: Backend> Tui<B> {
                     MIN_WIDTH, MIN_HEIGHT, terminal_width, terminal_height
                 );
                 let warning_widget = Paragraph::new(warning)
-                    .style(Style::default().fg(Color::Red))
+                    .style(theme.error())
                     .alignment(Alignment::Center);
                 f.render_widget(warning_widget, size);
                 return;
             }
 
-            let title_height = 8; // Title section height
-            let content_height = 24; // Content area: Now Playing (8) + Gap (1) + Create New Track (9) + Gap (1) + Load Song (5)
+            // Compact: a one-line title, Now Playing (4) + Gap (1) + Create New Track (7), no Load Song panel
+            let title_height = if compact { 1 } else { 8 }; // Title section height
+            let mut content_height = if compact { 12 } else { 28 }; // Content area: Now Playing (8) + Gap (1) + Create New Track (13) + Gap (1) + Load Song (5)
             let help_hint_height = 1;
+            // The visualizers sit under the Load Song panel, and quietly hide if they don't fit
+            let visualizers_fit = !compact
+                && terminal_height
+                    >= title_height + content_height + 1 + VISUALIZER_PANEL_HEIGHT + help_hint_height;
+            let scope_visible = self.show_scope && visualizers_fit;
+            let spectrum_visible = self.show_spectrum && visualizers_fit;
+            if scope_visible || spectrum_visible {
+                content_height += 1 + VISUALIZER_PANEL_HEIGHT; // Gap (1) + Visualizers
+            }
             let total_app_content_height = title_height + content_height + help_hint_height;
 
             let v_padding = (terminal_height.saturating_sub(total_app_content_height)) / 2;
@@ -569,10 +1519,14 @@ impl<B: Backend> Tui<B> {
                 "                       ♪ ♫ ♪  The 8 Bit Music DJ  ♪ ♫ ♪                       ",
             ];
 
-            let title_lines: Vec<Line> = ascii_art
-                .iter()
-                .map(|&line| Line::from(Span::styled(line, Style::default().fg(Color::Blue))))
-                .collect();
+            let title_lines: Vec<Line> = if compact {
+                vec![Line::from(Span::styled("♪ 8BitBeats ♪", theme.accent()))]
+            } else {
+                ascii_art
+                    .iter()
+                    .map(|&line| Line::from(Span::styled(line, theme.accent())))
+                    .collect()
+            };
 
             let title_paragraph = Paragraph::new(title_lines)
                 .alignment(Alignment::Center)
@@ -580,7 +1534,7 @@ impl<B: Backend> Tui<B> {
 
             f.render_widget(title_paragraph, title_area);
 
-            let content_width_percentage = 80; // Use 80% of available width for content
+            let content_width_percentage = if compact { 100 } else { 80 }; // Use 80% of available width for content
             let mut content_width = (content_area.width as u32 * content_width_percentage / 100) as u16;
 
             content_width = std::cmp::min(content_width, 100); // Max content width of 100 characters
@@ -594,81 +1548,290 @@ impl<B: Backend> Tui<B> {
                 height: content_area.height,
             };
 
+            // Without visualizers, the panels above get the whole content height
+            let visualizer_gap = if scope_visible || spectrum_visible { 1 } else { 0 };
             let panel_layout = Layout::default()
                 .direction(LayoutDirection::Vertical)
-                .constraints([
-                    Constraint::Length(8), // Now Playing panel
-                    Constraint::Length(1), // Gap
-                    Constraint::Length(11), // Create New Track panel
-                    Constraint::Length(1), // Gap
-                    Constraint::Length(5), // Load Song panel
-                    Constraint::Min(1),    // Remaining space
-                ])
+                .constraints(if compact {
+                    [
+                        Constraint::Length(4), // Now Playing panel: progress bar and controls only
+                        Constraint::Length(1), // Gap
+                        Constraint::Length(7), // Create New Track panel, without spacer rows
+                        Constraint::Length(0), // No gap
+                        Constraint::Length(0), // Load Song moves to a popup
+                        Constraint::Length(0), // No gap
+                        Constraint::Min(0),    // No visualizers
+                    ]
+                } else {
+                    [
+                        Constraint::Length(8), // Now Playing panel
+                        Constraint::Length(1), // Gap
+                        Constraint::Length(13), // Create New Track panel
+                        Constraint::Length(1), // Gap
+                        Constraint::Length(5), // Load Song panel
+                        Constraint::Length(visualizer_gap), // Gap, when the visualizers are visible
+                        Constraint::Min(0),    // Visualizer panels, when visible
+                    ]
+                })
                 .split(centered_content_area);
 
             let now_playing_area = panel_layout[0];
             let create_track_area = panel_layout[2];
-            let song_loader_area = panel_layout[4];
-
-            let now_playing_block = Block::default().title("Now Playing").borders(Borders::ALL);
-            let inner_now_playing = now_playing_block.inner(now_playing_area);
-            f.render_widget(now_playing_block, now_playing_area);
+            // In the compact layout the Load Song field only appears, as a popup, while it is edited
+            let song_loader_area = if !compact {
+                Some(panel_layout[4])
+            } else if self.state.input_mode == InputMode::SongLoaderEditing {
+                let popup_width = LOADER_POPUP_WIDTH.min(size.width);
+                let popup_area = Rect {
+                    x: (size.width - popup_width) / 2,
+                    y: (size.height - 3) / 2,
+                    width: popup_width,
+                    height: 3,
+                };
+                Some(popup_area)
+            } else {
+                None
+            };
+
+            let now_playing_block = Block::default().title("Now Playing").borders(Borders::ALL);
+            let inner_now_playing = now_playing_block.inner(now_playing_area);
+            f.render_widget(now_playing_block, now_playing_area);
 
-            // Layout for elements within the "Now Playing" panel
+            // Layout for elements within the "Now Playing" panel; the compact layout keeps
+            // only the progress bar and the controls, so the other rows get no height
+            let info_row_height = if compact { 0 } else { 1 };
             let now_playing_layout = Layout::default()
                 .direction(LayoutDirection::Vertical)
                 .constraints([
-                    Constraint::Length(1), // Song ID text
-                    Constraint::Length(1), // Progress Bar
-                    Constraint::Length(1), // Progress Text (MM:SS / MM:SS)
-                    Constraint::Length(1), // Empty space
-                    Constraint::Min(1),    // Controls row
+                    Constraint::Length(info_row_height), // Song ID text
+                    Constraint::Length(1),               // Progress Bar
+                    Constraint::Length(info_row_height), // Progress Text (MM:SS / MM:SS)
+                    Constraint::Length(info_row_height), // Current chord
+                    Constraint::Min(1),                  // Controls row
                 ])
-                .margin(1)
+                .horizontal_margin(1)
+                .vertical_margin(if compact { 0 } else { 1 })
                 .split(inner_now_playing);
 
             let song_id_display_text = format!("Song ID: {}", self.state.current_song_id_display.as_deref().unwrap_or("N/A"));
-            let song_id_paragraph = Paragraph::new(song_id_display_text)
+            // Saved songs are starred
+            let song_id_line = if current_song_saved {
+                Line::from(vec![
+                    Span::raw(song_id_display_text),
+                    Span::styled(" ★", theme.focus()),
+                ])
+            } else {
+                Line::from(song_id_display_text)
+            };
+            let song_id_paragraph = Paragraph::new(song_id_line)
                 .alignment(Alignment::Center);
             f.render_widget(song_id_paragraph, now_playing_layout[0]);
 
-            // Progress Bar
-            let progress_percentage = (self.state.current_song_progress * 100.0) as u16;
+            // Progress Bar, with the output level meter to its right
+            let progress_row_layout = Layout::default()
+                .direction(LayoutDirection::Horizontal)
+                .constraints([
+                    Constraint::Min(1),                    // Progress Bar
+                    Constraint::Length(1),                 // Spacer
+                    Constraint::Length(LEVEL_METER_WIDTH), // Level meter
+                ])
+                .split(now_playing_layout[1]);
+
+            // Without the progress text row, the compact layout shows the times on the bar
+            let progress_label = if let Some(spinner) = generating_spinner.filter(|_| compact) {
+                format!("{} Generating…", spinner)
+            } else if compact {
+                format!(
+                    "{} / {}",
+                    format_duration(self.state.current_song_elapsed_secs),
+                    duration_str
+                )
+            } else if endless_song {
+                ENDLESS_LENGTH_LABEL.to_string()
+            } else {
+                format!("{}%", (progress_ratio * 100.0) as u16)
+            };
             let progress_bar = Gauge::default()
                 .block(Block::default())
-                .gauge_style(Style::default().fg(Color::Blue).bg(Color::DarkGray))
-                .percent(progress_percentage)
-                .label(format!("{}%", progress_percentage));
-            f.render_widget(progress_bar, now_playing_layout[1]);
+                .gauge_style(theme.gauge())
+                .ratio(progress_ratio)
+                .use_unicode(true)
+                .label(progress_label);
+            f.render_widget(progress_bar, progress_row_layout[0]);
+            gauge_area = Some(progress_row_layout[0]);
+
+            let level_meter = Paragraph::new(level_meter_line(displayed_level, LEVEL_METER_WIDTH, theme));
+            f.render_widget(level_meter, progress_row_layout[2]);
 
             // Progress Text (MM:SS / MM:SS)
             let elapsed_str = format_duration(self.state.current_song_elapsed_secs);
-            let total_str = format_duration(self.state.current_song_duration_secs);
-            let progress_text = Paragraph::new(format!("{} / {}", elapsed_str, total_str))
-                .alignment(Alignment::Center);
+            let progress_text = Paragraph::new(match generating_spinner {
+                Some(spinner) => format!("{} Generating…", spinner),
+                None => format!("{} / {}", elapsed_str, duration_str),
+            })
+            .alignment(Alignment::Center);
             f.render_widget(progress_text, now_playing_layout[2]);
 
-            // Layout for playback controls (Rewind, Play/Pause, Skip)
+            // Beat indicator, left-aligned on the progress text row
+            if (self.state.current_song_duration_secs > 0.0 || endless_song) && !compact {
+                let on_beat = self.state.is_playing && self.beat_phase < BEAT_FLASH_PHASE;
+                let marker_style = if on_beat {
+                    theme.focus().add_modifier(Modifier::BOLD)
+                } else {
+                    theme.dim()
+                };
+                let beat_text = Paragraph::new(Line::from(vec![
+                    Span::styled("♪", marker_style),
+                    Span::raw(format!(
+                        " Bar {}, Beat {}",
+                        self.current_beat / self.beats_per_bar.max(1) + 1,
+                        self.current_beat % self.beats_per_bar.max(1) + 1
+                    )),
+                ]))
+                .alignment(Alignment::Left);
+                f.render_widget(beat_text, now_playing_layout[2]);
+            }
+
+            // A recent notice is briefly shown in place of the chord
+            let notice = self
+                .notice
+                .as_ref()
+                .filter(|(_, shown_at)| shown_at.elapsed() < NOTICE_DURATION)
+                .map(|(text, _)| text.as_str());
+            if compact {
+                // No chord row; notices go to the footer instead
+            } else if let Some(notice) = notice {
+                let notice_text = Paragraph::new(notice)
+                    .alignment(Alignment::Center)
+                    .style(theme.focus().add_modifier(Modifier::BOLD));
+                f.render_widget(notice_text, now_playing_layout[3]);
+            } else if let Some(chord) = &self.current_chord {
+                // Current chord and section, under the progress text
+                let chord_label = match self.current_section {
+                    Some(section) => format!("Chord: {} · {}", chord, section.label()),
+                    None => format!("Chord: {}", chord),
+                };
+                let chord_text = Paragraph::new(chord_label)
+                    .alignment(Alignment::Center)
+                    .style(theme.info());
+                f.render_widget(chord_text, now_playing_layout[3]);
+            }
+
+            // Loop badge, right-aligned on the chord row; stopping after the song overrides looping
+            if compact {
+                // No chord row
+            } else if self.stop_after_current {
+                let stop_text = Paragraph::new("■ Stopping after this track")
+                    .alignment(Alignment::Right)
+                    .style(theme.focus());
+                f.render_widget(stop_text, now_playing_layout[3]);
+            } else if self.state.is_looping {
+                let loop_text = Paragraph::new("⟳ Loop")
+                    .alignment(Alignment::Right)
+                    .style(theme.active());
+                f.render_widget(loop_text, now_playing_layout[3]);
+            }
+
+            // Random tempo, crossfade, drum, voice, chord, key change and waveform settings, left-aligned on the chord row, when not at their defaults
+            let mut settings = Vec::new();
+            if self.state.bpm.is_empty() && self.song_bpm > 0 {
+                settings.push(format!("BPM: {} (random)", self.song_bpm));
+            }
+            if self.state.crossfade_secs > 0 {
+                settings.push(format!("Crossfade: {}s", self.state.crossfade_secs));
+            }
+            if !self.state.drums {
+                settings.push("Drums: Off".to_string());
+            }
+            if self.state.harmony != HARMONY_OFF {
+                settings.push(format!("Harmony: {}", self.state.harmony));
+            }
+            if self.state.counter_melody != AUTO_COUNTER_MELODY {
+                settings.push(format!("Counter-Melody: {}", self.state.counter_melody));
+            }
+            if self.state.arp_chords != AUTO_ARP_CHORDS {
+                settings.push(format!("Arp Chords: {}", self.state.arp_chords));
+            }
+            if self.state.comping != AUTO_COMPING {
+                settings.push(format!("Comping: {}", self.state.comping));
+            }
+            if self.state.echo != AUTO_ECHO {
+                settings.push(format!("Echo: {}", self.state.echo));
+            }
+            if self.state.lofi != AUTO_LOFI {
+                settings.push(format!("Lo-fi: {}", self.state.lofi));
+            }
+            if self.state.brightness != AUTO_BRIGHTNESS {
+                settings.push(format!("Brightness: {}", self.state.brightness));
+            }
+            if self.state.nes_mode {
+                settings.push("NES Mode: On".to_string());
+            }
+            if self.state.pulse_width != AUTO_PULSE_WIDTH {
+                settings.push(format!("Pulse: {}", self.state.pulse_width));
+            }
+            if self.state.modulation != AUTO_MODULATION {
+                settings.push(format!("Key Change: {}", self.state.modulation));
+            }
+            for (track, waveform) in WAVEFORM_TRACKS.iter().zip(&self.state.waveforms) {
+                if waveform != AUTO_WAVEFORM {
+                    settings.push(format!("{}: {}", track, waveform));
+                }
+            }
+            if !settings.is_empty() && !compact {
+                let settings_text = Paragraph::new(settings.join("  "))
+                    .alignment(Alignment::Left)
+                    .style(theme.dim());
+                f.render_widget(settings_text, now_playing_layout[3]);
+            }
+
+            // Song loudness and volume, right-aligned on the progress text row
+            let volume_label = match self.loudness_db {
+                Some(loudness) => format!("{:.0} dB · Vol: {}%", loudness, (self.state.volume * 100.0).round() as u32),
+                None => format!("Vol: {}%", (self.state.volume * 100.0).round() as u32),
+            };
+            let volume_text = if self.state.is_muted {
+                Paragraph::new(Line::from(vec![
+                    Span::raw(volume_label),
+                    Span::styled(" [muted]", theme.error()),
+                ]))
+            } else {
+                Paragraph::new(volume_label)
+            }
+            .alignment(Alignment::Right);
+            if !compact {
+                f.render_widget(volume_text, now_playing_layout[2]);
+            }
+
+            // Layout for playback controls (Previous, Rewind, Play/Pause, Skip)
             let control_layout = Layout::default()
                 .direction(LayoutDirection::Horizontal)
                 .constraints([
-                    Constraint::Ratio(1, 3), // Rewind button
-                    Constraint::Ratio(1, 3), // Play/Pause button
-                    Constraint::Ratio(1, 3), // Skip button
+                    Constraint::Ratio(1, 4), // Previous button
+                    Constraint::Ratio(1, 4), // Rewind button
+                    Constraint::Ratio(1, 4), // Play/Pause button
+                    Constraint::Ratio(1, 4), // Skip button
                 ])
                 .split(now_playing_layout[4]);
 
+            let previous_style = if self.current_focus == InputId::Previous
+                && self.state.input_mode == InputMode::Navigation
+            {
+                theme.focus()
+            } else {
+                Style::default()
+            };
             let rewind_style = if self.current_focus == InputId::Rewind
                 && self.state.input_mode == InputMode::Navigation
             {
-                Style::default().fg(Color::Yellow)
+                theme.focus()
             } else {
                 Style::default()
             };
             let play_pause_style = if self.current_focus == InputId::PlayPause
                 && self.state.input_mode == InputMode::Navigation
             {
-                Style::default().fg(Color::Yellow)
+                theme.focus()
             } else {
                 Style::default()
             };
@@ -676,11 +1839,16 @@ impl<B: Backend> Tui<B> {
             let skip_style = if self.current_focus == InputId::Skip
                 && self.state.input_mode == InputMode::Navigation
             {
-                Style::default().fg(Color::Yellow)
+                theme.focus()
             } else {
                 Style::default()
             };
 
+            let previous = Paragraph::new("[|<< Prev]")
+                .style(previous_style)
+                .alignment(Alignment::Center)
+                .add_modifier(Modifier::BOLD);
+
             let rewind = Paragraph::new("[<< Rewind]")
                 .style(rewind_style)
                 .alignment(Alignment::Center)
@@ -702,9 +1870,14 @@ impl<B: Backend> Tui<B> {
                 .alignment(Alignment::Center)
                 .add_modifier(Modifier::BOLD);
 
-            f.render_widget(rewind, control_layout[0]);
-            f.render_widget(play_pause, control_layout[1]);
-            f.render_widget(skip, control_layout[2]);
+            f.render_widget(previous, control_layout[0]);
+            f.render_widget(rewind, control_layout[1]);
+            f.render_widget(play_pause, control_layout[2]);
+            f.render_widget(skip, control_layout[3]);
+            widget_areas.insert(InputId::Previous, control_layout[0]);
+            widget_areas.insert(InputId::Rewind, control_layout[1]);
+            widget_areas.insert(InputId::PlayPause, control_layout[2]);
+            widget_areas.insert(InputId::Skip, control_layout[3]);
 
             let create_track_block = Block::default()
                 .title("Create New Track")
@@ -713,165 +1886,426 @@ impl<B: Backend> Tui<B> {
             let inner_create_track = create_track_block.inner(create_track_area);
             f.render_widget(create_track_block, create_track_area);
 
+            // The compact layout drops the spaces, puts five parameters on the first row, Swing
+            // and Time on the second, and the three buttons on the last one
+            let space_height = if compact { 0 } else { 1 };
             let create_track_layout = Layout::default()
                 .direction(LayoutDirection::Vertical)
                 .constraints([
-                    Constraint::Length(1), // Parameters row 1 (Scale, Style)
-                    Constraint::Length(1), // Space
-                    Constraint::Length(1), // Parameters row 2 (BPM, Length)
-                    Constraint::Length(1), // Space
-                    Constraint::Length(1), // Seed row
-                    Constraint::Length(1), // Space
-                    Constraint::Length(1), // Generate button
-                    Constraint::Length(1), // Space
-                    Constraint::Length(1), // Generate random button
+                    Constraint::Length(1),            // Parameters row 1 (Scale, Key, Style)
+                    Constraint::Length(space_height), // Space
+                    Constraint::Length(1),            // Parameters row 2 (BPM, Swing, Time, Length)
+                    Constraint::Length(space_height), // Space
+                    Constraint::Length(1),            // Seed row
+                    Constraint::Length(space_height), // Space
+                    Constraint::Length(1),            // Mixer row (Melody, Chords, Bass)
+                    Constraint::Length(space_height), // Space
+                    Constraint::Length(1),            // Generate button
+                    Constraint::Length(space_height), // Space
+                    Constraint::Length(space_height), // Generate random button
                 ])
                 .split(inner_create_track);
 
-            let params_layout_top = Layout::default()
-                .direction(LayoutDirection::Horizontal)
-                .constraints([
-                    Constraint::Ratio(1, 4), // Cell for Scale
-                    Constraint::Ratio(1, 4), // Empty cell (spacer)
-                    Constraint::Ratio(1, 4), // Empty cell (spacer)
-                    Constraint::Ratio(1, 4), // Cell for Style
-                ])
-                .split(create_track_layout[0]);
+            // A length being typed is shown as typed, without the popup arrow
+            let editing_length = (self.current_focus == InputId::Length && self.state.input_mode == InputMode::Editing)
+                .then_some(&self.length_input);
+            // Shorter labels let the five parameters share a row in the compact layout
+            let (scale_text, quality_text, style_text, bpm_prefix, length_text) = if compact {
+                (
+                    format!("Scale [{}▼]", self.state.scale),
+                    format!("[{}▼]", self.state.quality.chars().take(3).collect::<String>()),
+                    format!("Style [{}▼]", self.state.style),
+                    "BPM [",
+                    match &editing_length {
+                        Some(input) => format!("Len [{}]", input),
+                        None => format!("Len [{}▼]", format_song_length(self.state.length_secs)),
+                    },
+                )
+            } else {
+                (
+                    format!("Scale: [ {} ▼]", self.state.scale),
+                    format!("Key: [ {} ▼]", self.state.quality),
+                    format!("Style: [ {} ▼]", self.state.style),
+                    "BPM: [",
+                    match &editing_length {
+                        Some(input) => format!("Length: [{}]", input),
+                        None => format!("Length: [{} ▼]", format_song_length(self.state.length_secs)),
+                    },
+                )
+            };
+            let bpm_text = format!("{}{}]", bpm_prefix, self.state.bpm);
+            let time_signature_text = if compact {
+                format!("Time [{}▼]", self.state.time_signature)
+            } else {
+                format!("Time: [ {} ▼]", self.state.time_signature)
+            };
+            let swing_prefix = if compact { "Swing [" } else { "Swing: [" };
+            let editing_swing =
+                self.current_focus == InputId::Swing && self.state.input_mode == InputMode::Editing;
+            let swing_text = if self.state.swing.is_empty() && !editing_swing {
+                format!("{}Auto]", swing_prefix) // The style's default swing
+            } else {
+                format!("{}{}%]", swing_prefix, self.state.swing)
+            };
+
+            // Cells for Scale, Key, Style, BPM, Length, Swing and Time
+            let (scale_cell, quality_cell, style_cell, bpm_cell, length_cell, swing_cell, time_signature_cell) = if compact {
+                // Each cell as wide as its text, with the leftover width spread between them
+                let params_layout = Layout::default()
+                    .direction(LayoutDirection::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(scale_text.chars().count() as u16),
+                        Constraint::Min(0),
+                        Constraint::Length(quality_text.chars().count() as u16),
+                        Constraint::Min(0),
+                        Constraint::Length(style_text.chars().count() as u16),
+                        Constraint::Min(0),
+                        Constraint::Length(bpm_text.chars().count() as u16),
+                        Constraint::Min(0),
+                        Constraint::Length(length_text.chars().count() as u16),
+                        Constraint::Min(0),
+                    ])
+                    .split(create_track_layout[0]);
+                // The first row is full, so Swing and Time get the second one
+                let feel_layout = Layout::default()
+                    .direction(LayoutDirection::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(swing_text.chars().count() as u16),
+                        Constraint::Min(0),
+                        Constraint::Length(time_signature_text.chars().count() as u16),
+                        Constraint::Min(0),
+                    ])
+                    .split(create_track_layout[2]);
+                (
+                    params_layout[1],
+                    params_layout[3],
+                    params_layout[5],
+                    params_layout[7],
+                    params_layout[9],
+                    feel_layout[1],
+                    feel_layout[3],
+                )
+            } else {
+                let params_layout_top = Layout::default()
+                    .direction(LayoutDirection::Horizontal)
+                    .constraints([
+                        Constraint::Ratio(1, 4), // Cell for Scale
+                        Constraint::Ratio(1, 4), // Cell for Key
+                        Constraint::Ratio(1, 4), // Empty cell (spacer)
+                        Constraint::Ratio(1, 4), // Cell for Style
+                    ])
+                    .split(create_track_layout[0]);
+
+                // Layout for BPM and Length (second row of parameters)
+                let params_layout_bottom = Layout::default()
+                    .direction(LayoutDirection::Horizontal)
+                    .constraints([
+                        Constraint::Ratio(1, 4), // Cell for BPM
+                        Constraint::Ratio(1, 4), // Cell for Swing
+                        Constraint::Ratio(1, 4), // Cell for Time
+                        Constraint::Ratio(1, 4), // Cell for Length
+                    ])
+                    .split(create_track_layout[2]); // Use the second parameter row
+                (
+                    params_layout_top[0],
+                    params_layout_top[1],
+                    params_layout_top[3],
+                    params_layout_bottom[0],
+                    params_layout_bottom[3],
+                    params_layout_bottom[1],
+                    params_layout_bottom[2],
+                )
+            };
 
             // Style for the Scale widget, indicating focus or editing state
             let scale_style = if self.current_focus == InputId::Scale {
                 if self.state.input_mode == InputMode::Navigation {
-                    Style::default().fg(Color::Yellow) // Focused
+                    theme.focus() // Focused
                 } else {
-                    Style::default().fg(Color::Green) // Editing or popup active
+                    theme.edit() // Editing or popup active
                 }
             } else {
                 Style::default() // Not focused
             };
 
-            let scale_widget_paragraph =
-                Paragraph::new(format!("Scale: [ {} ▼]", self.state.scale))
-                    .style(scale_style)
-                    .add_modifier(Modifier::BOLD)
-                    .alignment(Alignment::Center);
+            let scale_widget_paragraph = Paragraph::new(scale_text)
+                .style(scale_style)
+                .add_modifier(Modifier::BOLD)
+                .alignment(Alignment::Center);
 
-            f.render_widget(scale_widget_paragraph, params_layout_top[0]);
+            f.render_widget(scale_widget_paragraph, scale_cell);
+            widget_areas.insert(InputId::Scale, scale_cell);
+
+            let quality_style = if self.current_focus == InputId::Quality {
+                if self.state.input_mode == InputMode::Navigation {
+                    theme.focus()
+                } else {
+                    theme.edit()
+                }
+            } else {
+                Style::default() // Not focused
+            };
+            let quality_param = Paragraph::new(quality_text)
+                .style(quality_style)
+                .add_modifier(Modifier::BOLD)
+                .alignment(Alignment::Center);
+            f.render_widget(quality_param, quality_cell);
+            widget_areas.insert(InputId::Quality, quality_cell);
 
             let style_style = if self.current_focus == InputId::Style {
                 if self.state.input_mode == InputMode::Navigation {
-                    Style::default().fg(Color::Yellow)
+                    theme.focus()
                 } else {
-                    Style::default().fg(Color::Green)
+                    theme.edit()
                 }
             } else {
                 Style::default() // Not focused
             };
-            let style_param = Paragraph::new(format!("Style: [ {} ▼]", self.state.style))
+            let style_param = Paragraph::new(style_text)
                 .style(style_style) // Apply conditional style
                 .add_modifier(Modifier::BOLD)
                 .alignment(Alignment::Center);
-            f.render_widget(style_param, params_layout_top[3]);
-
-            // Layout for BPM and Length (second row of parameters)
-            let params_layout_bottom = Layout::default()
-                .direction(LayoutDirection::Horizontal)
-                .constraints([
-                    Constraint::Ratio(1, 4), // Cell for BPM
-                    Constraint::Ratio(1, 4), // Empty cell (spacer)
-                    Constraint::Ratio(1, 4), // Empty cell (spacer)
-                    Constraint::Ratio(1, 4), // Cell for Length
-                ])
-                .split(create_track_layout[3]); // Use the second parameter row
+            f.render_widget(style_param, style_cell);
+            widget_areas.insert(InputId::Style, style_cell);
 
             let bpm_style = if self.current_focus == InputId::Bpm {
                 if self.state.input_mode == InputMode::Navigation {
-                    Style::default().fg(Color::Yellow)
+                    theme.focus()
                 } else { // Editing
-                    Style::default().fg(Color::Green)
+                    theme.edit()
                 }
             } else {
                 Style::default()
             };
 
-            let bpm = Paragraph::new(format!("BPM: [{}]", self.state.bpm))
+            let bpm = Paragraph::new(bpm_text.clone())
                 .style(bpm_style)
                 .add_modifier(Modifier::BOLD)
                 .alignment(Alignment::Center);
-            f.render_widget(bpm, params_layout_bottom[0]); // Render BPM in the first cell of the bottom params row
+            f.render_widget(bpm, bpm_cell); // Render BPM in the first cell of the bottom params row
+            widget_areas.insert(InputId::Bpm, bpm_cell);
 
             let length_style = if self.current_focus == InputId::Length {
                  if self.state.input_mode == InputMode::Navigation {
-                    Style::default().fg(Color::Yellow)
+                    theme.focus()
                 } else { // Popup active
-                    Style::default().fg(Color::Green)
+                    theme.edit()
                 }
             } else {
                 Style::default()
             };
 
-            let length = Paragraph::new(format!("Length: [{} ▼]", self.state.length))
+            let length = Paragraph::new(length_text)
                 .style(length_style)
                 .add_modifier(Modifier::BOLD)
                 .alignment(Alignment::Center);
-            f.render_widget(length, params_layout_bottom[3]); // Render Length in the fourth cell of the bottom params row
+            f.render_widget(length, length_cell); // Render Length in the fourth cell of the bottom params row
+            widget_areas.insert(InputId::Length, length_cell);
 
-            let seed_style = if self.current_focus == InputId::Seed {
+            let swing_style = if self.current_focus == InputId::Swing {
+                if self.state.input_mode == InputMode::Navigation {
+                    theme.focus()
+                } else { // Editing
+                    theme.edit()
+                }
+            } else {
+                Style::default()
+            };
+
+            let swing = Paragraph::new(swing_text.clone())
+                .style(swing_style)
+                .add_modifier(Modifier::BOLD)
+                .alignment(Alignment::Center);
+            f.render_widget(swing, swing_cell);
+            widget_areas.insert(InputId::Swing, swing_cell);
+
+            let time_signature_style = if self.current_focus == InputId::TimeSignature {
                 if self.state.input_mode == InputMode::Navigation {
-                    Style::default().fg(Color::Yellow)
+                    theme.focus()
+                } else { // Popup active
+                    theme.edit()
+                }
+            } else {
+                Style::default()
+            };
+
+            let time_signature = Paragraph::new(time_signature_text)
+                .style(time_signature_style)
+                .add_modifier(Modifier::BOLD)
+                .alignment(Alignment::Center);
+            f.render_widget(time_signature, time_signature_cell);
+            widget_areas.insert(InputId::TimeSignature, time_signature_cell);
+
+            let seed_style = if self.current_focus == InputId::Seed {
+                if seed_rejected {
+                    theme.error()
+                } else if self.state.input_mode == InputMode::Navigation {
+                    theme.focus()
                 } else { // Editing
-                    Style::default().fg(Color::Green)
+                    theme.edit()
                 }
             } else {
                 Style::default()
             };
 
-            let seed_display_string = if self.state.seed.is_empty() {
+            let mut seed_display_string = if self.state.seed.is_empty() {
                 "Seed (optional): []".to_string()
             } else {
                 format!("Seed (optional): [{}]", self.state.seed)
             };
+            if self.current_focus == InputId::Seed
+                && self.state.input_mode == InputMode::Editing
+                && self.state.seed.len() >= SEED_COUNTER_FROM
+            {
+                seed_display_string.push_str(&format!(" {}/{}", self.state.seed.len(), MAX_SEED_DIGITS));
+            }
+
+            // Seed and the custom progression share their row
+            let seed_row_layout = Layout::default()
+                .direction(LayoutDirection::Horizontal)
+                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+                .split(create_track_layout[4]);
+            let (seed_cell, progression_cell) = (seed_row_layout[0], seed_row_layout[1]);
 
             let seed = Paragraph::new(seed_display_string.clone())
                 .style(seed_style)
                 .add_modifier(Modifier::BOLD)
                 .alignment(Alignment::Center);
-            f.render_widget(seed, create_track_layout[5]); // Render Seed in its dedicated row
+            f.render_widget(seed, seed_cell);
+            widget_areas.insert(InputId::Seed, seed_cell);
+
+            let progression_style = if self.current_focus == InputId::Progression {
+                if self.state.input_mode == InputMode::Navigation {
+                    theme.focus()
+                } else { // Editing
+                    theme.edit()
+                }
+            } else {
+                Style::default()
+            };
+            let progression_prefix = if compact { "Chords [" } else { "Chords: [" };
+            let editing_progression =
+                self.current_focus == InputId::Progression && self.state.input_mode == InputMode::Editing;
+            let progression_text = if self.state.progression.is_empty() && !editing_progression {
+                format!("{}Style]", progression_prefix) // The style's own progressions
+            } else {
+                format!("{}{}]", progression_prefix, self.state.progression)
+            };
+            let progression = Paragraph::new(progression_text.clone())
+                .style(progression_style)
+                .add_modifier(Modifier::BOLD)
+                .alignment(Alignment::Center);
+            f.render_widget(progression, progression_cell);
+            widget_areas.insert(InputId::Progression, progression_cell);
+
+            // Mixer row: one cell per track gain
+            let mixer_layout = Layout::default()
+                .direction(LayoutDirection::Horizontal)
+                .constraints([
+                    Constraint::Ratio(1, 3), // Melody gain
+                    Constraint::Ratio(1, 3), // Chord gain
+                    Constraint::Ratio(1, 3), // Bass gain
+                ])
+                .split(create_track_layout[6]);
+
+            let mixer_cells = [
+                (InputId::MelodyGain, "Melody", &self.state.melody_gain),
+                (InputId::ChordGain, "Chords", &self.state.chord_gain),
+                (InputId::BassGain, "Bass", &self.state.bass_gain),
+            ];
+            for (cell_index, (input_id, label, value)) in mixer_cells.iter().enumerate() {
+                let gain_style = if self.current_focus == *input_id {
+                    if self.state.input_mode == InputMode::Navigation {
+                        theme.focus()
+                    } else { // Editing
+                        theme.edit()
+                    }
+                } else {
+                    Style::default()
+                };
+                let gain_widget = Paragraph::new(format!("{}: [{}%]", label, value))
+                    .style(gain_style)
+                    .add_modifier(Modifier::BOLD)
+                    .alignment(Alignment::Center);
+                f.render_widget(gain_widget, mixer_layout[cell_index]);
+                widget_areas.insert(*input_id, mixer_layout[cell_index]);
+            }
 
-            let generate_style = if self.current_focus == InputId::Generate
+            // Busy while a song is being generated; another press would be ignored
+            let generate_style = if generating_spinner.is_some() {
+                theme.dim()
+            } else if self.current_focus == InputId::Generate
                 && self.state.input_mode == InputMode::Navigation
             {
-                Style::default().fg(Color::Yellow)
+                theme.focus()
             } else {
                 Style::default()
             };
 
+            // The compact layout shares the Generate row between Generate, Generate Random and Load Song
+            let button_layout = Layout::default()
+                .direction(LayoutDirection::Horizontal)
+                .constraints(if compact {
+                    vec![Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3)]
+                } else {
+                    vec![Constraint::Min(1)]
+                })
+                .split(create_track_layout[8]);
+            let (generate_area, generate_random_area) = if compact {
+                (button_layout[0], button_layout[1])
+            } else {
+                (create_track_layout[8], create_track_layout[10])
+            };
+
             let generate = Paragraph::new("[♫ Generate]")
                 .style(generate_style)
                 .add_modifier(Modifier::BOLD)
                 .alignment(Alignment::Center);
-            f.render_widget(generate, create_track_layout[6]); // Render Generate in its dedicated row
+            f.render_widget(generate, generate_area); // Render Generate in its dedicated row
+            widget_areas.insert(InputId::Generate, generate_area);
 
-            let generate_style = if self.current_focus == InputId::GenerateRandom
+            let generate_style = if generating_spinner.is_some() {
+                theme.dim()
+            } else if self.current_focus == InputId::GenerateRandom
                 && self.state.input_mode == InputMode::Navigation
             {
-                Style::default().fg(Color::Yellow)
+                theme.focus()
             } else {
                 Style::default()
             };
 
-            let generate_random = Paragraph::new("[♫ Generate Random]")
+            let generate_random = Paragraph::new(if compact { "[♫ Random]" } else { "[♫ Generate Random]" })
                 .style(generate_style)
                 .add_modifier(Modifier::BOLD)
                 .alignment(Alignment::Center);
-            f.render_widget(generate_random, create_track_layout[8]); // Render GenerateRandom in its dedicated row
+            f.render_widget(generate_random, generate_random_area); // Render GenerateRandom in its dedicated row
+            widget_areas.insert(InputId::GenerateRandom, generate_random_area);
+
+            // Compact layout: a button that opens the Load Song popup
+            if compact {
+                let load_style = if self.current_focus == InputId::SongLoader {
+                    theme.focus()
+                } else {
+                    Style::default()
+                };
+                let load_button = Paragraph::new("[Load Song]")
+                    .style(load_style)
+                    .add_modifier(Modifier::BOLD)
+                    .alignment(Alignment::Center);
+                f.render_widget(load_button, button_layout[2]);
+                widget_areas.insert(InputId::SongLoader, button_layout[2]);
+            }
 
             // Define song_loader_block and inner_song_loader_area early for cursor logic
             let song_loader_block = Block::default()
                 .title("Load Song (Enter to Load)")
-                .borders(Borders::ALL);
+                .borders(Borders::ALL)
+                .style(if compact { theme.surface() } else { Style::default() });
             // This inner area is what the cursor logic will use for its coordinate calculations
             let inner_song_loader_area_for_cursor_and_render =
-                song_loader_block.inner(song_loader_area);
+                song_loader_area.map(|area| song_loader_block.inner(area)).unwrap_or_default();
 
             // Show cursor when in editing mode - Ensure this uses correct layout variables
             if self.state.input_mode == InputMode::Editing
@@ -879,9 +2313,9 @@ impl<B: Backend> Tui<B> {
             {
                 match self.current_focus {
                     InputId::Bpm => {
-                        let bpm_widget_cell_area = params_layout_bottom[0]; // Use the new layout cell for BPM
-                        let full_text_content = format!("BPM: [{}]", self.state.bpm);
-                        let text_prefix_len = "BPM: [".len() as u16;
+                        let bpm_widget_cell_area = bpm_cell; // Use the new layout cell for BPM
+                        let full_text_content = &bpm_text;
+                        let text_prefix_len = bpm_prefix.len() as u16;
 
                         let centered_text_start_x = bpm_widget_cell_area.x
                             + (bpm_widget_cell_area.width / 2)
@@ -892,8 +2326,25 @@ impl<B: Backend> Tui<B> {
                         let y = bpm_widget_cell_area.y; // Use y from the cell area
                         f.set_cursor(x, y);
                     }
+                    InputId::Swing => {
+                        let centered_text_start_x = swing_cell.x
+                            + (swing_cell.width / 2).saturating_sub(swing_text.len() as u16 / 2);
+                        let x = centered_text_start_x
+                            + swing_prefix.len() as u16
+                            + self.state.swing.len() as u16;
+                        f.set_cursor(x, swing_cell.y);
+                    }
+                    InputId::Progression => {
+                        let centered_text_start_x = progression_cell.x
+                            + (progression_cell.width / 2)
+                                .saturating_sub(progression_text.chars().count() as u16 / 2);
+                        let x = centered_text_start_x
+                            + progression_prefix.len() as u16
+                            + self.state.progression.chars().count() as u16;
+                        f.set_cursor(x, progression_cell.y);
+                    }
                     InputId::Seed => {
-                        let seed_widget_row_area = create_track_layout[4]; // Row for Seed (now correct)
+                        let seed_widget_row_area = seed_cell; // Cell for Seed
                         let text_prefix_len = "Seed (optional): [".len() as u16;
                         // seed_display_string is defined above in the rendering part
                         let centered_text_start_x = seed_widget_row_area.x
@@ -904,6 +2355,23 @@ impl<B: Backend> Tui<B> {
                         let y = seed_widget_row_area.y;
                         f.set_cursor(x, y);
                     }
+                    InputId::MelodyGain | InputId::ChordGain | InputId::BassGain => {
+                        let (cell_index, label, value) = match self.current_focus {
+                            InputId::MelodyGain => (0, "Melody", &self.state.melody_gain),
+                            InputId::ChordGain => (1, "Chords", &self.state.chord_gain),
+                            _ => (2, "Bass", &self.state.bass_gain),
+                        };
+                        let gain_cell_area = mixer_layout[cell_index];
+                        let full_text_content = format!("{}: [{}%]", label, value);
+                        let text_prefix_len = format!("{}: [", label).len() as u16;
+
+                        let centered_text_start_x = gain_cell_area.x
+                            + (gain_cell_area.width / 2)
+                                .saturating_sub(full_text_content.len() as u16 / 2);
+
+                        let x = centered_text_start_x + text_prefix_len + value.len() as u16;
+                        f.set_cursor(x, gain_cell_area.y);
+                    }
                     InputId::SongLoader => {
                         // Added cursor handling for SongLoader
                         let song_loader_text_prefix = "Load: [";
@@ -933,25 +2401,38 @@ impl<B: Backend> Tui<B> {
 
             // Render Load Song panel
             // song_loader_block is already defined, inner_song_loader_area_for_cursor_and_render holds the inner rect
-            f.render_widget(song_loader_block, song_loader_area); // Render the block itself
+            if let Some(song_loader_area) = song_loader_area {
+                if compact {
+                    f.render_widget(Clear, song_loader_area);
+                    popup_area_drawn = Some(song_loader_area);
+                } else {
+                    widget_areas.insert(InputId::SongLoader, song_loader_area);
+                }
+                f.render_widget(song_loader_block, song_loader_area); // Render the block itself
+            }
 
             let song_loader_input_style = if self.current_focus == InputId::SongLoader {
                 if self.state.input_mode == InputMode::SongLoaderEditing {
-                    Style::default().fg(Color::Green) // Editing SongLoader
+                    theme.edit() // Editing SongLoader
                 } else {
-                    Style::default().fg(Color::Yellow) // Navigating to SongLoader
+                    theme.focus() // Navigating to SongLoader
                 }
             } else {
                 Style::default()
             };
 
-            let song_loader_display_text = if self.state.input_mode == InputMode::SongLoaderEditing
-            {
-                format!("Load: [{}]", self.state.song_loader_input)
-            } else if self.state.song_loader_input.is_empty() {
-                "Load: []".to_string()
+            // Content selected for replacement (after `/`) is shown in inverse video
+            let song_loader_display_text = if self.song_loader_selected {
+                Line::from(vec![
+                    Span::raw("Load: ["),
+                    Span::styled(
+                        self.state.song_loader_input.clone(),
+                        Style::default().add_modifier(Modifier::REVERSED),
+                    ),
+                    Span::raw("]"),
+                ])
             } else {
-                format!("Load: [{}]", self.state.song_loader_input)
+                Line::from(format!("Load: [{}]", self.state.song_loader_input))
             };
 
             let song_loader_paragraph = Paragraph::new(song_loader_display_text)
@@ -972,13 +2453,83 @@ impl<B: Backend> Tui<B> {
 
             f.render_widget(song_loader_paragraph, centered_loader_layout[1]);
 
+            // Visualizers, side by side when both are shown
+            let visualizer_layout = Layout::default()
+                .direction(LayoutDirection::Horizontal)
+                .constraints(if scope_visible && spectrum_visible {
+                    vec![Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]
+                } else {
+                    vec![Constraint::Min(1)]
+                })
+                .split(Rect {
+                    height: VISUALIZER_PANEL_HEIGHT.min(panel_layout[6].height),
+                    ..panel_layout[6]
+                });
+
+            // Oscilloscope
+            if scope_visible {
+                let scope_area = visualizer_layout[0];
+                let scope_points: Vec<(f64, f64)> = self
+                    .scope_samples
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &sample)| (i as f64, sample.clamp(-1.0, 1.0) as f64))
+                    .collect();
+                let scope_dataset = Dataset::default()
+                    .marker(symbols::Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(theme.active())
+                    .data(&scope_points);
+                let scope_chart = Chart::new(vec![scope_dataset])
+                    .block(Block::default().title("Oscilloscope").borders(Borders::ALL))
+                    .x_axis(Axis::default().bounds([0.0, scope_points.len().saturating_sub(1).max(1) as f64]))
+                    .y_axis(Axis::default().bounds([-1.0, 1.0]));
+                f.render_widget(scope_chart, scope_area);
+            }
+
+            // Spectrum analyzer
+            if spectrum_visible {
+                let spectrum_area = visualizer_layout[visualizer_layout.len() - 1];
+                let spectrum_block = Block::default().title("Spectrum").borders(Borders::ALL);
+                let inner_width = spectrum_block.inner(spectrum_area).width as usize;
+                // One-cell gaps between bars; as many bands as fit, widening bars when there is room
+                let band_count = inner_width.div_ceil(2).min(self.spectrum_bands.len());
+                let bands = merge_bands(&self.spectrum_bands, band_count);
+                let bar_width = if bands.is_empty() {
+                    1
+                } else {
+                    ((inner_width + 1) / bands.len()).saturating_sub(1).max(1) as u16
+                };
+                let bars: Vec<Bar> = bands
+                    .iter()
+                    .map(|&magnitude| {
+                        Bar::default()
+                            .value((level_fraction(magnitude) * 100.0) as u64)
+                            .text_value(String::new())
+                    })
+                    .collect();
+                let spectrum_chart = BarChart::default()
+                    .block(spectrum_block)
+                    .data(BarGroup::default().bars(&bars))
+                    .bar_width(bar_width)
+                    .bar_gap(1)
+                    .bar_style(theme.visual())
+                    .max(100);
+                f.render_widget(spectrum_chart, spectrum_area);
+            }
+
             // Popup rendering section (ensure it is present if popups are used)
             if self.state.input_mode == InputMode::ScalePopup
+                || self.state.input_mode == InputMode::QualityPopup
                 || self.state.input_mode == InputMode::StylePopup
                 || self.state.input_mode == InputMode::LengthPopup
+                || self.state.input_mode == InputMode::TimeSignaturePopup
             {
-                let popup_width = 25;
-                let popup_height = 15;
+                // Tall enough for every option (+2 for borders) when the terminal allows;
+                // otherwise the list scrolls
+                let popup_width = OPTION_POPUP_WIDTH.min(f.size().width);
+                let popup_height = (self.state.popup_options().len() as u16 + 2)
+                    .min(f.size().height.saturating_sub(2));
                 let popup_x = (f.size().width - popup_width) / 2;
                 let popup_y = (f.size().height - popup_height) / 2;
 
@@ -993,46 +2544,142 @@ impl<B: Backend> Tui<B> {
 
                 let title = match self.state.input_mode {
                     InputMode::ScalePopup => "Select Scale",
+                    InputMode::QualityPopup => "Select Key",
                     InputMode::StylePopup => "Select Style",
                     InputMode::LengthPopup => "Select Length",
+                    InputMode::TimeSignaturePopup => "Select Time",
                     _ => "",
                 };
+                // Typing filters the options; the filter is shown in the title
+                let title = if self.state.popup_filter.is_empty() {
+                    title.to_string()
+                } else {
+                    format!("{}: {}_", title, self.state.popup_filter)
+                };
                 let popup_block = Block::default()
                     .title(title)
                     .borders(Borders::ALL)
-                    .style(Style::default().bg(Color::DarkGray));
+                    .style(theme.surface());
+                f.render_widget(popup_block.clone(), popup_area);
+                let inner_popup_area = popup_block.inner(popup_area);
+
+                let options = self.state.popup_options();
+                let items: Vec<ListItem> = self
+                    .state
+                    .visible_popup_options()
+                    .into_iter()
+                    .map(|index| ListItem::new(options[index].clone()))
+                    .collect();
+                let item_count = items.len();
+                if items.is_empty() {
+                    let no_matches = Paragraph::new("No matches").style(theme.dim());
+                    f.render_widget(no_matches, inner_popup_area);
+                }
+                let list_widget = List::new(items)
+                    .block(Block::default())
+                    .highlight_style(theme.selection());
+                f.render_stateful_widget(
+                    list_widget,
+                    inner_popup_area,
+                    &mut self.state.popup_list_state,
+                );
+                render_popup_scrollbar(
+                    f,
+                    popup_area,
+                    item_count,
+                    inner_popup_area.height,
+                    &self.state.popup_list_state,
+                    theme,
+                );
+                popup_area_drawn = Some(popup_area);
+                popup_list_area = Some(inner_popup_area);
+            }
+
+            // Library popup, newest songs first
+            if self.state.input_mode == InputMode::LibraryPopup {
+                let popup_width = LIBRARY_POPUP_WIDTH.min(f.size().width);
+                let popup_height = LIBRARY_POPUP_HEIGHT.min(f.size().height);
+                let popup_area = Rect {
+                    x: (f.size().width - popup_width) / 2,
+                    y: (f.size().height - popup_height) / 2,
+                    width: popup_width,
+                    height: popup_height,
+                };
+
+                f.render_widget(Clear, popup_area);
+                let popup_block = Block::default()
+                    .title("Library (Enter: Load, d: Remove, Esc: Close)")
+                    .borders(Borders::ALL)
+                    .style(theme.surface());
                 f.render_widget(popup_block.clone(), popup_area);
                 let inner_popup_area = popup_block.inner(popup_area);
 
-                let items: Vec<ListItem> = match self.state.input_mode {
-                    InputMode::ScalePopup => self
-                        .state
-                        .scales
-                        .iter()
-                        .map(|s| ListItem::new(s.clone()))
-                        .collect(),
-                    InputMode::StylePopup => self
-                        .state
-                        .styles
-                        .iter()
-                        .map(|s| ListItem::new(s.clone()))
-                        .collect(),
-                    InputMode::LengthPopup => self
-                        .state
-                        .lengths
-                        .iter()
-                        .map(|s| ListItem::new(s.clone()))
-                        .collect(),
-                    _ => vec![],
+                let items: Vec<ListItem> = self
+                    .library
+                    .iter()
+                    .rev()
+                    .map(|entry| {
+                        ListItem::new(format!(
+                            "{}  {}",
+                            entry.song_id,
+                            library::format_saved_at(entry.saved_at)
+                        ))
+                    })
+                    .collect();
+                let list_widget = List::new(items)
+                    .block(Block::default())
+                    .highlight_style(theme.selection());
+                f.render_stateful_widget(
+                    list_widget,
+                    inner_popup_area,
+                    &mut self.state.popup_list_state,
+                );
+                render_popup_scrollbar(
+                    f,
+                    popup_area,
+                    self.library.len(),
+                    inner_popup_area.height,
+                    &self.state.popup_list_state,
+                    theme,
+                );
+                popup_area_drawn = Some(popup_area);
+                popup_list_area = Some(inner_popup_area);
+            }
+
+            // Waveform popup, one row per track
+            if self.state.input_mode == InputMode::WaveformPopup {
+                let popup_width = WAVEFORM_POPUP_WIDTH.min(f.size().width);
+                let popup_height = (WAVEFORM_TRACKS.len() as u16 + 2).min(f.size().height);
+                let popup_area = Rect {
+                    x: (f.size().width - popup_width) / 2,
+                    y: (f.size().height - popup_height) / 2,
+                    width: popup_width,
+                    height: popup_height,
                 };
+
+                f.render_widget(Clear, popup_area);
+                let popup_block = Block::default()
+                    .title("Waveforms (Enter: Change, Esc: Close)")
+                    .borders(Borders::ALL)
+                    .style(theme.surface());
+                f.render_widget(popup_block.clone(), popup_area);
+                let inner_popup_area = popup_block.inner(popup_area);
+
+                let items: Vec<ListItem> = WAVEFORM_TRACKS
+                    .iter()
+                    .zip(&self.state.waveforms)
+                    .map(|(track, waveform)| ListItem::new(format!("{}: {}", track, waveform)))
+                    .collect();
                 let list_widget = List::new(items)
                     .block(Block::default())
-                    .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+                    .highlight_style(theme.selection());
                 f.render_stateful_widget(
                     list_widget,
                     inner_popup_area,
                     &mut self.state.popup_list_state,
                 );
+                popup_area_drawn = Some(popup_area);
+                popup_list_area = Some(inner_popup_area);
             }
 
             // Song ID Error Popup
@@ -1053,11 +2700,18 @@ impl<B: Backend> Tui<B> {
                     };
 
                     f.render_widget(Clear, popup_area); // Clear the area for the popup
+                    popup_area_drawn = Some(popup_area);
 
+                    let popup_title = match self.current_focus {
+                        InputId::Progression => "Invalid Progression",
+                        InputId::Bpm => "Invalid BPM",
+                        InputId::Length => "Invalid Length",
+                        _ => "Invalid Song ID",
+                    };
                     let popup_block = Block::default()
-                        .title("Invalid Song ID")
+                        .title(popup_title)
                         .borders(Borders::ALL)
-                        .style(Style::default().bg(Color::DarkGray).fg(Color::Red)); // Red text for error
+                        .style(theme.surface().patch(theme.error())); // Error text on the popup background
 
                     let inner_popup_area = popup_block.inner(popup_area);
                     f.render_widget(popup_block.clone(), popup_area);
@@ -1074,12 +2728,12 @@ impl<B: Backend> Tui<B> {
 
                     let error_paragraph = Paragraph::new(error_msg.clone())
                         .wrap(ratatui::widgets::Wrap { trim: true })
-                        .style(Style::default().fg(Color::White)); // White text on dark gray bg
+                        .style(theme.text()); // Plain text on the popup background
                     f.render_widget(error_paragraph, popup_content_layout[0]);
 
                     let instruction_paragraph = Paragraph::new("Press Enter or Esc to correct.")
                         .alignment(Alignment::Center)
-                        .style(Style::default().fg(Color::Yellow));
+                        .style(theme.focus());
                     f.render_widget(instruction_paragraph, popup_content_layout[1]);
                 }
             }
@@ -1087,14 +2741,16 @@ impl<B: Backend> Tui<B> {
             // Help Popup / Menu
             if self.state.show_help {
                 let help_text = vec![
-                    Line::from(Span::styled("--- Hotkeys ---", Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow))),
+                    Line::from(Span::styled("--- Hotkeys ---", theme.focus().add_modifier(Modifier::BOLD))),
                     Line::from(""),
                     Line::from(Span::styled("Global:", Style::default().add_modifier(Modifier::UNDERLINED))),
                     Line::from("  q: Quit"),
                     Line::from("  p: Play/Pause"),
-                    Line::from("  r: Rewind Song"),
+                    Line::from("  r: Rewind to Section Start (Twice: Song Start)"),
                     Line::from("  f: Fast Forward (New Random Song)"),
                     Line::from("  ?: Toggle Help Menu"),
+                    Line::from("  Mouse: Click to Activate, Click Outside a Popup to Close"),
+                    Line::from("  Mouse: Click or Drag the Progress Bar to Seek"),
                     Line::from(""),
                     Line::from(Span::styled("Navigation Mode (Arrow Keys or Vim Keys):", Style::default().add_modifier(Modifier::UNDERLINED))),
                     Line::from("  ↑/k: Navigate Up"),
@@ -1102,21 +2758,53 @@ impl<B: Backend> Tui<B> {
                     Line::from("  ←/h: Navigate Left"),
                     Line::from("  →/l: Navigate Right"),
                     Line::from("  Enter: Select / Activate"),
+                    Line::from("  /: Jump to Load Song (typing replaces the ID)"),
+                    Line::from("  +/-: Volume Up/Down"),
+                    Line::from("  m: Mute/Unmute"),
+                    Line::from("  o: Toggle Oscilloscope"),
+                    Line::from("  s: Toggle Spectrum Analyzer"),
+                    Line::from("  L: Loop Current Song On/Off"),
+                    Line::from("  d: Drums On/Off (From the Next Song)"),
+                    Line::from("  .: Stop After Current Song On/Off"),
+                    Line::from("  x: Cycle Crossfade Between Songs (Off, 1-5s)"),
+                    Line::from("  K: Cycle Key Change in the Last Section (Auto, On, Off)"),
+                    Line::from("  H: Cycle Harmony Voice (Off, Chorus, Whole Song)"),
+                    Line::from("  C: Cycle Counter-Melody (Auto, On, Off)"),
+                    Line::from("  A: Cycle Chip Arpeggio Chords (Auto, On, Off)"),
+                    Line::from("  R: Cycle Chord Comping (Auto Rhythm, Sustain)"),
+                    Line::from("  P: Cycle Square Pulse Width (Auto, 12.5%, 25%, 50%, PWM)"),
+                    Line::from("  T: Cycle Brightness (Auto, Dark, Warm, Bright, Open)"),
+                    Line::from("  E: Melody Echo On/Off (Auto, Off)"),
+                    Line::from("  B: Cycle Lo-Fi Bit Crusher (Auto, Off, Light, Heavy)"),
+                    Line::from("  N: NES Mode On/Off (2 Pulses, Triangle Bass, Noise Drums)"),
+                    Line::from("  W: Choose Lead, Chord and Bass Waveforms (Enter Changes)"),
+                    Line::from("  M: Vary Melody (Same Song, New Melody)"),
+                    Line::from("  V: Vary Bass (Same Song, New Bass Line)"),
+                    Line::from("  b: Previous Song (Back Through History)"),
+                    Line::from("  S: Save/Remove Current Song in Library"),
+                    Line::from("  v: Open Library (Enter Loads, d Removes)"),
+                    Line::from("  y: Copy Song ID to Clipboard"),
+                    Line::from("  Y: Share Song (Short Code, Enter Copies It)"),
+                    Line::from("  F12: Toggle Debug Info"),
                     Line::from(""),
-                    Line::from(Span::styled("Editing Mode (for BPM, Seed, Load ID):", Style::default().add_modifier(Modifier::UNDERLINED))),
+                    Line::from(Span::styled("Editing Mode (for BPM, Length, Swing, Seed, Chords, Mixer, Load ID):", Style::default().add_modifier(Modifier::UNDERLINED))),
                     Line::from("  Enter: Confirm Edit"),
                     Line::from("  Esc: Cancel Edit"),
                     Line::from("  Backspace: Delete Character"),
+                    Line::from("  Ctrl+V: Paste (Invalid Characters Are Dropped)"),
+                    Line::from("  Length: Type a Number on It (7, 2.5, 90s, 2:30) or Pick Custom…"),
                     Line::from(""),
-                    Line::from(Span::styled("Popup Menus (Scale, Style, Length):", Style::default().add_modifier(Modifier::UNDERLINED))),
-                    Line::from("  ↑/k: Cycle Up"),
-                    Line::from("  ↓/j: Cycle Down"),
+                    Line::from(Span::styled("Popup Menus (Scale, Key, Style, Length, Time):", Style::default().add_modifier(Modifier::UNDERLINED))),
+                    Line::from("  ↑: Cycle Up"),
+                    Line::from("  ↓: Cycle Down"),
+                    Line::from("  PgUp/PgDn, Home/End: Jump a Page, to First/Last"),
                     Line::from("  Enter: Select Item"),
-                    Line::from("  Esc: Close Popup"),
+                    Line::from("  Type: Filter Items (Backspace Edits the Filter)"),
+                    Line::from("  Esc: Clear the Filter, Then Close Popup"),
                 ];
 
-                let popup_width = 60;
-                let popup_height = (help_text.len() + 2) as u16; // +2 for borders
+                let popup_width = 60.min(f.size().width);
+                let popup_height = ((help_text.len() + 2) as u16).min(f.size().height); // +2 for borders
 
                 let popup_x = (f.size().width.saturating_sub(popup_width)) / 2;
                 let popup_y = (f.size().height.saturating_sub(popup_height)) / 2;
@@ -1129,11 +2817,12 @@ impl<B: Backend> Tui<B> {
                 };
 
                 f.render_widget(Clear, popup_area); // Clear the area for the popup
+                popup_area_drawn = Some(popup_area);
 
                 let help_block = Block::default()
                     .title("Help - Hotkeys")
                     .borders(Borders::ALL)
-                    .style(Style::default().bg(Color::DarkGray));
+                    .style(theme.surface());
                 
                 let help_paragraph = Paragraph::new(help_text)
                     .block(help_block)
@@ -1142,13 +2831,147 @@ impl<B: Backend> Tui<B> {
                 f.render_widget(help_paragraph, popup_area);
             }
 
-            // Render Help Hint Footer
-            let help_hint = Paragraph::new("Press ? for help")
-                .style(Style::default().fg(Color::DarkGray))
-                .alignment(Alignment::Center);
+            // Share Popup
+            if self.state.input_mode == InputMode::SharePopup {
+                if let Some((song_id, code)) = &self.share {
+                    let popup_width = 64.min(f.size().width);
+                    let text_width = popup_width as usize - 4; // -4 for padding/borders
+                    let mut lines: Vec<Line> = vec![Line::from(Span::styled("Song ID", theme.dim()))];
+                    lines.extend(textwrap::wrap(song_id, text_width).into_iter().map(|l| Line::from(l.into_owned())));
+                    lines.push(Line::from(""));
+                    lines.push(Line::from(Span::styled("Share code", theme.dim())));
+                    lines.push(Line::from(Span::styled(code.clone(), theme.focus())));
+                    let popup_height = (lines.len() + 4) as u16; // +2 for the instruction and its spacer, +2 for borders
+
+                    let popup_area = Rect {
+                        x: (f.size().width.saturating_sub(popup_width)) / 2,
+                        y: (f.size().height.saturating_sub(popup_height)) / 2,
+                        width: popup_width,
+                        height: popup_height,
+                    };
+
+                    f.render_widget(Clear, popup_area);
+                    popup_area_drawn = Some(popup_area);
+
+                    let popup_block = Block::default()
+                        .title("Share Song")
+                        .borders(Borders::ALL)
+                        .style(theme.surface());
+                    let inner_popup_area = popup_block.inner(popup_area);
+                    f.render_widget(popup_block, popup_area);
+
+                    let popup_content_layout = Layout::default()
+                        .direction(LayoutDirection::Vertical)
+                        .constraints([
+                            Constraint::Min(1),    // ID and code
+                            Constraint::Length(1), // Spacer
+                            Constraint::Length(1), // Instruction
+                        ])
+                        .split(inner_popup_area);
+
+                    f.render_widget(Paragraph::new(lines).style(theme.text()), popup_content_layout[0]);
+
+                    let instruction_paragraph = Paragraph::new("Enter: Copy Share Code  Esc: Close")
+                        .alignment(Alignment::Center)
+                        .style(theme.focus());
+                    f.render_widget(instruction_paragraph, popup_content_layout[2]);
+                }
+            }
+
+            // What's New Popup (shown once after an upgrade)
+            if self.state.input_mode == InputMode::WhatsNewPopup {
+                let popup_width = 64.min(f.size().width);
+                let text_width = popup_width as usize - 4; // -4 for padding/borders
+                let wrapped_lines: Vec<Line> = self
+                    .state
+                    .whats_new_lines
+                    .iter()
+                    .flat_map(|line| {
+                        if line.is_empty() {
+                            vec![Line::from("")]
+                        } else {
+                            textwrap::wrap(line, text_width)
+                                .into_iter()
+                                .map(|l| Line::from(l.into_owned()))
+                                .collect()
+                        }
+                    })
+                    .collect();
+
+                // +2 for borders, +2 for the instruction line and its spacer
+                let max_popup_height = f.size().height.saturating_sub(2);
+                let popup_height = ((wrapped_lines.len() + 4) as u16).min(max_popup_height);
+                let visible_lines = popup_height.saturating_sub(4);
+                let max_scroll = (wrapped_lines.len() as u16).saturating_sub(visible_lines);
+                self.state.whats_new_scroll = self.state.whats_new_scroll.min(max_scroll);
+
+                let popup_area = Rect {
+                    x: (f.size().width.saturating_sub(popup_width)) / 2,
+                    y: (f.size().height.saturating_sub(popup_height)) / 2,
+                    width: popup_width,
+                    height: popup_height,
+                };
+
+                f.render_widget(Clear, popup_area);
+                popup_area_drawn = Some(popup_area);
+
+                let popup_block = Block::default()
+                    .title(format!("What's new in {}", env!("CARGO_PKG_VERSION")))
+                    .borders(Borders::ALL)
+                    .style(theme.surface());
+                let inner_popup_area = popup_block.inner(popup_area);
+                f.render_widget(popup_block, popup_area);
+
+                let popup_content_layout = Layout::default()
+                    .direction(LayoutDirection::Vertical)
+                    .constraints([
+                        Constraint::Min(1),    // Changelog text
+                        Constraint::Length(1), // Spacer
+                        Constraint::Length(1), // Instruction
+                    ])
+                    .split(inner_popup_area);
+
+                let changes_paragraph = Paragraph::new(wrapped_lines)
+                    .scroll((self.state.whats_new_scroll, 0))
+                    .style(theme.text());
+                f.render_widget(changes_paragraph, popup_content_layout[0]);
+
+                let instruction = if max_scroll > 0 {
+                    "↑/↓: Scroll  Enter: Don't show again"
+                } else {
+                    "Enter: Don't show again"
+                };
+                let instruction_paragraph = Paragraph::new(instruction)
+                    .alignment(Alignment::Center)
+                    .style(theme.focus());
+                f.render_widget(instruction_paragraph, popup_content_layout[2]);
+            }
+
+            // Render Help Hint Footer; the compact layout shows notices here instead
+            let help_hint = match notice.filter(|_| compact) {
+                Some(notice) => Paragraph::new(notice).style(theme.focus().add_modifier(Modifier::BOLD)),
+                None => Paragraph::new("Press ? for help").style(theme.dim()),
+            }
+            .alignment(Alignment::Center);
             f.render_widget(help_hint, footer_area);
 
+            // Debug overlay, right-aligned on the footer row
+            if self.show_debug {
+                let stats = &self.worker_stats;
+                let debug_text = Paragraph::new(format!(
+                    "workers {} | queued {} | running {} | done {} | cancelled {}",
+                    stats.workers, stats.queued, stats.running, stats.completed, stats.cancelled
+                ))
+                .style(theme.visual())
+                .alignment(Alignment::Right);
+                f.render_widget(debug_text, footer_area);
+            }
+
         })?;
+        self.widget_areas = widget_areas;
+        self.popup_area = popup_area_drawn;
+        self.popup_list_area = popup_list_area;
+        self.gauge_area = gauge_area;
         Ok(())
     }
 
@@ -1165,6 +2988,9 @@ impl<B: Backend> Tui<B> {
     }
 
     /* set_app_state - Replaces the current application state with a new one.
+     *
+     * While a text field is being edited, the new state is merged instead, keeping the
+     * edited field, the input mode and the value Esc would restore.
      *
      * inputs:
      *     - &mut self
@@ -1174,7 +3000,12 @@ impl<B: Backend> Tui<B> {
      *     - None
      */
     pub fn set_app_state(&mut self, new_state: AppState) {
-        self.state = new_state;
+        match self.state.input_mode {
+            InputMode::Editing | InputMode::SongLoaderEditing => {
+                self.state.merge_during_edit(new_state, self.current_focus);
+            }
+            _ => self.state = new_state,
+        }
     }
 
     /* set_playing_state - Explicitly sets the playback state (playing or paused).
@@ -1190,48 +3021,73 @@ impl<B: Backend> Tui<B> {
      *     - None
      */
     pub fn set_playing_state(&mut self, is_playing: bool) {
+        // Pausing stops the bar where it is now rather than at the last progress message
+        match (self.state.is_playing, is_playing) {
+            (true, false) => self.progress.freeze(Instant::now()),
+            (false, true) => self.progress.resume(Instant::now()),
+            _ => {}
+        }
         self.state.is_playing = is_playing;
     }
 
-    /* toggle_help - Toggles the visibility of the help menu.
+    /* show_whats_new - Opens the "What's new" popup with the given changelog lines.
      *
      * inputs:
      *     - &mut self
+     *     - lines (Vec<String>): The changelog lines to display.
      *
      * outputs:
      *     - None
      */
-    pub fn toggle_help(&mut self) {
-        self.state.show_help = !self.state.show_help;
+    pub fn show_whats_new(&mut self, lines: Vec<String>) {
+        self.state.whats_new_lines = lines;
+        self.state.whats_new_scroll = 0;
+        self.state.input_mode = InputMode::WhatsNewPopup;
     }
 
-    /* is_paused - Checks if music playback is currently paused.
+    /* set_volume - Sets the volume shown in the Now Playing panel.
      *
      * inputs:
-     *     - &self
+     *     - &mut self
+     *     - volume (f32): The master volume (1.0 = 100%).
      *
      * outputs:
-     *     - bool : True if playback is paused, false otherwise.
+     *     - None
      */
-    pub fn is_paused(&self) -> bool {
-        !self.state.is_playing
+    pub fn set_volume(&mut self, volume: f32) {
+        self.state.volume = volume;
     }
 
-    /* clear_song_loader_input - Clears the text from the song loader input field.
+    /* update_level - Sets the output level reported by the music service.
+     *
+     * The meter jumps up to louder levels and decays smoothly towards quieter ones,
+     * so a pause (level 0.0) lets it fall off rather than blanking instantly.
      *
      * inputs:
      *     - &mut self
+     *     - level (f32): Linear RMS output level (0.0 to 1.0).
      *
      * outputs:
      *     - None
      */
-    pub fn clear_song_loader_input(&mut self) {
-        self.state.song_loader_input.clear();
+    pub fn update_level(&mut self, level: f32) {
+        self.output_level = level;
     }
 
-    /* focus_on_play_pause - Sets the UI focus to the Play/Pause button.
+    /* update_scope - Sets the waveform window shown by the oscilloscope.
      *
-     * This also ensures the TUI is in Navigation mode.
+     * inputs:
+     *     - &mut self
+     *     - samples (Vec<f32>): Downsampled samples around the playback position (empty while paused).
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn update_scope(&mut self, samples: Vec<f32>) {
+        self.scope_samples = samples;
+    }
+
+    /* toggle_scope - Shows or hides the oscilloscope panel.
      *
      * inputs:
      *     - &mut self
@@ -1239,380 +3095,2250 @@ impl<B: Backend> Tui<B> {
      * outputs:
      *     - None
      */
-    pub fn focus_on_play_pause(&mut self) {
-        self.current_focus = InputId::PlayPause;
-        self.state.input_mode = InputMode::Navigation; // Ensure navigation mode after focusing.
+    pub fn toggle_scope(&mut self) {
+        self.show_scope = !self.show_scope;
     }
 
-    /* show_song_id_error - Displays an error message related to song ID loading.
+    /* set_generating - Shows or hides that a new song is being generated.
      *
-     * Sets the TUI to `SongIdErrorPopup` mode to show the message.
+     * While it shows, the Now Playing panel has a spinner instead of the song's times and the
+     * Generate buttons look busy.
      *
      * inputs:
      *     - &mut self
-     *     - error_message (String): The error message to display.
+     *     - generating (bool): True while the music service is generating a song.
      *
      * outputs:
      *     - None
      */
-    pub fn show_song_id_error(&mut self, error_message: String) {
-        self.state.song_id_error = Some(error_message);
-        self.state.input_mode = InputMode::SongIdErrorPopup;
+    pub fn set_generating(&mut self, generating: bool) {
+        match (generating, self.generating_since) {
+            (true, None) => self.generating_since = Some(Instant::now()),
+            (false, Some(_)) => self.generating_since = None,
+            _ => {}
+        }
     }
 
-    /* reset_current_song_progress - Resets playback progress for the current song (e.g., on rewind).
+    /* is_generating - Checks whether a new song is being generated.
      *
-     * This visually resets the elapsed time and progress bar to the beginning.
-     * The total song duration remains unchanged.
-     * Typically, playback is set to `true` after a rewind.
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: True from a Generate until the new song starts playing.
+     */
+    pub fn is_generating(&self) -> bool {
+        self.generating_since.is_some()
+    }
+
+    /* set_chord_timeline - Sets the chord timeline of the song that just started.
      *
      * inputs:
      *     - &mut self
+     *     - timeline (Vec<(u64, String)>): Start sample and name of every chord, in order.
      *
      * outputs:
      *     - None
      */
-    pub fn reset_current_song_progress(&mut self) {
-        // Only reset the current playback position visually.
-        // The actual duration and definitive progress comes from music_service.
-        // The existing current_song_duration_secs remains, so "MM:SS / TotalDuration" looks consistent.
-        self.state.current_song_elapsed_secs = 0.0;
-        self.state.current_song_progress = 0.0;
-        self.state.is_playing = true; // Ensure playing state is true after rewind.
+    pub fn set_chord_timeline(&mut self, timeline: Vec<(u64, String)>) {
+        self.chord_timeline = timeline;
+        self.current_chord = None;
     }
 
-    /* reset_progress_for_new_song - Resets all progress information for a new song.
+    /* set_section_timeline - Sets the section timeline of the song that just started.
      *
-     * Calls `update_progress(0,0)` to clear times and progress percentage.
+     * inputs:
+     *     - &mut self
+     *     - timeline (Vec<(u64, SongSection)>): Start sample of every section, in order.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_section_timeline(&mut self, timeline: Vec<(u64, SongSection)>) {
+        self.section_timeline = timeline;
+        self.current_section = None;
+    }
 
-     * handled by the main application logic when a new song starts.
+    /* update_loudness - Sets the measured loudness of the playing song.
      *
      * inputs:
      *     - &mut self
+     *     - loudness_db (Option<f32>): The song's loudness in dBFS, or None until it is fully rendered.
      *
      * outputs:
      *     - None
      */
-    pub fn reset_progress_for_new_song(&mut self) {
-        self.update_progress(0, 0);
-        // self.state.current_song_id_display = None; // Clearing ID is handled by main.rs/progress updates
+    pub fn update_loudness(&mut self, loudness_db: Option<f32>) {
+        self.loudness_db = loudness_db;
     }
 
-    /* handle_input - Processes user input events from the terminal.
+    /* update_beat - Sets the beat position reported by the music service.
+     *
+     * inputs:
+     *     - &mut self
+     *     - current_beat (u32): Zero-based beat at the playback position.
+     *     - beat_phase (f32): Position within that beat (0.0 to 1.0).
+     *     - beats_per_bar (u32): Beats in a bar of the playing song.
+     *     - bpm (u32): Tempo of the playing song, shown when its BPM was picked from the seed.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn update_beat(&mut self, current_beat: u32, beat_phase: f32, beats_per_bar: u32, bpm: u32) {
+        self.current_beat = current_beat;
+        self.beat_phase = beat_phase;
+        self.beats_per_bar = beats_per_bar;
+        self.song_bpm = bpm;
+    }
+
+    /* update_spectrum - Sets the band magnitudes shown by the spectrum analyzer.
      *
-     * This method polls for keyboard events. Based on the current `InputMode`
-     * (e.g., Navigation, Editing, Popup) and the specific key pressed, it determines
-     * the appropriate `UserAction` to return. It handles global shortcuts (like Quit, ToggleHelp),
-     * navigation between UI elements, text input into fields, interaction with popups,
-     * and actions related to music control and generation.
+     * An empty update (sent while paused) leaves the last bands on screen, freezing the display.
      *
      * inputs:
      *     - &mut self
+     *     - bands (Vec<f32>): Band magnitudes (0.0 to 1.0), lowest band first.
      *
      * outputs:
-     *     - std::io::Result<UserAction> : The determined `UserAction` or an I/O error.
+     *     - None
      */
-    pub fn handle_input(&mut self) -> std::io::Result<UserAction> {
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if self.state.show_help {
-                    // When help is shown, only '?' or 'q' on press do something.
-                    // All other events (other keys, or non-press events) are NoOp.
-                    if key.kind == event::KeyEventKind::Press {
-                        match key.code {
-                            KeyCode::Char('?') => return Ok(UserAction::ToggleHelp), // Action to close help
-                            KeyCode::Char('q') => return Ok(UserAction::Quit),
-                            _ => {} // Other pressed keys will fall through to the NoOp below
-                        }
-                    }
-                    return Ok(UserAction::NoOp); // Catch-all for any event if help is shown and not handled above
-                }
+    pub fn update_spectrum(&mut self, bands: Vec<f32>) {
+        if !bands.is_empty() {
+            self.spectrum_bands = bands;
+        }
+    }
 
-                // ---- Help is NOT shown at this point ----
-                // Global keybindings (available when help is NOT shown)
-                if key.kind == event::KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('?') => return Ok(UserAction::ToggleHelp), // Action to open help
-                        KeyCode::Char('q') => return Ok(UserAction::Quit),
-                        KeyCode::Char('p') => return Ok(UserAction::TogglePlayback),
-                        KeyCode::Char('r') => return Ok(UserAction::RewindSong),
-                        KeyCode::Char('f') => return Ok(UserAction::FastForwardSong),
-                        _ => {} 
-                    }
-                }
+    /* toggle_spectrum - Shows or hides the spectrum analyzer panel.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn toggle_spectrum(&mut self) {
+        self.show_spectrum = !self.show_spectrum;
+    }
 
-                if key.kind != event::KeyEventKind::Press {
-                    return Ok(UserAction::NoOp);
-                }
+    /* set_muted - Sets whether the Now Playing panel shows the muted badge.
+     *
+     * inputs:
+     *     - &mut self
+     *     - is_muted (bool): True if output is muted.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_muted(&mut self, is_muted: bool) {
+        self.state.is_muted = is_muted;
+    }
 
-                match self.state.input_mode {
-                    InputMode::Navigation => {
-                        match key.code {
-                            KeyCode::Up | KeyCode::Char('k') => {
-                                self.current_focus = next_focus(self.current_focus, Direction::Up);
-                                Ok(UserAction::Navigate)
-                            }
-                            KeyCode::Down | KeyCode::Char('j') => {
-                                self.current_focus =
-                                    next_focus(self.current_focus, Direction::Down);
-                                Ok(UserAction::Navigate)
-                            }
-                            KeyCode::Left | KeyCode::Char('h') => {
-                                self.current_focus =
-                                    next_focus(self.current_focus, Direction::Left);
-                                Ok(UserAction::Navigate)
-                            }
-                            KeyCode::Right | KeyCode::Char('l') => {
-                                self.current_focus =
-                                    next_focus(self.current_focus, Direction::Right);
-                                Ok(UserAction::Navigate)
-                            }
-                            KeyCode::Enter => match self.current_focus {
-                                InputId::Rewind => Ok(UserAction::RewindSong),
-                                InputId::PlayPause => {
-                                    Ok(UserAction::TogglePlayback)
-                                }
-                                InputId::Skip => Ok(UserAction::FastForwardSong),
-                                InputId::Scale => {
-                                    self.state.input_mode = InputMode::ScalePopup;
-                                    self.state.popup_list_state.select(Some(0));
-                                    Ok(UserAction::OpenPopup)
-                                }
-                                InputId::Style => {
-                                    self.state.input_mode = InputMode::StylePopup;
-                                    self.state.popup_list_state.select(Some(0));
-                                    Ok(UserAction::OpenPopup)
-                                }
-                                InputId::Length => {
-                                    self.state.input_mode = InputMode::LengthPopup;
-                                    self.state.popup_list_state.select(Some(0));
-                                    Ok(UserAction::OpenPopup)
-                                }
-                                InputId::Bpm => {
-                                    self.editing_original_value = Some(self.state.bpm.clone());
-                                    self.state.input_mode = InputMode::Editing;
-                                    Ok(UserAction::SwitchToEditing)
-                                }
-                                InputId::Seed => {
-                                    self.editing_original_value = Some(self.state.seed.clone());
-                                    self.state.input_mode = InputMode::Editing;
-                                    Ok(UserAction::SwitchToEditing)
-                                }
-                                InputId::Generate => Ok(UserAction::GenerateMusic),
-                                InputId::GenerateRandom => Ok(UserAction::GenerateRandomMusic),
-                                InputId::SongLoader => {
-                                    // Added SongLoader Enter in Navigation mode
-                                    self.editing_original_value =
-                                        Some(self.state.song_loader_input.clone());
-                                    self.state.input_mode = InputMode::SongLoaderEditing;
-                                    Ok(UserAction::SwitchToEditing)
-                                }
-                            },
-                            _ => Ok(UserAction::NoOp),
-                        }
-                    }
-                    InputMode::Editing => {
-                        match self.current_focus {
-                            InputId::Bpm => match key.code {
-                                KeyCode::Enter => {
-                                    self.editing_original_value = None;
-                                    self.state.input_mode = InputMode::Navigation;
-                                    Ok(UserAction::SwitchToNavigation)
-                                }
-                                KeyCode::Esc => {
-                                    if let Some(val) = self.editing_original_value.take() {
-                                        self.state.bpm = val;
-                                    }
-                                    self.state.input_mode = InputMode::Navigation;
-                                    Ok(UserAction::SwitchToNavigation)
-                                }
-                                KeyCode::Char(c) => {
-                                    if c.is_ascii_digit() && self.state.bpm.len() < 3 {
-                                        self.state.bpm.push(c);
-                                        Ok(UserAction::UpdateInput)
-                                    } else {
-                                        Ok(UserAction::NoOp)
-                                    }
-                                }
-                                KeyCode::Backspace => {
-                                    self.state.bpm.pop();
-                                    Ok(UserAction::UpdateInput)
-                                }
-                                _ => Ok(UserAction::NoOp),
-                            },
-                            InputId::Seed => match key.code {
-                                KeyCode::Enter => {
-                                    self.editing_original_value = None;
-                                    self.state.input_mode = InputMode::Navigation;
-                                    Ok(UserAction::SwitchToNavigation)
-                                }
-                                KeyCode::Esc => {
-                                    if let Some(val) = self.editing_original_value.take() {
-                                        self.state.seed = val;
-                                    }
-                                    self.state.input_mode = InputMode::Navigation;
-                                    Ok(UserAction::SwitchToNavigation)
-                                }
-                                KeyCode::Char(c) => {
-                                    if c.is_ascii_digit() {
-                                        self.state.seed.push(c);
-                                        Ok(UserAction::UpdateInput)
-                                    } else {
-                                        Ok(UserAction::NoOp)
-                                    }
-                                }
-                                KeyCode::Backspace => {
-                                    self.state.seed.pop();
-                                    Ok(UserAction::UpdateInput)
-                                }
-                                _ => Ok(UserAction::NoOp),
-                            },
-                            _ => Ok(UserAction::NoOp), // Should not happen if current_focus is Bpm, Seed, or QuickLoadString
-                        }
-                    }
-                    InputMode::ScalePopup | InputMode::StylePopup | InputMode::LengthPopup => {
-                        match key.code {
-                            KeyCode::Esc => {
-                                self.state.input_mode = InputMode::Navigation;
-                                Ok(UserAction::SwitchToNavigation)
-                            }
-                            KeyCode::Up => {
-                                let list_len = match self.state.input_mode {
-                                    InputMode::ScalePopup => self.state.scales.len(),
-                                    InputMode::StylePopup => self.state.styles.len(),
-                                    InputMode::LengthPopup => self.state.lengths.len(),
-                                    _ => 0, // Should not happen
-                                };
-                                if list_len > 0 {
-                                    let current_selection =
-                                        self.state.popup_list_state.selected().unwrap_or(0);
-                                    let next_selection = if current_selection == 0 {
-                                        list_len - 1
-                                    } else {
-                                        current_selection - 1
-                                    };
-                                    self.state.popup_list_state.select(Some(next_selection));
-                                }
-                                Ok(UserAction::CyclePopupOption)
-                            }
-                            KeyCode::Down => {
-                                let list_len = match self.state.input_mode {
-                                    InputMode::ScalePopup => self.state.scales.len(),
-                                    InputMode::StylePopup => self.state.styles.len(),
-                                    InputMode::LengthPopup => self.state.lengths.len(),
-                                    _ => 0, // Should not happen
-                                };
-                                if list_len > 0 {
-                                    let current_selection =
-                                        self.state.popup_list_state.selected().unwrap_or(0);
-                                    let next_selection = (current_selection + 1) % list_len;
-                                    self.state.popup_list_state.select(Some(next_selection));
-                                }
-                                Ok(UserAction::CyclePopupOption)
-                            }
-                            KeyCode::Enter => {
-                                if let Some(selected_index) = self.state.popup_list_state.selected()
-                                {
-                                    // Determine which popup is active by checking self.current_focus,
-                                    // as this was the field that triggered the popup.
-                                    match self.current_focus {
-                                        InputId::Scale => {
-                                            if selected_index < self.state.scales.len() {
-                                                self.state.scale =
-                                                    self.state.scales[selected_index].clone();
-                                            }
-                                        }
-                                        InputId::Style => {
-                                            if selected_index < self.state.styles.len() {
-                                                self.state.style =
-                                                    self.state.styles[selected_index].clone();
-                                            }
-                                        }
-                                        InputId::Length => {
-                                            if selected_index < self.state.lengths.len() {
-                                                self.state.length =
-                                                    self.state.lengths[selected_index].clone();
-                                            }
-                                        }
-                                        _ => {} // Should not happen, current_focus should be one of the above
-                                    }
-                                }
-                                self.state.input_mode = InputMode::Navigation;
-                                Ok(UserAction::SelectPopupItem)
-                            }
-                            _ => Ok(UserAction::NoOp),
-                        }
-                    }
-                    InputMode::SongLoaderEditing => {
-                        // Added new input mode handling
-                        match key.code {
-                            KeyCode::Enter => {
-                                self.editing_original_value = None;
-                                self.state.input_mode = InputMode::Navigation;
-                                // Potentially trim whitespace or validate before sending
-                                Ok(UserAction::AttemptLoadSong)
-                            }
-                            KeyCode::Esc => {
-                                if let Some(val) = self.editing_original_value.take() {
-                                    self.state.song_loader_input = val;
-                                }
-                                self.state.input_mode = InputMode::Navigation;
-                                Ok(UserAction::SwitchToNavigation)
-                            }
-                            KeyCode::Char(c) => {
-                                if c.is_alphanumeric() || c == '-' {
-                                    self.state.song_loader_input.push(c);
-                                    Ok(UserAction::UpdateInput)
-                                } else {
-                                    Ok(UserAction::NoOp)
-                                }
-                            }
-                            KeyCode::Backspace => {
-                                self.state.song_loader_input.pop();
-                                Ok(UserAction::UpdateInput)
-                            }
-                            _ => Ok(UserAction::NoOp),
-                        }
-                    }
-                    InputMode::SongIdErrorPopup => {
-                        // Handle input for the error popup
-                        match key.code {
-                            KeyCode::Enter | KeyCode::Esc => {
-                                self.state.input_mode = InputMode::SongLoaderEditing; // Go back to editing the ID
-                                self.state.song_id_error = None; // Clear the error
-                                Ok(UserAction::CloseSongIdErrorPopup)
-                            }
-                            _ => Ok(UserAction::NoOp), // Ignore other keys
-                        }
-                    }
-                }
-            } else {
-                Ok(UserAction::NoOp) // No key event if event::read() fails or is not a Key event
-            }
-        } else {
-            Ok(UserAction::NoOp) // No event polled within the timeout
-        }
+    /* toggle_debug - Shows or hides the debug overlay.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn toggle_debug(&mut self) {
+        self.show_debug = !self.show_debug;
     }
-}
 
-/* next_focus - Determines the next UI element to focus on based on navigation direction.
- *
- * Given the currently focused element (`current`) and a navigation `Direction`,
- * this function consults the `INPUT_GRAPH` to find the `InputId` of the
- * neighboring element in that direction. If no neighbor exists in the given
- * direction, focus remains on the current element.
- *
- * inputs:
- *     - current (InputId): The `InputId` of the currently focused UI element.
- *     - direction (Direction): The direction of navigation.
- *
- * outputs:
- *     - InputId : The `InputId` of the next element to focus, or the current one if no move is possible.
- */
-fn next_focus(current: InputId, direction: Direction) -> InputId {
-    let graph = get_input_graph();
-    graph
-        .get(&current)
-        .and_then(|node| node.neighbors.get(&direction).copied())
-        .unwrap_or(current) // If no neighbor, stay on the current input
+    /* set_stop_after_current - Shows or hides the "stopping after this track" indicator.
+     *
+     * inputs:
+     *     - &mut self
+     *     - enabled (bool): True if playback stops when the current song ends.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_stop_after_current(&mut self, enabled: bool) {
+        self.stop_after_current = enabled;
+    }
+
+    /* set_theme - Sets the colors the TUI draws with.
+     *
+     * inputs:
+     *     - &mut self
+     *     - theme (Theme): The theme to use.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /* update_worker_stats - Stores the worker pool counters shown in the debug overlay.
+     *
+     * inputs:
+     *     - &mut self
+     *     - stats (WorkerStats): The latest counters.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn update_worker_stats(&mut self, stats: WorkerStats) {
+        self.worker_stats = stats;
+    }
+
+    /* toggle_loop - Turns looping of the current song on or off.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - bool: True if looping is now on.
+     */
+    pub fn toggle_loop(&mut self) -> bool {
+        self.state.is_looping = !self.state.is_looping;
+        self.state.is_looping
+    }
+
+    /* toggle_drums - Turns the drum track on or off for the songs generated from now on.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - bool: True if drums are now on.
+     */
+    pub fn toggle_drums(&mut self) -> bool {
+        self.state.drums = !self.state.drums;
+        self.state.drums
+    }
+
+    /* cycle_crossfade - Steps the crossfade through off and 1 to `MAX_CROSSFADE_SECS` seconds.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - u32: The new crossfade in seconds (0 = off).
+     */
+    pub fn cycle_crossfade(&mut self) -> u32 {
+        self.state.crossfade_secs = (self.state.crossfade_secs + 1) % (MAX_CROSSFADE_SECS + 1);
+        self.state.crossfade_secs
+    }
+
+    /* cycle_modulation - Steps the key change setting through Auto, On and Off.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_modulation(&mut self) -> String {
+        self.state.modulation = next_modulation(&self.state.modulation).to_string();
+        self.state.modulation.clone()
+    }
+
+    /* cycle_harmony - Steps the harmony voice through off, choruses only and the whole song.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_harmony(&mut self) -> String {
+        self.state.harmony = next_harmony(&self.state.harmony).to_string();
+        self.state.harmony.clone()
+    }
+
+    /* cycle_counter_melody - Steps the counter-melody through auto, on and off.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_counter_melody(&mut self) -> String {
+        self.state.counter_melody = next_counter_melody(&self.state.counter_melody).to_string();
+        self.state.counter_melody.clone()
+    }
+
+    /* cycle_arp_chords - Steps the chip arpeggio chords through auto, on and off.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_arp_chords(&mut self) -> String {
+        self.state.arp_chords = next_arp_chords(&self.state.arp_chords).to_string();
+        self.state.arp_chords.clone()
+    }
+
+    /* cycle_comping - Switches the chords between a comping rhythm and sustained chords.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_comping(&mut self) -> String {
+        self.state.comping = next_comping(&self.state.comping).to_string();
+        self.state.comping.clone()
+    }
+
+    /* cycle_echo - Switches the melody echo between the style's and off.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_echo(&mut self) -> String {
+        self.state.echo = next_echo(&self.state.echo).to_string();
+        self.state.echo.clone()
+    }
+
+    /* cycle_lofi - Steps the lo-fi stage through auto, off, light and heavy.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_lofi(&mut self) -> String {
+        self.state.lofi = next_lofi(&self.state.lofi).to_string();
+        self.state.lofi.clone()
+    }
+
+    /* toggle_nes_mode - Turns NES mode on or off for the songs generated from now on.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - bool: True if NES mode is now on.
+     */
+    pub fn toggle_nes_mode(&mut self) -> bool {
+        self.state.nes_mode = !self.state.nes_mode;
+        self.state.nes_mode
+    }
+
+    /* cycle_pulse_width - Steps the duty of square waves through auto, 12.5%, 25%, 50% and PWM.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_pulse_width(&mut self) -> String {
+        self.state.pulse_width = next_pulse_width(&self.state.pulse_width).to_string();
+        self.state.pulse_width.clone()
+    }
+
+    /* cycle_brightness - Steps the low-pass tone through auto, dark, warm, bright and open.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_brightness(&mut self) -> String {
+        self.state.brightness = next_brightness(&self.state.brightness).to_string();
+        self.state.brightness.clone()
+    }
+
+    /* cycle_waveform - Steps a track's waveform through auto and every waveform.
+     *
+     * inputs:
+     *     - &mut self
+     *     - track (usize): The track, an index into `WAVEFORM_TRACKS`.
+     *
+     * outputs:
+     *     - String: The new setting.
+     */
+    pub fn cycle_waveform(&mut self, track: usize) -> String {
+        let setting = &mut self.state.waveforms[track];
+        *setting = next_waveform(setting).to_string();
+        setting.clone()
+    }
+
+    /* toggle_help - Toggles the visibility of the help menu.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn toggle_help(&mut self) {
+        self.state.show_help = !self.state.show_help;
+    }
+
+    /* is_paused - Checks if music playback is currently paused.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool : True if playback is paused, false otherwise.
+     */
+    pub fn is_paused(&self) -> bool {
+        !self.state.is_playing
+    }
+
+    /* clear_song_loader_input - Clears the text from the song loader input field.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn clear_song_loader_input(&mut self) {
+        self.state.song_loader_input.clear();
+    }
+
+    /* focus_on_play_pause - Sets the UI focus to the Play/Pause button.
+     *
+     * This also ensures the TUI is in Navigation mode.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn focus_on_play_pause(&mut self) {
+        self.current_focus = InputId::PlayPause;
+        self.state.input_mode = InputMode::Navigation; // Ensure navigation mode after focusing.
+    }
+
+    /* show_song_id_error - Displays an error message related to song ID loading.
+     *
+     * Sets the TUI to `SongIdErrorPopup` mode to show the message. A custom progression that
+     * doesn't parse, or a BPM or length out of range, is reported the same way while its field
+     * has the focus.
+     *
+     * inputs:
+     *     - &mut self
+     *     - error_message (String): The error message to display.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn show_song_id_error(&mut self, error_message: String) {
+        self.state.song_id_error = Some(error_message);
+        self.state.input_mode = InputMode::SongIdErrorPopup;
+    }
+
+    /* reset_current_song_progress - Resets playback progress for the current song (e.g., on rewind).
+     *
+     * This visually resets the elapsed time and progress bar to the beginning.
+     * The total song duration remains unchanged.
+     * Typically, playback is set to `true` after a rewind.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn reset_current_song_progress(&mut self) {
+        // Only reset the current playback position visually.
+        // The actual duration and definitive progress comes from music_service.
+        // The existing current_song_duration_secs remains, so "MM:SS / TotalDuration" looks consistent.
+        self.state.current_song_elapsed_secs = 0.0;
+        self.state.current_song_progress = 0.0;
+        self.progress.report(0, self.progress.total, Instant::now());
+        self.state.is_playing = true; // Ensure playing state is true after rewind.
+    }
+
+    /* show_rewind - Briefly shows where a Rewind press jumped to.
+     *
+     * inputs:
+     *     - &mut self
+     *     - target (RewindTarget): The section start or the song start.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn show_rewind(&mut self, target: RewindTarget) {
+        let text = match target {
+            RewindTarget::Section => "⏮ Section",
+            RewindTarget::Start => "⏮ Start",
+        };
+        self.show_notice(text);
+    }
+
+    /* show_notice - Briefly shows a short message in the Now Playing panel.
+     *
+     * inputs:
+     *     - &mut self
+     *     - text (&str): The message.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn show_notice(&mut self, text: &str) {
+        self.notice = Some((text.to_string(), Instant::now()));
+    }
+
+    /* set_library - Stores the saved songs shown in the library popup.
+     *
+     * inputs:
+     *     - &mut self
+     *     - entries (Vec<LibraryEntry>): The saved songs, oldest first.
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn set_library(&mut self, entries: Vec<LibraryEntry>) {
+        self.library = entries;
+        if self.state.input_mode == InputMode::LibraryPopup {
+            if self.library.is_empty() {
+                self.state.input_mode = InputMode::Navigation;
+            } else {
+                let selected = self.state.popup_list_state.selected().unwrap_or(0);
+                self.state.popup_list_state.select(Some(selected.min(self.library.len() - 1)));
+            }
+        }
+    }
+
+    /* start_length_edit - Starts typing a custom length into the Length field.
+     *
+     * inputs:
+     *     - &mut self
+     *     - first (Option<char>): The digit that started the edit, if one was typed.
+     *
+     * outputs:
+     *     - UserAction: `SwitchToEditing`.
+     */
+    fn start_length_edit(&mut self, first: Option<char>) -> UserAction {
+        self.current_focus = InputId::Length;
+        self.length_input = first.map(String::from).unwrap_or_default();
+        self.state.input_mode = InputMode::Editing;
+        UserAction::SwitchToEditing
+    }
+
+    /* show_share - Opens the share popup with a song's ID and its share code.
+     *
+     * inputs:
+     *     - &mut self
+     *     - song_id (String): The song ID.
+     *     - code (String): The same song as a share code (see `share::share_code`).
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn show_share(&mut self, song_id: String, code: String) {
+        self.share = Some((song_id, code));
+        self.state.input_mode = InputMode::SharePopup;
+    }
+
+    /* take_share_code - Returns the share code of the share popup, closing it for good.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<String>: The share code, if the popup was opened.
+     */
+    pub fn take_share_code(&mut self) -> Option<String> {
+        self.share.take().map(|(_, code)| code)
+    }
+
+    /* selected_library_song - Returns the song selected in the library popup.
+     *
+     * The popup lists the newest songs first.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - Option<String>: The selected song ID, if the popup has a selection.
+     */
+    pub fn selected_library_song(&self) -> Option<String> {
+        let selected = self.state.popup_list_state.selected()?;
+        let index = self.library.len().checked_sub(selected + 1)?;
+        Some(self.library[index].song_id.clone())
+    }
+
+    /* is_current_song_saved - Checks whether the current song is in the library.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - bool: True if the displayed song ID is saved.
+     */
+    fn is_current_song_saved(&self) -> bool {
+        self.state
+            .current_song_id_display
+            .as_ref()
+            .is_some_and(|song_id| self.library.iter().any(|entry| &entry.song_id == song_id))
+    }
+
+    /* reset_progress_for_new_song - Resets all progress information for a new song.
+     *
+     * Calls `update_progress(0,0)` to clear times and progress percentage.
+
+     * handled by the main application logic when a new song starts.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - None
+     */
+    pub fn reset_progress_for_new_song(&mut self) {
+        self.update_progress(0, 0, false);
+        // self.state.current_song_id_display = None; // Clearing ID is handled by main.rs/progress updates
+    }
+
+    /* gain_field_mut - Returns the mixer gain field belonging to the focused mixer control.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - Option<&mut String> : The focused gain field, or None if focus is not on the mixer.
+     */
+    fn gain_field_mut(&mut self) -> Option<&mut String> {
+        match self.current_focus {
+            InputId::MelodyGain => Some(&mut self.state.melody_gain),
+            InputId::ChordGain => Some(&mut self.state.chord_gain),
+            InputId::BassGain => Some(&mut self.state.bass_gain),
+            _ => None,
+        }
+    }
+
+    /* paste_text - Inserts pasted text into the field being edited.
+     *
+     * Characters the field wouldn't accept as keystrokes are dropped, and text beyond the
+     * field's length limit is cut off with a notice.
+     *
+     * inputs:
+     *     - &mut self
+     *     - text (&str): The pasted text.
+     *
+     * outputs:
+     *     - UserAction: UpdateInput if the field was edited, NoOp if nothing is being edited.
+     */
+    fn paste_text(&mut self, text: &str) -> UserAction {
+        let replace_selection = std::mem::take(&mut self.song_loader_selected);
+        let (field, accepts, max_len): (&mut String, fn(char) -> bool, usize) =
+            match (self.state.input_mode, self.current_focus) {
+                (InputMode::SongLoaderEditing, _) => {
+                    if replace_selection {
+                        self.state.song_loader_input.clear();
+                    }
+                    (
+                        &mut self.state.song_loader_input,
+                        |c| is_song_id_char(c),
+                        MAX_SONG_ID_LEN,
+                    )
+                }
+                (InputMode::Editing, InputId::Bpm) => {
+                    (&mut self.state.bpm, |c| c.is_ascii_digit(), 3)
+                }
+                (InputMode::Editing, InputId::Seed) => {
+                    (&mut self.state.seed, |c| c.is_ascii_digit(), MAX_SEED_DIGITS)
+                }
+                (InputMode::Editing, InputId::Swing) => {
+                    (&mut self.state.swing, |c| c.is_ascii_digit(), 3)
+                }
+                (InputMode::Editing, InputId::Progression) => {
+                    (&mut self.state.progression, |c| !c.is_control(), MAX_PROGRESSION_LEN)
+                }
+                (InputMode::Editing, InputId::Length) => {
+                    (&mut self.length_input, is_length_char, MAX_LENGTH_INPUT_LEN)
+                }
+                (InputMode::Editing, _) => match self.gain_field_mut() {
+                    Some(gain) => (gain, |c| c.is_ascii_digit(), 3),
+                    None => return UserAction::NoOp,
+                },
+                _ => return UserAction::NoOp,
+            };
+        let room = max_len.saturating_sub(field.chars().count());
+        let mut accepted = text.chars().filter(|c| accepts(*c));
+        field.extend(accepted.by_ref().take(room));
+        if accepted.next().is_some() {
+            if self.state.input_mode == InputMode::Editing && self.current_focus == InputId::Seed {
+                self.seed_rejected_at = Some(Instant::now());
+            }
+            self.show_notice(&format!("Paste cut to {} characters", max_len));
+        }
+        UserAction::UpdateInput
+    }
+
+    /* handle_input - Processes user input events from the terminal.
+     *
+     * This method polls for keyboard, paste and mouse events and turns them into the
+     * appropriate `UserAction`.
+     *
+     * inputs:
+     *     - &mut self
+     *
+     * outputs:
+     *     - std::io::Result<UserAction> : The determined `UserAction` or an I/O error.
+     */
+    pub fn handle_input(&mut self) -> std::io::Result<UserAction> {
+        if event::poll(std::time::Duration::from_millis(100))? {
+            let event = event::read()?;
+            if let Event::Paste(text) = &event {
+                if self.state.show_help {
+                    return Ok(UserAction::NoOp);
+                }
+                return Ok(self.paste_text(text));
+            }
+            match event {
+                Event::Key(key) => self.handle_key_event(key),
+                Event::Mouse(mouse) => self.handle_mouse_event(mouse),
+                _ => Ok(UserAction::NoOp), // Focus changes, resizes and the like
+            }
+        } else {
+            Ok(UserAction::NoOp) // No event polled within the timeout
+        }
+    }
+
+    /* handle_mouse_event - Turns a mouse click or scroll into a `UserAction`.
+     *
+     * Clicks act like Enter: on a control (after focusing it), or on a row of an open popup
+     * list (after selecting it). Clicking outside an open popup closes it like Esc, and
+     * clicking away from a field being edited finishes the edit like Enter, except Load Song,
+     * which is cancelled like Esc so a stray click never loads a song. The scroll wheel moves
+     * the selection of the popup under the pointer.
+     *
+     * inputs:
+     *     - &mut self
+     *     - mouse (MouseEvent): The mouse event.
+     *
+     * outputs:
+     *     - std::io::Result<UserAction> : The determined `UserAction` or an I/O error.
+     */
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) -> std::io::Result<UserAction> {
+        let (column, row) = (mouse.column, mouse.row);
+        let hit = |area: &Rect| {
+            column >= area.x && column < area.right() && row >= area.y && row < area.bottom()
+        };
+        let press = KeyEvent::new;
+        match mouse.kind {
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let scrollable = matches!(
+                    self.state.input_mode,
+                    InputMode::ScalePopup
+                        | InputMode::QualityPopup
+                        | InputMode::StylePopup
+                        | InputMode::LengthPopup
+                        | InputMode::TimeSignaturePopup
+                        | InputMode::LibraryPopup
+                        | InputMode::WaveformPopup
+                        | InputMode::WhatsNewPopup
+                );
+                if self.state.show_help || !scrollable || !self.popup_area.is_some_and(|area| hit(&area)) {
+                    return Ok(UserAction::NoOp);
+                }
+                let code = if mouse.kind == MouseEventKind::ScrollUp {
+                    KeyCode::Up
+                } else {
+                    KeyCode::Down
+                };
+                return self.handle_key_event(press(code, KeyModifiers::NONE));
+            }
+            // A release can go missing (e.g. outside the window), so a new click ends any scrub
+            MouseEventKind::Down(MouseButton::Left) => self.seeking = false,
+            MouseEventKind::Drag(MouseButton::Left) if self.seeking => {
+                return Ok(self.scrub_to(column));
+            }
+            MouseEventKind::Up(MouseButton::Left) | MouseEventKind::Moved if self.seeking => {
+                // The last dragged position, if the throttle held it back
+                self.seeking = false;
+                return Ok(self.unsent_seek.take().map_or(UserAction::NoOp, UserAction::Seek));
+            }
+            _ => return Ok(UserAction::NoOp),
+        }
+
+        if self.state.show_help {
+            return Ok(if self.popup_area.is_some_and(|area| hit(&area)) {
+                UserAction::NoOp
+            } else {
+                UserAction::ToggleHelp
+            });
+        }
+
+        if let Some(popup_area) = self.popup_area {
+            if !hit(&popup_area) {
+                return self.handle_key_event(press(KeyCode::Esc, KeyModifiers::NONE));
+            }
+            let Some(list_area) = self.popup_list_area.filter(|area| hit(area)) else {
+                return Ok(UserAction::NoOp);
+            };
+            let list_len = match self.state.input_mode {
+                InputMode::LibraryPopup => self.library.len(),
+                InputMode::WaveformPopup => WAVEFORM_TRACKS.len(),
+                _ => self.state.visible_popup_options().len(),
+            };
+            let index = self.state.popup_list_state.offset() + (row - list_area.y) as usize;
+            if index >= list_len {
+                return Ok(UserAction::NoOp);
+            }
+            self.state.popup_list_state.select(Some(index));
+            return self.handle_key_event(press(KeyCode::Enter, KeyModifiers::NONE));
+        }
+
+        // Clicking the progress bar seeks, and holding the button down scrubs; an endless song
+        // has no length to seek within
+        let song_loaded = self.progress.total > 0 && !self.endless_song;
+        if self.state.input_mode == InputMode::Navigation
+            && song_loaded
+            && self.gauge_area.is_some_and(|area| hit(&area))
+        {
+            self.seeking = true;
+            self.seek_awaiting_progress = false;
+            return Ok(self.scrub_to(column));
+        }
+
+        let target = self
+            .widget_areas
+            .iter()
+            .find(|(_, area)| hit(area))
+            .map(|(input_id, _)| *input_id);
+        match self.state.input_mode {
+            InputMode::Navigation => match target {
+                Some(input_id) => {
+                    self.current_focus = input_id;
+                    self.handle_key_event(press(KeyCode::Enter, KeyModifiers::NONE))
+                }
+                None => Ok(UserAction::NoOp),
+            },
+            InputMode::Editing | InputMode::SongLoaderEditing if target != Some(self.current_focus) => {
+                let code = if self.state.input_mode == InputMode::SongLoaderEditing {
+                    KeyCode::Esc
+                } else {
+                    KeyCode::Enter
+                };
+                self.handle_key_event(press(code, KeyModifiers::NONE))
+            }
+            _ => Ok(UserAction::NoOp),
+        }
+    }
+
+    /* open_option_popup - Opens the Scale, Key, Style, Length or Time popup on the current value.
+     *
+     * inputs:
+     *     - &mut self
+     *     - mode (InputMode): `ScalePopup`, `QualityPopup`, `StylePopup`, `LengthPopup` or
+     *       `TimeSignaturePopup`.
+     *
+     * outputs:
+     *     - UserAction : `OpenPopup`.
+     */
+    fn open_option_popup(&mut self, mode: InputMode) -> UserAction {
+        let (options, value) = match mode {
+            InputMode::ScalePopup => (&self.state.scales, &self.state.scale),
+            InputMode::QualityPopup => (&self.state.qualities, &self.state.quality),
+            InputMode::StylePopup => (&self.state.styles, &self.state.style),
+            InputMode::TimeSignaturePopup => (&self.state.time_signatures, &self.state.time_signature),
+            _ => (&self.state.lengths, &format_song_length(self.state.length_secs)),
+        };
+        let index = option_index(options, value);
+        self.state.input_mode = mode;
+        self.state.popup_filter.clear();
+        self.state.popup_list_state.select(Some(index));
+        UserAction::OpenPopup
+    }
+
+    /* set_popup_filter - Changes the filter of the open option popup.
+     *
+     * The first match is highlighted; with the filter cleared, the current value is
+     * highlighted again, as when the popup opened.
+     *
+     * inputs:
+     *     - &mut self
+     *     - filter (String): The new filter.
+     *
+     * outputs:
+     *     - UserAction : `UpdateInput`.
+     */
+    fn set_popup_filter(&mut self, filter: String) -> UserAction {
+        self.state.popup_filter = filter;
+        let selected = if self.state.popup_filter.is_empty() {
+            let value = match self.state.input_mode {
+                InputMode::ScalePopup => self.state.scale.clone(),
+                InputMode::QualityPopup => self.state.quality.clone(),
+                InputMode::StylePopup => self.state.style.clone(),
+                InputMode::TimeSignaturePopup => self.state.time_signature.clone(),
+                _ => format_song_length(self.state.length_secs),
+            };
+            Some(option_index(self.state.popup_options(), &value))
+        } else if self.state.visible_popup_options().is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        *self.state.popup_list_state.offset_mut() = 0;
+        self.state.popup_list_state.select(selected);
+        UserAction::UpdateInput
+    }
+
+    /* move_popup_selection - Moves the selection of the open popup's list.
+     *
+     * The list scrolls to keep the selection visible when it's next drawn. Pages are as
+     * tall as the list was last drawn.
+     *
+     * inputs:
+     *     - &mut self
+     *     - code (KeyCode): Up, Down, PageUp, PageDown, Home or End.
+     *     - list_len (usize): The number of rows in the list.
+     *
+     * outputs:
+     *     - None
+     */
+    fn move_popup_selection(&mut self, code: KeyCode, list_len: usize) {
+        if list_len == 0 {
+            return;
+        }
+        let page = self.popup_list_area.map_or(1, |area| area.height as usize);
+        let selected = self.state.popup_list_state.selected().unwrap_or(0);
+        self.state
+            .popup_list_state
+            .select(Some(popup_selection_after(code, selected, list_len, page)));
+    }
+
+    /* scrub_to - Moves the progress bar to a column of the gauge and seeks there.
+     *
+     * The bar and elapsed time show the new position right away. At most one seek is sent
+     * per progress update; positions in between are kept in `unsent_seek` and sent with the
+     * next drag event or the button release.
+     *
+     * inputs:
+     *     - &mut self
+     *     - column (u16): The pointer's column; columns outside the gauge clamp to its ends.
+     *
+     * outputs:
+     *     - UserAction : `Seek` with the fraction of the song, or `NoOp` while throttled.
+     */
+    fn scrub_to(&mut self, column: u16) -> UserAction {
+        let (Some(area), total) = (self.gauge_area, self.progress.total) else {
+            return UserAction::NoOp;
+        };
+        if total == 0 || area.width == 0 {
+            return UserAction::NoOp;
+        }
+        let offset = column.saturating_sub(area.x).min(area.width - 1);
+        let fraction = ((offset as f32 + 0.5) / area.width as f32).clamp(0.0, 1.0);
+
+        // Predicted position, until the music service confirms it
+        let position = (fraction as f64 * total as f64) as u64;
+        self.progress.report(position, total, Instant::now());
+        self.state.current_song_progress = fraction;
+        self.state.current_song_elapsed_secs = position as f32 / TUI_SAMPLE_RATE;
+
+        if self.seek_awaiting_progress {
+            self.unsent_seek = Some(fraction);
+            return UserAction::NoOp;
+        }
+        self.seek_awaiting_progress = true;
+        self.unsent_seek = None;
+        UserAction::Seek(fraction)
+    }
+
+    /* handle_key_event - Turns a key press into a `UserAction`.
+     *
+     * Based on the current `InputMode` (e.g., Navigation, Editing, Popup) and the key,
+     * it handles global shortcuts (like Quit, ToggleHelp), navigation between UI elements,
+     * text input into fields, interaction with popups, and actions related to music
+     * control and generation.
+     *
+     * inputs:
+     *     - &mut self
+     *     - key (KeyEvent): The key event.
+     *
+     * outputs:
+     *     - std::io::Result<UserAction> : The determined `UserAction` or an I/O error.
+     */
+    fn handle_key_event(&mut self, key: KeyEvent) -> std::io::Result<UserAction> {
+        if self.state.show_help {
+            // When help is shown, only '?' or 'q' on press do something.
+            // All other events (other keys, or non-press events) are NoOp.
+            if key.kind == event::KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Char('?') => return Ok(UserAction::ToggleHelp), // Action to close help
+                    KeyCode::Char('q') => return Ok(UserAction::Quit),
+                    _ => {} // Other pressed keys will fall through to the NoOp below
+                }
+            }
+            return Ok(UserAction::NoOp); // Catch-all for any event if help is shown and not handled above
+        }
+
+        // ---- Help is NOT shown at this point ----
+        // Global keybindings (available when help is NOT shown); in an option popup,
+        // letters and digits go to its filter instead, and in a text field every character
+        // is typed (share codes contain q, p, r and f)
+        let filtering = matches!(
+            self.state.input_mode,
+            InputMode::ScalePopup
+                | InputMode::QualityPopup
+                | InputMode::StylePopup
+                | InputMode::LengthPopup
+                | InputMode::TimeSignaturePopup
+        ) && matches!(key.code, KeyCode::Char(c) if is_popup_filter_char(c));
+        let typing = matches!(
+            self.state.input_mode,
+            InputMode::Editing | InputMode::SongLoaderEditing
+        ) && matches!(key.code, KeyCode::Char(_));
+        if key.kind == event::KeyEventKind::Press && !filtering && !typing {
+            match key.code {
+                KeyCode::Char('?') => return Ok(UserAction::ToggleHelp), // Action to open help
+                KeyCode::Char('q') => return Ok(UserAction::Quit),
+                KeyCode::Char('p') => return Ok(UserAction::TogglePlayback),
+                KeyCode::Char('r') => return Ok(UserAction::RewindSong),
+                KeyCode::Char('f') => return Ok(UserAction::FastForwardSong),
+                _ => {} 
+            }
+        }
+
+        if key.kind != event::KeyEventKind::Press {
+            return Ok(UserAction::NoOp);
+        }
+
+        if key.code == KeyCode::Char('v')
+            && key.modifiers.contains(KeyModifiers::CONTROL)
+            && matches!(
+                self.state.input_mode,
+                InputMode::Editing | InputMode::SongLoaderEditing
+            )
+        {
+            return Ok(match clipboard::paste() {
+                Ok(text) => self.paste_text(&text),
+                Err(_) => {
+                    self.show_notice("Clipboard is unavailable");
+                    UserAction::NoOp
+                }
+            });
+        }
+
+        match self.state.input_mode {
+            InputMode::Navigation => {
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        self.current_focus = next_focus(self.current_focus, Direction::Up, self.compact);
+                        Ok(UserAction::Navigate)
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        self.current_focus =
+                            next_focus(self.current_focus, Direction::Down, self.compact);
+                        Ok(UserAction::Navigate)
+                    }
+                    KeyCode::Left | KeyCode::Char('h') => {
+                        self.current_focus =
+                            next_focus(self.current_focus, Direction::Left, self.compact);
+                        Ok(UserAction::Navigate)
+                    }
+                    KeyCode::Right | KeyCode::Char('l') => {
+                        self.current_focus =
+                            next_focus(self.current_focus, Direction::Right, self.compact);
+                        Ok(UserAction::Navigate)
+                    }
+                    KeyCode::Char('+') | KeyCode::Char('=') => Ok(UserAction::VolumeUp),
+                    KeyCode::Char('-') => Ok(UserAction::VolumeDown),
+                    KeyCode::Char('m') => Ok(UserAction::ToggleMute),
+                    KeyCode::Char('o') => Ok(UserAction::ToggleScope),
+                    KeyCode::Char('s') => Ok(UserAction::ToggleSpectrum),
+                    KeyCode::Char('L') => Ok(UserAction::ToggleLoop),
+                    KeyCode::Char('d') => Ok(UserAction::ToggleDrums),
+                    KeyCode::Char('.') => Ok(UserAction::ToggleStopAfterCurrent),
+                    KeyCode::Char('x') => Ok(UserAction::CycleCrossfade),
+                    KeyCode::Char('K') => Ok(UserAction::CycleModulation),
+                    KeyCode::Char('H') => Ok(UserAction::CycleHarmony),
+                    KeyCode::Char('C') => Ok(UserAction::CycleCounterMelody),
+                    KeyCode::Char('A') => Ok(UserAction::CycleArpChords),
+                    KeyCode::Char('R') => Ok(UserAction::CycleComping),
+                    KeyCode::Char('P') => Ok(UserAction::CyclePulseWidth),
+                    KeyCode::Char('T') => Ok(UserAction::CycleBrightness),
+                    KeyCode::Char('E') => Ok(UserAction::CycleEcho),
+                    KeyCode::Char('B') => Ok(UserAction::CycleLoFi),
+                    KeyCode::Char('N') => Ok(UserAction::ToggleNesMode),
+                    KeyCode::Char('M') => Ok(UserAction::VarySong(VariedTrack::Melody)),
+                    KeyCode::Char('V') => Ok(UserAction::VarySong(VariedTrack::Bass)),
+                    KeyCode::Char('W') => {
+                        self.state.input_mode = InputMode::WaveformPopup;
+                        self.state.popup_list_state.select(Some(0));
+                        Ok(UserAction::OpenPopup)
+                    }
+                    KeyCode::Char('b') => Ok(UserAction::PreviousSong),
+                    KeyCode::Char('S') => Ok(UserAction::ToggleSaveSong),
+                    KeyCode::Char('y') => Ok(UserAction::CopySongId),
+                    KeyCode::Char('Y') => Ok(UserAction::ShareSong),
+                    // Typing a number on Length enters a custom length; Enter still opens the presets
+                    KeyCode::Char(c) if c.is_ascii_digit() && self.current_focus == InputId::Length => {
+                        Ok(self.start_length_edit(Some(c)))
+                    }
+                    KeyCode::Char('v') => {
+                        if self.library.is_empty() {
+                            self.show_notice("Library is empty (S saves a song)");
+                            return Ok(UserAction::NoOp);
+                        }
+                        self.state.input_mode = InputMode::LibraryPopup;
+                        self.state.popup_list_state.select(Some(0));
+                        Ok(UserAction::OpenPopup)
+                    }
+                    KeyCode::F(12) => Ok(UserAction::ToggleDebug),
+                    KeyCode::Char('/') => {
+                        // Jump straight to the loader with its content selected for replacement
+                        self.current_focus = InputId::SongLoader;
+                        self.editing_original_value =
+                            Some(self.state.song_loader_input.clone());
+                        self.song_loader_selected = !self.state.song_loader_input.is_empty();
+                        self.state.input_mode = InputMode::SongLoaderEditing;
+                        Ok(UserAction::SwitchToEditing)
+                    }
+                    KeyCode::Enter => match self.current_focus {
+                        InputId::Previous => Ok(UserAction::PreviousSong),
+                        InputId::Rewind => Ok(UserAction::RewindSong),
+                        InputId::PlayPause => {
+                            Ok(UserAction::TogglePlayback)
+                        }
+                        InputId::Skip => Ok(UserAction::FastForwardSong),
+                        InputId::Scale => Ok(self.open_option_popup(InputMode::ScalePopup)),
+                        InputId::Quality => Ok(self.open_option_popup(InputMode::QualityPopup)),
+                        InputId::Style => Ok(self.open_option_popup(InputMode::StylePopup)),
+                        InputId::Length => Ok(self.open_option_popup(InputMode::LengthPopup)),
+                        InputId::TimeSignature => Ok(self.open_option_popup(InputMode::TimeSignaturePopup)),
+                        InputId::Bpm => {
+                            self.editing_original_value = Some(self.state.bpm.clone());
+                            self.state.input_mode = InputMode::Editing;
+                            Ok(UserAction::SwitchToEditing)
+                        }
+                        InputId::Seed => {
+                            self.editing_original_value = Some(self.state.seed.clone());
+                            self.state.input_mode = InputMode::Editing;
+                            Ok(UserAction::SwitchToEditing)
+                        }
+                        InputId::Swing => {
+                            self.editing_original_value = Some(self.state.swing.clone());
+                            self.state.input_mode = InputMode::Editing;
+                            Ok(UserAction::SwitchToEditing)
+                        }
+                        InputId::Progression => {
+                            self.editing_original_value = Some(self.state.progression.clone());
+                            self.state.input_mode = InputMode::Editing;
+                            Ok(UserAction::SwitchToEditing)
+                        }
+                        InputId::MelodyGain | InputId::ChordGain | InputId::BassGain => {
+                            let value = self.gain_field_mut().cloned().unwrap_or_default();
+                            self.editing_original_value = Some(value);
+                            self.state.input_mode = InputMode::Editing;
+                            Ok(UserAction::SwitchToEditing)
+                        }
+                        InputId::Generate => Ok(UserAction::GenerateMusic),
+                        InputId::GenerateRandom => Ok(UserAction::GenerateRandomMusic),
+                        InputId::SongLoader => {
+                            // Added SongLoader Enter in Navigation mode
+                            self.editing_original_value =
+                                Some(self.state.song_loader_input.clone());
+                            self.state.input_mode = InputMode::SongLoaderEditing;
+                            Ok(UserAction::SwitchToEditing)
+                        }
+                    },
+                    _ => Ok(UserAction::NoOp),
+                }
+            }
+            InputMode::Editing => {
+                match self.current_focus {
+                    InputId::Bpm => match key.code {
+                        KeyCode::Enter => {
+                            // "080" is kept as "80"; a BPM out of range stays in the field behind
+                            // the error popup
+                            match parse_bpm(&self.state.bpm) {
+                                Ok(tempo) => {
+                                    self.state.bpm = tempo.map(|t| t.to_string()).unwrap_or_default();
+                                    self.editing_original_value = None;
+                                    self.state.input_mode = InputMode::Navigation;
+                                    Ok(UserAction::SwitchToNavigation)
+                                }
+                                Err(error_message) => {
+                                    self.show_song_id_error(error_message);
+                                    Ok(UserAction::NoOp)
+                                }
+                            }
+                        }
+                        KeyCode::Esc => {
+                            if let Some(val) = self.editing_original_value.take() {
+                                self.state.bpm = val;
+                            }
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Char(c) => {
+                            if c.is_ascii_digit() && self.state.bpm.len() < 3 {
+                                self.state.bpm.push(c);
+                                Ok(UserAction::UpdateInput)
+                            } else {
+                                Ok(UserAction::NoOp)
+                            }
+                        }
+                        KeyCode::Backspace => {
+                            self.state.bpm.pop();
+                            Ok(UserAction::UpdateInput)
+                        }
+                        _ => Ok(UserAction::NoOp),
+                    },
+                    InputId::Swing => match key.code {
+                        KeyCode::Enter => {
+                            // Clamp to 0-100; left empty, the style's default swing is used
+                            if let Ok(percent) = self.state.swing.parse::<u32>() {
+                                self.state.swing = percent.min(MAX_SWING_PERCENT).to_string();
+                            }
+                            self.editing_original_value = None;
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Esc => {
+                            if let Some(val) = self.editing_original_value.take() {
+                                self.state.swing = val;
+                            }
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Char(c) => {
+                            if c.is_ascii_digit() && self.state.swing.len() < 3 {
+                                self.state.swing.push(c);
+                                Ok(UserAction::UpdateInput)
+                            } else {
+                                Ok(UserAction::NoOp)
+                            }
+                        }
+                        KeyCode::Backspace => {
+                            self.state.swing.pop();
+                            Ok(UserAction::UpdateInput)
+                        }
+                        _ => Ok(UserAction::NoOp),
+                    },
+                    InputId::Length => match key.code {
+                        KeyCode::Enter => {
+                            // Kept as seconds; a length that can't be read stays in the field behind
+                            // the error popup
+                            match parse_song_length(&self.length_input) {
+                                Ok(secs) => {
+                                    self.state.length_secs = secs;
+                                    self.length_input.clear();
+                                    self.state.input_mode = InputMode::Navigation;
+                                    Ok(UserAction::SwitchToNavigation)
+                                }
+                                Err(error_message) => {
+                                    self.show_song_id_error(error_message);
+                                    Ok(UserAction::NoOp)
+                                }
+                            }
+                        }
+                        KeyCode::Esc => {
+                            self.length_input.clear();
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Char(c) => {
+                            if is_length_char(c) && self.length_input.len() < MAX_LENGTH_INPUT_LEN {
+                                self.length_input.push(c);
+                                Ok(UserAction::UpdateInput)
+                            } else {
+                                Ok(UserAction::NoOp)
+                            }
+                        }
+                        KeyCode::Backspace => {
+                            self.length_input.pop();
+                            Ok(UserAction::UpdateInput)
+                        }
+                        _ => Ok(UserAction::NoOp),
+                    },
+                    InputId::Progression => match key.code {
+                        KeyCode::Enter => {
+                            // Respelled the way song IDs carry it; a progression that doesn't parse
+                            // stays in the field behind the error popup
+                            match progs::parse_roman_progression(&self.state.progression) {
+                                Ok(chords) => {
+                                    self.state.progression = progs::roman_progression_text(&chords);
+                                    self.editing_original_value = None;
+                                    self.state.input_mode = InputMode::Navigation;
+                                    Ok(UserAction::SwitchToNavigation)
+                                }
+                                Err(error_message) => {
+                                    self.show_song_id_error(error_message);
+                                    Ok(UserAction::NoOp)
+                                }
+                            }
+                        }
+                        KeyCode::Esc => {
+                            if let Some(val) = self.editing_original_value.take() {
+                                self.state.progression = val;
+                            }
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Char(c) => {
+                            if !c.is_control() && self.state.progression.chars().count() < MAX_PROGRESSION_LEN {
+                                self.state.progression.push(c);
+                                Ok(UserAction::UpdateInput)
+                            } else {
+                                Ok(UserAction::NoOp)
+                            }
+                        }
+                        KeyCode::Backspace => {
+                            self.state.progression.pop();
+                            Ok(UserAction::UpdateInput)
+                        }
+                        _ => Ok(UserAction::NoOp),
+                    },
+                    InputId::Seed => match key.code {
+                        KeyCode::Enter => {
+                            // 20 digits can still be more than u64::MAX
+                            if !self.state.seed.is_empty() && self.state.seed.parse::<u64>().is_err() {
+                                self.seed_rejected_at = Some(Instant::now());
+                                self.show_notice(&format!("Seed must be at most {}", u64::MAX));
+                                return Ok(UserAction::NoOp);
+                            }
+                            self.editing_original_value = None;
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Esc => {
+                            if let Some(val) = self.editing_original_value.take() {
+                                self.state.seed = val;
+                            }
+                            self.state.input_mode = InputMode::Navigation;
+                            Ok(UserAction::SwitchToNavigation)
+                        }
+                        KeyCode::Char(c) => {
+                            if !c.is_ascii_digit() {
+                                Ok(UserAction::NoOp)
+                            } else if self.state.seed.len() >= MAX_SEED_DIGITS {
+                                self.seed_rejected_at = Some(Instant::now());
+                                Ok(UserAction::NoOp)
+                            } else {
+                                self.state.seed.push(c);
+                                Ok(UserAction::UpdateInput)
+                            }
+                        }
+                        KeyCode::Backspace => {
+                            self.state.seed.pop();
+                            Ok(UserAction::UpdateInput)
+                        }
+                        _ => Ok(UserAction::NoOp),
+                    },
+                    InputId::MelodyGain | InputId::ChordGain | InputId::BassGain => {
+                        match key.code {
+                            KeyCode::Enter => {
+                                // Clamp to 0-100 and fall back to the default mix when left empty
+                                if let Some(gain) = self.gain_field_mut() {
+                                    *gain = match gain.parse::<u32>() {
+                                        Ok(percent) => percent.min(100).to_string(),
+                                        Err(_) => DEFAULT_TRACK_GAIN_PERCENT.to_string(),
+                                    };
+                                }
+                                self.editing_original_value = None;
+                                self.state.input_mode = InputMode::Navigation;
+                                Ok(UserAction::SwitchToNavigation)
+                            }
+                            KeyCode::Esc => {
+                                if let Some(val) = self.editing_original_value.take() {
+                                    if let Some(gain) = self.gain_field_mut() {
+                                        *gain = val;
+                                    }
+                                }
+                                self.state.input_mode = InputMode::Navigation;
+                                Ok(UserAction::SwitchToNavigation)
+                            }
+                            KeyCode::Char(c) => match self.gain_field_mut() {
+                                Some(gain) if c.is_ascii_digit() && gain.len() < 3 => {
+                                    gain.push(c);
+                                    Ok(UserAction::UpdateInput)
+                                }
+                                _ => Ok(UserAction::NoOp),
+                            },
+                            KeyCode::Backspace => {
+                                if let Some(gain) = self.gain_field_mut() {
+                                    gain.pop();
+                                }
+                                Ok(UserAction::UpdateInput)
+                            }
+                            _ => Ok(UserAction::NoOp),
+                        }
+                    }
+                    _ => Ok(UserAction::NoOp), // Should not happen if current_focus is an editable text field
+                }
+            }
+            InputMode::ScalePopup
+            | InputMode::QualityPopup
+            | InputMode::StylePopup
+            | InputMode::LengthPopup
+            | InputMode::TimeSignaturePopup => {
+                match key.code {
+                    // Esc clears the filter first, then closes the popup
+                    KeyCode::Esc if !self.state.popup_filter.is_empty() => {
+                        Ok(self.set_popup_filter(String::new()))
+                    }
+                    KeyCode::Esc => {
+                        self.state.input_mode = InputMode::Navigation;
+                        Ok(UserAction::SwitchToNavigation)
+                    }
+                    KeyCode::Char(c) if is_popup_filter_char(c) => {
+                        let filter = format!("{}{}", self.state.popup_filter, c);
+                        Ok(self.set_popup_filter(filter))
+                    }
+                    KeyCode::Backspace => {
+                        let mut filter = self.state.popup_filter.clone();
+                        filter.pop();
+                        Ok(self.set_popup_filter(filter))
+                    }
+                    KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::PageUp
+                    | KeyCode::PageDown
+                    | KeyCode::Home
+                    | KeyCode::End => {
+                        let list_len = self.state.visible_popup_options().len();
+                        self.move_popup_selection(key.code, list_len);
+                        Ok(UserAction::CyclePopupOption)
+                    }
+                    KeyCode::Enter => {
+                        // The selection indexes the filtered options; map it back to the full list
+                        let visible = self.state.visible_popup_options();
+                        let Some(&selected_index) = self
+                            .state
+                            .popup_list_state
+                            .selected()
+                            .and_then(|index| visible.get(index))
+                        else {
+                            return Ok(UserAction::NoOp); // Nothing matches the filter
+                        };
+                        let choice = self.state.popup_options()[selected_index].clone();
+                        let popup = self.state.input_mode;
+                        self.state.popup_filter.clear();
+                        self.state.input_mode = InputMode::Navigation;
+                        match popup {
+                            InputMode::ScalePopup => self.state.scale = choice,
+                            InputMode::QualityPopup => self.state.quality = choice,
+                            InputMode::StylePopup => self.state.style = choice,
+                            InputMode::TimeSignaturePopup => self.state.time_signature = choice,
+                            _ if choice == CUSTOM_LENGTH_OPTION => return Ok(self.start_length_edit(None)),
+                            _ => {
+                                self.state.length_secs = parse_song_length(&choice).unwrap_or(DEFAULT_SONG_SECS);
+                            }
+                        }
+                        Ok(UserAction::SelectPopupItem)
+                    }
+                    _ => Ok(UserAction::NoOp),
+                }
+            }
+            InputMode::SongLoaderEditing => {
+                // Added new input mode handling
+                // Any key ends the "selected" state; an edit replaces the selected text first
+                let was_selected = std::mem::take(&mut self.song_loader_selected);
+                match key.code {
+                    KeyCode::Enter => {
+                        self.editing_original_value = None;
+                        self.state.input_mode = InputMode::Navigation;
+                        // Whitespace and case are forgiven when the ID is parsed
+                        Ok(UserAction::AttemptLoadSong)
+                    }
+                    KeyCode::Esc => {
+                        if let Some(val) = self.editing_original_value.take() {
+                            self.state.song_loader_input = val;
+                        }
+                        self.state.input_mode = InputMode::Navigation;
+                        Ok(UserAction::SwitchToNavigation)
+                    }
+                    KeyCode::Char(c) => {
+                        if is_song_id_char(c) {
+                            if was_selected {
+                                self.state.song_loader_input.clear();
+                            }
+                            self.state.song_loader_input.push(c);
+                            Ok(UserAction::UpdateInput)
+                        } else {
+                            self.song_loader_selected = was_selected; // Ignored keys keep the selection
+                            Ok(UserAction::NoOp)
+                        }
+                    }
+                    KeyCode::Backspace => {
+                        if was_selected {
+                            self.state.song_loader_input.clear();
+                        } else {
+                            self.state.song_loader_input.pop();
+                        }
+                        Ok(UserAction::UpdateInput)
+                    }
+                    _ => Ok(UserAction::NoOp),
+                }
+            }
+            InputMode::LibraryPopup => match key.code {
+                KeyCode::Esc | KeyCode::Char('v') => {
+                    self.state.input_mode = InputMode::Navigation;
+                    Ok(UserAction::SwitchToNavigation)
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.move_popup_selection(KeyCode::Up, self.library.len());
+                    Ok(UserAction::CyclePopupOption)
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.move_popup_selection(KeyCode::Down, self.library.len());
+                    Ok(UserAction::CyclePopupOption)
+                }
+                KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {
+                    self.move_popup_selection(key.code, self.library.len());
+                    Ok(UserAction::CyclePopupOption)
+                }
+                KeyCode::Enter => {
+                    self.state.input_mode = InputMode::Navigation;
+                    match self.selected_library_song() {
+                        // Loaded exactly as if the ID had been typed into Load Song
+                        Some(song_id) => {
+                            self.state.song_loader_input = song_id;
+                            Ok(UserAction::AttemptLoadSong)
+                        }
+                        None => Ok(UserAction::SwitchToNavigation),
+                    }
+                }
+                KeyCode::Char('d') | KeyCode::Delete => Ok(UserAction::RemoveLibraryEntry),
+                _ => Ok(UserAction::NoOp),
+            },
+            InputMode::WaveformPopup => match key.code {
+                KeyCode::Esc | KeyCode::Char('W') => {
+                    self.state.input_mode = InputMode::Navigation;
+                    Ok(UserAction::SwitchToNavigation)
+                }
+                KeyCode::Up | KeyCode::Char('k') | KeyCode::Down | KeyCode::Char('j') => {
+                    let code = if matches!(key.code, KeyCode::Up | KeyCode::Char('k')) {
+                        KeyCode::Up
+                    } else {
+                        KeyCode::Down
+                    };
+                    self.move_popup_selection(code, WAVEFORM_TRACKS.len());
+                    Ok(UserAction::CyclePopupOption)
+                }
+                // The popup stays open, so several tracks can be changed in turn
+                KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Right | KeyCode::Char('l') => {
+                    let track = self.state.popup_list_state.selected().unwrap_or(0);
+                    Ok(UserAction::CycleWaveform(track.min(WAVEFORM_TRACKS.len() - 1)))
+                }
+                _ => Ok(UserAction::NoOp),
+            },
+            InputMode::SharePopup => match key.code {
+                KeyCode::Enter => {
+                    self.state.input_mode = InputMode::Navigation;
+                    Ok(UserAction::CopyShareCode)
+                }
+                KeyCode::Esc => {
+                    self.state.input_mode = InputMode::Navigation;
+                    self.share = None;
+                    Ok(UserAction::SwitchToNavigation)
+                }
+                _ => Ok(UserAction::NoOp),
+            },
+            InputMode::WhatsNewPopup => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.state.whats_new_scroll = self.state.whats_new_scroll.saturating_sub(1);
+                    Ok(UserAction::ScrollPopup)
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    // Upper bound is clamped in draw(), which knows the wrapped line count
+                    self.state.whats_new_scroll = self.state.whats_new_scroll.saturating_add(1);
+                    Ok(UserAction::ScrollPopup)
+                }
+                KeyCode::Enter | KeyCode::Esc => {
+                    self.state.input_mode = InputMode::Navigation;
+                    self.state.whats_new_lines.clear();
+                    self.state.whats_new_scroll = 0;
+                    Ok(UserAction::DismissWhatsNew)
+                }
+                _ => Ok(UserAction::NoOp),
+            },
+            InputMode::SongIdErrorPopup => {
+                // Handle input for the error popup
+                match key.code {
+                    KeyCode::Enter | KeyCode::Esc => {
+                        // Go back to editing the ID, or the progression, BPM or length that was turned down
+                        self.state.input_mode = match self.current_focus {
+                            InputId::Progression | InputId::Bpm | InputId::Length => InputMode::Editing,
+                            _ => InputMode::SongLoaderEditing,
+                        };
+                        self.state.song_id_error = None; // Clear the error
+                        Ok(UserAction::CloseSongIdErrorPopup)
+                    }
+                    _ => Ok(UserAction::NoOp), // Ignore other keys
+                }
+            }
+        }
+    }
+}
+
+/* is_song_id_char - Checks whether a character can be part of a song ID.
+ *
+ * inputs:
+ *     - c (char): The typed or pasted character.
+ *
+ * outputs:
+ *     - bool : True for letters, digits, the '-' between parts, the '#' of a sharp key, the
+ *       separator of the chords of a custom progression, the ':' after the ID's version, the
+ *       '.' of a fractional length, and spaces (trimmed when the ID loads).
+ */
+fn is_song_id_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || c == '-'
+        || c == '#'
+        || c == ' '
+        || c == PROGRESSION_ID_SEPARATOR
+        || c == SONG_ID_VERSION_SEPARATOR
+        || c == '.'
+}
+
+/* is_length_char - Checks whether a character can be typed into a custom length.
+ *
+ * inputs:
+ *     - c (char): The typed or pasted character.
+ *
+ * outputs:
+ *     - bool : True for digits, the ':' and '.' of "2:30" and "2.5", and the letters and space
+ *       of "90s", "90 sec" and "5 min".
+ */
+fn is_length_char(c: char) -> bool {
+    c.is_ascii_digit() || matches!(c, ':' | '.' | ' ' | 's' | 'e' | 'c' | 'm' | 'i' | 'n')
+}
+
+/* is_popup_filter_char - Checks whether a typed character goes to an option popup's filter.
+ *
+ * inputs:
+ *     - c (char): The typed character.
+ *
+ * outputs:
+ *     - bool : True for characters that appear in option names (letters, digits, '#', space).
+ */
+fn is_popup_filter_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '#' || c == ' '
+}
+
+/* popup_selection_after - Works out where a navigation key moves a popup list's selection.
+ *
+ * Up and Down wrap around; PageUp, PageDown, Home and End stop at the ends.
+ *
+ * inputs:
+ *     - code (KeyCode): The key pressed.
+ *     - selected (usize): The selected row.
+ *     - list_len (usize): The number of rows, at least 1.
+ *     - page (usize): The number of rows the popup shows at once.
+ *
+ * outputs:
+ *     - usize : The newly selected row.
+ */
+fn popup_selection_after(code: KeyCode, selected: usize, list_len: usize, page: usize) -> usize {
+    let last = list_len - 1;
+    match code {
+        KeyCode::Up => (selected + last) % list_len,
+        KeyCode::Down => (selected + 1) % list_len,
+        KeyCode::PageUp => selected.saturating_sub(page.max(1)),
+        KeyCode::PageDown => (selected + page.max(1)).min(last),
+        KeyCode::Home => 0,
+        KeyCode::End => last,
+        _ => selected.min(last),
+    }
+}
+
+/* render_popup_scrollbar - Draws a scrollbar over a popup's right border when its list overflows.
+ *
+ * inputs:
+ *     - f (&mut Frame): The frame being drawn.
+ *     - popup_area (Rect): The popup, borders included.
+ *     - list_len (usize): The number of rows in the list.
+ *     - visible_rows (u16): The number of rows the popup shows at once.
+ *     - list_state (&ListState): The list's state, after rendering (so its offset is current).
+ *     - theme (&Theme): Colors to draw with.
+ *
+ * outputs:
+ *     - None
+ */
+fn render_popup_scrollbar(
+    f: &mut Frame,
+    popup_area: Rect,
+    list_len: usize,
+    visible_rows: u16,
+    list_state: &ListState,
+    theme: &Theme,
+) {
+    if list_len <= visible_rows as usize {
+        return;
+    }
+    // The thumb tracks the first visible row, so it spans the scrollable range exactly
+    let scroll_range = list_len - visible_rows as usize + 1;
+    let mut scrollbar_state = ScrollbarState::new(scroll_range).position(list_state.offset());
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight).style(theme.text());
+    f.render_stateful_widget(
+        scrollbar,
+        popup_area.inner(&Margin {
+            vertical: 1,
+            horizontal: 0,
+        }),
+        &mut scrollbar_state,
+    );
+}
+
+/* option_index - Finds the row of a popup option.
+ *
+ * inputs:
+ *     - options (&[String]): The popup's options.
+ *     - value (&str): The currently chosen value.
+ *
+ * outputs:
+ *     - usize : The value's index, or 0 (the first option) if it isn't one of the options.
+ */
+fn option_index(options: &[String], value: &str) -> usize {
+    options.iter().position(|option| option == value).unwrap_or(0)
+}
+
+/* compact_neighbor - Overrides `INPUT_GRAPH` edges for the compact layout.
+ *
+ * The compact layout puts Scale, Key, Style, BPM and Length on one row, Swing and Time on
+ * the next, and Generate, Generate Random and Load Song on a third, so moving along those rows (and into
+ * them from above) needs different neighbors. Every other edge is unchanged.
+ *
+ * inputs:
+ *     - current (InputId): The `InputId` of the currently focused UI element.
+ *     - direction (Direction): The direction of navigation.
+ *
+ * outputs:
+ *     - Option<InputId> : The compact layout's neighbor, or None to use `INPUT_GRAPH`.
+ */
+fn compact_neighbor(current: InputId, direction: Direction) -> Option<InputId> {
+    use Direction::{Down, Left, Right, Up};
+    let neighbor = match (current, direction) {
+        (InputId::Previous, Down) => InputId::Scale,
+        (InputId::Rewind, Down) => InputId::Style,
+        (InputId::PlayPause, Down) => InputId::Bpm,
+        (InputId::Skip, Down) => InputId::Length,
+        (InputId::Scale, Up) | (InputId::Quality, Up) => InputId::Previous,
+        (InputId::Scale, Left) => InputId::Length,
+        (InputId::Scale, Down) | (InputId::Quality, Down) | (InputId::Style, Down) => InputId::Swing,
+        (InputId::Bpm, Down) | (InputId::Length, Down) => InputId::TimeSignature,
+        (InputId::Style, Up) => InputId::Rewind,
+        (InputId::Style, Right) => InputId::Bpm,
+        (InputId::Bpm, Up) => InputId::PlayPause,
+        (InputId::Bpm, Left) => InputId::Style,
+        (InputId::Bpm, Right) => InputId::Length,
+        (InputId::Length, Up) => InputId::Skip,
+        (InputId::Length, Left) => InputId::Bpm,
+        (InputId::Length, Right) => InputId::Scale,
+        (InputId::Swing, Up) => InputId::Style,
+        (InputId::Swing, Left) | (InputId::Swing, Right) => InputId::TimeSignature,
+        (InputId::TimeSignature, Up) => InputId::Bpm,
+        (InputId::TimeSignature, Left) | (InputId::TimeSignature, Right) => InputId::Swing,
+        (InputId::Seed, Up) => InputId::Swing,
+        (InputId::Progression, Up) => InputId::TimeSignature,
+        (InputId::Generate, Left) => InputId::SongLoader,
+        (InputId::Generate, Right) => InputId::GenerateRandom,
+        (InputId::Generate, Down) => InputId::Generate,
+        (InputId::GenerateRandom, Up) => InputId::ChordGain,
+        (InputId::GenerateRandom, Left) => InputId::Generate,
+        (InputId::GenerateRandom, Right) => InputId::SongLoader,
+        (InputId::SongLoader, Up) => InputId::BassGain,
+        (InputId::SongLoader, Left) => InputId::GenerateRandom,
+        (InputId::SongLoader, Right) => InputId::Generate,
+        _ => return None,
+    };
+    Some(neighbor)
+}
+
+/* next_focus - Determines the next UI element to focus on based on navigation direction.
+ *
+ * Given the currently focused element (`current`) and a navigation `Direction`,
+ * this function consults the `INPUT_GRAPH` to find the `InputId` of the
+ * neighboring element in that direction. If no neighbor exists in the given
+ * direction, focus remains on the current element.
+ *
+ * inputs:
+ *     - current (InputId): The `InputId` of the currently focused UI element.
+ *     - direction (Direction): The direction of navigation.
+ *     - compact (bool): True if the compact layout is shown.
+ *
+ * outputs:
+ *     - InputId : The `InputId` of the next element to focus, or the current one if no move is possible.
+ */
+fn next_focus(current: InputId, direction: Direction, compact: bool) -> InputId {
+    if let Some(neighbor) = compact.then(|| compact_neighbor(current, direction)).flatten() {
+        return neighbor;
+    }
+    let graph = get_input_graph();
+    graph
+        .get(&current)
+        .and_then(|node| node.neighbors.get(&direction).copied())
+        .unwrap_or(current) // If no neighbor, stay on the current input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    fn test_tui() -> Tui<TestBackend> {
+        Tui::new(TestBackend::new(120, 40)).unwrap()
+    }
+
+    fn press(tui: &mut Tui<TestBackend>, code: KeyCode) -> UserAction {
+        tui.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE)).unwrap()
+    }
+
+    fn type_text(tui: &mut Tui<TestBackend>, text: &str) -> Vec<UserAction> {
+        text.chars().map(|c| press(tui, KeyCode::Char(c))).collect()
+    }
+
+    #[test]
+    fn typing_a_share_code_never_triggers_global_keys() {
+        let mut tui = test_tui();
+        tui.current_focus = InputId::SongLoader;
+        tui.state.input_mode = InputMode::SongLoaderEditing;
+        let code = "8BB-qprf?Qq";
+        for action in type_text(&mut tui, code) {
+            // '?' can't be part of an ID, so it is ignored rather than opening the help
+            assert!(matches!(action, UserAction::UpdateInput | UserAction::NoOp), "{:?}", action);
+        }
+        assert_eq!(tui.state.song_loader_input, code.replace('?', ""));
+        assert_eq!(tui.state.input_mode, InputMode::SongLoaderEditing);
+    }
+
+    #[test]
+    fn global_keys_still_work_outside_text_fields() {
+        let mut tui = test_tui();
+        assert_eq!(press(&mut tui, KeyCode::Char('p')), UserAction::TogglePlayback);
+        assert_eq!(press(&mut tui, KeyCode::Char('q')), UserAction::Quit);
+    }
+
+    // TEXT_FIELDS: Every input edited as text, which a background update must leave alone.
+    const TEXT_FIELDS: [InputId; 8] = [
+        InputId::Bpm,
+        InputId::Seed,
+        InputId::Swing,
+        InputId::Progression,
+        InputId::MelodyGain,
+        InputId::ChordGain,
+        InputId::BassGain,
+        InputId::SongLoader,
+    ];
+
+    fn text_field(state: &mut AppState, id: InputId) -> &mut String {
+        match id {
+            InputId::Bpm => &mut state.bpm,
+            InputId::Seed => &mut state.seed,
+            InputId::Swing => &mut state.swing,
+            InputId::Progression => &mut state.progression,
+            InputId::MelodyGain => &mut state.melody_gain,
+            InputId::ChordGain => &mut state.chord_gain,
+            InputId::BassGain => &mut state.bass_gain,
+            InputId::SongLoader => &mut state.song_loader_input,
+            _ => unreachable!("{:?} is not a text field", id),
+        }
+    }
+
+    #[test]
+    fn a_background_update_mid_edit_keeps_the_edited_field() {
+        for editing in TEXT_FIELDS {
+            let mut tui = test_tui();
+            tui.current_focus = editing;
+            assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::SwitchToEditing);
+            let original = text_field(&mut tui.state, editing).clone();
+            *text_field(&mut tui.state, editing) = "12".to_string();
+            let editing_mode = tui.state.input_mode;
+
+            // A song generated in the background, as sent along with its progress
+            let mut incoming = tui.state.clone();
+            for id in TEXT_FIELDS {
+                *text_field(&mut incoming, id) = "77".to_string();
+            }
+            incoming.input_mode = InputMode::Navigation;
+            incoming.is_playing = true;
+            incoming.current_song_id_display = Some("8BB-new".to_string());
+            tui.set_app_state(incoming);
+
+            for id in TEXT_FIELDS {
+                let expected = if id == editing { "12" } else { "77" };
+                assert_eq!(text_field(&mut tui.state, id), expected, "{:?} while editing {:?}", id, editing);
+            }
+            assert_eq!(tui.state.input_mode, editing_mode, "the edit of {:?} was ended", editing);
+            assert!(tui.state.is_playing);
+            assert_eq!(tui.state.current_song_id_display.as_deref(), Some("8BB-new"));
+
+            // Esc still restores the value from before the edit
+            press(&mut tui, KeyCode::Esc);
+            assert_eq!(tui.state.input_mode, InputMode::Navigation);
+            assert_eq!(*text_field(&mut tui.state, editing), original, "Esc after editing {:?}", editing);
+        }
+    }
+
+    #[test]
+    fn a_background_update_outside_an_edit_replaces_the_state() {
+        let mut tui = test_tui();
+        tui.current_focus = InputId::Bpm;
+        let mut incoming = tui.state.clone();
+        incoming.bpm = "77".to_string();
+        tui.set_app_state(incoming);
+        assert_eq!(tui.state.bpm, "77");
+    }
+
+    #[test]
+    fn progress_advances_with_the_clock_between_reports() {
+        let reported = Instant::now();
+        let mut progress = ProgressInterpolator::default();
+        let total = TUI_SAMPLE_RATE as u64 * 60;
+        progress.report(TUI_SAMPLE_RATE as u64, total, reported);
+        assert_eq!(progress.position_at(reported, true), TUI_SAMPLE_RATE as u64);
+        let later = reported + MAX_PROGRESS_EXTRAPOLATION / 2;
+        let advanced = (MAX_PROGRESS_EXTRAPOLATION / 2).as_secs_f64() * TUI_SAMPLE_RATE as f64;
+        assert_eq!(progress.position_at(later, true), TUI_SAMPLE_RATE as u64 + advanced as u64);
+        // Paused, the bar holds the reported position
+        assert_eq!(progress.position_at(later, false), TUI_SAMPLE_RATE as u64);
+    }
+
+    #[test]
+    fn progress_never_runs_more_than_one_interval_ahead_or_past_the_end() {
+        let reported = Instant::now();
+        let mut progress = ProgressInterpolator::default();
+        let total = TUI_SAMPLE_RATE as u64 * 60;
+        progress.report(0, total, reported);
+        let limit = (MAX_PROGRESS_EXTRAPOLATION.as_secs_f64() * TUI_SAMPLE_RATE as f64) as u64;
+        assert_eq!(progress.position_at(reported + Duration::from_secs(30), true), limit);
+
+        let near_end = reported + Duration::from_secs(1);
+        progress.report(total - 10, total, near_end);
+        assert_eq!(progress.position_at(near_end + MAX_PROGRESS_EXTRAPOLATION, true), total);
+        assert_eq!(progress.ratio(near_end + MAX_PROGRESS_EXTRAPOLATION, true), 1.0);
+        // A clock reading from before the report doesn't move the bar back
+        assert_eq!(progress.position_at(reported, true), total - 10);
+    }
+
+    #[test]
+    fn a_paused_bar_holds_its_estimate_until_resumed() {
+        let reported = Instant::now();
+        let mut progress = ProgressInterpolator::default();
+        let total = TUI_SAMPLE_RATE as u64 * 60;
+        progress.report(0, total, reported);
+        let paused = reported + MAX_PROGRESS_EXTRAPOLATION / 2;
+        let held = progress.position_at(paused, true);
+        progress.freeze(paused);
+        assert_eq!(progress.position_at(paused + Duration::from_secs(10), false), held);
+
+        let resumed = paused + Duration::from_secs(10);
+        progress.resume(resumed);
+        assert_eq!(progress.position_at(resumed, true), held);
+        assert!(progress.position_at(resumed + MAX_PROGRESS_EXTRAPOLATION / 4, true) > held);
+    }
+
+    #[test]
+    fn progress_of_an_unknown_length_is_zero() {
+        let progress = ProgressInterpolator::default();
+        let now = Instant::now();
+        assert_eq!(progress.position_at(now, true), 0);
+        assert_eq!(progress.ratio(now, true), 0.0);
+    }
+
+    fn screen_text(tui: &mut Tui<TestBackend>) -> String {
+        tui.draw().unwrap();
+        let buffer = tui.terminal.backend().buffer();
+        buffer.content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    fn edit_seed(tui: &mut Tui<TestBackend>) {
+        tui.current_focus = InputId::Seed;
+        assert_eq!(press(tui, KeyCode::Enter), UserAction::SwitchToEditing);
+    }
+
+    #[test]
+    fn the_largest_seed_is_accepted_and_the_digit_after_it_rejected() {
+        let mut tui = test_tui();
+        edit_seed(&mut tui);
+        let largest = u64::MAX.to_string();
+        assert_eq!(largest.len(), MAX_SEED_DIGITS);
+        assert!(type_text(&mut tui, &largest).iter().all(|action| *action == UserAction::UpdateInput));
+        assert!(screen_text(&mut tui).contains("20/20"));
+
+        assert_eq!(press(&mut tui, KeyCode::Char('1')), UserAction::NoOp);
+        assert!(tui.seed_rejected_at.is_some());
+        assert_eq!(tui.state.seed, largest);
+
+        assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::SwitchToNavigation);
+        assert_eq!(tui.state.seed, largest);
+        assert!(!screen_text(&mut tui).contains("20/20"));
+    }
+
+    #[test]
+    fn a_twenty_digit_seed_past_the_largest_is_refused_on_confirm() {
+        let mut tui = test_tui();
+        edit_seed(&mut tui);
+        type_text(&mut tui, "18446744073709551616");
+        assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::NoOp);
+        assert_eq!(tui.state.input_mode, InputMode::Editing);
+        let notice = tui.notice.as_ref().map(|(text, _)| text.as_str());
+        assert_eq!(notice, Some("Seed must be at most 18446744073709551615"));
+
+        // One digit less is a valid seed again
+        press(&mut tui, KeyCode::Backspace);
+        assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::SwitchToNavigation);
+        assert_eq!(tui.state.seed, "1844674407370955161");
+    }
+
+    #[test]
+    fn the_seed_counter_only_shows_near_the_limit() {
+        let mut tui = test_tui();
+        edit_seed(&mut tui);
+        type_text(&mut tui, &"9".repeat(SEED_COUNTER_FROM - 1));
+        assert!(!screen_text(&mut tui).contains(&format!("/{}", MAX_SEED_DIGITS)));
+        type_text(&mut tui, "9");
+        assert!(screen_text(&mut tui).contains(&format!("{}/{}", SEED_COUNTER_FROM, MAX_SEED_DIGITS)));
+    }
+
+    #[test]
+    fn slash_jumps_to_the_song_loader_from_anywhere_in_the_form() {
+        for focus in [InputId::Previous, InputId::Scale, InputId::Bpm, InputId::Seed, InputId::Generate, InputId::SongLoader] {
+            let mut tui = test_tui();
+            tui.current_focus = focus;
+            assert_eq!(press(&mut tui, KeyCode::Char('/')), UserAction::SwitchToEditing, "from {:?}", focus);
+            assert_eq!(tui.current_focus, InputId::SongLoader);
+            assert_eq!(tui.state.input_mode, InputMode::SongLoaderEditing);
+        }
+    }
+
+    #[test]
+    fn slash_selects_the_loader_content_for_replacement() {
+        let mut tui = test_tui();
+        tui.state.song_loader_input = "8BB-old".to_string();
+        press(&mut tui, KeyCode::Char('/'));
+        assert!(tui.song_loader_selected);
+        // A key the field ignores keeps the selection; the first typed character replaces it
+        assert_eq!(press(&mut tui, KeyCode::Char('?')), UserAction::NoOp);
+        type_text(&mut tui, "C1");
+        assert_eq!(tui.state.song_loader_input, "C1");
+        press(&mut tui, KeyCode::Esc);
+        assert_eq!(tui.state.song_loader_input, "8BB-old");
+
+        // Backspace on the selection clears it all
+        press(&mut tui, KeyCode::Char('/'));
+        press(&mut tui, KeyCode::Backspace);
+        assert_eq!(tui.state.song_loader_input, "");
+    }
+
+    #[test]
+    fn editing_the_loader_without_slash_appends() {
+        let mut tui = test_tui();
+        tui.state.song_loader_input = "8BB-old".to_string();
+        tui.current_focus = InputId::SongLoader;
+        press(&mut tui, KeyCode::Enter);
+        assert!(!tui.song_loader_selected);
+        type_text(&mut tui, "C1");
+        assert_eq!(tui.state.song_loader_input, "8BB-oldC1");
+
+        // An empty loader has nothing to select either
+        let mut tui = test_tui();
+        press(&mut tui, KeyCode::Char('/'));
+        assert!(!tui.song_loader_selected);
+        type_text(&mut tui, "C1");
+        assert_eq!(tui.state.song_loader_input, "C1");
+    }
+
+    #[test]
+    fn slash_is_just_a_character_while_editing_another_field() {
+        let mut tui = test_tui();
+        tui.current_focus = InputId::Bpm;
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(press(&mut tui, KeyCode::Char('/')), UserAction::NoOp);
+        assert_eq!(tui.current_focus, InputId::Bpm);
+        assert_eq!(tui.state.input_mode, InputMode::Editing);
+    }
+
+    #[test]
+    fn option_index_finds_the_value_or_falls_back_to_the_first_row() {
+        let options: Vec<String> = ["C", "C#", "D"].iter().map(|option| option.to_string()).collect();
+        assert_eq!(option_index(&options, "C"), 0);
+        assert_eq!(option_index(&options, "D"), 2);
+        assert_eq!(option_index(&options, "c#"), 0); // Values are stored exactly as the options
+        assert_eq!(option_index(&options, "H"), 0);
+        assert_eq!(option_index(&[], "C"), 0);
+    }
+
+    #[test]
+    fn every_option_popup_opens_on_the_current_value() {
+        let focuses = [InputId::Scale, InputId::Quality, InputId::Style, InputId::Length, InputId::TimeSignature];
+        for focus in focuses {
+            let mut tui = test_tui();
+            tui.current_focus = focus;
+            assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::OpenPopup);
+            // Choose the option before the last (Length's last one asks for a custom length)
+            let chosen = tui.state.popup_options().len() - 2;
+            press(&mut tui, KeyCode::End);
+            press(&mut tui, KeyCode::Up);
+            press(&mut tui, KeyCode::Enter);
+            assert_eq!(tui.state.input_mode, InputMode::Navigation, "{:?}", focus);
+            press(&mut tui, KeyCode::Enter);
+            assert_eq!(tui.state.popup_list_state.selected(), Some(chosen), "{:?}", focus);
+
+            // Closing without choosing keeps it there
+            press(&mut tui, KeyCode::Up);
+            press(&mut tui, KeyCode::Esc);
+            press(&mut tui, KeyCode::Enter);
+            assert_eq!(tui.state.popup_list_state.selected(), Some(chosen), "{:?} after Esc", focus);
+        }
+    }
+
+    #[test]
+    fn a_popup_for_an_unknown_value_opens_on_the_first_row() {
+        let mut tui = test_tui();
+        tui.state.scale = "G".to_string();
+        tui.state.style = "Polka".to_string();
+        tui.current_focus = InputId::Scale;
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.state.popup_list_state.selected(), Some(option_index(&tui.state.scales, "G")));
+        assert_ne!(tui.state.popup_list_state.selected(), Some(0));
+        press(&mut tui, KeyCode::Esc);
+        tui.current_focus = InputId::Style;
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.state.popup_list_state.selected(), Some(0));
+    }
+
+    #[test]
+    fn popup_keys_wrap_with_arrows_and_stop_at_the_ends_otherwise() {
+        // (key, selected, expected) in a list of 30 rows, 10 to a page
+        let cases = [
+            (KeyCode::Up, 0, 29),
+            (KeyCode::Up, 5, 4),
+            (KeyCode::Down, 29, 0),
+            (KeyCode::Down, 5, 6),
+            (KeyCode::PageUp, 15, 5),
+            (KeyCode::PageUp, 5, 0),
+            (KeyCode::PageDown, 15, 25),
+            (KeyCode::PageDown, 25, 29),
+            (KeyCode::Home, 17, 0),
+            (KeyCode::End, 3, 29),
+            (KeyCode::Tab, 40, 29),
+        ];
+        for (code, selected, expected) in cases {
+            assert_eq!(popup_selection_after(code, selected, 30, 10), expected, "{:?} from {}", code, selected);
+        }
+        // A popup too small to show a row still pages by one
+        assert_eq!(popup_selection_after(KeyCode::PageDown, 3, 30, 0), 4);
+        assert_eq!(popup_selection_after(KeyCode::Up, 0, 1, 10), 0);
+    }
+
+    #[test]
+    fn a_long_popup_keeps_its_selection_on_screen() {
+        let mut tui = Tui::new(TestBackend::new(120, 20)).unwrap();
+        tui.state.styles = (1..=30).map(|index| format!("Style {:02}", index)).collect();
+        tui.state.style = "Style 01".to_string();
+        tui.current_focus = InputId::Style;
+        press(&mut tui, KeyCode::Enter);
+        assert!(!screen_text(&mut tui).contains("Style 30"), "the whole list fit on a 20-row screen");
+
+        let page = tui.popup_list_area.expect("the popup was not drawn").height as usize;
+        press(&mut tui, KeyCode::PageDown);
+        assert_eq!(tui.state.popup_list_state.selected(), Some(page));
+        assert!(screen_text(&mut tui).contains(&format!("Style {:02}", page + 1)));
+
+        press(&mut tui, KeyCode::End);
+        assert!(screen_text(&mut tui).contains("Style 30"));
+        // Down wraps around to the top, which scrolls back into view
+        press(&mut tui, KeyCode::Down);
+        assert_eq!(tui.state.popup_list_state.selected(), Some(0));
+        assert!(!screen_text(&mut tui).contains("Style 30"));
+        press(&mut tui, KeyCode::Up);
+        assert!(screen_text(&mut tui).contains("Style 30"));
+
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.state.style, "Style 30");
+    }
+
+    #[test]
+    fn a_song_id_error_shows_its_suggestion_and_enter_returns_to_the_id() {
+        let mut tui = test_tui();
+        tui.current_focus = InputId::SongLoader;
+        tui.state.input_mode = InputMode::SongLoaderEditing;
+        type_text(&mut tui, "C-Jaz-120-1-42");
+        assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::AttemptLoadSong);
+        // As the main loop reports a song ID that doesn't parse
+        let error = crate::gen::parse_song_id_to_app_state(&tui.state.song_loader_input).unwrap_err();
+        tui.show_song_id_error(error);
+        assert!(screen_text(&mut tui).contains("Did you mean 'Jazz'?"));
+
+        assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::CloseSongIdErrorPopup);
+        assert_eq!(tui.state.input_mode, InputMode::SongLoaderEditing);
+        assert_eq!(tui.state.song_loader_input, "C-Jaz-120-1-42");
+        assert_eq!(tui.state.song_id_error, None);
+        // The typo is fixed in place
+        for _ in "Jaz-120-1-42".chars() {
+            press(&mut tui, KeyCode::Backspace);
+        }
+        type_text(&mut tui, "Jazz-120-1-42");
+        assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::AttemptLoadSong);
+        assert!(crate::gen::parse_song_id_to_app_state(&tui.state.song_loader_input).is_ok());
+    }
+
+    #[test]
+    fn leaving_the_bpm_field_checks_the_edges_of_its_range() {
+        for (typed, kept) in [("20", "20"), ("400", "400"), ("040", "40"), ("", "")] {
+            let mut tui = test_tui();
+            tui.current_focus = InputId::Bpm;
+            tui.state.input_mode = InputMode::Editing;
+            tui.state.bpm.clear();
+            type_text(&mut tui, typed);
+            assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::SwitchToNavigation, "for '{}'", typed);
+            assert_eq!(tui.state.bpm, kept);
+            assert_eq!(tui.state.song_id_error, None);
+        }
+        for typed in ["19", "401", "0", "007"] {
+            let mut tui = test_tui();
+            tui.current_focus = InputId::Bpm;
+            tui.state.input_mode = InputMode::Editing;
+            tui.state.bpm.clear();
+            type_text(&mut tui, typed);
+            assert_eq!(press(&mut tui, KeyCode::Enter), UserAction::NoOp, "for '{}'", typed);
+            // The BPM stays in the field behind the error popup
+            assert_eq!(tui.state.bpm, typed);
+            assert!(tui.state.song_id_error.as_deref().is_some_and(|error| error.contains("use 20 to 400")));
+        }
+    }
+
+    #[test]
+    fn a_messy_song_id_is_pasted_into_the_loader_whole() {
+        for id in ["  c-pop-120-5min-42\n", "v2: D - Rock - 100 - 2.5 - 9", "f#m-jazz-96-2:30-1\r\n"] {
+            let mut tui = test_tui();
+            tui.current_focus = InputId::SongLoader;
+            tui.state.input_mode = InputMode::SongLoaderEditing;
+            tui.state.song_loader_input.clear();
+            assert_eq!(tui.paste_text(id), UserAction::UpdateInput);
+            // Only the line break is dropped
+            assert_eq!(tui.state.song_loader_input, id.trim_end_matches(['\r', '\n']));
+            assert!(crate::gen::parse_song_id_to_app_state(&tui.state.song_loader_input).is_ok(), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn a_loaded_song_keeps_its_sound_and_the_listeners_playback() {
+        let listener = AppState {
+            harmony: "Song".to_string(),
+            drums: false,
+            melody_gain: "90".to_string(),
+            fade_out_secs: Some(0),
+            is_random: true,
+            is_looping: true,
+            crossfade_secs: 4,
+            volume: 0.5,
+            is_muted: true,
+            ..AppState::default()
+        };
+        let mut loaded = crate::gen::parse_song_id_to_app_state("v2:Am-Jazz-96-2-h1-o8-42").unwrap();
+        loaded.carry_playback_settings(&listener);
+        assert_eq!((loaded.harmony.as_str(), loaded.drums, loaded.melody_gain.as_str()), ("Chorus", true, "50"));
+        assert_eq!(loaded.fade_out_secs, Some(8));
+        assert!(loaded.is_random && loaded.is_looping && loaded.is_muted);
+        assert_eq!((loaded.crossfade_secs, loaded.volume), (4, 0.5));
+    }
 }
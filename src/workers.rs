@@ -0,0 +1,494 @@
+use crossbeam_channel::{Receiver as CrossbeamReceiver, Sender as CrossbeamSender};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// WORKERS_ENV_VAR: Overrides the number of generation workers (at least 1).
+const WORKERS_ENV_VAR: &str = "EIGHTBITBEATS_WORKERS";
+
+// QUIT_SHUTDOWN_DEADLINE: How long quitting waits for generation work that is already running.
+pub const QUIT_SHUTDOWN_DEADLINE: Duration = Duration::from_millis(500);
+
+// SHUTDOWN_POLL_INTERVAL: How often `shutdown` checks whether the workers have finished.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// ForkSlot: A forked job until the first of a worker and `ForkedJob::join` takes it.
+type ForkSlot<T> = Arc<Mutex<Option<Box<dyn FnOnce() -> T + Send + 'static>>>>;
+
+/* QueuedJob - A job waiting in the pool queue, with the flag its `TaskHandle` sets on drop.
+ *
+ * fields:
+ *     - job (Job): The work to run.
+ *     - cancelled (Arc<AtomicBool>): Set once nobody wants the result any more.
+ */
+struct QueuedJob {
+    job: Job,
+    cancelled: Arc<AtomicBool>,
+}
+
+/* WorkerStats - A snapshot of the pool counters, shown in the debug overlay.
+ *
+ * fields:
+ *     - workers (usize): Number of worker threads in the pool.
+ *     - queued (usize): Jobs waiting for a free worker.
+ *     - running (usize): Jobs currently running.
+ *     - completed (usize): Jobs finished since startup.
+ *     - cancelled (usize): Queued jobs dropped before they started.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WorkerStats {
+    pub workers: usize,
+    pub queued: usize,
+    pub running: usize,
+    pub completed: usize,
+    pub cancelled: usize,
+}
+
+/* Counters - Shared counters updated by the workers.
+ *
+ * fields:
+ *     - queued (AtomicUsize): Jobs waiting for a free worker.
+ *     - running (AtomicUsize): Jobs currently running.
+ *     - completed (AtomicUsize): Jobs finished since startup.
+ *     - cancelled (AtomicUsize): Queued jobs dropped before they started.
+ */
+#[derive(Default)]
+struct Counters {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    completed: AtomicUsize,
+    cancelled: AtomicUsize,
+}
+
+/* TaskHandle - Keeps a submitted job wanted.
+ *
+ * Dropping the handle cancels the job if it has not started yet, so work for a song that
+ * was skipped before its turn came never runs. A job that already started runs to completion.
+ *
+ * fields:
+ *     - cancelled (Arc<AtomicBool>): Shared with the queued job.
+ */
+pub struct TaskHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/* ForkedJob - Part of a job split off to run on another worker (see `WorkerPool::fork`).
+ *
+ * fields:
+ *     - slot (ForkSlot<T>): The work, until a worker or `join` takes it.
+ *     - result (CrossbeamReceiver<thread::Result<T>>): The result once a worker has run it.
+ *     - _task (Option<TaskHandle>): Keeps the work queued; None if the pool has shut down.
+ */
+pub struct ForkedJob<T> {
+    slot: ForkSlot<T>,
+    result: CrossbeamReceiver<thread::Result<T>>,
+    _task: Option<TaskHandle>,
+}
+
+impl<T> ForkedJob<T> {
+    /* join - Returns the result of the forked work.
+     *
+     * If no worker has started the work yet it runs right here, so a job waiting on its own
+     * forks never waits on a busy pool (with a single worker everything runs on the caller).
+     * A panic in the work is raised again on the calling thread.
+     *
+     * inputs:
+     *     - self
+     *
+     * outputs:
+     *     - T: The result.
+     */
+    pub fn join(self) -> T {
+        let unstarted = self.slot.lock().ok().and_then(|mut slot| slot.take());
+        if let Some(work) = unstarted {
+            return work();
+        }
+        match self.result.recv() {
+            Ok(result) => result.unwrap_or_else(|panic| panic::resume_unwind(panic)),
+            Err(_) => panic!("A forked job was lost by its worker"),
+        }
+    }
+}
+
+/* WorkerPool - A fixed set of threads that runs all song generation work.
+ *
+ * Generation is CPU-bound, so rather than spawning a thread per render, jobs are queued and
+ * run by at most `workers` threads. Long-lived service threads (playback, input) are not
+ * scheduled here; they keep their own dedicated threads.
+ *
+ * fields:
+ *     - job_sender (Mutex<Option<CrossbeamSender<QueuedJob>>>): Queue of pending jobs; None once shut down.
+ *     - handles (Mutex<Vec<JoinHandle<()>>>): The worker threads.
+ *     - counters (Arc<Counters>): Shared job counters.
+ *     - shutting_down (Arc<AtomicBool>): Tells workers to discard queued jobs.
+ *     - workers (usize): Number of worker threads.
+ */
+pub struct WorkerPool {
+    job_sender: Mutex<Option<CrossbeamSender<QueuedJob>>>,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+    counters: Arc<Counters>,
+    shutting_down: Arc<AtomicBool>,
+    workers: usize,
+}
+
+/* default_worker_count - Returns the pool size to use when none is configured.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - usize: `EIGHTBITBEATS_WORKERS` if set to a positive number, otherwise the number of
+ *       CPU cores minus one (leaving a core for playback and the UI), and at least 1.
+ */
+fn default_worker_count() -> usize {
+    let configured = std::env::var(WORKERS_ENV_VAR)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|&count| count > 0);
+    configured.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map_or(1, |cores| cores.get().saturating_sub(1))
+            .max(1)
+    })
+}
+
+/* worker_loop - Runs queued jobs until the queue is closed.
+ *
+ * A job that panics is caught here, so the pool never loses a worker to it.
+ *
+ * inputs:
+ *     - jobs (CrossbeamReceiver<QueuedJob>): The shared job queue.
+ *     - counters (Arc<Counters>): Shared job counters.
+ *     - shutting_down (Arc<AtomicBool>): When set, remaining queued jobs are discarded.
+ *
+ * outputs:
+ *     - None
+ */
+fn worker_loop(
+    jobs: CrossbeamReceiver<QueuedJob>,
+    counters: Arc<Counters>,
+    shutting_down: Arc<AtomicBool>,
+) {
+    for queued in jobs.iter() {
+        counters.queued.fetch_sub(1, Ordering::SeqCst);
+        if shutting_down.load(Ordering::SeqCst) || queued.cancelled.load(Ordering::SeqCst) {
+            counters.cancelled.fetch_add(1, Ordering::SeqCst);
+            continue;
+        }
+        counters.running.fetch_add(1, Ordering::SeqCst);
+        // The panic message has already been printed by the panic hook
+        let _ = panic::catch_unwind(AssertUnwindSafe(queued.job));
+        counters.running.fetch_sub(1, Ordering::SeqCst);
+        counters.completed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl WorkerPool {
+    /* new - Starts a pool with a fixed number of worker threads.
+     *
+     * inputs:
+     *     - workers (usize): Number of threads (at least 1 is used).
+     *
+     * outputs:
+     *     - Self: The running pool.
+     */
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        let (job_sender, job_receiver) = crossbeam_channel::unbounded::<QueuedJob>();
+        let counters = Arc::new(Counters::default());
+        let shutting_down = Arc::new(AtomicBool::new(false));
+        let handles = (0..workers)
+            .map(|index| {
+                let jobs = job_receiver.clone();
+                let counters = Arc::clone(&counters);
+                let shutting_down = Arc::clone(&shutting_down);
+                thread::Builder::new()
+                    .name(format!("generation-worker-{}", index))
+                    .spawn(move || worker_loop(jobs, counters, shutting_down))
+                    .expect("Failed to spawn generation worker")
+            })
+            .collect();
+        Self {
+            job_sender: Mutex::new(Some(job_sender)),
+            handles: Mutex::new(handles),
+            counters,
+            shutting_down,
+            workers,
+        }
+    }
+
+    /* submit - Queues a job to run on the next free worker.
+     *
+     * inputs:
+     *     - &self
+     *     - job (impl FnOnce() + Send + 'static): The work to run.
+     *
+     * outputs:
+     *     - Option<TaskHandle>: A handle that cancels the job if dropped before it starts,
+     *       or None if the pool has been shut down (the job is dropped without running).
+     */
+    pub fn submit(&self, job: impl FnOnce() + Send + 'static) -> Option<TaskHandle> {
+        let sender = self.job_sender.lock().ok()?;
+        let sender = sender.as_ref()?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.counters.queued.fetch_add(1, Ordering::SeqCst);
+        let queued = QueuedJob {
+            job: Box::new(job),
+            cancelled: Arc::clone(&cancelled),
+        };
+        if sender.send(queued).is_err() {
+            self.counters.queued.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(TaskHandle { cancelled })
+    }
+
+    /* fork - Splits off work for another worker to run while the caller carries on.
+     *
+     * Unlike `submit`, the caller waits for the result with `ForkedJob::join`, which runs the
+     * work itself if no worker got to it first. Nothing runs beyond the pool's workers and the
+     * callers, however deeply jobs fork.
+     *
+     * inputs:
+     *     - &self
+     *     - work (impl FnOnce() -> T + Send + 'static): The work to run.
+     *
+     * outputs:
+     *     - ForkedJob<T>: The handle to join for the result.
+     */
+    pub fn fork<T: Send + 'static>(&self, work: impl FnOnce() -> T + Send + 'static) -> ForkedJob<T> {
+        let slot: ForkSlot<T> = Arc::new(Mutex::new(Some(Box::new(work))));
+        let (result_sender, result) = crossbeam_channel::bounded(1);
+        let queued_slot = Arc::clone(&slot);
+        let task = self.submit(move || {
+            let work = queued_slot.lock().ok().and_then(|mut slot| slot.take());
+            if let Some(work) = work {
+                let _ = result_sender.send(panic::catch_unwind(AssertUnwindSafe(work)));
+            }
+        });
+        ForkedJob {
+            slot,
+            result,
+            _task: task,
+        }
+    }
+
+    /* stats - Returns a snapshot of the pool counters.
+     *
+     * inputs:
+     *     - &self
+     *
+     * outputs:
+     *     - WorkerStats: The current counters.
+     */
+    pub fn stats(&self) -> WorkerStats {
+        WorkerStats {
+            workers: self.workers,
+            queued: self.counters.queued.load(Ordering::SeqCst),
+            running: self.counters.running.load(Ordering::SeqCst),
+            completed: self.counters.completed.load(Ordering::SeqCst),
+            cancelled: self.counters.cancelled.load(Ordering::SeqCst),
+        }
+    }
+
+    /* shutdown - Cancels queued jobs and waits for running ones to finish.
+     *
+     * New submissions are refused from this point on. Workers still busy when the deadline
+     * passes are left to finish in the background (they exit with the process).
+     *
+     * inputs:
+     *     - &self
+     *     - deadline (Duration): How long to wait for running jobs.
+     *
+     * outputs:
+     *     - bool: True if every worker finished within the deadline.
+     */
+    pub fn shutdown(&self, deadline: Duration) -> bool {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        // Closing the queue makes each worker exit once it has discarded what is left
+        if let Ok(mut sender) = self.job_sender.lock() {
+            sender.take();
+        }
+        let mut handles = match self.handles.lock() {
+            Ok(mut handles) => std::mem::take(&mut *handles),
+            Err(_) => return false,
+        };
+        let started = Instant::now();
+        while handles.iter().any(|handle| !handle.is_finished()) {
+            if started.elapsed() >= deadline {
+                return false;
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+        for handle in handles.drain(..) {
+            let _ = handle.join();
+        }
+        true
+    }
+}
+
+/* global - Returns the application-wide worker pool, starting it on first use.
+ *
+ * inputs:
+ *     - None
+ *
+ * outputs:
+ *     - &'static WorkerPool: The shared pool.
+ */
+pub fn global() -> &'static WorkerPool {
+    static POOL: OnceLock<WorkerPool> = OnceLock::new();
+    POOL.get_or_init(|| WorkerPool::new(default_worker_count()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn a_fork_no_worker_picks_up_runs_on_the_caller() {
+        let pool = WorkerPool::new(1);
+        let (release_sender, release) = crossbeam_channel::bounded::<()>(0);
+        // Keeps the only worker busy
+        let _busy = pool.submit(move || {
+            let _ = release.recv();
+        });
+        let caller = thread::current().id();
+        assert_eq!(pool.fork(|| thread::current().id()).join(), caller);
+        release_sender.send(()).unwrap();
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn a_fork_a_worker_picks_up_returns_its_result() {
+        let pool = WorkerPool::new(1);
+        let (started_sender, started) = crossbeam_channel::bounded(1);
+        let fork = pool.fork(move || {
+            started_sender.send(thread::current().id()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            7
+        });
+        let worker = started.recv_timeout(Duration::from_secs(5)).expect("no worker ran the fork");
+        assert_ne!(worker, thread::current().id());
+        assert_eq!(fork.join(), 7);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn forks_still_run_once_the_pool_has_shut_down() {
+        let pool = WorkerPool::new(2);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(pool.fork(|| 5).join(), 5);
+    }
+
+    #[test]
+    fn a_panicking_fork_panics_on_join() {
+        let pool = WorkerPool::new(1);
+        let fork = pool.fork(|| -> u32 { panic!("fork failed") });
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| fork.join())).is_err());
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn simultaneous_generations_queue_for_the_workers() {
+        let pool = WorkerPool::new(2);
+        let (release_sender, release) = crossbeam_channel::unbounded::<()>();
+        let (started_sender, started) = crossbeam_channel::unbounded();
+        let handles: Vec<TaskHandle> = (0..6)
+            .map(|_| {
+                let release = release.clone();
+                let started_sender = started_sender.clone();
+                pool.submit(move || {
+                    started_sender.send(thread::current().id()).unwrap();
+                    let _ = release.recv();
+                })
+                .expect("the pool refused a job")
+            })
+            .collect();
+
+        let mut workers: HashSet<_> = (0..2)
+            .map(|_| started.recv_timeout(Duration::from_secs(5)).expect("no worker started a job"))
+            .collect();
+        // Only as many jobs run as there are workers; the rest wait their turn
+        assert!(started.recv_timeout(Duration::from_millis(100)).is_err());
+        let stats = pool.stats();
+        assert_eq!((stats.running, stats.queued), (2, 4));
+
+        for _ in 0..6 {
+            release_sender.send(()).unwrap();
+        }
+        workers.extend((0..4).map(|_| started.recv_timeout(Duration::from_secs(5)).unwrap()));
+        assert_eq!(workers.len(), 2, "jobs ran on threads outside the pool");
+        drop(handles);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(pool.stats().completed, 6);
+    }
+
+    #[test]
+    fn shutdown_cancels_queued_jobs_within_the_deadline() {
+        let pool = WorkerPool::new(1);
+        let ran = Arc::new(AtomicUsize::new(0));
+        let (started_sender, started) = crossbeam_channel::bounded(1);
+        let _running = pool.submit(move || {
+            started_sender.send(()).unwrap();
+            thread::sleep(Duration::from_millis(100));
+        });
+        started.recv_timeout(Duration::from_secs(5)).expect("no worker started the job");
+        let _queued: Vec<_> = (0..3)
+            .map(|_| {
+                let ran = Arc::clone(&ran);
+                pool.submit(move || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        let deadline = Duration::from_secs(1);
+        let shutting_down = Instant::now();
+        assert!(pool.shutdown(deadline));
+        assert!(shutting_down.elapsed() < deadline);
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+        let stats = pool.stats();
+        assert_eq!((stats.completed, stats.cancelled, stats.queued), (1, 3, 0));
+        assert!(pool.submit(|| {}).is_none());
+    }
+
+    #[test]
+    fn shutdown_gives_up_on_a_job_that_outlasts_the_deadline() {
+        let pool = WorkerPool::new(1);
+        let (release_sender, release) = crossbeam_channel::bounded::<()>(1);
+        let (started_sender, started) = crossbeam_channel::bounded(1);
+        let _running = pool.submit(move || {
+            started_sender.send(()).unwrap();
+            let _ = release.recv();
+        });
+        started.recv_timeout(Duration::from_secs(5)).expect("no worker started the job");
+
+        let deadline = Duration::from_millis(50);
+        let shutting_down = Instant::now();
+        assert!(!pool.shutdown(deadline));
+        assert!(shutting_down.elapsed() < deadline + Duration::from_millis(100));
+        release_sender.send(()).unwrap();
+    }
+
+    #[test]
+    fn a_panicking_job_leaves_its_worker_running() {
+        let pool = WorkerPool::new(1);
+        let _failed = pool.submit(|| panic!("job failed"));
+        let (done_sender, done) = crossbeam_channel::bounded(1);
+        let _next = pool.submit(move || done_sender.send(()).unwrap());
+        assert!(done.recv_timeout(Duration::from_secs(5)).is_ok(), "the worker died with the panic");
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(pool.stats().completed, 2);
+    }
+}